@@ -0,0 +1,169 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use inkwell::OptimizationLevel;
+use std::fmt::Write;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use venti::codegen::codegen::CodeGen;
+use venti::venti_lexer::lexer::Lexer;
+use venti::venti_parser::arena::Arena;
+use venti::venti_parser::ast::{Expr, ExprKind, Statement};
+use venti::venti_parser::parser::Parser;
+use venti::Context;
+
+/*
+Times the passes a program goes through on generated programs of growing size, each a
+chain of functions with loops, matches and calls like those people write:
+
+    cargo bench --bench compiler
+
+Lexing is reported in tokens per second, parsing in statements per second and code
+generation as the time to emit the unoptimized IR of the whole program. Criterion keeps
+the results of the last run under `target/criterion`, and reports how much each benchmark
+changed since then, so a slower pass shows up as a regression.
+*/
+
+/// The numbers of functions in the generated programs.
+const SIZES: [usize; 3] = [10, 100, 1000];
+
+/// Writes a program of `functions` functions, each calling the one before it, and a call
+/// of the last one at the top level.
+fn program(functions: usize) -> String {
+    let mut source = String::new();
+    for i in 0..functions {
+        let call = match i {
+            0 => "a".to_string(),
+            _ => format!("f{}(a, b - 1)", i - 1),
+        };
+        let _ = write!(
+            source,
+            "fn_venti f{i}(a, b) {{
+    venti total = a * {i} + b;
+    for_venti j in 0..b {{
+        total = total + j;
+    }}
+    match_venti total {{
+        0 => {{
+            return_venti a;
+        }}
+        _ => {{
+            return_venti total - {call};
+        }}
+    }}
+}}
+
+"
+        );
+    }
+    let _ = writeln!(source, "printventi(f{}(1, 2));", functions - 1);
+    source
+}
+
+fn parse<'a>(source: &str, arena: &'a Arena<Expr<'a>>) -> Vec<Statement<'a>> {
+    Parser::new(Lexer::new(source), arena)
+        .parse()
+        .expect("the generated program parses")
+}
+
+/// Counts the statements of `statements` and of the blocks in them, leaving out the
+/// `Line` markers the parser adds.
+fn count_statements(statements: &[Statement]) -> usize {
+    statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::Line(_) => 0,
+            Statement::Function { body, .. }
+            | Statement::AsyncFunction { body, .. }
+            | Statement::For { body, .. }
+            | Statement::Test { body, .. }
+            | Statement::Expression(Expr {
+                kind: ExprKind::Spawn(body),
+                ..
+            }) => 1 + count_statements(body),
+            Statement::Match { arms, .. } => {
+                1 + arms
+                    .iter()
+                    .map(|arm| count_statements(&arm.body))
+                    .sum::<usize>()
+            }
+            _ => 1,
+        })
+        .sum()
+}
+
+fn lex(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lex");
+    for functions in SIZES {
+        let source = program(functions);
+        let tokens = Lexer::new(&source).count();
+        group.throughput(Throughput::Elements(tokens as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(functions),
+            &source,
+            |b, source| {
+                b.iter(|| {
+                    for token in Lexer::new(source) {
+                        black_box(token.expect("the generated program lexes"));
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn parse_statements(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for functions in SIZES {
+        let source = program(functions);
+        let statements = count_statements(&parse(&source, &Arena::new()));
+        group.throughput(Throughput::Elements(statements as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(functions),
+            &source,
+            |b, source| {
+                b.iter(|| {
+                    let arena = Arena::new();
+                    black_box(parse(source, &arena));
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn codegen(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codegen");
+    // Each iteration builds a whole module, which takes far longer than lexing
+    group.sample_size(20);
+    for functions in SIZES {
+        let source = program(functions);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(functions),
+            &source,
+            |b, source| {
+                // The code generator consumes the tree, so each iteration parses its own,
+                // into an arena the tree borrows, before its time starts
+                b.iter_custom(|iterations| {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iterations {
+                        let arena = Arena::new();
+                        let ast = parse(source, &arena);
+                        let start = Instant::now();
+                        let context = Context::create();
+                        let mut codegen = CodeGen::new(&context, OptimizationLevel::None);
+                        codegen
+                            .compile(ast)
+                            .expect("the generated program compiles");
+                        black_box(codegen.module().print_to_string());
+                        elapsed += start.elapsed();
+                    }
+                    elapsed
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, lex, parse_statements, codegen);
+criterion_main!(benches);
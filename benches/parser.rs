@@ -0,0 +1,73 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+use venti::venti_lexer::lexer::Lexer;
+use venti::venti_parser::arena::Arena;
+use venti::venti_parser::parser::Parser;
+
+/*
+Times the lexer and parser on generated programs, each a single expression of about
+100,000 nodes, to show how the front end copes with large expressions:
+
+    cargo bench --bench parser
+
+The expressions are balanced trees of parenthesized binary operations, so they are only
+about 17 levels deep and no pass recurses far enough to overflow the stack. Parsing is
+reported in nodes per second, and Criterion compares each run with the last as it does for
+the compiler benchmarks.
+
+Each parse puts its nodes in an arena of its own, which is dropped with the tree, so the
+time covers allocating and freeing the nodes as a compile would.
+*/
+
+/// The operators the generated expressions cycle through.
+const OPERATORS: [&str; 4] = ["+", "-", "*", "/"];
+
+/// Writes a balanced expression with `leaves` operands into `out`, each operand being
+/// `operand` or, when that is `None`, a number.
+///
+/// # Returns
+///
+/// The number of expression nodes written: `leaves` operands and one fewer operations.
+fn expression(leaves: usize, depth: usize, operand: Option<&str>, out: &mut String) -> usize {
+    if leaves == 1 {
+        match operand {
+            Some(operand) => out.push_str(operand),
+            None => out.push_str(&(depth + 1).to_string()),
+        }
+        return 1;
+    }
+    out.push('(');
+    let left = expression(leaves / 2, depth + 1, operand, out);
+    out.push(' ');
+    out.push_str(OPERATORS[depth % OPERATORS.len()]);
+    out.push(' ');
+    let right = expression(leaves - leaves / 2, depth + 1, operand, out);
+    out.push(')');
+    left + right + 1
+}
+
+fn parse_expression(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_expression");
+    // Each iteration parses a whole 100,000-node expression
+    group.sample_size(20);
+    // The same shape with numbers and with variable reads, whose names are allocated too
+    for (name, operand) in [("numbers", None), ("variables", Some("v"))] {
+        let mut source = String::from("venti v = 1;\nventi x = ");
+        let nodes = expression(50_000, 0, operand, &mut source);
+        source.push_str(";\n");
+        group.throughput(Throughput::Elements(nodes as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &source, |b, source| {
+            b.iter(|| {
+                let arena = Arena::new();
+                let ast = Parser::new(Lexer::new(source), &arena)
+                    .parse()
+                    .expect("the generated program parses");
+                black_box(ast);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse_expression);
+criterion_main!(benches);
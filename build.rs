@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+Hashes the sources the compiler is built from into `VENTI_BUILD_HASH`, which
+`cache::CacheKey::compiler` keys every cache entry on. The version alone does not change
+between two builds of a working tree, so a module cached by one build would otherwise be
+loaded by the next, even if the code it generates has changed since.
+
+The manifest and lock file are hashed with the sources, so changing a dependency such as
+inkwell also starts a fresh cache.
+*/
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn main() {
+    let mut files = Vec::new();
+    sources(Path::new("src"), &mut files);
+    files.sort();
+    files.extend(["Cargo.toml", "Cargo.lock"].map(PathBuf::from));
+
+    let mut hash = FNV_OFFSET;
+    for file in &files {
+        // A missing lock file hashes the same as an empty one
+        let text = fs::read(file).unwrap_or_default();
+        for part in [file.to_string_lossy().as_bytes(), &text] {
+            hash = fnv1a(hash, &(part.len() as u64).to_le_bytes());
+            hash = fnv1a(hash, part);
+        }
+    }
+
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rustc-env=VENTI_BUILD_HASH={:016x}", hash);
+}
+
+/// Adds every file under `directory` to `files`.
+fn sources(directory: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            sources(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// Hashes `bytes` into `hash` with 64-bit FNV-1a.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
@@ -0,0 +1,258 @@
+use crate::errors::VentiError;
+use crate::source::SourceMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/*
+The cache keeps the bitcode of programs `venti run` compiled, so running an unchanged
+program again loads its module straight from disk instead of lexing, parsing, checking and
+generating code for it once more.
+
+An entry is found by a `CacheKey`: a hash of the compiler build, where the program lives,
+its text and every option that changes the code generated for it. Where the program lives
+is part of the key because its imports are relative to it. The files it imports are not
+known until it is parsed, so they are recorded next to the entry instead, each with a hash
+of its text, and an entry is only used while every one of them still has that text:
+
+    <cache>/<key>/module.bc   the optimized module
+    <cache>/<key>/imports     one `<hash> <path>` line per imported file
+
+Each file is written under a temporary name and renamed into place, the bitcode first, so
+an entry whose imports list is in place always has the bitcode that goes with it. Entries
+are never removed; deleting the cache directory is always safe.
+
+The cache also keeps the unoptimized module of each top-level function `venti build` and
+`venti run` compile, so a program that changed is only partly generated again. Those are
+keyed by `codegen::incremental`, which hashes what each function's code depends on:
+
+    <cache>/functions/<key>.bc   the module of one function
+*/
+
+/// A directory holding compiled modules keyed by `CacheKey`.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    directory: PathBuf,
+}
+
+/// Identifies what a compiled module was compiled from and how.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheKey {
+    hash: u64,
+}
+
+impl CacheKey {
+    /// Starts a key for the program `text` found at `path`, under this compiler build.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Where the program lives, which its imports are relative to.
+    /// * `text` - The source of the program.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `CacheKey`.
+    pub fn new(path: &Path, text: &str) -> Self {
+        CacheKey::compiler()
+            .with_option("path", &path.to_string_lossy())
+            .with_option("source", text)
+    }
+
+    /// Starts a key that only holds the compiler build, for keys built from something other
+    /// than a whole program.
+    ///
+    /// The build is its version and the hash `build.rs` makes of the sources it was built
+    /// from, so a compiler built from changed sources never loads what another one cached.
+    pub fn compiler() -> Self {
+        CacheKey { hash: FNV_OFFSET }
+            .with_option("version", env!("CARGO_PKG_VERSION"))
+            .with_option("build", env!("VENTI_BUILD_HASH"))
+    }
+
+    /// Adds an option that changes the generated code, such as the optimization level.
+    pub fn with_option(mut self, name: &str, value: &str) -> Self {
+        // Each part is preceded by its length, so no two lists of parts hash the same text
+        for part in [name, value] {
+            self.hash = fnv1a(self.hash, &(part.len() as u64).to_le_bytes());
+            self.hash = fnv1a(self.hash, part.as_bytes());
+        }
+        self
+    }
+
+    /// Adds another key, such as the key of something the compiled code depends on.
+    pub fn with_key(self, name: &str, key: CacheKey) -> Self {
+        self.with_option(name, &key.entry())
+    }
+
+    /// The name of the key's entry in the cache directory.
+    fn entry(self) -> String {
+        format!("{:016x}", self.hash)
+    }
+}
+
+impl Cache {
+    /// Creates a `Cache` that keeps its entries in `directory`, which is created when the
+    /// first entry is stored.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Cache`.
+    pub fn new(directory: &Path) -> Self {
+        Cache {
+            directory: directory.to_path_buf(),
+        }
+    }
+
+    /// Opens the user's cache: `VENTI_CACHE_DIR` when it is set, otherwise `venti` in
+    /// `XDG_CACHE_HOME` or in `~/.cache`.
+    ///
+    /// # Returns
+    ///
+    /// The cache, or `None` if none of those variables is set.
+    pub fn from_env() -> Option<Self> {
+        let directory = match env::var_os("VENTI_CACHE_DIR") {
+            Some(directory) => PathBuf::from(directory),
+            None => env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?
+                .join("venti"),
+        };
+        Some(Cache::new(&directory))
+    }
+
+    /// Looks up the module compiled for `key`.
+    ///
+    /// # Returns
+    ///
+    /// The path of the module's bitcode, or `None` if there is no entry for `key` or a
+    /// file the program imports has changed since it was stored.
+    pub fn lookup(&self, key: CacheKey) -> Option<PathBuf> {
+        let entry = self.directory.join(key.entry());
+        let imports = fs::read_to_string(entry.join("imports")).ok()?;
+        for line in imports.lines() {
+            let (hash, path) = line.split_once(' ')?;
+            let text = fs::read_to_string(path).ok()?;
+            if format!("{:016x}", fnv1a(FNV_OFFSET, text.as_bytes())) != hash {
+                return None;
+            }
+        }
+        let bitcode = entry.join("module.bc");
+        bitcode.is_file().then_some(bitcode)
+    }
+
+    /// Stores the module compiled for `key`, replacing any entry it already has.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - What the module was compiled from.
+    /// * `sources` - The files the program was read from; every one after the first is an
+    ///   import the entry depends on.
+    /// * `write_bitcode` - Writes the module's bitcode to the path it is given.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), VentiError>` - Nothing, or an IO error if the entry could not be written.
+    pub fn store(
+        &self,
+        key: CacheKey,
+        sources: &SourceMap,
+        write_bitcode: impl FnOnce(&Path) -> Result<(), VentiError>,
+    ) -> Result<(), VentiError> {
+        let entry = self.directory.join(key.entry());
+        fs::create_dir_all(&entry).map_err(|e| {
+            VentiError::IOError(format!("Could not create {}: {}", entry.display(), e), None)
+        })?;
+
+        let imports = sources
+            .files()
+            .iter()
+            .skip(1)
+            .map(|file| {
+                let path = fs::canonicalize(&file.path).unwrap_or_else(|_| file.path.clone());
+                format!(
+                    "{:016x} {}\n",
+                    fnv1a(FNV_OFFSET, file.text.as_bytes()),
+                    path.display()
+                )
+            })
+            .collect::<String>();
+
+        // Other runs may be reading the entry, so each file only appears once it is whole
+        let temporary = |name: &str| entry.join(format!("{}.{}", name, process::id()));
+        let bitcode = temporary("module.bc");
+        write_bitcode(&bitcode)?;
+        rename(&bitcode, &entry.join("module.bc"))?;
+        let list = temporary("imports");
+        fs::write(&list, imports).map_err(|e| {
+            VentiError::IOError(format!("Could not write {}: {}", list.display(), e), None)
+        })?;
+        rename(&list, &entry.join("imports"))
+    }
+
+    /// Looks up the module compiled for a single function.
+    ///
+    /// # Returns
+    ///
+    /// The path of the module's bitcode, or `None` if there is no entry for `key`.
+    pub fn lookup_function(&self, key: CacheKey) -> Option<PathBuf> {
+        let bitcode = self.function_path(key);
+        bitcode.is_file().then_some(bitcode)
+    }
+
+    /// Stores the module compiled for a single function, replacing any entry it has.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - What the function's module was compiled from.
+    /// * `write_bitcode` - Writes the module's bitcode to the path it is given.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), VentiError>` - Nothing, or an IO error if the entry could not be written.
+    pub fn store_function(
+        &self,
+        key: CacheKey,
+        write_bitcode: impl FnOnce(&Path) -> Result<(), VentiError>,
+    ) -> Result<(), VentiError> {
+        let functions = self.directory.join("functions");
+        fs::create_dir_all(&functions).map_err(|e| {
+            VentiError::IOError(
+                format!("Could not create {}: {}", functions.display(), e),
+                None,
+            )
+        })?;
+        let bitcode = self.function_path(key);
+        let temporary = bitcode.with_extension(format!("bc.{}", process::id()));
+        write_bitcode(&temporary)?;
+        rename(&temporary, &bitcode)
+    }
+
+    /// The path of the module compiled for a single function.
+    fn function_path(&self, key: CacheKey) -> PathBuf {
+        self.directory
+            .join("functions")
+            .join(format!("{}.bc", key.entry()))
+    }
+}
+
+/// Moves a file written under a temporary name to where it belongs.
+fn rename(from: &Path, to: &Path) -> Result<(), VentiError> {
+    fs::rename(from, to).map_err(|e| {
+        let _ = fs::remove_file(from);
+        VentiError::IOError(format!("Could not write {}: {}", to.display(), e), None)
+    })
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Folds `bytes` into the 64-bit FNV-1a hash `hash`, which is the same on every platform
+/// and compiler version, unlike the hashers in `std`.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
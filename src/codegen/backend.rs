@@ -0,0 +1,354 @@
+use crate::errors::VentiError;
+use inkwell::attributes::AttributeLoc;
+use inkwell::context::Context;
+use inkwell::module::Module;
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+};
+use inkwell::OptimizationLevel;
+use std::ffi::{c_char, CString};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/*
+The backend turns a compiled module into files on disk. Textual IR and bitcode are written
+by CodeGen itself; everything else goes through an LLVM TargetMachine for the host or for
+the triple passed with `--target`. Executables are produced by emitting an object file
+and handing it to the system C compiler driver (`cc`, or whatever `CC` names), which also
+links in libc for printf, malloc and friends.
+
+WebAssembly has no libc to link against. For wasm triples CodeGen prints through functions
+imported from the host's `venti` module, and the object is linked into a `.wasm` module with
+`wasm-ld` (or whatever `WASM_LD` names) that exports `main` and leaves imports undefined.
+*/
+
+/// The kinds of artifact the compiler can produce.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputKind {
+    LlvmIr,
+    Bitcode,
+    Assembly,
+    Object,
+    Executable,
+    /// C source, which only the C backend writes.
+    C,
+}
+
+impl OutputKind {
+    /// Parses the value of the `--emit` flag.
+    pub fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "llvm-ir" => Some(OutputKind::LlvmIr),
+            "bc" => Some(OutputKind::Bitcode),
+            "asm" => Some(OutputKind::Assembly),
+            "obj" => Some(OutputKind::Object),
+            "exe" => Some(OutputKind::Executable),
+            "c" => Some(OutputKind::C),
+            _ => None,
+        }
+    }
+
+    /// The file extension used for this kind of output, if any.
+    pub fn extension(self, wasm: bool) -> Option<&'static str> {
+        match self {
+            OutputKind::LlvmIr => Some("ll"),
+            OutputKind::Bitcode => Some("bc"),
+            OutputKind::Assembly => Some("s"),
+            OutputKind::Object => Some("o"),
+            OutputKind::Executable if wasm => Some("wasm"),
+            OutputKind::Executable => None,
+            OutputKind::C => Some("c"),
+        }
+    }
+}
+
+/// Picks the output path for `input` when `-o` is not given: the input file's stem in the
+/// current directory, with the extension for `kind`.
+pub fn default_output_path(input: &Path, kind: OutputKind, wasm: bool) -> PathBuf {
+    let stem = input
+        .file_stem()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("output"));
+    match kind.extension(wasm) {
+        Some(extension) => stem.with_extension(extension),
+        None => stem,
+    }
+}
+
+/// Expands the `wasm32` and `wasm64` shorthands accepted by `--target` into full triples.
+pub fn normalize_triple(triple: &str) -> String {
+    match triple {
+        "wasm32" | "wasm64" => format!("{}-unknown-unknown", triple),
+        _ => triple.to_string(),
+    }
+}
+
+/// Returns true if `triple` names a WebAssembly target.
+pub fn is_wasm(triple: &str) -> bool {
+    triple.starts_with("wasm32") || triple.starts_with("wasm64")
+}
+
+/// Maps a numeric `-O` level to LLVM's optimization level.
+pub fn optimization_level(level: u8) -> OptimizationLevel {
+    match level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Less,
+        2 => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    }
+}
+
+/// Runs LLVM's standard pass pipeline for `-O<level>` over `module`.
+///
+/// Level 0 runs no passes. Higher levels use the `default<On>` pipelines, which start by
+/// promoting stack slots to registers (mem2reg) and include instcombine and GVN.
+pub fn optimize(module: &Module, machine: &TargetMachine, level: u8) -> Result<(), VentiError> {
+    if level == 0 {
+        return Ok(());
+    }
+    module
+        .run_passes(
+            &format!("default<O{}>", level.min(3)),
+            machine,
+            PassBuilderOptions::create(),
+        )
+        .map_err(|e| VentiError::CodegenError(e.to_string(), None))
+}
+
+/// Sets the inliner threshold used for calls to every function defined in `module`,
+/// replacing LLVM's default of 225 for the passes run by `optimize`.
+///
+/// Higher thresholds inline larger functions; functions marked `noinline` are never
+/// inlined.
+pub fn set_inline_threshold(module: &Module, threshold: u32) {
+    let context = module.get_context();
+    let attribute =
+        context.create_string_attribute("function-inline-threshold", &threshold.to_string());
+    for function in module.get_functions() {
+        if function.count_basic_blocks() > 0 {
+            function.add_attribute(AttributeLoc::Function, attribute);
+        }
+    }
+}
+
+/// Parses the value of the `--relocation-model` flag.
+pub fn relocation_model(flag: &str) -> Option<RelocMode> {
+    match flag {
+        "pic" => Some(RelocMode::PIC),
+        "static" => Some(RelocMode::Static),
+        _ => None,
+    }
+}
+
+/// Parses the value of the `--code-model` flag.
+pub fn code_model(flag: &str) -> Option<CodeModel> {
+    match flag {
+        "default" => Some(CodeModel::Default),
+        "small" => Some(CodeModel::Small),
+        "kernel" => Some(CodeModel::Kernel),
+        "medium" => Some(CodeModel::Medium),
+        "large" => Some(CodeModel::Large),
+        _ => None,
+    }
+}
+
+/// Creates a target machine for `triple`, or for the host when no triple is given.
+///
+/// Cross targets are compiled for LLVM's default CPU of that architecture.
+///
+/// # Arguments
+///
+/// * `triple` - The target triple, or `None` for the host.
+/// * `level` - The optimization level used for code generation.
+/// * `reloc` - The relocation model, or `None` for the target's usual one.
+/// * `code_model` - The code model, which bounds how far apart code and data may be.
+pub fn target_machine(
+    triple: Option<&str>,
+    level: OptimizationLevel,
+    reloc: Option<RelocMode>,
+    code_model: CodeModel,
+) -> Result<TargetMachine, VentiError> {
+    let config = InitializationConfig::default();
+    // wasm-ld expects non-relocatable code unless it is asked for a shared module
+    let reloc = match (reloc, triple) {
+        (Some(reloc), _) => reloc,
+        (None, Some(triple)) if is_wasm(triple) => RelocMode::Static,
+        (None, _) => RelocMode::PIC,
+    };
+    let (triple, cpu, features) = match triple {
+        Some(triple) => {
+            initialize_target(triple, &config)?;
+            (TargetTriple::create(triple), String::new(), String::new())
+        }
+        None => {
+            initialize_host()?;
+            (
+                TargetMachine::get_default_triple(),
+                TargetMachine::get_host_cpu_name().to_string(),
+                TargetMachine::get_host_cpu_features().to_string(),
+            )
+        }
+    };
+    let target =
+        Target::from_triple(&triple).map_err(|e| VentiError::CodegenError(e.to_string(), None))?;
+    target
+        .create_target_machine(
+            &triple, &cpu, &features, level,
+            // Position-independent code links into the PIE executables most systems default
+            // to, and into shared libraries
+            reloc, code_model,
+        )
+        .ok_or_else(|| {
+            VentiError::CodegenError(
+                format!(
+                    "Could not create a target machine for {}",
+                    triple.as_str().to_string_lossy()
+                ),
+                None,
+            )
+        })
+}
+
+/// Initializes the LLVM backend for the host, which the JIT also needs.
+pub fn initialize_host() -> Result<(), VentiError> {
+    Target::initialize_native(&InitializationConfig::default())
+        .map_err(|e| VentiError::CodegenError(e, None))
+}
+
+/// Initializes the LLVM backend for the architecture named at the start of `triple`.
+fn initialize_target(triple: &str, config: &InitializationConfig) -> Result<(), VentiError> {
+    let arch = triple.split('-').next().unwrap_or_default();
+    match arch {
+        "x86_64" | "i386" | "i586" | "i686" => Target::initialize_x86(config),
+        "aarch64" | "arm64" => Target::initialize_aarch64(config),
+        "riscv32" | "riscv64" => Target::initialize_riscv(config),
+        "wasm32" | "wasm64" => Target::initialize_webassembly(config),
+        arch if arch.starts_with("arm") || arch.starts_with("thumb") => {
+            Target::initialize_arm(config)
+        }
+        _ => {
+            return Err(VentiError::CodegenError(
+                format!("Unsupported target architecture '{}'", arch),
+                None,
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Stamps `module` with the triple and data layout of `machine`.
+pub fn configure_module(module: &Module, machine: &TargetMachine) {
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+}
+
+/// Writes `module` as a native object file for `machine`.
+pub fn write_object(
+    module: &Module,
+    machine: &TargetMachine,
+    path: &Path,
+) -> Result<(), VentiError> {
+    machine
+        .write_to_file(module, FileType::Object, path)
+        .map_err(|e| VentiError::CodegenError(e.to_string(), None))
+}
+
+/// Writes `module` as textual assembly for `machine`.
+pub fn write_assembly(
+    module: &Module,
+    machine: &TargetMachine,
+    path: &Path,
+) -> Result<(), VentiError> {
+    machine
+        .write_to_file(module, FileType::Assembly, path)
+        .map_err(|e| VentiError::CodegenError(e.to_string(), None))
+}
+
+/// Reads a module written with `CodeGen::write_bitcode` back into `context`.
+pub fn read_bitcode<'ctx>(context: &'ctx Context, path: &Path) -> Result<Module<'ctx>, VentiError> {
+    Module::parse_bitcode_from_path(path, context)
+        .map_err(|e| VentiError::IOError(format!("Could not read {}: {}", path.display(), e), None))
+}
+
+/// Runs the `main` of a compiled program with the JIT execution engine.
+///
+/// # Arguments
+///
+/// * `args` - The arguments `main` is called with, starting with the program's name.
+///
+/// # Returns
+///
+/// The program's exit code.
+pub fn run_jit(
+    module: &Module,
+    level: OptimizationLevel,
+    args: &[String],
+) -> Result<i32, VentiError> {
+    let execution_engine = module
+        .create_jit_execution_engine(level)
+        .map_err(|e| VentiError::RuntimeError(e.to_string(), None))?;
+    // An argument cannot hold a NUL byte, since it comes from the command line
+    let args = args
+        .iter()
+        .map(|arg| CString::new(arg.as_str()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| VentiError::RuntimeError(e.to_string(), None))?;
+    let mut argv = args.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
+    argv.push(std::ptr::null());
+    // Safety: `main` is generated by `CodeGen::compile` with exactly this signature, and
+    // `argv` and the strings it points to outlive the call
+    unsafe {
+        let main = execution_engine
+            .get_function::<unsafe extern "C" fn(i32, *const *const c_char) -> i32>("main")
+            .map_err(|e| VentiError::RuntimeError(e.to_string(), None))?;
+        Ok(main.call(args.len() as i32, argv.as_ptr()))
+    }
+}
+
+/// Links an object file into an executable with the system C compiler driver.
+///
+/// Objects built with the static relocation model cannot go into a position-independent
+/// executable, so `pie` should be false for them.
+pub fn link_executable(object: &Path, output: &Path, pie: bool) -> Result<(), VentiError> {
+    let linker = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let mut command = Command::new(&linker);
+    // Async functions run on pthreads, and the math builtins may call into libm
+    command.arg(object).arg("-pthread").arg("-lm");
+    if !pie {
+        command.arg("-no-pie");
+    }
+    run_linker(command.arg("-o").arg(output), &linker)
+}
+
+/// Links a WebAssembly object file into a `.wasm` module that exports `main`.
+///
+/// The print functions are left as imports for the host to provide.
+pub fn link_wasm(object: &Path, output: &Path) -> Result<(), VentiError> {
+    let linker = std::env::var("WASM_LD").unwrap_or_else(|_| "wasm-ld".to_string());
+    run_linker(
+        Command::new(&linker)
+            .args(["--no-entry", "--export=main", "--allow-undefined"])
+            .arg(object)
+            .arg("-o")
+            .arg(output),
+        &linker,
+    )
+}
+
+/// Runs a prepared linker command, turning a failed link into a codegen error.
+fn run_linker(command: &mut Command, linker: &str) -> Result<(), VentiError> {
+    let result = command.output().map_err(|e| {
+        VentiError::IOError(format!("Could not run linker '{}': {}", linker, e), None)
+    })?;
+    if !result.status.success() {
+        return Err(VentiError::CodegenError(
+            format!(
+                "Linking failed: {}",
+                String::from_utf8_lossy(&result.stderr).trim()
+            ),
+            None,
+        ));
+    }
+    Ok(())
+}
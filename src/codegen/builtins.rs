@@ -1,3 +1,60 @@
 pub fn print(value: &str) {
     println!("{}", value);
 }
+
+/// Returns true if `name` is a function the compiler provides rather than the program.
+///
+/// A function the program defines with the same name takes precedence.
+pub fn is_builtin(name: &str) -> bool {
+    arity(name).is_some()
+}
+
+/// Returns the number of arguments the builtin `name` takes, or `None` if there is no
+/// such builtin.
+pub fn arity(name: &str) -> Option<usize> {
+    match name {
+        "random" | "now_millis" | "timer" | "args" | "exec_status" | "channel" | "vec" => Some(0),
+        "join" | "len" | "alloc" | "free" | "panic_venti" | "upper" | "lower" | "trim"
+        | "to_int" | "to_float" | "sqrt" | "abs" | "floor" | "ceil" | "sin" | "cos"
+        | "read_file" | "input_venti" | "seed_random" | "sleep_ms" | "elapsed" | "env"
+        | "exit_venti" | "exec" | "json_parse" | "json_kind" | "json_stringify" | "http_get"
+        | "tcp_listen" | "tcp_accept" | "tcp_port" | "tcp_recv" | "tcp_close" | "recv"
+        | "collect" | "chars" | "sort" => Some(1),
+        "byte_at" | "contains" | "split" | "pow" | "min" | "max" | "write_file" | "append_file"
+        | "random_int" | "http_post" | "tcp_connect" | "tcp_send" | "send" | "map" | "filter"
+        | "sort_by" | "binary_search" => Some(2),
+        "substring" | "reduce" => Some(3),
+        _ => None,
+    }
+}
+
+/// Returns true if `name` is one of the math builtins, which take integers and floats alike.
+pub fn is_math(name: &str) -> bool {
+    matches!(
+        name,
+        "sqrt" | "pow" | "abs" | "floor" | "ceil" | "sin" | "cos" | "min" | "max"
+    )
+}
+
+/// Returns true if `name` is one of the builtins that take the values of an array, range,
+/// buffer or iterator.
+pub fn is_iterator(name: &str) -> bool {
+    matches!(name, "map" | "filter" | "reduce" | "collect")
+}
+
+/// Returns true if `name` is one of the builtins that sort or search the elements of a
+/// vector or buffer.
+pub fn is_sorting(name: &str) -> bool {
+    matches!(name, "sort" | "sort_by" | "binary_search")
+}
+
+/// Returns the value of the constant `name` the compiler provides, such as `PI`.
+///
+/// A variable the program declares with the same name takes precedence.
+pub fn constant(name: &str) -> Option<f64> {
+    match name {
+        "PI" => Some(std::f64::consts::PI),
+        "E" => Some(std::f64::consts::E),
+        _ => None,
+    }
+}
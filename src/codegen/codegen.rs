@@ -1,21 +1,123 @@
+use crate::cache::{Cache, CacheKey};
+use crate::codegen::debug::DebugInfo;
+use crate::codegen::incremental::FunctionGraph;
+use crate::codegen::{backend, builtins, runtime, symbols};
 use crate::errors::VentiError;
-use crate::venti_parser::ast::{BinOp, Expr, Statement};
-use async_std::task;
+use crate::semantic::analyzer;
+use crate::semantic::types::Type;
+use crate::symbols::{DefId, DefKind, ScopeId, SymbolTable};
+use crate::traits::{self, Traits};
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::ast::{
+    self, BinOp, Expr, ExprKind, InlineHint, MatchArm, Parameter, Pattern, Statement, UnaryOp,
+    VarType,
+};
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
 use inkwell::execution_engine::ExecutionEngine;
-use inkwell::module::Module;
-use inkwell::types::{BasicTypeEnum, IntType};
-use inkwell::values::{BasicValueEnum, FloatValue, IntValue};
-use inkwell::OptimizationLevel;
+use inkwell::intrinsics::Intrinsic;
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::module::{Linkage, Module};
+use inkwell::targets::TargetTriple;
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType};
+use inkwell::values::{
+    BasicMetadataValueEnum, BasicValue, BasicValueEnum, FloatValue, FunctionValue, GlobalValue,
+    IntValue, PointerValue, StructValue,
+};
+use inkwell::{
+    AddressSpace, AtomicOrdering, AtomicRMWBinOp, FloatPredicate, IntPredicate, OptimizationLevel,
+};
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr};
 use std::fs::File;
 use std::io::Write;
+use std::path::Path;
+use std::ptr;
+use std::thread;
+
+/// The symbol of the count of failed `assert_venti`s, which `venti test` reads after each
+/// test.
+const ASSERT_FAILURES: &str = "venti_assert_failures";
+
+/// The symbol host functions are called through: `void (ptr host, ptr args, ptr result)`.
+pub const HOST_CALL: &str = "venti_host_call";
+
+/// A function-local variable: the stack slot holding it and the type stored there.
+#[derive(Clone, Copy)]
+struct Local<'ctx> {
+    slot: PointerValue<'ctx>,
+    value_type: BasicTypeEnum<'ctx>,
+}
+
+/// The globals of the program a module has declared or defined, so each is added to it
+/// once.
+#[derive(Default)]
+struct Declared<'ctx> {
+    variables: HashMap<DefId, GlobalValue<'ctx>>,
+    // The table of each trait and type, by their names
+    trait_tables: HashMap<(String, String), GlobalValue<'ctx>>,
+}
 
 pub struct CodeGen<'ctx> {
     context: &'ctx Context,
     builder: Builder<'ctx>,
     module: Module<'ctx>,
-    execution_engine: ExecutionEngine<'ctx>,
+    opt_level: OptimizationLevel,
+    // Lower printventi to imported host functions instead of printf, for targets without libc
+    print_imports: bool,
+    // DWARF debug info, when compiling with `-g`
+    debug: Option<DebugInfo<'ctx>>,
+    locals: HashMap<String, Local<'ctx>>,
+    // Locals of the current function holding strings or maps, innermost last, each owning a
+    // reference that is released when it goes out of scope
+    owned: Vec<Local<'ctx>>,
+    // Slots holding the strings and maps made by the statements being compiled, innermost
+    // last, each owning a reference that is released when its statement ends
+    temporaries: Vec<Local<'ctx>>,
+    // The handler frames of the `try_venti` blocks the current function is inside,
+    // outermost first
+    handlers: Vec<PointerValue<'ctx>>,
+    // The number of parameters of each function the program declares. These take
+    // precedence over builtins of the same name
+    defined_functions: HashMap<String, usize>,
+    // The C type of each C function the program declares with `extern_venti`
+    extern_functions: HashMap<String, FunctionType<'ctx>>,
+    // The traits of the program and of earlier session entries, which give the order of
+    // the functions in the table a value of each is passed with
+    traits: Traits,
+    // The names the analyzer resolved in the program and in earlier session entries, whose
+    // types a module declares a top-level variable defined in another with
+    symbols: SymbolTable<Type>,
+    // The globals of the module being compiled
+    declared: Declared<'ctx>,
+    lambda_count: usize,
+    spawn_count: usize,
+    // True while compiling statements that sit directly at the top of the program
+    global_scope: bool,
+    // The module holding `main`, while a top-level function is compiled into a module of
+    // its own
+    program: Option<Module<'ctx>>,
+    // The modules of the earlier entries of an interactive session, and the JIT running them
+    session: Vec<Module<'ctx>>,
+    engine: Option<ExecutionEngine<'ctx>>,
+    // The symbol of the wrapper `call_function` generated for each function it has called
+    call_wrappers: HashMap<String, String>,
+    // The addresses the JIT resolves the host's external functions to
+    symbol_addresses: HashMap<String, usize>,
+    // The span of the statement being compiled, whose line is 0 when spans are not recorded
+    span: Span,
+    // Where the modules of top-level functions are kept between compilations, and the key
+    // of everything besides the program that changes them
+    function_cache: Option<(Cache, CacheKey)>,
+    // The key of each top-level function of the program being compiled
+    functions: FunctionGraph,
+    // How many threads may compile independent top-level functions at once
+    jobs: usize,
+    // The bitcode of the modules of the top-level functions other threads compiled, or the
+    // error compiling them, until they are linked in program order
+    precompiled: HashMap<String, Result<Vec<u8>, VentiError>>,
 }
 
 /*
@@ -25,7 +127,7 @@ Fields:
 *    context: A reference to the LLVM Context, which manages the memory and lifetime of LLVM objects.
 *    builder: A Builder used to generate LLVM instructions.
 *    module: A Module that contains the generated code.
-*    execution_engine: An ExecutionEngine that allows the JIT (Just-In-Time) compilation and execution of the generated code.
+*    opt_level: The optimization level used when the program is run with the JIT (Just-In-Time) execution engine, which is only created on demand.
 *    Methods: new(context: &'ctx Context) -> Self
 *    Creates a new CodeGen instance. This method initializes the LLVM Module, Builder, and ExecutionEngine. It also declares the printf function for use in generated code.
 
@@ -52,12 +154,13 @@ statement: The Statement node to be compiled.
 
 Returns:
 * Ok(()) on success, or an error (VentiError) if the statement cannot be compiled.
-* compile_async_function(&self, identifier: String, body: Vec<Statement>) -> Result<(), VentiError>
-* Compiles an asynchronous function by generating LLVM code for the function body and appending it to the module.
+* compile_async_function(&self, identifier: String, params: Vec<Parameter>, body: Vec<Statement>) -> Result<(), VentiError>
+* Compiles an asynchronous function. The body becomes a function of its own, and the async function itself spawns a task that runs the body on a new thread and returns the task.
 
 Parameters:
 
 identifier: The name of the async function.
+params: The parameters of the async function.
 body: A vector of Statement nodes representing the function body.
 
 Returns:
@@ -72,31 +175,26 @@ expr: The expression node to be compiled.
 Returns:
 
 A BasicValueEnum containing the compiled value, or an error (VentiError) if the expression cannot be compiled.
-async_task(&self, value: BasicValueEnum<'ctx>) -> BasicValueEnum<'ctx>
-A placeholder function for handling asynchronous tasks. In an actual implementation, this function would handle the creation and execution of asynchronous tasks.
+await_task(&self, task: BasicValueEnum<'ctx>) -> Result<BasicValueEnum<'ctx>, VentiError>
+Blocks until an asynchronous task has finished.
 
 Parameters:
 
-value: The value to be handled asynchronously.
+task: The task returned by calling an async function.
 
 Returns:
 
-The value as a BasicValueEnum. (In the current implementation, this function simply returns the input value.)
+The result of the task as a BasicValueEnum, or an error (VentiError) if the value is not a task.
 */
 
 impl<'ctx> CodeGen<'ctx> {
-    pub fn new(context: &'ctx Context) -> Self {
+    pub fn new(context: &'ctx Context, opt_level: OptimizationLevel) -> Self {
         let module = context.create_module("venti");
         let builder = context.create_builder();
-        let execution_engine = module
-            .create_jit_execution_engine(OptimizationLevel::None)
-            .unwrap();
 
         // Declare printf function
         let i32_type = context.i32_type();
-        let i8ptr_type = context
-            .i8_type()
-            .ptr_type(inkwell::AddressSpace::Generic.into());
+        let i8ptr_type = context.ptr_type(AddressSpace::default());
         let printf_type = i32_type.fn_type(&[i8ptr_type.into()], true);
         module.add_function("printf", printf_type, None);
 
@@ -104,130 +202,6012 @@ impl<'ctx> CodeGen<'ctx> {
             context,
             builder,
             module,
-            execution_engine,
+            opt_level,
+            print_imports: false,
+            debug: None,
+            locals: HashMap::new(),
+            owned: Vec::new(),
+            temporaries: Vec::new(),
+            handlers: Vec::new(),
+            defined_functions: HashMap::new(),
+            extern_functions: HashMap::new(),
+            traits: Traits::new(),
+            symbols: SymbolTable::new(),
+            declared: Declared::default(),
+            lambda_count: 0,
+            spawn_count: 0,
+            global_scope: false,
+            program: None,
+            session: Vec::new(),
+            engine: None,
+            call_wrappers: HashMap::new(),
+            symbol_addresses: HashMap::new(),
+            span: Span::default(),
+            function_cache: None,
+            functions: FunctionGraph::default(),
+            jobs: 1,
+            precompiled: HashMap::new(),
+        }
+    }
+
+    /// Lowers `printventi` to calls to host functions imported from the `venti` module
+    /// (`print_i64`, `print_f64` and `print_str`) instead of `printf`.
+    ///
+    /// Targets such as WebAssembly have no libc, so the embedder provides these.
+    pub fn with_print_imports(mut self) -> Self {
+        self.print_imports = true;
+        self
+    }
+
+    /// Names the module, which is part of the symbol of every function and top-level
+    /// variable in it.
+    pub fn with_module_name(self, name: &str) -> Self {
+        self.module.set_name(name);
+        self
+    }
+
+    /// Compiles the program for the target `triple`, which decides how the runtime uses
+    /// the libc constants that differ between platforms. Without a triple, the program is
+    /// compiled for the platform the compiler runs on.
+    pub fn with_target_triple(self, triple: &TargetTriple) -> Self {
+        self.module.set_triple(triple);
+        self
+    }
+
+    /// Compiles the program with the names the analyzer resolved in it, which give each
+    /// top-level variable its definition and type.
+    pub fn with_symbols(mut self, symbols: SymbolTable<Type>) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Replaces the names the analyzer resolved, before compiling a session entry checked
+    /// after the earlier ones.
+    pub fn set_symbols(&mut self, symbols: SymbolTable<Type>) {
+        self.symbols = symbols;
+    }
+
+    /// Emits DWARF debug info describing the program's functions, variables and source
+    /// lines, so it can be stepped through in a debugger.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The path of the source file being compiled.
+    /// * `pointer_bits` - The size of a pointer on the target, in bits.
+    pub fn with_debug_info(mut self, source: &Path, pointer_bits: u64) -> Self {
+        let optimized = self.opt_level != OptimizationLevel::None;
+        self.debug = Some(DebugInfo::new(
+            self.context,
+            &self.module,
+            source,
+            optimized,
+            pointer_bits,
+        ));
+        self
+    }
+
+    /// Keeps the module of each top-level function in `cache`, and reuses it instead of
+    /// compiling the function again while nothing its code depends on has changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The cache to keep the modules in.
+    /// * `key` - A key holding every option that changes the generated code, such as the
+    ///   target.
+    pub fn with_function_cache(mut self, cache: Cache, key: CacheKey) -> Self {
+        self.function_cache = Some((cache, key));
+        self
+    }
+
+    /// Compiles the program's independent top-level functions on up to `jobs` threads,
+    /// each with an LLVM context of its own, and links their modules in as if they had been
+    /// compiled one after another.
+    ///
+    /// A function is independent when it uses no top-level variables, whose types are only
+    /// known once the statements before the function are compiled. Functions are still
+    /// optimized together, after linking, so calls between them can be inlined.
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    pub fn compile(&mut self, statements: Vec<Statement>) -> Result<(), VentiError> {
+        let statements = traits::flatten(statements);
+        let statements = if self.function_cache.is_some() || self.jobs > 1 {
+            let key = match &self.function_cache {
+                Some((_, key)) => *key,
+                None => CacheKey::compiler(),
+            };
+            let key = key
+                .with_option("module", &self.module_name())
+                .with_option("print-imports", &self.print_imports.to_string());
+            self.functions = FunctionGraph::new(&statements, key);
+            self.compile_in_parallel(statements)?
+        } else {
+            statements
+        };
+        // Top-level statements run, in order, inside a generated `main`
+        self.compile_entry("main", statements, false, false)?;
+        if let Some(debug) = &self.debug {
+            debug.finalize();
+        }
+        self.verify()
+    }
+
+    /// Compiles one entry of an interactive session and runs it with the JIT.
+    ///
+    /// Every entry is compiled into a module of its own that is added to the same
+    /// execution engine, so it can use the top-level variables and functions of the
+    /// entries before it. An entry that fails to compile leaves the session as it was.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The checked top-level statements of the entry.
+    pub fn run_entry(&mut self, statements: Vec<Statement>) -> Result<(), VentiError> {
+        self.run_in_session(statements, false).map(|_| ())
+    }
+
+    /// Compiles the body of a `test_venti` block as one entry of the session and runs it,
+    /// after the entries holding the program's declarations.
+    ///
+    /// # Returns
+    ///
+    /// The number of assertions that failed while the test ran.
+    pub fn run_test(&mut self, body: Vec<Statement>) -> Result<i64, VentiError> {
+        // The body becomes a function, so its variables are locals and it may return early.
+        // No identifier contains a `.`, so the name cannot clash with the program's
+        let identifier = format!("test.{}", self.session.len());
+        let entry = vec![
+            Statement::Function {
+                identifier: identifier.clone(),
+                params: Vec::new(),
+                returns: None,
+                body,
+                inline: None,
+            },
+            Statement::FunctionCall {
+                identifier,
+                args: Vec::new(),
+            },
+        ];
+        self.run_in_session(entry, true).map(i64::from)
+    }
+
+    /// Compiles `statements` into a module of their own, adds it to the session's JIT and
+    /// runs it.
+    ///
+    /// # Returns
+    ///
+    /// The value the entry returns: the number of failed assertions with `tally`, else 0.
+    /// A runtime error the entry raises stops it and is returned as a
+    /// `VentiError::RuntimeError`, while what the entry defined stays in the session.
+    fn run_in_session(
+        &mut self,
+        statements: Vec<Statement>,
+        tally: bool,
+    ) -> Result<i32, VentiError> {
+        let name = format!("__venti_entry_{}", self.session.len());
+        let statements = traits::flatten(statements);
+        let engine =
+            self.extend_session(|codegen| codegen.compile_entry(&name, statements, true, tally))?;
+        // Safety: `compile_entry` generated the entry with exactly this signature, and it
+        // stores only a message from `venti_raise` in `raised`
+        unsafe {
+            let entry = engine
+                .get_function::<unsafe extern "C" fn(*mut *mut c_char) -> i32>(&name)
+                .map_err(|e| VentiError::RuntimeError(e.to_string(), None))?;
+            let mut raised = ptr::null_mut();
+            let code = entry.call(&mut raised);
+            if raised.is_null() {
+                Ok(code)
+            } else {
+                Err(raised_error(raised))
+            }
+        }
+    }
+
+    /// Parks the module being compiled in the session, builds a fresh one with `build` and
+    /// adds it to the session's JIT, creating the JIT on first use.
+    ///
+    /// A module that fails to build leaves the session as it was.
+    fn extend_session(
+        &mut self,
+        build: impl FnOnce(&mut Self) -> Result<(), VentiError>,
+    ) -> Result<&ExecutionEngine<'ctx>, VentiError> {
+        let module = self.create_module();
+        let previous = std::mem::replace(&mut self.module, module);
+        self.session.push(previous);
+        let declared = std::mem::take(&mut self.declared);
+        let defined_functions = self.defined_functions.clone();
+        let traits = self.traits.clone();
+        if let Err(error) = build(self).and_then(|_| self.verify()) {
+            self.declared = declared;
+            self.defined_functions = defined_functions;
+            self.traits = traits;
+            self.module = self
+                .session
+                .pop()
+                .expect("the previous module was just parked");
+            return Err(error);
+        }
+
+        if let Some(engine) = &self.engine {
+            engine.add_module(&self.module).map_err(|_| {
+                VentiError::RuntimeError("Could not add the entry to the JIT".to_string(), None)
+            })?;
+        } else {
+            let engine = self
+                .module
+                .create_jit_execution_engine(self.opt_level)
+                .map_err(|e| VentiError::RuntimeError(e.to_string(), None))?;
+            self.engine = Some(engine);
+        }
+        let engine = self.engine.as_ref().expect("the JIT was just created");
+        for (symbol, &address) in &self.symbol_addresses {
+            if let Some(function) = self.module.get_function(symbol) {
+                engine.add_global_mapping(&function, address);
+            }
+        }
+        Ok(engine)
+    }
+
+    /// Returns the type of the program's function `name`, for callers outside the
+    /// program, or `None` if the program has no such function.
+    ///
+    /// Parameters are i64 for ints and untyped ones, f64 for floats, i1 for bools and
+    /// `venti.str` for strings. Functions written in Venti return an i64; host functions
+    /// return whatever they were defined to.
+    pub fn function_type(&self, name: &str) -> Option<FunctionType<'ctx>> {
+        let arity = *self.defined_functions.get(name)?;
+        let symbol = symbols::mangle_function(&self.module_name(), name, arity);
+        std::iter::once(&self.module)
+            .chain(self.other_modules())
+            .find_map(|module| module.get_function(&symbol))
+            .map(|function| function.get_type())
+    }
+
+    /// Calls the program's function `name` with the session's JIT, once `run_entry` has
+    /// compiled it.
+    ///
+    /// Values cross between Rust and generated code in 64-bit slots: ints as they are,
+    /// bools as 0 or 1 and floats as their bits, except that a string takes two, the
+    /// address of its data and its length. A string passed in must be laid out as the
+    /// runtime lays out strings, with a reference count before its data and a NUL after
+    /// it, and outlive the call. The first call to each function generates a wrapper that
+    /// unpacks the argument slots and packs the result, in a session module of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The function to call.
+    /// * `args` - The argument slots, one or two per parameter.
+    ///
+    /// # Returns
+    ///
+    /// The slots holding the function's result, of which only a string uses the second,
+    /// or a `VentiError::RuntimeError` if the function raised one.
+    pub fn call_function(&mut self, name: &str, args: &[u64]) -> Result<[u64; 2], VentiError> {
+        let function_type = self.function_type(name).ok_or_else(|| {
+            VentiError::RuntimeError(format!("Undefined function '{}'", name), None)
+        })?;
+        let expected = function_type
+            .get_param_types()
+            .into_iter()
+            .map(slot_count)
+            .sum::<u64>();
+        if args.len() as u64 != expected {
+            return Err(VentiError::RuntimeError(
+                format!(
+                    "Function '{}' takes {} argument slots, got {}",
+                    name,
+                    expected,
+                    args.len()
+                ),
+                None,
+            ));
+        }
+
+        let wrapper = match self.call_wrappers.get(name) {
+            Some(wrapper) => wrapper.clone(),
+            None => {
+                let wrapper = format!("__venti_call_{}", self.session.len());
+                self.extend_session(|codegen| codegen.build_call_wrapper(name, &wrapper))?;
+                self.call_wrappers.insert(name.to_string(), wrapper.clone());
+                wrapper
+            }
+        };
+        let engine = self
+            .engine
+            .as_ref()
+            .expect("the wrapper was added to the JIT");
+        let mut result = [0; 2];
+        // Safety: `build_call_wrapper` generated the wrapper with exactly this signature,
+        // it reads only the slots counted above and stores only a message from
+        // `venti_raise` in `raised`
+        unsafe {
+            let call = engine
+                .get_function::<unsafe extern "C" fn(*const u64, *mut u64, *mut *mut c_char)>(
+                    &wrapper,
+                )
+                .map_err(|e| VentiError::RuntimeError(e.to_string(), None))?;
+            let mut raised = ptr::null_mut();
+            call.call(args.as_ptr(), result.as_mut_ptr(), &mut raised);
+            if !raised.is_null() {
+                return Err(raised_error(raised));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Generates the function `wrapper`, which takes pointers to the argument and result
+    /// slots `call_function` describes and calls the program's function `name` with them.
+    ///
+    /// The wrapper's third parameter points to where it stores the message of a runtime
+    /// error the function raises, instead of a result.
+    fn build_call_wrapper(&mut self, name: &str, wrapper: &str) -> Result<(), VentiError> {
+        let function = self
+            .defined_functions
+            .get(name)
+            .and_then(|&arity| self.function(name, arity))
+            .ok_or_else(|| {
+                VentiError::RuntimeError(format!("Undefined function '{}'", name), None)
+            })?;
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let wrapper = self.module.add_function(
+            wrapper,
+            self.context
+                .void_type()
+                .fn_type(&[ptr_type.into(), ptr_type.into(), ptr_type.into()], false),
+            None,
+        );
+        let entry = self.context.append_basic_block(wrapper, "entry");
+        let body = self.context.append_basic_block(wrapper, "body");
+        let catch = self.context.append_basic_block(wrapper, "catch");
+        self.builder.position_at_end(entry);
+        let (args, result, error_slot) = match wrapper.get_params()[..] {
+            [args, result, error_slot] => (
+                args.into_pointer_value(),
+                result.into_pointer_value(),
+                error_slot.into_pointer_value(),
+            ),
+            _ => unreachable!("the wrapper takes the argument, result and error slots"),
+        };
+        let frame = self.push_handler(body, catch)?;
+
+        self.builder.position_at_end(body);
+
+        let mut next = 0;
+        let mut values: Vec<BasicMetadataValueEnum> = Vec::new();
+        for param_type in function.get_type().get_param_types() {
+            values.push(self.unpack_slots(args, &mut next, param_type)?.into());
+        }
+        if let Some(value) = self
+            .builder
+            .build_call(function, &values, "result")?
+            .try_as_basic_value()
+            .left()
+        {
+            self.pack_slots(result, &mut 0, value)?;
+        }
+        self.restore_handler(frame)?;
+        self.builder.build_return(None)?;
+
+        self.builder.position_at_end(catch);
+        let message = self.caught_message(frame)?;
+        self.builder.build_store(error_slot, message)?;
+        self.builder.build_return(None)?;
+        Ok(())
+    }
+
+    /// Resolves the external function `symbol` to `address` in the session's JIT, for
+    /// functions the embedder provides.
+    pub fn map_symbol(&mut self, symbol: &str, address: usize) {
+        self.symbol_addresses.insert(symbol.to_string(), address);
+    }
+
+    /// Defines the function `name`, whose body is a function of the host program, so the
+    /// program can call it like one of its own.
+    ///
+    /// The definition packs its arguments into slots as `call_function` does and calls
+    /// `HOST_CALL`, which must be mapped with `map_symbol`, with `host`, the argument
+    /// slots and two slots for the result. A string result points at bytes owned by the
+    /// host, which are copied into a new string before anything else runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the program calls the function by.
+    /// * `param_types` - The types of its parameters, as `function_type` describes them.
+    /// * `return_type` - The type of its result.
+    /// * `host` - The address `HOST_CALL` is given to find the host's function.
+    pub fn define_host_function(
+        &mut self,
+        name: &str,
+        param_types: &[BasicTypeEnum<'ctx>],
+        return_type: BasicTypeEnum<'ctx>,
+        host: usize,
+    ) -> Result<(), VentiError> {
+        self.extend_session(|codegen| {
+            codegen.build_host_function(name, param_types, return_type, host)
+        })
+        .map(|_| ())
+    }
+
+    /// Generates the definition `define_host_function` describes.
+    fn build_host_function(
+        &mut self,
+        name: &str,
+        param_types: &[BasicTypeEnum<'ctx>],
+        return_type: BasicTypeEnum<'ctx>,
+        host: usize,
+    ) -> Result<(), VentiError> {
+        if self.defined_functions.contains_key(name) {
+            return Err(VentiError::CodegenError(
+                format!("Function '{}' is defined more than once", name),
+                None,
+            ));
+        }
+        self.defined_functions
+            .insert(name.to_string(), param_types.len());
+        let symbol = symbols::mangle_function(&self.module_name(), name, param_types.len());
+        let param_types = param_types
+            .iter()
+            .map(|&param_type| param_type.into())
+            .collect::<Vec<BasicMetadataTypeEnum>>();
+        let function =
+            self.module
+                .add_function(&symbol, return_type.fn_type(&param_types, false), None);
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let count = function
+            .get_type()
+            .get_param_types()
+            .into_iter()
+            .map(slot_count)
+            .sum::<u64>();
+        // An empty array would do, but a slot to point at keeps the IR unsurprising
+        let args = self
+            .builder
+            .build_alloca(i64_type.array_type(count.max(1) as u32), "args")?;
+        let mut next = 0;
+        for param in function.get_param_iter() {
+            self.pack_slots(args, &mut next, param)?;
+        }
+        let result = self
+            .builder
+            .build_alloca(i64_type.array_type(2), "result")?;
+        let host_call = self.module.get_function(HOST_CALL).unwrap_or_else(|| {
+            let fn_type = self
+                .context
+                .void_type()
+                .fn_type(&[ptr_type.into(), ptr_type.into(), ptr_type.into()], false);
+            self.module.add_function(HOST_CALL, fn_type, None)
+        });
+        let host = i64_type
+            .const_int(host as u64, false)
+            .const_to_pointer(ptr_type);
+        self.builder
+            .build_call(host_call, &[host.into(), args.into(), result.into()], "")?;
+
+        let value = self.unpack_slots(result, &mut 0, return_type)?;
+        let value = match value {
+            // Copy the host's bytes, which only live until its next call
+            BasicValueEnum::StructValue(string) => {
+                let (data, len) = self.string_parts(string)?;
+                let copy = runtime::helper(self.context, &self.module, "venti_str_copy")?;
+                let data = self
+                    .call_value(copy, &[data.into(), len.into()], "copy")?
+                    .into_pointer_value();
+                self.build_string_value(data, len)?.into()
+            }
+            value => value,
+        };
+        self.builder.build_return(Some(&value))?;
+        Ok(())
+    }
+
+    /// Stores `value` into `slots` from slot `next` on, in the layout `call_function`
+    /// describes, and moves `next` past the slots it took.
+    fn pack_slots(
+        &self,
+        slots: PointerValue<'ctx>,
+        next: &mut u64,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<(), VentiError> {
+        let i64_type = self.context.i64_type();
+        let words = match value {
+            BasicValueEnum::FloatValue(float) => {
+                vec![self
+                    .builder
+                    .build_bit_cast(float, i64_type, "bits")?
+                    .into_int_value()]
+            }
+            BasicValueEnum::IntValue(int) => {
+                vec![self
+                    .builder
+                    .build_int_z_extend_or_bit_cast(int, i64_type, "word")?]
+            }
+            BasicValueEnum::StructValue(string) => {
+                let (data, len) = self.string_parts(string)?;
+                vec![self.builder.build_ptr_to_int(data, i64_type, "data")?, len]
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Only ints, floats, bools and strings can be passed to or from the host"
+                        .to_string(),
+                    None,
+                ))
+            }
+        };
+        for word in words {
+            let slot = self.slot(slots, next)?;
+            self.builder.build_store(slot, word)?;
+        }
+        Ok(())
+    }
+
+    /// Loads a value of `value_type` from `slots` from slot `next` on, in the layout
+    /// `call_function` describes, and moves `next` past the slots it took.
+    fn unpack_slots(
+        &self,
+        slots: PointerValue<'ctx>,
+        next: &mut u64,
+        value_type: BasicTypeEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let slot = self.slot(slots, next)?;
+        let word = self
+            .builder
+            .build_load(i64_type, slot, "word")?
+            .into_int_value();
+        Ok(match value_type {
+            BasicTypeEnum::FloatType(float_type) => {
+                self.builder.build_bit_cast(word, float_type, "float")?
+            }
+            BasicTypeEnum::IntType(int_type) if int_type.get_bit_width() == 1 => self
+                .builder
+                .build_int_compare(IntPredicate::NE, word, i64_type.const_zero(), "bool")?
+                .into(),
+            BasicTypeEnum::StructType(_) => {
+                let ptr_type = self.context.ptr_type(AddressSpace::default());
+                let data = self.builder.build_int_to_ptr(word, ptr_type, "data")?;
+                let slot = self.slot(slots, next)?;
+                let len = self
+                    .builder
+                    .build_load(i64_type, slot, "len")?
+                    .into_int_value();
+                self.build_string_value(data, len)?.into()
+            }
+            _ => word.into(),
+        })
+    }
+
+    /// Returns a pointer to slot `next` of `slots` and moves `next` on to the slot after it.
+    fn slot(
+        &self,
+        slots: PointerValue<'ctx>,
+        next: &mut u64,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let index = i64_type.const_int(*next, false);
+        *next += 1;
+        // Safety: callers of the wrappers and host functions pass as many slots as are read
+        Ok(unsafe {
+            self.builder
+                .build_in_bounds_gep(i64_type, slots, &[index], "slot")?
+        })
+    }
+
+    /// Builds the `venti.str` value of the string with `data` and `len`.
+    fn build_string_value(
+        &self,
+        data: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+    ) -> Result<StructValue<'ctx>, VentiError> {
+        let string_type = runtime::string_type(self.context);
+        let string = self
+            .builder
+            .build_insert_value(string_type.get_undef(), data, 0, "string")?
+            .into_struct_value();
+        Ok(self
+            .builder
+            .build_insert_value(string, len, 1, "string")?
+            .into_struct_value())
+    }
+
+    /// Compiles top-level statements into the function `name`, which runs them in order
+    /// and returns the program's exit code.
+    ///
+    /// With `session`, the function is an entry of an interactive session. It flushes the
+    /// C library's output streams before it returns, so what it printed appears before
+    /// whatever the caller prints next, and it takes a pointer where it stores the message
+    /// of a runtime error its statements raise, returning 0 once one has. With `tally`, it
+    /// resets the count of failed assertions when it starts and returns that count
+    /// instead.
+    fn compile_entry(
+        &mut self,
+        name: &str,
+        statements: Vec<Statement>,
+        session: bool,
+        tally: bool,
+    ) -> Result<(), VentiError> {
+        // Declare every top-level function first so bodies can call functions defined
+        // later in the file, including mutually recursive ones
+        self.declare_top_level(&statements)?;
+
+        // A program's `main` keeps its arguments for `arg`, except in WebAssembly, where
+        // the host calls it with none
+        let takes_args = name == "main" && !self.print_imports;
+        let i32_type = self.context.i32_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let params = if takes_args {
+            vec![i32_type.into(), ptr_type.into()]
+        } else if session {
+            vec![ptr_type.into()]
+        } else {
+            Vec::new()
+        };
+        let entry_point = self
+            .module
+            .add_function(name, i32_type.fn_type(&params, false), None);
+        let entry = self.context.append_basic_block(entry_point, "entry");
+        self.builder.position_at_end(entry);
+        self.enter_debug_scope(entry_point);
+        let i64_type = self.context.i64_type();
+        if takes_args {
+            let (argc_global, argv_global) = runtime::process_args(self.context, &self.module);
+            let (argc, argv) = match (entry_point.get_nth_param(0), entry_point.get_nth_param(1)) {
+                (Some(argc), Some(argv)) => (argc.into_int_value(), argv),
+                _ => {
+                    return Err(VentiError::CodegenError(
+                        "Missing parameter of 'main'".to_string(),
+                        None,
+                    ))
+                }
+            };
+            let argc = self.builder.build_int_s_extend(argc, i64_type, "argc")?;
+            self.builder.build_store(argc_global, argc)?;
+            self.builder.build_store(argv_global, argv)?;
+        }
+        if tally {
+            let failures = self.assert_failures().as_pointer_value();
+            self.builder.build_store(failures, i64_type.const_zero())?;
+        }
+        let guard = if session {
+            let body = self.context.append_basic_block(entry_point, "entry_body");
+            let catch = self.context.append_basic_block(entry_point, "entry_catch");
+            let frame = self.push_handler(body, catch)?;
+            self.builder.position_at_end(body);
+            self.handlers.push(frame);
+            Some((frame, catch))
+        } else {
+            None
+        };
+
+        // A program ending in an integer expression exits with its value, like a
+        // `return_venti` of it
+        let exit = match name {
+            "main" => ast::exit_expression(&statements),
+            _ => None,
+        };
+        self.global_scope = true;
+        for (index, statement) in statements.into_iter().enumerate() {
+            match statement {
+                Statement::Function { .. } | Statement::AsyncFunction { .. } => {
+                    self.compile_in_own_module(statement)?
+                }
+                Statement::Expression(expr) if Some(index) == exit => {
+                    let value = self
+                        .compile_expr(expr)
+                        .map_err(|error| error.at(self.span))?;
+                    // Bools are integers to LLVM too, but only 1 bit wide
+                    let is_int = matches!(value, BasicValueEnum::IntValue(code)
+                        if code.get_type().get_bit_width() == 64);
+                    if is_int {
+                        self.build_return(Some(value))?;
+                    } else {
+                        self.release_temporaries(0)?;
+                    }
+                    self.temporaries.clear();
+                }
+                statement => self
+                    .compile_statement(statement)
+                    .map_err(|error| error.at(self.span))?,
+            }
+        }
+        self.global_scope = false;
+        if let Some((frame, _)) = guard {
+            self.handlers.pop();
+            if self.block_is_open() {
+                self.restore_handler(frame)?;
+            }
+        }
+        if self.block_is_open() {
+            if session {
+                self.flush_output()?;
+            }
+            let code = if tally {
+                let failures = self.assert_failures().as_pointer_value();
+                let count = self
+                    .builder
+                    .build_load(i64_type, failures, "failures")?
+                    .into_int_value();
+                self.builder
+                    .build_int_truncate(count, self.context.i32_type(), "code")?
+            } else {
+                self.context.i32_type().const_zero()
+            };
+            self.builder.build_return(Some(&code))?;
         }
+        if let Some((frame, catch)) = guard {
+            self.builder.position_at_end(catch);
+            let message = self.caught_message(frame)?;
+            let error_slot = entry_point
+                .get_first_param()
+                .ok_or_else(|| {
+                    VentiError::CodegenError("Missing parameter of the entry".to_string(), None)
+                })?
+                .into_pointer_value();
+            self.builder.build_store(error_slot, message)?;
+            self.flush_output()?;
+            self.builder.build_return(Some(&i32_type.const_zero()))?;
+        }
+        self.leave_debug_scope();
+        Ok(())
     }
 
-    pub fn compile(&self, statements: Vec<Statement>) -> Result<(), VentiError> {
+    /// Flushes the C library's output streams, so what generated code printed appears
+    /// before whatever the host prints next.
+    fn flush_output(&self) -> Result<(), VentiError> {
+        let fflush = runtime::libc(self.context, &self.module, "fflush")?;
+        let all_streams = self.context.ptr_type(AddressSpace::default()).const_null();
+        self.builder.build_call(fflush, &[all_streams.into()], "")?;
+        Ok(())
+    }
+
+    /// Adds the top-level functions and C functions of `statements` to the module, and
+    /// records their traits.
+    fn declare_top_level(&mut self, statements: &[Statement]) -> Result<(), VentiError> {
         for statement in statements {
-            self.compile_statement(statement)?;
+            self.traits.declare(statement);
+            if let Statement::Function {
+                identifier, params, ..
+            }
+            | Statement::AsyncFunction {
+                identifier, params, ..
+            } = statement
+            {
+                let return_type = match statement {
+                    Statement::AsyncFunction { .. } => self.task_return_type(),
+                    Statement::Function { returns, .. } => self.llvm_type(returns.as_ref()),
+                    _ => self.context.i64_type().into(),
+                };
+                self.declare_function(identifier, params, return_type)?;
+            }
+            if let Statement::Extern {
+                identifier,
+                params,
+                returns,
+            } = statement
+            {
+                self.declare_extern(identifier, params, returns.as_ref())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the program's independent top-level functions to other threads when there
+    /// are enough of them, each thread compiling its share into modules of their own.
+    ///
+    /// # Returns
+    ///
+    /// The program's statements, where each function that was moved is left as a
+    /// declaration marking where its module is linked in.
+    fn compile_in_parallel<'a>(
+        &mut self,
+        statements: Vec<Statement<'a>>,
+    ) -> Result<Vec<Statement<'a>>, VentiError> {
+        // Debug info belongs to the program's module, so it cannot be generated elsewhere
+        if self.jobs < 2 || self.debug.is_some() {
+            return Ok(statements);
         }
+        let detached = |statement: &Statement| {
+            declared_name(statement).is_some_and(|name| self.functions.is_independent(name))
+                && self
+                    .cached_function(statement)
+                    .is_none_or(|(cache, key)| cache.lookup_function(key).is_none())
+        };
+        let count = statements
+            .iter()
+            .filter(|statement| detached(statement))
+            .count();
+        if count < 2 {
+            return Ok(statements);
+        }
+
+        let jobs = self.jobs.min(count);
+        let mut shares = (0..jobs).map(|_| Vec::new()).collect::<Vec<_>>();
+        let mut declarations = Vec::new();
+        let mut remaining = Vec::with_capacity(statements.len());
+        let mut dealt = 0;
+        for statement in statements {
+            if let Some(declaration) = declaration(&statement) {
+                declarations.push(declaration);
+            }
+            if detached(&statement) {
+                remaining.extend(declaration(&statement));
+                // Functions are dealt out in turn, like cards
+                shares[dealt % jobs].push(statement);
+                dealt += 1;
+            } else {
+                remaining.push(statement);
+            }
+        }
+
+        let module_name = self.module_name();
+        let triple = self.module.get_triple();
+        let triple = triple.as_str().to_string_lossy().into_owned();
+        let (opt_level, print_imports) = (self.opt_level, self.print_imports);
+        let (declarations, symbols) = (&declarations, &self.symbols);
+        let (module_name, triple) = (&module_name, &triple);
+        let compiled = thread::scope(|scope| {
+            let workers = shares
+                .into_iter()
+                .map(|share| {
+                    scope.spawn(move || {
+                        compile_detached(
+                            module_name,
+                            triple,
+                            opt_level,
+                            print_imports,
+                            declarations,
+                            symbols,
+                            share,
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<Result<Vec<_>, VentiError>>()
+        })?;
+        self.precompiled.extend(compiled.into_iter().flatten());
+        Ok(remaining)
+    }
 
-        // Print the generated LLVM IR to a file
+    /// Checks the module being compiled, to catch broken IR here rather than in whatever
+    /// consumes the output.
+    fn verify(&self) -> Result<(), VentiError> {
+        self.module.verify().map_err(|message| {
+            VentiError::CodegenError(
+                format!(
+                    "Generated invalid LLVM IR:\n{}",
+                    message.to_string().trim_end()
+                ),
+                None,
+            )
+        })
+    }
+
+    /// Returns the module holding the compiled program.
+    pub fn module(&self) -> &Module<'ctx> {
+        &self.module
+    }
+
+    /// Runs the compiled program's `main` with the JIT execution engine, without
+    /// arguments.
+    ///
+    /// # Returns
+    ///
+    /// The program's exit code.
+    pub fn run(&self) -> Result<i32, VentiError> {
+        backend::run_jit(&self.module, self.opt_level, &[])
+    }
+
+    /// Writes the module's textual LLVM IR to `path`.
+    pub fn write_ir(&self, path: &Path) -> Result<(), VentiError> {
         let ir = self.module.print_to_string().to_string();
-        let mut file = File::create("output.ll").map_err(|e| VentiError::IOError(e.to_string()))?;
+        let mut file = File::create(path).map_err(|e| VentiError::IOError(e.to_string(), None))?;
         file.write_all(ir.as_bytes())
-            .map_err(|e| VentiError::IOError(e.to_string()))?;
+            .map_err(|e| VentiError::IOError(e.to_string(), None))?;
         Ok(())
     }
 
-    fn compile_statement(&self, statement: Statement) -> Result<(), VentiError> {
+    /// Writes the module as LLVM bitcode to `path`.
+    pub fn write_bitcode(&self, path: &Path) -> Result<(), VentiError> {
+        if self.module.write_bitcode_to_path(path) {
+            Ok(())
+        } else {
+            Err(VentiError::IOError(
+                format!("Could not write bitcode to {}", path.display()),
+                None,
+            ))
+        }
+    }
+
+    fn compile_statement(&mut self, statement: Statement) -> Result<(), VentiError> {
+        let previous_temporaries = self.temporaries.len();
+        let result = self.compile_statement_kind(statement).and_then(|()| {
+            if self.block_is_open() {
+                self.release_temporaries(previous_temporaries)
+            } else {
+                Ok(())
+            }
+        });
+        self.temporaries.truncate(previous_temporaries);
+        result
+    }
+
+    fn compile_statement_kind(&mut self, statement: Statement) -> Result<(), VentiError> {
         match statement {
             Statement::VariableDeclaration { identifier, value } => {
                 let value = self.compile_expr(value)?;
-                let global = self.module.add_global(value.get_type(), None, &identifier);
-                global.set_initializer(&value);
+                if !self.global_scope {
+                    return self.bind_local(&identifier, value);
+                }
+                let symbol = symbols::mangle_global(&self.module_name(), &identifier);
+                let global = self.module.add_global(value.get_type(), None, &symbol);
+                let defined = self.global_definition(&identifier).ok_or_else(|| {
+                    VentiError::CodegenError(
+                        format!("Variable '{}' was not checked", identifier),
+                        None,
+                    )
+                })?;
+                self.declared.variables.insert(defined, global);
+                if is_constant(value) {
+                    global.set_initializer(&value);
+                    return Ok(());
+                }
+                // Other initializers run where the declaration sits in `main`, so
+                // top-level variables are set up in declaration order
+                global.set_initializer(&value.get_type().const_zero());
+                self.retain(value)?;
+                self.builder.build_store(global.as_pointer_value(), value)?;
                 Ok(())
             }
-            Statement::FunctionCall { identifier, args } => {
-                let function = self.module.get_function(&identifier).ok_or_else(|| {
-                    VentiError::CodegenError(format!("Undefined function '{}'", identifier))
+            Statement::VariableAssignment { identifier, value } => {
+                if let Some(local) = self.locals.get(&identifier).copied() {
+                    let value = self.compile_expr(value)?;
+                    if value.get_type() != local.value_type {
+                        return Err(VentiError::CodegenError(
+                            format!(
+                                "Cannot assign a value of a different type to '{}'",
+                                identifier
+                            ),
+                            None,
+                        ));
+                    }
+                    return self.assign_slot(local.slot, value);
+                }
+                let global = self.global(&identifier).ok_or_else(|| {
+                    VentiError::CodegenError(format!("Undefined variable '{}'", identifier), None)
                 })?;
-                let compiled_args = args
-                    .into_iter()
-                    .map(|arg| self.compile_expr(arg))
-                    .collect::<Result<Vec<_>, _>>()?;
-                self.builder
-                    .build_call(function, &compiled_args, "call_func");
-                Ok(())
+                let value = self.compile_expr(value)?;
+                self.assign_slot(global.as_pointer_value(), value)
             }
-            Statement::Print(expr) => {
-                let value = self.compile_expr(expr)?;
-                let printf = self.module.get_function("printf").ok_or_else(|| {
-                    VentiError::CodegenError("Expected 'printf' function".to_string())
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => {
+                let target = self.compile_expr(target)?;
+                if let BasicValueEnum::StructValue(buffer) = target {
+                    if runtime::is_named(buffer.get_type(), runtime::BUFFER_TYPE_NAME) {
+                        let slot = self.buffer_slot(buffer, index)?;
+                        let value = self.compile_slot_value(value)?;
+                        self.builder.build_store(slot, value)?;
+                        return Ok(());
+                    }
+                    if runtime::is_named(buffer.get_type(), runtime::VECTOR_TYPE_NAME) {
+                        let header = self.expect_vector(target, "index")?;
+                        let index = self.compile_vector_index(index)?;
+                        // The value may push to the vector and move its slots, so the
+                        // slot is only found once the value is known
+                        let value = self.compile_slot_value(value)?;
+                        let slot = self.vector_slot(header, index)?;
+                        self.builder.build_store(slot, value)?;
+                        return Ok(());
+                    }
+                }
+                let header = self.map_header(target)?.ok_or_else(|| {
+                    VentiError::CodegenError(
+                        "Only map entries, buffer slots and vector elements can be assigned; arrays are immutable"
+                            .to_string(),
+                        None,
+                    )
                 })?;
+                let key = self.compile_map_key(index)?;
+                let value = self.compile_slot_value(value)?;
+                let insert = runtime::helper(self.context, &self.module, "venti_map_insert")?;
                 self.builder
-                    .build_call(printf, &[value.into()], "printf_call");
+                    .build_call(insert, &[header.into(), key.into(), value.into()], "")?;
+                Ok(())
+            }
+            Statement::FunctionCall { identifier, args } => {
+                self.compile_call(&identifier, args)?;
+                Ok(())
+            }
+            Statement::Print { value, newline } => {
+                let value = self.compile_expr(value)?;
+                self.compile_print(value, newline)
+            }
+            Statement::Expression(expr) => {
+                self.compile_expr(expr)?;
                 Ok(())
             }
-            Statement::AsyncFunction { identifier, body } => {
-                self.compile_async_function(identifier, body)
+            Statement::Function {
+                identifier,
+                params,
+                returns,
+                body,
+                inline,
+            } => self.compile_function(identifier, params, returns, body, inline),
+            Statement::Return(value) => {
+                let value = value.map(|value| self.compile_expr(value)).transpose()?;
+                self.build_return(value)
+            }
+            Statement::Match { scrutinee, arms } => self.compile_match(scrutinee, arms),
+            Statement::For {
+                variable,
+                iterable,
+                body,
+            } => self.compile_for(variable, iterable, body),
+            Statement::AsyncFunction {
+                identifier,
+                params,
+                body,
+                inline,
+            } => self.compile_async_function(identifier, params, body, inline),
+            Statement::Assert(condition) => self.compile_assert(condition),
+            Statement::Try {
+                body,
+                error,
+                handler,
+            } => self.compile_try(body, error, handler),
+            // Tests are compiled one at a time by `run_test`, and only under `venti test`
+            Statement::Test { .. } => Ok(()),
+            // Imports are merged into the program before it reaches codegen
+            Statement::Comment { .. } | Statement::Import(_) => Ok(()),
+            // C functions are declared by `compile_entry` before anything calls them
+            Statement::Extern { .. } => Ok(()),
+            // Traits are recorded by `compile_entry`, and implementations were flattened
+            // into functions by `compile`
+            Statement::Trait { .. } | Statement::Impl { .. } => Ok(()),
+            Statement::Line(span) => {
+                self.span = span;
+                if let Some(debug) = &mut self.debug {
+                    debug.set_line(span.line);
+                }
+                self.apply_debug_location();
+                Ok(())
             }
         }
     }
 
-    fn compile_async_function(
-        &self,
-        identifier: String,
-        body: Vec<Statement>,
-    ) -> Result<(), VentiError> {
-        let func_type = self.context.void_type().fn_type(&[], false);
-        let function = self.module.add_function(&identifier, func_type, None);
-        let entry = self.context.append_basic_block(function, "entry");
-        self.builder.position_at_end(entry);
+    /// Starts the debug info scope of `function`, which covers the code emitted until the
+    /// matching `leave_debug_scope`.
+    fn enter_debug_scope(&mut self, function: FunctionValue<'ctx>) {
+        if let Some(debug) = &mut self.debug {
+            debug.enter_function(function);
+        }
+        self.apply_debug_location();
+    }
 
-        for statement in body {
-            self.compile_statement(statement)?;
+    /// Returns to the debug info scope of the enclosing function.
+    fn leave_debug_scope(&mut self) {
+        if let Some(debug) = &mut self.debug {
+            debug.leave_function();
         }
+        self.apply_debug_location();
+    }
 
-        self.builder.build_return(None);
-        Ok(())
+    /// Points the builder's debug location at the current source line, so the
+    /// instructions that follow are attributed to it.
+    fn apply_debug_location(&self) {
+        let Some(debug) = &self.debug else {
+            return;
+        };
+        match debug.location(self.context) {
+            Some(location) => self.builder.set_current_debug_location(location),
+            None => self.builder.unset_current_debug_location(),
+        }
     }
 
-    fn compile_expr(&self, expr: Expr) -> Result<BasicValueEnum<'ctx>, VentiError> {
-        match expr {
-            Expr::Number(n) => Ok(self.context.i64_type().const_int(n as u64, false).into()),
-            Expr::Float(f) => Ok(self.context.f64_type().const_float(f).into()),
-            Expr::Boolean(b) => Ok(self.context.i1_type().const_int(b as u64, false).into()),
-            Expr::String(s) => Ok(self
-                .builder
-                .build_global_string_ptr(&s, "str")
-                .as_pointer_value()
-                .into()),
-            Expr::Identifier(id) => {
-                let global = self.module.get_global(&id).ok_or_else(|| {
-                    VentiError::CodegenError(format!("Undefined variable '{}'", id))
-                })?;
-                Ok(global.as_pointer_value().into())
-            }
-            Expr::BinaryOp(left, op, right) => {
-                let left = self.compile_expr(*left)?.into_int_value();
-                let right = self.compile_expr(*right)?.into_int_value();
-                let result = match op {
-                    BinOp::Add => self.builder.build_int_add(left, right, "tmpadd"),
-                    BinOp::Subtract => self.builder.build_int_sub(left, right, "tmpsub"),
-                    BinOp::Multiply => self.builder.build_int_mul(left, right, "tmpmul"),
-                    BinOp::Divide => self.builder.build_int_signed_div(left, right, "tmpdiv"),
-                };
-                Ok(result.into())
-            }
-            Expr::Array(elements) => {
-                let array_type = self.context.i32_type().array_type(elements.len() as u32);
-                let array_alloca = self.builder.build_alloca(array_type, "array");
-                for (i, element) in elements.into_iter().enumerate() {
-                    let value = self.compile_expr(element)?.into_int_value();
-                    let index = self.context.i32_type().const_int(i as u64, false);
-                    let ptr = unsafe {
-                        self.builder
-                            .build_gep(array_alloca, &[index.into()], "element_ptr", false)
-                    };
-                    self.builder.build_store(ptr, value);
-                }
-                Ok(array_alloca.into())
-            }
-            Expr::Await(inner_expr) => {
-                let inner_value = self.compile_expr(*inner_expr)?;
-                let async_task = self.async_task(inner_value);
-                Ok(async_task.into())
+    /// Adds a function's signature to the module without a body.
+    ///
+    /// # Returns
+    ///
+    /// The declared function, or a `VentiError` if a different function of that name exists.
+    fn declare_function(
+        &mut self,
+        identifier: &str,
+        params: &[Parameter],
+        return_type: BasicTypeEnum<'ctx>,
+    ) -> Result<FunctionValue<'ctx>, VentiError> {
+        let param_types = params
+            .iter()
+            .map(|param| self.llvm_type(param.var_type.as_ref()).into())
+            .collect::<Vec<BasicMetadataTypeEnum>>();
+        let fn_type = return_type.fn_type(&param_types, false);
+        let arity = *self
+            .defined_functions
+            .entry(identifier.to_string())
+            .or_insert(params.len());
+        match self.function(identifier, arity) {
+            Some(function) if function.get_type() == fn_type => Ok(function),
+            None if arity == params.len() => {
+                let symbol = symbols::mangle_function(&self.module_name(), identifier, arity);
+                Ok(self.module.add_function(&symbol, fn_type, None))
             }
             _ => Err(VentiError::CodegenError(
-                "Unsupported expression".to_string(),
+                format!(
+                    "Function '{}' is declared more than once with different parameters",
+                    identifier
+                ),
+                None,
+            )),
+        }
+    }
+
+    /// Records the C function `identifier` declared with `extern_venti` and declares it in
+    /// the module under its own, unmangled name.
+    ///
+    /// The parameter and result types are C's: `int` is an `int`, `float` a `double`,
+    /// `bool` a `_Bool` and `string` a `const char *`. A function with no result type
+    /// returns `void`.
+    ///
+    /// # Returns
+    ///
+    /// Nothing, or a `VentiError` if the function was already declared with other types.
+    fn declare_extern(
+        &mut self,
+        identifier: &str,
+        params: &[Parameter],
+        returns: Option<&VarType>,
+    ) -> Result<(), VentiError> {
+        let param_types = params
+            .iter()
+            .map(|param| self.c_type(param.var_type.as_ref()).into())
+            .collect::<Vec<BasicMetadataTypeEnum>>();
+        let fn_type = match returns {
+            Some(var_type) => self.c_type(Some(var_type)).fn_type(&param_types, false),
+            None => self.context.void_type().fn_type(&param_types, false),
+        };
+        if let Some(&existing) = self.extern_functions.get(identifier) {
+            if existing != fn_type {
+                return Err(VentiError::CodegenError(
+                    format!(
+                        "C function '{}' is declared more than once with different types",
+                        identifier
+                    ),
+                    None,
+                ));
+            }
+        }
+        self.extern_functions
+            .insert(identifier.to_string(), fn_type);
+        self.extern_function(identifier).map(|_| ())
+    }
+
+    /// Returns the C type a parameter or result of a C function annotated `var_type` has.
+    fn c_type(&self, var_type: Option<&VarType>) -> BasicTypeEnum<'ctx> {
+        match var_type {
+            Some(VarType::Float) => self.context.f64_type().into(),
+            Some(VarType::Bool) => self.context.bool_type().into(),
+            Some(VarType::String) => self.context.ptr_type(AddressSpace::default()).into(),
+            // The checker rejects C functions taking traits or vectors
+            Some(VarType::Int) | Some(VarType::Trait(_)) | Some(VarType::Vec) | None => {
+                self.context.i32_type().into()
+            }
+        }
+    }
+
+    /// Looks up the C function `name` declared with `extern_venti`, declaring it in the
+    /// current module with the C calling convention on first use.
+    ///
+    /// # Returns
+    ///
+    /// The function, or a `VentiError` if the module already has a function of that name
+    /// with other types, such as one the runtime uses.
+    fn extern_function(&self, name: &str) -> Result<FunctionValue<'ctx>, VentiError> {
+        let fn_type = *self.extern_functions.get(name).ok_or_else(|| {
+            VentiError::CodegenError(format!("Undefined C function '{}'", name), None)
+        })?;
+        match self.module.get_function(name) {
+            Some(function) if function.get_type() == fn_type => Ok(function),
+            Some(_) => Err(VentiError::CodegenError(
+                format!(
+                    "C function '{}' is declared with other types than the runtime uses",
+                    name
+                ),
+                None,
             )),
+            None => {
+                let function = self
+                    .module
+                    .add_function(name, fn_type, Some(Linkage::External));
+                function.set_call_conventions(0); // The C calling convention
+                                                  // C passes a `_Bool` as a whole byte, zero-extended from its one bit
+                let zeroext = self
+                    .context
+                    .create_enum_attribute(Attribute::get_named_enum_kind_id("zeroext"), 0);
+                let bool_type = self.context.bool_type().into();
+                for (index, param_type) in fn_type.get_param_types().into_iter().enumerate() {
+                    if param_type == bool_type {
+                        function.add_attribute(AttributeLoc::Param(index as u32), zeroext);
+                    }
+                }
+                if fn_type.get_return_type() == Some(bool_type) {
+                    function.add_attribute(AttributeLoc::Return, zeroext);
+                }
+                Ok(function)
+            }
+        }
+    }
+
+    /// Compiles the top-level function `statement` into a fresh module and links that into
+    /// the program's module.
+    ///
+    /// Each function's module only declares the functions and top-level variables it
+    /// uses, so it can be generated without looking inside the rest of the program. Its
+    /// runtime helpers are private to it and get renamed when linking if another module
+    /// has them too.
+    ///
+    fn compile_in_own_module(&mut self, statement: Statement) -> Result<(), VentiError> {
+        let module = self.function_module(statement)?;
+        self.module
+            .link_in_module(module)
+            .map_err(|e| VentiError::CodegenError(e.to_string(), None))
+    }
+
+    /// Returns the module of the top-level function `statement`.
+    ///
+    /// With a function cache, the module is read from the cache when it has one for the
+    /// function's key, and stored there once it is compiled otherwise. A function another
+    /// thread compiled is read from the bitcode that thread produced.
+    fn function_module(&mut self, statement: Statement) -> Result<Module<'ctx>, VentiError> {
+        let cached = self.cached_function(&statement);
+        if let Some((cache, key)) = &cached {
+            // An entry that cannot be read is compiled again and replaced
+            let module = cache
+                .lookup_function(*key)
+                .and_then(|path| backend::read_bitcode(self.context, &path).ok());
+            if let Some(module) = module {
+                return Ok(module);
+            }
+        }
+
+        let module = match declared_name(&statement).and_then(|name| self.precompiled.remove(name))
+        {
+            Some(bitcode) => {
+                let buffer = MemoryBuffer::create_from_memory_range_copy(&bitcode?, "function");
+                Module::parse_bitcode_from_buffer(&buffer, self.context)
+                    .map_err(|e| VentiError::CodegenError(e.to_string(), None))?
+            }
+            None => {
+                // Symbols are mangled with the module name, so every module shares it
+                let module = self.create_module();
+                self.program = Some(std::mem::replace(&mut self.module, module));
+                let declared = std::mem::take(&mut self.declared);
+                let result = self.compile_statement(statement);
+                self.declared = declared;
+                let program = self
+                    .program
+                    .take()
+                    .expect("the program module is parked while a function is compiled");
+                let module = std::mem::replace(&mut self.module, program);
+                result?;
+                // Linking a module whose struct types are the program's own strips their
+                // names, so the function's module is read back with types of its own
+                let buffer = module.write_bitcode_to_memory();
+                Module::parse_bitcode_from_buffer(&buffer, self.context)
+                    .map_err(|e| VentiError::CodegenError(e.to_string(), None))?
+            }
+        };
+        if let Some((cache, key)) = cached {
+            // The cache only saves time, so failing to write it does not fail the build
+            let _ = cache.store_function(key, |path| {
+                if module.write_bitcode_to_path(path) {
+                    Ok(())
+                } else {
+                    Err(VentiError::IOError(
+                        format!("Could not write bitcode to {}", path.display()),
+                        None,
+                    ))
+                }
+            });
+        }
+        Ok(module)
+    }
+
+    /// Returns the cache and key the module of the top-level function `statement` is kept
+    /// under, or `None` if it is not cached.
+    fn cached_function(&self, statement: &Statement) -> Option<(Cache, CacheKey)> {
+        // Debug info describes the lines a function is on, which its key leaves out
+        if self.debug.is_some() {
+            return None;
+        }
+        let identifier = declared_name(statement)?;
+        let (cache, _) = self.function_cache.as_ref()?;
+        Some((cache.clone(), self.functions.key(identifier)?))
+    }
+
+    /// Returns the modules whose functions the module being compiled may use: the
+    /// program's, and those of earlier session entries.
+    fn other_modules(&self) -> impl Iterator<Item = &Module<'ctx>> {
+        self.program.iter().chain(self.session.iter().rev())
+    }
+
+    /// Returns the name of the module being compiled.
+    fn module_name(&self) -> String {
+        self.module.get_name().to_string_lossy().into_owned()
+    }
+
+    /// Creates an empty module with the name and target triple of the module being
+    /// compiled.
+    fn create_module(&self) -> Module<'ctx> {
+        let module = self.context.create_module(&self.module_name());
+        module.set_triple(&self.module.get_triple());
+        module
+    }
+
+    /// Looks up the program's function `name` taking `arity` arguments by its symbol.
+    ///
+    /// A function from another module is declared in the current one on first use.
+    fn function(&self, name: &str, arity: usize) -> Option<FunctionValue<'ctx>> {
+        let symbol = symbols::mangle_function(&self.module_name(), name, arity);
+        self.module.get_function(&symbol).or_else(|| {
+            let function = self
+                .other_modules()
+                .find_map(|module| module.get_function(&symbol))?;
+            Some(self.module.add_function(&symbol, function.get_type(), None))
+        })
+    }
+
+    /// Returns the count of failed assertions, which every module shares.
+    ///
+    /// The first module to need the count defines it; later ones declare it.
+    fn assert_failures(&self) -> GlobalValue<'ctx> {
+        if let Some(global) = self.module.get_global(ASSERT_FAILURES) {
+            return global;
+        }
+        let i64_type = self.context.i64_type();
+        let defined = self
+            .other_modules()
+            .any(|module| module.get_global(ASSERT_FAILURES).is_some());
+        let global = self.module.add_global(i64_type, None, ASSERT_FAILURES);
+        if !defined {
+            global.set_initializer(&i64_type.const_zero());
+        }
+        global
+    }
+
+    /// Compiles `assert_venti`, which prints where a false condition is and counts it as a
+    /// failure, without stopping the program.
+    fn compile_assert(&mut self, condition: Expr) -> Result<(), VentiError> {
+        let condition = match self.compile_expr(condition)? {
+            BasicValueEnum::IntValue(condition) => condition,
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "assert_venti needs an int or bool condition".to_string(),
+                    None,
+                ))
+            }
+        };
+        let holds = self.builder.build_int_compare(
+            IntPredicate::NE,
+            condition,
+            condition.get_type().const_zero(),
+            "holds",
+        )?;
+        let function = self.current_function()?;
+        let failed_block = self.context.append_basic_block(function, "assert_failed");
+        let end_block = self.context.append_basic_block(function, "assert_end");
+        self.builder
+            .build_conditional_branch(holds, end_block, failed_block)?;
+
+        self.builder.position_at_end(failed_block);
+        let message = match self.span.line {
+            0 => "Assertion failed".to_string(),
+            line => format!("Assertion failed on line {}", line),
+        };
+        let message = self.compile_expr(Expr::new(ExprKind::String(message), self.span))?;
+        self.compile_print(message, true)?;
+        // Tasks may assert at the same time as the code that spawned them
+        self.builder.build_atomicrmw(
+            AtomicRMWBinOp::Add,
+            self.assert_failures().as_pointer_value(),
+            self.context.i64_type().const_int(1, false),
+            AtomicOrdering::SequentiallyConsistent,
+        )?;
+        self.builder.build_unconditional_branch(end_block)?;
+
+        self.builder.position_at_end(end_block);
+        Ok(())
+    }
+
+    /// Compiles `try_venti`, which runs `body` and, if it raises a runtime error, stops it
+    /// and runs `handler` with the error's message bound to `error`.
+    ///
+    /// The block installs a handler frame for the runtime's `venti_raise` to jump back to
+    /// (see `push_handler`).
+    fn compile_try(
+        &mut self,
+        body: Vec<Statement>,
+        error: String,
+        handler: Vec<Statement>,
+    ) -> Result<(), VentiError> {
+        let function = self.current_function()?;
+        let body_block = self.context.append_basic_block(function, "try_body");
+        let catch_block = self.context.append_basic_block(function, "try_catch");
+        let end_block = self.context.append_basic_block(function, "try_end");
+        let frame = self.push_handler(body_block, catch_block)?;
+
+        self.builder.position_at_end(body_block);
+        self.handlers.push(frame);
+        let result = self.compile_block(body, Vec::new());
+        self.handlers.pop();
+        result?;
+        if self.block_is_open() {
+            self.restore_handler(frame)?;
+            self.builder.build_unconditional_branch(end_block)?;
+        }
+
+        self.builder.position_at_end(catch_block);
+        let message = self.caught_message(frame)?;
+        let text = self.c_string(message)?;
+        let free = runtime::libc(self.context, &self.module, "free")?;
+        self.builder.build_call(free, &[message.into()], "")?;
+        self.compile_block(handler, vec![(error, text.into())])?;
+        if self.block_is_open() {
+            self.builder.build_unconditional_branch(end_block)?;
+        }
+
+        self.builder.position_at_end(end_block);
+        Ok(())
+    }
+
+    /// Puts a handler frame on the current function's stack and makes it the thread's
+    /// innermost handler, then carries on in `body`, or in `catch` once `venti_raise` has
+    /// jumped back to it (see `runtime`).
+    ///
+    /// Since `setjmp` returns a second time with whatever the registers held when the error
+    /// was raised, the function is left unoptimized, which keeps every local in its stack
+    /// slot.
+    ///
+    /// # Returns
+    ///
+    /// The frame, which must be made to give way to the handler it replaced before the
+    /// function returns.
+    fn push_handler(
+        &mut self,
+        body: BasicBlock<'ctx>,
+        catch: BasicBlock<'ctx>,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let function = self.current_function()?;
+        for name in ["noinline", "optnone"] {
+            let kind = Attribute::get_named_enum_kind_id(name);
+            function.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_enum_attribute(kind, 0),
+            );
         }
+        let handler_type = runtime::handler_type(self.context);
+        let frame = self.build_entry_alloca(handler_type.into(), "handler")?;
+        let handler_get = runtime::helper(self.context, &self.module, "venti_handler_get")?;
+        let handler_set = runtime::helper(self.context, &self.module, "venti_handler_set")?;
+        let previous = self.call_value(handler_get, &[], "previous_handler")?;
+        let previous_ptr = self
+            .builder
+            .build_struct_gep(handler_type, frame, 0, "previous_ptr")?;
+        self.builder.build_store(previous_ptr, previous)?;
+        self.builder.build_call(handler_set, &[frame.into()], "")?;
+
+        let setjmp = runtime::libc(self.context, &self.module, "setjmp")?;
+        let jump_buffer = self
+            .builder
+            .build_struct_gep(handler_type, frame, 2, "jump_buffer")?;
+        let jumped = self
+            .call_value(setjmp, &[jump_buffer.into()], "jumped")?
+            .into_int_value();
+        let is_caught = self.builder.build_int_compare(
+            IntPredicate::NE,
+            jumped,
+            jumped.get_type().const_zero(),
+            "is_caught",
+        )?;
+        self.builder
+            .build_conditional_branch(is_caught, catch, body)?;
+        Ok(frame)
     }
 
-    fn async_task(&self, value: BasicValueEnum<'ctx>) -> BasicValueEnum<'ctx> {
-        // Placeholder: In actual implementation, handle async task creation
-        value
+    /// Loads the malloc'd message of the error the handler `frame` caught, once
+    /// `venti_raise` has put the previous handler back.
+    fn caught_message(&self, frame: PointerValue<'ctx>) -> Result<PointerValue<'ctx>, VentiError> {
+        let message_ptr = self.builder.build_struct_gep(
+            runtime::handler_type(self.context),
+            frame,
+            1,
+            "message_ptr",
+        )?;
+        Ok(self
+            .builder
+            .build_load(
+                self.context.ptr_type(AddressSpace::default()),
+                message_ptr,
+                "message",
+            )?
+            .into_pointer_value())
+    }
+
+    /// Makes the handler `frame` replaced the innermost one again.
+    fn restore_handler(&self, frame: PointerValue<'ctx>) -> Result<(), VentiError> {
+        let handler_type = runtime::handler_type(self.context);
+        let previous_ptr = self
+            .builder
+            .build_struct_gep(handler_type, frame, 0, "previous_ptr")?;
+        let previous = self.builder.build_load(
+            self.context.ptr_type(AddressSpace::default()),
+            previous_ptr,
+            "previous_handler",
+        )?;
+        let handler_set = runtime::helper(self.context, &self.module, "venti_handler_set")?;
+        self.builder
+            .build_call(handler_set, &[previous.into()], "")?;
+        Ok(())
+    }
+
+    /// Looks up the program's top-level variable `name`.
+    ///
+    /// A variable defined in another module is declared in the current one on first use,
+    /// with the type the analyzer gave it.
+    fn global(&mut self, name: &str) -> Option<GlobalValue<'ctx>> {
+        let defined = self.global_definition(name)?;
+        if let Some(&global) = self.declared.variables.get(&defined) {
+            return Some(global);
+        }
+        let value_type = self.value_type(&self.symbols.definition(defined).info)?;
+        let symbol = symbols::mangle_global(&self.module_name(), name);
+        let global = self.module.add_global(value_type, None, &symbol);
+        self.declared.variables.insert(defined, global);
+        Some(global)
+    }
+
+    /// Finds the definition of the top-level variable `name` among the names the analyzer
+    /// resolved.
+    fn global_definition(&self, name: &str) -> Option<DefId> {
+        let defined = self.symbols.lookup_in(ScopeId::ROOT, name)?;
+        (self.symbols.definition(defined).kind == DefKind::Variable).then_some(defined)
+    }
+
+    /// Compiles a function definition into its own LLVM function.
+    ///
+    /// Parameters are bound as locals for the duration of the body, and the builder is
+    /// returned to wherever it was positioned before the definition.
+    fn compile_function(
+        &mut self,
+        identifier: String,
+        params: Vec<Parameter>,
+        returns: Option<VarType>,
+        body: Vec<Statement>,
+        inline: Option<InlineHint>,
+    ) -> Result<(), VentiError> {
+        let function =
+            self.declare_function(&identifier, &params, self.llvm_type(returns.as_ref()))?;
+        if function.count_basic_blocks() > 0 {
+            return Err(VentiError::CodegenError(
+                format!("Function '{}' is defined more than once", identifier),
+                None,
+            ));
+        }
+        if let Some(hint) = inline {
+            // `inlinehint` only raises the inliner's threshold, `noinline` forbids inlining
+            let name = match hint {
+                InlineHint::Inline => "inlinehint",
+                InlineHint::NoInline => "noinline",
+            };
+            let kind = Attribute::get_named_enum_kind_id(name);
+            function.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_enum_attribute(kind, 0),
+            );
+        }
+        let names = params.into_iter().map(|param| param.name).collect();
+        self.compile_function_body(function, names, body)
+    }
+
+    /// Compiles `body` into the empty function `function`, binding its parameters to
+    /// `names` as locals.
+    ///
+    /// The builder is returned to wherever it was positioned before.
+    fn compile_function_body(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        names: Vec<String>,
+        body: Vec<Statement>,
+    ) -> Result<(), VentiError> {
+        let previous_block = self.builder.get_insert_block();
+        let previous_locals = std::mem::take(&mut self.locals);
+        let previous_owned = std::mem::take(&mut self.owned);
+        let previous_temporaries = std::mem::take(&mut self.temporaries);
+        let previous_handlers = std::mem::take(&mut self.handlers);
+        let previous_scope = std::mem::replace(&mut self.global_scope, false);
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        self.enter_debug_scope(function);
+
+        for (index, (name, value)) in names.iter().zip(function.get_param_iter()).enumerate() {
+            value.set_name(name);
+            self.bind_variable(name, value, Some(index as u32 + 1))?;
+        }
+        for statement in body {
+            self.compile_statement(statement)?;
+        }
+
+        // Falling off the end of a function returns 0
+        if self.block_is_open() {
+            self.build_return(None)?;
+        }
+        self.leave_debug_scope();
+
+        self.locals = previous_locals;
+        self.owned = previous_owned;
+        self.temporaries = previous_temporaries;
+        self.handlers = previous_handlers;
+        self.global_scope = previous_scope;
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        Ok(())
+    }
+
+    /// Prints `value` with a single `printf` call whose format string follows its type.
+    fn compile_print(
+        &mut self,
+        value: BasicValueEnum<'ctx>,
+        newline: bool,
+    ) -> Result<(), VentiError> {
+        if self.print_imports {
+            self.print_with_imports(value)?;
+            if newline {
+                self.print_import_str("\n")?;
+            }
+            return Ok(());
+        }
+
+        let mut format = String::new();
+        let mut args = Vec::new();
+        self.format_value(value, &mut format, &mut args)?;
+        if newline {
+            format.push('\n');
+        }
+
+        let printf = runtime::libc(self.context, &self.module, "printf")?;
+        let format = self
+            .builder
+            .build_global_string_ptr(&format, "format")?
+            .as_pointer_value();
+        let mut printf_args: Vec<BasicMetadataValueEnum> = vec![format.into()];
+        printf_args.extend(args.into_iter().map(BasicMetadataValueEnum::from));
+        self.builder
+            .build_call(printf, &printf_args, "printf_call")?;
+        Ok(())
+    }
+
+    /// Prints `value` through the host's print imports, following the same layout as
+    /// `format_value`.
+    fn print_with_imports(&mut self, value: BasicValueEnum<'ctx>) -> Result<(), VentiError> {
+        match value {
+            BasicValueEnum::IntValue(value) if value.get_type().get_bit_width() == 1 => {
+                let text = self.bool_string(value)?;
+                self.print_with_imports(text.into())
+            }
+            BasicValueEnum::IntValue(value) => {
+                let value = self.builder.build_int_s_extend_or_bit_cast(
+                    value,
+                    self.context.i64_type(),
+                    "print_int",
+                )?;
+                self.call_print_import("print_i64", value.into())
+            }
+            BasicValueEnum::FloatValue(value) => {
+                let value =
+                    self.builder
+                        .build_float_ext(value, self.context.f64_type(), "print_float")?;
+                self.call_print_import("print_f64", value.into())
+            }
+            BasicValueEnum::StructValue(string)
+                if runtime::is_named(string.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                let (data, _) = self.printable_string(string)?;
+                self.call_print_import("print_str", data.into())
+            }
+            BasicValueEnum::StructValue(json)
+                if runtime::is_named(json.get_type(), runtime::JSON_TYPE_NAME) =>
+            {
+                let text = self.json_text(json)?;
+                self.print_with_imports(text)
+            }
+            BasicValueEnum::ArrayValue(array) => {
+                self.print_import_str("[")?;
+                for index in 0..array.get_type().len() {
+                    if index > 0 {
+                        self.print_import_str(", ")?;
+                    }
+                    let element = self
+                        .builder
+                        .build_extract_value(array, index, "print_elem")?;
+                    self.print_with_imports(element)?;
+                }
+                self.print_import_str("]")
+            }
+            BasicValueEnum::StructValue(range)
+                if runtime::is_named(range.get_type(), runtime::RANGE_TYPE_NAME) =>
+            {
+                let start = self.builder.build_extract_value(range, 0, "range_start")?;
+                let end = self.builder.build_extract_value(range, 1, "range_end")?;
+                self.call_print_import("print_i64", start)?;
+                self.print_import_str("..")?;
+                self.call_print_import("print_i64", end)
+            }
+            _ => Err(VentiError::CodegenError(
+                "This value cannot be printed".to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Returns the JSON value `json` as a string to print: a string as its text, and any
+    /// other value as JSON.
+    fn json_text(&self, json: StructValue<'ctx>) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let node = self.builder.build_extract_value(json, 0, "json_node")?;
+        let text = runtime::helper(self.context, &self.module, "venti_json_text")?;
+        self.call_value(text, &[node.into()], "json_text")
+    }
+
+    /// Prints a fixed piece of text through the host's `print_str` import.
+    fn print_import_str(&mut self, text: &str) -> Result<(), VentiError> {
+        let text = self
+            .builder
+            .build_global_string_ptr(text, "print_text")?
+            .as_pointer_value();
+        self.call_print_import("print_str", text.into())
+    }
+
+    /// Calls the host print import `name`, declaring it on first use.
+    fn call_print_import(
+        &mut self,
+        name: &str,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<(), VentiError> {
+        let symbol = format!("venti_{}", name);
+        let function = self.module.get_function(&symbol).unwrap_or_else(|| {
+            let fn_type = self
+                .context
+                .void_type()
+                .fn_type(&[value.get_type().into()], false);
+            let function = self.module.add_function(&symbol, fn_type, None);
+            for (key, attribute) in [("wasm-import-module", "venti"), ("wasm-import-name", name)] {
+                function.add_attribute(
+                    AttributeLoc::Function,
+                    self.context.create_string_attribute(key, attribute),
+                );
+            }
+            function
+        });
+        self.builder.build_call(function, &[value.into()], "")?;
+        Ok(())
+    }
+
+    /// Appends the `printf` conversion for `value` to `format` and its arguments to `args`.
+    ///
+    /// Arrays and ranges have a statically known shape, so they are spelled out in the
+    /// format string itself rather than formatted at run time.
+    fn format_value(
+        &mut self,
+        value: BasicValueEnum<'ctx>,
+        format: &mut String,
+        args: &mut Vec<BasicValueEnum<'ctx>>,
+    ) -> Result<(), VentiError> {
+        match value {
+            BasicValueEnum::IntValue(value) if value.get_type().get_bit_width() == 1 => {
+                let text = self.bool_string(value)?;
+                self.format_value(text.into(), format, args)?;
+            }
+            BasicValueEnum::IntValue(value) => {
+                format.push_str("%ld");
+                let value = self.builder.build_int_s_extend_or_bit_cast(
+                    value,
+                    self.context.i64_type(),
+                    "print_int",
+                )?;
+                args.push(value.into());
+            }
+            BasicValueEnum::FloatValue(value) => {
+                format.push_str("%f");
+                let value =
+                    self.builder
+                        .build_float_ext(value, self.context.f64_type(), "print_float")?;
+                args.push(value.into());
+            }
+            BasicValueEnum::StructValue(string)
+                if runtime::is_named(string.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                // The length bounds the output, so printf never reads past the string
+                format.push_str("%.*s");
+                let (data, len) = self.printable_string(string)?;
+                let len =
+                    self.builder
+                        .build_int_truncate(len, self.context.i32_type(), "print_len")?;
+                args.push(len.into());
+                args.push(data.into());
+            }
+            BasicValueEnum::StructValue(json)
+                if runtime::is_named(json.get_type(), runtime::JSON_TYPE_NAME) =>
+            {
+                let text = self.json_text(json)?;
+                self.format_value(text, format, args)?;
+            }
+            BasicValueEnum::ArrayValue(array) => {
+                format.push('[');
+                for index in 0..array.get_type().len() {
+                    if index > 0 {
+                        format.push_str(", ");
+                    }
+                    let element = self
+                        .builder
+                        .build_extract_value(array, index, "print_elem")?;
+                    self.format_value(element, format, args)?;
+                }
+                format.push(']');
+            }
+            BasicValueEnum::StructValue(range)
+                if runtime::is_named(range.get_type(), runtime::RANGE_TYPE_NAME) =>
+            {
+                format.push_str("%ld..%ld");
+                args.push(self.builder.build_extract_value(range, 0, "range_start")?);
+                args.push(self.builder.build_extract_value(range, 1, "range_end")?);
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "This value cannot be printed".to_string(),
+                    None,
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles a nested block of statements with `bindings` in scope.
+    ///
+    /// Variables declared inside the block, and the bindings themselves, are dropped
+    /// when the block ends, releasing any strings and maps they hold.
+    fn compile_block(
+        &mut self,
+        body: Vec<Statement>,
+        bindings: Vec<(String, BasicValueEnum<'ctx>)>,
+    ) -> Result<(), VentiError> {
+        let previous_locals = self.locals.clone();
+        let previous_owned = self.owned.len();
+        let previous_scope = std::mem::replace(&mut self.global_scope, false);
+        let result = bindings
+            .into_iter()
+            .try_for_each(|(name, value)| self.bind_local(&name, value))
+            .and_then(|()| {
+                body.into_iter()
+                    .try_for_each(|statement| self.compile_statement(statement))
+            })
+            .and_then(|()| {
+                if self.block_is_open() {
+                    self.release_owned(previous_owned)
+                } else {
+                    Ok(())
+                }
+            });
+        self.owned.truncate(previous_owned);
+        self.locals = previous_locals;
+        self.global_scope = previous_scope;
+        result
+    }
+
+    /// Returns from the current function, converting integer results to its return type.
+    ///
+    /// A bare `return_venti;` returns zero, or an empty vector from a function returning
+    /// one, and the result of top-level code is narrowed to `main`'s `i32` exit code. Every
+    /// string and map held by a local of the function or made by the statements being left
+    /// is released first, and the handlers of the `try_venti` blocks being left are taken
+    /// down.
+    fn build_return(&mut self, value: Option<BasicValueEnum<'ctx>>) -> Result<(), VentiError> {
+        // A returned vector outlives the locals that may own it, and reaches the caller
+        // unowned
+        let returned_vector = value
+            .map(|value| self.expect_vector(value, "return_venti"))
+            .and_then(Result::ok);
+        if let Some(value) = value.filter(|_| returned_vector.is_some()) {
+            self.retain(value)?;
+        }
+        self.release_temporaries(0)?;
+        self.release_owned(0)?;
+        if let Some(header) = returned_vector {
+            let disown = runtime::helper(self.context, &self.module, "venti_vec_disown")?;
+            self.builder.build_call(disown, &[header.into()], "")?;
+        }
+        if let Some(&outermost) = self.handlers.first() {
+            self.restore_handler(outermost)?;
+        }
+        let return_type = self.current_function()?.get_type().get_return_type();
+        let value = match (return_type, value) {
+            (Some(BasicTypeEnum::IntType(int_type)), Some(BasicValueEnum::IntValue(value))) => {
+                // A bool returned as an integer is 0 or 1
+                let is_bool = value.get_type().get_bit_width() == 1;
+                Some(
+                    self.builder
+                        .build_int_cast_sign_flag(value, int_type, !is_bool, "ret_value")?
+                        .as_basic_value_enum(),
+                )
+            }
+            (Some(BasicTypeEnum::IntType(_)), Some(BasicValueEnum::FloatValue(_))) => {
+                return Err(VentiError::CodegenError(
+                    "Functions can only return integers".to_string(),
+                    None,
+                ))
+            }
+            // A function returning a vector without saying which returns an empty one
+            (Some(BasicTypeEnum::StructType(vector_type)), None)
+                if runtime::is_named(vector_type, runtime::VECTOR_TYPE_NAME) =>
+            {
+                let new = runtime::helper(self.context, &self.module, "venti_vec_new")?;
+                let header = self.call_value(new, &[], "vector")?;
+                Some(self.vector_value(header.into_pointer_value())?.into())
+            }
+            (Some(return_type), None) => Some(return_type.const_zero()),
+            (_, value) => value,
+        };
+        match value {
+            Some(value) => self.builder.build_return(Some(&value))?,
+            None => self.builder.build_return(None)?,
+        };
+        Ok(())
+    }
+
+    /// Lowers a match statement.
+    ///
+    /// Integer scrutinees become a single `switch`; string scrutinees test each arm in
+    /// order with `strcmp`. Arms that fall off their end continue after the match.
+    fn compile_match(&mut self, scrutinee: Expr, arms: Vec<MatchArm>) -> Result<(), VentiError> {
+        if let Some(position) = arms
+            .iter()
+            .position(|arm| matches!(arm.pattern, Pattern::Wildcard))
+        {
+            if position != arms.len() - 1 {
+                return Err(VentiError::CodegenError(
+                    "The '_' arm must be the last arm of a match".to_string(),
+                    None,
+                ));
+            }
+        }
+
+        let scrutinee = self.compile_expr(scrutinee)?;
+        let function = self.current_function()?;
+        let end_block = self.context.append_basic_block(function, "match_end");
+        let arm_blocks = arms
+            .iter()
+            .map(|_| self.context.append_basic_block(function, "match_arm"))
+            .collect::<Vec<_>>();
+        let default_block = arms
+            .iter()
+            .zip(&arm_blocks)
+            .find(|(arm, _)| matches!(arm.pattern, Pattern::Wildcard))
+            .map(|(_, block)| *block)
+            .unwrap_or(end_block);
+
+        match scrutinee {
+            BasicValueEnum::IntValue(value) => {
+                let mut cases: Vec<(IntValue<'ctx>, BasicBlock<'ctx>)> = Vec::new();
+                for (arm, block) in arms.iter().zip(&arm_blocks) {
+                    match &arm.pattern {
+                        Pattern::Number(n) => {
+                            let case = value.get_type().const_int(*n as u64, true);
+                            if cases.iter().any(|(existing, _)| *existing == case) {
+                                return Err(VentiError::CodegenError(
+                                    format!("Duplicate match arm for {}", n),
+                                    None,
+                                ));
+                            }
+                            cases.push((case, *block));
+                        }
+                        Pattern::String(_) => {
+                            return Err(VentiError::CodegenError(
+                                "Cannot match an integer against a string pattern".to_string(),
+                                None,
+                            ))
+                        }
+                        Pattern::Wildcard => {}
+                    }
+                }
+                self.builder.build_switch(value, default_block, &cases)?;
+            }
+            BasicValueEnum::StructValue(string)
+                if runtime::is_named(string.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                let (value, _) = self.string_parts(string)?;
+                let strcmp = runtime::libc(self.context, &self.module, "strcmp")?;
+                for (arm, block) in arms.iter().zip(&arm_blocks) {
+                    let pattern = match &arm.pattern {
+                        Pattern::String(pattern) => pattern,
+                        Pattern::Number(_) => {
+                            return Err(VentiError::CodegenError(
+                                "Cannot match a string against an integer pattern".to_string(),
+                                None,
+                            ))
+                        }
+                        Pattern::Wildcard => continue,
+                    };
+                    let pattern = self
+                        .builder
+                        .build_global_string_ptr(pattern, "pattern")?
+                        .as_pointer_value();
+                    let order = self
+                        .call_value(strcmp, &[value.into(), pattern.into()], "strcmp_call")?
+                        .into_int_value();
+                    let is_match = self.builder.build_int_compare(
+                        IntPredicate::EQ,
+                        order,
+                        order.get_type().const_zero(),
+                        "is_match",
+                    )?;
+                    let next_block = self.context.append_basic_block(function, "match_next");
+                    self.builder
+                        .build_conditional_branch(is_match, *block, next_block)?;
+                    self.builder.position_at_end(next_block);
+                }
+                self.builder.build_unconditional_branch(default_block)?;
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Only integers and strings can be matched".to_string(),
+                    None,
+                ))
+            }
+        }
+
+        for (arm, block) in arms.into_iter().zip(arm_blocks) {
+            self.builder.position_at_end(block);
+            self.compile_block(arm.body, Vec::new())?;
+            if self.block_is_open() {
+                self.builder.build_unconditional_branch(end_block)?;
+            }
+        }
+        end_block.move_after(self.current_block()?).map_err(|_| {
+            VentiError::CodegenError("Could not order the match blocks".to_string(), None)
+        })?;
+        self.builder.position_at_end(end_block);
+        Ok(())
+    }
+
+    /// Lowers `for_venti variable in iterable { ... }`.
+    ///
+    /// The iterable is either a range or an integer `n`, which counts from 0 up to but
+    /// not including `n`. The loop variable lives in a stack slot and is only visible
+    /// inside the body.
+    fn compile_for(
+        &mut self,
+        variable: String,
+        iterable: Expr,
+        body: Vec<Statement>,
+    ) -> Result<(), VentiError> {
+        let i64_type = self.context.i64_type();
+        // Arrays and buffers loop over their indices and bind the element at each one
+        let mut elements = None;
+        let mut held = None;
+        let (start, end, step) = match self.compile_expr(iterable)? {
+            BasicValueEnum::IntValue(count) => (
+                i64_type.const_zero(),
+                self.builder
+                    .build_int_s_extend_or_bit_cast(count, i64_type, "loop_end")?,
+                i64_type.const_int(1, false),
+            ),
+            BasicValueEnum::ArrayValue(array) => {
+                let array_type = array.get_type();
+                let slot = self.build_entry_alloca(array_type.into(), "for_array")?;
+                self.builder.build_store(slot, array)?;
+                elements = Some((slot, array_type.get_element_type()));
+                (
+                    i64_type.const_zero(),
+                    i64_type.const_int(array_type.len() as u64, false),
+                    i64_type.const_int(1, false),
+                )
+            }
+            BasicValueEnum::StructValue(buffer)
+                if runtime::is_named(buffer.get_type(), runtime::BUFFER_TYPE_NAME) =>
+            {
+                let data = self
+                    .builder
+                    .build_extract_value(buffer, 0, "buffer_data")?
+                    .into_pointer_value();
+                elements = Some((data, i64_type.into()));
+                (
+                    i64_type.const_zero(),
+                    self.builder
+                        .build_extract_value(buffer, 1, "buffer_len")?
+                        .into_int_value(),
+                    i64_type.const_int(1, false),
+                )
+            }
+            BasicValueEnum::StructValue(iterator)
+                if runtime::is_named(iterator.get_type(), runtime::ITERATOR_TYPE_NAME) =>
+            {
+                return self.compile_for_iterator(variable, iterator, body);
+            }
+            // A string vector cannot change, so the loop reads its slots directly, holding a
+            // reference in case the body assigns over the variable it came from
+            BasicValueEnum::StructValue(strings)
+                if runtime::is_named(strings.get_type(), runtime::STRING_VECTOR_TYPE_NAME) =>
+            {
+                self.retain(strings.into())?;
+                held = Some(strings.into());
+                let header = self
+                    .builder
+                    .build_extract_value(strings, 0, "strings")?
+                    .into_pointer_value();
+                let header_type = runtime::string_vector_header_type(self.context);
+                let field = |index, name| {
+                    let field = self
+                        .builder
+                        .build_struct_gep(header_type, header, index, name)?;
+                    Ok::<_, VentiError>(field)
+                };
+                let len = self
+                    .builder
+                    .build_load(i64_type, field(0, "strings_len_ptr")?, "strings_len")?
+                    .into_int_value();
+                let slots = self
+                    .builder
+                    .build_load(
+                        self.context.ptr_type(AddressSpace::default()),
+                        field(1, "strings_slots_ptr")?,
+                        "strings_slots",
+                    )?
+                    .into_pointer_value();
+                elements = Some((slots, runtime::string_type(self.context).into()));
+                (i64_type.const_zero(), len, i64_type.const_int(1, false))
+            }
+            // A vector is looped over through a copy, so the body may push to it
+            vector @ BasicValueEnum::StructValue(value)
+                if runtime::is_named(value.get_type(), runtime::VECTOR_TYPE_NAME) =>
+            {
+                let header = self.iterator_header(vector, "for_venti")?;
+                let iterator = self.iterator_value(header)?;
+                return self.compile_for_iterator(variable, iterator, body);
+            }
+            BasicValueEnum::StructValue(range)
+                if runtime::is_named(range.get_type(), runtime::RANGE_TYPE_NAME) =>
+            {
+                let field = |index, name| {
+                    self.builder
+                        .build_extract_value(range, index, name)
+                        .map(|value| value.into_int_value())
+                };
+                (
+                    field(0, "range_start")?,
+                    field(1, "range_end")?,
+                    field(2, "range_step")?,
+                )
+            }
+            _ => return Err(VentiError::CodegenError(
+                "for_venti can only iterate over ranges, integers, arrays, buffers, vectors and iterators"
+                    .to_string(),
+                None,
+            )),
+        };
+
+        let function = self.current_function()?;
+        let cond_block = self.context.append_basic_block(function, "for_cond");
+        let body_block = self.context.append_basic_block(function, "for_body");
+        let step_block = self.context.append_basic_block(function, "for_step");
+        let end_block = self.context.append_basic_block(function, "for_end");
+
+        let counter = self.build_entry_alloca(i64_type.into(), "for_counter")?;
+        self.builder.build_store(counter, start)?;
+        self.builder.build_unconditional_branch(cond_block)?;
+
+        self.builder.position_at_end(cond_block);
+        let current = self
+            .builder
+            .build_load(i64_type, counter, &variable)?
+            .into_int_value();
+        let in_range =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, current, end, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, body_block, end_block)?;
+
+        self.builder.position_at_end(body_block);
+        let value = match elements {
+            Some((base, element_type)) => {
+                // Safety: the loop condition keeps `current` below the length
+                let element = unsafe {
+                    self.builder.build_in_bounds_gep(
+                        element_type,
+                        base,
+                        &[current],
+                        "element_ptr",
+                    )?
+                };
+                self.builder.build_load(element_type, element, &variable)?
+            }
+            None => current.into(),
+        };
+        self.compile_block(body, vec![(variable, value)])?;
+        if self.block_is_open() {
+            self.builder.build_unconditional_branch(step_block)?;
+        }
+
+        self.builder.position_at_end(step_block);
+        let next = self.builder.build_int_add(current, step, "next")?;
+        self.builder.build_store(counter, next)?;
+        self.builder.build_unconditional_branch(cond_block)?;
+
+        self.builder.position_at_end(end_block);
+        if let Some(held) = held {
+            self.release(held)?;
+        }
+        Ok(())
+    }
+
+    /// Lowers `for_venti` over an iterator, taking its next value until it runs out.
+    ///
+    /// The loop holds a reference to the iterator, which is dropped when the loop ends or
+    /// the function returns from inside it, so an iterator made for the loop is freed.
+    fn compile_for_iterator(
+        &mut self,
+        variable: String,
+        iterator: StructValue<'ctx>,
+        body: Vec<Statement>,
+    ) -> Result<(), VentiError> {
+        let previous_owned = self.owned.len();
+        let slot = self.build_entry_alloca(iterator.get_type().into(), "for_iterator")?;
+        self.builder.build_store(slot, iterator)?;
+        self.retain(iterator.into())?;
+        self.owned.push(Local {
+            slot,
+            value_type: iterator.get_type().into(),
+        });
+        let header = self.builder.build_extract_value(iterator, 0, "iterator")?;
+        let next = runtime::helper(self.context, &self.module, "venti_iter_next")?;
+
+        let function = self.current_function()?;
+        let cond_block = self.context.append_basic_block(function, "for_cond");
+        let body_block = self.context.append_basic_block(function, "for_body");
+        let end_block = self.context.append_basic_block(function, "for_end");
+        self.builder.build_unconditional_branch(cond_block)?;
+
+        self.builder.position_at_end(cond_block);
+        let pair = self
+            .call_value(next, &[header.into()], "next")?
+            .into_struct_value();
+        let is_done = self
+            .builder
+            .build_extract_value(pair, 1, "is_done")?
+            .into_int_value();
+        self.builder
+            .build_conditional_branch(is_done, end_block, body_block)?;
+
+        self.builder.position_at_end(body_block);
+        let value = self.builder.build_extract_value(pair, 0, &variable)?;
+        self.compile_block(body, vec![(variable, value)])?;
+        if self.block_is_open() {
+            self.builder.build_unconditional_branch(cond_block)?;
+        }
+
+        self.builder.position_at_end(end_block);
+        self.release_owned(previous_owned)?;
+        self.owned.truncate(previous_owned);
+        Ok(())
+    }
+
+    /// Builds a `venti.range` value; an inclusive range is stored with its end bumped by one.
+    fn compile_range<'a>(
+        &mut self,
+        start: Expr<'a>,
+        end: Expr<'a>,
+        inclusive: bool,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let mut bounds = Vec::new();
+        for bound in [start, end] {
+            match self.compile_expr(bound)? {
+                BasicValueEnum::IntValue(bound) => bounds.push(
+                    self.builder
+                        .build_int_s_extend_or_bit_cast(bound, i64_type, "range_bound")?,
+                ),
+                _ => {
+                    return Err(VentiError::CodegenError(
+                        "Range bounds must be integers".to_string(),
+                        None,
+                    ))
+                }
+            }
+        }
+        let (start, mut end) = (bounds[0], bounds[1]);
+        if inclusive {
+            end = self
+                .builder
+                .build_int_add(end, i64_type.const_int(1, false), "range_end")?;
+        }
+
+        let mut range = runtime::range_type(self.context).get_undef();
+        for (index, field) in [start, end, i64_type.const_int(1, false)]
+            .into_iter()
+            .enumerate()
+        {
+            range = self
+                .builder
+                .build_insert_value(range, field, index as u32, "range")?
+                .into_struct_value();
+        }
+        Ok(range.into())
+    }
+
+    /// Allocates a stack slot in the current function's entry block.
+    ///
+    /// Keeping every slot in the entry block means loops do not grow the stack and LLVM
+    /// can promote the slots back to registers.
+    fn build_entry_alloca(
+        &self,
+        value_type: BasicTypeEnum<'ctx>,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        Ok(self.entry_builder()?.build_alloca(value_type, name)?)
+    }
+
+    /// Returns a builder positioned at the start of the current function's entry block.
+    fn entry_builder(&self) -> Result<Builder<'ctx>, VentiError> {
+        let entry = self
+            .current_function()?
+            .get_first_basic_block()
+            .ok_or_else(|| {
+                VentiError::CodegenError("Function has no entry block".to_string(), None)
+            })?;
+        let entry_builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(instruction) => entry_builder.position_before(&instruction),
+            None => entry_builder.position_at_end(entry),
+        }
+        Ok(entry_builder)
+    }
+
+    /// Stores `value` in a new stack slot and binds `name` to it in the current scope.
+    fn bind_local(&mut self, name: &str, value: BasicValueEnum<'ctx>) -> Result<(), VentiError> {
+        self.bind_variable(name, value, None)
+    }
+
+    /// Binds `name` like `bind_local`, describing it in the debug info as parameter
+    /// `arg_no` (counting from 1) of the current function, or as a local when `None`.
+    fn bind_variable(
+        &mut self,
+        name: &str,
+        value: BasicValueEnum<'ctx>,
+        arg_no: Option<u32>,
+    ) -> Result<(), VentiError> {
+        let slot = self.build_entry_alloca(value.get_type(), name)?;
+        self.builder.build_store(slot, value)?;
+        let local = Local {
+            slot,
+            value_type: value.get_type(),
+        };
+        if is_counted(local.value_type) {
+            self.retain(value)?;
+            self.owned.push(local);
+        }
+        if let Some(debug) = &self.debug {
+            debug.declare_variable(
+                self.context,
+                name,
+                slot,
+                value.get_type(),
+                arg_no,
+                self.current_block()?,
+            );
+        }
+        self.locals.insert(name.to_string(), local);
+        Ok(())
+    }
+
+    /// Stores `value` over the variable in `slot`, moving its reference when it is a
+    /// string or map: the new value is retained and the one it replaces released.
+    fn assign_slot(
+        &self,
+        slot: PointerValue<'ctx>,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<(), VentiError> {
+        if !is_counted(value.get_type()) {
+            self.builder.build_store(slot, value)?;
+            return Ok(());
+        }
+        // Retaining first keeps `x = x` from freeing the value
+        self.retain(value)?;
+        let previous = self
+            .builder
+            .build_load(value.get_type(), slot, "previous")?;
+        self.builder.build_store(slot, value)?;
+        self.release(previous)
+    }
+
+    /// Releases the values held by `owned` locals from `start` on, innermost first.
+    fn release_owned(&self, start: usize) -> Result<(), VentiError> {
+        for local in self.owned[start..].iter().rev() {
+            let value = self
+                .builder
+                .build_load(local.value_type, local.slot, "owned")?;
+            self.release(value)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps a reference to `value`, made by an expression of the statement being compiled,
+    /// until the statement ends.
+    ///
+    /// The value is kept in a slot of its own, zeroed on entry to the function, so the end
+    /// of the statement can release it whichever branches made it. A slot reached again
+    /// before then, by a loop condition or after a raise skipped the release, releases
+    /// the value it kept the last time.
+    fn hold_temporary(&mut self, value: BasicValueEnum<'ctx>) -> Result<(), VentiError> {
+        // Literals are never freed, so they need no reference
+        if !is_counted(value.get_type()) || is_constant(value) {
+            return Ok(());
+        }
+        let value_type = value.get_type();
+        let entry_builder = self.entry_builder()?;
+        let slot = entry_builder.build_alloca(value_type, "temporary")?;
+        entry_builder.build_store(slot, value_type.const_zero())?;
+        self.retain(value)?;
+        let previous = self.builder.build_load(value_type, slot, "previous")?;
+        self.builder.build_store(slot, value)?;
+        self.release(previous)?;
+        self.temporaries.push(Local { slot, value_type });
+        Ok(())
+    }
+
+    /// Releases the values held in `temporaries` from `start` on, innermost first, and
+    /// zeroes their slots for the next time the statement runs.
+    fn release_temporaries(&self, start: usize) -> Result<(), VentiError> {
+        for local in self.temporaries[start..].iter().rev() {
+            let value = self
+                .builder
+                .build_load(local.value_type, local.slot, "temporary")?;
+            self.release(value)?;
+            self.builder
+                .build_store(local.slot, local.value_type.const_zero())?;
+        }
+        Ok(())
+    }
+
+    /// Adds a reference to every string, map, channel, iterator and vector in `value`.
+    fn retain(&self, value: BasicValueEnum<'ctx>) -> Result<(), VentiError> {
+        self.count_references(value, "retain")
+    }
+
+    /// Drops a reference to every string, map, channel, iterator and vector in `value`,
+    /// freeing those that have none left.
+    fn release(&self, value: BasicValueEnum<'ctx>) -> Result<(), VentiError> {
+        self.count_references(value, "release")
+    }
+
+    /// Calls the runtime's `retain` or `release` helper, named by `action`, on every string,
+    /// map, channel, iterator and vector in `value`, looking through arrays.
+    fn count_references(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        action: &str,
+    ) -> Result<(), VentiError> {
+        if !is_counted(value.get_type()) {
+            return Ok(());
+        }
+        match value {
+            BasicValueEnum::ArrayValue(array) => {
+                for index in 0..array.get_type().len() {
+                    let element = self.builder.build_extract_value(array, index, "element")?;
+                    self.count_references(element, action)?;
+                }
+                Ok(())
+            }
+            BasicValueEnum::StructValue(string)
+                if runtime::is_named(string.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                let (data, _) = self.string_parts(string)?;
+                let helper =
+                    runtime::helper(self.context, &self.module, &format!("venti_str_{}", action))?;
+                self.builder.build_call(helper, &[data.into()], "")?;
+                Ok(())
+            }
+            BasicValueEnum::StructValue(channel)
+                if runtime::is_named(channel.get_type(), runtime::CHANNEL_TYPE_NAME) =>
+            {
+                let header = self.builder.build_extract_value(channel, 0, "channel")?;
+                let helper = runtime::helper(
+                    self.context,
+                    &self.module,
+                    &format!("venti_channel_{}", action),
+                )?;
+                self.builder.build_call(helper, &[header.into()], "")?;
+                Ok(())
+            }
+            BasicValueEnum::StructValue(iterator)
+                if runtime::is_named(iterator.get_type(), runtime::ITERATOR_TYPE_NAME) =>
+            {
+                let header = self.builder.build_extract_value(iterator, 0, "iterator")?;
+                let helper = runtime::helper(
+                    self.context,
+                    &self.module,
+                    &format!("venti_iter_{}", action),
+                )?;
+                self.builder.build_call(helper, &[header.into()], "")?;
+                Ok(())
+            }
+            BasicValueEnum::StructValue(vector)
+                if runtime::is_named(vector.get_type(), runtime::VECTOR_TYPE_NAME) =>
+            {
+                let header = self.builder.build_extract_value(vector, 0, "vector")?;
+                let helper =
+                    runtime::helper(self.context, &self.module, &format!("venti_vec_{}", action))?;
+                self.builder.build_call(helper, &[header.into()], "")?;
+                Ok(())
+            }
+            BasicValueEnum::StructValue(strings)
+                if runtime::is_named(strings.get_type(), runtime::STRING_VECTOR_TYPE_NAME) =>
+            {
+                let header = self.builder.build_extract_value(strings, 0, "strings")?;
+                let helper = runtime::helper(
+                    self.context,
+                    &self.module,
+                    &format!("venti_strvec_{}", action),
+                )?;
+                self.builder.build_call(helper, &[header.into()], "")?;
+                Ok(())
+            }
+            _ => {
+                let Some(header) = self.map_header(value)? else {
+                    return Ok(());
+                };
+                let helper =
+                    runtime::helper(self.context, &self.module, &format!("venti_map_{}", action))?;
+                self.builder.build_call(helper, &[header.into()], "")?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Calls a function defined in the module.
+    ///
+    /// # Returns
+    ///
+    /// The call's result, or `None` when the callee returns `void`.
+    fn compile_call(
+        &mut self,
+        identifier: &str,
+        args: Vec<Expr>,
+    ) -> Result<Option<BasicValueEnum<'ctx>>, VentiError> {
+        if self.extern_functions.contains_key(identifier) {
+            return self.compile_extern_call(identifier, args).map(Some);
+        }
+        let Some(&arity) = self.defined_functions.get(identifier) else {
+            if builtins::is_builtin(identifier) {
+                return self.compile_builtin(identifier, args).map(Some);
+            }
+            return Err(VentiError::CodegenError(
+                format!("Undefined function '{}'", identifier),
+                None,
+            ));
+        };
+        if arity != args.len() {
+            return Err(VentiError::CodegenError(
+                format!(
+                    "Function '{}' expects {} arguments but {} were given",
+                    identifier,
+                    arity,
+                    args.len()
+                ),
+                None,
+            ));
+        }
+        let function = self.function(identifier, arity).ok_or_else(|| {
+            VentiError::CodegenError(format!("Undefined function '{}'", identifier), None)
+        })?;
+        let args = args
+            .into_iter()
+            .map(|arg| self.compile_expr(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let compiled_args = self.pass_args(function.get_type().get_param_types(), args)?;
+        let call = self
+            .builder
+            .build_call(function, &compiled_args, "call_func")?;
+        Ok(call.try_as_basic_value().left())
+    }
+
+    /// Prepares `args` to be passed as parameters of the types `param_types`, boxing each
+    /// one passed where a trait is expected into a value of the trait.
+    fn pass_args(
+        &mut self,
+        param_types: Vec<BasicTypeEnum<'ctx>>,
+        args: Vec<BasicValueEnum<'ctx>>,
+    ) -> Result<Vec<BasicMetadataValueEnum<'ctx>>, VentiError> {
+        let mut passed = Vec::with_capacity(args.len());
+        for (arg, param_type) in args.into_iter().zip(param_types) {
+            let arg = match param_type {
+                BasicTypeEnum::StructType(struct_type) if arg.get_type() != param_type => {
+                    match runtime::trait_name(struct_type) {
+                        Some(trait_name) => self.box_trait_value(arg, &trait_name)?.into(),
+                        None => arg,
+                    }
+                }
+                _ => arg,
+            };
+            passed.push(arg.into());
+        }
+        Ok(passed)
+    }
+
+    /// Boxes `value` into a value of the trait `trait_name`: a copy of it in memory from
+    /// malloc, with the table of the functions implementing the trait for its type.
+    fn box_trait_value(
+        &mut self,
+        value: BasicValueEnum<'ctx>,
+        trait_name: &str,
+    ) -> Result<StructValue<'ctx>, VentiError> {
+        let type_name = source_type_name(value.get_type()).ok_or_else(|| {
+            VentiError::CodegenError(
+                format!(
+                    "Only ints, floats, bools and strings can be passed as {}",
+                    trait_name
+                ),
+                None,
+            )
+        })?;
+        let table = self.trait_table(trait_name, type_name)?;
+        let malloc = runtime::libc(self.context, &self.module, "malloc")?;
+        let size = value
+            .get_type()
+            .size_of()
+            .ok_or_else(|| VentiError::CodegenError("Boxed value is unsized".to_string(), None))?;
+        let data = self
+            .call_value(malloc, &[size.into()], "boxed")?
+            .into_pointer_value();
+        // The box is never freed, so it keeps the string it holds
+        self.builder.build_store(data, value)?;
+        self.retain(value)?;
+        let trait_type = runtime::trait_type(self.context, trait_name);
+        let boxed = self
+            .builder
+            .build_insert_value(trait_type.get_undef(), data, 0, "trait_value")?
+            .into_struct_value();
+        Ok(self
+            .builder
+            .build_insert_value(boxed, table, 1, "trait_value")?
+            .into_struct_value())
+    }
+
+    /// Returns the table of the functions implementing `trait_name` for `type_name`,
+    /// emitting it in the module on first use.
+    ///
+    /// Each entry is a function taking a pointer to the boxed value and the method's
+    /// arguments, which loads the value and calls the method's implementation.
+    fn trait_table(
+        &mut self,
+        trait_name: &str,
+        type_name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let key = (trait_name.to_string(), type_name.to_string());
+        if let Some(table) = self.declared.trait_tables.get(&key) {
+            return Ok(table.as_pointer_value());
+        }
+        let symbol = format!("__venti_table.{}.{}", trait_name, type_name);
+        let methods = self
+            .traits
+            .methods(trait_name)
+            .ok_or_else(|| {
+                VentiError::CodegenError(format!("Undefined trait '{}'", trait_name), None)
+            })?
+            .to_vec();
+        let previous_block = self.builder.get_insert_block();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let mut entries = Vec::with_capacity(methods.len());
+        for method in methods {
+            let name = traits::method_function(trait_name, type_name, &method.name);
+            let function = self
+                .function(&name, method.params.len() + 1)
+                .ok_or_else(|| {
+                    VentiError::CodegenError(
+                        format!(
+                            "{} does not implement method '{}' of trait {}",
+                            type_name, method.name, trait_name
+                        ),
+                        None,
+                    )
+                })?;
+            let param_types = function.get_type().get_param_types();
+            let mut shim_params: Vec<BasicMetadataTypeEnum> = vec![ptr_type.into()];
+            shim_params.extend(
+                param_types[1..]
+                    .iter()
+                    .map(|&param| BasicMetadataTypeEnum::from(param)),
+            );
+            let shim = self.module.add_function(
+                &format!("__venti_method.{}", name),
+                self.context.i64_type().fn_type(&shim_params, false),
+                Some(Linkage::Internal),
+            );
+            let entry = self.context.append_basic_block(shim, "entry");
+            self.builder.position_at_end(entry);
+            self.enter_debug_scope(shim);
+            let data = shim
+                .get_first_param()
+                .ok_or_else(|| {
+                    VentiError::CodegenError("Method has no receiver".to_string(), None)
+                })?
+                .into_pointer_value();
+            let receiver = self.builder.build_load(param_types[0], data, "receiver")?;
+            let mut args: Vec<BasicMetadataValueEnum> = vec![receiver.into()];
+            args.extend(
+                shim.get_param_iter()
+                    .skip(1)
+                    .map(BasicMetadataValueEnum::from),
+            );
+            let result = self.call_value(function, &args, "method_result")?;
+            self.builder.build_return(Some(&result))?;
+            self.leave_debug_scope();
+            entries.push(shim.as_global_value().as_pointer_value());
+        }
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+
+        let table_type = ptr_type.array_type(entries.len() as u32);
+        let table = self.module.add_global(table_type, None, &symbol);
+        table.set_linkage(Linkage::Internal);
+        table.set_constant(true);
+        table.set_initializer(&ptr_type.const_array(&entries));
+        self.declared.trait_tables.insert(key, table);
+        Ok(table.as_pointer_value())
+    }
+
+    /// Calls a C function declared with `extern_venti`, converting each argument to its C
+    /// type and the result back.
+    ///
+    /// A string is passed as a pointer to its NUL-terminated data, or null for `nothing`,
+    /// and a string the function returns is copied, with null becoming `nothing`. Ints are
+    /// narrowed to C's `int` and widened back, and a function returning `void` gives 0.
+    fn compile_extern_call(
+        &mut self,
+        identifier: &str,
+        args: Vec<Expr>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let function = self.extern_function(identifier)?;
+        let param_types = function.get_type().get_param_types();
+        if param_types.len() != args.len() {
+            return Err(VentiError::CodegenError(
+                format!(
+                    "C function '{}' expects {} arguments but {} were given",
+                    identifier,
+                    param_types.len(),
+                    args.len()
+                ),
+                None,
+            ));
+        }
+        let mut compiled_args = Vec::new();
+        for (arg, param_type) in args.into_iter().zip(param_types) {
+            let value = match (self.compile_expr(arg)?, param_type) {
+                (BasicValueEnum::IntValue(int), BasicTypeEnum::IntType(c_int))
+                    if int.get_type().get_bit_width() > c_int.get_bit_width() =>
+                {
+                    self.builder.build_int_truncate(int, c_int, "c_int")?.into()
+                }
+                (BasicValueEnum::IntValue(int), BasicTypeEnum::FloatType(_)) => {
+                    self.promote_to_float(int)?.into()
+                }
+                (BasicValueEnum::StructValue(string), BasicTypeEnum::PointerType(_)) => {
+                    self.string_parts(string)?.0.into()
+                }
+                (value, _) => value,
+            };
+            compiled_args.push(BasicMetadataValueEnum::from(value));
+        }
+        let call = self
+            .builder
+            .build_call(function, &compiled_args, "c_call")?;
+        let i64_type = self.context.i64_type();
+        Ok(match call.try_as_basic_value().left() {
+            None => i64_type.const_zero().into(),
+            Some(BasicValueEnum::IntValue(int)) if int.get_type().get_bit_width() == 32 => self
+                .builder
+                .build_int_s_extend(int, i64_type, "c_result")?
+                .into(),
+            Some(BasicValueEnum::PointerValue(data)) => self.c_string(data)?.into(),
+            Some(value) => value,
+        })
+    }
+
+    /// Copies the NUL-terminated string at `data`, which a C function returned, into a new
+    /// string, or gives `nothing` when `data` is null.
+    fn c_string(&mut self, data: PointerValue<'ctx>) -> Result<StructValue<'ctx>, VentiError> {
+        let function = self.current_function()?;
+        let null_block = self.current_block()?;
+        let copy_block = self.context.append_basic_block(function, "c_string_copy");
+        let end_block = self.context.append_basic_block(function, "c_string_end");
+        let is_null = self.builder.build_is_null(data, "is_null")?;
+        self.builder
+            .build_conditional_branch(is_null, end_block, copy_block)?;
+
+        self.builder.position_at_end(copy_block);
+        let strlen = runtime::libc(self.context, &self.module, "strlen")?;
+        let len = self
+            .call_value(strlen, &[data.into()], "c_len")?
+            .into_int_value();
+        let copy = runtime::helper(self.context, &self.module, "venti_str_copy")?;
+        let copied = self
+            .call_value(copy, &[data.into(), len.into()], "copy")?
+            .into_pointer_value();
+        let string = self.build_string_value(copied, len)?;
+        self.builder.build_unconditional_branch(end_block)?;
+
+        self.builder.position_at_end(end_block);
+        let string_type = runtime::string_type(self.context);
+        let nothing = string_type.const_zero();
+        let phi = self.builder.build_phi(string_type, "c_string")?;
+        phi.add_incoming(&[(&nothing, null_block), (&string, copy_block)]);
+        Ok(phi.as_basic_value().into_struct_value())
+    }
+
+    /// Lowers a call to one of the built-in functions.
+    ///
+    /// `join(task)` waits for a spawned block or async call to finish, like `await`.
+    fn compile_builtin(
+        &mut self,
+        identifier: &str,
+        args: Vec<Expr>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let arity = builtins::arity(identifier).ok_or_else(|| {
+            VentiError::CodegenError(format!("Unknown builtin '{}'", identifier), None)
+        })?;
+        if args.len() != arity {
+            return Err(VentiError::CodegenError(
+                format!(
+                    "Builtin '{}' expects {} argument(s) but {} were given",
+                    identifier,
+                    arity,
+                    args.len()
+                ),
+                None,
+            ));
+        }
+        if identifier == "free" {
+            return self.compile_free(args.into_iter().next());
+        }
+        // `len(x)` is the same as `x.len()`, including the static length of arrays
+        if identifier == "len" {
+            let receiver = args.into_iter().next().ok_or_else(|| {
+                VentiError::CodegenError("Missing builtin argument".to_string(), None)
+            })?;
+            return self.compile_method_call(receiver, "len", Vec::new());
+        }
+        // What `recv` gives is the type of the values the analyzer found sent on the channel
+        let element = match (identifier, args.first()) {
+            ("recv", Some(channel)) => analyzer::channel_element(&self.symbols, channel),
+            _ => Type::Int,
+        };
+        let mut args = args
+            .into_iter()
+            .map(|arg| self.compile_expr(arg))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter();
+        let mut next_arg = || {
+            args.next().ok_or_else(|| {
+                VentiError::CodegenError("Missing builtin argument".to_string(), None)
+            })
+        };
+        match identifier {
+            "join" => self.await_task(next_arg()?),
+            "alloc" => {
+                let len =
+                    match next_arg()? {
+                        BasicValueEnum::IntValue(len) => self
+                            .builder
+                            .build_int_s_extend_or_bit_cast(len, self.context.i64_type(), "len")?,
+                        _ => {
+                            return Err(VentiError::CodegenError(
+                                "'alloc' expects an integer length".to_string(),
+                                None,
+                            ))
+                        }
+                    };
+                let alloc = runtime::helper(self.context, &self.module, "venti_buffer_alloc")?;
+                let data = self.call_value(alloc, &[len.into()], "buffer_data")?;
+                let buffer = runtime::buffer_type(self.context).get_undef();
+                let buffer = self
+                    .builder
+                    .build_insert_value(buffer, data, 0, "buffer_data")?;
+                let buffer = self.builder.build_insert_value(buffer, len, 1, "buffer")?;
+                Ok(buffer.as_basic_value_enum())
+            }
+            "byte_at" => {
+                let string = self.expect_string(next_arg()?, "byte_at")?;
+                let index = match next_arg()? {
+                    BasicValueEnum::IntValue(index) => self
+                        .builder
+                        .build_int_s_extend_or_bit_cast(index, self.context.i64_type(), "index")?,
+                    _ => {
+                        return Err(VentiError::CodegenError(
+                            "'byte_at' expects an integer index".to_string(),
+                            None,
+                        ))
+                    }
+                };
+                let (data, len) = self.string_parts(string)?;
+                let byte_at = runtime::helper(self.context, &self.module, "venti_str_byte_at")?;
+                self.call_value(byte_at, &[data.into(), len.into(), index.into()], "byte")
+            }
+            "input_venti" => {
+                let prompt = self.expect_string(next_arg()?, "input_venti")?;
+                let (prompt, _) = self.string_parts(prompt)?;
+                let read_line = runtime::helper(self.context, &self.module, "venti_read_line")?;
+                self.call_value(read_line, &[prompt.into()], "line")
+            }
+            "read_file" => {
+                let path = self.expect_string(next_arg()?, "read_file")?;
+                let (path, _) = self.string_parts(path)?;
+                let read = runtime::helper(self.context, &self.module, "venti_file_read")?;
+                self.call_value(read, &[path.into()], "contents")
+            }
+            "write_file" | "append_file" => {
+                let path = self.expect_string(next_arg()?, identifier)?;
+                let contents = self.expect_string(next_arg()?, identifier)?;
+                let (path, _) = self.string_parts(path)?;
+                let (data, len) = self.string_parts(contents)?;
+                let mode = if identifier == "write_file" {
+                    "wb"
+                } else {
+                    "ab"
+                };
+                let mode = self.builder.build_global_string_ptr(mode, "mode")?;
+                let write = runtime::helper(self.context, &self.module, "venti_file_write")?;
+                self.call_value(
+                    write,
+                    &[
+                        path.into(),
+                        data.into(),
+                        len.into(),
+                        mode.as_pointer_value().into(),
+                    ],
+                    "written",
+                )
+            }
+            "random" => {
+                let random = runtime::helper(self.context, &self.module, "venti_random_float")?;
+                self.call_value(random, &[], "random")
+            }
+            "random_int" => {
+                let low = self.expect_int(next_arg()?, "random_int")?;
+                let high = self.expect_int(next_arg()?, "random_int")?;
+                let random = runtime::helper(self.context, &self.module, "venti_random_int")?;
+                self.call_value(random, &[low.into(), high.into()], "random_int")
+            }
+            "seed_random" => {
+                let seed = self.expect_int(next_arg()?, "seed_random")?;
+                let seed_random = runtime::helper(self.context, &self.module, "venti_random_seed")?;
+                self.builder.build_call(seed_random, &[seed.into()], "")?;
+                Ok(self.context.i64_type().const_zero().into())
+            }
+            "now_millis" | "timer" => {
+                let clock =
+                    runtime::helper(self.context, &self.module, &format!("venti_{}", identifier))?;
+                self.call_value(clock, &[], identifier)
+            }
+            "elapsed" => {
+                let start = self.expect_int(next_arg()?, "elapsed")?;
+                let elapsed = runtime::helper(self.context, &self.module, "venti_elapsed")?;
+                self.call_value(elapsed, &[start.into()], "elapsed")
+            }
+            "sleep_ms" => {
+                let millis = self.expect_int(next_arg()?, "sleep_ms")?;
+                let sleep = runtime::helper(self.context, &self.module, "venti_sleep_ms")?;
+                self.builder.build_call(sleep, &[millis.into()], "")?;
+                Ok(self.context.i64_type().const_zero().into())
+            }
+            "exit_venti" => {
+                let code = self.expect_int(next_arg()?, "exit_venti")?;
+                let code =
+                    self.builder
+                        .build_int_truncate(code, self.context.i32_type(), "code")?;
+                // libc's `exit` flushes what the program printed
+                let exit = runtime::libc(self.context, &self.module, "exit")?;
+                self.builder.build_call(exit, &[code.into()], "")?;
+                Ok(self.context.i64_type().const_zero().into())
+            }
+            "args" => {
+                let args = runtime::helper(self.context, &self.module, "venti_args")?;
+                let header = self.call_value(args, &[], "args")?;
+                Ok(self
+                    .string_vector_value(header.into_pointer_value())?
+                    .into())
+            }
+            "env" => {
+                let name = self.expect_string(next_arg()?, "env")?;
+                let (name, _) = self.string_parts(name)?;
+                let env = runtime::helper(self.context, &self.module, "venti_env")?;
+                self.call_value(env, &[name.into()], "env")
+            }
+            "exec" => {
+                let command = self.expect_string(next_arg()?, "exec")?;
+                let (command, _) = self.string_parts(command)?;
+                let exec = runtime::helper(self.context, &self.module, "venti_exec")?;
+                self.call_value(exec, &[command.into()], "exec")
+            }
+            "http_get" | "http_post" => {
+                let url = self.expect_string(next_arg()?, identifier)?;
+                let (url, _) = self.string_parts(url)?;
+                let (method, body, len) = if identifier == "http_post" {
+                    let body = self.expect_string(next_arg()?, identifier)?;
+                    let (body, len) = self.string_parts(body)?;
+                    ("POST", body, len)
+                } else {
+                    (
+                        "GET",
+                        self.context.ptr_type(AddressSpace::default()).const_null(),
+                        self.context.i64_type().const_zero(),
+                    )
+                };
+                let method = self.builder.build_global_string_ptr(method, "method")?;
+                let http = runtime::helper(self.context, &self.module, "venti_http")?;
+                self.call_value(
+                    http,
+                    &[
+                        method.as_pointer_value().into(),
+                        url.into(),
+                        body.into(),
+                        len.into(),
+                    ],
+                    identifier,
+                )
+            }
+            "channel" => {
+                let new = runtime::helper(self.context, &self.module, "venti_channel_new")?;
+                let header = self.call_value(new, &[], "channel")?;
+                let channel = self.builder.build_insert_value(
+                    runtime::channel_type(self.context).get_undef(),
+                    header,
+                    0,
+                    "channel_value",
+                )?;
+                Ok(channel.as_basic_value_enum())
+            }
+            "vec" => {
+                let new = runtime::helper(self.context, &self.module, "venti_vec_new")?;
+                let header = self.call_value(new, &[], "vector")?;
+                Ok(self.vector_value(header.into_pointer_value())?.into())
+            }
+            "send" => {
+                let channel = self.expect_channel(next_arg()?, "send")?;
+                let value = self.channel_slot(next_arg()?)?;
+                let send = runtime::helper(self.context, &self.module, "venti_channel_send")?;
+                self.builder
+                    .build_call(send, &[channel.into(), value.into()], "")?;
+                Ok(self.context.i64_type().const_zero().into())
+            }
+            "recv" => {
+                let channel = self.expect_channel(next_arg()?, "recv")?;
+                let recv = runtime::helper(self.context, &self.module, "venti_channel_recv")?;
+                let slot = self
+                    .call_value(recv, &[channel.into()], "received")?
+                    .into_int_value();
+                self.channel_value(slot, &element)
+            }
+            "map" | "filter" => {
+                let source = self.iterator_header(next_arg()?, identifier)?;
+                let (function, env) = self.expect_closure(next_arg()?, identifier)?;
+                let kind = if identifier == "map" {
+                    runtime::ITER_MAP
+                } else {
+                    runtime::ITER_FILTER
+                };
+                let zero = self.context.i64_type().const_zero();
+                let null = self.context.ptr_type(AddressSpace::default()).const_null();
+                let header =
+                    self.new_iterator(kind, [zero, zero, zero], [null, function, env, source])?;
+                Ok(self.iterator_value(header)?.into())
+            }
+            "reduce" => {
+                let header = self.iterator_header(next_arg()?, identifier)?;
+                let initial = self.expect_int(next_arg()?, identifier)?;
+                let (function, env) = self.expect_closure(next_arg()?, identifier)?;
+                let reduce = runtime::helper(self.context, &self.module, "venti_iter_reduce")?;
+                // Holding a reference while it runs frees an iterator made for the call
+                let iterator = self.iterator_value(header)?;
+                self.retain(iterator.into())?;
+                let reduced = self.call_value(
+                    reduce,
+                    &[header.into(), initial.into(), function.into(), env.into()],
+                    "reduced",
+                )?;
+                self.release(iterator.into())?;
+                Ok(reduced)
+            }
+            "collect" => {
+                let header = self.iterator_header(next_arg()?, identifier)?;
+                let collect = runtime::helper(self.context, &self.module, "venti_iter_collect")?;
+                let iterator = self.iterator_value(header)?;
+                self.retain(iterator.into())?;
+                let buffer = self.call_value(collect, &[header.into()], "collected")?;
+                self.release(iterator.into())?;
+                Ok(buffer)
+            }
+            "sort" | "sort_by" => {
+                let (slots, len) = self.expect_slots(next_arg()?, identifier)?;
+                let (function, env) = match identifier {
+                    "sort_by" => self.expect_closure(next_arg()?, identifier)?,
+                    // A null closure sorts in ascending order
+                    _ => {
+                        let null = self.context.ptr_type(AddressSpace::default()).const_null();
+                        (null, null)
+                    }
+                };
+                let sort = runtime::helper(self.context, &self.module, "venti_sort")?;
+                self.builder.build_call(
+                    sort,
+                    &[slots.into(), len.into(), function.into(), env.into()],
+                    "",
+                )?;
+                Ok(self.context.i64_type().const_zero().into())
+            }
+            "binary_search" => {
+                let (slots, len) = self.expect_slots(next_arg()?, identifier)?;
+                let value = self.expect_int(next_arg()?, identifier)?;
+                let search = runtime::helper(self.context, &self.module, "venti_binary_search")?;
+                self.call_value(search, &[slots.into(), len.into(), value.into()], "found")
+            }
+            "chars" => {
+                let string = self.expect_string(next_arg()?, "chars")?;
+                let (data, len) = self.string_parts(string)?;
+                let chars = runtime::helper(self.context, &self.module, "venti_str_chars")?;
+                let header = self
+                    .call_value(chars, &[data.into(), len.into()], "chars")?
+                    .into_pointer_value();
+                Ok(self.iterator_value(header)?.into())
+            }
+            "tcp_connect" => {
+                let host = self.expect_string(next_arg()?, "tcp_connect")?;
+                let port = self.expect_int(next_arg()?, "tcp_connect")?;
+                let (host, _) = self.string_parts(host)?;
+                let connect = runtime::helper(self.context, &self.module, "venti_tcp_connect")?;
+                self.call_value(connect, &[host.into(), port.into()], "socket")
+            }
+            "tcp_listen" => {
+                let port = self.expect_int(next_arg()?, "tcp_listen")?;
+                let listen = runtime::helper(self.context, &self.module, "venti_tcp_listen")?;
+                self.call_value(listen, &[port.into()], "listener")
+            }
+            "tcp_accept" => {
+                let listener = self.expect_int(next_arg()?, "tcp_accept")?;
+                let accept = runtime::helper(self.context, &self.module, "venti_tcp_accept")?;
+                self.call_value(accept, &[listener.into()], "socket")
+            }
+            "tcp_port" => {
+                let listener = self.expect_int(next_arg()?, "tcp_port")?;
+                let port = runtime::helper(self.context, &self.module, "venti_tcp_port")?;
+                self.call_value(port, &[listener.into()], "port")
+            }
+            "tcp_send" => {
+                let socket = self.expect_int(next_arg()?, "tcp_send")?;
+                let data = self.expect_string(next_arg()?, "tcp_send")?;
+                let (data, len) = self.string_parts(data)?;
+                let send = runtime::helper(self.context, &self.module, "venti_tcp_send")?;
+                self.builder
+                    .build_call(send, &[socket.into(), data.into(), len.into()], "")?;
+                Ok(len.into())
+            }
+            "tcp_recv" => {
+                let socket = self.expect_int(next_arg()?, "tcp_recv")?;
+                let recv = runtime::helper(self.context, &self.module, "venti_tcp_recv")?;
+                self.call_value(recv, &[socket.into()], "received")
+            }
+            "tcp_close" => {
+                let socket = self.expect_int(next_arg()?, "tcp_close")?;
+                let close = runtime::helper(self.context, &self.module, "venti_tcp_close")?;
+                self.builder.build_call(close, &[socket.into()], "")?;
+                Ok(self.context.i64_type().const_zero().into())
+            }
+            "exec_status" => {
+                let exec_status = runtime::helper(self.context, &self.module, "venti_exec_status")?;
+                self.call_value(exec_status, &[], "exec_status")
+            }
+            "json_parse" => {
+                let text = self.expect_string(next_arg()?, "json_parse")?;
+                let (data, len) = self.string_parts(text)?;
+                let parse = runtime::helper(self.context, &self.module, "venti_json_parse")?;
+                let node = self.call_value(parse, &[data.into(), len.into()], "json")?;
+                self.json_value(node.into_pointer_value())
+            }
+            "json_kind" => {
+                let node = self.expect_json(next_arg()?, "json_kind")?;
+                let kind = runtime::helper(self.context, &self.module, "venti_json_kind")?;
+                let kind = self
+                    .call_value(kind, &[node.into()], "kind")?
+                    .into_int_value();
+                let mut name = self.build_string(runtime::JSON_KINDS[0])?;
+                for (index, kind_name) in runtime::JSON_KINDS.iter().enumerate().skip(1) {
+                    let is_kind = self.builder.build_int_compare(
+                        IntPredicate::EQ,
+                        kind,
+                        self.context.i64_type().const_int(index as u64, false),
+                        "is_kind",
+                    )?;
+                    name = self
+                        .builder
+                        .build_select(is_kind, self.build_string(kind_name)?, name, "kind_name")?
+                        .into_struct_value();
+                }
+                Ok(name.into())
+            }
+            "json_stringify" => {
+                let value = next_arg()?;
+                let writer_type = runtime::writer_type(self.context);
+                let writer = self.build_entry_alloca(writer_type.into(), "json_writer")?;
+                self.builder.build_store(writer, writer_type.const_zero())?;
+                self.write_json(writer, value)?;
+                let finish = runtime::helper(self.context, &self.module, "venti_write_finish")?;
+                self.call_value(finish, &[writer.into()], "json")
+            }
+            "sqrt" | "floor" | "ceil" | "sin" | "cos" => {
+                let value = self.expect_number(next_arg()?, identifier)?;
+                let intrinsic = self.float_intrinsic(&format!("llvm.{}", identifier))?;
+                self.call_value(intrinsic, &[value.into()], identifier)
+            }
+            "pow" => {
+                let base = self.expect_number(next_arg()?, "pow")?;
+                let exponent = self.expect_number(next_arg()?, "pow")?;
+                let pow = self.float_intrinsic("llvm.pow")?;
+                self.call_value(pow, &[base.into(), exponent.into()], "pow")
+            }
+            "abs" => match next_arg()? {
+                BasicValueEnum::IntValue(value) => {
+                    let value = self.builder.build_int_s_extend_or_bit_cast(
+                        value,
+                        self.context.i64_type(),
+                        "int",
+                    )?;
+                    let abs = self.intrinsic("llvm.abs", self.context.i64_type().into())?;
+                    // The absolute value of the smallest integer wraps back to itself
+                    let poison_on_min = self.context.bool_type().const_zero();
+                    self.call_value(abs, &[value.into(), poison_on_min.into()], "abs")
+                }
+                value => {
+                    let value = self.expect_number(value, "abs")?;
+                    let fabs = self.float_intrinsic("llvm.fabs")?;
+                    self.call_value(fabs, &[value.into()], "abs")
+                }
+            },
+            "min" | "max" => match (next_arg()?, next_arg()?) {
+                (BasicValueEnum::IntValue(left), BasicValueEnum::IntValue(right)) => {
+                    let i64_type = self.context.i64_type();
+                    let left = self
+                        .builder
+                        .build_int_s_extend_or_bit_cast(left, i64_type, "left")?;
+                    let right = self
+                        .builder
+                        .build_int_s_extend_or_bit_cast(right, i64_type, "right")?;
+                    let name = if identifier == "min" {
+                        "llvm.smin"
+                    } else {
+                        "llvm.smax"
+                    };
+                    let intrinsic = self.intrinsic(name, i64_type.into())?;
+                    self.call_value(intrinsic, &[left.into(), right.into()], identifier)
+                }
+                (left, right) => {
+                    let left = self.expect_number(left, identifier)?;
+                    let right = self.expect_number(right, identifier)?;
+                    // Like fmin and fmax, a NaN gives way to the other operand
+                    let name = if identifier == "min" {
+                        "llvm.minnum"
+                    } else {
+                        "llvm.maxnum"
+                    };
+                    let intrinsic = self.float_intrinsic(name)?;
+                    self.call_value(intrinsic, &[left.into(), right.into()], identifier)
+                }
+            },
+            "upper" | "lower" | "trim" => {
+                let string = self.expect_string(next_arg()?, identifier)?;
+                let (data, len) = self.string_parts(string)?;
+                let helper = runtime::helper(
+                    self.context,
+                    &self.module,
+                    &format!("venti_str_{}", identifier),
+                )?;
+                self.call_value(helper, &[data.into(), len.into()], identifier)
+            }
+            "contains" => {
+                let string = self.expect_string(next_arg()?, "contains")?;
+                let needle = self.expect_string(next_arg()?, "contains")?;
+                let (data, len) = self.string_parts(string)?;
+                let (needle, needle_len) = self.string_parts(needle)?;
+                let contains = runtime::helper(self.context, &self.module, "venti_str_contains")?;
+                self.call_value(
+                    contains,
+                    &[data.into(), len.into(), needle.into(), needle_len.into()],
+                    "contains",
+                )
+            }
+            "substring" => {
+                let string = self.expect_string(next_arg()?, "substring")?;
+                let start = self.expect_int(next_arg()?, "substring")?;
+                let end = self.expect_int(next_arg()?, "substring")?;
+                let (data, len) = self.string_parts(string)?;
+                let substring = runtime::helper(self.context, &self.module, "venti_str_substring")?;
+                self.call_value(
+                    substring,
+                    &[data.into(), len.into(), start.into(), end.into()],
+                    "substring",
+                )
+            }
+            "split" => {
+                let string = self.expect_string(next_arg()?, "split")?;
+                let separator = self.expect_string(next_arg()?, "split")?;
+                let (data, len) = self.string_parts(string)?;
+                let (separator, separator_len) = self.string_parts(separator)?;
+                let split = runtime::helper(self.context, &self.module, "venti_str_split")?;
+                let header = self.call_value(
+                    split,
+                    &[
+                        data.into(),
+                        len.into(),
+                        separator.into(),
+                        separator_len.into(),
+                    ],
+                    "pieces",
+                )?;
+                Ok(self
+                    .string_vector_value(header.into_pointer_value())?
+                    .into())
+            }
+            "to_int" | "to_float" => {
+                let string = self.expect_string(next_arg()?, identifier)?;
+                let (data, len) = self.string_parts(string)?;
+                let parse = runtime::helper(
+                    self.context,
+                    &self.module,
+                    &format!("venti_str_{}", identifier),
+                )?;
+                self.call_value(parse, &[data.into(), len.into()], "parsed")
+            }
+            "panic_venti" => {
+                let message = self.expect_string(next_arg()?, "panic_venti")?;
+                let (data, _) = self.string_parts(message)?;
+                let name = self.panic_location()?;
+                let name = self.builder.build_global_string_ptr(&name, "function")?;
+                let backtrace = self
+                    .context
+                    .bool_type()
+                    .const_int(self.debug.is_some() as u64, false);
+                let panic = runtime::helper(self.context, &self.module, "venti_panic")?;
+                self.builder.build_call(
+                    panic,
+                    &[
+                        data.into(),
+                        name.as_pointer_value().into(),
+                        backtrace.into(),
+                    ],
+                    "",
+                )?;
+                // `venti_panic` never returns, so the value is never used
+                Ok(self.context.i64_type().const_zero().into())
+            }
+            _ => Err(VentiError::CodegenError(
+                format!("Unknown builtin '{}'", identifier),
+                None,
+            )),
+        }
+    }
+
+    /// Returns the name the source gives the function being compiled, which a panic in it
+    /// reports: lambdas and spawned blocks have none, and async functions are named after
+    /// the function rather than the body codegen splits off.
+    fn panic_location(&self) -> Result<String, VentiError> {
+        let symbol = self
+            .current_function()?
+            .get_name()
+            .to_string_lossy()
+            .into_owned();
+        let name = symbols::demangle(&symbol).unwrap_or(&symbol);
+        Ok(if name.starts_with("__venti_lambda_") {
+            "<lambda>".to_string()
+        } else if name.starts_with("__venti_spawn_") {
+            "<spawn>".to_string()
+        } else {
+            name.strip_prefix("__venti_async_")
+                .unwrap_or(name)
+                .to_string()
+        })
+    }
+
+    /// Builds the calls writing `value` as JSON into the `venti.writer` at `writer`.
+    ///
+    /// Arrays are unrolled, since their length is known here, and maps and strings are
+    /// written by runtime helpers.
+    fn write_json(
+        &self,
+        writer: PointerValue<'ctx>,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<(), VentiError> {
+        let write = |name: &str, args: &[BasicMetadataValueEnum<'ctx>]| {
+            let helper = runtime::helper(self.context, &self.module, name)?;
+            let mut call_args = vec![writer.into()];
+            call_args.extend_from_slice(args);
+            self.builder.build_call(helper, &call_args, "")?;
+            Ok::<(), VentiError>(())
+        };
+        let write_text = |text: &str| {
+            let bytes = self.builder.build_global_string_ptr(text, "json_text")?;
+            let len = self.context.i64_type().const_int(text.len() as u64, false);
+            write(
+                "venti_write_raw",
+                &[bytes.as_pointer_value().into(), len.into()],
+            )
+        };
+        match value {
+            BasicValueEnum::IntValue(value) if value.get_type().get_bit_width() == 1 => {
+                let (text, len) = self.string_parts(self.bool_string(value)?)?;
+                write("venti_write_raw", &[text.into(), len.into()])
+            }
+            BasicValueEnum::IntValue(value) => {
+                let value = self.builder.build_int_s_extend_or_bit_cast(
+                    value,
+                    self.context.i64_type(),
+                    "json_int",
+                )?;
+                write("venti_json_int", &[value.into()])
+            }
+            BasicValueEnum::FloatValue(value) => {
+                let value =
+                    self.builder
+                        .build_float_ext(value, self.context.f64_type(), "json_float")?;
+                write("venti_json_float", &[value.into()])
+            }
+            BasicValueEnum::StructValue(string)
+                if runtime::is_named(string.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                let (data, len) = self.string_parts(string)?;
+                write("venti_json_quote", &[data.into(), len.into()])
+            }
+            BasicValueEnum::StructValue(map)
+                if runtime::is_named(map.get_type(), runtime::MAP_TYPE_NAME) =>
+            {
+                let header = self.builder.build_extract_value(map, 0, "map_header")?;
+                write("venti_json_map", &[header.into()])
+            }
+            BasicValueEnum::StructValue(json)
+                if runtime::is_named(json.get_type(), runtime::JSON_TYPE_NAME) =>
+            {
+                let node = self.builder.build_extract_value(json, 0, "json_node")?;
+                write("venti_json_write", &[node.into()])
+            }
+            BasicValueEnum::ArrayValue(array) => {
+                write_text("[")?;
+                for index in 0..array.get_type().len() {
+                    if index > 0 {
+                        write_text(",")?;
+                    }
+                    let element = self
+                        .builder
+                        .build_extract_value(array, index, "json_element")?;
+                    self.write_json(writer, element)?;
+                }
+                write_text("]")
+            }
+            _ => Err(VentiError::CodegenError(
+                "'json_stringify' cannot write this value".to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Lowers `free(buffer)`, releasing the buffer's slots.
+    ///
+    /// When the buffer is named by a variable, the variable is reset to the empty buffer,
+    /// so using it afterwards is a bounds error and freeing it again does nothing.
+    fn compile_free(&mut self, arg: Option<Expr>) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let arg = arg
+            .ok_or_else(|| VentiError::CodegenError("'free' expects a buffer".to_string(), None))?;
+        let owner = match &arg.kind {
+            ExprKind::Identifier(name) => Some(name.clone()),
+            _ => None,
+        };
+        let buffer = match self.compile_expr(arg)? {
+            BasicValueEnum::StructValue(buffer)
+                if runtime::is_named(buffer.get_type(), runtime::BUFFER_TYPE_NAME) =>
+            {
+                buffer
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "'free' expects a buffer".to_string(),
+                    None,
+                ))
+            }
+        };
+        let data = self.builder.build_extract_value(buffer, 0, "buffer_data")?;
+        let free = runtime::libc(self.context, &self.module, "free")?;
+        self.builder.build_call(free, &[data.into()], "")?;
+
+        let slot = owner.and_then(|name| match self.locals.get(&name) {
+            Some(local) => Some(local.slot),
+            None => self.global(&name).map(|global| global.as_pointer_value()),
+        });
+        if let Some(slot) = slot {
+            self.builder
+                .build_store(slot, buffer.get_type().const_zero())?;
+        }
+        Ok(self.context.i64_type().const_zero().into())
+    }
+
+    /// Returns the address of slot `index` of `buffer`, checking it against the length.
+    fn buffer_slot(
+        &mut self,
+        buffer: StructValue<'ctx>,
+        index: Expr,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let index = match self.compile_expr(index)? {
+            BasicValueEnum::IntValue(index) => self.builder.build_int_s_extend_or_bit_cast(
+                index,
+                self.context.i64_type(),
+                "index",
+            )?,
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Buffer index must be an integer".to_string(),
+                    None,
+                ))
+            }
+        };
+        let data = self.builder.build_extract_value(buffer, 0, "buffer_data")?;
+        let len = self.builder.build_extract_value(buffer, 1, "buffer_len")?;
+        let slot = runtime::helper(self.context, &self.module, "venti_buffer_slot")?;
+        Ok(self
+            .call_value(slot, &[data.into(), len.into(), index.into()], "slot")?
+            .into_pointer_value())
+    }
+
+    /// Compiles the index of a vector element, which must be an integer.
+    fn compile_vector_index(&mut self, index: Expr) -> Result<IntValue<'ctx>, VentiError> {
+        match self.compile_expr(index)? {
+            BasicValueEnum::IntValue(index) => Ok(self.builder.build_int_s_extend_or_bit_cast(
+                index,
+                self.context.i64_type(),
+                "index",
+            )?),
+            _ => Err(VentiError::CodegenError(
+                "Vector index must be an integer".to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Returns the address of element `index` of the vector behind `header`, checking it
+    /// against the length.
+    fn vector_slot(
+        &self,
+        header: PointerValue<'ctx>,
+        index: IntValue<'ctx>,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let slot = runtime::helper(self.context, &self.module, "venti_vec_slot")?;
+        Ok(self
+            .call_value(slot, &[header.into(), index.into()], "slot")?
+            .into_pointer_value())
+    }
+
+    /// Returns `value` as a string, or an error naming the builtin `name` that needed one.
+    fn expect_string(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        name: &str,
+    ) -> Result<StructValue<'ctx>, VentiError> {
+        match value {
+            BasicValueEnum::StructValue(string)
+                if runtime::is_named(string.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                Ok(string)
+            }
+            _ => Err(VentiError::CodegenError(
+                format!("'{}' expects a string", name),
+                None,
+            )),
+        }
+    }
+
+    /// Wraps the string vector header `header` in a `venti.strvec` value.
+    fn string_vector_value(
+        &self,
+        header: PointerValue<'ctx>,
+    ) -> Result<StructValue<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_insert_value(
+                runtime::string_vector_type(self.context).get_undef(),
+                header,
+                0,
+                "string_vector_value",
+            )?
+            .into_struct_value())
+    }
+
+    /// Returns the node pointer of the JSON value `value`, or an error naming the builtin
+    /// `name` that needed one.
+    fn expect_json(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        match value {
+            BasicValueEnum::StructValue(json)
+                if runtime::is_named(json.get_type(), runtime::JSON_TYPE_NAME) =>
+            {
+                let node = self.builder.build_extract_value(json, 0, "json_node")?;
+                Ok(node.into_pointer_value())
+            }
+            _ => Err(VentiError::CodegenError(
+                format!("'{}' expects a JSON value", name),
+                None,
+            )),
+        }
+    }
+
+    /// Builds the `venti.json` value of the node at `node`.
+    fn json_value(&self, node: PointerValue<'ctx>) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_insert_value(
+                runtime::json_type(self.context).get_undef(),
+                node,
+                0,
+                "json",
+            )?
+            .as_basic_value_enum())
+    }
+
+    /// Returns the header pointer of the channel `value`, or an error naming the builtin
+    /// `name` that needed one.
+    fn expect_channel(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        match value {
+            BasicValueEnum::StructValue(channel)
+                if runtime::is_named(channel.get_type(), runtime::CHANNEL_TYPE_NAME) =>
+            {
+                let header = self.builder.build_extract_value(channel, 0, "channel")?;
+                Ok(header.into_pointer_value())
+            }
+            _ => Err(VentiError::CodegenError(
+                format!("'{}' expects a channel", name),
+                None,
+            )),
+        }
+    }
+
+    /// Returns the header pointer of the vector `value`, or an error naming the builtin or
+    /// method `name` that needed one.
+    fn expect_vector(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        match value {
+            BasicValueEnum::StructValue(vector)
+                if runtime::is_named(vector.get_type(), runtime::VECTOR_TYPE_NAME) =>
+            {
+                let header = self.builder.build_extract_value(vector, 0, "vector")?;
+                Ok(header.into_pointer_value())
+            }
+            _ => Err(VentiError::CodegenError(
+                format!("'{}' expects a vector", name),
+                None,
+            )),
+        }
+    }
+
+    /// Returns the function and environment pointers of the closure `value`, or an error
+    /// naming the builtin `name` that needed one.
+    fn expect_closure(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        name: &str,
+    ) -> Result<(PointerValue<'ctx>, PointerValue<'ctx>), VentiError> {
+        match value {
+            // Closures are the only unnamed structs
+            BasicValueEnum::StructValue(closure)
+                if closure.get_type().get_name().is_none()
+                    && closure.get_type().count_fields() == 2 =>
+            {
+                let function = self.builder.build_extract_value(closure, 0, "closure_fn")?;
+                let env = self
+                    .builder
+                    .build_extract_value(closure, 1, "closure_env")?;
+                Ok((function.into_pointer_value(), env.into_pointer_value()))
+            }
+            _ => Err(VentiError::CodegenError(
+                format!("'{}' expects a lambda", name),
+                None,
+            )),
+        }
+    }
+
+    /// Returns the slots and length of the vector or buffer `value`, or an error naming the
+    /// builtin `name` that needed one.
+    fn expect_slots(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        name: &str,
+    ) -> Result<(PointerValue<'ctx>, IntValue<'ctx>), VentiError> {
+        match value {
+            BasicValueEnum::StructValue(buffer)
+                if runtime::is_named(buffer.get_type(), runtime::BUFFER_TYPE_NAME) =>
+            {
+                let data = self.builder.build_extract_value(buffer, 0, "buffer_data")?;
+                let len = self.builder.build_extract_value(buffer, 1, "buffer_len")?;
+                Ok((data.into_pointer_value(), len.into_int_value()))
+            }
+            BasicValueEnum::StructValue(vector)
+                if runtime::is_named(vector.get_type(), runtime::VECTOR_TYPE_NAME) =>
+            {
+                let header = self.expect_vector(value, name)?;
+                let header_type = runtime::vector_header_type(self.context);
+                let len_field =
+                    self.builder
+                        .build_struct_gep(header_type, header, 0, "len_field")?;
+                let len =
+                    self.builder
+                        .build_load(self.context.i64_type(), len_field, "vector_len")?;
+                let slots_field =
+                    self.builder
+                        .build_struct_gep(header_type, header, 2, "slots_field")?;
+                let slots = self.builder.build_load(
+                    self.context.ptr_type(AddressSpace::default()),
+                    slots_field,
+                    "slots",
+                )?;
+                Ok((slots.into_pointer_value(), len.into_int_value()))
+            }
+            _ => Err(VentiError::CodegenError(
+                format!("'{}' expects a vector or buffer", name),
+                None,
+            )),
+        }
+    }
+
+    /// Returns the header of an iterator over the values of `value`, or an error naming the
+    /// builtin `name` that needed one.
+    ///
+    /// An iterator is returned as it is. Ranges are counted through, and the elements of an
+    /// array, buffer or vector are copied into slots the new iterator owns.
+    fn iterator_header(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let null = self.context.ptr_type(AddressSpace::default()).const_null();
+        let (zero, one) = (i64_type.const_zero(), i64_type.const_int(1, false));
+        let malloc = runtime::libc(self.context, &self.module, "malloc")?;
+        match value {
+            BasicValueEnum::StructValue(iterator)
+                if runtime::is_named(iterator.get_type(), runtime::ITERATOR_TYPE_NAME) =>
+            {
+                let header = self.builder.build_extract_value(iterator, 0, "iterator")?;
+                Ok(header.into_pointer_value())
+            }
+            BasicValueEnum::StructValue(range)
+                if runtime::is_named(range.get_type(), runtime::RANGE_TYPE_NAME) =>
+            {
+                let field = |index, name| {
+                    self.builder
+                        .build_extract_value(range, index, name)
+                        .map(|value| value.into_int_value())
+                };
+                let counts = [
+                    field(0, "range_start")?,
+                    field(1, "range_end")?,
+                    field(2, "range_step")?,
+                ];
+                self.new_iterator(runtime::ITER_SOURCE, counts, [null; 4])
+            }
+            BasicValueEnum::StructValue(buffer)
+                if runtime::is_named(buffer.get_type(), runtime::BUFFER_TYPE_NAME) =>
+            {
+                let data = self
+                    .builder
+                    .build_extract_value(buffer, 0, "buffer_data")?
+                    .into_pointer_value();
+                let len = self
+                    .builder
+                    .build_extract_value(buffer, 1, "buffer_len")?
+                    .into_int_value();
+                let size =
+                    self.builder
+                        .build_int_mul(len, i64_type.const_int(8, false), "buffer_size")?;
+                let slots = self
+                    .call_value(malloc, &[size.into()], "slots")?
+                    .into_pointer_value();
+                self.builder.build_memcpy(slots, 8, data, 8, size)?;
+                self.new_iterator(
+                    runtime::ITER_SOURCE,
+                    [zero, len, one],
+                    [slots, null, null, null],
+                )
+            }
+            BasicValueEnum::StructValue(vector)
+                if runtime::is_named(vector.get_type(), runtime::VECTOR_TYPE_NAME) =>
+            {
+                let header = self.expect_vector(value, name)?;
+                let header_type = runtime::vector_header_type(self.context);
+                let len_field =
+                    self.builder
+                        .build_struct_gep(header_type, header, 0, "len_field")?;
+                let len = self
+                    .builder
+                    .build_load(i64_type, len_field, "vector_len")?
+                    .into_int_value();
+                let slots_field =
+                    self.builder
+                        .build_struct_gep(header_type, header, 2, "slots_field")?;
+                let data = self
+                    .builder
+                    .build_load(
+                        self.context.ptr_type(AddressSpace::default()),
+                        slots_field,
+                        "data",
+                    )?
+                    .into_pointer_value();
+                let size =
+                    self.builder
+                        .build_int_mul(len, i64_type.const_int(8, false), "vector_size")?;
+                let slots = self
+                    .call_value(malloc, &[size.into()], "slots")?
+                    .into_pointer_value();
+                self.builder.build_memcpy(slots, 8, data, 8, size)?;
+                self.new_iterator(
+                    runtime::ITER_SOURCE,
+                    [zero, len, one],
+                    [slots, null, null, null],
+                )
+            }
+            BasicValueEnum::ArrayValue(array)
+                if array.get_type().get_element_type() == i64_type.into() =>
+            {
+                let array_type = array.get_type();
+                let len = i64_type.const_int(array_type.len() as u64, false);
+                let slots = if array_type.is_empty() {
+                    null
+                } else {
+                    let size = array_type.size_of().ok_or_else(|| {
+                        VentiError::CodegenError("Array is unsized".to_string(), None)
+                    })?;
+                    let slots = self
+                        .call_value(malloc, &[size.into()], "slots")?
+                        .into_pointer_value();
+                    self.builder.build_store(slots, array)?;
+                    slots
+                };
+                self.new_iterator(
+                    runtime::ITER_SOURCE,
+                    [zero, len, one],
+                    [slots, null, null, null],
+                )
+            }
+            _ => Err(VentiError::CodegenError(
+                format!(
+                    "'{}' expects a range, buffer, iterator or array of ints",
+                    name
+                ),
+                None,
+            )),
+        }
+    }
+
+    /// Calls `venti_iter_new` with the `kind`, `{ pos, end, step }` and
+    /// `{ slots, function, env, source }` of a new iterator.
+    ///
+    /// # Returns
+    ///
+    /// The header of the iterator, which is unowned.
+    fn new_iterator(
+        &self,
+        kind: u64,
+        counts: [IntValue<'ctx>; 3],
+        pointers: [PointerValue<'ctx>; 4],
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let new = runtime::helper(self.context, &self.module, "venti_iter_new")?;
+        let mut args: Vec<BasicMetadataValueEnum> =
+            vec![self.context.i64_type().const_int(kind, false).into()];
+        args.extend(
+            counts
+                .iter()
+                .map(|&count| BasicMetadataValueEnum::from(count)),
+        );
+        args.extend(
+            pointers
+                .iter()
+                .map(|&pointer| BasicMetadataValueEnum::from(pointer)),
+        );
+        Ok(self
+            .call_value(new, &args, "iterator")?
+            .into_pointer_value())
+    }
+
+    /// Wraps the vector header `header` in a `venti.vec` value.
+    fn vector_value(&self, header: PointerValue<'ctx>) -> Result<StructValue<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_insert_value(
+                runtime::vector_type(self.context).get_undef(),
+                header,
+                0,
+                "vector_value",
+            )?
+            .into_struct_value())
+    }
+
+    /// Wraps the iterator header `header` in a `venti.iter` value.
+    fn iterator_value(&self, header: PointerValue<'ctx>) -> Result<StructValue<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_insert_value(
+                runtime::iterator_type(self.context).get_undef(),
+                header,
+                0,
+                "iterator_value",
+            )?
+            .into_struct_value())
+    }
+
+    /// Returns `value` as a float, converting an integer, or an error naming the math
+    /// builtin `name` that needed a number.
+    fn expect_number(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        name: &str,
+    ) -> Result<FloatValue<'ctx>, VentiError> {
+        match value {
+            BasicValueEnum::FloatValue(value) => Ok(value),
+            BasicValueEnum::IntValue(value) => self.promote_to_float(value),
+            _ => Err(VentiError::CodegenError(
+                format!("'{}' expects a number", name),
+                None,
+            )),
+        }
+    }
+
+    /// Declares the overload of the LLVM intrinsic `name` for values of type `ty`.
+    fn intrinsic(
+        &self,
+        name: &str,
+        ty: BasicTypeEnum<'ctx>,
+    ) -> Result<FunctionValue<'ctx>, VentiError> {
+        Intrinsic::find(name)
+            .and_then(|intrinsic| intrinsic.get_declaration(&self.module, &[ty]))
+            .ok_or_else(|| {
+                VentiError::CodegenError(format!("LLVM has no intrinsic '{}'", name), None)
+            })
+    }
+
+    /// Declares the `f64` overload of the LLVM intrinsic `name`, which LLVM lowers to an
+    /// instruction or a call into libm.
+    fn float_intrinsic(&self, name: &str) -> Result<FunctionValue<'ctx>, VentiError> {
+        self.intrinsic(name, self.context.f64_type().into())
+    }
+
+    /// Returns `value` as a 64-bit integer, or an error naming the builtin `name` that
+    /// needed one.
+    fn expect_int(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        name: &str,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        match value {
+            BasicValueEnum::IntValue(value) => Ok(self.builder.build_int_s_extend_or_bit_cast(
+                value,
+                self.context.i64_type(),
+                "int",
+            )?),
+            _ => Err(VentiError::CodegenError(
+                format!("'{}' expects an integer", name),
+                None,
+            )),
+        }
+    }
+
+    /// Turns a value sent on a channel into the integer slot the channel keeps it in: a
+    /// float by its bits, and a bool as 0 or 1.
+    fn channel_slot(&self, value: BasicValueEnum<'ctx>) -> Result<IntValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        match value {
+            BasicValueEnum::FloatValue(value) => Ok(self
+                .builder
+                .build_bit_cast(value, i64_type, "bits")?
+                .into_int_value()),
+            BasicValueEnum::IntValue(value) if value.get_type().get_bit_width() == 1 => {
+                Ok(self.builder.build_int_z_extend(value, i64_type, "bool")?)
+            }
+            value => self.expect_int(value, "send"),
+        }
+    }
+
+    /// Turns the integer slot a channel kept a value in back into a value of `element`,
+    /// the type of the values sent on it.
+    fn channel_value(
+        &self,
+        slot: IntValue<'ctx>,
+        element: &Type,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        match element {
+            Type::Float => {
+                Ok(self
+                    .builder
+                    .build_bit_cast(slot, self.context.f64_type(), "float")?)
+            }
+            Type::Bool => Ok(self
+                .builder
+                .build_int_compare(
+                    IntPredicate::NE,
+                    slot,
+                    self.context.i64_type().const_zero(),
+                    "bool",
+                )?
+                .into()),
+            _ => Ok(slot.into()),
+        }
+    }
+
+    /// Builds the constant string value for `text`: its bytes, NUL-terminated, and length.
+    ///
+    /// The bytes are stored after an immortal reference count, so literals are never freed.
+    fn build_string(&self, text: &str) -> Result<StructValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let bytes = self.context.const_string(text.as_bytes(), true);
+        let literal = self.context.const_struct(
+            &[
+                i64_type
+                    .const_int(runtime::IMMORTAL_COUNT as u64, true)
+                    .into(),
+                bytes.into(),
+            ],
+            false,
+        );
+        let global = self.module.add_global(literal.get_type(), None, "str");
+        global.set_initializer(&literal);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+        global.set_unnamed_addr(true);
+        global.set_alignment(8);
+        // Safety: field 1 of the literal's struct is its bytes
+        let data = unsafe {
+            global.as_pointer_value().const_gep(
+                literal.get_type(),
+                &[i32_type.const_zero(), i32_type.const_int(1, false)],
+            )
+        };
+        let len = i64_type.const_int(text.len() as u64, false);
+        Ok(runtime::string_type(self.context).const_named_struct(&[data.into(), len.into()]))
+    }
+
+    /// Returns the string `true` or `false`, following the bool `value`.
+    fn bool_string(&self, value: IntValue<'ctx>) -> Result<StructValue<'ctx>, VentiError> {
+        let text = self.builder.build_select(
+            value,
+            self.build_string("true")?,
+            self.build_string("false")?,
+            "bool_text",
+        )?;
+        Ok(text.into_struct_value())
+    }
+
+    /// Splits a string value into its data pointer and length.
+    fn string_parts(
+        &self,
+        string: StructValue<'ctx>,
+    ) -> Result<(PointerValue<'ctx>, IntValue<'ctx>), VentiError> {
+        let data = self.builder.build_extract_value(string, 0, "str_data")?;
+        let len = self.builder.build_extract_value(string, 1, "str_len")?;
+        Ok((data.into_pointer_value(), len.into_int_value()))
+    }
+
+    /// Splits a string value for printing, replacing `nothing` with the text "nothing".
+    fn printable_string(
+        &self,
+        string: StructValue<'ctx>,
+    ) -> Result<(PointerValue<'ctx>, IntValue<'ctx>), VentiError> {
+        let (data, len) = self.string_parts(string)?;
+        let (nothing, nothing_len) = self.string_parts(self.build_string("nothing")?)?;
+        let is_null = self.builder.build_is_null(data, "is_nothing")?;
+        let data = self
+            .builder
+            .build_select(is_null, nothing, data, "print_str")?;
+        let len = self
+            .builder
+            .build_select(is_null, nothing_len, len, "print_len")?;
+        Ok((data.into_pointer_value(), len.into_int_value()))
+    }
+
+    /// Returns true when the builder sits in a block that has no terminator yet.
+    fn block_is_open(&self) -> bool {
+        self.builder
+            .get_insert_block()
+            .is_some_and(|block| block.get_terminator().is_none())
+    }
+
+    /// Lifts a lambda into a uniquely named LLVM function and builds its closure value.
+    ///
+    /// Enclosing locals referenced by the body are copied into a heap-allocated environment
+    /// struct, which the lifted function receives as its first argument. The closure itself
+    /// is a `{ function pointer, environment pointer }` pair.
+    fn compile_lambda(
+        &mut self,
+        params: Vec<Parameter>,
+        body: Expr,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let mut referenced = Vec::new();
+        referenced_identifiers(&body, &mut referenced);
+        let mut captures = Vec::new();
+        for name in referenced {
+            if params.iter().any(|param| param.name == name) {
+                continue;
+            }
+            if let Some(local) = self.locals.get(&name).copied() {
+                // Captures are by value, taken when the lambda is created
+                let value = self
+                    .builder
+                    .build_load(local.value_type, local.slot, &name)?;
+                captures.push((name, value));
+            }
+        }
+
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let env_type = self.context.struct_type(
+            &captures
+                .iter()
+                .map(|(_, value)| value.get_type())
+                .collect::<Vec<_>>(),
+            false,
+        );
+        let env = if captures.is_empty() {
+            ptr_type.const_null()
+        } else {
+            let malloc = runtime::libc(self.context, &self.module, "malloc")?;
+            let env_size = env_type.size_of().ok_or_else(|| {
+                VentiError::CodegenError("Lambda environment is unsized".to_string(), None)
+            })?;
+            let env = self
+                .call_value(malloc, &[env_size.into()], "lambda_env")?
+                .into_pointer_value();
+            // The environment is never freed, so it keeps the strings and maps it captures
+            for (index, (_, value)) in captures.iter().enumerate() {
+                let field =
+                    self.builder
+                        .build_struct_gep(env_type, env, index as u32, "env_field")?;
+                self.builder.build_store(field, *value)?;
+                self.retain(*value)?;
+            }
+            env
+        };
+
+        let mut param_types: Vec<BasicMetadataTypeEnum> = vec![ptr_type.into()];
+        param_types.extend(
+            params
+                .iter()
+                .map(|param| BasicMetadataTypeEnum::from(self.llvm_type(param.var_type.as_ref()))),
+        );
+        let i64_type = self.context.i64_type();
+        let fn_type = i64_type.fn_type(&param_types, false);
+        let name = format!("__venti_lambda_{}", self.lambda_count);
+        self.lambda_count += 1;
+        // Private, since a function's module read from the cache numbers its lambdas afresh
+        let function = self
+            .module
+            .add_function(&name, fn_type, Some(Linkage::Internal));
+
+        let previous_block = self.builder.get_insert_block();
+        let previous_locals = std::mem::take(&mut self.locals);
+        let previous_owned = std::mem::take(&mut self.owned);
+        let previous_temporaries = std::mem::take(&mut self.temporaries);
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        self.enter_debug_scope(function);
+
+        let mut lambda_params = function.get_param_iter();
+        let env_param = lambda_params
+            .next()
+            .ok_or_else(|| {
+                VentiError::CodegenError("Lambda is missing its environment".to_string(), None)
+            })?
+            .into_pointer_value();
+        env_param.set_name("env");
+        for (index, (name, value)) in captures.iter().enumerate() {
+            let field =
+                self.builder
+                    .build_struct_gep(env_type, env_param, index as u32, "env_field")?;
+            let captured = self.builder.build_load(value.get_type(), field, name)?;
+            self.bind_local(name, captured)?;
+        }
+        for (index, (param, value)) in params.iter().zip(lambda_params).enumerate() {
+            value.set_name(&param.name);
+            // The environment is argument 1
+            self.bind_variable(&param.name, value, Some(index as u32 + 2))?;
+        }
+
+        let result = match self.compile_expr(body)? {
+            BasicValueEnum::IntValue(value) => self.widen(value, "lambda_result")?,
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Lambdas must return an integer value".to_string(),
+                    None,
+                ))
+            }
+        };
+        self.release_temporaries(0)?;
+        self.release_owned(0)?;
+        self.builder.build_return(Some(&result))?;
+        self.leave_debug_scope();
+
+        self.locals = previous_locals;
+        self.owned = previous_owned;
+        self.temporaries = previous_temporaries;
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+
+        let closure_type = self
+            .context
+            .struct_type(&[ptr_type.into(), ptr_type.into()], false);
+        let closure = self.builder.build_insert_value(
+            closure_type.get_undef(),
+            function.as_global_value().as_pointer_value(),
+            0,
+            "closure_fn",
+        )?;
+        let closure = self
+            .builder
+            .build_insert_value(closure, env, 1, "closure")?;
+        Ok(closure.as_basic_value_enum())
+    }
+
+    /// Lowers `left ?? right`, evaluating `right` only when `left` is `nothing`.
+    ///
+    /// Only pointer-typed values can be `nothing`, so any other left operand is returned
+    /// as-is without evaluating the right-hand side.
+    fn compile_coalesce(
+        &mut self,
+        left: Expr,
+        right: Expr,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let left = match self.compile_expr(left)? {
+            BasicValueEnum::StructValue(left)
+                if runtime::is_named(left.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                left
+            }
+            other => return Ok(other),
+        };
+        let function = self.current_function()?;
+        let left_block = self.current_block()?;
+        let right_block = self.context.append_basic_block(function, "coalesce_rhs");
+        let end_block = self.context.append_basic_block(function, "coalesce_end");
+
+        let (data, _) = self.string_parts(left)?;
+        let is_null = self.builder.build_is_null(data, "is_nothing")?;
+        self.builder
+            .build_conditional_branch(is_null, right_block, end_block)?;
+
+        self.builder.position_at_end(right_block);
+        let right = match self.compile_expr(right)? {
+            BasicValueEnum::StructValue(right) if right.get_type() == left.get_type() => right,
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Both operands of '??' must be nullable values".to_string(),
+                    None,
+                ))
+            }
+        };
+        let right_end = self.current_block()?;
+        self.builder.build_unconditional_branch(end_block)?;
+
+        self.builder.position_at_end(end_block);
+        let phi = self.builder.build_phi(left.get_type(), "coalesce")?;
+        phi.add_incoming(&[(&left, left_block), (&right, right_end)]);
+        Ok(phi.as_basic_value())
+    }
+
+    /// Lowers `left && right` or `left || right`, evaluating `right` only when `left` does
+    /// not already decide the result.
+    fn compile_logical(
+        &mut self,
+        left: Expr,
+        op: BinOp,
+        right: Expr,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let left = self.compile_bool_operand(left, op)?;
+        let function = self.current_function()?;
+        let left_block = self.current_block()?;
+        let right_block = self.context.append_basic_block(function, "logical_rhs");
+        let end_block = self.context.append_basic_block(function, "logical_end");
+        let (on_true, on_false) = match op {
+            BinOp::And => (right_block, end_block),
+            _ => (end_block, right_block),
+        };
+        self.builder
+            .build_conditional_branch(left, on_true, on_false)?;
+
+        self.builder.position_at_end(right_block);
+        let right = self.compile_bool_operand(right, op)?;
+        let right_end = self.current_block()?;
+        self.builder.build_unconditional_branch(end_block)?;
+
+        self.builder.position_at_end(end_block);
+        let phi = self
+            .builder
+            .build_phi(self.context.bool_type(), "logical")?;
+        phi.add_incoming(&[(&left, left_block), (&right, right_end)]);
+        Ok(phi.as_basic_value())
+    }
+
+    /// Compiles an operand of `&&` or `||`, which must be a bool.
+    fn compile_bool_operand(
+        &mut self,
+        operand: Expr,
+        op: BinOp,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        match self.compile_expr(operand)? {
+            BasicValueEnum::IntValue(value) if value.get_type().get_bit_width() == 1 => Ok(value),
+            _ => Err(VentiError::CodegenError(
+                format!(
+                    "Both operands of '{}' must be bools",
+                    if op == BinOp::And { "&&" } else { "||" }
+                ),
+                None,
+            )),
+        }
+    }
+
+    /// Returns the block the builder is currently emitting into.
+    fn current_block(&self) -> Result<BasicBlock<'ctx>, VentiError> {
+        self.builder.get_insert_block().ok_or_else(|| {
+            VentiError::CodegenError("Builder position is not set".to_string(), None)
+        })
+    }
+
+    /// Returns the function the builder is currently emitting into.
+    fn current_function(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        self.current_block()?.get_parent().ok_or_else(|| {
+            VentiError::CodegenError("Block has no parent function".to_string(), None)
+        })
+    }
+
+    /// Calls a closure value produced by `compile_lambda`.
+    fn compile_closure_call(
+        &mut self,
+        closure: BasicValueEnum<'ctx>,
+        args: Vec<Expr>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let closure = match closure {
+            BasicValueEnum::StructValue(closure) if closure.get_type().count_fields() == 2 => {
+                closure
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Expression is not callable".to_string(),
+                    None,
+                ))
+            }
+        };
+        let function = self
+            .builder
+            .build_extract_value(closure, 0, "closure_fn")?
+            .into_pointer_value();
+        let env = self
+            .builder
+            .build_extract_value(closure, 1, "closure_env")?;
+
+        let mut compiled_args = vec![env];
+        for arg in args {
+            compiled_args.push(self.compile_expr(arg)?);
+        }
+        let param_types = compiled_args
+            .iter()
+            .map(|arg| arg.get_type().into())
+            .collect::<Vec<BasicMetadataTypeEnum>>();
+        let compiled_args = compiled_args
+            .into_iter()
+            .map(BasicMetadataValueEnum::from)
+            .collect::<Vec<_>>();
+        let fn_type = self.context.i64_type().fn_type(&param_types, false);
+        self.builder
+            .build_indirect_call(fn_type, function, &compiled_args, "closure_call")?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| VentiError::CodegenError("Closure returned no value".to_string(), None))
+    }
+
+    /// Maps an optional type annotation to its LLVM type; unannotated values are integers.
+    fn llvm_type(&self, var_type: Option<&VarType>) -> BasicTypeEnum<'ctx> {
+        match var_type {
+            Some(VarType::Float) => self.context.f64_type().into(),
+            Some(VarType::Bool) => self.context.bool_type().into(),
+            Some(VarType::String) => runtime::string_type(self.context).into(),
+            Some(VarType::Trait(name)) => runtime::trait_type(self.context, name).into(),
+            Some(VarType::Vec) => runtime::vector_type(self.context).into(),
+            Some(VarType::Int) | None => self.context.i64_type().into(),
+        }
+    }
+
+    /// Maps a type the analyzer inferred to the LLVM type of its values, or `None` for a
+    /// type only known once it is compiled.
+    fn value_type(&self, ty: &Type) -> Option<BasicTypeEnum<'ctx>> {
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        Some(match ty {
+            Type::Int => self.context.i64_type().into(),
+            Type::Float => self.context.f64_type().into(),
+            Type::Bool => self.context.bool_type().into(),
+            Type::String | Type::Nothing => runtime::string_type(self.context).into(),
+            Type::Array(element, len) => self.value_type(element)?.array_type(*len as u32).into(),
+            Type::Map => runtime::map_type(self.context).into(),
+            Type::Buffer => runtime::buffer_type(self.context).into(),
+            Type::Range => runtime::range_type(self.context).into(),
+            Type::Lambda(_) => self
+                .context
+                .struct_type(&[ptr_type.into(), ptr_type.into()], false)
+                .into(),
+            Type::Task => ptr_type.into(),
+            Type::Channel(_) => runtime::channel_type(self.context).into(),
+            Type::Iterator => runtime::iterator_type(self.context).into(),
+            Type::Vector => runtime::vector_type(self.context).into(),
+            Type::StringVector => runtime::string_vector_type(self.context).into(),
+            Type::Json => runtime::json_type(self.context).into(),
+            Type::Trait(name) => runtime::trait_type(self.context, name).into(),
+            Type::Unknown => return None,
+        })
+    }
+
+    /// Compiles an async function.
+    ///
+    /// The body is compiled as an ordinary function named `__venti_async_<name>`. The
+    /// function `<name>` itself stores its arguments in a task frame and spawns a thread
+    /// running the entry point `__venti_task_<name>`, which calls the body and records the
+    /// result. Calling an async function returns the task, a pointer to its frame.
+    fn compile_async_function(
+        &mut self,
+        identifier: String,
+        params: Vec<Parameter>,
+        body: Vec<Statement>,
+        inline: Option<InlineHint>,
+    ) -> Result<(), VentiError> {
+        let task_function = self.declare_function(&identifier, &params, self.task_return_type())?;
+        if task_function.count_basic_blocks() > 0 {
+            return Err(VentiError::CodegenError(
+                format!("Function '{}' is defined more than once", identifier),
+                None,
+            ));
+        }
+        let body_name = format!("__venti_async_{}", identifier);
+        let arity = params.len();
+        self.compile_function(body_name.clone(), params, None, body, inline)?;
+        let body_function = self.function(&body_name, arity).ok_or_else(|| {
+            VentiError::CodegenError(
+                format!("Missing body of async function '{}'", identifier),
+                None,
+            )
+        })?;
+
+        let previous_block = self.builder.get_insert_block();
+        let entry_point =
+            self.build_task_entry(&format!("__venti_task_{}", identifier), body_function)?;
+
+        // Calling the async function fills in a new frame and starts the task
+        let entry = self.context.append_basic_block(task_function, "entry");
+        self.builder.position_at_end(entry);
+        self.enter_debug_scope(task_function);
+        let args = task_function.get_param_iter().collect::<Vec<_>>();
+        let frame = self.build_task_start(entry_point, &args)?;
+        self.builder.build_return(Some(&frame))?;
+        self.leave_debug_scope();
+
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        Ok(())
+    }
+
+    /// Compiles a `spawn_venti` block, which runs on its own thread.
+    ///
+    /// The block becomes a function `__venti_spawn_<n>` whose parameters are the locals
+    /// it uses. Those are copied into the task frame when the block is spawned, so the
+    /// block works on a snapshot of them while globals stay shared.
+    fn compile_spawn(&mut self, body: Vec<Statement>) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let mut referenced = Vec::new();
+        statement_identifiers(&body, &mut referenced);
+        let mut captures = Vec::new();
+        for name in referenced {
+            if let Some(local) = self.locals.get(&name).copied() {
+                let value = self
+                    .builder
+                    .build_load(local.value_type, local.slot, &name)?;
+                captures.push((name, value));
+            }
+        }
+
+        let param_types = captures
+            .iter()
+            .map(|(_, value)| value.get_type().into())
+            .collect::<Vec<BasicMetadataTypeEnum>>();
+        let name = format!("__venti_spawn_{}", self.spawn_count);
+        self.spawn_count += 1;
+        let body_function = self.module.add_function(
+            &name,
+            self.context.i64_type().fn_type(&param_types, false),
+            Some(Linkage::Internal),
+        );
+        let (names, values): (Vec<_>, Vec<_>) = captures.into_iter().unzip();
+        self.compile_function_body(body_function, names, body)?;
+
+        let entry_point =
+            self.build_task_entry(&format!("__venti_task_{}", name), body_function)?;
+        let frame = self.build_task_start(entry_point, &values)?;
+        Ok(frame.into())
+    }
+
+    /// Returns the type of a task frame: the `venti.task` header followed by the arguments
+    /// of the task's body.
+    fn task_frame_type(&self, arg_types: &[BasicTypeEnum<'ctx>]) -> StructType<'ctx> {
+        let mut fields = vec![runtime::task_type(self.context).as_basic_type_enum()];
+        fields.extend(arg_types);
+        self.context.struct_type(&fields, false)
+    }
+
+    /// Emits the thread entry point `name` for a task, which unpacks the arguments from
+    /// the frame, calls `body_function` and stores its result.
+    fn build_task_entry(
+        &mut self,
+        name: &str,
+        body_function: FunctionValue<'ctx>,
+    ) -> Result<FunctionValue<'ctx>, VentiError> {
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let task_type = runtime::task_type(self.context);
+        let param_types = body_function
+            .get_param_iter()
+            .map(|param| param.get_type())
+            .collect::<Vec<_>>();
+        let frame_type = self.task_frame_type(&param_types);
+        let previous_block = self.builder.get_insert_block();
+
+        let entry_point = self.module.add_function(
+            name,
+            ptr_type.fn_type(&[ptr_type.into()], false),
+            Some(Linkage::Internal),
+        );
+        let entry = self.context.append_basic_block(entry_point, "entry");
+        self.builder.position_at_end(entry);
+        self.enter_debug_scope(entry_point);
+        let frame = entry_point
+            .get_first_param()
+            .ok_or_else(|| VentiError::CodegenError("Task entry has no frame".to_string(), None))?
+            .into_pointer_value();
+        frame.set_name("frame");
+        let mut args = Vec::new();
+        for (index, param_type) in param_types.iter().enumerate() {
+            let field =
+                self.builder
+                    .build_struct_gep(frame_type, frame, index as u32 + 1, "arg_ptr")?;
+            args.push(self.builder.build_load(*param_type, field, "arg")?);
+        }
+        let call_args = args.iter().map(|arg| (*arg).into()).collect::<Vec<_>>();
+        let result = self.call_value(body_function, &call_args, "task_result")?;
+        // The body has retained what it needs, so the frame's references can go
+        for arg in args {
+            self.release(arg)?;
+        }
+        let result_ptr = self
+            .builder
+            .build_struct_gep(task_type, frame, 1, "result_ptr")?;
+        self.builder.build_store(result_ptr, result)?;
+        self.builder.build_return(Some(&ptr_type.const_null()))?;
+        self.leave_debug_scope();
+
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        Ok(entry_point)
+    }
+
+    /// Allocates a task frame holding `args` and starts a thread running `entry_point` on it.
+    ///
+    /// # Returns
+    ///
+    /// The task, a pointer to its frame.
+    fn build_task_start(
+        &mut self,
+        entry_point: FunctionValue<'ctx>,
+        args: &[BasicValueEnum<'ctx>],
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let task_type = runtime::task_type(self.context);
+        let arg_types = args.iter().map(|arg| arg.get_type()).collect::<Vec<_>>();
+        let frame_type = self.task_frame_type(&arg_types);
+        let malloc = runtime::libc(self.context, &self.module, "malloc")?;
+        let frame_size = frame_type
+            .size_of()
+            .ok_or_else(|| VentiError::CodegenError("Task frame is unsized".to_string(), None))?;
+        let frame = self
+            .call_value(malloc, &[frame_size.into()], "frame")?
+            .into_pointer_value();
+        let joined = self
+            .builder
+            .build_struct_gep(task_type, frame, 2, "joined_ptr")?;
+        self.builder
+            .build_store(joined, self.context.i64_type().const_zero())?;
+        // The frame holds a reference to each string and map until the task has started
+        for (index, value) in args.iter().enumerate() {
+            let field =
+                self.builder
+                    .build_struct_gep(frame_type, frame, index as u32 + 1, "arg_ptr")?;
+            self.builder.build_store(field, *value)?;
+            self.retain(*value)?;
+        }
+        let spawn = runtime::helper(self.context, &self.module, "venti_task_spawn")?;
+        self.builder.build_call(
+            spawn,
+            &[
+                frame.into(),
+                entry_point.as_global_value().as_pointer_value().into(),
+            ],
+            "",
+        )?;
+        Ok(frame)
+    }
+
+    /// The type returned by calling an async function: a pointer to its task frame.
+    fn task_return_type(&self) -> BasicTypeEnum<'ctx> {
+        self.context.ptr_type(AddressSpace::default()).into()
+    }
+
+    fn compile_expr(&mut self, expr: Expr) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let span = expr.span;
+        // A variable is not a new value, so it is not kept until the statement ends
+        let is_variable = matches!(expr.kind, ExprKind::Identifier(_));
+        let value = self
+            .compile_expr_kind(expr.kind)
+            .map_err(|error| error.at(span))?;
+        if !is_variable {
+            self.hold_temporary(value)?;
+        }
+        Ok(value)
+    }
+
+    fn compile_expr_kind(&mut self, kind: ExprKind) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        match kind {
+            ExprKind::Number(n) => Ok(self.context.i64_type().const_int(n as u64, false).into()),
+            ExprKind::Float(f) => Ok(self.context.f64_type().const_float(f).into()),
+            ExprKind::Boolean(b) => Ok(self.context.bool_type().const_int(b as u64, false).into()),
+            ExprKind::Null => Ok(runtime::string_type(self.context)
+                .const_named_struct(&[
+                    self.context
+                        .ptr_type(AddressSpace::default())
+                        .const_null()
+                        .into(),
+                    self.context.i64_type().const_zero().into(),
+                ])
+                .into()),
+            ExprKind::String(s) => Ok(self.build_string(&s)?.into()),
+            ExprKind::Identifier(id) => {
+                if let Some(local) = self.locals.get(&id) {
+                    return Ok(self.builder.build_load(local.value_type, local.slot, &id)?);
+                }
+                if let (None, Some(value)) = (self.global(&id), builtins::constant(&id)) {
+                    return Ok(self.context.f64_type().const_float(value).into());
+                }
+                let global = self.global(&id).ok_or_else(|| {
+                    VentiError::CodegenError(format!("Undefined variable '{}'", id), None)
+                })?;
+                let value_type =
+                    BasicTypeEnum::try_from(global.get_value_type()).map_err(|_| {
+                        VentiError::CodegenError(
+                            format!("Variable '{}' has no value type", id),
+                            None,
+                        )
+                    })?;
+                Ok(self
+                    .builder
+                    .build_load(value_type, global.as_pointer_value(), &id)?)
+            }
+            ExprKind::BinaryOp(left, BinOp::Coalesce, right) => {
+                self.compile_coalesce(left.take(), right.take())
+            }
+            ExprKind::BinaryOp(left, op @ (BinOp::And | BinOp::Or), right) => {
+                self.compile_logical(left.take(), op, right.take())
+            }
+            ExprKind::BinaryOp(left, op, right) => {
+                let left = self.compile_expr(left.take())?;
+                let right = self.compile_expr(right.take())?;
+                self.compile_binary_op(left, op, right)
+            }
+            ExprKind::UnaryOp(op, operand) => match (op, self.compile_expr(operand.take())?) {
+                (UnaryOp::Negate, BasicValueEnum::IntValue(operand)) => {
+                    Ok(self.builder.build_int_neg(operand, "tmpneg")?.into())
+                }
+                (UnaryOp::Negate, BasicValueEnum::FloatValue(operand)) => {
+                    Ok(self.builder.build_float_neg(operand, "tmpfneg")?.into())
+                }
+                (UnaryOp::Not, BasicValueEnum::IntValue(operand))
+                    if operand.get_type().get_bit_width() == 1 =>
+                {
+                    Ok(self.builder.build_not(operand, "tmpnot")?.into())
+                }
+                (op, _) => Err(VentiError::CodegenError(
+                    format!("Operator {:?} is not supported on this operand", op),
+                    None,
+                )),
+            },
+            ExprKind::Array(elements) => self.compile_array(elements),
+            ExprKind::Map(entries) => self.compile_map(entries),
+            ExprKind::Range {
+                start,
+                end,
+                inclusive,
+            } => self.compile_range(start.take(), end.take(), inclusive),
+            ExprKind::Index { target, index } => self.compile_index(target.take(), index.take()),
+            ExprKind::Call { callee, args } => {
+                if let ExprKind::Identifier(identifier) = &callee.kind {
+                    let is_function = !self.locals.contains_key(identifier)
+                        && (self.defined_functions.contains_key(identifier)
+                            || self.extern_functions.contains_key(identifier)
+                            || builtins::is_builtin(identifier));
+                    if is_function {
+                        return self.compile_call(identifier, args)?.ok_or_else(|| {
+                            VentiError::CodegenError(
+                                format!("Function '{}' does not return a value", identifier),
+                                None,
+                            )
+                        });
+                    }
+                }
+                let closure = self.compile_expr(callee.take())?;
+                self.compile_closure_call(closure, args)
+            }
+            ExprKind::Lambda { params, body } => self.compile_lambda(params, body.take()),
+            ExprKind::MethodCall {
+                receiver,
+                name,
+                args,
+            } => self.compile_method_call(receiver.take(), &name, args),
+            ExprKind::Spawn(body) => self.compile_spawn(body),
+            ExprKind::Await(task) => {
+                let task = self.compile_expr(task.take())?;
+                self.await_task(task)
+            }
+            ExprKind::Cast { value, target } => self.compile_cast(value.take(), &target),
+            _ => Err(VentiError::CodegenError(
+                "Unsupported expression".to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Lowers `value as target` between ints, floats and bools.
+    ///
+    /// Floats convert to integers by truncating toward zero and saturating at the ends of
+    /// the `int` range, with NaN becoming 0, so every cast has a defined result. Anything
+    /// nonzero converts to `true`.
+    fn compile_cast(
+        &mut self,
+        value: Expr,
+        target: &VarType,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let f64_type = self.context.f64_type();
+        let value = self.compile_expr(value)?;
+        // A JSON value is converted by the runtime, which checks that it is of the kind cast to
+        if let Ok(node) = self.expect_json(value, "as") {
+            let helper = match target {
+                VarType::Int => "venti_json_to_int",
+                VarType::Float => "venti_json_to_float",
+                VarType::Bool => "venti_json_to_bool",
+                VarType::String => "venti_json_to_str",
+                _ => {
+                    return Err(VentiError::CodegenError(
+                        format!("Cannot cast json to {}", target.name()),
+                        None,
+                    ))
+                }
+            };
+            let helper = runtime::helper(self.context, &self.module, helper)?;
+            return self.call_value(helper, &[node.into()], "cast");
+        }
+        let result = match (value, target) {
+            (BasicValueEnum::IntValue(value), VarType::Int) => {
+                let is_bool = value.get_type().get_bit_width() == 1;
+                self.builder
+                    .build_int_cast_sign_flag(value, i64_type, !is_bool, "cast_int")?
+                    .into()
+            }
+            (BasicValueEnum::IntValue(value), VarType::Float)
+                if value.get_type().get_bit_width() == 1 =>
+            {
+                self.builder
+                    .build_unsigned_int_to_float(value, f64_type, "cast_float")?
+                    .into()
+            }
+            (BasicValueEnum::IntValue(value), VarType::Float) => {
+                self.promote_to_float(value)?.into()
+            }
+            (BasicValueEnum::IntValue(value), VarType::Bool) => self
+                .builder
+                .build_int_compare(
+                    IntPredicate::NE,
+                    value,
+                    value.get_type().const_zero(),
+                    "cast_bool",
+                )?
+                .into(),
+            (BasicValueEnum::FloatValue(value), VarType::Int) => {
+                let fptosi_sat = Intrinsic::find("llvm.fptosi.sat")
+                    .and_then(|intrinsic| {
+                        intrinsic.get_declaration(&self.module, &[i64_type.into(), f64_type.into()])
+                    })
+                    .ok_or_else(|| {
+                        VentiError::CodegenError(
+                            "LLVM has no saturating float to int conversion".to_string(),
+                            None,
+                        )
+                    })?;
+                self.call_value(fptosi_sat, &[value.into()], "cast_int")?
+            }
+            (BasicValueEnum::FloatValue(value), VarType::Float) => value.into(),
+            (BasicValueEnum::FloatValue(value), VarType::Bool) => self
+                .builder
+                .build_float_compare(
+                    FloatPredicate::UNE,
+                    value,
+                    f64_type.const_zero(),
+                    "cast_bool",
+                )?
+                .into(),
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Only numbers and bools can be cast".to_string(),
+                    None,
+                ))
+            }
+        };
+        Ok(result)
+    }
+
+    /// Builds an array literal as a first-class aggregate value.
+    ///
+    /// The LLVM type follows the elements, so `[[1, 2], [3, 4]]` becomes a
+    /// `[2 x [2 x i64]]`. All elements must share one type.
+    fn compile_array(&mut self, elements: Vec<Expr>) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let values = elements
+            .into_iter()
+            .map(|element| self.compile_expr(element))
+            .collect::<Result<Vec<_>, _>>()?;
+        let element_type = values
+            .first()
+            .map(|value| value.get_type())
+            .unwrap_or_else(|| self.context.i64_type().into());
+        if values.iter().any(|value| value.get_type() != element_type) {
+            return Err(VentiError::CodegenError(
+                "All elements of an array must have the same type".to_string(),
+                None,
+            ));
+        }
+
+        let array_type = element_type.array_type(values.len() as u32);
+        let mut array = array_type.get_undef();
+        for (index, value) in values.into_iter().enumerate() {
+            array = self
+                .builder
+                .build_insert_value(array, value, index as u32, "array_elem")?
+                .into_array_value();
+        }
+        Ok(array.into())
+    }
+
+    /// Builds a map literal by allocating a runtime table and inserting every entry.
+    ///
+    /// Keys must be strings and values integers. The table starts large enough that the
+    /// literal's own entries never trigger a resize.
+    fn compile_map(
+        &mut self,
+        entries: Vec<(Expr, Expr)>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let capacity = (entries.len() * 2).max(8).next_power_of_two();
+        let map_new = runtime::helper(self.context, &self.module, "venti_map_new")?;
+        let capacity = self.context.i64_type().const_int(capacity as u64, false);
+        let header = self
+            .call_value(map_new, &[capacity.into()], "map")?
+            .into_pointer_value();
+
+        let insert = runtime::helper(self.context, &self.module, "venti_map_insert")?;
+        for (key, value) in entries {
+            let key = self.compile_map_key(key)?;
+            let value = self.compile_slot_value(value)?;
+            self.builder
+                .build_call(insert, &[header.into(), key.into(), value.into()], "")?;
+        }
+
+        let map = self.builder.build_insert_value(
+            runtime::map_type(self.context).get_undef(),
+            header,
+            0,
+            "map_value",
+        )?;
+        Ok(map.as_basic_value_enum())
+    }
+
+    /// Compiles a map key, which must be a string.
+    fn compile_map_key(&mut self, key: Expr) -> Result<PointerValue<'ctx>, VentiError> {
+        match self.compile_expr(key)? {
+            BasicValueEnum::StructValue(key)
+                if runtime::is_named(key.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                Ok(self.string_parts(key)?.0)
+            }
+            _ => Err(VentiError::CodegenError(
+                "Map keys must be strings".to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Compiles a value stored in a map entry or buffer slot, widening it to the `i64`
+    /// the runtime stores.
+    fn compile_slot_value(&mut self, value: Expr) -> Result<IntValue<'ctx>, VentiError> {
+        match self.compile_expr(value)? {
+            BasicValueEnum::IntValue(value) => self.widen(value, "map_entry"),
+            _ => Err(VentiError::CodegenError(
+                "Map and buffer values must be integers".to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Widens an integer to 64 bits, a bool becoming 0 or 1.
+    fn widen(&self, value: IntValue<'ctx>, name: &str) -> Result<IntValue<'ctx>, VentiError> {
+        let is_bool = value.get_type().get_bit_width() == 1;
+        Ok(self
+            .builder
+            .build_int_cast_sign_flag(value, self.context.i64_type(), !is_bool, name)?)
+    }
+
+    /// Returns the runtime header pointer of a map value, or `None` if `value` is not a map.
+    fn map_header(
+        &self,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<Option<PointerValue<'ctx>>, VentiError> {
+        match value {
+            BasicValueEnum::StructValue(map)
+                if runtime::is_named(map.get_type(), runtime::MAP_TYPE_NAME) =>
+            {
+                let header = self.builder.build_extract_value(map, 0, "map_header")?;
+                Ok(Some(header.into_pointer_value()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Builds a call to a function that returns a value.
+    fn call_value(
+        &self,
+        function: FunctionValue<'ctx>,
+        args: &[BasicMetadataValueEnum<'ctx>],
+        name: &str,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        self.builder
+            .build_call(function, args, name)?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| {
+                VentiError::CodegenError(
+                    format!(
+                        "'{}' returned no value",
+                        function.get_name().to_string_lossy()
+                    ),
+                    None,
+                )
+            })
+    }
+
+    /// Lowers `index` into the JSON value at `node`: a string looks up the member of an
+    /// object and an integer the element of an array, and either gives JSON null when the
+    /// value has no such member or element.
+    fn json_index(
+        &mut self,
+        node: PointerValue<'ctx>,
+        index: Expr,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let found = match self.compile_expr(index)? {
+            BasicValueEnum::StructValue(key)
+                if runtime::is_named(key.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                let (key, key_len) = self.string_parts(key)?;
+                let member = runtime::helper(self.context, &self.module, "venti_json_member")?;
+                self.call_value(member, &[node.into(), key.into(), key_len.into()], "member")?
+            }
+            BasicValueEnum::IntValue(index) => {
+                let index = self.builder.build_int_s_extend_or_bit_cast(
+                    index,
+                    self.context.i64_type(),
+                    "index",
+                )?;
+                let element = runtime::helper(self.context, &self.module, "venti_json_element")?;
+                self.call_value(element, &[node.into(), index.into()], "element")?
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "JSON values are indexed by a string key or an integer".to_string(),
+                    None,
+                ))
+            }
+        };
+        self.json_value(found.into_pointer_value())
+    }
+
+    /// Lowers `target[index]` on an array or map value.
+    ///
+    /// Constant array indexes are bounds-checked at compile time and extract the element
+    /// directly; other indexes go through a stack slot and a GEP. Map lookups call into
+    /// the runtime, which exits with an error if the key is missing.
+    fn compile_index(
+        &mut self,
+        target: Expr,
+        index: Expr,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let target = self.compile_expr(target)?;
+        if let Ok(node) = self.expect_json(target, "index") {
+            return self.json_index(node, index);
+        }
+        if let Some(header) = self.map_header(target)? {
+            let key = self.compile_map_key(index)?;
+            let get = runtime::helper(self.context, &self.module, "venti_map_get")?;
+            return self.call_value(get, &[header.into(), key.into()], "map_get");
+        }
+        let array = match target {
+            BasicValueEnum::ArrayValue(array) => array,
+            BasicValueEnum::StructValue(buffer)
+                if runtime::is_named(buffer.get_type(), runtime::BUFFER_TYPE_NAME) =>
+            {
+                let slot = self.buffer_slot(buffer, index)?;
+                return Ok(self
+                    .builder
+                    .build_load(self.context.i64_type(), slot, "slot_value")?);
+            }
+            BasicValueEnum::StructValue(string)
+                if runtime::is_named(string.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                return self.slice_string(string, index);
+            }
+            BasicValueEnum::StructValue(vector)
+                if runtime::is_named(vector.get_type(), runtime::VECTOR_TYPE_NAME) =>
+            {
+                let header = self.expect_vector(target, "index")?;
+                let index = match self.compile_expr(index)? {
+                    BasicValueEnum::StructValue(range)
+                        if runtime::is_named(range.get_type(), runtime::RANGE_TYPE_NAME) =>
+                    {
+                        return self.slice_vector(header, range);
+                    }
+                    BasicValueEnum::IntValue(index) => self
+                        .builder
+                        .build_int_s_extend_or_bit_cast(index, self.context.i64_type(), "index")?,
+                    _ => {
+                        return Err(VentiError::CodegenError(
+                            "Vector index must be an integer or a range".to_string(),
+                            None,
+                        ))
+                    }
+                };
+                let slot = self.vector_slot(header, index)?;
+                return Ok(self
+                    .builder
+                    .build_load(self.context.i64_type(), slot, "element")?);
+            }
+            BasicValueEnum::StructValue(strings)
+                if runtime::is_named(strings.get_type(), runtime::STRING_VECTOR_TYPE_NAME) =>
+            {
+                let header = self
+                    .builder
+                    .build_extract_value(strings, 0, "strings")?
+                    .into_pointer_value();
+                let index = self.compile_expr(index)?;
+                let index = self.expect_int(index, "index")?;
+                let slot_helper = runtime::helper(self.context, &self.module, "venti_strvec_slot")?;
+                let slot = self
+                    .call_value(slot_helper, &[header.into(), index.into()], "slot")?
+                    .into_pointer_value();
+                return Ok(self.builder.build_load(
+                    runtime::string_type(self.context),
+                    slot,
+                    "element",
+                )?);
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Only arrays, maps, buffers, vectors and strings can be indexed".to_string(),
+                    None,
+                ))
+            }
+        };
+        let index = match self.compile_expr(index)? {
+            BasicValueEnum::IntValue(index) => index,
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Array index must be an integer".to_string(),
+                    None,
+                ))
+            }
+        };
+
+        let array_type = array.get_type();
+        if let Some(constant) = index.get_zero_extended_constant() {
+            if constant >= array_type.len() as u64 {
+                return Err(VentiError::CodegenError(
+                    format!(
+                        "Index {} is out of bounds for an array of length {}",
+                        constant,
+                        array_type.len()
+                    ),
+                    None,
+                ));
+            }
+            return Ok(self
+                .builder
+                .build_extract_value(array, constant as u32, "element")?);
+        }
+
+        let slot = self.builder.build_alloca(array_type, "array_slot")?;
+        self.builder.build_store(slot, array)?;
+        let zero = self.context.i64_type().const_zero();
+        let element_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(array_type, slot, &[zero, index], "element_ptr")?
+        };
+        Ok(self
+            .builder
+            .build_load(array_type.get_element_type(), element_ptr, "element")?)
+    }
+
+    /// Lowers `string[range]`: a copy of the bytes the range covers, which must lie within
+    /// the string and start and end on character boundaries.
+    fn slice_string(
+        &mut self,
+        string: StructValue<'ctx>,
+        range: Expr,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let range = match self.compile_expr(range)? {
+            BasicValueEnum::StructValue(range)
+                if runtime::is_named(range.get_type(), runtime::RANGE_TYPE_NAME) =>
+            {
+                range
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Strings can only be sliced with a range".to_string(),
+                    None,
+                ))
+            }
+        };
+        let start = self.builder.build_extract_value(range, 0, "slice_start")?;
+        let end = self.builder.build_extract_value(range, 1, "slice_end")?;
+        let (data, len) = self.string_parts(string)?;
+        let slice = runtime::helper(self.context, &self.module, "venti_str_range")?;
+        self.call_value(
+            slice,
+            &[data.into(), len.into(), start.into(), end.into()],
+            "slice",
+        )
+    }
+
+    /// Lowers `vector[range]`: a new vector copying the elements the range covers, which
+    /// must lie within the vector.
+    fn slice_vector(
+        &self,
+        header: PointerValue<'ctx>,
+        range: StructValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let start = self.builder.build_extract_value(range, 0, "slice_start")?;
+        let end = self.builder.build_extract_value(range, 1, "slice_end")?;
+        let slice = runtime::helper(self.context, &self.module, "venti_vec_range")?;
+        let slice = self
+            .call_value(slice, &[header.into(), start.into(), end.into()], "slice")?
+            .into_pointer_value();
+        Ok(self.vector_value(slice)?.into())
+    }
+
+    /// Lowers a call to one of the built-in methods.
+    ///
+    /// Only methods with a fixed lowering are supported; anything else is reported as a
+    /// codegen error naming the method.
+    fn compile_method_call(
+        &mut self,
+        receiver: Expr,
+        name: &str,
+        args: Vec<Expr>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        match (name, args.len()) {
+            ("len", 0) => {
+                if let Some(len) = self.static_array_len(&receiver) {
+                    return Ok(self.context.i64_type().const_int(len, false).into());
+                }
+                let receiver = self.compile_expr(receiver)?;
+                if let Ok(node) = self.expect_json(receiver, "len") {
+                    let len = runtime::helper(self.context, &self.module, "venti_json_len")?;
+                    return self.call_value(len, &[node.into()], "json_len");
+                }
+                if let Some(header) = self.map_header(receiver)? {
+                    let header_type = runtime::map_header_type(self.context);
+                    let len =
+                        self.builder
+                            .build_struct_gep(header_type, header, 1, "map_len_ptr")?;
+                    return Ok(self
+                        .builder
+                        .build_load(self.context.i64_type(), len, "map_len")?);
+                }
+                match receiver {
+                    BasicValueEnum::ArrayValue(array) => Ok(self
+                        .context
+                        .i64_type()
+                        .const_int(array.get_type().len() as u64, false)
+                        .into()),
+                    // Strings and buffers both keep their length in field 1
+                    BasicValueEnum::StructValue(value)
+                        if runtime::is_named(value.get_type(), runtime::STRING_TYPE_NAME)
+                            || runtime::is_named(value.get_type(), runtime::BUFFER_TYPE_NAME) =>
+                    {
+                        Ok(self.builder.build_extract_value(value, 1, "len")?)
+                    }
+                    BasicValueEnum::StructValue(value)
+                        if runtime::is_named(value.get_type(), runtime::VECTOR_TYPE_NAME) =>
+                    {
+                        let header = self.expect_vector(receiver, "len")?;
+                        let header_type = runtime::vector_header_type(self.context);
+                        let len = self.builder.build_struct_gep(
+                            header_type,
+                            header,
+                            0,
+                            "vector_len_ptr",
+                        )?;
+                        Ok(self
+                            .builder
+                            .build_load(self.context.i64_type(), len, "vector_len")?)
+                    }
+                    BasicValueEnum::StructValue(strings)
+                        if runtime::is_named(
+                            strings.get_type(),
+                            runtime::STRING_VECTOR_TYPE_NAME,
+                        ) =>
+                    {
+                        let header = self
+                            .builder
+                            .build_extract_value(strings, 0, "strings")?
+                            .into_pointer_value();
+                        let len = self.builder.build_struct_gep(
+                            runtime::string_vector_header_type(self.context),
+                            header,
+                            0,
+                            "strings_len_ptr",
+                        )?;
+                        Ok(self
+                            .builder
+                            .build_load(self.context.i64_type(), len, "strings_len")?)
+                    }
+                    _ => Err(VentiError::CodegenError(
+                        "'len' can only be called on arrays, maps, strings, buffers and vectors"
+                            .to_string(),
+                        None,
+                    )),
+                }
+            }
+            ("push", 1) => {
+                let receiver = self.compile_expr(receiver)?;
+                let Ok(header) = self.expect_vector(receiver, "push") else {
+                    return Err(VentiError::CodegenError(
+                        "'push' is not supported on fixed-size arrays".to_string(),
+                        None,
+                    ));
+                };
+                let value = args.into_iter().next().ok_or_else(|| {
+                    VentiError::CodegenError("Missing method argument".to_string(), None)
+                })?;
+                let value = self.compile_expr(value)?;
+                let value = self.expect_int(value, "push")?;
+                let push = runtime::helper(self.context, &self.module, "venti_vec_push")?;
+                self.builder
+                    .build_call(push, &[header.into(), value.into()], "")?;
+                Ok(self.context.i64_type().const_zero().into())
+            }
+            ("pop", 0) => {
+                let receiver = self.compile_expr(receiver)?;
+                let header = self.expect_vector(receiver, "pop")?;
+                let pop = runtime::helper(self.context, &self.module, "venti_vec_pop")?;
+                self.call_value(pop, &[header.into()], "popped")
+            }
+            ("has", 1) => {
+                let receiver = self.compile_expr(receiver)?;
+                let header = self.map_header(receiver)?.ok_or_else(|| {
+                    VentiError::CodegenError("'has' can only be called on maps".to_string(), None)
+                })?;
+                let key = self.compile_map_key(args.into_iter().next().ok_or_else(|| {
+                    VentiError::CodegenError("'has' expects a key".to_string(), None)
+                })?)?;
+                let has = runtime::helper(self.context, &self.module, "venti_map_has")?;
+                let present = self
+                    .call_value(has, &[header.into(), key.into()], "map_has")?
+                    .into_int_value();
+                Ok(self
+                    .builder
+                    .build_int_z_extend(present, self.context.i64_type(), "has")?
+                    .into())
+            }
+            ("step", 1) => {
+                let range = match self.compile_expr(receiver)? {
+                    BasicValueEnum::StructValue(range)
+                        if runtime::is_named(range.get_type(), runtime::RANGE_TYPE_NAME) =>
+                    {
+                        range
+                    }
+                    _ => {
+                        return Err(VentiError::CodegenError(
+                            "'step' can only be called on ranges".to_string(),
+                            None,
+                        ))
+                    }
+                };
+                let step = match args.into_iter().next().map(|arg| self.compile_expr(arg)) {
+                    Some(Ok(BasicValueEnum::IntValue(step))) => step,
+                    Some(Err(err)) => return Err(err),
+                    _ => {
+                        return Err(VentiError::CodegenError(
+                            "'step' expects an integer".to_string(),
+                            None,
+                        ))
+                    }
+                };
+                // Ranges only count upwards, so a constant step must be positive
+                if step
+                    .get_sign_extended_constant()
+                    .is_some_and(|constant| constant <= 0)
+                {
+                    return Err(VentiError::CodegenError(
+                        "Range step must be positive".to_string(),
+                        None,
+                    ));
+                }
+                let step = self.builder.build_int_s_extend_or_bit_cast(
+                    step,
+                    self.context.i64_type(),
+                    "range_step",
+                )?;
+                Ok(self
+                    .builder
+                    .build_insert_value(range, step, 2, "stepped_range")?
+                    .as_basic_value_enum())
+            }
+            ("len", _) | ("push", _) | ("pop", _) | ("has", _) | ("step", _) => {
+                Err(VentiError::CodegenError(
+                    format!("Wrong number of arguments to method '{}'", name),
+                    None,
+                ))
+            }
+            _ => self.compile_trait_method_call(receiver, name, args),
+        }
+    }
+
+    /// Calls the method `name` of a trait: through the table of `receiver` when it is a
+    /// value of the trait, or else the function implementing it for the receiver's type.
+    fn compile_trait_method_call(
+        &mut self,
+        receiver: Expr,
+        name: &str,
+        args: Vec<Expr>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let receiver = self.compile_expr(receiver)?;
+        let args = args
+            .into_iter()
+            .map(|arg| self.compile_expr(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let BasicValueEnum::StructValue(value) = receiver {
+            if let Some(trait_name) = runtime::trait_name(value.get_type()) {
+                return self.compile_dynamic_call(value, &trait_name, name, args);
+            }
+        }
+        let function = source_type_name(receiver.get_type())
+            .and_then(|type_name| {
+                self.traits.resolve(type_name, name, |function| {
+                    self.defined_functions.contains_key(function)
+                })
+            })
+            .and_then(|function| self.function(&function, args.len() + 1))
+            .ok_or_else(|| VentiError::CodegenError(format!("Unknown method '{}'", name), None))?;
+        let args = std::iter::once(receiver).chain(args).collect();
+        let args = self.pass_args(function.get_type().get_param_types(), args)?;
+        self.call_value(function, &args, "method_call")
+    }
+
+    /// Calls the method `name` of the trait `trait_name` on `value`, a value of the trait,
+    /// through the function at the method's slot of its table.
+    fn compile_dynamic_call(
+        &mut self,
+        value: StructValue<'ctx>,
+        trait_name: &str,
+        name: &str,
+        args: Vec<BasicValueEnum<'ctx>>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let slot = self.traits.slot(trait_name, name).ok_or_else(|| {
+            VentiError::CodegenError(
+                format!("Trait {} has no method '{}'", trait_name, name),
+                None,
+            )
+        })?;
+        let param_types = self.traits.methods(trait_name).unwrap_or_default()[slot]
+            .params
+            .iter()
+            .map(|param| self.llvm_type(param.var_type.as_ref()))
+            .collect::<Vec<_>>();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let mut method_params: Vec<BasicMetadataTypeEnum> = vec![ptr_type.into()];
+        method_params.extend(
+            param_types
+                .iter()
+                .map(|&param| BasicMetadataTypeEnum::from(param)),
+        );
+        let fn_type = self.context.i64_type().fn_type(&method_params, false);
+
+        let data = self.builder.build_extract_value(value, 0, "trait_data")?;
+        let table = self
+            .builder
+            .build_extract_value(value, 1, "trait_table")?
+            .into_pointer_value();
+        let index = self.context.i64_type().const_int(slot as u64, false);
+        // Safety: the table has an entry for each method of the trait
+        let entry = unsafe {
+            self.builder
+                .build_in_bounds_gep(ptr_type, table, &[index], "method_ptr")?
+        };
+        let method = self
+            .builder
+            .build_load(ptr_type, entry, "method")?
+            .into_pointer_value();
+        let mut call_args: Vec<BasicMetadataValueEnum> = vec![data.into()];
+        call_args.extend(self.pass_args(param_types, args)?);
+        self.builder
+            .build_indirect_call(fn_type, method, &call_args, "method_call")?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| VentiError::CodegenError("Method returned no value".to_string(), None))
+    }
+
+    /// Returns the length of an array whose size is known at compile time.
+    fn static_array_len(&self, expr: &Expr) -> Option<u64> {
+        match &expr.kind {
+            ExprKind::Array(elements) => Some(elements.len() as u64),
+            ExprKind::Identifier(id) => match self.locals.get(id) {
+                Some(Local {
+                    value_type: BasicTypeEnum::ArrayType(array_type),
+                    ..
+                }) => Some(array_type.len() as u64),
+                Some(_) => None,
+                None => match self.symbols.definition(self.global_definition(id)?).info {
+                    Type::Array(_, len) => Some(len as u64),
+                    _ => None,
+                },
+            },
+            _ => None,
+        }
+    }
+
+    /// Lowers a binary operator applied to two compiled operands.
+    ///
+    /// Integer operands use integer instructions. If either operand is a float, an
+    /// integer operand is converted to `f64` and the float instructions are used. Two
+    /// strings are compared by their contents.
+    fn compile_binary_op(
+        &mut self,
+        left: BasicValueEnum<'ctx>,
+        op: BinOp,
+        right: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let (left, right) = match (left, right) {
+            (BasicValueEnum::IntValue(left), BasicValueEnum::IntValue(right)) => {
+                return self.compile_int_op(left, op, right)
+            }
+            (BasicValueEnum::StructValue(left), BasicValueEnum::StructValue(right))
+                if runtime::is_named(left.get_type(), runtime::STRING_TYPE_NAME)
+                    && runtime::is_named(right.get_type(), runtime::STRING_TYPE_NAME) =>
+            {
+                return self.compile_string_compare(left, op, right)
+            }
+            (BasicValueEnum::StructValue(left), BasicValueEnum::StructValue(right))
+                if runtime::is_named(left.get_type(), runtime::VECTOR_TYPE_NAME)
+                    && runtime::is_named(right.get_type(), runtime::VECTOR_TYPE_NAME) =>
+            {
+                return self.compile_vector_concat(left, op, right)
+            }
+            (BasicValueEnum::FloatValue(left), BasicValueEnum::FloatValue(right)) => (left, right),
+            (BasicValueEnum::FloatValue(left), BasicValueEnum::IntValue(right)) => {
+                (left, self.promote_to_float(right)?)
+            }
+            (BasicValueEnum::IntValue(left), BasicValueEnum::FloatValue(right)) => {
+                (self.promote_to_float(left)?, right)
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    format!("Operator {:?} needs numeric operands", op),
+                    None,
+                ))
+            }
+        };
+
+        let result = match op {
+            BinOp::Add => self.builder.build_float_add(left, right, "tmpfadd")?.into(),
+            BinOp::Subtract => self.builder.build_float_sub(left, right, "tmpfsub")?.into(),
+            BinOp::Multiply => self.builder.build_float_mul(left, right, "tmpfmul")?.into(),
+            BinOp::Divide => self.builder.build_float_div(left, right, "tmpfdiv")?.into(),
+            BinOp::Equal => self.build_float_compare(FloatPredicate::OEQ, left, right)?,
+            BinOp::NotEqual => self.build_float_compare(FloatPredicate::UNE, left, right)?,
+            BinOp::Less => self.build_float_compare(FloatPredicate::OLT, left, right)?,
+            BinOp::LessEqual => self.build_float_compare(FloatPredicate::OLE, left, right)?,
+            BinOp::Greater => self.build_float_compare(FloatPredicate::OGT, left, right)?,
+            BinOp::GreaterEqual => self.build_float_compare(FloatPredicate::OGE, left, right)?,
+            _ => {
+                return Err(VentiError::CodegenError(
+                    format!("Operator {:?} is not supported on floats", op),
+                    None,
+                ))
+            }
+        };
+        Ok(result)
+    }
+
+    /// Compares two strings byte by byte with `strcmp`, yielding a bool like integer
+    /// comparisons.
+    fn compile_string_compare(
+        &mut self,
+        left: StructValue<'ctx>,
+        op: BinOp,
+        right: StructValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let predicate = match op {
+            BinOp::Equal => IntPredicate::EQ,
+            BinOp::NotEqual => IntPredicate::NE,
+            BinOp::Less => IntPredicate::SLT,
+            BinOp::LessEqual => IntPredicate::SLE,
+            BinOp::Greater => IntPredicate::SGT,
+            BinOp::GreaterEqual => IntPredicate::SGE,
+            _ => {
+                return Err(VentiError::CodegenError(
+                    format!("Operator {:?} is not supported on strings", op),
+                    None,
+                ))
+            }
+        };
+        let (left, _) = self.string_parts(left)?;
+        let (right, _) = self.string_parts(right)?;
+        let strcmp = runtime::libc(self.context, &self.module, "strcmp")?;
+        let order = self
+            .call_value(strcmp, &[left.into(), right.into()], "strcmp_call")?
+            .into_int_value();
+        let order = self
+            .builder
+            .build_int_s_extend(order, self.context.i64_type(), "order")?;
+        let zero = order.get_type().const_zero();
+        Ok(self.build_compare(predicate, order, zero)?.into())
+    }
+
+    /// Lowers `left + right` on two vectors: a new vector with the elements of both.
+    fn compile_vector_concat(
+        &self,
+        left: StructValue<'ctx>,
+        op: BinOp,
+        right: StructValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        if op != BinOp::Add {
+            return Err(VentiError::CodegenError(
+                format!("Operator {:?} is not supported on vectors", op),
+                None,
+            ));
+        }
+        let left = self.expect_vector(left.into(), "+")?;
+        let right = self.expect_vector(right.into(), "+")?;
+        let concat = runtime::helper(self.context, &self.module, "venti_vec_concat")?;
+        let joined = self
+            .call_value(concat, &[left.into(), right.into()], "joined")?
+            .into_pointer_value();
+        Ok(self.vector_value(joined)?.into())
+    }
+
+    /// Lowers a binary operator on two integers.
+    fn compile_int_op(
+        &mut self,
+        left: IntValue<'ctx>,
+        op: BinOp,
+        right: IntValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let result = match op {
+            BinOp::Add => self.builder.build_int_add(left, right, "tmpadd")?,
+            BinOp::Subtract => self.builder.build_int_sub(left, right, "tmpsub")?,
+            BinOp::Multiply => self.builder.build_int_mul(left, right, "tmpmul")?,
+            BinOp::Divide => {
+                let divide = runtime::helper(self.context, &self.module, "venti_int_div")?;
+                self.call_value(divide, &[left.into(), right.into()], "tmpdiv")?
+                    .into_int_value()
+            }
+            BinOp::Equal => self.build_compare(IntPredicate::EQ, left, right)?,
+            BinOp::NotEqual => self.build_compare(IntPredicate::NE, left, right)?,
+            BinOp::Less => self.build_compare(IntPredicate::SLT, left, right)?,
+            BinOp::LessEqual => self.build_compare(IntPredicate::SLE, left, right)?,
+            BinOp::Greater => self.build_compare(IntPredicate::SGT, left, right)?,
+            BinOp::GreaterEqual => self.build_compare(IntPredicate::SGE, left, right)?,
+            BinOp::BitAnd => self.builder.build_and(left, right, "tmpbitand")?,
+            BinOp::BitOr => self.builder.build_or(left, right, "tmpbitor")?,
+            BinOp::BitXor => self.builder.build_xor(left, right, "tmpxor")?,
+            BinOp::ShiftLeft => self.builder.build_left_shift(left, right, "tmpshl")?,
+            // `>>` is an arithmetic shift, matching the signed integer type
+            BinOp::ShiftRight => self
+                .builder
+                .build_right_shift(left, right, true, "tmpshr")?,
+            BinOp::Coalesce => unreachable!("lowered by compile_coalesce"),
+            BinOp::And | BinOp::Or => unreachable!("lowered by compile_logical"),
+        };
+        Ok(result.into())
+    }
+
+    /// Converts a signed integer to `f64` for mixed integer and float arithmetic.
+    fn promote_to_float(&self, value: IntValue<'ctx>) -> Result<FloatValue<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_signed_int_to_float(value, self.context.f64_type(), "tmpitof")?)
+    }
+
+    /// Builds a float comparison, whose `i1` result is a bool like that of integer
+    /// comparisons.
+    fn build_float_compare(
+        &self,
+        predicate: FloatPredicate,
+        left: FloatValue<'ctx>,
+        right: FloatValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_float_compare(predicate, left, right, "tmpfcmp")?
+            .into())
+    }
+
+    /// Builds an integer comparison, whose `i1` result is a bool whatever the width of the
+    /// operands.
+    fn build_compare(
+        &self,
+        predicate: IntPredicate,
+        left: inkwell::values::IntValue<'ctx>,
+        right: inkwell::values::IntValue<'ctx>,
+    ) -> Result<inkwell::values::IntValue<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_int_compare(predicate, left, right, "tmpcmp")?)
+    }
+
+    /// Blocks until `task` has finished and returns its result.
+    fn await_task(&self, task: BasicValueEnum<'ctx>) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let task = match task {
+            BasicValueEnum::PointerValue(task) => task,
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Only tasks returned by async functions can be awaited".to_string(),
+                    None,
+                ))
+            }
+        };
+        let await_fn = runtime::helper(self.context, &self.module, "venti_task_await")?;
+        self.call_value(await_fn, &[task.into()], "awaited")
+    }
+}
+
+/// Returns the name of the top-level function `statement` defines, or `None` if it does
+/// not define one.
+fn declared_name<'s>(statement: &'s Statement) -> Option<&'s String> {
+    match statement {
+        Statement::Function { identifier, .. } | Statement::AsyncFunction { identifier, .. } => {
+            Some(identifier)
+        }
+        _ => None,
+    }
+}
+
+/// Returns what another module needs to know of `statement` to call the function or C
+/// function it declares: the statement without the function's body, or the trait it
+/// declares as it is.
+fn declaration<'a>(statement: &Statement<'a>) -> Option<Statement<'a>> {
+    match statement {
+        Statement::Function {
+            identifier,
+            params,
+            returns,
+            inline,
+            ..
+        } => Some(Statement::Function {
+            identifier: identifier.clone(),
+            params: params.clone(),
+            returns: returns.clone(),
+            body: Vec::new(),
+            inline: *inline,
+        }),
+        Statement::AsyncFunction {
+            identifier,
+            params,
+            inline,
+            ..
+        } => Some(Statement::AsyncFunction {
+            identifier: identifier.clone(),
+            params: params.clone(),
+            body: Vec::new(),
+            inline: *inline,
+        }),
+        Statement::Extern {
+            identifier,
+            params,
+            returns,
+        } => Some(Statement::Extern {
+            identifier: identifier.clone(),
+            params: params.clone(),
+            returns: returns.clone(),
+        }),
+        // Functions taking a trait need the order of its methods to pass values of it
+        Statement::Trait {
+            identifier,
+            methods,
+        } => Some(Statement::Trait {
+            identifier: identifier.clone(),
+            methods: methods.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// The bitcode of each module `compile_detached` compiled, or the error compiling it, by
+/// function name.
+type DetachedModules = Vec<(String, Result<Vec<u8>, VentiError>)>;
+
+/// Compiles top-level functions into modules of their own on the current thread, with a
+/// context of its own, since an LLVM context cannot be shared between threads.
+///
+/// # Arguments
+///
+/// * `module_name` - The name of the program's module, which symbols are mangled with.
+/// * `triple` - The target triple of the program's module, which may be empty.
+/// * `opt_level` - The optimization level of the program.
+/// * `print_imports` - Whether `printventi` calls host functions instead of `printf`.
+/// * `declarations` - Every top-level function and C function of the program, without
+///   bodies.
+/// * `symbols` - The names the analyzer resolved in the program.
+/// * `functions` - The functions to compile.
+///
+/// # Returns
+///
+/// The bitcode of each function's module, or the error compiling it, by function name.
+/// An error declaring the program's functions is returned on its own.
+fn compile_detached(
+    module_name: &str,
+    triple: &str,
+    opt_level: OptimizationLevel,
+    print_imports: bool,
+    declarations: &[Statement],
+    symbols: &SymbolTable<Type>,
+    functions: Vec<Statement>,
+) -> Result<DetachedModules, VentiError> {
+    let context = Context::create();
+    let mut codegen = CodeGen::new(&context, opt_level)
+        .with_module_name(module_name)
+        .with_target_triple(&TargetTriple::create(triple))
+        .with_symbols(symbols.clone());
+    codegen.print_imports = print_imports;
+    codegen.declare_top_level(declarations)?;
+    Ok(functions
+        .into_iter()
+        .filter_map(|function| {
+            let name = declared_name(&function)?.clone();
+            let bitcode = codegen
+                .function_module(function)
+                .map(|module| module.write_bitcode_to_memory().as_slice().to_vec());
+            Some((name, bitcode))
+        })
+        .collect())
+}
+
+/// Collects, in first-use order and without duplicates, every identifier an expression reads.
+fn referenced_identifiers(expr: &Expr, names: &mut Vec<String>) {
+    match &expr.kind {
+        ExprKind::Identifier(name) => {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        ExprKind::BinaryOp(left, _, right) => {
+            referenced_identifiers(left, names);
+            referenced_identifiers(right, names);
+        }
+        ExprKind::UnaryOp(_, operand)
+        | ExprKind::Async(operand)
+        | ExprKind::Await(operand)
+        | ExprKind::Cast { value: operand, .. } => referenced_identifiers(operand, names),
+        ExprKind::Call { callee, args } => {
+            referenced_identifiers(callee, names);
+            args.iter()
+                .for_each(|arg| referenced_identifiers(arg, names));
+        }
+        ExprKind::MethodCall { receiver, args, .. } => {
+            referenced_identifiers(receiver, names);
+            args.iter()
+                .for_each(|arg| referenced_identifiers(arg, names));
+        }
+        ExprKind::Lambda { body, .. } => referenced_identifiers(body, names),
+        ExprKind::Array(elements) => elements
+            .iter()
+            .for_each(|element| referenced_identifiers(element, names)),
+        ExprKind::Range { start, end, .. } => {
+            referenced_identifiers(start, names);
+            referenced_identifiers(end, names);
+        }
+        ExprKind::Map(entries) => entries.iter().for_each(|(key, value)| {
+            referenced_identifiers(key, names);
+            referenced_identifiers(value, names);
+        }),
+        ExprKind::Index { target, index } => {
+            referenced_identifiers(target, names);
+            referenced_identifiers(index, names);
+        }
+        ExprKind::Spawn(body) => statement_identifiers(body, names),
+        ExprKind::Number(_)
+        | ExprKind::Float(_)
+        | ExprKind::String(_)
+        | ExprKind::Boolean(_)
+        | ExprKind::Null => {}
+    }
+}
+
+/// Collects every identifier a block of statements reads or assigns, like
+/// `referenced_identifiers` does for a single expression.
+fn statement_identifiers(statements: &[Statement], names: &mut Vec<String>) {
+    for statement in statements {
+        match statement {
+            Statement::VariableDeclaration { value, .. } | Statement::Print { value, .. } => {
+                referenced_identifiers(value, names)
+            }
+            Statement::VariableAssignment { identifier, value } => {
+                if !names.contains(identifier) {
+                    names.push(identifier.clone());
+                }
+                referenced_identifiers(value, names);
+            }
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => {
+                referenced_identifiers(target, names);
+                referenced_identifiers(index, names);
+                referenced_identifiers(value, names);
+            }
+            Statement::FunctionCall { args, .. } => args
+                .iter()
+                .for_each(|arg| referenced_identifiers(arg, names)),
+            Statement::Expression(expr)
+            | Statement::Return(Some(expr))
+            | Statement::Assert(expr) => referenced_identifiers(expr, names),
+            Statement::Match { scrutinee, arms } => {
+                referenced_identifiers(scrutinee, names);
+                arms.iter()
+                    .for_each(|arm| statement_identifiers(&arm.body, names));
+            }
+            Statement::For { iterable, body, .. } => {
+                referenced_identifiers(iterable, names);
+                statement_identifiers(body, names);
+            }
+            Statement::Try { body, handler, .. } => {
+                statement_identifiers(body, names);
+                statement_identifiers(handler, names);
+            }
+            // Nested functions cannot see the enclosing locals
+            Statement::Function { .. }
+            | Statement::AsyncFunction { .. }
+            | Statement::Return(None)
+            | Statement::Test { .. }
+            | Statement::Comment { .. }
+            | Statement::Import(_)
+            | Statement::Extern { .. }
+            | Statement::Trait { .. }
+            | Statement::Impl { .. }
+            | Statement::Line(_) => {}
+        }
+    }
+}
+
+/// Turns the message of a runtime error that reached the session into the error.
+///
+/// # Safety
+///
+/// `message` must be a NUL-terminated string from `malloc` that nothing else frees.
+unsafe fn raised_error(message: *mut c_char) -> VentiError {
+    let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+    free(message.cast());
+    VentiError::RuntimeError(text, None)
+}
+
+extern "C" {
+    fn free(ptr: *mut c_void);
+}
+
+/// The number of 64-bit slots a value of `value_type` takes when it crosses between Rust
+/// and generated code: two for a string, one for anything else.
+fn slot_count(value_type: BasicTypeEnum) -> u64 {
+    if value_type.is_struct_type() {
+        2
+    } else {
+        1
+    }
+}
+
+/// Returns true if values of `value_type` are, or contain, reference-counted strings or maps.
+fn is_counted(value_type: BasicTypeEnum) -> bool {
+    match value_type {
+        BasicTypeEnum::ArrayType(array_type) => is_counted(array_type.get_element_type()),
+        BasicTypeEnum::StructType(struct_type) => {
+            runtime::is_named(struct_type, runtime::STRING_TYPE_NAME)
+                || runtime::is_named(struct_type, runtime::MAP_TYPE_NAME)
+                || runtime::is_named(struct_type, runtime::CHANNEL_TYPE_NAME)
+                || runtime::is_named(struct_type, runtime::ITERATOR_TYPE_NAME)
+                || runtime::is_named(struct_type, runtime::VECTOR_TYPE_NAME)
+                || runtime::is_named(struct_type, runtime::STRING_VECTOR_TYPE_NAME)
+        }
+        _ => false,
+    }
+}
+
+/// Returns the name a value of `value_type` has in the source, for the types a trait can be
+/// implemented for, or `None` for any other type.
+fn source_type_name(value_type: BasicTypeEnum) -> Option<&'static str> {
+    match value_type {
+        BasicTypeEnum::IntType(int_type) if int_type.get_bit_width() == 1 => Some("bool"),
+        BasicTypeEnum::IntType(int_type) if int_type.get_bit_width() == 64 => Some("int"),
+        BasicTypeEnum::FloatType(_) => Some("float"),
+        BasicTypeEnum::StructType(struct_type)
+            if runtime::is_named(struct_type, runtime::STRING_TYPE_NAME) =>
+        {
+            Some("string")
+        }
+        _ => None,
+    }
+}
+
+/// Returns true if `value` is a compile-time constant that can initialize a global.
+fn is_constant(value: BasicValueEnum) -> bool {
+    match value {
+        BasicValueEnum::ArrayValue(value) => value.is_const(),
+        BasicValueEnum::IntValue(value) => value.is_const(),
+        BasicValueEnum::FloatValue(value) => value.is_const(),
+        BasicValueEnum::PointerValue(value) => value.is_const(),
+        BasicValueEnum::StructValue(value) => value.is_const(),
+        BasicValueEnum::VectorValue(value) => value.is_const(),
     }
 }
@@ -0,0 +1,297 @@
+use crate::codegen::{runtime, symbols};
+use inkwell::basic_block::BasicBlock;
+use inkwell::context::Context;
+use inkwell::debug_info::{
+    debug_metadata_version, AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants,
+    DILocation, DIScope, DIType, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+};
+use inkwell::module::{FlagBehavior, Module};
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{FunctionValue, PointerValue};
+use inkwell::AddressSpace;
+use std::path::Path;
+
+/*
+Debug info is emitted when compiling with `-g`. The parser marks the line each statement
+starts on with a `Statement::Line`, and CodeGen passes those lines on to DebugInfo, which
+describes the program as DWARF metadata: one compile unit for the source file, a subprogram
+for every generated function, and a local variable for every int, float, bool or string
+stored in a stack slot. Strings appear as a struct of their data pointer and length.
+
+Venti has no DWARF language code of its own, so the compile unit claims to be C. Debuggers
+then show values using C's rules, which match how Venti stores them.
+*/
+
+// DWARF base type encodings (DW_ATE_*)
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_SIGNED_CHAR: u32 = 0x06;
+
+/// The debug types of the values debuggers can display.
+struct DebugTypes<'ctx> {
+    int: DIType<'ctx>,
+    float: DIType<'ctx>,
+    bool: DIType<'ctx>,
+    string: DIType<'ctx>,
+}
+
+/// Builds the DWARF debug info for one module.
+pub struct DebugInfo<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    compile_unit: DICompileUnit<'ctx>,
+    file: DIFile<'ctx>,
+    types: DebugTypes<'ctx>,
+    optimized: bool,
+    // The source line of the statement being compiled
+    line: u32,
+    // The subprograms of the functions being compiled, innermost last, each with the line
+    // to return to once it is done
+    scopes: Vec<(DIScope<'ctx>, u32)>,
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    /// Creates the debug info for `module`, compiled from the source file `source`.
+    ///
+    /// # Arguments
+    ///
+    /// * `module` - The module the debug info describes.
+    /// * `source` - The path of the Venti source file.
+    /// * `optimized` - Whether the module is compiled with optimizations.
+    /// * `pointer_bits` - The size of a pointer on the target, in bits.
+    ///
+    /// # Returns
+    ///
+    /// A new `DebugInfo` with a compile unit for `source`.
+    pub fn new(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        source: &Path,
+        optimized: bool,
+        pointer_bits: u64,
+    ) -> Self {
+        let filename = source
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let directory = source
+            .parent()
+            .and_then(|parent| std::fs::canonicalize(parent.join(".")).ok())
+            .map(|parent| parent.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context
+                .i32_type()
+                .const_int(debug_metadata_version() as u64, false),
+        );
+        let (builder, compile_unit) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &filename,
+            &directory,
+            "venti",
+            optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let file = compile_unit.get_file();
+        let basic_type = |name: &str, bits: u64, encoding: u32| {
+            builder
+                .create_basic_type(name, bits, encoding, DIFlags::PUBLIC)
+                .expect("basic types are named")
+                .as_type()
+        };
+        let int = basic_type("int", 64, DW_ATE_SIGNED);
+        let char_type = basic_type("char", 8, DW_ATE_SIGNED_CHAR);
+        let data_type = builder
+            .create_pointer_type("", char_type, pointer_bits, 0, AddressSpace::default())
+            .as_type();
+        // A `venti.str` is `{ ptr data, i64 len }`, with `len` aligned to 8 bytes
+        let scope = compile_unit.as_debug_info_scope();
+        let member = |name: &str, bits: u64, offset: u64, member_type: DIType<'ctx>| {
+            builder
+                .create_member_type(
+                    scope,
+                    name,
+                    file,
+                    0,
+                    bits,
+                    0,
+                    offset,
+                    DIFlags::PUBLIC,
+                    member_type,
+                )
+                .as_type()
+        };
+        let string = builder
+            .create_struct_type(
+                scope,
+                "string",
+                file,
+                0,
+                128,
+                64,
+                DIFlags::PUBLIC,
+                None,
+                &[
+                    member("data", pointer_bits, 0, data_type),
+                    member("len", 64, 64, int),
+                ],
+                0,
+                None,
+                "venti.str",
+            )
+            .as_type();
+        let types = DebugTypes {
+            int,
+            float: basic_type("float", 64, DW_ATE_FLOAT),
+            bool: basic_type("bool", 8, DW_ATE_BOOLEAN),
+            string,
+        };
+        DebugInfo {
+            builder,
+            compile_unit,
+            file,
+            types,
+            optimized,
+            line: 1,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Records that the statements that follow start on `line` of the source.
+    pub fn set_line(&mut self, line: u32) {
+        self.line = line;
+    }
+
+    /// Gives `function` a subprogram starting at the current line and makes it the scope
+    /// of the code that follows, until `leave_function`.
+    ///
+    /// Mangled functions are described by their source name, with the symbol as their
+    /// linkage name.
+    pub fn enter_function(&mut self, function: FunctionValue<'ctx>) {
+        let symbol = function.get_name().to_string_lossy().into_owned();
+        let name = symbols::demangle(&symbol).unwrap_or(&symbol);
+        let linkage_name = (name != symbol).then_some(symbol.as_str());
+        let subroutine_type = self.builder.create_subroutine_type(
+            self.file,
+            Some(self.types.int),
+            &[],
+            DIFlags::PUBLIC,
+        );
+        let subprogram = self.builder.create_function(
+            self.compile_unit.as_debug_info_scope(),
+            name,
+            linkage_name,
+            self.file,
+            self.line,
+            subroutine_type,
+            false,
+            true,
+            self.line,
+            DIFlags::PUBLIC,
+            self.optimized,
+        );
+        function.set_subprogram(subprogram);
+        self.scopes
+            .push((subprogram.as_debug_info_scope(), self.line));
+    }
+
+    /// Returns to the function that was being compiled before the last `enter_function`.
+    pub fn leave_function(&mut self) {
+        if let Some((_, line)) = self.scopes.pop() {
+            self.line = line;
+        }
+    }
+
+    /// Returns the debug location of the current line, or `None` outside any function.
+    pub fn location(&self, context: &'ctx Context) -> Option<DILocation<'ctx>> {
+        let (scope, _) = self.scopes.last()?;
+        Some(
+            self.builder
+                .create_debug_location(context, self.line, 0, *scope, None),
+        )
+    }
+
+    /// Describes the local variable `name`, stored in `slot`, at the end of `block`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The variable's name in the source.
+    /// * `slot` - The stack slot holding the variable.
+    /// * `value_type` - The type stored in the slot. Arrays, maps, ranges, closures and
+    ///   tasks have no debug type, so they are left out.
+    /// * `arg_no` - The 1-based position of the variable among the function's parameters,
+    ///   or `None` for other locals.
+    /// * `block` - The block the variable comes into scope in.
+    pub fn declare_variable(
+        &self,
+        context: &'ctx Context,
+        name: &str,
+        slot: PointerValue<'ctx>,
+        value_type: BasicTypeEnum<'ctx>,
+        arg_no: Option<u32>,
+        block: BasicBlock<'ctx>,
+    ) {
+        let (Some(location), Some(debug_type)) =
+            (self.location(context), self.debug_type(value_type))
+        else {
+            return;
+        };
+        let scope = location.get_scope();
+        let variable = match arg_no {
+            Some(arg_no) => self.builder.create_parameter_variable(
+                scope,
+                name,
+                arg_no,
+                self.file,
+                self.line,
+                debug_type,
+                true,
+                DIFlags::ZERO,
+            ),
+            None => self.builder.create_auto_variable(
+                scope,
+                name,
+                self.file,
+                self.line,
+                debug_type,
+                true,
+                DIFlags::ZERO,
+                0,
+            ),
+        };
+        self.builder
+            .insert_declare_at_end(slot, Some(variable), None, location, block);
+    }
+
+    /// Resolves the debug info so the module can be verified and emitted.
+    pub fn finalize(&self) {
+        self.builder.finalize();
+    }
+
+    fn debug_type(&self, value_type: BasicTypeEnum<'ctx>) -> Option<DIType<'ctx>> {
+        match value_type {
+            BasicTypeEnum::IntType(int_type) if int_type.get_bit_width() == 1 => {
+                Some(self.types.bool)
+            }
+            BasicTypeEnum::IntType(_) => Some(self.types.int),
+            BasicTypeEnum::FloatType(_) => Some(self.types.float),
+            BasicTypeEnum::StructType(struct_type)
+                if runtime::is_named(struct_type, runtime::STRING_TYPE_NAME) =>
+            {
+                Some(self.types.string)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use crate::cache::CacheKey;
+use crate::venti_parser::ast::{Expr, ExprKind, Parameter, Statement, VarType};
+use std::collections::{HashMap, HashSet};
+
+/*
+Incremental compilation reuses the module of every top-level function whose code would
+come out the same as the last time it was compiled, so editing one function of a large
+program only lowers that function again and relinks the rest from the cache.
+
+`CodeGen` compiles each top-level function into a module of its own that only declares
+the functions, C functions and top-level variables it uses, so a function's code depends
+on nothing but:
+
+*   its own definition, apart from the spans it was parsed from;
+*   the signature of each top-level function it names, which calls to it are built
+    against, but not the body, which lives in that function's module;
+*   the declaration of each C function it names;
+*   the top-level variables it names, whose types come from the statements before it;
+*   the traits of the program and the types implementing them, which decide the function
+    a method call resolves to and the table a value passed as a trait is passed with.
+
+The `FunctionGraph` follows those edges from each function and hashes what is at their
+ends into the function's `CacheKey`. Changing a function's body only changes its own key,
+while changing its parameters or result type also changes the keys of the functions that
+call it.
+Top-level statements are not tracked one by one: a function naming any top-level
+variable depends on every top-level statement before it.
+
+Some functions are never cached. A function containing `assert_venti` shares the count of
+failed assertions, which the first module needing it defines and later ones declare, and
+a function with nested function definitions declares more functions as it is compiled.
+Neither are functions defined more than once, which are reported when they are compiled,
+nor functions calling `recv`, which gives values of the type the first `send` on the
+channel decides, wherever in the program that is.
+
+A cacheable function that names no top-level variable depends on nothing but declarations,
+so it is independent of the code generated before it and may be compiled on another thread
+(see `CodeGen::with_jobs`).
+*/
+
+/// The cache key of each top-level function of a program whose module may be cached, and
+/// which of those functions are independent.
+#[derive(Debug, Default)]
+pub struct FunctionGraph {
+    keys: HashMap<String, CacheKey>,
+    independent: HashSet<String>,
+}
+
+impl FunctionGraph {
+    /// Works out what each top-level function of `statements` depends on and keys it.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The top-level statements of the program, as codegen receives them.
+    /// * `base` - A key holding everything else that changes the generated code, such as
+    ///   the module name.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `FunctionGraph`.
+    pub fn new(statements: &[Statement], mut base: CacheKey) -> Self {
+        let mut signatures = HashMap::new();
+        let mut traits = HashSet::new();
+        let mut externs = HashMap::new();
+        let mut duplicates = HashSet::new();
+        let mut globals = HashSet::new();
+        for statement in statements {
+            match statement {
+                Statement::Function {
+                    identifier,
+                    params,
+                    returns,
+                    ..
+                } => {
+                    if signatures
+                        .insert(identifier, signature(params, returns.as_ref(), false))
+                        .is_some()
+                    {
+                        duplicates.insert(identifier);
+                    }
+                }
+                Statement::AsyncFunction {
+                    identifier, params, ..
+                } => {
+                    if signatures
+                        .insert(identifier, signature(params, None, true))
+                        .is_some()
+                    {
+                        duplicates.insert(identifier);
+                    }
+                }
+                Statement::Extern { identifier, .. } => {
+                    externs.insert(identifier, without_spans(statement));
+                }
+                Statement::Trait { identifier, .. } => {
+                    traits.insert(identifier.as_str());
+                    base = base.with_option("trait", &without_spans(statement));
+                }
+                statement => top_level_variables(statement, &mut globals),
+            }
+        }
+        // The methods of the implementations, which `traits::flatten` named after their trait
+        let mut methods: Vec<&str> = signatures
+            .keys()
+            .map(|name| name.as_str())
+            .filter(|name| {
+                name.split_once('.')
+                    .is_some_and(|(prefix, _)| traits.contains(prefix))
+            })
+            .collect();
+        methods.sort_unstable();
+        base = base.with_option("methods", &methods.join(","));
+
+        // Each function that names a top-level variable depends on the statements before it
+        let mut before = base;
+        let mut keys = HashMap::new();
+        let mut independent = HashSet::new();
+        for statement in statements {
+            let (Statement::Function {
+                identifier, body, ..
+            }
+            | Statement::AsyncFunction {
+                identifier, body, ..
+            }) = statement
+            else {
+                before = before.with_option("statement", &without_spans(statement));
+                continue;
+            };
+            let mut uses = Uses::default();
+            uses.statements(body);
+            if uses.uncacheable || duplicates.contains(identifier) {
+                continue;
+            }
+
+            let mut key = base.with_option("function", &without_spans(statement));
+            for name in &uses.names {
+                if let Some(signature) = signatures.get(name) {
+                    key = key.with_option(name, signature);
+                }
+                if let Some(declaration) = externs.get(name) {
+                    key = key.with_option(name, declaration);
+                }
+            }
+            if uses.names.iter().any(|name| globals.contains(name)) {
+                key = key.with_key("globals", before);
+            } else {
+                independent.insert(identifier.clone());
+            }
+            keys.insert(identifier.clone(), key);
+        }
+        FunctionGraph { keys, independent }
+    }
+
+    /// Returns the key of the top-level function `identifier`, or `None` if its module is
+    /// not cached.
+    pub fn key(&self, identifier: &str) -> Option<CacheKey> {
+        self.keys.get(identifier).copied()
+    }
+
+    /// Returns whether the top-level function `identifier` can be compiled without the
+    /// code generated for the statements before it.
+    pub fn is_independent(&self, identifier: &str) -> bool {
+        self.independent.contains(identifier)
+    }
+}
+
+/// What the body of a function refers to.
+#[derive(Default)]
+struct Uses {
+    // Every name the body reads, assigns or calls, in first-use order
+    names: Vec<String>,
+    // Whether the body holds something that keeps the function from being cached
+    uncacheable: bool,
+}
+
+impl Uses {
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::VariableDeclaration { value, .. } | Statement::Print { value, .. } => {
+                    self.expr(value)
+                }
+                Statement::VariableAssignment { identifier, value } => {
+                    self.name(identifier);
+                    self.expr(value);
+                }
+                Statement::IndexAssignment {
+                    target,
+                    index,
+                    value,
+                } => {
+                    self.expr(target);
+                    self.expr(index);
+                    self.expr(value);
+                }
+                Statement::FunctionCall { identifier, args } => {
+                    self.name(identifier);
+                    args.iter().for_each(|arg| self.expr(arg));
+                }
+                Statement::Expression(expr) | Statement::Return(Some(expr)) => self.expr(expr),
+                Statement::Match { scrutinee, arms } => {
+                    self.expr(scrutinee);
+                    arms.iter().for_each(|arm| self.statements(&arm.body));
+                }
+                Statement::For { iterable, body, .. } => {
+                    self.expr(iterable);
+                    self.statements(body);
+                }
+                Statement::Test { body, .. } => self.statements(body),
+                Statement::Try { body, handler, .. } => {
+                    self.statements(body);
+                    self.statements(handler);
+                }
+                Statement::Function { .. }
+                | Statement::AsyncFunction { .. }
+                | Statement::Assert(_)
+                | Statement::Extern { .. }
+                | Statement::Trait { .. }
+                | Statement::Impl { .. } => self.uncacheable = true,
+                Statement::Return(None)
+                | Statement::Comment { .. }
+                | Statement::Import(_)
+                | Statement::Line(_) => {}
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Identifier(name) => self.name(name),
+            ExprKind::BinaryOp(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::UnaryOp(_, operand)
+            | ExprKind::Async(operand)
+            | ExprKind::Await(operand)
+            | ExprKind::Cast { value: operand, .. } => self.expr(operand),
+            ExprKind::Call { callee, args } => {
+                self.expr(callee);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::MethodCall { receiver, args, .. } => {
+                self.expr(receiver);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::Lambda { body, .. } => self.expr(body),
+            ExprKind::Array(elements) => elements.iter().for_each(|element| self.expr(element)),
+            ExprKind::Map(entries) => entries.iter().for_each(|(key, value)| {
+                self.expr(key);
+                self.expr(value);
+            }),
+            ExprKind::Range { start, end, .. } => {
+                self.expr(start);
+                self.expr(end);
+            }
+            ExprKind::Index { target, index } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            ExprKind::Spawn(body) => self.statements(body),
+            ExprKind::Number(_)
+            | ExprKind::Float(_)
+            | ExprKind::String(_)
+            | ExprKind::Boolean(_)
+            | ExprKind::Null => {}
+        }
+    }
+
+    fn name(&mut self, name: &str) {
+        if name == "recv" {
+            self.uncacheable = true;
+        }
+        if !self.names.iter().any(|known| known == name) {
+            self.names.push(name.to_string());
+        }
+    }
+}
+
+/// Describes what callers of a function see of it: whether it is async, the types of its
+/// parameters, but not their names, and the type of its result.
+fn signature(params: &[Parameter], returns: Option<&VarType>, is_async: bool) -> String {
+    let types = params
+        .iter()
+        .map(|param| format!("{:?}", param.var_type))
+        .collect::<Vec<_>>();
+    format!(
+        "async: {}, params: {}, returns: {:?}",
+        is_async,
+        types.join(", "),
+        returns
+    )
+}
+
+/// Adds the top-level variables `statement` declares, including those declared in the
+/// bodies of top-level `match_venti`, `for_venti` and `try_venti` statements.
+fn top_level_variables<'a>(statement: &'a Statement, globals: &mut HashSet<&'a String>) {
+    match statement {
+        Statement::VariableDeclaration { identifier, .. } => {
+            globals.insert(identifier);
+        }
+        Statement::For { variable, body, .. } => {
+            globals.insert(variable);
+            body.iter()
+                .for_each(|statement| top_level_variables(statement, globals));
+        }
+        Statement::Match { arms, .. } => arms
+            .iter()
+            .flat_map(|arm| &arm.body)
+            .for_each(|statement| top_level_variables(statement, globals)),
+        Statement::Try {
+            body,
+            error,
+            handler,
+        } => {
+            globals.insert(error);
+            body.iter()
+                .chain(handler)
+                .for_each(|statement| top_level_variables(statement, globals));
+        }
+        _ => {}
+    }
+}
+
+/// Renders `statement` the way `Debug` does but without its spans, so moving a function
+/// around its file does not change its key.
+fn without_spans(statement: &Statement) -> String {
+    let debug = format!("{:?}", statement);
+    let mut text = String::with_capacity(debug.len());
+    let mut in_string = false;
+    let mut chars = debug.char_indices();
+    while let Some((index, c)) = chars.next() {
+        if in_string {
+            text.push(c);
+            match c {
+                '\\' => text.extend(chars.next().map(|(_, escaped)| escaped)),
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+            text.push(c);
+        } else if debug[index..].starts_with("Span {") {
+            // A span only holds numbers, so it ends at the first closing brace
+            for (_, c) in chars.by_ref() {
+                if c == '}' {
+                    break;
+                }
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    text
+}
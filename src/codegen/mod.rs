@@ -1,2 +1,8 @@
-pub mod codegen;
+pub mod backend;
 pub mod builtins;
+#[allow(clippy::module_inception)]
+pub mod codegen;
+pub mod debug;
+pub mod incremental;
+pub mod runtime;
+pub mod symbols;
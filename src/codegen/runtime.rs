@@ -0,0 +1,9824 @@
+use crate::errors::VentiError;
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::basic_block::BasicBlock;
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::intrinsics::Intrinsic;
+use inkwell::module::{Linkage, Module};
+use inkwell::types::{BasicMetadataTypeEnum, BasicTypeEnum, FunctionType, StructType};
+use inkwell::values::{
+    BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, IntValue, PointerValue,
+};
+use inkwell::{AddressSpace, AtomicOrdering, AtomicRMWBinOp, FloatPredicate, IntPredicate};
+
+/*
+The runtime module emits the helper functions that generated code calls into. Helpers are
+written directly as LLVM IR into the module being compiled, so programs need nothing beyond
+libc at link or JIT time. Each helper is emitted at most once, the first time codegen asks
+for it, with internal linkage.
+
+Strings are `venti.str` values `{ ptr data, i64 len }`. The bytes are NUL-terminated as
+well, so the data pointer can be handed straight to libc, and `nothing` is the string
+`{ null, 0 }`. The 8 bytes before the data hold the string's reference count.
+The string builtins, `upper`, `trim`, `split`, `to_int` and the rest, are helpers built on
+libc's `toupper`, `isspace`, `strstr`, `strtoll` and `strtod`, which the JIT or linker
+resolves like `malloc`. They treat `nothing` as the empty string, and the strings they
+return are new and unowned, like those from `venti_str_copy`. Slicing a string with a
+range checks the offsets against its length and its UTF-8, so a slice never splits a
+character.
+
+`input_venti` reads standard input a byte at a time with `getchar`, so it needs no
+`stdin` symbol, whose name differs between C libraries. `read_file`, `write_file` and
+`append_file` go through stdio. A file that cannot be opened,
+read or written is a runtime error like any other, so `try_venti` can recover from it.
+
+`random`, `random_int` and `seed_random` draw from a splitmix64 generator whose state is a
+shared weak global, like the handler key. Draws step it atomically, so tasks can draw at
+the same time, and it is the same generator the interpreter uses, so a seeded program draws
+the same numbers however it runs. Until `seed_random` is called, the first draw seeds the
+state from `time`.
+
+`now_millis` reads the wall clock with `clock_gettime`, while `timer` and `elapsed` read the
+monotonic clock, so a timing is not thrown off when the wall clock is set. macOS numbers
+that clock differently from Linux, so the module's target triple decides which is used.
+`sleep_ms` is a `nanosleep`, which only pauses the calling thread.
+
+The generated `main` takes the usual `argc` and `argv` and stores them in weak globals,
+where `args` finds them and copies each argument after the program's name into a string
+vector; entries the REPL or an `Engine` runs leave them at zero, so those see no
+arguments. `env` is a `getenv` that copies the C string it returns, and gives `nothing`
+for a variable that is not set.
+
+`exec` runs a command through `popen`, so with `/bin/sh`, and reads everything it writes to
+stdout into a buffer that doubles as it fills. The exit status `pclose` reports is decoded
+the way the shell does and kept in a weak global for `exec_status`.
+
+`json_parse` first runs `venti_json_check` over the whole document, a recursive scanner
+that follows the JSON grammar and stops at `JSON_MAX_DEPTH` levels of nesting, so it cannot
+exhaust the stack, then reads the valid text into a tree of malloc'd nodes
+`{ i64 kind, i64 len, ptr data }`. A `venti.json` value `{ ptr }` points at one, and null is
+the null pointer, which is what indexing gives for a member or element that is not there.
+Nodes are never freed, like boxed values and task frames, so the strings a node hands out
+are immortal and a value can be copied and dropped freely. `json_stringify` writes into a
+`venti.writer` `{ ptr data, i64 len, i64 cap }` on the caller's stack whose bytes grow
+like `exec`'s buffer, and copies them into a string at the end. Maps are written with their
+keys in byte order, picking the least key after the last one written each time, so the
+output does not depend on the table's layout.
+
+The TCP builtins and `http_get` and `http_post` use libc's sockets directly, and a socket
+is the `i64` of its file descriptor. `venti_tcp_open` resolves the host with `getaddrinfo`
+and tries each address in turn, reading the `struct addrinfo` entries at offsets that
+differ between macOS and Linux, so the module's target triple decides which are used.
+Connecting and listening share it; a listening socket sets `SO_REUSEADDR` first, so a
+program can listen on the same port again as soon as it restarts. Only the calling thread
+waits on a socket, so async functions can use them side by side. Sending to a peer that
+hung up raises `SIGPIPE`, which opening a socket ignores so that the send fails with a
+runtime error instead of ending the program.
+
+The HTTP builtins are a blocking HTTP/1.0 client on top of these. The request asks the
+server to close the connection after its response, so the response is everything received
+until then, gathered in a `venti.writer`; its body is returned whatever the status.
+
+Strings and maps are reference counted. Every variable holding one owns a reference:
+codegen retains the value when it is bound or assigned and releases it when it is
+overwritten or goes out of scope, and the memory is freed when the last reference goes.
+A new value that no variable holds, such as the result of `upper(x)` passed straight to
+`printventi`, is owned by the statement that made it until the statement ends. The release
+helpers ignore a null header, which is what the slot of a value not made yet holds.
+Counts are updated atomically, since tasks share values with the code that spawned them. A
+negative count marks a value that is never freed, which is how string literals are
+stored. Arrays are values, so counting an array counts each string or map in it.
+
+Buffers, made by `alloc(n)`, are `venti.buffer` values `{ ptr data, i64 len }` pointing at
+`len` zeroed i64 slots from calloc. Every read and write goes through `venti_buffer_slot`,
+which checks the index against the length. `free` releases the slots and codegen resets
+the freed variable to the empty buffer `{ null, 0 }`, so later accesses through it fail
+the bounds check instead of touching freed memory, and freeing it again does nothing.
+
+Ranges are plain `venti.range` values `{ i64 start, i64 end, i64 step }` with an exclusive
+end, so `1..=10` is stored as `1..11`. They need no helpers.
+
+A value passed where a trait is expected is a `venti.dyn.<Trait>` value `{ ptr data,
+ptr table }`. The data is a copy of the value in memory from malloc, and the table holds a
+function for each method of the trait, in the order the trait declares them, which takes
+the data pointer and the method's arguments. Codegen builds both, so they need no helpers
+either. Like task frames, boxed values are never freed.
+
+Maps are string-keyed hash tables using open addressing with linear probing. A map value
+in generated code is a `venti.map` struct wrapping a pointer to the heap header
+`{ i64 capacity, i64 len, ptr keys, ptr values, i64 refs }`. The capacity is always a power
+of two and the table doubles once it is half full. A map owns a reference to each of its
+keys.
+
+`split` gives a `venti.strvec` `{ ptr header }`, a vector of strings that cannot change,
+whose heap header is `{ i64 len, ptr slots, i64 refs }` with a `venti.str` in each slot.
+The vector owns a reference to each of its strings, and the last reference to the vector
+releases them. Since it never changes, `for_venti` reads its slots in place, holding a
+reference to it while the loop runs.
+
+Async functions run as tasks on their own pthread. Calling one allocates a frame that
+starts with the `venti.task` header `{ i64 thread, i64 result, i64 joined }`, followed by
+the call's arguments, and spawns a thread that runs the body and stores its result in the
+header. Awaiting a task joins the thread once and then reads the result, so a task can be
+awaited any number of times. Frames are never freed, since any copy of the task may still
+be awaited later.
+
+Channels let tasks pass values to each other, each kept in an i64 slot: an int as it is, a
+float by its bits and a bool as 0 or 1. A channel value is a `venti.channel` struct
+wrapping a pointer to the heap header `{ i64 len, i64 head, i64 capacity, ptr slots, i64
+refs, lock, ready }`: a queue kept in a ring of slots whose capacity is a power of two and
+doubles when it fills, guarded by the pthread mutex `lock`. `send` adds to the back of the
+queue and signals the condition `ready`, and `recv` takes from the front, waiting on
+`ready` while the queue is empty. Channels are reference counted like maps, and the last
+reference frees the queue with whatever is still in it.
+
+Vectors are growable arrays of integers. A vector value is a `venti.vec` struct wrapping a
+pointer to the heap header `{ i64 len, i64 capacity, ptr slots, i64 refs }`, whose slots
+double when a push finds them full. Reads and writes go through `venti_vec_slot`, which
+checks the index against the length. Slicing a vector with a range and adding two vectors
+both copy the elements into a new vector, so the result never shares slots with its
+operands. Vectors are reference counted like maps, so passing
+one to a function shares it. A function returning a vector retains it before releasing
+its locals and then disowns it with `venti_vec_disown`, which drops that reference
+without freeing, so the caller receives it unowned like any new value. Like iterators,
+vectors are not locked, so two tasks must not change one at once.
+
+`sort` and `sort_by` sort the slots of a vector or buffer in place with `venti_sort`, a
+stable bottom-up merge sort that merges runs back and forth between the slots and a scratch
+copy. `sort_by` passes it a closure that it calls with pairs of elements, as iterators call
+theirs; `sort` passes a null closure, which compares the elements themselves.
+`binary_search` halves the slots down to the first one holding the value.
+
+Iterators are lazy sequences of integers. An iterator value is a `venti.iter` struct
+wrapping a pointer to the heap header `{ i64 kind, i64 pos, i64 end, i64 step, ptr slots,
+ptr function, ptr env, ptr source, i64 refs }`. A source iterator counts `pos` up to `end`
+by `step`, giving each count, or the slot it indexes when `slots` is set; arrays and
+buffers are copied into slots of their own, so the iterator does not see later changes. A
+map or filter iterator holds a reference to the iterator it reads from and the closure it
+calls on each value. `venti_iter_next` gives the next value and whether the iterator has
+run out as an `{ i64, i1 }` pair, which is all `reduce`, `collect` and `for_venti` ask of
+an iterator. `chars` decodes a string's UTF-8 into the slots of a source iterator up
+front. Iterators are reference counted like maps, but are not locked, so two tasks
+must not read from one at once.
+
+Runtime errors are raised through `venti_raise` with a malloc'd message. Each `try_venti`
+block puts a `venti.handler` frame on its function's stack, holding the handler it
+replaces, a slot for the message and a `jmp_buf` filled by `setjmp`, and makes it the
+thread's innermost handler. Raising puts the replaced handler back and `longjmp`s into the
+block's `catch_venti` with the message; without a handler, the message is printed and the
+program exits with status 1. Each thread's innermost handler is kept in a pthread key
+rather than a thread-local global, which the JIT cannot place, so an error in a task is
+never caught by the code that spawned it. Jumping out skips releasing whatever the
+abandoned calls held, which is leaked.
+
+`panic_venti` calls `venti_panic`, which is not a runtime error: no handler sees it. It
+prints the message and the function that panicked, then, in programs built with debug
+info, the stack from `backtrace`, and exits with status 101.
+*/
+
+/// Name of the LLVM struct type that marks a value as a Venti map.
+pub const MAP_TYPE_NAME: &str = "venti.map";
+
+/// Name of the LLVM struct type that marks a value as a Venti string.
+pub const STRING_TYPE_NAME: &str = "venti.str";
+
+/// Name of the LLVM struct type that marks a value as a Venti buffer.
+pub const BUFFER_TYPE_NAME: &str = "venti.buffer";
+
+/// Name of the LLVM struct type that marks a value as a Venti range.
+pub const RANGE_TYPE_NAME: &str = "venti.range";
+
+/// Returns the named struct type `name`, creating it with `fields` on first use.
+fn named_type<'ctx>(
+    context: &'ctx Context,
+    name: &str,
+    fields: &[BasicTypeEnum<'ctx>],
+) -> StructType<'ctx> {
+    context.get_struct_type(name).unwrap_or_else(|| {
+        let struct_type = context.opaque_struct_type(name);
+        struct_type.set_body(fields, false);
+        struct_type
+    })
+}
+
+/// Returns the `venti.map` value type, creating it in the context on first use.
+pub fn map_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    named_type(
+        context,
+        MAP_TYPE_NAME,
+        &[context.ptr_type(AddressSpace::default()).into()],
+    )
+}
+
+/// Returns the `venti.str` value type, creating it in the context on first use.
+pub fn string_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    named_type(
+        context,
+        STRING_TYPE_NAME,
+        &[
+            context.ptr_type(AddressSpace::default()).into(),
+            context.i64_type().into(),
+        ],
+    )
+}
+
+/// Returns the `venti.buffer` value type, creating it in the context on first use.
+pub fn buffer_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    named_type(
+        context,
+        BUFFER_TYPE_NAME,
+        &[
+            context.ptr_type(AddressSpace::default()).into(),
+            context.i64_type().into(),
+        ],
+    )
+}
+
+/// Returns the `venti.range` value type, creating it in the context on first use.
+pub fn range_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    let i64_type = context.i64_type();
+    named_type(
+        context,
+        RANGE_TYPE_NAME,
+        &[i64_type.into(), i64_type.into(), i64_type.into()],
+    )
+}
+
+/// Name of the LLVM struct type that marks a value as a Venti string vector.
+pub const STRING_VECTOR_TYPE_NAME: &str = "venti.strvec";
+
+/// Returns the `venti.strvec` value type, creating it in the context on first use.
+pub fn string_vector_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    named_type(
+        context,
+        STRING_VECTOR_TYPE_NAME,
+        &[context.ptr_type(AddressSpace::default()).into()],
+    )
+}
+
+/// Returns the heap header behind every string vector: `{ len, slots, refs }`.
+pub fn string_vector_header_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    let i64_type = context.i64_type();
+    context.struct_type(
+        &[
+            i64_type.into(),
+            context.ptr_type(AddressSpace::default()).into(),
+            i64_type.into(),
+        ],
+        false,
+    )
+}
+
+/// Prefix of the name of the LLVM struct type of each trait's values, which the name of
+/// the trait completes.
+pub const TRAIT_TYPE_PREFIX: &str = "venti.dyn.";
+
+/// Returns the value type of the trait `trait_name`, creating it in the context on first
+/// use: a pointer to the boxed value and a pointer to the table of the functions
+/// implementing the trait for its type.
+pub fn trait_type<'ctx>(context: &'ctx Context, trait_name: &str) -> StructType<'ctx> {
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    named_type(
+        context,
+        &format!("{}{}", TRAIT_TYPE_PREFIX, trait_name),
+        &[ptr_type.into(), ptr_type.into()],
+    )
+}
+
+/// Returns the name of the trait `struct_type` is the value type of, or `None` if it is
+/// not a trait's.
+pub fn trait_name(struct_type: StructType) -> Option<String> {
+    let name = struct_type.get_name()?.to_str().ok()?;
+    name.strip_prefix(TRAIT_TYPE_PREFIX).map(str::to_string)
+}
+
+/// Returns the heap header behind every map: `{ capacity, len, keys, values, refs }`.
+pub fn map_header_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    let i64_type = context.i64_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    context.struct_type(
+        &[
+            i64_type.into(),
+            i64_type.into(),
+            ptr_type.into(),
+            ptr_type.into(),
+            i64_type.into(),
+        ],
+        false,
+    )
+}
+
+/// The reference count stored with string literals, which marks them as never freed.
+pub const IMMORTAL_COUNT: i64 = -1;
+
+/// Name of the LLVM struct type at the start of every async task frame.
+pub const TASK_TYPE_NAME: &str = "venti.task";
+
+/// Returns the `venti.task` frame header, creating it in the context on first use.
+pub fn task_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    let i64_type = context.i64_type();
+    named_type(
+        context,
+        TASK_TYPE_NAME,
+        &[i64_type.into(), i64_type.into(), i64_type.into()],
+    )
+}
+
+/// Name of the LLVM struct type that marks a value as a Venti channel.
+pub const CHANNEL_TYPE_NAME: &str = "venti.channel";
+
+/// Returns the `venti.channel` value type, creating it in the context on first use.
+pub fn channel_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    named_type(
+        context,
+        CHANNEL_TYPE_NAME,
+        &[context.ptr_type(AddressSpace::default()).into()],
+    )
+}
+
+/// The bytes set aside for a `pthread_mutex_t` or a `pthread_cond_t`, as many as the
+/// larger of them takes on any platform Venti targets.
+pub const PTHREAD_SYNC_SIZE: u32 = 64;
+
+/// Returns the heap header behind every channel:
+/// `{ len, head, capacity, slots, refs, lock, ready }`, where `lock` is a
+/// `pthread_mutex_t` and `ready` a `pthread_cond_t`.
+pub fn channel_header_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    let i64_type = context.i64_type();
+    // i64 arrays keep the mutex and condition aligned
+    let sync_type = i64_type.array_type(PTHREAD_SYNC_SIZE / 8);
+    context.struct_type(
+        &[
+            i64_type.into(),
+            i64_type.into(),
+            i64_type.into(),
+            context.ptr_type(AddressSpace::default()).into(),
+            i64_type.into(),
+            sync_type.into(),
+            sync_type.into(),
+        ],
+        false,
+    )
+}
+
+/// Name of the LLVM struct type that marks a value as a Venti vector.
+pub const VECTOR_TYPE_NAME: &str = "venti.vec";
+
+/// Returns the `venti.vec` value type, creating it in the context on first use.
+pub fn vector_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    named_type(
+        context,
+        VECTOR_TYPE_NAME,
+        &[context.ptr_type(AddressSpace::default()).into()],
+    )
+}
+
+/// Returns the heap header behind every vector: `{ len, capacity, slots, refs }`.
+pub fn vector_header_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    let i64_type = context.i64_type();
+    context.struct_type(
+        &[
+            i64_type.into(),
+            i64_type.into(),
+            context.ptr_type(AddressSpace::default()).into(),
+            i64_type.into(),
+        ],
+        false,
+    )
+}
+
+/// Name of the LLVM struct type that marks a value as a Venti iterator.
+pub const ITERATOR_TYPE_NAME: &str = "venti.iter";
+
+/// Returns the `venti.iter` value type, creating it in the context on first use.
+pub fn iterator_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    named_type(
+        context,
+        ITERATOR_TYPE_NAME,
+        &[context.ptr_type(AddressSpace::default()).into()],
+    )
+}
+
+/// Returns the heap header behind every iterator:
+/// `{ kind, pos, end, step, slots, function, env, source, refs }`.
+pub fn iterator_header_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    let i64_type = context.i64_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    context.struct_type(
+        &[
+            i64_type.into(),
+            i64_type.into(),
+            i64_type.into(),
+            i64_type.into(),
+            ptr_type.into(),
+            ptr_type.into(),
+            ptr_type.into(),
+            ptr_type.into(),
+            i64_type.into(),
+        ],
+        false,
+    )
+}
+
+/// Returns the `{ value, done }` pair `venti_iter_next` gives.
+pub fn iterator_next_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    context.struct_type(
+        &[context.i64_type().into(), context.bool_type().into()],
+        false,
+    )
+}
+
+/// The kind of an iterator that counts through a range or the slots of an array or buffer.
+pub const ITER_SOURCE: u64 = 0;
+
+/// The kind of an iterator made by `map`.
+pub const ITER_MAP: u64 = 1;
+
+/// The kind of an iterator made by `filter`.
+pub const ITER_FILTER: u64 = 2;
+
+/// Name of the LLVM struct type of the frame each `try_venti` block installs.
+pub const HANDLER_TYPE_NAME: &str = "venti.handler";
+
+/// Symbol of the key of the thread-specific slot holding the innermost handler, plus one so
+/// that zero means the key has not been created yet.
+pub const HANDLER_KEY: &str = "venti_handler_key";
+
+/// Symbol of the random number generator's state, zero until the generator is seeded.
+pub const RANDOM_STATE: &str = "venti_random_state";
+
+/// The step the random number generator's state advances by on every draw, the same step
+/// the interpreter's generator uses.
+pub const RANDOM_STEP: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Symbol of the argument count `main` was called with, zero until `main` stores it.
+pub const ARGC: &str = "venti_argc";
+
+/// Symbol of the argument vector `main` was called with, null until `main` stores it.
+pub const ARGV: &str = "venti_argv";
+
+/// Returns the globals holding the arguments `main` was called with, an i64 count and a
+/// pointer to the vector, adding them to `module` on first use. Each module defines them
+/// weakly, so one definition of each is kept.
+pub fn process_args<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+) -> (PointerValue<'ctx>, PointerValue<'ctx>) {
+    let argc = module.get_global(ARGC).unwrap_or_else(|| {
+        let i64_type = context.i64_type();
+        let global = module.add_global(i64_type, None, ARGC);
+        global.set_linkage(Linkage::WeakAny);
+        global.set_initializer(&i64_type.const_zero());
+        global
+    });
+    let argv = module.get_global(ARGV).unwrap_or_else(|| {
+        let ptr_type = context.ptr_type(AddressSpace::default());
+        let global = module.add_global(ptr_type, None, ARGV);
+        global.set_linkage(Linkage::WeakAny);
+        global.set_initializer(&ptr_type.const_null());
+        global
+    });
+    (argc.as_pointer_value(), argv.as_pointer_value())
+}
+
+/// Symbol of the exit status of the last command `exec` ran.
+pub const EXEC_STATUS: &str = "venti_exec_status_code";
+
+/// How deeply arrays and objects may nest in a document the JSON builtins read, the same
+/// limit the interpreter has.
+pub const JSON_MAX_DEPTH: u64 = 128;
+
+/// Name of the LLVM struct type that marks a value as a JSON value read by `json_parse`.
+pub const JSON_TYPE_NAME: &str = "venti.json";
+
+/// Returns the `venti.json` value type, creating it in the context on first use: a
+/// pointer to the value's node, which is null for JSON null.
+pub fn json_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    named_type(
+        context,
+        JSON_TYPE_NAME,
+        &[context.ptr_type(AddressSpace::default()).into()],
+    )
+}
+
+/// Returns the type of the node behind a `venti.json` value: its kind, and a length and
+/// data that depend on the kind.
+fn json_node_type(context: &Context) -> StructType<'_> {
+    let i64_type = context.i64_type();
+    named_type(
+        context,
+        "venti.json_node",
+        &[
+            i64_type.into(),
+            i64_type.into(),
+            context.ptr_type(AddressSpace::default()).into(),
+        ],
+    )
+}
+
+/// The names `json_kind` gives the kinds of JSON value, indexed by the kind a
+/// `venti.json` node stores.
+pub const JSON_KINDS: [&str; 6] = ["null", "bool", "number", "string", "array", "object"];
+
+/// The kind of JSON null, which has no node.
+const JSON_NULL: u64 = 0;
+
+/// The kind of a JSON bool node, whose length is 1 for `true` and 0 for `false`.
+const JSON_BOOL: u64 = 1;
+
+/// The kind of a JSON number node, whose data is the number's text.
+const JSON_NUMBER: u64 = 2;
+
+/// The kind of a JSON string node, whose data is the decoded string.
+const JSON_STRING: u64 = 3;
+
+/// The kind of a JSON array node, whose data holds a node pointer for each element.
+const JSON_ARRAY: u64 = 4;
+
+/// The kind of a JSON object node, whose data holds the string node of each member's key
+/// followed by its value's node, and whose length counts the members.
+const JSON_OBJECT: u64 = 5;
+
+/// Name of the LLVM struct type of the growing buffer `json_stringify` and the HTTP builtins
+/// write into.
+pub const WRITER_TYPE_NAME: &str = "venti.writer";
+
+/// Returns the `venti.writer` type, creating it in the context on first use: the
+/// malloc'd bytes written so far, how many there are, and how many fit.
+pub fn writer_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    let i64_type = context.i64_type();
+    named_type(
+        context,
+        WRITER_TYPE_NAME,
+        &[
+            context.ptr_type(AddressSpace::default()).into(),
+            i64_type.into(),
+            i64_type.into(),
+        ],
+    )
+}
+
+/// The `clock_gettime` clock counting from the Unix epoch.
+pub const CLOCK_REALTIME: u64 = 0;
+
+/// The `clock_gettime` clock that never jumps, which macOS numbers differently from Linux.
+pub fn clock_monotonic(module: &Module) -> u64 {
+    if targets_macos(module) {
+        6
+    } else {
+        1
+    }
+}
+
+/// Whether `module` is compiled for macOS, whose libc numbers some constants and lays out
+/// some structs differently from Linux. A module without a target triple is compiled for
+/// the platform the compiler runs on.
+pub fn targets_macos(module: &Module) -> bool {
+    let triple = module.get_triple();
+    let triple = triple.as_str().to_string_lossy();
+    if triple.is_empty() {
+        return cfg!(target_os = "macos");
+    }
+    triple.contains("-apple-") || triple.contains("darwin") || triple.contains("macos")
+}
+
+/// Nanoseconds in a second, the unit of a `clock_gettime` reading.
+pub const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Nanoseconds in a millisecond, the unit `now_millis`, `sleep_ms` and `elapsed` use.
+pub const NANOS_PER_MILLI: u64 = 1_000_000;
+
+/// The socket type of a TCP connection, `SOCK_STREAM`.
+pub const SOCK_STREAM: u64 = 1;
+
+/// The `getaddrinfo` flag asking for addresses to listen on, `AI_PASSIVE`.
+pub const AI_PASSIVE: u64 = 1;
+
+/// The `setsockopt` level of the socket's own options, which macOS numbers differently
+/// from Linux.
+pub fn sol_socket(module: &Module) -> u64 {
+    if targets_macos(module) {
+        0xffff
+    } else {
+        1
+    }
+}
+
+/// The option letting a port be listened on while connections to it are still closing,
+/// numbered differently on macOS and Linux like `SOL_SOCKET`.
+pub fn so_reuseaddr(module: &Module) -> u64 {
+    if targets_macos(module) {
+        4
+    } else {
+        2
+    }
+}
+
+/// How many connections a listening socket holds until they are accepted.
+pub const LISTEN_BACKLOG: u64 = 128;
+
+/// The most bytes `tcp_recv` returns at once.
+pub const TCP_CHUNK_SIZE: u32 = 4096;
+
+/// The signal a write to a closed connection raises, which would end the program.
+pub const SIGPIPE: u64 = 13;
+
+/// The size of a `struct addrinfo`, whose `ai_flags`, `ai_family`, `ai_socktype`,
+/// `ai_protocol` and `ai_addrlen` are the 32-bit integers at its start.
+pub const ADDRINFO_SIZE: u64 = 48;
+
+/// The size of a `struct sockaddr_storage`, which holds an address of any family.
+pub const SOCKADDR_STORAGE_SIZE: u64 = 128;
+
+/// Where the port is in a `struct sockaddr_in` or `sockaddr_in6`, in network byte order.
+pub const SOCKADDR_PORT: u64 = 2;
+
+/// Where `ai_next` is in a `struct addrinfo`, its last field.
+pub const ADDRINFO_NEXT: u64 = 40;
+
+/// Where `ai_addr` is in a `struct addrinfo`. macOS puts `ai_canonname` before it and Linux
+/// after.
+pub fn addrinfo_addr(module: &Module) -> u64 {
+    if targets_macos(module) {
+        32
+    } else {
+        24
+    }
+}
+
+/// The HTTP request the HTTP builtins send, given the method, the path, the host and port
+/// as the URL has them, and the length of the body that follows.
+pub const HTTP_REQUEST: &str = "%s %s HTTP/1.0\r\nHost: %.*s\r\nUser-Agent: venti\r\nContent-Length: %ld\r\nConnection: close\r\n\r\n";
+
+/// Returns the `venti.handler` frame, creating it in the context on first use: the handler
+/// it replaced, the message of the error it caught, and the `jmp_buf` to return to. The
+/// buffer is larger than any platform's `jmp_buf`.
+pub fn handler_type<'ctx>(context: &'ctx Context) -> StructType<'ctx> {
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    named_type(
+        context,
+        HANDLER_TYPE_NAME,
+        &[
+            ptr_type.into(),
+            ptr_type.into(),
+            context.i64_type().array_type(64).into(),
+        ],
+    )
+}
+
+/// Returns true if `struct_type` is the named struct type `name`.
+pub fn is_named<'ctx>(struct_type: StructType<'ctx>, name: &str) -> bool {
+    struct_type
+        .get_name()
+        .is_some_and(|type_name| type_name.to_bytes() == name.as_bytes())
+}
+
+/// Looks up a libc function in the module, declaring it with its C signature on first use.
+pub fn libc<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    name: &str,
+) -> Result<FunctionValue<'ctx>, VentiError> {
+    if let Some(function) = module.get_function(name) {
+        return Ok(function);
+    }
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    let void_type = context.void_type();
+    let fn_type = match name {
+        "malloc" => ptr_type.fn_type(&[i64_type.into()], false),
+        "calloc" => ptr_type.fn_type(&[i64_type.into(), i64_type.into()], false),
+        "realloc" => ptr_type.fn_type(&[ptr_type.into(), i64_type.into()], false),
+        "free" => void_type.fn_type(&[ptr_type.into()], false),
+        "strcmp" => i32_type.fn_type(&[ptr_type.into(), ptr_type.into()], false),
+        "strlen" => i64_type.fn_type(&[ptr_type.into()], false),
+        "strstr" => ptr_type.fn_type(&[ptr_type.into(), ptr_type.into()], false),
+        "strncmp" | "memcmp" => {
+            i32_type.fn_type(&[ptr_type.into(), ptr_type.into(), i64_type.into()], false)
+        }
+        "strtoll" => i64_type.fn_type(&[ptr_type.into(), ptr_type.into(), i32_type.into()], false),
+        "strtod" => context
+            .f64_type()
+            .fn_type(&[ptr_type.into(), ptr_type.into()], false),
+        "toupper" | "tolower" | "isspace" | "isxdigit" => {
+            i32_type.fn_type(&[i32_type.into()], false)
+        }
+        "fopen" => ptr_type.fn_type(&[ptr_type.into(), ptr_type.into()], false),
+        "fclose" => i32_type.fn_type(&[ptr_type.into()], false),
+        "fread" | "fwrite" => i64_type.fn_type(
+            &[
+                ptr_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                ptr_type.into(),
+            ],
+            false,
+        ),
+        "fseek" => i32_type.fn_type(&[ptr_type.into(), i64_type.into(), i32_type.into()], false),
+        "ftell" => i64_type.fn_type(&[ptr_type.into()], false),
+        "printf" => i32_type.fn_type(&[ptr_type.into()], true),
+        "exit" => void_type.fn_type(&[i32_type.into()], false),
+        "fflush" => i32_type.fn_type(&[ptr_type.into()], false),
+        "getchar" => i32_type.fn_type(&[], false),
+        // time_t is a signed 64-bit integer on the platforms Venti targets
+        "time" => i64_type.fn_type(&[ptr_type.into()], false),
+        // A timespec is a time_t and a long, so two i64s on the same platforms
+        "clock_gettime" => i32_type.fn_type(&[i32_type.into(), ptr_type.into()], false),
+        "nanosleep" => i32_type.fn_type(&[ptr_type.into(), ptr_type.into()], false),
+        "getenv" => ptr_type.fn_type(&[ptr_type.into()], false),
+        "popen" => ptr_type.fn_type(&[ptr_type.into(), ptr_type.into()], false),
+        "strcspn" => i64_type.fn_type(&[ptr_type.into(), ptr_type.into()], false),
+        "getaddrinfo" => i32_type.fn_type(
+            &[
+                ptr_type.into(),
+                ptr_type.into(),
+                ptr_type.into(),
+                ptr_type.into(),
+            ],
+            false,
+        ),
+        "freeaddrinfo" => void_type.fn_type(&[ptr_type.into()], false),
+        "socket" => i32_type.fn_type(&[i32_type.into(), i32_type.into(), i32_type.into()], false),
+        "connect" | "bind" => {
+            i32_type.fn_type(&[i32_type.into(), ptr_type.into(), i32_type.into()], false)
+        }
+        "setsockopt" => i32_type.fn_type(
+            &[
+                i32_type.into(),
+                i32_type.into(),
+                i32_type.into(),
+                ptr_type.into(),
+                i32_type.into(),
+            ],
+            false,
+        ),
+        "listen" => i32_type.fn_type(&[i32_type.into(), i32_type.into()], false),
+        "accept" | "getsockname" => {
+            i32_type.fn_type(&[i32_type.into(), ptr_type.into(), ptr_type.into()], false)
+        }
+        "send" | "recv" => i64_type.fn_type(
+            &[
+                i32_type.into(),
+                ptr_type.into(),
+                i64_type.into(),
+                i32_type.into(),
+            ],
+            false,
+        ),
+        "close" => i32_type.fn_type(&[i32_type.into()], false),
+        "signal" => ptr_type.fn_type(&[i32_type.into(), ptr_type.into()], false),
+        "pclose" => i32_type.fn_type(&[ptr_type.into()], false),
+        "backtrace" => i32_type.fn_type(&[ptr_type.into(), i32_type.into()], false),
+        "backtrace_symbols_fd" => {
+            void_type.fn_type(&[ptr_type.into(), i32_type.into(), i32_type.into()], false)
+        }
+        // pthread_t is an unsigned long on the platforms Venti targets
+        "pthread_create" => i32_type.fn_type(
+            &[
+                ptr_type.into(),
+                ptr_type.into(),
+                ptr_type.into(),
+                ptr_type.into(),
+            ],
+            false,
+        ),
+        "pthread_join" => i32_type.fn_type(&[i64_type.into(), ptr_type.into()], false),
+        "pthread_mutex_init" | "pthread_cond_init" | "pthread_cond_wait" => {
+            i32_type.fn_type(&[ptr_type.into(), ptr_type.into()], false)
+        }
+        "pthread_mutex_lock"
+        | "pthread_mutex_unlock"
+        | "pthread_mutex_destroy"
+        | "pthread_cond_signal"
+        | "pthread_cond_destroy" => i32_type.fn_type(&[ptr_type.into()], false),
+        // pthread_key_t is an unsigned int or an unsigned long, so keys are created in a
+        // zeroed i64 and passed as one, which holds either
+        "pthread_key_create" => i32_type.fn_type(&[ptr_type.into(), ptr_type.into()], false),
+        "pthread_key_delete" => i32_type.fn_type(&[i64_type.into()], false),
+        "pthread_getspecific" => ptr_type.fn_type(&[i64_type.into()], false),
+        "pthread_setspecific" => i32_type.fn_type(&[i64_type.into(), ptr_type.into()], false),
+        "snprintf" => i32_type.fn_type(&[ptr_type.into(), i64_type.into(), ptr_type.into()], true),
+        "setjmp" => {
+            let function =
+                module.add_function(name, i32_type.fn_type(&[ptr_type.into()], false), None);
+            let kind = Attribute::get_named_enum_kind_id("returns_twice");
+            function.add_attribute(
+                AttributeLoc::Function,
+                context.create_enum_attribute(kind, 0),
+            );
+            return Ok(function);
+        }
+        "longjmp" => {
+            let function = module.add_function(
+                name,
+                void_type.fn_type(&[ptr_type.into(), i32_type.into()], false),
+                None,
+            );
+            let kind = Attribute::get_named_enum_kind_id("noreturn");
+            function.add_attribute(
+                AttributeLoc::Function,
+                context.create_enum_attribute(kind, 0),
+            );
+            return Ok(function);
+        }
+        _ => {
+            return Err(VentiError::CodegenError(
+                format!("Unknown runtime dependency '{}'", name),
+                None,
+            ))
+        }
+    };
+    Ok(module.add_function(name, fn_type, None))
+}
+
+/// Returns a runtime helper, emitting it (and anything it depends on) on first use.
+pub fn helper<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    name: &str,
+) -> Result<FunctionValue<'ctx>, VentiError> {
+    if let Some(function) = module.get_function(name) {
+        return Ok(function);
+    }
+    let emitter = Emitter {
+        context,
+        module,
+        builder: context.create_builder(),
+    };
+    match name {
+        "venti_hash_str" => emitter.hash_str(),
+        "venti_map_new" => emitter.map_new(),
+        "venti_map_find_slot" => emitter.map_find_slot(),
+        "venti_map_grow" => emitter.map_grow(),
+        "venti_map_insert" => emitter.map_insert(),
+        "venti_map_get" => emitter.map_get(),
+        "venti_map_has" => emitter.map_has(),
+        "venti_str_byte_at" => emitter.str_byte_at(),
+        "venti_str_copy" => emitter.str_copy(),
+        "venti_str_slice" => emitter.str_slice(),
+        "venti_str_upper" => emitter.str_case("venti_str_upper", "toupper"),
+        "venti_str_lower" => emitter.str_case("venti_str_lower", "tolower"),
+        "venti_str_trim" => emitter.str_trim(),
+        "venti_str_substring" => emitter.str_substring(),
+        "venti_str_range" => emitter.str_range(),
+        "venti_str_chars" => emitter.str_chars(),
+        "venti_str_contains" => emitter.str_contains(),
+        "venti_str_split" => emitter.str_split(),
+        "venti_str_to_int" => emitter.str_to_int(),
+        "venti_str_to_float" => emitter.str_to_float(),
+        "venti_read_line" => emitter.read_line(),
+        "venti_file_read" => emitter.file_read(),
+        "venti_file_write" => emitter.file_write(),
+        "venti_random_next" => emitter.random_next(),
+        "venti_random_float" => emitter.random_float(),
+        "venti_random_int" => emitter.random_int(),
+        "venti_random_seed" => emitter.random_seed(),
+        "venti_now_millis" => emitter.now_millis(),
+        "venti_timer" => emitter.timer(),
+        "venti_elapsed" => emitter.elapsed(),
+        "venti_sleep_ms" => emitter.sleep_ms(),
+        "venti_str_from_c" => emitter.str_from_c(),
+        "venti_arg_count" => emitter.arg_count(),
+        "venti_arg" => emitter.arg(),
+        "venti_args" => emitter.args(),
+        "venti_env" => emitter.env(),
+        "venti_exec" => emitter.exec(),
+        "venti_exec_status" => emitter.exec_status(),
+        "venti_tcp_open" => emitter.tcp_open(),
+        "venti_tcp_connect" => emitter.tcp_connect(),
+        "venti_tcp_listen" => emitter.tcp_listen(),
+        "venti_tcp_accept" => emitter.tcp_accept(),
+        "venti_tcp_port" => emitter.tcp_port(),
+        "venti_tcp_send" => emitter.tcp_send(),
+        "venti_tcp_recv" => emitter.tcp_recv(),
+        "venti_tcp_close" => emitter.tcp_close(),
+        "venti_tcp_read_all" => emitter.tcp_read_all(),
+        "venti_http" => emitter.http(),
+        "venti_json_space" => emitter.json_space(),
+        "venti_json_digits" => emitter.json_digits(),
+        "venti_json_skip_string" => emitter.json_skip_string(),
+        "venti_json_skip_number" => emitter.json_skip_number(),
+        "venti_json_skip" => emitter.json_skip(),
+        "venti_json_check" => emitter.json_check(),
+        "venti_json_unescape" => emitter.json_unescape(),
+        "venti_json_build" => emitter.json_build(),
+        "venti_json_parse" => emitter.json_parse(),
+        "venti_json_kind" => emitter.json_kind(),
+        "venti_json_member" => emitter.json_member(),
+        "venti_json_element" => emitter.json_element(),
+        "venti_json_len" => emitter.json_len(),
+        "venti_json_to_int" => emitter.json_to_int(),
+        "venti_json_to_float" => emitter.json_to_float(),
+        "venti_json_to_bool" => emitter.json_to_bool(),
+        "venti_json_to_str" => emitter.json_to_str(),
+        "venti_write_raw" => emitter.write_raw(),
+        "venti_json_quote" => emitter.json_quote(),
+        "venti_json_int" => emitter.json_int(),
+        "venti_json_float" => emitter.json_float(),
+        "venti_json_map" => emitter.json_map(),
+        "venti_json_write" => emitter.json_write(),
+        "venti_json_text" => emitter.json_text(),
+        "venti_write_finish" => emitter.write_finish(),
+        "venti_rc_retain" => emitter.rc_retain(),
+        "venti_rc_release" => emitter.rc_release(),
+        "venti_str_retain" => emitter.str_retain(),
+        "venti_str_release" => emitter.str_release(),
+        "venti_map_retain" => emitter.map_retain(),
+        "venti_map_release" => emitter.map_release(),
+        "venti_int_div" => emitter.int_div(),
+        "venti_buffer_alloc" => emitter.buffer_alloc(),
+        "venti_buffer_slot" => emitter.buffer_slot(),
+        "venti_strvec_new" => emitter.strvec_new(),
+        "venti_strvec_slot" => emitter.strvec_slot(),
+        "venti_strvec_retain" => emitter.strvec_retain(),
+        "venti_strvec_release" => emitter.strvec_release(),
+        "venti_task_spawn" => emitter.task_spawn(),
+        "venti_task_await" => emitter.task_await(),
+        "venti_channel_new" => emitter.channel_new(),
+        "venti_channel_send" => emitter.channel_send(),
+        "venti_channel_recv" => emitter.channel_recv(),
+        "venti_channel_retain" => emitter.channel_retain(),
+        "venti_channel_release" => emitter.channel_release(),
+        "venti_vec_new" => emitter.vec_new(),
+        "venti_vec_push" => emitter.vec_push(),
+        "venti_vec_pop" => emitter.vec_pop(),
+        "venti_vec_slot" => emitter.vec_slot(),
+        "venti_vec_extend" => emitter.vec_extend(),
+        "venti_vec_range" => emitter.vec_range(),
+        "venti_vec_concat" => emitter.vec_concat(),
+        "venti_vec_retain" => emitter.vec_retain(),
+        "venti_vec_release" => emitter.vec_release(),
+        "venti_vec_disown" => emitter.vec_disown(),
+        "venti_sort_merge" => emitter.sort_merge(),
+        "venti_sort" => emitter.sort(),
+        "venti_binary_search" => emitter.binary_search(),
+        "venti_iter_new" => emitter.iter_new(),
+        "venti_iter_next" => emitter.iter_next(),
+        "venti_iter_reduce" => emitter.iter_reduce(),
+        "venti_iter_collect" => emitter.iter_collect(),
+        "venti_iter_retain" => emitter.iter_retain(),
+        "venti_iter_release" => emitter.iter_release(),
+        "venti_handler_key" => emitter.handler_key(),
+        "venti_handler_get" => emitter.handler_get(),
+        "venti_handler_set" => emitter.handler_set(),
+        "venti_raise" => emitter.raise(),
+        "venti_panic" => emitter.panic(),
+        _ => Err(VentiError::CodegenError(
+            format!("Unknown runtime helper '{}'", name),
+            None,
+        )),
+    }
+}
+
+/// Returns parameter `index` of a helper being emitted.
+fn param<'ctx>(
+    function: FunctionValue<'ctx>,
+    index: u32,
+) -> Result<BasicValueEnum<'ctx>, VentiError> {
+    function
+        .get_nth_param(index)
+        .ok_or_else(|| VentiError::CodegenError(format!("Missing parameter {}", index), None))
+}
+
+/// Emits runtime helpers with a builder of its own, leaving codegen's builder untouched.
+struct Emitter<'a, 'ctx> {
+    context: &'ctx Context,
+    module: &'a Module<'ctx>,
+    builder: Builder<'ctx>,
+}
+
+impl<'a, 'ctx> Emitter<'a, 'ctx> {
+    fn declare(&self, name: &str, fn_type: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        self.module
+            .add_function(name, fn_type, Some(Linkage::Internal))
+    }
+
+    fn block(&self, function: FunctionValue<'ctx>, name: &str) -> BasicBlock<'ctx> {
+        self.context.append_basic_block(function, name)
+    }
+
+    /// Ends `block` by branching to `done` when `header` is null, and otherwise carries on
+    /// in a new block following it.
+    fn skip_null(
+        &self,
+        block: BasicBlock<'ctx>,
+        header: PointerValue<'ctx>,
+        done: BasicBlock<'ctx>,
+    ) -> Result<(), VentiError> {
+        let present = self.context.insert_basic_block_after(block, "present");
+        let is_null = self.builder.build_is_null(header, "is_null")?;
+        self.builder
+            .build_conditional_branch(is_null, done, present)?;
+        self.builder.position_at_end(present);
+        Ok(())
+    }
+
+    /// Builds a call to a function that returns a value.
+    fn call(
+        &self,
+        function: FunctionValue<'ctx>,
+        args: &[BasicMetadataValueEnum<'ctx>],
+        name: &str,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        self.builder
+            .build_call(function, args, name)?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| {
+                VentiError::CodegenError(
+                    format!(
+                        "'{}' returned no value",
+                        function.get_name().to_string_lossy()
+                    ),
+                    None,
+                )
+            })
+    }
+
+    fn libc(&self, name: &str) -> Result<FunctionValue<'ctx>, VentiError> {
+        libc(self.context, self.module, name)
+    }
+
+    fn helper(&self, name: &str) -> Result<FunctionValue<'ctx>, VentiError> {
+        helper(self.context, self.module, name)
+    }
+
+    fn ptr_type(&self) -> inkwell::types::PointerType<'ctx> {
+        self.context.ptr_type(AddressSpace::default())
+    }
+
+    fn map_header_type(&self) -> StructType<'ctx> {
+        map_header_type(self.context)
+    }
+
+    /// Loads field `index` of the map header behind `header`.
+    fn load_header_field(
+        &self,
+        header: PointerValue<'ctx>,
+        index: u32,
+        name: &str,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let header_type = self.map_header_type();
+        let field = self
+            .builder
+            .build_struct_gep(header_type, header, index, name)?;
+        let field_type = header_type.get_field_type_at_index(index).ok_or_else(|| {
+            VentiError::CodegenError("Invalid map header field".to_string(), None)
+        })?;
+        Ok(self.builder.build_load(field_type, field, name)?)
+    }
+
+    /// Stores `value` into field `index` of the map header behind `header`.
+    fn store_header_field<V: BasicValue<'ctx>>(
+        &self,
+        header: PointerValue<'ctx>,
+        index: u32,
+        value: V,
+    ) -> Result<(), VentiError> {
+        let field =
+            self.builder
+                .build_struct_gep(self.map_header_type(), header, index, "field")?;
+        self.builder.build_store(field, value)?;
+        Ok(())
+    }
+
+    /// Returns a pointer to slot `index` of a keys, values or buffer array.
+    fn slot_ptr(
+        &self,
+        array: PointerValue<'ctx>,
+        index: IntValue<'ctx>,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        // Keys are pointers and values are i64, so every slot is 8 bytes wide
+        Ok(unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i64_type(), array, &[index], name)?
+        })
+    }
+
+    /// `i64 venti_hash_str(ptr)`: FNV-1a over a NUL-terminated string.
+    fn hash_str(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_hash_str",
+            i64_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let string = param(function, 0)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let loop_block = self.block(function, "loop");
+        let body = self.block(function, "body");
+        let exit = self.block(function, "exit");
+
+        self.builder.position_at_end(entry);
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let hash = self.builder.build_phi(i64_type, "hash")?;
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let byte_ptr = unsafe {
+            self.builder.build_in_bounds_gep(
+                i8_type,
+                string,
+                &[index.as_basic_value().into_int_value()],
+                "byte_ptr",
+            )?
+        };
+        let byte = self
+            .builder
+            .build_load(i8_type, byte_ptr, "byte")?
+            .into_int_value();
+        let at_end = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            byte,
+            i8_type.const_zero(),
+            "at_end",
+        )?;
+        self.builder.build_conditional_branch(at_end, exit, body)?;
+
+        self.builder.position_at_end(body);
+        let byte = self.builder.build_int_z_extend(byte, i64_type, "byte64")?;
+        let mixed =
+            self.builder
+                .build_xor(hash.as_basic_value().into_int_value(), byte, "mixed")?;
+        let next_hash = self.builder.build_int_mul(
+            mixed,
+            i64_type.const_int(0x100000001b3, false),
+            "next_hash",
+        )?;
+        let next_index = self.builder.build_int_add(
+            index.as_basic_value().into_int_value(),
+            i64_type.const_int(1, false),
+            "next_index",
+        )?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        hash.add_incoming(&[
+            (&i64_type.const_int(0xcbf29ce484222325, false), entry),
+            (&next_hash, body),
+        ]);
+        index.add_incoming(&[(&i64_type.const_zero(), entry), (&next_index, body)]);
+
+        self.builder.position_at_end(exit);
+        self.builder.build_return(Some(&hash.as_basic_value()))?;
+        Ok(function)
+    }
+
+    /// `ptr venti_map_new(i64 capacity)`: allocates an empty map; `capacity` must be a power of two.
+    fn map_new(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_map_new",
+            self.ptr_type().fn_type(&[i64_type.into()], false),
+        );
+        let capacity = param(function, 0)?.into_int_value();
+        let entry = self.block(function, "entry");
+        self.builder.position_at_end(entry);
+
+        let malloc = self.libc("malloc")?;
+        let calloc = self.libc("calloc")?;
+        let header_size = self
+            .map_header_type()
+            .size_of()
+            .ok_or_else(|| VentiError::CodegenError("Map header is unsized".to_string(), None))?;
+        let header = self
+            .call(malloc, &[header_size.into()], "header")?
+            .into_pointer_value();
+        let slot_size = i64_type.const_int(8, false);
+        let keys = self.call(calloc, &[capacity.into(), slot_size.into()], "keys")?;
+        let values = self.call(calloc, &[capacity.into(), slot_size.into()], "values")?;
+
+        self.store_header_field(header, 0, capacity)?;
+        self.store_header_field(header, 1, i64_type.const_zero())?;
+        self.store_header_field(header, 2, keys)?;
+        self.store_header_field(header, 3, values)?;
+        // The map is unowned until it is stored in a variable
+        self.store_header_field(header, 4, i64_type.const_zero())?;
+        self.builder.build_return(Some(&header))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_map_find_slot(ptr header, ptr key)`: the slot holding `key`, or the
+    /// empty slot where it would be inserted.
+    fn map_find_slot(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_map_find_slot",
+            i64_type.fn_type(&[self.ptr_type().into(), self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let key = param(function, 1)?.into_pointer_value();
+        let hash_str = self.helper("venti_hash_str")?;
+        let strcmp = self.libc("strcmp")?;
+
+        let entry = self.block(function, "entry");
+        let probe = self.block(function, "probe");
+        let compare = self.block(function, "compare");
+        let advance = self.block(function, "advance");
+        let found = self.block(function, "found");
+
+        self.builder.position_at_end(entry);
+        let capacity = self
+            .load_header_field(header, 0, "capacity")?
+            .into_int_value();
+        let keys = self
+            .load_header_field(header, 2, "keys")?
+            .into_pointer_value();
+        let mask = self
+            .builder
+            .build_int_sub(capacity, i64_type.const_int(1, false), "mask")?;
+        let hash = self.call(hash_str, &[key.into()], "hash")?.into_int_value();
+        let start = self.builder.build_and(hash, mask, "start")?;
+        self.builder.build_unconditional_branch(probe)?;
+
+        self.builder.position_at_end(probe);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let slot = self.slot_ptr(keys, index_value, "key_slot")?;
+        let slot_key = self
+            .builder
+            .build_load(self.ptr_type(), slot, "slot_key")?
+            .into_pointer_value();
+        let is_empty = self.builder.build_is_null(slot_key, "is_empty")?;
+        self.builder
+            .build_conditional_branch(is_empty, found, compare)?;
+
+        self.builder.position_at_end(compare);
+        let order = self
+            .call(strcmp, &[slot_key.into(), key.into()], "order")?
+            .into_int_value();
+        let is_match = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            order,
+            self.context.i32_type().const_zero(),
+            "is_match",
+        )?;
+        self.builder
+            .build_conditional_branch(is_match, found, advance)?;
+
+        self.builder.position_at_end(advance);
+        let next = self
+            .builder
+            .build_int_add(index_value, i64_type.const_int(1, false), "next")?;
+        let next = self.builder.build_and(next, mask, "wrapped")?;
+        self.builder.build_unconditional_branch(probe)?;
+
+        index.add_incoming(&[(&start, entry), (&next, advance)]);
+
+        self.builder.position_at_end(found);
+        self.builder.build_return(Some(&index_value))?;
+        Ok(function)
+    }
+
+    /// `void venti_map_grow(ptr header)`: doubles the capacity and rehashes every entry.
+    fn map_grow(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_map_grow",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let calloc = self.libc("calloc")?;
+        let free = self.libc("free")?;
+        let find_slot = self.helper("venti_map_find_slot")?;
+
+        let entry = self.block(function, "entry");
+        let loop_block = self.block(function, "loop");
+        let body = self.block(function, "body");
+        let move_block = self.block(function, "move");
+        let next_block = self.block(function, "next");
+        let exit = self.block(function, "exit");
+
+        self.builder.position_at_end(entry);
+        let old_capacity = self
+            .load_header_field(header, 0, "old_capacity")?
+            .into_int_value();
+        let old_keys = self
+            .load_header_field(header, 2, "old_keys")?
+            .into_pointer_value();
+        let old_values = self
+            .load_header_field(header, 3, "old_values")?
+            .into_pointer_value();
+        let capacity =
+            self.builder
+                .build_int_mul(old_capacity, i64_type.const_int(2, false), "capacity")?;
+        let slot_size = i64_type.const_int(8, false);
+        let keys = self
+            .call(calloc, &[capacity.into(), slot_size.into()], "keys")?
+            .into_pointer_value();
+        let values = self
+            .call(calloc, &[capacity.into(), slot_size.into()], "values")?
+            .into_pointer_value();
+        self.store_header_field(header, 0, capacity)?;
+        self.store_header_field(header, 2, keys)?;
+        self.store_header_field(header, 3, values)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let done =
+            self.builder
+                .build_int_compare(IntPredicate::UGE, index_value, old_capacity, "done")?;
+        self.builder.build_conditional_branch(done, exit, body)?;
+
+        self.builder.position_at_end(body);
+        let old_key_slot = self.slot_ptr(old_keys, index_value, "old_key_slot")?;
+        let key = self
+            .builder
+            .build_load(self.ptr_type(), old_key_slot, "key")?
+            .into_pointer_value();
+        let is_empty = self.builder.build_is_null(key, "is_empty")?;
+        self.builder
+            .build_conditional_branch(is_empty, next_block, move_block)?;
+
+        self.builder.position_at_end(move_block);
+        let slot = self
+            .call(find_slot, &[header.into(), key.into()], "slot")?
+            .into_int_value();
+        let key_slot = self.slot_ptr(keys, slot, "key_slot")?;
+        self.builder.build_store(key_slot, key)?;
+        let old_value_slot = self.slot_ptr(old_values, index_value, "old_value_slot")?;
+        let value = self.builder.build_load(i64_type, old_value_slot, "value")?;
+        let value_slot = self.slot_ptr(values, slot, "value_slot")?;
+        self.builder.build_store(value_slot, value)?;
+        self.builder.build_unconditional_branch(next_block)?;
+
+        self.builder.position_at_end(next_block);
+        let next = self
+            .builder
+            .build_int_add(index_value, i64_type.const_int(1, false), "next")?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        index.add_incoming(&[(&i64_type.const_zero(), entry), (&next, next_block)]);
+
+        self.builder.position_at_end(exit);
+        self.builder.build_call(free, &[old_keys.into()], "")?;
+        self.builder.build_call(free, &[old_values.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_map_insert(ptr header, ptr key, i64 value)`: inserts or overwrites `key`.
+    fn map_insert(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_map_insert",
+            self.context.void_type().fn_type(
+                &[
+                    self.ptr_type().into(),
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                ],
+                false,
+            ),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let key = param(function, 1)?.into_pointer_value();
+        let value = param(function, 2)?.into_int_value();
+        let grow = self.helper("venti_map_grow")?;
+        let find_slot = self.helper("venti_map_find_slot")?;
+        let str_retain = self.helper("venti_str_retain")?;
+
+        let entry = self.block(function, "entry");
+        let grow_block = self.block(function, "grow");
+        let insert = self.block(function, "insert");
+        let fresh = self.block(function, "fresh");
+        let store = self.block(function, "store");
+
+        self.builder.position_at_end(entry);
+        let len = self.load_header_field(header, 1, "len")?.into_int_value();
+        let capacity = self
+            .load_header_field(header, 0, "capacity")?
+            .into_int_value();
+        let next_len = self
+            .builder
+            .build_int_add(len, i64_type.const_int(1, false), "next_len")?;
+        let needed =
+            self.builder
+                .build_int_mul(next_len, i64_type.const_int(2, false), "needed")?;
+        let is_full =
+            self.builder
+                .build_int_compare(IntPredicate::UGT, needed, capacity, "is_full")?;
+        self.builder
+            .build_conditional_branch(is_full, grow_block, insert)?;
+
+        self.builder.position_at_end(grow_block);
+        self.builder.build_call(grow, &[header.into()], "")?;
+        self.builder.build_unconditional_branch(insert)?;
+
+        self.builder.position_at_end(insert);
+        let slot = self
+            .call(find_slot, &[header.into(), key.into()], "slot")?
+            .into_int_value();
+        let keys = self
+            .load_header_field(header, 2, "keys")?
+            .into_pointer_value();
+        let key_slot = self.slot_ptr(keys, slot, "key_slot")?;
+        let existing = self
+            .builder
+            .build_load(self.ptr_type(), key_slot, "existing")?
+            .into_pointer_value();
+        let is_new = self.builder.build_is_null(existing, "is_new")?;
+        self.builder
+            .build_conditional_branch(is_new, fresh, store)?;
+
+        self.builder.position_at_end(fresh);
+        self.builder.build_call(str_retain, &[key.into()], "")?;
+        self.builder.build_store(key_slot, key)?;
+        self.store_header_field(header, 1, next_len)?;
+        self.builder.build_unconditional_branch(store)?;
+
+        self.builder.position_at_end(store);
+        let values = self
+            .load_header_field(header, 3, "values")?
+            .into_pointer_value();
+        let value_slot = self.slot_ptr(values, slot, "value_slot")?;
+        self.builder.build_store(value_slot, value)?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `i64 venti_map_get(ptr header, ptr key)`: the value stored under `key`; a missing key
+    /// is a runtime error.
+    fn map_get(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_map_get",
+            i64_type.fn_type(&[self.ptr_type().into(), self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let key = param(function, 1)?.into_pointer_value();
+        let find_slot = self.helper("venti_map_find_slot")?;
+
+        let entry = self.block(function, "entry");
+        let missing = self.block(function, "missing");
+        let found = self.block(function, "found");
+
+        self.builder.position_at_end(entry);
+        let slot = self
+            .call(find_slot, &[header.into(), key.into()], "slot")?
+            .into_int_value();
+        let keys = self
+            .load_header_field(header, 2, "keys")?
+            .into_pointer_value();
+        let key_slot = self.slot_ptr(keys, slot, "key_slot")?;
+        let existing = self
+            .builder
+            .build_load(self.ptr_type(), key_slot, "existing")?
+            .into_pointer_value();
+        let is_missing = self.builder.build_is_null(existing, "is_missing")?;
+        self.builder
+            .build_conditional_branch(is_missing, missing, found)?;
+
+        self.builder.position_at_end(missing);
+        self.runtime_error("key %s not found in map", &[key.into()])?;
+
+        self.builder.position_at_end(found);
+        let values = self
+            .load_header_field(header, 3, "values")?
+            .into_pointer_value();
+        let value_slot = self.slot_ptr(values, slot, "value_slot")?;
+        let value = self.builder.build_load(i64_type, value_slot, "value")?;
+        self.builder.build_return(Some(&value))?;
+        Ok(function)
+    }
+
+    /// `i1 venti_map_has(ptr header, ptr key)`: whether `key` is present.
+    fn map_has(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_map_has",
+            self.context
+                .bool_type()
+                .fn_type(&[self.ptr_type().into(), self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let key = param(function, 1)?.into_pointer_value();
+        let find_slot = self.helper("venti_map_find_slot")?;
+
+        let entry = self.block(function, "entry");
+        self.builder.position_at_end(entry);
+        let slot = self
+            .call(find_slot, &[header.into(), key.into()], "slot")?
+            .into_int_value();
+        let keys = self
+            .load_header_field(header, 2, "keys")?
+            .into_pointer_value();
+        let key_slot = self.slot_ptr(keys, slot, "key_slot")?;
+        let existing = self
+            .builder
+            .build_load(self.ptr_type(), key_slot, "existing")?
+            .into_pointer_value();
+        let present = self.builder.build_is_not_null(existing, "present")?;
+        self.builder.build_return(Some(&present))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_str_byte_at(ptr data, i64 len, i64 index)`: the byte at `index` of a
+    /// string, or -1 when `index` is out of range.
+    fn str_byte_at(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_str_byte_at",
+            i64_type.fn_type(
+                &[self.ptr_type().into(), i64_type.into(), i64_type.into()],
+                false,
+            ),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let index = param(function, 2)?.into_int_value();
+        let entry = self.block(function, "entry");
+        let in_range = self.block(function, "in_range");
+        let out_of_range = self.block(function, "out_of_range");
+
+        self.builder.position_at_end(entry);
+        // Compared unsigned, a negative index is past the end too
+        let is_in_range =
+            self.builder
+                .build_int_compare(IntPredicate::ULT, index, len, "is_in_range")?;
+        self.builder
+            .build_conditional_branch(is_in_range, in_range, out_of_range)?;
+
+        self.builder.position_at_end(in_range);
+        let byte_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), data, &[index], "byte_ptr")?
+        };
+        let byte = self
+            .builder
+            .build_load(self.context.i8_type(), byte_ptr, "byte")?
+            .into_int_value();
+        let byte = self.builder.build_int_z_extend(byte, i64_type, "byte64")?;
+        self.builder.build_return(Some(&byte))?;
+
+        self.builder.position_at_end(out_of_range);
+        self.builder
+            .build_return(Some(&i64_type.const_all_ones()))?;
+        Ok(function)
+    }
+
+    /// `ptr venti_str_copy(ptr bytes, i64 len)`: copies `len` bytes into a new, unowned
+    /// string and returns its data.
+    fn str_copy(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_str_copy",
+            self.ptr_type()
+                .fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let bytes = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let entry = self.block(function, "entry");
+        self.builder.position_at_end(entry);
+
+        // The count, the bytes and the NUL after them
+        let size = self
+            .builder
+            .build_int_add(len, i64_type.const_int(9, false), "size")?;
+        let malloc = self.libc("malloc")?;
+        let count = self
+            .call(malloc, &[size.into()], "count")?
+            .into_pointer_value();
+        // The string is unowned until it is stored in a variable
+        self.builder.build_store(count, i64_type.const_zero())?;
+        let data = unsafe {
+            self.builder.build_in_bounds_gep(
+                i64_type,
+                count,
+                &[i64_type.const_int(1, false)],
+                "data",
+            )?
+        };
+        self.builder.build_memcpy(data, 1, bytes, 1, len)?;
+        let end = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), data, &[len], "end")?
+        };
+        self.builder
+            .build_store(end, self.context.i8_type().const_zero())?;
+        self.builder.build_return(Some(&data))?;
+        Ok(function)
+    }
+
+    /// Builds the `venti.str` value of the string with `data` and `len`.
+    fn string_value(
+        &self,
+        data: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let string = self
+            .builder
+            .build_insert_value(string_type(self.context).get_undef(), data, 0, "string")?
+            .into_struct_value();
+        Ok(self
+            .builder
+            .build_insert_value(string, len, 1, "string")?
+            .as_basic_value_enum())
+    }
+
+    /// Returns whether the byte at `index` of `data` is whitespace, as C's `isspace` sees it.
+    fn is_space_at(
+        &self,
+        data: PointerValue<'ctx>,
+        index: IntValue<'ctx>,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let byte_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), data, &[index], "byte_ptr")?
+        };
+        let byte = self
+            .builder
+            .build_load(self.context.i8_type(), byte_ptr, "byte")?
+            .into_int_value();
+        let byte = self.builder.build_int_z_extend(byte, i32_type, "byte32")?;
+        let space = self
+            .call(self.libc("isspace")?, &[byte.into()], "space")?
+            .into_int_value();
+        Ok(self.builder.build_int_compare(
+            IntPredicate::NE,
+            space,
+            i32_type.const_zero(),
+            "is_space",
+        )?)
+    }
+
+    /// `venti.str venti_str_slice(ptr data, i64 start, i64 end)`: a new string holding the
+    /// bytes of `data` from `start` up to `end`, which must be within the string.
+    fn str_slice(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_str_slice",
+            string_type(self.context).fn_type(
+                &[self.ptr_type().into(), i64_type.into(), i64_type.into()],
+                false,
+            ),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let start = param(function, 1)?.into_int_value();
+        let end = param(function, 2)?.into_int_value();
+        let copy = self.helper("venti_str_copy")?;
+        let entry = self.block(function, "entry");
+        self.builder.position_at_end(entry);
+
+        let bytes = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), data, &[start], "bytes")?
+        };
+        let len = self.builder.build_int_sub(end, start, "len")?;
+        let copied = self
+            .call(copy, &[bytes.into(), len.into()], "copy")?
+            .into_pointer_value();
+        let string = self.string_value(copied, len)?;
+        self.builder.build_return(Some(&string))?;
+        Ok(function)
+    }
+
+    /// `venti.str <name>(ptr data, i64 len)`: a copy of the string with every byte passed
+    /// through the libc function `convert`, `toupper` or `tolower`.
+    fn str_case(&self, name: &str, convert: &str) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            name,
+            string_type(self.context).fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let copy = self.helper("venti_str_copy")?;
+        let convert = self.libc(convert)?;
+        let entry = self.block(function, "entry");
+        let loop_block = self.block(function, "loop");
+        let body = self.block(function, "body");
+        let exit = self.block(function, "exit");
+
+        self.builder.position_at_end(entry);
+        let copied = self
+            .call(copy, &[data.into(), len.into()], "copy")?
+            .into_pointer_value();
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let in_range =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, index_value, len, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, body, exit)?;
+
+        self.builder.position_at_end(body);
+        let byte_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(i8_type, copied, &[index_value], "byte_ptr")?
+        };
+        let byte = self
+            .builder
+            .build_load(i8_type, byte_ptr, "byte")?
+            .into_int_value();
+        let byte = self.builder.build_int_z_extend(byte, i32_type, "byte32")?;
+        let converted = self
+            .call(convert, &[byte.into()], "converted")?
+            .into_int_value();
+        let converted = self
+            .builder
+            .build_int_truncate(converted, i8_type, "converted8")?;
+        self.builder.build_store(byte_ptr, converted)?;
+        let next_index =
+            self.builder
+                .build_int_add(index_value, i64_type.const_int(1, false), "next_index")?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        index.add_incoming(&[(&i64_type.const_zero(), entry), (&next_index, body)]);
+
+        self.builder.position_at_end(exit);
+        let string = self.string_value(copied, len)?;
+        self.builder.build_return(Some(&string))?;
+        Ok(function)
+    }
+
+    /// `venti.str venti_str_trim(ptr data, i64 len)`: a copy of the string without the
+    /// whitespace at either end.
+    fn str_trim(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let one = i64_type.const_int(1, false);
+        let function = self.declare(
+            "venti_str_trim",
+            string_type(self.context).fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let slice = self.helper("venti_str_slice")?;
+        let entry = self.block(function, "entry");
+        let front = self.block(function, "front");
+        let front_check = self.block(function, "front_check");
+        let back = self.block(function, "back");
+        let back_check = self.block(function, "back_check");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        self.builder.build_unconditional_branch(front)?;
+
+        // Skip whitespace from the front, then from the back down to where the front stopped
+        self.builder.position_at_end(front);
+        let start = self.builder.build_phi(i64_type, "start")?;
+        let start_value = start.as_basic_value().into_int_value();
+        let in_range =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, start_value, len, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, front_check, back)?;
+
+        self.builder.position_at_end(front_check);
+        let is_space = self.is_space_at(data, start_value)?;
+        let next_start = self.builder.build_int_add(start_value, one, "next_start")?;
+        self.builder
+            .build_conditional_branch(is_space, front, back)?;
+        start.add_incoming(&[(&i64_type.const_zero(), entry), (&next_start, front_check)]);
+
+        self.builder.position_at_end(back);
+        let end = self.builder.build_phi(i64_type, "end")?;
+        let end_value = end.as_basic_value().into_int_value();
+        let in_range = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            end_value,
+            start_value,
+            "in_range",
+        )?;
+        self.builder
+            .build_conditional_branch(in_range, back_check, done)?;
+
+        self.builder.position_at_end(back_check);
+        let last = self.builder.build_int_sub(end_value, one, "last")?;
+        let is_space = self.is_space_at(data, last)?;
+        self.builder
+            .build_conditional_branch(is_space, back, done)?;
+        end.add_incoming(&[(&len, front), (&len, front_check), (&last, back_check)]);
+
+        self.builder.position_at_end(done);
+        let trimmed = self.call(
+            slice,
+            &[data.into(), start_value.into(), end_value.into()],
+            "trimmed",
+        )?;
+        self.builder.build_return(Some(&trimmed))?;
+        Ok(function)
+    }
+
+    /// Returns `value` limited to the range from `low` to `high`.
+    fn clamp(
+        &self,
+        value: IntValue<'ctx>,
+        low: IntValue<'ctx>,
+        high: IntValue<'ctx>,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        let is_low = self
+            .builder
+            .build_int_compare(IntPredicate::SLT, value, low, "is_low")?;
+        let value = self
+            .builder
+            .build_select(is_low, low, value, "at_least_low")?
+            .into_int_value();
+        let is_high = self
+            .builder
+            .build_int_compare(IntPredicate::SGT, value, high, "is_high")?;
+        Ok(self
+            .builder
+            .build_select(is_high, high, value, "clamped")?
+            .into_int_value())
+    }
+
+    /// `venti.str venti_str_substring(ptr data, i64 len, i64 start, i64 end)`: a copy of the
+    /// bytes from `start` up to `end`, after limiting both to the string and `start` to
+    /// `end`, so indices out of range never fail.
+    fn str_substring(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_str_substring",
+            string_type(self.context).fn_type(
+                &[
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                    i64_type.into(),
+                    i64_type.into(),
+                ],
+                false,
+            ),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let start = param(function, 2)?.into_int_value();
+        let end = param(function, 3)?.into_int_value();
+        let slice = self.helper("venti_str_slice")?;
+        let entry = self.block(function, "entry");
+        self.builder.position_at_end(entry);
+
+        let end = self.clamp(end, i64_type.const_zero(), len)?;
+        let start = self.clamp(start, i64_type.const_zero(), end)?;
+        let substring = self.call(slice, &[data.into(), start.into(), end.into()], "substring")?;
+        self.builder.build_return(Some(&substring))?;
+        Ok(function)
+    }
+
+    /// Returns whether the i8 `byte` continues a UTF-8 sequence rather than starting one.
+    fn is_continuation(
+        &self,
+        byte: IntValue<'ctx>,
+        name: &str,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let top_bits = self
+            .builder
+            .build_and(byte, i8_type.const_int(0xc0, false), "top_bits")?;
+        Ok(self.builder.build_int_compare(
+            IntPredicate::EQ,
+            top_bits,
+            i8_type.const_int(0x80, false),
+            name,
+        )?)
+    }
+
+    /// `venti.str venti_str_range(ptr data, i64 len, i64 start, i64 end)`: a copy of the
+    /// bytes from `start` up to `end`, as `s[start..end]` gives. Offsets outside the string,
+    /// a `start` past `end` and an offset inside a character are runtime errors.
+    fn str_range(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_str_range",
+            string_type(self.context).fn_type(
+                &[
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                    i64_type.into(),
+                    i64_type.into(),
+                ],
+                false,
+            ),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let start = param(function, 2)?.into_int_value();
+        let end = param(function, 3)?.into_int_value();
+        let slice = self.helper("venti_str_slice")?;
+        let entry = self.block(function, "entry");
+        let check_start = self.block(function, "check_start");
+        let start_byte = self.block(function, "start_byte");
+        let check_end = self.block(function, "check_end");
+        let end_byte = self.block(function, "end_byte");
+        let in_bounds = self.block(function, "in_bounds");
+        let out_of_range = self.block(function, "out_of_range");
+        let splits = self.block(function, "splits");
+
+        self.builder.position_at_end(entry);
+        let is_negative = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            start,
+            i64_type.const_zero(),
+            "is_negative",
+        )?;
+        let is_reversed =
+            self.builder
+                .build_int_compare(IntPredicate::SGT, start, end, "is_reversed")?;
+        let is_past = self
+            .builder
+            .build_int_compare(IntPredicate::SGT, end, len, "is_past")?;
+        let is_outside = self
+            .builder
+            .build_or(is_negative, is_reversed, "is_outside")?;
+        let is_outside = self.builder.build_or(is_outside, is_past, "is_outside")?;
+        self.builder
+            .build_conditional_branch(is_outside, out_of_range, check_start)?;
+
+        // An offset at the end of the string is always a boundary, and reading the byte
+        // there would read through the null data of `nothing`
+        for (offset, check, read, next) in [
+            (start, check_start, start_byte, check_end),
+            (end, check_end, end_byte, in_bounds),
+        ] {
+            self.builder.position_at_end(check);
+            let is_inside =
+                self.builder
+                    .build_int_compare(IntPredicate::SLT, offset, len, "is_inside")?;
+            self.builder
+                .build_conditional_branch(is_inside, read, next)?;
+
+            self.builder.position_at_end(read);
+            let byte = self.text_byte(data, offset, "byte")?;
+            let is_split = self.is_continuation(byte, "is_split")?;
+            self.builder
+                .build_conditional_branch(is_split, splits, next)?;
+        }
+
+        self.builder.position_at_end(in_bounds);
+        let sliced = self.call(slice, &[data.into(), start.into(), end.into()], "sliced")?;
+        self.builder.build_return(Some(&sliced))?;
+
+        self.builder.position_at_end(out_of_range);
+        self.runtime_error(
+            "string slice %ld..%ld is out of range for a string of %ld bytes",
+            &[start.into(), end.into(), len.into()],
+        )?;
+
+        self.builder.position_at_end(splits);
+        self.runtime_error(
+            "string slice %ld..%ld does not start and end on character boundaries",
+            &[start.into(), end.into()],
+        )?;
+        Ok(function)
+    }
+
+    /// `i64 venti_str_contains(ptr data, i64 len, ptr needle, i64 needle_len)`: 1 if
+    /// `needle` occurs in the string, else 0. Every string contains the empty string.
+    fn str_contains(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_str_contains",
+            i64_type.fn_type(
+                &[
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                ],
+                false,
+            ),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let needle = param(function, 2)?.into_pointer_value();
+        let needle_len = param(function, 3)?.into_int_value();
+        let strstr = self.libc("strstr")?;
+        let entry = self.block(function, "entry");
+        let check_text = self.block(function, "check_text");
+        let search = self.block(function, "search");
+        let found = self.block(function, "found");
+        let missing = self.block(function, "missing");
+
+        // Empty strings are checked first, since `nothing` has no bytes to hand to strstr
+        self.builder.position_at_end(entry);
+        let is_empty_needle = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            needle_len,
+            i64_type.const_zero(),
+            "is_empty_needle",
+        )?;
+        self.builder
+            .build_conditional_branch(is_empty_needle, found, check_text)?;
+
+        self.builder.position_at_end(check_text);
+        let is_empty = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            len,
+            i64_type.const_zero(),
+            "is_empty",
+        )?;
+        self.builder
+            .build_conditional_branch(is_empty, missing, search)?;
+
+        self.builder.position_at_end(search);
+        let match_ptr = self
+            .call(strstr, &[data.into(), needle.into()], "match")?
+            .into_pointer_value();
+        let is_missing = self.builder.build_is_null(match_ptr, "is_missing")?;
+        self.builder
+            .build_conditional_branch(is_missing, missing, found)?;
+
+        self.builder.position_at_end(found);
+        self.builder
+            .build_return(Some(&i64_type.const_int(1, false)))?;
+
+        self.builder.position_at_end(missing);
+        self.builder.build_return(Some(&i64_type.const_zero()))?;
+        Ok(function)
+    }
+
+    /// `ptr venti_str_split(ptr data, i64 len, ptr separator, i64 separator_len)`: a new
+    /// string vector holding the pieces of the string cut at every `separator`. The pieces
+    /// are counted first, so the slots are allocated once. An empty separator is a runtime
+    /// error.
+    fn str_split(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_str_split",
+            self.ptr_type().fn_type(
+                &[
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                ],
+                false,
+            ),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let separator = param(function, 2)?.into_pointer_value();
+        let separator_len = param(function, 3)?.into_int_value();
+        let new = self.helper("venti_strvec_new")?;
+        let slice = self.helper("venti_str_slice")?;
+        let entry = self.block(function, "entry");
+        let empty_separator = self.block(function, "empty_separator");
+        let count_loop = self.block(function, "count_loop");
+        let count_search = self.block(function, "count_search");
+        let count_found = self.block(function, "count_found");
+        let allocate = self.block(function, "allocate");
+        let fill_loop = self.block(function, "fill_loop");
+        let fill_search = self.block(function, "fill_search");
+        let fill_found = self.block(function, "fill_found");
+        let last = self.block(function, "last");
+
+        self.builder.position_at_end(entry);
+        let is_empty_separator = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            separator_len,
+            i64_type.const_zero(),
+            "is_empty_separator",
+        )?;
+        self.builder
+            .build_conditional_branch(is_empty_separator, empty_separator, count_loop)?;
+
+        self.builder.position_at_end(empty_separator);
+        self.runtime_error("cannot split a string at an empty separator", &[])?;
+
+        // Each turn of either loop finds the end of the piece starting at `start`, and
+        // the last piece runs to the end of the string
+        self.builder.position_at_end(count_loop);
+        let count_start = self.builder.build_phi(i64_type, "count_start")?;
+        let count = self.builder.build_phi(i64_type, "count")?;
+        let count_start_value = count_start.as_basic_value().into_int_value();
+        let count_value = count.as_basic_value().into_int_value();
+        let end = self.find_separator(
+            (data, len, separator),
+            count_start_value,
+            count_search,
+            count_found,
+            allocate,
+        )?;
+
+        self.builder.position_at_end(count_found);
+        let next_start = self
+            .builder
+            .build_int_add(end, separator_len, "next_start")?;
+        let next_count =
+            self.builder
+                .build_int_add(count_value, i64_type.const_int(1, false), "next_count")?;
+        self.builder.build_unconditional_branch(count_loop)?;
+        count_start.add_incoming(&[(&i64_type.const_zero(), entry), (&next_start, count_found)]);
+        count.add_incoming(&[
+            (&i64_type.const_int(1, false), entry),
+            (&next_count, count_found),
+        ]);
+
+        self.builder.position_at_end(allocate);
+        let header = self
+            .call(new, &[count_value.into()], "header")?
+            .into_pointer_value();
+        let slots_field = self.string_vector_field(header, 1, "slots_field")?;
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?
+            .into_pointer_value();
+        self.builder.build_unconditional_branch(fill_loop)?;
+
+        self.builder.position_at_end(fill_loop);
+        let fill_start = self.builder.build_phi(i64_type, "fill_start")?;
+        let piece = self.builder.build_phi(i64_type, "piece")?;
+        let fill_start_value = fill_start.as_basic_value().into_int_value();
+        let piece_value = piece.as_basic_value().into_int_value();
+        let end = self.find_separator(
+            (data, len, separator),
+            fill_start_value,
+            fill_search,
+            fill_found,
+            last,
+        )?;
+
+        let store_piece = |end: IntValue<'ctx>| -> Result<(), VentiError> {
+            let taken = self.call(
+                slice,
+                &[data.into(), fill_start_value.into(), end.into()],
+                "taken",
+            )?;
+            self.string_vector_store(slots, piece_value, taken)
+        };
+
+        self.builder.position_at_end(fill_found);
+        store_piece(end)?;
+        let next_start = self
+            .builder
+            .build_int_add(end, separator_len, "next_start")?;
+        let next_piece =
+            self.builder
+                .build_int_add(piece_value, i64_type.const_int(1, false), "next_piece")?;
+        self.builder.build_unconditional_branch(fill_loop)?;
+        fill_start.add_incoming(&[
+            (&i64_type.const_zero(), allocate),
+            (&next_start, fill_found),
+        ]);
+        piece.add_incoming(&[
+            (&i64_type.const_zero(), allocate),
+            (&next_piece, fill_found),
+        ]);
+
+        self.builder.position_at_end(last);
+        store_piece(len)?;
+        self.builder.build_return(Some(&header))?;
+        Ok(function)
+    }
+
+    /// Emits the search for the next `separator` in the string `data` of length `len` at or
+    /// after `start`, branching from the current block through `search` to `found` when
+    /// there is one and to `missing` when the rest of the string has none.
+    ///
+    /// # Returns
+    ///
+    /// The offset of the separator found, for use in `found`.
+    fn find_separator(
+        &self,
+        (data, len, separator): (PointerValue<'ctx>, IntValue<'ctx>, PointerValue<'ctx>),
+        start: IntValue<'ctx>,
+        search: BasicBlock<'ctx>,
+        found: BasicBlock<'ctx>,
+        missing: BasicBlock<'ctx>,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let strstr = self.libc("strstr")?;
+        // `nothing` has no bytes to search, so the search only starts inside the string
+        let has_rest = self
+            .builder
+            .build_int_compare(IntPredicate::SLT, start, len, "has_rest")?;
+        self.builder
+            .build_conditional_branch(has_rest, search, missing)?;
+
+        self.builder.position_at_end(search);
+        let rest = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), data, &[start], "rest")?
+        };
+        let match_ptr = self
+            .call(strstr, &[rest.into(), separator.into()], "match")?
+            .into_pointer_value();
+        let match_address = self
+            .builder
+            .build_ptr_to_int(match_ptr, i64_type, "match_address")?;
+        let data_address = self
+            .builder
+            .build_ptr_to_int(data, i64_type, "data_address")?;
+        let end = self
+            .builder
+            .build_int_sub(match_address, data_address, "end")?;
+        let is_missing = self.builder.build_is_null(match_ptr, "is_missing")?;
+        self.builder
+            .build_conditional_branch(is_missing, missing, found)?;
+        Ok(end)
+    }
+
+    /// Returns the string data `data` as a C string, with the empty string in place of
+    /// `nothing`, which has no bytes.
+    fn c_text(&self, data: PointerValue<'ctx>) -> Result<PointerValue<'ctx>, VentiError> {
+        let empty = self
+            .builder
+            .build_global_string_ptr("", "empty")?
+            .as_pointer_value();
+        let is_null = self.builder.build_is_null(data, "is_nothing")?;
+        Ok(self
+            .builder
+            .build_select(is_null, empty, data, "text")?
+            .into_pointer_value())
+    }
+
+    /// Emits the checks of `venti_str_to_int` and `venti_str_to_float`: the string must
+    /// not be empty or start with whitespace, which `parse` would skip, and `parse` must
+    /// use every byte of it; otherwise a runtime error names the `kind` of number expected.
+    /// Returns the parsed value, leaving the builder in the block where the checks passed.
+    ///
+    /// # Arguments
+    ///
+    /// * `parse` - Builds the call to the libc parser, given the data and where to store
+    ///   the end of what it parsed.
+    fn parse_checked<F>(
+        &self,
+        function: FunctionValue<'ctx>,
+        kind: &str,
+        parse: F,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError>
+    where
+        F: FnOnce(
+            PointerValue<'ctx>,
+            PointerValue<'ctx>,
+        ) -> Result<BasicValueEnum<'ctx>, VentiError>,
+    {
+        let i64_type = self.context.i64_type();
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let entry = self.block(function, "entry");
+        let check_space = self.block(function, "check_space");
+        let parse_block = self.block(function, "parse");
+        let parsed = self.block(function, "parsed");
+        let invalid = self.block(function, "invalid");
+
+        self.builder.position_at_end(entry);
+        let end_ptr = self.builder.build_alloca(self.ptr_type(), "end_ptr")?;
+        let is_empty = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            len,
+            i64_type.const_zero(),
+            "is_empty",
+        )?;
+        self.builder
+            .build_conditional_branch(is_empty, invalid, check_space)?;
+
+        self.builder.position_at_end(check_space);
+        let is_space = self.is_space_at(data, i64_type.const_zero())?;
+        self.builder
+            .build_conditional_branch(is_space, invalid, parse_block)?;
+
+        self.builder.position_at_end(parse_block);
+        let value = parse(data, end_ptr)?;
+        let end = self
+            .builder
+            .build_load(self.ptr_type(), end_ptr, "end")?
+            .into_pointer_value();
+        let expected_end = unsafe {
+            self.builder.build_in_bounds_gep(
+                self.context.i8_type(),
+                data,
+                &[len],
+                "expected_end",
+            )?
+        };
+        let end_address = self
+            .builder
+            .build_ptr_to_int(end, i64_type, "end_address")?;
+        let expected_address =
+            self.builder
+                .build_ptr_to_int(expected_end, i64_type, "expected_address")?;
+        let is_complete = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            end_address,
+            expected_address,
+            "is_complete",
+        )?;
+        self.builder
+            .build_conditional_branch(is_complete, parsed, invalid)?;
+
+        self.builder.position_at_end(invalid);
+        let text = self.c_text(data)?;
+        self.runtime_error(&format!("could not parse '%s' as {}", kind), &[text.into()])?;
+
+        self.builder.position_at_end(parsed);
+        Ok(value)
+    }
+
+    /// `i64 venti_str_to_int(ptr data, i64 len)`: the string read as a decimal integer; a
+    /// string that is not one is a runtime error.
+    fn str_to_int(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_str_to_int",
+            i64_type.fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let strtoll = self.libc("strtoll")?;
+        let value = self.parse_checked(function, "an int", |data, end_ptr| {
+            self.call(
+                strtoll,
+                &[
+                    data.into(),
+                    end_ptr.into(),
+                    self.context.i32_type().const_int(10, false).into(),
+                ],
+                "value",
+            )
+        })?;
+        self.builder.build_return(Some(&value))?;
+        Ok(function)
+    }
+
+    /// `double venti_str_to_float(ptr data, i64 len)`: the string read as a floating-point
+    /// number; a string that is not one is a runtime error.
+    fn str_to_float(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_str_to_float",
+            self.context
+                .f64_type()
+                .fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let strtod = self.libc("strtod")?;
+        let value = self.parse_checked(function, "a float", |data, end_ptr| {
+            self.call(strtod, &[data.into(), end_ptr.into()], "value")
+        })?;
+        self.builder.build_return(Some(&value))?;
+        Ok(function)
+    }
+
+    /// `venti.str venti_read_line(ptr prompt)`: prints `prompt`, then reads a line from
+    /// standard input and returns it without its line ending, or `nothing` at the end of
+    /// the input.
+    fn read_line(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_read_line",
+            string_type(self.context).fn_type(&[self.ptr_type().into()], false),
+        );
+        let prompt = param(function, 0)?.into_pointer_value();
+        let printf = self.libc("printf")?;
+        let fflush = self.libc("fflush")?;
+        let getchar = self.libc("getchar")?;
+        let malloc = self.libc("malloc")?;
+        let realloc = self.libc("realloc")?;
+        let free = self.libc("free")?;
+        let copy = self.helper("venti_str_copy")?;
+        let entry = self.block(function, "entry");
+        let loop_block = self.block(function, "loop");
+        let check_newline = self.block(function, "check_newline");
+        let check_room = self.block(function, "check_room");
+        let grow = self.block(function, "grow");
+        let append = self.block(function, "append");
+        let at_eof = self.block(function, "at_eof");
+        let no_line = self.block(function, "no_line");
+        let check_return = self.block(function, "check_return");
+        let strip_return = self.block(function, "strip_return");
+        let finish = self.block(function, "finish");
+
+        // The line so far lives in a malloc'd buffer that doubles when it fills up
+        self.builder.position_at_end(entry);
+        let buffer_slot = self.builder.build_alloca(self.ptr_type(), "buffer_slot")?;
+        let len_slot = self.builder.build_alloca(i64_type, "len_slot")?;
+        let capacity_slot = self.builder.build_alloca(i64_type, "capacity_slot")?;
+        let format = self
+            .builder
+            .build_global_string_ptr("%s", "prompt_format")?;
+        let prompt = self.c_text(prompt)?;
+        self.builder.build_call(
+            printf,
+            &[format.as_pointer_value().into(), prompt.into()],
+            "",
+        )?;
+        // Flushing shows the prompt before waiting for the line
+        self.builder
+            .build_call(fflush, &[self.ptr_type().const_null().into()], "")?;
+        let initial_capacity = i64_type.const_int(64, false);
+        let buffer = self.call(malloc, &[initial_capacity.into()], "buffer")?;
+        self.builder.build_store(buffer_slot, buffer)?;
+        self.builder.build_store(len_slot, i64_type.const_zero())?;
+        self.builder.build_store(capacity_slot, initial_capacity)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let byte = self.call(getchar, &[], "byte")?.into_int_value();
+        let is_eof = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            byte,
+            i32_type.const_zero(),
+            "is_eof",
+        )?;
+        self.builder
+            .build_conditional_branch(is_eof, at_eof, check_newline)?;
+
+        self.builder.position_at_end(check_newline);
+        let is_newline = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            byte,
+            i32_type.const_int(u64::from(b'\n'), false),
+            "is_newline",
+        )?;
+        self.builder
+            .build_conditional_branch(is_newline, check_return, check_room)?;
+
+        self.builder.position_at_end(check_room);
+        let len = self
+            .builder
+            .build_load(i64_type, len_slot, "len")?
+            .into_int_value();
+        let capacity = self
+            .builder
+            .build_load(i64_type, capacity_slot, "capacity")?
+            .into_int_value();
+        let is_full = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, len, capacity, "is_full")?;
+        self.builder
+            .build_conditional_branch(is_full, grow, append)?;
+
+        self.builder.position_at_end(grow);
+        let capacity =
+            self.builder
+                .build_int_mul(capacity, i64_type.const_int(2, false), "new_capacity")?;
+        let buffer = self
+            .builder
+            .build_load(self.ptr_type(), buffer_slot, "buffer")?;
+        let buffer = self.call(realloc, &[buffer.into(), capacity.into()], "grown")?;
+        self.builder.build_store(buffer_slot, buffer)?;
+        self.builder.build_store(capacity_slot, capacity)?;
+        self.builder.build_unconditional_branch(append)?;
+
+        self.builder.position_at_end(append);
+        let buffer = self
+            .builder
+            .build_load(self.ptr_type(), buffer_slot, "buffer")?
+            .into_pointer_value();
+        let len = self
+            .builder
+            .build_load(i64_type, len_slot, "len")?
+            .into_int_value();
+        let byte_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(i8_type, buffer, &[len], "byte_ptr")?
+        };
+        let byte = self.builder.build_int_truncate(byte, i8_type, "byte8")?;
+        self.builder.build_store(byte_ptr, byte)?;
+        let len = self
+            .builder
+            .build_int_add(len, i64_type.const_int(1, false), "next_len")?;
+        self.builder.build_store(len_slot, len)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        // A last line without a newline is still a line
+        self.builder.position_at_end(at_eof);
+        let len = self
+            .builder
+            .build_load(i64_type, len_slot, "len")?
+            .into_int_value();
+        let is_empty = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            len,
+            i64_type.const_zero(),
+            "is_empty",
+        )?;
+        self.builder
+            .build_conditional_branch(is_empty, no_line, check_return)?;
+
+        self.builder.position_at_end(no_line);
+        let buffer = self
+            .builder
+            .build_load(self.ptr_type(), buffer_slot, "buffer")?;
+        self.builder.build_call(free, &[buffer.into()], "")?;
+        self.builder
+            .build_return(Some(&string_type(self.context).const_zero()))?;
+
+        // Lines ending in "\r\n" lose the carriage return as well
+        self.builder.position_at_end(check_return);
+        let len = self
+            .builder
+            .build_load(i64_type, len_slot, "len")?
+            .into_int_value();
+        let is_nonempty = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            len,
+            i64_type.const_zero(),
+            "is_nonempty",
+        )?;
+        self.builder
+            .build_conditional_branch(is_nonempty, strip_return, finish)?;
+
+        self.builder.position_at_end(strip_return);
+        let buffer = self
+            .builder
+            .build_load(self.ptr_type(), buffer_slot, "buffer")?
+            .into_pointer_value();
+        let last = self
+            .builder
+            .build_int_sub(len, i64_type.const_int(1, false), "last")?;
+        let last_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(i8_type, buffer, &[last], "last_ptr")?
+        };
+        let last_byte = self
+            .builder
+            .build_load(i8_type, last_ptr, "last_byte")?
+            .into_int_value();
+        let is_return = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            last_byte,
+            i8_type.const_int(u64::from(b'\r'), false),
+            "is_return",
+        )?;
+        let len = self.builder.build_select(is_return, last, len, "len")?;
+        self.builder.build_store(len_slot, len)?;
+        self.builder.build_unconditional_branch(finish)?;
+
+        self.builder.position_at_end(finish);
+        let buffer = self
+            .builder
+            .build_load(self.ptr_type(), buffer_slot, "buffer")?
+            .into_pointer_value();
+        let len = self
+            .builder
+            .build_load(i64_type, len_slot, "len")?
+            .into_int_value();
+        let data = self
+            .call(copy, &[buffer.into(), len.into()], "data")?
+            .into_pointer_value();
+        self.builder.build_call(free, &[buffer.into()], "")?;
+        let line = self.string_value(data, len)?;
+        self.builder.build_return(Some(&line))?;
+        Ok(function)
+    }
+
+    /// `venti.str venti_file_read(ptr path)`: the whole contents of the file at `path`; a
+    /// file that cannot be opened or read is a runtime error.
+    fn file_read(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_file_read",
+            string_type(self.context).fn_type(&[self.ptr_type().into()], false),
+        );
+        let path = param(function, 0)?.into_pointer_value();
+        let fopen = self.libc("fopen")?;
+        let fseek = self.libc("fseek")?;
+        let ftell = self.libc("ftell")?;
+        let fread = self.libc("fread")?;
+        let fclose = self.libc("fclose")?;
+        let malloc = self.libc("malloc")?;
+        let free = self.libc("free")?;
+        let copy = self.helper("venti_str_copy")?;
+        let entry = self.block(function, "entry");
+        let measure = self.block(function, "measure");
+        let read = self.block(function, "read");
+        let unmeasured = self.block(function, "unmeasured");
+        let failed = self.block(function, "failed");
+
+        self.builder.position_at_end(entry);
+        let path = self.c_text(path)?;
+        let mode = self.builder.build_global_string_ptr("rb", "mode")?;
+        let file = self
+            .call(
+                fopen,
+                &[path.into(), mode.as_pointer_value().into()],
+                "file",
+            )?
+            .into_pointer_value();
+        let is_missing = self.builder.build_is_null(file, "is_missing")?;
+        self.builder
+            .build_conditional_branch(is_missing, failed, measure)?;
+
+        // The size comes from seeking to the end, so files that cannot seek cannot be read
+        self.builder.position_at_end(measure);
+        self.builder.build_call(
+            fseek,
+            &[
+                file.into(),
+                i64_type.const_zero().into(),
+                i32_type.const_int(2, false).into(),
+            ],
+            "",
+        )?;
+        let size = self.call(ftell, &[file.into()], "size")?.into_int_value();
+        self.builder.build_call(
+            fseek,
+            &[
+                file.into(),
+                i64_type.const_zero().into(),
+                i32_type.const_zero().into(),
+            ],
+            "",
+        )?;
+        let is_unmeasured = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            size,
+            i64_type.const_zero(),
+            "is_unmeasured",
+        )?;
+        self.builder
+            .build_conditional_branch(is_unmeasured, unmeasured, read)?;
+
+        self.builder.position_at_end(read);
+        let buffer_size =
+            self.builder
+                .build_int_add(size, i64_type.const_int(1, false), "buffer_size")?;
+        let buffer = self
+            .call(malloc, &[buffer_size.into()], "buffer")?
+            .into_pointer_value();
+        let len = self.call(
+            fread,
+            &[
+                buffer.into(),
+                i64_type.const_int(1, false).into(),
+                size.into(),
+                file.into(),
+            ],
+            "len",
+        )?;
+        self.builder.build_call(fclose, &[file.into()], "")?;
+        let data = self
+            .call(copy, &[buffer.into(), len.into()], "data")?
+            .into_pointer_value();
+        self.builder.build_call(free, &[buffer.into()], "")?;
+        let contents = self.string_value(data, len.into_int_value())?;
+        self.builder.build_return(Some(&contents))?;
+
+        self.builder.position_at_end(unmeasured);
+        self.builder.build_call(fclose, &[file.into()], "")?;
+        self.builder.build_unconditional_branch(failed)?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error("could not read file '%s'", &[path.into()])?;
+        Ok(function)
+    }
+
+    /// `i64 venti_file_write(ptr path, ptr data, i64 len, ptr mode)`: writes the `len` bytes
+    /// at `data` to the file at `path`, opened with the `fopen` mode `mode` so that it is
+    /// either replaced or appended to, and returns how many bytes were written. A file
+    /// that cannot be opened or written is a runtime error.
+    fn file_write(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_file_write",
+            i64_type.fn_type(
+                &[
+                    self.ptr_type().into(),
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                    self.ptr_type().into(),
+                ],
+                false,
+            ),
+        );
+        let path = param(function, 0)?.into_pointer_value();
+        let data = param(function, 1)?.into_pointer_value();
+        let len = param(function, 2)?.into_int_value();
+        let mode = param(function, 3)?.into_pointer_value();
+        let fopen = self.libc("fopen")?;
+        let fwrite = self.libc("fwrite")?;
+        let fclose = self.libc("fclose")?;
+        let entry = self.block(function, "entry");
+        let write = self.block(function, "write");
+        let done = self.block(function, "done");
+        let failed = self.block(function, "failed");
+
+        self.builder.position_at_end(entry);
+        let path = self.c_text(path)?;
+        let file = self
+            .call(fopen, &[path.into(), mode.into()], "file")?
+            .into_pointer_value();
+        let is_missing = self.builder.build_is_null(file, "is_missing")?;
+        self.builder
+            .build_conditional_branch(is_missing, failed, write)?;
+
+        self.builder.position_at_end(write);
+        let written = self
+            .call(
+                fwrite,
+                &[
+                    data.into(),
+                    i64_type.const_int(1, false).into(),
+                    len.into(),
+                    file.into(),
+                ],
+                "written",
+            )?
+            .into_int_value();
+        // Buffered bytes are only written out when the file is closed, which can fail too
+        let closed = self
+            .call(fclose, &[file.into()], "closed")?
+            .into_int_value();
+        let is_complete =
+            self.builder
+                .build_int_compare(IntPredicate::EQ, written, len, "is_complete")?;
+        let is_closed = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            closed,
+            self.context.i32_type().const_zero(),
+            "is_closed",
+        )?;
+        let succeeded = self
+            .builder
+            .build_and(is_complete, is_closed, "succeeded")?;
+        self.builder
+            .build_conditional_branch(succeeded, done, failed)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(Some(&len))?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error("could not write to file '%s'", &[path.into()])?;
+        Ok(function)
+    }
+
+    /// Returns the state of the random number generator, which every module shares. Each
+    /// module defines it weakly, so one definition is kept.
+    fn random_state_global(&self) -> PointerValue<'ctx> {
+        let global = self.module.get_global(RANDOM_STATE).unwrap_or_else(|| {
+            let i64_type = self.context.i64_type();
+            let global = self.module.add_global(i64_type, None, RANDOM_STATE);
+            global.set_linkage(Linkage::WeakAny);
+            global.set_initializer(&i64_type.const_zero());
+            global
+        });
+        global.as_pointer_value()
+    }
+
+    /// Builds the splitmix64 output function, which scrambles a state into a draw.
+    fn random_mix(&self, state: IntValue<'ctx>) -> Result<IntValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let mut z = state;
+        for (shift, multiplier) in [(30, 0xbf58_476d_1ce4_e5b9), (27, 0x94d0_49bb_1331_11eb)] {
+            let shifted = self.builder.build_right_shift(
+                z,
+                i64_type.const_int(shift, false),
+                false,
+                "shifted",
+            )?;
+            let mixed = self.builder.build_xor(z, shifted, "mixed")?;
+            z = self.builder.build_int_mul(
+                mixed,
+                i64_type.const_int(multiplier, false),
+                "scrambled",
+            )?;
+        }
+        let shifted =
+            self.builder
+                .build_right_shift(z, i64_type.const_int(31, false), false, "shifted")?;
+        Ok(self.builder.build_xor(z, shifted, "draw")?)
+    }
+
+    /// `i64 venti_random_next()`: the next 64 random bits, seeding the generator from the
+    /// clock if nothing has seeded it yet.
+    fn random_next(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let step = i64_type.const_int(RANDOM_STEP, false);
+        let function = self.declare("venti_random_next", i64_type.fn_type(&[], false));
+        let global = self.random_state_global();
+        let time = self.libc("time")?;
+        let entry = self.block(function, "entry");
+        let seed = self.block(function, "seed");
+        let draw = self.block(function, "draw");
+
+        self.builder.position_at_end(entry);
+        // Adding zero reads the state atomically
+        let state = self.builder.build_atomicrmw(
+            AtomicRMWBinOp::Add,
+            global,
+            i64_type.const_zero(),
+            AtomicOrdering::SequentiallyConsistent,
+        )?;
+        let is_unseeded = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            state,
+            i64_type.const_zero(),
+            "is_unseeded",
+        )?;
+        self.builder
+            .build_conditional_branch(is_unseeded, seed, draw)?;
+
+        // Threads that seed at the same time keep the first seed stored
+        self.builder.position_at_end(seed);
+        let seconds = self
+            .call(time, &[self.ptr_type().const_null().into()], "seconds")?
+            .into_int_value();
+        let scaled = self.builder.build_int_mul(seconds, step, "scaled")?;
+        let seeded = self
+            .builder
+            .build_or(scaled, i64_type.const_int(1, false), "seeded")?;
+        self.builder.build_cmpxchg(
+            global,
+            i64_type.const_zero(),
+            seeded,
+            AtomicOrdering::SequentiallyConsistent,
+            AtomicOrdering::SequentiallyConsistent,
+        )?;
+        self.builder.build_unconditional_branch(draw)?;
+
+        // Stepping atomically gives threads drawing at once different states
+        self.builder.position_at_end(draw);
+        let previous = self.builder.build_atomicrmw(
+            AtomicRMWBinOp::Add,
+            global,
+            step,
+            AtomicOrdering::SequentiallyConsistent,
+        )?;
+        let state = self.builder.build_int_add(previous, step, "state")?;
+        let bits = self.random_mix(state)?;
+        self.builder.build_return(Some(&bits))?;
+        Ok(function)
+    }
+
+    /// `double venti_random_float()`: a random float from 0 up to but not including 1,
+    /// made from the top 53 bits of a draw.
+    fn random_float(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let f64_type = self.context.f64_type();
+        let function = self.declare("venti_random_float", f64_type.fn_type(&[], false));
+        let next = self.helper("venti_random_next")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let bits = self.call(next, &[], "bits")?.into_int_value();
+        let top = self.builder.build_right_shift(
+            bits,
+            self.context.i64_type().const_int(11, false),
+            false,
+            "top",
+        )?;
+        let top = self
+            .builder
+            .build_unsigned_int_to_float(top, f64_type, "top")?;
+        let float = self.builder.build_float_div(
+            top,
+            f64_type.const_float((1u64 << 53) as f64),
+            "float",
+        )?;
+        self.builder.build_return(Some(&float))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_random_int(i64 low, i64 high)`: a random integer from `low` to `high`,
+    /// both included; `high` below `low` is a runtime error.
+    fn random_int(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_random_int",
+            i64_type.fn_type(&[i64_type.into(), i64_type.into()], false),
+        );
+        let low = param(function, 0)?.into_int_value();
+        let high = param(function, 1)?.into_int_value();
+        let next = self.helper("venti_random_next")?;
+        let entry = self.block(function, "entry");
+        let draw = self.block(function, "draw");
+        let empty = self.block(function, "empty");
+
+        self.builder.position_at_end(entry);
+        let is_empty = self
+            .builder
+            .build_int_compare(IntPredicate::SLT, high, low, "is_empty")?;
+        self.builder
+            .build_conditional_branch(is_empty, empty, draw)?;
+
+        self.builder.position_at_end(draw);
+        let span = self.builder.build_int_sub(high, low, "span")?;
+        let count = self
+            .builder
+            .build_int_add(span, i64_type.const_int(1, false), "count")?;
+        let bits = self.call(next, &[], "bits")?.into_int_value();
+        // The count wraps to zero when the range covers every integer, and any draw fits
+        let is_full = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            count,
+            i64_type.const_zero(),
+            "is_full",
+        )?;
+        let divisor = self
+            .builder
+            .build_select(is_full, i64_type.const_int(1, false), count, "divisor")?
+            .into_int_value();
+        let remainder = self
+            .builder
+            .build_int_unsigned_rem(bits, divisor, "remainder")?;
+        let offset = self
+            .builder
+            .build_select(is_full, bits, remainder, "offset")?
+            .into_int_value();
+        let value = self.builder.build_int_add(low, offset, "value")?;
+        self.builder.build_return(Some(&value))?;
+
+        self.builder.position_at_end(empty);
+        self.runtime_error(
+            "random_int needs a range, but %ld is above %ld",
+            &[low.into(), high.into()],
+        )?;
+        Ok(function)
+    }
+
+    /// `void venti_random_seed(i64 seed)`: seeds the generator, so the numbers drawn after
+    /// it are the same on every run.
+    fn random_seed(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_random_seed",
+            self.context.void_type().fn_type(&[i64_type.into()], false),
+        );
+        let seed = param(function, 0)?.into_int_value();
+        let global = self.random_state_global();
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let state =
+            self.builder
+                .build_xor(seed, i64_type.const_int(RANDOM_STEP, false), "state")?;
+        self.builder.build_atomicrmw(
+            AtomicRMWBinOp::Xchg,
+            global,
+            state,
+            AtomicOrdering::SequentiallyConsistent,
+        )?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// Builds a reading of the clock `clock` from `clock_gettime`, in nanoseconds.
+    fn clock_nanos(&self, clock: u64) -> Result<IntValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let timespec_type = self
+            .context
+            .struct_type(&[i64_type.into(), i64_type.into()], false);
+        let clock_gettime = self.libc("clock_gettime")?;
+        let timespec = self.builder.build_alloca(timespec_type, "timespec")?;
+        self.builder.build_call(
+            clock_gettime,
+            &[
+                self.context.i32_type().const_int(clock, false).into(),
+                timespec.into(),
+            ],
+            "",
+        )?;
+        let seconds_ptr =
+            self.builder
+                .build_struct_gep(timespec_type, timespec, 0, "seconds_ptr")?;
+        let seconds = self
+            .builder
+            .build_load(i64_type, seconds_ptr, "seconds")?
+            .into_int_value();
+        let nanos_ptr = self
+            .builder
+            .build_struct_gep(timespec_type, timespec, 1, "nanos_ptr")?;
+        let nanos = self
+            .builder
+            .build_load(i64_type, nanos_ptr, "nanos")?
+            .into_int_value();
+        let whole = self.builder.build_int_mul(
+            seconds,
+            i64_type.const_int(NANOS_PER_SECOND, false),
+            "whole",
+        )?;
+        Ok(self.builder.build_int_add(whole, nanos, "reading")?)
+    }
+
+    /// `i64 venti_now_millis()`: the milliseconds since the Unix epoch.
+    fn now_millis(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare("venti_now_millis", i64_type.fn_type(&[], false));
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let nanos = self.clock_nanos(CLOCK_REALTIME)?;
+        let millis = self.builder.build_int_signed_div(
+            nanos,
+            i64_type.const_int(NANOS_PER_MILLI, false),
+            "millis",
+        )?;
+        self.builder.build_return(Some(&millis))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_timer()`: a reading of the monotonic clock in nanoseconds, which only
+    /// means something to `venti_elapsed`.
+    fn timer(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare("venti_timer", self.context.i64_type().fn_type(&[], false));
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let reading = self.clock_nanos(clock_monotonic(self.module))?;
+        self.builder.build_return(Some(&reading))?;
+        Ok(function)
+    }
+
+    /// `double venti_elapsed(i64 start)`: the milliseconds since `venti_timer` returned
+    /// `start`.
+    fn elapsed(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let f64_type = self.context.f64_type();
+        let function = self.declare(
+            "venti_elapsed",
+            f64_type.fn_type(&[self.context.i64_type().into()], false),
+        );
+        let start = param(function, 0)?.into_int_value();
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let now = self.clock_nanos(clock_monotonic(self.module))?;
+        let nanos = self.builder.build_int_sub(now, start, "nanos")?;
+        let nanos = self
+            .builder
+            .build_signed_int_to_float(nanos, f64_type, "nanos")?;
+        let millis = self.builder.build_float_div(
+            nanos,
+            f64_type.const_float(NANOS_PER_MILLI as f64),
+            "millis",
+        )?;
+        self.builder.build_return(Some(&millis))?;
+        Ok(function)
+    }
+
+    /// `void venti_sleep_ms(i64 millis)`: pauses the calling thread for `millis`
+    /// milliseconds, or not at all when `millis` is not positive.
+    fn sleep_ms(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let millis_per_second = i64_type.const_int(NANOS_PER_SECOND / NANOS_PER_MILLI, false);
+        let function = self.declare(
+            "venti_sleep_ms",
+            self.context.void_type().fn_type(&[i64_type.into()], false),
+        );
+        let millis = param(function, 0)?.into_int_value();
+        let timespec_type = self
+            .context
+            .struct_type(&[i64_type.into(), i64_type.into()], false);
+        let nanosleep = self.libc("nanosleep")?;
+        let entry = self.block(function, "entry");
+        let sleep = self.block(function, "sleep");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let timespec = self.builder.build_alloca(timespec_type, "timespec")?;
+        let is_positive = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            millis,
+            i64_type.const_zero(),
+            "is_positive",
+        )?;
+        self.builder
+            .build_conditional_branch(is_positive, sleep, done)?;
+
+        self.builder.position_at_end(sleep);
+        let seconds = self
+            .builder
+            .build_int_signed_div(millis, millis_per_second, "seconds")?;
+        let rest = self
+            .builder
+            .build_int_signed_rem(millis, millis_per_second, "rest")?;
+        let nanos = self.builder.build_int_mul(
+            rest,
+            i64_type.const_int(NANOS_PER_MILLI, false),
+            "nanos",
+        )?;
+        let seconds_ptr =
+            self.builder
+                .build_struct_gep(timespec_type, timespec, 0, "seconds_ptr")?;
+        self.builder.build_store(seconds_ptr, seconds)?;
+        let nanos_ptr = self
+            .builder
+            .build_struct_gep(timespec_type, timespec, 1, "nanos_ptr")?;
+        self.builder.build_store(nanos_ptr, nanos)?;
+        self.builder.build_call(
+            nanosleep,
+            &[timespec.into(), self.ptr_type().const_null().into()],
+            "",
+        )?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `venti.str venti_str_from_c(ptr text)`: a copy of the C string `text`, or `nothing`
+    /// for a null pointer.
+    fn str_from_c(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let string_type = string_type(self.context);
+        let function = self.declare(
+            "venti_str_from_c",
+            string_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let text = param(function, 0)?.into_pointer_value();
+        let strlen = self.libc("strlen")?;
+        let copy = self.helper("venti_str_copy")?;
+        let entry = self.block(function, "entry");
+        let present = self.block(function, "present");
+        let missing = self.block(function, "missing");
+
+        self.builder.position_at_end(entry);
+        let is_null = self.builder.build_is_null(text, "is_null")?;
+        self.builder
+            .build_conditional_branch(is_null, missing, present)?;
+
+        self.builder.position_at_end(present);
+        let len = self.call(strlen, &[text.into()], "len")?.into_int_value();
+        let data = self
+            .call(copy, &[text.into(), len.into()], "data")?
+            .into_pointer_value();
+        let string = self.string_value(data, len)?;
+        self.builder.build_return(Some(&string))?;
+
+        self.builder.position_at_end(missing);
+        self.builder.build_return(Some(&string_type.const_zero()))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_arg_count()`: how many arguments the program was given, not counting
+    /// its own name.
+    fn arg_count(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare("venti_arg_count", i64_type.fn_type(&[], false));
+        let (argc, _) = process_args(self.context, self.module);
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let argc = self
+            .builder
+            .build_load(i64_type, argc, "argc")?
+            .into_int_value();
+        // Entries run by the REPL or an `Engine` have no `main` to store a name
+        let count = self
+            .builder
+            .build_int_sub(argc, i64_type.const_int(1, false), "count")?;
+        let has_name = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            argc,
+            i64_type.const_zero(),
+            "has_name",
+        )?;
+        let count = self
+            .builder
+            .build_select(has_name, count, i64_type.const_zero(), "count")?;
+        self.builder.build_return(Some(&count))?;
+        Ok(function)
+    }
+
+    /// `venti.str venti_arg(i64 index)`: argument `index` of the program, counting from 0
+    /// after its own name, or `nothing` past the last one.
+    fn arg(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let string_type = string_type(self.context);
+        let function = self.declare("venti_arg", string_type.fn_type(&[i64_type.into()], false));
+        let index = param(function, 0)?.into_int_value();
+        let (_, argv) = process_args(self.context, self.module);
+        let arg_count = self.helper("venti_arg_count")?;
+        let from_c = self.helper("venti_str_from_c")?;
+        let entry = self.block(function, "entry");
+        let present = self.block(function, "present");
+        let missing = self.block(function, "missing");
+
+        self.builder.position_at_end(entry);
+        let count = self.call(arg_count, &[], "count")?.into_int_value();
+        // Unsigned, a negative index is above every count
+        let in_range =
+            self.builder
+                .build_int_compare(IntPredicate::ULT, index, count, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, present, missing)?;
+
+        self.builder.position_at_end(present);
+        let argv = self
+            .builder
+            .build_load(self.ptr_type(), argv, "argv")?
+            .into_pointer_value();
+        let position =
+            self.builder
+                .build_int_add(index, i64_type.const_int(1, false), "position")?;
+        let arg_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.ptr_type(), argv, &[position], "arg_ptr")?
+        };
+        let text = self.builder.build_load(self.ptr_type(), arg_ptr, "text")?;
+        let arg = self.call(from_c, &[text.into()], "arg")?;
+        self.builder.build_return(Some(&arg))?;
+
+        self.builder.position_at_end(missing);
+        self.builder.build_return(Some(&string_type.const_zero()))?;
+        Ok(function)
+    }
+
+    /// `ptr venti_args()`: a new string vector holding the arguments of the program, after
+    /// its own name.
+    fn args(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare("venti_args", self.ptr_type().fn_type(&[], false));
+        let arg_count = self.helper("venti_arg_count")?;
+        let arg = self.helper("venti_arg")?;
+        let new = self.helper("venti_strvec_new")?;
+        let entry = self.block(function, "entry");
+        let loop_block = self.block(function, "loop");
+        let body = self.block(function, "body");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let count = self.call(arg_count, &[], "count")?.into_int_value();
+        let header = self
+            .call(new, &[count.into()], "header")?
+            .into_pointer_value();
+        let slots_field = self.string_vector_field(header, 1, "slots_field")?;
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?
+            .into_pointer_value();
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let has_next =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, index_value, count, "has_next")?;
+        self.builder
+            .build_conditional_branch(has_next, body, done)?;
+
+        self.builder.position_at_end(body);
+        let text = self.call(arg, &[index_value.into()], "text")?;
+        self.string_vector_store(slots, index_value, text)?;
+        let next = self
+            .builder
+            .build_int_add(index_value, i64_type.const_int(1, false), "next")?;
+        self.builder.build_unconditional_branch(loop_block)?;
+        index.add_incoming(&[(&i64_type.const_zero(), entry), (&next, body)]);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(Some(&header))?;
+        Ok(function)
+    }
+
+    /// `venti.str venti_env(ptr name)`: the value of the environment variable `name`, or
+    /// `nothing` if it is not set.
+    fn env(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_env",
+            string_type(self.context).fn_type(&[self.ptr_type().into()], false),
+        );
+        let name = param(function, 0)?.into_pointer_value();
+        let getenv = self.libc("getenv")?;
+        let from_c = self.helper("venti_str_from_c")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let name = self.c_text(name)?;
+        let value = self.call(getenv, &[name.into()], "value")?;
+        let value = self.call(from_c, &[value.into()], "env")?;
+        self.builder.build_return(Some(&value))?;
+        Ok(function)
+    }
+
+    /// Returns the exit status of the last command `exec` ran, which every module shares.
+    /// Each module defines it weakly, so one definition is kept.
+    fn exec_status_global(&self) -> PointerValue<'ctx> {
+        let global = self.module.get_global(EXEC_STATUS).unwrap_or_else(|| {
+            let i64_type = self.context.i64_type();
+            let global = self.module.add_global(i64_type, None, EXEC_STATUS);
+            global.set_linkage(Linkage::WeakAny);
+            global.set_initializer(&i64_type.const_zero());
+            global
+        });
+        global.as_pointer_value()
+    }
+
+    /// `venti.str venti_exec(ptr command)`: runs `command` with the shell and returns what
+    /// it wrote to stdout, keeping its exit status for `venti_exec_status`; a command the
+    /// shell cannot be started for is a runtime error.
+    fn exec(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_exec",
+            string_type(self.context).fn_type(&[self.ptr_type().into()], false),
+        );
+        let command = param(function, 0)?.into_pointer_value();
+        let fflush = self.libc("fflush")?;
+        let popen = self.libc("popen")?;
+        let pclose = self.libc("pclose")?;
+        let fread = self.libc("fread")?;
+        let malloc = self.libc("malloc")?;
+        let realloc = self.libc("realloc")?;
+        let free = self.libc("free")?;
+        let copy = self.helper("venti_str_copy")?;
+        let entry = self.block(function, "entry");
+        let started = self.block(function, "started");
+        let read = self.block(function, "read");
+        let check_room = self.block(function, "check_room");
+        let grow = self.block(function, "grow");
+        let close = self.block(function, "close");
+        let failed = self.block(function, "failed");
+
+        self.builder.position_at_end(entry);
+        let buffer_slot = self.builder.build_alloca(self.ptr_type(), "buffer_slot")?;
+        let len_slot = self.builder.build_alloca(i64_type, "len_slot")?;
+        let capacity_slot = self.builder.build_alloca(i64_type, "capacity_slot")?;
+        // What the program printed so far comes before anything the command writes
+        self.builder
+            .build_call(fflush, &[self.ptr_type().const_null().into()], "")?;
+        let command = self.c_text(command)?;
+        let mode = self.builder.build_global_string_ptr("r", "mode")?;
+        let pipe = self
+            .call(
+                popen,
+                &[command.into(), mode.as_pointer_value().into()],
+                "pipe",
+            )?
+            .into_pointer_value();
+        let is_missing = self.builder.build_is_null(pipe, "is_missing")?;
+        self.builder
+            .build_conditional_branch(is_missing, failed, started)?;
+
+        // The output so far lives in a malloc'd buffer that doubles when it fills up
+        self.builder.position_at_end(started);
+        let initial_capacity = i64_type.const_int(4096, false);
+        let buffer = self.call(malloc, &[initial_capacity.into()], "buffer")?;
+        self.builder.build_store(buffer_slot, buffer)?;
+        self.builder.build_store(len_slot, i64_type.const_zero())?;
+        self.builder.build_store(capacity_slot, initial_capacity)?;
+        self.builder.build_unconditional_branch(read)?;
+
+        self.builder.position_at_end(read);
+        let buffer = self
+            .builder
+            .build_load(self.ptr_type(), buffer_slot, "buffer")?
+            .into_pointer_value();
+        let len = self
+            .builder
+            .build_load(i64_type, len_slot, "len")?
+            .into_int_value();
+        let capacity = self
+            .builder
+            .build_load(i64_type, capacity_slot, "capacity")?
+            .into_int_value();
+        let end = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), buffer, &[len], "end")?
+        };
+        let room = self.builder.build_int_sub(capacity, len, "room")?;
+        let read_len = self
+            .call(
+                fread,
+                &[
+                    end.into(),
+                    i64_type.const_int(1, false).into(),
+                    room.into(),
+                    pipe.into(),
+                ],
+                "read_len",
+            )?
+            .into_int_value();
+        let len = self.builder.build_int_add(len, read_len, "len")?;
+        self.builder.build_store(len_slot, len)?;
+        // fread only comes back short at the end of the output
+        let is_done = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            read_len,
+            i64_type.const_zero(),
+            "is_done",
+        )?;
+        self.builder
+            .build_conditional_branch(is_done, close, check_room)?;
+
+        self.builder.position_at_end(check_room);
+        let is_full = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, len, capacity, "is_full")?;
+        self.builder.build_conditional_branch(is_full, grow, read)?;
+
+        self.builder.position_at_end(grow);
+        let capacity =
+            self.builder
+                .build_int_mul(capacity, i64_type.const_int(2, false), "new_capacity")?;
+        let buffer = self.call(realloc, &[buffer.into(), capacity.into()], "grown")?;
+        self.builder.build_store(buffer_slot, buffer)?;
+        self.builder.build_store(capacity_slot, capacity)?;
+        self.builder.build_unconditional_branch(read)?;
+
+        // A command killed by a signal exits with 128 plus the signal, as in the shell
+        self.builder.position_at_end(close);
+        let status = self
+            .call(pclose, &[pipe.into()], "status")?
+            .into_int_value();
+        let signal = self
+            .builder
+            .build_and(status, i32_type.const_int(0x7f, false), "signal")?;
+        let code =
+            self.builder
+                .build_right_shift(status, i32_type.const_int(8, false), false, "code")?;
+        let code = self
+            .builder
+            .build_and(code, i32_type.const_int(0xff, false), "code")?;
+        let killed_code =
+            self.builder
+                .build_int_add(signal, i32_type.const_int(128, false), "killed_code")?;
+        let is_exited = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            signal,
+            i32_type.const_zero(),
+            "is_exited",
+        )?;
+        let code = self
+            .builder
+            .build_select(is_exited, code, killed_code, "exit_code")?
+            .into_int_value();
+        let code = self
+            .builder
+            .build_int_z_extend(code, i64_type, "exit_code")?;
+        self.builder.build_atomicrmw(
+            AtomicRMWBinOp::Xchg,
+            self.exec_status_global(),
+            code,
+            AtomicOrdering::SequentiallyConsistent,
+        )?;
+        let buffer = self
+            .builder
+            .build_load(self.ptr_type(), buffer_slot, "buffer")?
+            .into_pointer_value();
+        let data = self
+            .call(copy, &[buffer.into(), len.into()], "data")?
+            .into_pointer_value();
+        self.builder.build_call(free, &[buffer.into()], "")?;
+        let output = self.string_value(data, len)?;
+        self.builder.build_return(Some(&output))?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error("could not run command '%s'", &[command.into()])?;
+        Ok(function)
+    }
+
+    /// `i64 venti_exec_status()`: the exit status of the last command `venti_exec` ran, or 0
+    /// before the first.
+    fn exec_status(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare("venti_exec_status", i64_type.fn_type(&[], false));
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        // Adding zero reads the status atomically
+        let status = self.builder.build_atomicrmw(
+            AtomicRMWBinOp::Add,
+            self.exec_status_global(),
+            i64_type.const_zero(),
+            AtomicOrdering::SequentiallyConsistent,
+        )?;
+        self.builder.build_return(Some(&status))?;
+        Ok(function)
+    }
+
+    /// Returns a pointer `offset` bytes past `base`.
+    fn byte_offset(
+        &self,
+        base: PointerValue<'ctx>,
+        offset: u64,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let offset = self.context.i64_type().const_int(offset, false);
+        Ok(unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), base, &[offset], name)?
+        })
+    }
+
+    /// `i64 venti_tcp_open(ptr host, i64 port, i1 passive)`: tries each address `host`, a
+    /// name or an address, resolves to, and returns a socket connected to `port` on the
+    /// first that accepts, or -1 if none does. A passive open, with a null `host`, instead
+    /// returns a socket listening on `port` of every local address.
+    fn tcp_open(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_tcp_open",
+            i64_type.fn_type(
+                &[
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                    self.context.bool_type().into(),
+                ],
+                false,
+            ),
+        );
+        let host = param(function, 0)?.into_pointer_value();
+        let port = param(function, 1)?.into_int_value();
+        let passive = param(function, 2)?.into_int_value();
+        let signal = self.libc("signal")?;
+        let snprintf = self.libc("snprintf")?;
+        let getaddrinfo = self.libc("getaddrinfo")?;
+        let freeaddrinfo = self.libc("freeaddrinfo")?;
+        let socket = self.libc("socket")?;
+        let connect = self.libc("connect")?;
+        let setsockopt = self.libc("setsockopt")?;
+        let bind = self.libc("bind")?;
+        let listen = self.libc("listen")?;
+        let close = self.libc("close")?;
+        let entry = self.block(function, "entry");
+        let resolved = self.block(function, "resolved");
+        let try_block = self.block(function, "try");
+        let attempt = self.block(function, "attempt");
+        let opened = self.block(function, "opened");
+        let connect_block = self.block(function, "connect");
+        let bind_block = self.block(function, "bind");
+        let listen_block = self.block(function, "listen");
+        let refused = self.block(function, "refused");
+        let next = self.block(function, "next");
+        let connected = self.block(function, "connected");
+        let exhausted = self.block(function, "exhausted");
+        let failed = self.block(function, "failed");
+
+        self.builder.position_at_end(entry);
+        // A peer closing the connection makes sending fail instead of ending the program
+        let ignore = self.builder.build_int_to_ptr(
+            i64_type.const_int(1, false),
+            self.ptr_type(),
+            "ignore",
+        )?;
+        self.builder.build_call(
+            signal,
+            &[i32_type.const_int(SIGPIPE, false).into(), ignore.into()],
+            "",
+        )?;
+        let one = self.builder.build_alloca(i32_type, "one")?;
+        self.builder
+            .build_store(one, i32_type.const_int(1, false))?;
+        let service_size = 24;
+        let service = self
+            .builder
+            .build_alloca(i8_type.array_type(service_size), "service")?;
+        let port_format = self.builder.build_global_string_ptr("%ld", "port_format")?;
+        self.builder.build_call(
+            snprintf,
+            &[
+                service.into(),
+                i64_type.const_int(service_size.into(), false).into(),
+                port_format.as_pointer_value().into(),
+                port.into(),
+            ],
+            "",
+        )?;
+        // An i64 array keeps the hints aligned for their pointers
+        let hints = self
+            .builder
+            .build_alloca(i64_type.array_type((ADDRINFO_SIZE / 8) as u32), "hints")?;
+        self.builder.build_memset(
+            hints,
+            8,
+            i8_type.const_zero(),
+            i64_type.const_int(ADDRINFO_SIZE, false),
+        )?;
+        let flags = self.builder.build_select(
+            passive,
+            i32_type.const_int(AI_PASSIVE, false),
+            i32_type.const_zero(),
+            "flags",
+        )?;
+        self.builder.build_store(hints, flags)?;
+        let hints_socktype = self.byte_offset(hints, 8, "hints_socktype")?;
+        self.builder
+            .build_store(hints_socktype, i32_type.const_int(SOCK_STREAM, false))?;
+        let addresses_slot = self
+            .builder
+            .build_alloca(self.ptr_type(), "addresses_slot")?;
+        let status = self
+            .call(
+                getaddrinfo,
+                &[
+                    host.into(),
+                    service.into(),
+                    hints.into(),
+                    addresses_slot.into(),
+                ],
+                "status",
+            )?
+            .into_int_value();
+        let is_resolved = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            status,
+            i32_type.const_zero(),
+            "is_resolved",
+        )?;
+        self.builder
+            .build_conditional_branch(is_resolved, resolved, failed)?;
+
+        self.builder.position_at_end(resolved);
+        let addresses = self
+            .builder
+            .build_load(self.ptr_type(), addresses_slot, "addresses")?
+            .into_pointer_value();
+        self.builder.build_unconditional_branch(try_block)?;
+
+        self.builder.position_at_end(try_block);
+        let address = self.builder.build_phi(self.ptr_type(), "address")?;
+        let address_value = address.as_basic_value().into_pointer_value();
+        let is_end = self.builder.build_is_null(address_value, "is_end")?;
+        self.builder
+            .build_conditional_branch(is_end, exhausted, attempt)?;
+
+        self.builder.position_at_end(attempt);
+        let mut fields = Vec::new();
+        for (offset, name) in [
+            (4, "family"),
+            (8, "socktype"),
+            (12, "protocol"),
+            (16, "address_len"),
+        ] {
+            let field = self.byte_offset(address_value, offset, name)?;
+            fields.push(self.builder.build_load(i32_type, field, name)?);
+        }
+        let sockaddr_field =
+            self.byte_offset(address_value, addrinfo_addr(self.module), "sockaddr_field")?;
+        let sockaddr = self
+            .builder
+            .build_load(self.ptr_type(), sockaddr_field, "sockaddr")?;
+        let fd = self
+            .call(
+                socket,
+                &[fields[0].into(), fields[1].into(), fields[2].into()],
+                "fd",
+            )?
+            .into_int_value();
+        let is_open = self.builder.build_int_compare(
+            IntPredicate::SGE,
+            fd,
+            i32_type.const_zero(),
+            "is_open",
+        )?;
+        self.builder
+            .build_conditional_branch(is_open, opened, next)?;
+
+        self.builder.position_at_end(opened);
+        self.builder
+            .build_conditional_branch(passive, bind_block, connect_block)?;
+
+        self.builder.position_at_end(connect_block);
+        let result = self
+            .call(
+                connect,
+                &[fd.into(), sockaddr.into(), fields[3].into()],
+                "result",
+            )?
+            .into_int_value();
+        let is_connected = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            result,
+            i32_type.const_zero(),
+            "is_connected",
+        )?;
+        self.builder
+            .build_conditional_branch(is_connected, connected, refused)?;
+
+        // The port can be listened on again straight after a program that did exits
+        self.builder.position_at_end(bind_block);
+        self.builder.build_call(
+            setsockopt,
+            &[
+                fd.into(),
+                i32_type.const_int(sol_socket(self.module), false).into(),
+                i32_type.const_int(so_reuseaddr(self.module), false).into(),
+                one.into(),
+                i32_type.const_int(4, false).into(),
+            ],
+            "",
+        )?;
+        let result = self
+            .call(
+                bind,
+                &[fd.into(), sockaddr.into(), fields[3].into()],
+                "result",
+            )?
+            .into_int_value();
+        let is_bound = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            result,
+            i32_type.const_zero(),
+            "is_bound",
+        )?;
+        self.builder
+            .build_conditional_branch(is_bound, listen_block, refused)?;
+
+        self.builder.position_at_end(listen_block);
+        let result = self
+            .call(
+                listen,
+                &[fd.into(), i32_type.const_int(LISTEN_BACKLOG, false).into()],
+                "result",
+            )?
+            .into_int_value();
+        let is_listening = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            result,
+            i32_type.const_zero(),
+            "is_listening",
+        )?;
+        self.builder
+            .build_conditional_branch(is_listening, connected, refused)?;
+
+        self.builder.position_at_end(refused);
+        self.builder.build_call(close, &[fd.into()], "")?;
+        self.builder.build_unconditional_branch(next)?;
+
+        self.builder.position_at_end(next);
+        let next_field = self.byte_offset(address_value, ADDRINFO_NEXT, "next_field")?;
+        let next_address = self
+            .builder
+            .build_load(self.ptr_type(), next_field, "next_address")?;
+        self.builder.build_unconditional_branch(try_block)?;
+        address.add_incoming(&[(&addresses, resolved), (&next_address, next)]);
+
+        self.builder.position_at_end(connected);
+        self.builder
+            .build_call(freeaddrinfo, &[addresses.into()], "")?;
+        let fd = self.builder.build_int_s_extend(fd, i64_type, "socket")?;
+        self.builder.build_return(Some(&fd))?;
+
+        self.builder.position_at_end(exhausted);
+        self.builder
+            .build_call(freeaddrinfo, &[addresses.into()], "")?;
+        self.builder.build_unconditional_branch(failed)?;
+
+        self.builder.position_at_end(failed);
+        self.builder
+            .build_return(Some(&i64_type.const_all_ones()))?;
+        Ok(function)
+    }
+
+    /// Checks the socket `venti_tcp_open` returned, raising a runtime error with `message`
+    /// and its `args` if it is -1, and returns it otherwise.
+    fn opened_socket(
+        &self,
+        function: FunctionValue<'ctx>,
+        socket: IntValue<'ctx>,
+        message: &str,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) -> Result<(), VentiError> {
+        let is_open = self.builder.build_int_compare(
+            IntPredicate::SGE,
+            socket,
+            socket.get_type().const_zero(),
+            "is_open",
+        )?;
+        let open = self.block(function, "open");
+        let failed = self.block(function, "failed");
+        self.builder
+            .build_conditional_branch(is_open, open, failed)?;
+
+        self.builder.position_at_end(open);
+        self.builder.build_return(Some(&socket))?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error(message, args)
+    }
+
+    /// `i64 venti_tcp_connect(ptr host, i64 port)`: opens a TCP connection to `port` on
+    /// `host` and returns its socket; a host that cannot be resolved or reached is a
+    /// runtime error.
+    fn tcp_connect(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_tcp_connect",
+            i64_type.fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let host = param(function, 0)?.into_pointer_value();
+        let port = param(function, 1)?.into_int_value();
+        let open = self.helper("venti_tcp_open")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let host = self.c_text(host)?;
+        let socket = self
+            .call(
+                open,
+                &[
+                    host.into(),
+                    port.into(),
+                    self.context.bool_type().const_zero().into(),
+                ],
+                "socket",
+            )?
+            .into_int_value();
+        self.opened_socket(
+            function,
+            socket,
+            "could not connect to %s:%ld",
+            &[host.into(), port.into()],
+        )?;
+        Ok(function)
+    }
+
+    /// `i64 venti_tcp_listen(i64 port)`: opens a socket listening for TCP connections to
+    /// `port` and returns it; a port that cannot be listened on is a runtime error.
+    fn tcp_listen(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_tcp_listen",
+            i64_type.fn_type(&[i64_type.into()], false),
+        );
+        let port = param(function, 0)?.into_int_value();
+        let open = self.helper("venti_tcp_open")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let socket = self
+            .call(
+                open,
+                &[
+                    self.ptr_type().const_null().into(),
+                    port.into(),
+                    self.context.bool_type().const_all_ones().into(),
+                ],
+                "socket",
+            )?
+            .into_int_value();
+        self.opened_socket(
+            function,
+            socket,
+            "could not listen on port %ld",
+            &[port.into()],
+        )?;
+        Ok(function)
+    }
+
+    /// `i64 venti_tcp_accept(i64 listener)`: waits for a connection to the listening socket
+    /// `listener` and returns its socket; a socket that is not listening is a runtime error.
+    fn tcp_accept(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_tcp_accept",
+            i64_type.fn_type(&[i64_type.into()], false),
+        );
+        let listener = param(function, 0)?.into_int_value();
+        let accept = self.libc("accept")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let fd = self
+            .builder
+            .build_int_truncate(listener, i32_type, "listener")?;
+        let null = self.ptr_type().const_null();
+        let socket = self
+            .call(accept, &[fd.into(), null.into(), null.into()], "fd")?
+            .into_int_value();
+        let socket = self
+            .builder
+            .build_int_s_extend(socket, i64_type, "socket")?;
+        self.opened_socket(function, socket, "could not accept a connection", &[])?;
+        Ok(function)
+    }
+
+    /// `i64 venti_tcp_port(i64 listener)`: returns the port the socket `listener` listens
+    /// on, which is the one the system picked when it was asked to listen on port 0; a
+    /// socket that is not open is a runtime error.
+    fn tcp_port(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_tcp_port",
+            i64_type.fn_type(&[i64_type.into()], false),
+        );
+        let listener = param(function, 0)?.into_int_value();
+        let getsockname = self.libc("getsockname")?;
+        let entry = self.block(function, "entry");
+        let named = self.block(function, "named");
+        let failed = self.block(function, "failed");
+
+        self.builder.position_at_end(entry);
+        let fd = self.builder.build_int_truncate(listener, i32_type, "fd")?;
+        // An i64 array keeps the address aligned for any family
+        let address = self.builder.build_alloca(
+            i64_type.array_type((SOCKADDR_STORAGE_SIZE / 8) as u32),
+            "address",
+        )?;
+        let address_len = self.builder.build_alloca(i32_type, "address_len")?;
+        self.builder.build_store(
+            address_len,
+            i32_type.const_int(SOCKADDR_STORAGE_SIZE, false),
+        )?;
+        let status = self
+            .call(
+                getsockname,
+                &[fd.into(), address.into(), address_len.into()],
+                "status",
+            )?
+            .into_int_value();
+        let is_named = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            status,
+            i32_type.const_zero(),
+            "is_named",
+        )?;
+        self.builder
+            .build_conditional_branch(is_named, named, failed)?;
+
+        self.builder.position_at_end(named);
+        // The port is big-endian whatever the machine's byte order
+        let high_field = self.byte_offset(address, SOCKADDR_PORT, "high_field")?;
+        let low_field = self.byte_offset(address, SOCKADDR_PORT + 1, "low_field")?;
+        let high = self
+            .builder
+            .build_load(i8_type, high_field, "high")?
+            .into_int_value();
+        let low = self
+            .builder
+            .build_load(i8_type, low_field, "low")?
+            .into_int_value();
+        let high = self.builder.build_int_z_extend(high, i64_type, "high")?;
+        let low = self.builder.build_int_z_extend(low, i64_type, "low")?;
+        let high = self
+            .builder
+            .build_left_shift(high, i64_type.const_int(8, false), "high")?;
+        let port = self.builder.build_or(high, low, "port")?;
+        self.builder.build_return(Some(&port))?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error("could not find the port of socket %ld", &[listener.into()])?;
+        Ok(function)
+    }
+
+    /// `void venti_tcp_send(i64 socket, ptr data, i64 len)`: sends `len` bytes over a
+    /// connection; a connection that was closed or failed is a runtime error.
+    fn tcp_send(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_tcp_send",
+            self.context.void_type().fn_type(
+                &[i64_type.into(), self.ptr_type().into(), i64_type.into()],
+                false,
+            ),
+        );
+        let socket = param(function, 0)?.into_int_value();
+        let data = param(function, 1)?.into_pointer_value();
+        let len = param(function, 2)?.into_int_value();
+        let send = self.libc("send")?;
+        let entry = self.block(function, "entry");
+        let check = self.block(function, "check");
+        let send_block = self.block(function, "send");
+        let sent_some = self.block(function, "sent_some");
+        let done = self.block(function, "done");
+        let failed = self.block(function, "failed");
+
+        self.builder.position_at_end(entry);
+        let fd = self.builder.build_int_truncate(socket, i32_type, "fd")?;
+        self.builder.build_unconditional_branch(check)?;
+
+        // `send` may take only part of the bytes, so it is called until all are sent
+        self.builder.position_at_end(check);
+        let sent = self.builder.build_phi(i64_type, "sent")?;
+        let sent_value = sent.as_basic_value().into_int_value();
+        let is_done =
+            self.builder
+                .build_int_compare(IntPredicate::SGE, sent_value, len, "is_done")?;
+        self.builder
+            .build_conditional_branch(is_done, done, send_block)?;
+
+        self.builder.position_at_end(send_block);
+        let rest = self.builder.build_int_sub(len, sent_value, "rest")?;
+        let start = unsafe {
+            self.builder.build_in_bounds_gep(
+                self.context.i8_type(),
+                data,
+                &[sent_value],
+                "start",
+            )?
+        };
+        let count = self
+            .call(
+                send,
+                &[
+                    fd.into(),
+                    start.into(),
+                    rest.into(),
+                    i32_type.const_zero().into(),
+                ],
+                "count",
+            )?
+            .into_int_value();
+        let is_sent = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            count,
+            i64_type.const_zero(),
+            "is_sent",
+        )?;
+        self.builder
+            .build_conditional_branch(is_sent, sent_some, failed)?;
+
+        self.builder.position_at_end(sent_some);
+        let now_sent = self.builder.build_int_add(sent_value, count, "now_sent")?;
+        self.builder.build_unconditional_branch(check)?;
+        sent.add_incoming(&[(&i64_type.const_zero(), entry), (&now_sent, sent_some)]);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error("could not send: the connection is closed", &[])?;
+        Ok(function)
+    }
+
+    /// `void venti_tcp_read_all(i64 socket, ptr writer)`: writes everything received over a
+    /// connection to the `venti.writer` at `writer`, until the peer closes it; a connection
+    /// that fails is a runtime error.
+    fn tcp_read_all(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_tcp_read_all",
+            self.context
+                .void_type()
+                .fn_type(&[i64_type.into(), self.ptr_type().into()], false),
+        );
+        let socket = param(function, 0)?.into_int_value();
+        let writer = param(function, 1)?.into_pointer_value();
+        let recv = self.libc("recv")?;
+        let write_raw = self.helper("venti_write_raw")?;
+        let entry = self.block(function, "entry");
+        let read = self.block(function, "read");
+        let append = self.block(function, "append");
+        let check_end = self.block(function, "check_end");
+        let done = self.block(function, "done");
+        let failed = self.block(function, "failed");
+
+        self.builder.position_at_end(entry);
+        let chunk_size = TCP_CHUNK_SIZE;
+        let chunk = self
+            .builder
+            .build_alloca(self.context.i8_type().array_type(chunk_size), "chunk")?;
+        let fd = self.builder.build_int_truncate(socket, i32_type, "fd")?;
+        self.builder.build_unconditional_branch(read)?;
+
+        self.builder.position_at_end(read);
+        let count = self
+            .call(
+                recv,
+                &[
+                    fd.into(),
+                    chunk.into(),
+                    i64_type.const_int(chunk_size.into(), false).into(),
+                    i32_type.const_zero().into(),
+                ],
+                "count",
+            )?
+            .into_int_value();
+        let has_data = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            count,
+            i64_type.const_zero(),
+            "has_data",
+        )?;
+        self.builder
+            .build_conditional_branch(has_data, append, check_end)?;
+
+        self.builder.position_at_end(append);
+        self.builder
+            .build_call(write_raw, &[writer.into(), chunk.into(), count.into()], "")?;
+        self.builder.build_unconditional_branch(read)?;
+
+        // `recv` returns 0 once the peer has closed the connection, and -1 if it failed
+        self.builder.position_at_end(check_end);
+        let is_closed = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            count,
+            i64_type.const_zero(),
+            "is_closed",
+        )?;
+        self.builder
+            .build_conditional_branch(is_closed, done, failed)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error("could not receive: the connection failed", &[])?;
+        Ok(function)
+    }
+
+    /// `venti.str venti_tcp_recv(i64 socket)`: returns the next bytes received over a
+    /// connection, at most `TCP_CHUNK_SIZE` of them, or nothing once the peer has closed it;
+    /// a connection that fails is a runtime error.
+    fn tcp_recv(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let string_type = string_type(self.context);
+        let function = self.declare(
+            "venti_tcp_recv",
+            string_type.fn_type(&[i64_type.into()], false),
+        );
+        let socket = param(function, 0)?.into_int_value();
+        let recv = self.libc("recv")?;
+        let copy = self.helper("venti_str_copy")?;
+        let entry = self.block(function, "entry");
+        let received = self.block(function, "received");
+        let check_end = self.block(function, "check_end");
+        let closed = self.block(function, "closed");
+        let failed = self.block(function, "failed");
+
+        self.builder.position_at_end(entry);
+        let chunk = self
+            .builder
+            .build_alloca(self.context.i8_type().array_type(TCP_CHUNK_SIZE), "chunk")?;
+        let fd = self.builder.build_int_truncate(socket, i32_type, "fd")?;
+        let count = self
+            .call(
+                recv,
+                &[
+                    fd.into(),
+                    chunk.into(),
+                    i64_type.const_int(TCP_CHUNK_SIZE.into(), false).into(),
+                    i32_type.const_zero().into(),
+                ],
+                "count",
+            )?
+            .into_int_value();
+        let has_data = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            count,
+            i64_type.const_zero(),
+            "has_data",
+        )?;
+        self.builder
+            .build_conditional_branch(has_data, received, check_end)?;
+
+        self.builder.position_at_end(received);
+        let data = self
+            .call(copy, &[chunk.into(), count.into()], "data")?
+            .into_pointer_value();
+        let string = self.string_value(data, count)?;
+        self.builder.build_return(Some(&string))?;
+
+        self.builder.position_at_end(check_end);
+        let is_closed = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            count,
+            i64_type.const_zero(),
+            "is_closed",
+        )?;
+        self.builder
+            .build_conditional_branch(is_closed, closed, failed)?;
+
+        self.builder.position_at_end(closed);
+        self.builder.build_return(Some(&string_type.const_zero()))?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error("could not receive: the connection failed", &[])?;
+        Ok(function)
+    }
+
+    /// `void venti_tcp_close(i64 socket)`: closes a connection or listening socket.
+    fn tcp_close(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_tcp_close",
+            self.context.void_type().fn_type(&[i64_type.into()], false),
+        );
+        let socket = param(function, 0)?.into_int_value();
+        let close = self.libc("close")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let fd = self
+            .builder
+            .build_int_truncate(socket, self.context.i32_type(), "fd")?;
+        self.builder.build_call(close, &[fd.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `venti.str venti_http(ptr method, ptr url, ptr body, i64 len)`: sends an HTTP
+    /// request with `method` and the `len` bytes of `body` to `url`, which must be an
+    /// `http://` URL, and returns the body of the response, whatever its status; a URL that
+    /// cannot be requested or a response that is not HTTP is a runtime error.
+    fn http(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_http",
+            string_type(self.context).fn_type(
+                &[
+                    self.ptr_type().into(),
+                    self.ptr_type().into(),
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                ],
+                false,
+            ),
+        );
+        let method = param(function, 0)?.into_pointer_value();
+        let url = param(function, 1)?.into_pointer_value();
+        let body = param(function, 2)?.into_pointer_value();
+        let body_len = param(function, 3)?.into_int_value();
+        let strncmp = self.libc("strncmp")?;
+        let strcspn = self.libc("strcspn")?;
+        let strtoll = self.libc("strtoll")?;
+        let strstr = self.libc("strstr")?;
+        let snprintf = self.libc("snprintf")?;
+        let malloc = self.libc("malloc")?;
+        let free = self.libc("free")?;
+        let close = self.libc("close")?;
+        let connect = self.helper("venti_tcp_connect")?;
+        let send = self.helper("venti_tcp_send")?;
+        let read_all = self.helper("venti_tcp_read_all")?;
+        let write_raw = self.helper("venti_write_raw")?;
+        let copy = self.helper("venti_str_copy")?;
+        let entry = self.block(function, "entry");
+        let unsupported = self.block(function, "unsupported");
+        let request = self.block(function, "request");
+        let found = self.block(function, "found");
+        let invalid = self.block(function, "invalid");
+
+        self.builder.position_at_end(entry);
+        let writer = self
+            .builder
+            .build_alloca(writer_type(self.context), "writer")?;
+        let url = self.c_text(url)?;
+        let scheme = "http://";
+        let scheme_text = self.builder.build_global_string_ptr(scheme, "scheme")?;
+        let scheme_len = i64_type.const_int(scheme.len() as u64, false);
+        let order = self
+            .call(
+                strncmp,
+                &[
+                    url.into(),
+                    scheme_text.as_pointer_value().into(),
+                    scheme_len.into(),
+                ],
+                "order",
+            )?
+            .into_int_value();
+        let is_http = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            order,
+            i32_type.const_zero(),
+            "is_http",
+        )?;
+        self.builder
+            .build_conditional_branch(is_http, request, unsupported)?;
+
+        self.builder.position_at_end(unsupported);
+        self.runtime_error(
+            "only http:// URLs can be requested, not '%s'",
+            &[url.into()],
+        )?;
+
+        // The authority, the host and port, runs from the scheme up to the path
+        self.builder.position_at_end(request);
+        let authority = self.byte_offset(url, scheme.len() as u64, "authority")?;
+        let path_stop = self.builder.build_global_string_ptr("/", "path_stop")?;
+        let authority_len = self
+            .call(
+                strcspn,
+                &[authority.into(), path_stop.as_pointer_value().into()],
+                "authority_len",
+            )?
+            .into_int_value();
+        let host_stop = self.builder.build_global_string_ptr(":/", "host_stop")?;
+        let host_len = self
+            .call(
+                strcspn,
+                &[authority.into(), host_stop.as_pointer_value().into()],
+                "host_len",
+            )?
+            .into_int_value();
+        let host_size = self.add_const(host_len, 1, "host_size")?;
+        let host = self
+            .call(malloc, &[host_size.into()], "host")?
+            .into_pointer_value();
+        self.builder.build_memcpy(host, 1, authority, 1, host_len)?;
+        let host_end = unsafe {
+            self.builder
+                .build_in_bounds_gep(i8_type, host, &[host_len], "host_end")?
+        };
+        self.builder.build_store(host_end, i8_type.const_zero())?;
+        let after_host = unsafe {
+            self.builder
+                .build_in_bounds_gep(i8_type, authority, &[host_len], "after_host")?
+        };
+        let separator = self
+            .builder
+            .build_load(i8_type, after_host, "separator")?
+            .into_int_value();
+        let has_port = self.is_one_of(separator, b":", "has_port")?;
+        let port_text = self.byte_offset(after_host, 1, "port_text")?;
+        let given_port = self
+            .call(
+                strtoll,
+                &[
+                    port_text.into(),
+                    self.ptr_type().const_null().into(),
+                    i32_type.const_int(10, false).into(),
+                ],
+                "given_port",
+            )?
+            .into_int_value();
+        let port = self.builder.build_select(
+            has_port,
+            given_port,
+            i64_type.const_int(80, false),
+            "port",
+        )?;
+        let path_start = unsafe {
+            self.builder
+                .build_in_bounds_gep(i8_type, authority, &[authority_len], "path_start")?
+        };
+        let path_first = self
+            .builder
+            .build_load(i8_type, path_start, "path_first")?
+            .into_int_value();
+        let has_path = self.builder.build_int_compare(
+            IntPredicate::NE,
+            path_first,
+            i8_type.const_zero(),
+            "has_path",
+        )?;
+        let root = self.builder.build_global_string_ptr("/", "root")?;
+        let path =
+            self.builder
+                .build_select(has_path, path_start, root.as_pointer_value(), "path")?;
+        let socket = self
+            .call(connect, &[host.into(), port.into()], "socket")?
+            .into_int_value();
+        self.builder.build_call(free, &[host.into()], "")?;
+
+        let format = self
+            .builder
+            .build_global_string_ptr(HTTP_REQUEST, "request_format")?
+            .as_pointer_value();
+        let authority_width =
+            self.builder
+                .build_int_truncate(authority_len, i32_type, "authority_width")?;
+        let format_args: [BasicMetadataValueEnum; 6] = [
+            format.into(),
+            method.into(),
+            path.into(),
+            authority_width.into(),
+            authority.into(),
+            body_len.into(),
+        ];
+        let mut measure_args = vec![
+            self.ptr_type().const_null().into(),
+            i64_type.const_zero().into(),
+        ];
+        measure_args.extend_from_slice(&format_args);
+        let header_len = self
+            .call(snprintf, &measure_args, "header_len")?
+            .into_int_value();
+        let header_len = self
+            .builder
+            .build_int_s_extend(header_len, i64_type, "header_len")?;
+        let header_size = self.add_const(header_len, 1, "header_size")?;
+        let header = self
+            .call(malloc, &[header_size.into()], "header")?
+            .into_pointer_value();
+        let mut write_args = vec![header.into(), header_size.into()];
+        write_args.extend_from_slice(&format_args);
+        self.builder.build_call(snprintf, &write_args, "")?;
+        self.builder
+            .build_call(send, &[socket.into(), header.into(), header_len.into()], "")?;
+        self.builder.build_call(free, &[header.into()], "")?;
+        self.builder
+            .build_call(send, &[socket.into(), body.into(), body_len.into()], "")?;
+
+        // HTTP/1.0 has the server close the connection after the response, which ends it
+        self.builder
+            .build_store(writer, writer_type(self.context).const_zero())?;
+        self.builder
+            .build_call(read_all, &[socket.into(), writer.into()], "")?;
+        let fd = self.builder.build_int_truncate(socket, i32_type, "fd")?;
+        self.builder.build_call(close, &[fd.into()], "")?;
+        // Writing nothing still makes room for the NUL `strstr` needs
+        let empty = self.builder.build_global_string_ptr("", "empty")?;
+        self.builder.build_call(
+            write_raw,
+            &[
+                writer.into(),
+                empty.as_pointer_value().into(),
+                i64_type.const_zero().into(),
+            ],
+            "",
+        )?;
+        let data_field = self.writer_field(writer, 0, "data_field")?;
+        let len_field = self.writer_field(writer, 1, "len_field")?;
+        let data = self
+            .builder
+            .build_load(self.ptr_type(), data_field, "data")?
+            .into_pointer_value();
+        let len = self
+            .builder
+            .build_load(i64_type, len_field, "len")?
+            .into_int_value();
+        let end = unsafe {
+            self.builder
+                .build_in_bounds_gep(i8_type, data, &[len], "end")?
+        };
+        self.builder.build_store(end, i8_type.const_zero())?;
+        let blank_line = self
+            .builder
+            .build_global_string_ptr("\r\n\r\n", "blank_line")?;
+        let headers_end = self
+            .call(
+                strstr,
+                &[data.into(), blank_line.as_pointer_value().into()],
+                "headers_end",
+            )?
+            .into_pointer_value();
+        let is_missing = self.builder.build_is_null(headers_end, "is_missing")?;
+        self.builder
+            .build_conditional_branch(is_missing, invalid, found)?;
+
+        self.builder.position_at_end(found);
+        let content = self.byte_offset(headers_end, 4, "content")?;
+        let content_address =
+            self.builder
+                .build_ptr_to_int(content, i64_type, "content_address")?;
+        let data_address = self
+            .builder
+            .build_ptr_to_int(data, i64_type, "data_address")?;
+        let headers_len =
+            self.builder
+                .build_int_sub(content_address, data_address, "headers_len")?;
+        let content_len = self
+            .builder
+            .build_int_sub(len, headers_len, "content_len")?;
+        let text = self
+            .call(copy, &[content.into(), content_len.into()], "text")?
+            .into_pointer_value();
+        self.builder.build_call(free, &[data.into()], "")?;
+        let text = self.string_value(text, content_len)?;
+        self.builder.build_return(Some(&text))?;
+
+        self.builder.position_at_end(invalid);
+        self.runtime_error("invalid HTTP response from '%s'", &[url.into()])?;
+        Ok(function)
+    }
+
+    /// Loads the byte at `pos` of `text`.
+    fn text_byte(
+        &self,
+        text: PointerValue<'ctx>,
+        pos: IntValue<'ctx>,
+        name: &str,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let byte_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(i8_type, text, &[pos], "byte_ptr")?
+        };
+        Ok(self
+            .builder
+            .build_load(i8_type, byte_ptr, name)?
+            .into_int_value())
+    }
+
+    /// Returns whether the i8 `byte` is one of `bytes`.
+    fn is_one_of(
+        &self,
+        byte: IntValue<'ctx>,
+        bytes: &[u8],
+        name: &str,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let mut found = self.context.bool_type().const_zero();
+        for &expected in bytes {
+            let is_expected = self.builder.build_int_compare(
+                IntPredicate::EQ,
+                byte,
+                i8_type.const_int(expected as u64, false),
+                name,
+            )?;
+            found = self.builder.build_or(found, is_expected, name)?;
+        }
+        Ok(found)
+    }
+
+    /// Returns whether `value` is at least `low` and below `high`.
+    fn is_within(
+        &self,
+        value: IntValue<'ctx>,
+        low: u64,
+        high: u64,
+        name: &str,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        let int_type = value.get_type();
+        // Compared unsigned, anything below `low` wraps around above the range
+        let offset = self
+            .builder
+            .build_int_sub(value, int_type.const_int(low, false), name)?;
+        Ok(self.builder.build_int_compare(
+            IntPredicate::ULT,
+            offset,
+            int_type.const_int(high - low, false),
+            name,
+        )?)
+    }
+
+    /// Returns `value` plus the constant `n`.
+    fn add_const(
+        &self,
+        value: IntValue<'ctx>,
+        n: u64,
+        name: &str,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_int_add(value, value.get_type().const_int(n, false), name)?)
+    }
+
+    /// Declares a JSON scanner helper, `i64 <name>(ptr text, i64 pos)`.
+    fn declare_json_scan(&self, name: &str) -> FunctionValue<'ctx> {
+        let i64_type = self.context.i64_type();
+        self.declare(
+            name,
+            i64_type.fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        )
+    }
+
+    /// `i64 venti_json_space(ptr text, i64 pos)`: the position after the JSON whitespace
+    /// starting at `pos`.
+    fn json_space(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare_json_scan("venti_json_space");
+        let text = param(function, 0)?.into_pointer_value();
+        let start = param(function, 1)?.into_int_value();
+        let entry = self.block(function, "entry");
+        let loop_block = self.block(function, "loop");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let pos = self.builder.build_phi(i64_type, "pos")?;
+        let pos_value = pos.as_basic_value().into_int_value();
+        let byte = self.text_byte(text, pos_value, "byte")?;
+        let is_space = self.is_one_of(byte, b" \t\n\r", "is_space")?;
+        let next = self.add_const(pos_value, 1, "next")?;
+        self.builder
+            .build_conditional_branch(is_space, loop_block, done)?;
+        pos.add_incoming(&[(&start, entry), (&next, loop_block)]);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(Some(&pos_value))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_json_digits(ptr text, i64 pos)`: the position after the decimal digits
+    /// starting at `pos`.
+    fn json_digits(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare_json_scan("venti_json_digits");
+        let text = param(function, 0)?.into_pointer_value();
+        let start = param(function, 1)?.into_int_value();
+        let entry = self.block(function, "entry");
+        let loop_block = self.block(function, "loop");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let pos = self.builder.build_phi(i64_type, "pos")?;
+        let pos_value = pos.as_basic_value().into_int_value();
+        let byte = self.text_byte(text, pos_value, "byte")?;
+        let is_digit = self.is_within(byte, b'0' as u64, b'9' as u64 + 1, "is_digit")?;
+        let next = self.add_const(pos_value, 1, "next")?;
+        self.builder
+            .build_conditional_branch(is_digit, loop_block, done)?;
+        pos.add_incoming(&[(&start, entry), (&next, loop_block)]);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(Some(&pos_value))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_json_skip_string(ptr text, i64 pos)`: the end of the string literal
+    /// starting with the quote at `pos`, or -1 if it is not a valid one.
+    fn json_skip_string(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare_json_scan("venti_json_skip_string");
+        let text = param(function, 0)?.into_pointer_value();
+        let start = param(function, 1)?.into_int_value();
+        let isxdigit = self.libc("isxdigit")?;
+        let entry = self.block(function, "entry");
+        let loop_block = self.block(function, "loop");
+        let closed = self.block(function, "closed");
+        let other = self.block(function, "other");
+        let plain = self.block(function, "plain");
+        let escape = self.block(function, "escape");
+        let simple = self.block(function, "simple");
+        let check_unicode = self.block(function, "check_unicode");
+        let hex_blocks = (0..4)
+            .map(|_| self.block(function, "hex_digit"))
+            .collect::<Vec<_>>();
+        let unicode = self.block(function, "unicode");
+        let invalid = self.block(function, "invalid");
+
+        self.builder.position_at_end(entry);
+        let pos_slot = self.builder.build_alloca(i64_type, "pos_slot")?;
+        let after_quote = self.add_const(start, 1, "after_quote")?;
+        self.builder.build_store(pos_slot, after_quote)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let pos = self
+            .builder
+            .build_load(i64_type, pos_slot, "pos")?
+            .into_int_value();
+        let byte = self.text_byte(text, pos, "byte")?;
+        self.builder.build_switch(
+            byte,
+            other,
+            &[
+                (i8_type.const_int(b'"' as u64, false), closed),
+                (i8_type.const_int(b'\\' as u64, false), escape),
+            ],
+        )?;
+
+        self.builder.position_at_end(closed);
+        let end = self.add_const(pos, 1, "end")?;
+        self.builder.build_return(Some(&end))?;
+
+        // Control characters, and the end of the text, must not appear in a string
+        self.builder.position_at_end(other);
+        let is_control = self.builder.build_int_compare(
+            IntPredicate::ULT,
+            byte,
+            i8_type.const_int(0x20, false),
+            "is_control",
+        )?;
+        self.builder
+            .build_conditional_branch(is_control, invalid, plain)?;
+
+        self.builder.position_at_end(plain);
+        let next = self.add_const(pos, 1, "next")?;
+        self.builder.build_store(pos_slot, next)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(escape);
+        let escaped_pos = self.add_const(pos, 1, "escaped_pos")?;
+        let escaped = self.text_byte(text, escaped_pos, "escaped")?;
+        let is_simple = self.is_one_of(escaped, b"\"\\/bfnrt", "is_simple")?;
+        self.builder
+            .build_conditional_branch(is_simple, simple, check_unicode)?;
+
+        self.builder.position_at_end(simple);
+        let next = self.add_const(pos, 2, "next")?;
+        self.builder.build_store(pos_slot, next)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(check_unicode);
+        let is_unicode = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            escaped,
+            i8_type.const_int(b'u' as u64, false),
+            "is_unicode",
+        )?;
+        self.builder
+            .build_conditional_branch(is_unicode, hex_blocks[0], invalid)?;
+
+        // Each digit is checked before the next is read, so none is read past the end
+        for (index, &block) in hex_blocks.iter().enumerate() {
+            self.builder.position_at_end(block);
+            let digit_pos = self.add_const(pos, index as u64 + 2, "digit_pos")?;
+            let digit = self.text_byte(text, digit_pos, "digit")?;
+            let digit = self
+                .builder
+                .build_int_z_extend(digit, i32_type, "digit32")?;
+            let is_hex = self
+                .call(isxdigit, &[digit.into()], "is_hex")?
+                .into_int_value();
+            let is_hex = self.builder.build_int_compare(
+                IntPredicate::NE,
+                is_hex,
+                i32_type.const_zero(),
+                "is_hex",
+            )?;
+            let next_block = hex_blocks.get(index + 1).copied().unwrap_or(unicode);
+            self.builder
+                .build_conditional_branch(is_hex, next_block, invalid)?;
+        }
+
+        self.builder.position_at_end(unicode);
+        let next = self.add_const(pos, 6, "next")?;
+        self.builder.build_store(pos_slot, next)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(invalid);
+        self.builder
+            .build_return(Some(&i64_type.const_all_ones()))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_json_skip_number(ptr text, i64 pos)`: the end of the number starting at
+    /// `pos`, or -1 if it is not a valid one.
+    fn json_skip_number(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare_json_scan("venti_json_skip_number");
+        let text = param(function, 0)?.into_pointer_value();
+        let pos = param(function, 1)?.into_int_value();
+        let digits = self.helper("venti_json_digits")?;
+        let entry = self.block(function, "entry");
+        let integer = self.block(function, "integer");
+        let fraction = self.block(function, "fraction");
+        let check_exponent = self.block(function, "check_exponent");
+        let exponent = self.block(function, "exponent");
+        let exponent_done = self.block(function, "exponent_done");
+        let done = self.block(function, "done");
+        let invalid = self.block(function, "invalid");
+
+        self.builder.position_at_end(entry);
+        let first = self.text_byte(text, pos, "first")?;
+        let is_negative = self.is_one_of(first, b"-", "is_negative")?;
+        let after_sign = self.add_const(pos, 1, "after_sign")?;
+        let start = self
+            .builder
+            .build_select(is_negative, after_sign, pos, "start")?
+            .into_int_value();
+        let end = self
+            .call(digits, &[text.into(), start.into()], "end")?
+            .into_int_value();
+        // One or more digits, without leading zeros
+        let is_empty = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, end, start, "is_empty")?;
+        let lead = self.text_byte(text, start, "lead")?;
+        let is_zero = self.is_one_of(lead, b"0", "is_zero")?;
+        let second = self.add_const(start, 1, "second")?;
+        let is_long = self
+            .builder
+            .build_int_compare(IntPredicate::UGT, end, second, "is_long")?;
+        let is_padded = self.builder.build_and(is_zero, is_long, "is_padded")?;
+        let is_bad = self.builder.build_or(is_empty, is_padded, "is_bad")?;
+        self.builder
+            .build_conditional_branch(is_bad, invalid, integer)?;
+
+        self.builder.position_at_end(integer);
+        let next = self.text_byte(text, end, "next")?;
+        let is_dot = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            next,
+            i8_type.const_int(b'.' as u64, false),
+            "is_dot",
+        )?;
+        self.builder
+            .build_conditional_branch(is_dot, fraction, check_exponent)?;
+
+        self.builder.position_at_end(fraction);
+        let after_dot = self.add_const(end, 1, "after_dot")?;
+        let fraction_end = self
+            .call(digits, &[text.into(), after_dot.into()], "fraction_end")?
+            .into_int_value();
+        let has_digits = self.builder.build_int_compare(
+            IntPredicate::NE,
+            fraction_end,
+            after_dot,
+            "has_digits",
+        )?;
+        self.builder
+            .build_conditional_branch(has_digits, check_exponent, invalid)?;
+
+        self.builder.position_at_end(check_exponent);
+        let mantissa_end = self.builder.build_phi(i64_type, "mantissa_end")?;
+        mantissa_end.add_incoming(&[(&end, integer), (&fraction_end, fraction)]);
+        let mantissa_end = mantissa_end.as_basic_value().into_int_value();
+        let next = self.text_byte(text, mantissa_end, "next")?;
+        let is_exponent = self.is_one_of(next, b"eE", "is_exponent")?;
+        self.builder
+            .build_conditional_branch(is_exponent, exponent, done)?;
+
+        self.builder.position_at_end(exponent);
+        let after_e = self.add_const(mantissa_end, 1, "after_e")?;
+        let sign = self.text_byte(text, after_e, "sign")?;
+        let is_sign = self.is_one_of(sign, b"+-", "is_sign")?;
+        let after_sign = self.add_const(after_e, 1, "after_sign")?;
+        let exponent_start = self
+            .builder
+            .build_select(is_sign, after_sign, after_e, "exponent_start")?
+            .into_int_value();
+        let exponent_end = self
+            .call(
+                digits,
+                &[text.into(), exponent_start.into()],
+                "exponent_end",
+            )?
+            .into_int_value();
+        let has_digits = self.builder.build_int_compare(
+            IntPredicate::NE,
+            exponent_end,
+            exponent_start,
+            "has_digits",
+        )?;
+        self.builder
+            .build_conditional_branch(has_digits, exponent_done, invalid)?;
+
+        self.builder.position_at_end(exponent_done);
+        self.builder.build_return(Some(&exponent_end))?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(Some(&mantissa_end))?;
+
+        self.builder.position_at_end(invalid);
+        self.builder
+            .build_return(Some(&i64_type.const_all_ones()))?;
+        Ok(function)
+    }
+
+    /// Returns whether the text at `pos` of `text` starts with `literal`.
+    fn starts_with(
+        &self,
+        text: PointerValue<'ctx>,
+        pos: IntValue<'ctx>,
+        literal: &str,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let strncmp = self.libc("strncmp")?;
+        let rest = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), text, &[pos], "rest")?
+        };
+        let literal_ptr = self.builder.build_global_string_ptr(literal, "literal")?;
+        // strncmp stops at the end of the text, so it never reads past it
+        let order = self
+            .call(
+                strncmp,
+                &[
+                    rest.into(),
+                    literal_ptr.as_pointer_value().into(),
+                    i64_type.const_int(literal.len() as u64, false).into(),
+                ],
+                "order",
+            )?
+            .into_int_value();
+        Ok(self.builder.build_int_compare(
+            IntPredicate::EQ,
+            order,
+            self.context.i32_type().const_zero(),
+            "is_literal",
+        )?)
+    }
+
+    /// `i64 venti_json_skip(ptr text, i64 pos, i64 depth)`: the end of the value starting
+    /// at `pos`, inside `depth` arrays and objects, or -1 if there is no valid value there.
+    fn json_skip(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_json_skip",
+            i64_type.fn_type(
+                &[self.ptr_type().into(), i64_type.into(), i64_type.into()],
+                false,
+            ),
+        );
+        let text = param(function, 0)?.into_pointer_value();
+        let pos = param(function, 1)?.into_int_value();
+        let depth = param(function, 2)?.into_int_value();
+        let space = self.helper("venti_json_space")?;
+        let skip_string = self.helper("venti_json_skip_string")?;
+        let skip_number = self.helper("venti_json_skip_number")?;
+        let entry = self.block(function, "entry");
+        let string = self.block(function, "string");
+        let number = self.block(function, "number");
+        let literal = self.block(function, "literal");
+        let four = self.block(function, "four");
+        let check_false = self.block(function, "check_false");
+        let container = self.block(function, "container");
+        let open_block = self.block(function, "open");
+        let empty = self.block(function, "empty");
+        let member = self.block(function, "member");
+        let key = self.block(function, "key");
+        let key_string = self.block(function, "key_string");
+        let after_key = self.block(function, "after_key");
+        let colon = self.block(function, "colon");
+        let element = self.block(function, "element");
+        let after_element = self.block(function, "after_element");
+        let closed = self.block(function, "closed");
+        let check_comma = self.block(function, "check_comma");
+        let comma = self.block(function, "comma");
+        let invalid = self.block(function, "invalid");
+
+        self.builder.position_at_end(entry);
+        let pos_slot = self.builder.build_alloca(i64_type, "pos_slot")?;
+        let open = self.text_byte(text, pos, "open")?;
+        let mut cases = vec![
+            (i8_type.const_int(b'[' as u64, false), container),
+            (i8_type.const_int(b'{' as u64, false), container),
+            (i8_type.const_int(b'"' as u64, false), string),
+            (i8_type.const_int(b'-' as u64, false), number),
+        ];
+        cases.extend((b'0'..=b'9').map(|digit| (i8_type.const_int(digit as u64, false), number)));
+        self.builder.build_switch(open, literal, &cases)?;
+
+        self.builder.position_at_end(string);
+        let end = self.call(skip_string, &[text.into(), pos.into()], "end")?;
+        self.builder.build_return(Some(&end))?;
+
+        self.builder.position_at_end(number);
+        let end = self.call(skip_number, &[text.into(), pos.into()], "end")?;
+        self.builder.build_return(Some(&end))?;
+
+        self.builder.position_at_end(literal);
+        let is_true = self.starts_with(text, pos, "true")?;
+        let is_null = self.starts_with(text, pos, "null")?;
+        let is_four = self.builder.build_or(is_true, is_null, "is_four")?;
+        self.builder
+            .build_conditional_branch(is_four, four, check_false)?;
+
+        self.builder.position_at_end(four);
+        let end = self.add_const(pos, 4, "end")?;
+        self.builder.build_return(Some(&end))?;
+
+        self.builder.position_at_end(check_false);
+        let is_false = self.starts_with(text, pos, "false")?;
+        let end = self.add_const(pos, 5, "end")?;
+        let end = self
+            .builder
+            .build_select(is_false, end, i64_type.const_all_ones(), "end")?;
+        self.builder.build_return(Some(&end))?;
+
+        // Nesting is bounded, so a document cannot recurse deep enough to overflow the stack
+        self.builder.position_at_end(container);
+        let is_deep = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            depth,
+            i64_type.const_int(JSON_MAX_DEPTH, false),
+            "is_deep",
+        )?;
+        self.builder
+            .build_conditional_branch(is_deep, invalid, open_block)?;
+
+        self.builder.position_at_end(open_block);
+        let is_object = self.is_one_of(open, b"{", "is_object")?;
+        let close = self.builder.build_select(
+            is_object,
+            i8_type.const_int(b'}' as u64, false),
+            i8_type.const_int(b']' as u64, false),
+            "close",
+        )?;
+        let close = close.into_int_value();
+        let inside = self.add_const(pos, 1, "inside")?;
+        let first = self
+            .call(space, &[text.into(), inside.into()], "first")?
+            .into_int_value();
+        self.builder.build_store(pos_slot, first)?;
+        let byte = self.text_byte(text, first, "byte")?;
+        let is_empty = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, byte, close, "is_empty")?;
+        self.builder
+            .build_conditional_branch(is_empty, empty, member)?;
+
+        self.builder.position_at_end(empty);
+        let end = self.add_const(first, 1, "end")?;
+        self.builder.build_return(Some(&end))?;
+
+        self.builder.position_at_end(member);
+        self.builder
+            .build_conditional_branch(is_object, key, element)?;
+
+        self.builder.position_at_end(key);
+        let key_pos = self
+            .builder
+            .build_load(i64_type, pos_slot, "key_pos")?
+            .into_int_value();
+        let byte = self.text_byte(text, key_pos, "byte")?;
+        let is_quote = self.is_one_of(byte, b"\"", "is_quote")?;
+        self.builder
+            .build_conditional_branch(is_quote, key_string, invalid)?;
+
+        self.builder.position_at_end(key_string);
+        let key_end = self
+            .call(skip_string, &[text.into(), key_pos.into()], "key_end")?
+            .into_int_value();
+        let is_bad = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            key_end,
+            i64_type.const_zero(),
+            "is_bad",
+        )?;
+        self.builder
+            .build_conditional_branch(is_bad, invalid, after_key)?;
+
+        self.builder.position_at_end(after_key);
+        let key_end = self
+            .call(space, &[text.into(), key_end.into()], "key_end")?
+            .into_int_value();
+        let byte = self.text_byte(text, key_end, "byte")?;
+        let is_colon = self.is_one_of(byte, b":", "is_colon")?;
+        self.builder
+            .build_conditional_branch(is_colon, colon, invalid)?;
+
+        self.builder.position_at_end(colon);
+        let after_colon = self.add_const(key_end, 1, "after_colon")?;
+        let value_pos = self.call(space, &[text.into(), after_colon.into()], "value_pos")?;
+        self.builder.build_store(pos_slot, value_pos)?;
+        self.builder.build_unconditional_branch(element)?;
+
+        self.builder.position_at_end(element);
+        let value_pos = self.builder.build_load(i64_type, pos_slot, "value_pos")?;
+        let inner_depth = self.add_const(depth, 1, "inner_depth")?;
+        let value_end = self
+            .call(
+                function,
+                &[text.into(), value_pos.into(), inner_depth.into()],
+                "value_end",
+            )?
+            .into_int_value();
+        let is_bad = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            value_end,
+            i64_type.const_zero(),
+            "is_bad",
+        )?;
+        self.builder
+            .build_conditional_branch(is_bad, invalid, after_element)?;
+
+        self.builder.position_at_end(after_element);
+        let value_end = self
+            .call(space, &[text.into(), value_end.into()], "value_end")?
+            .into_int_value();
+        let byte = self.text_byte(text, value_end, "byte")?;
+        let is_close = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, byte, close, "is_close")?;
+        self.builder
+            .build_conditional_branch(is_close, closed, check_comma)?;
+
+        self.builder.position_at_end(closed);
+        let end = self.add_const(value_end, 1, "end")?;
+        self.builder.build_return(Some(&end))?;
+
+        self.builder.position_at_end(check_comma);
+        let is_comma = self.is_one_of(byte, b",", "is_comma")?;
+        self.builder
+            .build_conditional_branch(is_comma, comma, invalid)?;
+
+        self.builder.position_at_end(comma);
+        let after_comma = self.add_const(value_end, 1, "after_comma")?;
+        let next = self.call(space, &[text.into(), after_comma.into()], "next")?;
+        self.builder.build_store(pos_slot, next)?;
+        self.builder.build_unconditional_branch(member)?;
+
+        self.builder.position_at_end(invalid);
+        self.builder
+            .build_return(Some(&i64_type.const_all_ones()))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_json_check(ptr data, i64 len)`: the position the value of the document
+    /// starts at; a document that is not valid JSON is a runtime error.
+    fn json_check(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_json_check",
+            i64_type.fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let space = self.helper("venti_json_space")?;
+        let skip = self.helper("venti_json_skip")?;
+        let entry = self.block(function, "entry");
+        let check_end = self.block(function, "check_end");
+        let valid = self.block(function, "valid");
+        let invalid = self.block(function, "invalid");
+
+        self.builder.position_at_end(entry);
+        let text = self.c_text(data)?;
+        let start = self
+            .call(space, &[text.into(), i64_type.const_zero().into()], "start")?
+            .into_int_value();
+        let end = self
+            .call(
+                skip,
+                &[text.into(), start.into(), i64_type.const_zero().into()],
+                "end",
+            )?
+            .into_int_value();
+        let is_bad = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            end,
+            i64_type.const_zero(),
+            "is_bad",
+        )?;
+        self.builder
+            .build_conditional_branch(is_bad, invalid, check_end)?;
+
+        // Only whitespace may follow the value
+        self.builder.position_at_end(check_end);
+        let rest = self
+            .call(space, &[text.into(), end.into()], "rest")?
+            .into_int_value();
+        let is_whole = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, rest, len, "is_whole")?;
+        self.builder
+            .build_conditional_branch(is_whole, valid, invalid)?;
+
+        self.builder.position_at_end(valid);
+        self.builder.build_return(Some(&start))?;
+
+        self.builder.position_at_end(invalid);
+        self.runtime_error("invalid JSON", &[])?;
+        Ok(function)
+    }
+
+    /// Builds the value of the four hex digits at `pos` of `text`, as an i64.
+    fn json_hex(
+        &self,
+        text: PointerValue<'ctx>,
+        pos: IntValue<'ctx>,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let tolower = self.libc("tolower")?;
+        let mut code = i64_type.const_zero();
+        for index in 0..4 {
+            let digit_pos = self.add_const(pos, index, "digit_pos")?;
+            let digit = self.text_byte(text, digit_pos, "digit")?;
+            let digit = self
+                .builder
+                .build_int_z_extend(digit, i32_type, "digit32")?;
+            let digit = self
+                .call(tolower, &[digit.into()], "lower")?
+                .into_int_value();
+            let digit = self
+                .builder
+                .build_int_z_extend(digit, i64_type, "digit64")?;
+            let is_decimal = self.builder.build_int_compare(
+                IntPredicate::ULE,
+                digit,
+                i64_type.const_int(b'9' as u64, false),
+                "is_decimal",
+            )?;
+            let decimal = self.builder.build_int_sub(
+                digit,
+                i64_type.const_int(b'0' as u64, false),
+                "decimal",
+            )?;
+            let letter = self.builder.build_int_sub(
+                digit,
+                i64_type.const_int(b'a' as u64 - 10, false),
+                "letter",
+            )?;
+            let value = self
+                .builder
+                .build_select(is_decimal, decimal, letter, "value")?
+                .into_int_value();
+            let shifted =
+                self.builder
+                    .build_int_mul(code, i64_type.const_int(16, false), "shifted")?;
+            code = self.builder.build_int_add(shifted, value, "code")?;
+        }
+        Ok(code)
+    }
+
+    /// Stores the low byte of `value` at the end of `out`, whose length is in `len_slot`.
+    fn push_byte(
+        &self,
+        out: PointerValue<'ctx>,
+        len_slot: PointerValue<'ctx>,
+        value: IntValue<'ctx>,
+    ) -> Result<(), VentiError> {
+        let i64_type = self.context.i64_type();
+        let len = self
+            .builder
+            .build_load(i64_type, len_slot, "len")?
+            .into_int_value();
+        let byte_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), out, &[len], "byte_ptr")?
+        };
+        let byte =
+            self.builder
+                .build_int_truncate_or_bit_cast(value, self.context.i8_type(), "byte")?;
+        self.builder.build_store(byte_ptr, byte)?;
+        let len = self.add_const(len, 1, "len")?;
+        self.builder.build_store(len_slot, len)?;
+        Ok(())
+    }
+
+    /// `i64 venti_json_unescape(ptr text, i64 pos, ptr out)`: decodes the valid string
+    /// literal starting with the quote at `pos` into `out`, which must have room for the
+    /// literal, and returns the length of the result; a lone surrogate becomes U+FFFD.
+    fn json_unescape(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_json_unescape",
+            i64_type.fn_type(
+                &[
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                    self.ptr_type().into(),
+                ],
+                false,
+            ),
+        );
+        let text = param(function, 0)?.into_pointer_value();
+        let start = param(function, 1)?.into_int_value();
+        let out = param(function, 2)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let loop_block = self.block(function, "loop");
+        let plain = self.block(function, "plain");
+        let escape = self.block(function, "escape");
+        let simple = self.block(function, "simple");
+        let unicode = self.block(function, "unicode");
+        let pair = self.block(function, "pair");
+        let encode = self.block(function, "encode");
+        let one = self.block(function, "one");
+        let check_two = self.block(function, "check_two");
+        let two = self.block(function, "two");
+        let check_three = self.block(function, "check_three");
+        let three = self.block(function, "three");
+        let four = self.block(function, "four");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let pos_slot = self.builder.build_alloca(i64_type, "pos_slot")?;
+        let len_slot = self.builder.build_alloca(i64_type, "len_slot")?;
+        let after_quote = self.add_const(start, 1, "after_quote")?;
+        self.builder.build_store(pos_slot, after_quote)?;
+        self.builder.build_store(len_slot, i64_type.const_zero())?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let pos = self
+            .builder
+            .build_load(i64_type, pos_slot, "pos")?
+            .into_int_value();
+        let byte = self.text_byte(text, pos, "byte")?;
+        self.builder.build_switch(
+            byte,
+            plain,
+            &[
+                (i8_type.const_int(b'"' as u64, false), done),
+                (i8_type.const_int(b'\\' as u64, false), escape),
+            ],
+        )?;
+
+        self.builder.position_at_end(plain);
+        self.push_byte(out, len_slot, byte)?;
+        let next = self.add_const(pos, 1, "next")?;
+        self.builder.build_store(pos_slot, next)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(escape);
+        let escaped_pos = self.add_const(pos, 1, "escaped_pos")?;
+        let escaped = self.text_byte(text, escaped_pos, "escaped")?;
+        let after_escape = self.add_const(pos, 2, "after_escape")?;
+        let is_unicode = self.is_one_of(escaped, b"u", "is_unicode")?;
+        self.builder
+            .build_conditional_branch(is_unicode, unicode, simple)?;
+
+        // Any other escaped byte stands for itself
+        self.builder.position_at_end(simple);
+        let mut decoded = escaped;
+        for (letter, byte) in [
+            (b'b', 0x08),
+            (b'f', 0x0c),
+            (b'n', b'\n'),
+            (b'r', b'\r'),
+            (b't', b'\t'),
+        ] {
+            let is_letter = self.is_one_of(escaped, &[letter], "is_letter")?;
+            decoded = self
+                .builder
+                .build_select(
+                    is_letter,
+                    i8_type.const_int(byte as u64, false),
+                    decoded,
+                    "decoded",
+                )?
+                .into_int_value();
+        }
+        self.push_byte(out, len_slot, decoded)?;
+        self.builder.build_store(pos_slot, after_escape)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        // A high surrogate followed by the escape of a low one is a single character
+        self.builder.position_at_end(unicode);
+        let code = self.json_hex(text, after_escape)?;
+        let after_code = self.add_const(after_escape, 4, "after_code")?;
+        let is_high = self.is_within(code, 0xd800, 0xdc00, "is_high")?;
+        let next = self.text_byte(text, after_code, "next")?;
+        let is_backslash = self.is_one_of(next, b"\\", "is_backslash")?;
+        let after_backslash = self.add_const(after_code, 1, "after_backslash")?;
+        let next = self.text_byte(text, after_backslash, "next")?;
+        let is_u = self.is_one_of(next, b"u", "is_u")?;
+        let is_escape = self.builder.build_and(is_backslash, is_u, "is_escape")?;
+        let has_pair = self.builder.build_and(is_high, is_escape, "has_pair")?;
+        self.builder
+            .build_conditional_branch(has_pair, pair, encode)?;
+
+        self.builder.position_at_end(pair);
+        let low_pos = self.add_const(after_code, 2, "low_pos")?;
+        let low = self.json_hex(text, low_pos)?;
+        let is_low = self.is_within(low, 0xdc00, 0xe000, "is_low")?;
+        let high_bits =
+            self.builder
+                .build_int_sub(code, i64_type.const_int(0xd800, false), "high_bits")?;
+        let high_bits =
+            self.builder
+                .build_left_shift(high_bits, i64_type.const_int(10, false), "high_bits")?;
+        let low_bits =
+            self.builder
+                .build_int_sub(low, i64_type.const_int(0xdc00, false), "low_bits")?;
+        let combined = self
+            .builder
+            .build_int_add(high_bits, low_bits, "combined")?;
+        let combined = self.add_const(combined, 0x10000, "combined")?;
+        let paired_code = self
+            .builder
+            .build_select(is_low, combined, code, "paired_code")?;
+        let after_low = self.add_const(after_code, 6, "after_low")?;
+        let paired_end = self
+            .builder
+            .build_select(is_low, after_low, after_code, "paired_end")?;
+        self.builder.build_unconditional_branch(encode)?;
+
+        self.builder.position_at_end(encode);
+        let code_phi = self.builder.build_phi(i64_type, "code")?;
+        code_phi.add_incoming(&[(&code, unicode), (&paired_code, pair)]);
+        let end_phi = self.builder.build_phi(i64_type, "end")?;
+        end_phi.add_incoming(&[(&after_code, unicode), (&paired_end, pair)]);
+        self.builder
+            .build_store(pos_slot, end_phi.as_basic_value())?;
+        let code = code_phi.as_basic_value().into_int_value();
+        let is_surrogate = self.is_within(code, 0xd800, 0xe000, "is_surrogate")?;
+        let code = self
+            .builder
+            .build_select(
+                is_surrogate,
+                i64_type.const_int(0xfffd, false),
+                code,
+                "code",
+            )?
+            .into_int_value();
+        let is_one = self.builder.build_int_compare(
+            IntPredicate::ULT,
+            code,
+            i64_type.const_int(0x80, false),
+            "is_one",
+        )?;
+        self.builder
+            .build_conditional_branch(is_one, one, check_two)?;
+
+        self.builder.position_at_end(check_two);
+        let is_two = self.builder.build_int_compare(
+            IntPredicate::ULT,
+            code,
+            i64_type.const_int(0x800, false),
+            "is_two",
+        )?;
+        self.builder
+            .build_conditional_branch(is_two, two, check_three)?;
+
+        self.builder.position_at_end(check_three);
+        let is_three = self.builder.build_int_compare(
+            IntPredicate::ULT,
+            code,
+            i64_type.const_int(0x10000, false),
+            "is_three",
+        )?;
+        self.builder
+            .build_conditional_branch(is_three, three, four)?;
+
+        // UTF-8 puts the top bits in a lead byte marked with the length, then six bits
+        // in each continuation byte
+        for (block, lead, count) in [
+            (one, 0x00, 0),
+            (two, 0xc0, 1),
+            (three, 0xe0, 2),
+            (four, 0xf0, 3),
+        ] {
+            self.builder.position_at_end(block);
+            let top = self.builder.build_right_shift(
+                code,
+                i64_type.const_int(6 * count, false),
+                false,
+                "top",
+            )?;
+            let lead_byte = self
+                .builder
+                .build_or(top, i64_type.const_int(lead, false), "lead")?;
+            self.push_byte(out, len_slot, lead_byte)?;
+            for shift in (0..count).rev() {
+                let bits = self.builder.build_right_shift(
+                    code,
+                    i64_type.const_int(6 * shift, false),
+                    false,
+                    "bits",
+                )?;
+                let bits = self
+                    .builder
+                    .build_and(bits, i64_type.const_int(0x3f, false), "bits")?;
+                let continuation =
+                    self.builder
+                        .build_or(bits, i64_type.const_int(0x80, false), "continuation")?;
+                self.push_byte(out, len_slot, continuation)?;
+            }
+            self.builder.build_unconditional_branch(loop_block)?;
+        }
+
+        self.builder.position_at_end(done);
+        let len = self.builder.build_load(i64_type, len_slot, "len")?;
+        self.builder.build_return(Some(&len))?;
+        Ok(function)
+    }
+
+    /// Returns `len` bytes copied from `bytes` into a new string whose count marks it as
+    /// never freed, for the text a `venti.json` node holds.
+    fn immortal_copy(
+        &self,
+        bytes: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let data = self
+            .call(
+                self.helper("venti_str_copy")?,
+                &[bytes.into(), len.into()],
+                "data",
+            )?
+            .into_pointer_value();
+        let count = unsafe {
+            self.builder.build_in_bounds_gep(
+                i64_type,
+                data,
+                &[i64_type.const_all_ones()],
+                "count",
+            )?
+        };
+        self.builder
+            .build_store(count, i64_type.const_int(IMMORTAL_COUNT as u64, true))?;
+        Ok(data)
+    }
+
+    /// Returns a new `venti.json` node of `kind` holding `len` and `data`.
+    fn json_node(
+        &self,
+        kind: IntValue<'ctx>,
+        len: IntValue<'ctx>,
+        data: PointerValue<'ctx>,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let node_type = json_node_type(self.context);
+        let size = node_type
+            .size_of()
+            .ok_or_else(|| VentiError::CodegenError("JSON nodes have no size".to_string(), None))?;
+        let node = self
+            .call(self.libc("malloc")?, &[size.into()], "node")?
+            .into_pointer_value();
+        for (index, value) in [(0, kind.into()), (1, len.into()), (2, data.into())] {
+            let field = self
+                .builder
+                .build_struct_gep(node_type, node, index, "field")?;
+            self.builder.build_store::<BasicValueEnum>(field, value)?;
+        }
+        Ok(node)
+    }
+
+    /// Loads field `index` of the `venti.json` node behind `node`.
+    fn json_field(
+        &self,
+        node: PointerValue<'ctx>,
+        index: u32,
+        name: &str,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let node_type = json_node_type(self.context);
+        let field = self
+            .builder
+            .build_struct_gep(node_type, node, index, name)?;
+        let field_type = node_type.get_field_type_at_index(index).ok_or_else(|| {
+            VentiError::CodegenError("JSON nodes have 3 fields".to_string(), None)
+        })?;
+        Ok(self.builder.build_load(field_type, field, name)?)
+    }
+
+    /// Returns whether the `i64` `value` is the constant `n`.
+    fn is_const(
+        &self,
+        value: IntValue<'ctx>,
+        n: u64,
+        name: &str,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        Ok(self.builder.build_int_compare(
+            IntPredicate::EQ,
+            value,
+            value.get_type().const_int(n, false),
+            name,
+        )?)
+    }
+
+    /// Emits the code adding the node `item` to the items of the array or object
+    /// `venti_json_build` is reading, growing them when they are full.
+    fn json_push(
+        &self,
+        function: FunctionValue<'ctx>,
+        items_slot: PointerValue<'ctx>,
+        count_slot: PointerValue<'ctx>,
+        capacity_slot: PointerValue<'ctx>,
+        item: PointerValue<'ctx>,
+    ) -> Result<(), VentiError> {
+        let i64_type = self.context.i64_type();
+        let grow = self.block(function, "grow");
+        let store = self.block(function, "store");
+        let count = self
+            .builder
+            .build_load(i64_type, count_slot, "count")?
+            .into_int_value();
+        let capacity = self
+            .builder
+            .build_load(i64_type, capacity_slot, "capacity")?
+            .into_int_value();
+        let is_full =
+            self.builder
+                .build_int_compare(IntPredicate::EQ, count, capacity, "is_full")?;
+        self.builder
+            .build_conditional_branch(is_full, grow, store)?;
+
+        self.builder.position_at_end(grow);
+        let doubled =
+            self.builder
+                .build_int_mul(capacity, i64_type.const_int(2, false), "doubled")?;
+        let capacity = self.add_const(doubled, 8, "capacity")?;
+        let size = self
+            .builder
+            .build_int_mul(capacity, i64_type.const_int(8, false), "size")?;
+        let items = self
+            .builder
+            .build_load(self.ptr_type(), items_slot, "items")?;
+        let items = self.call(self.libc("realloc")?, &[items.into(), size.into()], "grown")?;
+        self.builder.build_store(items_slot, items)?;
+        self.builder.build_store(capacity_slot, capacity)?;
+        self.builder.build_unconditional_branch(store)?;
+
+        self.builder.position_at_end(store);
+        let items = self
+            .builder
+            .build_load(self.ptr_type(), items_slot, "items")?
+            .into_pointer_value();
+        let slot = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.ptr_type(), items, &[count], "slot")?
+        };
+        self.builder.build_store(slot, item)?;
+        let count = self.add_const(count, 1, "count")?;
+        self.builder.build_store(count_slot, count)?;
+        Ok(())
+    }
+
+    /// `ptr venti_json_build(ptr text, ptr pos)`: a new `venti.json` node for the valid
+    /// value starting at the position `pos` points to, which is moved to the value's end.
+    fn json_build(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_json_build",
+            self.ptr_type()
+                .fn_type(&[self.ptr_type().into(), self.ptr_type().into()], false),
+        );
+        let text = param(function, 0)?.into_pointer_value();
+        let pos = param(function, 1)?.into_pointer_value();
+        let space = self.helper("venti_json_space")?;
+        let skip_string = self.helper("venti_json_skip_string")?;
+        let skip_number = self.helper("venti_json_skip_number")?;
+        let unescape = self.helper("venti_json_unescape")?;
+        let malloc = self.libc("malloc")?;
+        let free = self.libc("free")?;
+        let entry = self.block(function, "entry");
+        let null = self.block(function, "null");
+        let true_block = self.block(function, "true");
+        let false_block = self.block(function, "false");
+        let number = self.block(function, "number");
+        let string = self.block(function, "string");
+        let container = self.block(function, "container");
+        let loop_block = self.block(function, "loop");
+        let member = self.block(function, "member");
+        let key = self.block(function, "key");
+        let value = self.block(function, "value");
+        let finish = self.block(function, "finish");
+
+        self.builder.position_at_end(entry);
+        let items_slot = self.builder.build_alloca(self.ptr_type(), "items_slot")?;
+        let count_slot = self.builder.build_alloca(i64_type, "count_slot")?;
+        let capacity_slot = self.builder.build_alloca(i64_type, "capacity_slot")?;
+        let start = self
+            .builder
+            .build_load(i64_type, pos, "start")?
+            .into_int_value();
+        let open = self.text_byte(text, start, "open")?;
+        self.builder.build_switch(
+            open,
+            number,
+            &[
+                (i8_type.const_int(b'n' as u64, false), null),
+                (i8_type.const_int(b't' as u64, false), true_block),
+                (i8_type.const_int(b'f' as u64, false), false_block),
+                (i8_type.const_int(b'"' as u64, false), string),
+                (i8_type.const_int(b'[' as u64, false), container),
+                (i8_type.const_int(b'{' as u64, false), container),
+            ],
+        )?;
+
+        // JSON null is the null node
+        self.builder.position_at_end(null);
+        let end = self.add_const(start, 4, "end")?;
+        self.builder.build_store(pos, end)?;
+        self.builder
+            .build_return(Some(&self.ptr_type().const_null()))?;
+
+        for (block, literal) in [(true_block, "true"), (false_block, "false")] {
+            self.builder.position_at_end(block);
+            let end = self.add_const(start, literal.len() as u64, "end")?;
+            self.builder.build_store(pos, end)?;
+            let node = self.json_node(
+                i64_type.const_int(JSON_BOOL, false),
+                i64_type.const_int((literal == "true") as u64, false),
+                self.ptr_type().const_null(),
+            )?;
+            self.builder.build_return(Some(&node))?;
+        }
+
+        // Numbers keep their text, which the conversions read and writing copies out
+        self.builder.position_at_end(number);
+        let end = self
+            .call(skip_number, &[text.into(), start.into()], "end")?
+            .into_int_value();
+        self.builder.build_store(pos, end)?;
+        let len = self.builder.build_int_sub(end, start, "len")?;
+        let bytes = unsafe {
+            self.builder
+                .build_in_bounds_gep(i8_type, text, &[start], "bytes")?
+        };
+        let data = self.immortal_copy(bytes, len)?;
+        let node = self.json_node(i64_type.const_int(JSON_NUMBER, false), len, data)?;
+        self.builder.build_return(Some(&node))?;
+
+        // Decoding never makes a string longer than its literal
+        self.builder.position_at_end(string);
+        let end = self
+            .call(skip_string, &[text.into(), start.into()], "end")?
+            .into_int_value();
+        self.builder.build_store(pos, end)?;
+        let size = self.builder.build_int_sub(end, start, "size")?;
+        let buffer = self
+            .call(malloc, &[size.into()], "buffer")?
+            .into_pointer_value();
+        let len = self
+            .call(unescape, &[text.into(), start.into(), buffer.into()], "len")?
+            .into_int_value();
+        let data = self.immortal_copy(buffer, len)?;
+        self.builder.build_call(free, &[buffer.into()], "")?;
+        let node = self.json_node(i64_type.const_int(JSON_STRING, false), len, data)?;
+        self.builder.build_return(Some(&node))?;
+
+        // An object's items are its keys and values in turn
+        self.builder.position_at_end(container);
+        let is_object = self.is_one_of(open, b"{", "is_object")?;
+        self.builder
+            .build_store(items_slot, self.ptr_type().const_null())?;
+        self.builder
+            .build_store(count_slot, i64_type.const_zero())?;
+        self.builder
+            .build_store(capacity_slot, i64_type.const_zero())?;
+        let inside = self.add_const(start, 1, "inside")?;
+        let first = self.call(space, &[text.into(), inside.into()], "first")?;
+        self.builder.build_store(pos, first)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let member_pos = self
+            .builder
+            .build_load(i64_type, pos, "member_pos")?
+            .into_int_value();
+        let byte = self.text_byte(text, member_pos, "byte")?;
+        let is_end = self.is_one_of(byte, b"]}", "is_end")?;
+        self.builder
+            .build_conditional_branch(is_end, finish, member)?;
+
+        self.builder.position_at_end(member);
+        self.builder
+            .build_conditional_branch(is_object, key, value)?;
+
+        self.builder.position_at_end(key);
+        let name = self
+            .call(function, &[text.into(), pos.into()], "name")?
+            .into_pointer_value();
+        self.json_push(function, items_slot, count_slot, capacity_slot, name)?;
+        let key_end = self
+            .builder
+            .build_load(i64_type, pos, "key_end")?
+            .into_int_value();
+        let colon = self
+            .call(space, &[text.into(), key_end.into()], "colon")?
+            .into_int_value();
+        let after_colon = self.add_const(colon, 1, "after_colon")?;
+        let value_pos = self.call(space, &[text.into(), after_colon.into()], "value_pos")?;
+        self.builder.build_store(pos, value_pos)?;
+        self.builder.build_unconditional_branch(value)?;
+
+        self.builder.position_at_end(value);
+        let item = self
+            .call(function, &[text.into(), pos.into()], "item")?
+            .into_pointer_value();
+        self.json_push(function, items_slot, count_slot, capacity_slot, item)?;
+        let value_end = self
+            .builder
+            .build_load(i64_type, pos, "value_end")?
+            .into_int_value();
+        let value_end = self
+            .call(space, &[text.into(), value_end.into()], "value_end")?
+            .into_int_value();
+        let byte = self.text_byte(text, value_end, "byte")?;
+        let is_comma = self.is_one_of(byte, b",", "is_comma")?;
+        let after_comma = self.add_const(value_end, 1, "after_comma")?;
+        let after_comma = self
+            .call(space, &[text.into(), after_comma.into()], "after_comma")?
+            .into_int_value();
+        let next_pos = self
+            .builder
+            .build_select(is_comma, after_comma, value_end, "next_pos")?;
+        self.builder.build_store(pos, next_pos)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(finish);
+        let end = self.add_const(member_pos, 1, "end")?;
+        self.builder.build_store(pos, end)?;
+        let count = self
+            .builder
+            .build_load(i64_type, count_slot, "count")?
+            .into_int_value();
+        let shift = self
+            .builder
+            .build_int_z_extend(is_object, i64_type, "shift")?;
+        let len = self.builder.build_right_shift(count, shift, false, "len")?;
+        let kind = self
+            .builder
+            .build_select(
+                is_object,
+                i64_type.const_int(JSON_OBJECT, false),
+                i64_type.const_int(JSON_ARRAY, false),
+                "kind",
+            )?
+            .into_int_value();
+        let items = self
+            .builder
+            .build_load(self.ptr_type(), items_slot, "items")?
+            .into_pointer_value();
+        let node = self.json_node(kind, len, items)?;
+        self.builder.build_return(Some(&node))?;
+        Ok(function)
+    }
+
+    /// `ptr venti_json_parse(ptr data, i64 len)`: the document read into `venti.json`
+    /// nodes, which is a runtime error if it is not valid JSON.
+    fn json_parse(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_json_parse",
+            self.ptr_type()
+                .fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let check = self.helper("venti_json_check")?;
+        let build = self.helper("venti_json_build")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let pos = self.builder.build_alloca(i64_type, "pos")?;
+        let start = self.call(check, &[data.into(), len.into()], "start")?;
+        self.builder.build_store(pos, start)?;
+        let text = self.c_text(data)?;
+        let node = self.call(build, &[text.into(), pos.into()], "node")?;
+        self.builder.build_return(Some(&node))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_json_kind(ptr node)`: the kind of the value at `node`, as an index into
+    /// `JSON_KINDS`.
+    fn json_kind(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_json_kind",
+            i64_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let node = param(function, 0)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let null = self.block(function, "null");
+        let other = self.block(function, "other");
+
+        self.builder.position_at_end(entry);
+        let is_null = self.builder.build_is_null(node, "is_null")?;
+        self.builder
+            .build_conditional_branch(is_null, null, other)?;
+
+        self.builder.position_at_end(null);
+        self.builder
+            .build_return(Some(&i64_type.const_int(JSON_NULL, false)))?;
+
+        self.builder.position_at_end(other);
+        let kind = self.json_field(node, 0, "kind")?;
+        self.builder.build_return(Some(&kind))?;
+        Ok(function)
+    }
+
+    /// Emits the start of a helper taking the `venti.json` node in its first parameter,
+    /// which returns `missing` unless the node is of `kind`, and leaves the builder where
+    /// it is.
+    fn json_expect_kind(
+        &self,
+        function: FunctionValue<'ctx>,
+        kind: u64,
+        missing: BasicValueEnum<'ctx>,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let node = param(function, 0)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let other = self.block(function, "other");
+        let found = self.block(function, "found");
+
+        self.builder.position_at_end(entry);
+        let found_kind = self
+            .call(self.helper("venti_json_kind")?, &[node.into()], "kind")?
+            .into_int_value();
+        let is_kind = self.is_const(found_kind, kind, "is_kind")?;
+        self.builder
+            .build_conditional_branch(is_kind, found, other)?;
+
+        self.builder.position_at_end(other);
+        self.builder.build_return(Some(&missing))?;
+
+        self.builder.position_at_end(found);
+        Ok(node)
+    }
+
+    /// `ptr venti_json_member(ptr node, ptr key, i64 key_len)`: the value of the last member
+    /// named `key` of the object at `node`, or null if it has none or is not an object.
+    fn json_member(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_json_member",
+            self.ptr_type().fn_type(
+                &[
+                    self.ptr_type().into(),
+                    self.ptr_type().into(),
+                    i64_type.into(),
+                ],
+                false,
+            ),
+        );
+        let null = self.ptr_type().const_null();
+        let node = self.json_expect_kind(function, JSON_OBJECT, null.into())?;
+        let key = param(function, 1)?.into_pointer_value();
+        let key_len = param(function, 2)?.into_int_value();
+        let memcmp = self.libc("memcmp")?;
+        let search = self
+            .builder
+            .get_insert_block()
+            .ok_or_else(|| VentiError::CodegenError("No block to search from".to_string(), None))?;
+        let loop_block = self.block(function, "loop");
+        let member = self.block(function, "member");
+        let compare = self.block(function, "compare");
+        let found = self.block(function, "found");
+        let missing = self.block(function, "missing");
+
+        let len = self.json_field(node, 1, "len")?.into_int_value();
+        let items = self.json_field(node, 2, "items")?.into_pointer_value();
+        let key = self.c_text(key)?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        // Searching from the end finds the member a later duplicate key sets
+        self.builder.position_at_end(loop_block);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let is_done = self.is_const(index_value, 0, "is_done")?;
+        self.builder
+            .build_conditional_branch(is_done, missing, member)?;
+
+        self.builder.position_at_end(member);
+        let previous =
+            self.builder
+                .build_int_sub(index_value, i64_type.const_int(1, false), "previous")?;
+        let key_index =
+            self.builder
+                .build_int_mul(previous, i64_type.const_int(2, false), "key_index")?;
+        let name_slot = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.ptr_type(), items, &[key_index], "name_slot")?
+        };
+        let name = self
+            .builder
+            .build_load(self.ptr_type(), name_slot, "name")?
+            .into_pointer_value();
+        let name_len = self.json_field(name, 1, "name_len")?.into_int_value();
+        let same_len =
+            self.builder
+                .build_int_compare(IntPredicate::EQ, name_len, key_len, "same_len")?;
+        self.builder
+            .build_conditional_branch(same_len, compare, loop_block)?;
+
+        self.builder.position_at_end(compare);
+        let name_data = self.json_field(name, 2, "name_data")?;
+        let order = self
+            .call(
+                memcmp,
+                &[name_data.into(), key.into(), key_len.into()],
+                "order",
+            )?
+            .into_int_value();
+        let is_match = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            order,
+            i32_type.const_zero(),
+            "is_match",
+        )?;
+        self.builder
+            .build_conditional_branch(is_match, found, loop_block)?;
+        index.add_incoming(&[(&len, search), (&previous, member), (&previous, compare)]);
+
+        self.builder.position_at_end(found);
+        let value_index = self.add_const(key_index, 1, "value_index")?;
+        let value_slot = unsafe {
+            self.builder.build_in_bounds_gep(
+                self.ptr_type(),
+                items,
+                &[value_index],
+                "value_slot",
+            )?
+        };
+        let value = self
+            .builder
+            .build_load(self.ptr_type(), value_slot, "value")?;
+        self.builder.build_return(Some(&value))?;
+
+        self.builder.position_at_end(missing);
+        self.builder.build_return(Some(&null))?;
+        Ok(function)
+    }
+
+    /// `ptr venti_json_element(ptr node, i64 index)`: element `index` of the array at
+    /// `node`, or null if it has none or is not an array.
+    fn json_element(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_json_element",
+            self.ptr_type()
+                .fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let null = self.ptr_type().const_null();
+        let node = self.json_expect_kind(function, JSON_ARRAY, null.into())?;
+        let index = param(function, 1)?.into_int_value();
+        let element = self.block(function, "element");
+        let missing = self.block(function, "missing");
+
+        // Compared unsigned, a negative index is past the end
+        let len = self.json_field(node, 1, "len")?.into_int_value();
+        let in_range = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, index, len, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, element, missing)?;
+
+        self.builder.position_at_end(element);
+        let items = self.json_field(node, 2, "items")?.into_pointer_value();
+        let slot = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.ptr_type(), items, &[index], "slot")?
+        };
+        let value = self.builder.build_load(self.ptr_type(), slot, "value")?;
+        self.builder.build_return(Some(&value))?;
+
+        self.builder.position_at_end(missing);
+        self.builder.build_return(Some(&null))?;
+        Ok(function)
+    }
+
+    /// `i64 venti_json_len(ptr node)`: the number of elements of the array or members of
+    /// the object at `node`, and 0 for any other value.
+    fn json_len(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_json_len",
+            i64_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let node = param(function, 0)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let container = self.block(function, "container");
+        let other = self.block(function, "other");
+
+        self.builder.position_at_end(entry);
+        let kind = self
+            .call(self.helper("venti_json_kind")?, &[node.into()], "kind")?
+            .into_int_value();
+        let is_container = self.builder.build_int_compare(
+            IntPredicate::UGE,
+            kind,
+            i64_type.const_int(JSON_ARRAY, false),
+            "is_container",
+        )?;
+        self.builder
+            .build_conditional_branch(is_container, container, other)?;
+
+        self.builder.position_at_end(container);
+        let len = self.json_field(node, 1, "len")?;
+        self.builder.build_return(Some(&len))?;
+
+        self.builder.position_at_end(other);
+        self.builder.build_return(Some(&i64_type.const_zero()))?;
+        Ok(function)
+    }
+
+    /// Returns the name of the JSON `kind`, as a C string.
+    fn json_kind_name(&self, kind: IntValue<'ctx>) -> Result<PointerValue<'ctx>, VentiError> {
+        let mut name = self
+            .builder
+            .build_global_string_ptr(JSON_KINDS[0], "kind_name")?
+            .as_pointer_value();
+        for (index, kind_name) in JSON_KINDS.iter().enumerate().skip(1) {
+            let is_kind = self.is_const(kind, index as u64, "is_kind")?;
+            let text = self
+                .builder
+                .build_global_string_ptr(kind_name, "kind_name")?
+                .as_pointer_value();
+            name = self
+                .builder
+                .build_select(is_kind, text, name, "kind_name")?
+                .into_pointer_value();
+        }
+        Ok(name)
+    }
+
+    /// Emits the start of a conversion of the `venti.json` node in the first parameter of
+    /// `function` to `target`, which returns `null` for a null node when it is given. Any
+    /// other node not of `kind` is a runtime error. Leaves the builder where the node is
+    /// known to be of `kind`.
+    fn json_convert(
+        &self,
+        function: FunctionValue<'ctx>,
+        kind: u64,
+        target: &str,
+        null: Option<BasicValueEnum<'ctx>>,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        let node = param(function, 0)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let mismatch = self.block(function, "mismatch");
+        let found = self.block(function, "found");
+
+        self.builder.position_at_end(entry);
+        let found_kind = self
+            .call(self.helper("venti_json_kind")?, &[node.into()], "kind")?
+            .into_int_value();
+        let is_kind = self.is_const(found_kind, kind, "is_kind")?;
+        self.builder
+            .build_conditional_branch(is_kind, found, mismatch)?;
+
+        self.builder.position_at_end(mismatch);
+        if let Some(null) = null {
+            let null_block = self.block(function, "null");
+            let other = self.block(function, "other");
+            let is_null = self.is_const(found_kind, JSON_NULL, "is_null")?;
+            self.builder
+                .build_conditional_branch(is_null, null_block, other)?;
+            self.builder.position_at_end(null_block);
+            self.builder.build_return(Some(&null))?;
+            self.builder.position_at_end(other);
+        }
+        let name = self.json_kind_name(found_kind)?;
+        self.runtime_error(
+            &format!("Cannot convert a JSON %s to {}", target),
+            &[name.into()],
+        )?;
+
+        self.builder.position_at_end(found);
+        Ok(node)
+    }
+
+    /// `i64 venti_json_to_int(ptr node)`: the number at `node` as an int, keeping every
+    /// digit of an integer and truncating any other number toward zero, saturating; any
+    /// other value is a runtime error.
+    fn json_to_int(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let f64_type = self.context.f64_type();
+        let function = self.declare(
+            "venti_json_to_int",
+            i64_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let node = self.json_convert(function, JSON_NUMBER, "int", None)?;
+        let strtoll = self.libc("strtoll")?;
+        let strtod = self.libc("strtod")?;
+        let fptosi_sat = Intrinsic::find("llvm.fptosi.sat")
+            .and_then(|intrinsic| {
+                intrinsic.get_declaration(self.module, &[i64_type.into(), f64_type.into()])
+            })
+            .ok_or_else(|| {
+                VentiError::CodegenError(
+                    "LLVM has no saturating float to int conversion".to_string(),
+                    None,
+                )
+            })?;
+        let integer = self.block(function, "integer");
+        let other = self.block(function, "other");
+
+        let len = self.json_field(node, 1, "len")?.into_int_value();
+        let data = self.json_field(node, 2, "data")?.into_pointer_value();
+        let end_slot = self.builder.build_alloca(self.ptr_type(), "end_slot")?;
+        let value = self.call(
+            strtoll,
+            &[
+                data.into(),
+                end_slot.into(),
+                self.context.i32_type().const_int(10, false).into(),
+            ],
+            "value",
+        )?;
+        let end = self
+            .builder
+            .build_load(self.ptr_type(), end_slot, "end")?
+            .into_pointer_value();
+        let text_end = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), data, &[len], "text_end")?
+        };
+        let is_integer =
+            self.builder
+                .build_int_compare(IntPredicate::EQ, end, text_end, "is_integer")?;
+        self.builder
+            .build_conditional_branch(is_integer, integer, other)?;
+
+        self.builder.position_at_end(integer);
+        self.builder.build_return(Some(&value))?;
+
+        self.builder.position_at_end(other);
+        let float = self.call(
+            strtod,
+            &[data.into(), self.ptr_type().const_null().into()],
+            "float",
+        )?;
+        let value = self.call(fptosi_sat, &[float.into()], "value")?;
+        self.builder.build_return(Some(&value))?;
+        Ok(function)
+    }
+
+    /// `double venti_json_to_float(ptr node)`: the number at `node` as a float; any other
+    /// value is a runtime error.
+    fn json_to_float(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_json_to_float",
+            self.context
+                .f64_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let node = self.json_convert(function, JSON_NUMBER, "float", None)?;
+        let data = self.json_field(node, 2, "data")?;
+        let value = self.call(
+            self.libc("strtod")?,
+            &[data.into(), self.ptr_type().const_null().into()],
+            "value",
+        )?;
+        self.builder.build_return(Some(&value))?;
+        Ok(function)
+    }
+
+    /// `i1 venti_json_to_bool(ptr node)`: the bool at `node`; any other value is a runtime
+    /// error.
+    fn json_to_bool(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_json_to_bool",
+            self.context
+                .bool_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let node = self.json_convert(function, JSON_BOOL, "bool", None)?;
+        let value = self.json_field(node, 1, "value")?.into_int_value();
+        let value = self
+            .builder
+            .build_int_truncate(value, self.context.bool_type(), "value")?;
+        self.builder.build_return(Some(&value))?;
+        Ok(function)
+    }
+
+    /// `venti.str venti_json_to_str(ptr node)`: the string at `node`, or `nothing` for
+    /// null; any other value is a runtime error. The string is never freed, like the node.
+    fn json_to_str(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let string_type = string_type(self.context);
+        let function = self.declare(
+            "venti_json_to_str",
+            string_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let nothing = string_type.const_zero().into();
+        let node = self.json_convert(function, JSON_STRING, "string", Some(nothing))?;
+        let len = self.json_field(node, 1, "len")?.into_int_value();
+        let data = self.json_field(node, 2, "data")?.into_pointer_value();
+        let value = self.string_value(data, len)?;
+        self.builder.build_return(Some(&value))?;
+        Ok(function)
+    }
+
+    /// Returns a pointer to field `index` of the `venti.writer` behind `writer`.
+    fn writer_field(
+        &self,
+        writer: PointerValue<'ctx>,
+        index: u32,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_struct_gep(writer_type(self.context), writer, index, name)?)
+    }
+
+    /// Builds a call adding the bytes of `text` to what `writer` is writing.
+    fn write_text(&self, writer: PointerValue<'ctx>, text: &str) -> Result<(), VentiError> {
+        let bytes = self.builder.build_global_string_ptr(text, "text")?;
+        let len = self.context.i64_type().const_int(text.len() as u64, false);
+        self.builder.build_call(
+            self.helper("venti_write_raw")?,
+            &[writer.into(), bytes.as_pointer_value().into(), len.into()],
+            "",
+        )?;
+        Ok(())
+    }
+
+    /// Declares a helper writing to a `venti.writer`, `void <name>(ptr writer, <value>)`.
+    fn declare_write(
+        &self,
+        name: &str,
+        value: &[BasicMetadataTypeEnum<'ctx>],
+    ) -> FunctionValue<'ctx> {
+        let mut params: Vec<BasicMetadataTypeEnum> = vec![self.ptr_type().into()];
+        params.extend_from_slice(value);
+        self.declare(name, self.context.void_type().fn_type(&params, false))
+    }
+
+    /// `void venti_write_raw(ptr writer, ptr bytes, i64 len)`: adds `len` bytes to the text
+    /// being written, keeping room for a NUL after them.
+    fn write_raw(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare_write(
+            "venti_write_raw",
+            &[self.ptr_type().into(), i64_type.into()],
+        );
+        let writer = param(function, 0)?.into_pointer_value();
+        let bytes = param(function, 1)?.into_pointer_value();
+        let len = param(function, 2)?.into_int_value();
+        let realloc = self.libc("realloc")?;
+        let entry = self.block(function, "entry");
+        let grow = self.block(function, "grow");
+        let resize = self.block(function, "resize");
+        let copy = self.block(function, "copy");
+
+        self.builder.position_at_end(entry);
+        let data_field = self.writer_field(writer, 0, "data_field")?;
+        let len_field = self.writer_field(writer, 1, "len_field")?;
+        let capacity_field = self.writer_field(writer, 2, "capacity_field")?;
+        let written = self
+            .builder
+            .build_load(i64_type, len_field, "written")?
+            .into_int_value();
+        let capacity = self
+            .builder
+            .build_load(i64_type, capacity_field, "capacity")?
+            .into_int_value();
+        let needed = self.builder.build_int_add(written, len, "needed")?;
+        let needed = self.add_const(needed, 1, "needed")?;
+        let is_full =
+            self.builder
+                .build_int_compare(IntPredicate::UGT, needed, capacity, "is_full")?;
+        self.builder.build_conditional_branch(is_full, grow, copy)?;
+
+        self.builder.position_at_end(grow);
+        let old_capacity = self.builder.build_phi(i64_type, "old_capacity")?;
+        let old_value = old_capacity.as_basic_value().into_int_value();
+        let doubled =
+            self.builder
+                .build_int_mul(old_value, i64_type.const_int(2, false), "doubled")?;
+        let new_capacity = self.add_const(doubled, 16, "new_capacity")?;
+        old_capacity.add_incoming(&[(&capacity, entry), (&new_capacity, grow)]);
+        let still_full = self.builder.build_int_compare(
+            IntPredicate::UGT,
+            needed,
+            new_capacity,
+            "still_full",
+        )?;
+        self.builder
+            .build_conditional_branch(still_full, grow, resize)?;
+
+        self.builder.position_at_end(resize);
+        let data = self
+            .builder
+            .build_load(self.ptr_type(), data_field, "data")?;
+        let data = self.call(realloc, &[data.into(), new_capacity.into()], "grown")?;
+        self.builder.build_store(data_field, data)?;
+        self.builder.build_store(capacity_field, new_capacity)?;
+        self.builder.build_unconditional_branch(copy)?;
+
+        self.builder.position_at_end(copy);
+        let data = self
+            .builder
+            .build_load(self.ptr_type(), data_field, "data")?
+            .into_pointer_value();
+        let end = unsafe {
+            self.builder
+                .build_in_bounds_gep(self.context.i8_type(), data, &[written], "end")?
+        };
+        self.builder.build_memcpy(end, 1, bytes, 1, len)?;
+        let written = self.builder.build_int_add(written, len, "written")?;
+        self.builder.build_store(len_field, written)?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_json_quote(ptr writer, ptr data, i64 len)`: writes a string as a JSON
+    /// string, or `null` for `nothing`.
+    fn json_quote(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i8_type = self.context.i8_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare_write(
+            "venti_json_quote",
+            &[self.ptr_type().into(), i64_type.into()],
+        );
+        let writer = param(function, 0)?.into_pointer_value();
+        let data = param(function, 1)?.into_pointer_value();
+        let len = param(function, 2)?.into_int_value();
+        let raw = self.helper("venti_write_raw")?;
+        let snprintf = self.libc("snprintf")?;
+        let entry = self.block(function, "entry");
+        let null = self.block(function, "null");
+        let open = self.block(function, "open");
+        let loop_block = self.block(function, "loop");
+        let body = self.block(function, "body");
+        let check_control = self.block(function, "check_control");
+        let control = self.block(function, "control");
+        let plain = self.block(function, "plain");
+        let next = self.block(function, "next");
+        let close = self.block(function, "close");
+        let escapes = [
+            (b'"', "\\\""),
+            (b'\\', "\\\\"),
+            (b'\n', "\\n"),
+            (b'\r', "\\r"),
+            (b'\t', "\\t"),
+        ]
+        .map(|(byte, escape)| (byte, escape, self.block(function, "escape")));
+
+        self.builder.position_at_end(entry);
+        let escaped = self
+            .builder
+            .build_alloca(i8_type.array_type(8), "escaped")?;
+        let is_nothing = self.builder.build_is_null(data, "is_nothing")?;
+        self.builder
+            .build_conditional_branch(is_nothing, null, open)?;
+
+        self.builder.position_at_end(null);
+        self.write_text(writer, "null")?;
+        self.builder.build_return(None)?;
+
+        self.builder.position_at_end(open);
+        self.write_text(writer, "\"")?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let in_range =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, index_value, len, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, body, close)?;
+
+        self.builder.position_at_end(body);
+        let byte_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(i8_type, data, &[index_value], "byte_ptr")?
+        };
+        let byte = self
+            .builder
+            .build_load(i8_type, byte_ptr, "byte")?
+            .into_int_value();
+        let cases = escapes
+            .iter()
+            .map(|&(byte, _, block)| (i8_type.const_int(byte as u64, false), block))
+            .collect::<Vec<_>>();
+        self.builder.build_switch(byte, check_control, &cases)?;
+
+        for &(_, escape, block) in &escapes {
+            self.builder.position_at_end(block);
+            self.write_text(writer, escape)?;
+            self.builder.build_unconditional_branch(next)?;
+        }
+
+        // Other control characters are written as \u escapes
+        self.builder.position_at_end(check_control);
+        let is_control = self.builder.build_int_compare(
+            IntPredicate::ULT,
+            byte,
+            i8_type.const_int(0x20, false),
+            "is_control",
+        )?;
+        self.builder
+            .build_conditional_branch(is_control, control, plain)?;
+
+        self.builder.position_at_end(control);
+        let format = self.builder.build_global_string_ptr("\\u%04x", "format")?;
+        let code = self
+            .builder
+            .build_int_z_extend(byte, self.context.i32_type(), "code")?;
+        self.builder.build_call(
+            snprintf,
+            &[
+                escaped.into(),
+                i64_type.const_int(8, false).into(),
+                format.as_pointer_value().into(),
+                code.into(),
+            ],
+            "",
+        )?;
+        self.builder.build_call(
+            raw,
+            &[
+                writer.into(),
+                escaped.into(),
+                i64_type.const_int(6, false).into(),
+            ],
+            "",
+        )?;
+        self.builder.build_unconditional_branch(next)?;
+
+        self.builder.position_at_end(plain);
+        self.builder.build_call(
+            raw,
+            &[
+                writer.into(),
+                byte_ptr.into(),
+                i64_type.const_int(1, false).into(),
+            ],
+            "",
+        )?;
+        self.builder.build_unconditional_branch(next)?;
+
+        self.builder.position_at_end(next);
+        let next_index = self.add_const(index_value, 1, "next_index")?;
+        self.builder.build_unconditional_branch(loop_block)?;
+        index.add_incoming(&[(&i64_type.const_zero(), open), (&next_index, next)]);
+
+        self.builder.position_at_end(close);
+        self.write_text(writer, "\"")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_json_int(ptr writer, i64 value)`: writes an integer.
+    fn json_int(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare_write("venti_json_int", &[i64_type.into()]);
+        let writer = param(function, 0)?.into_pointer_value();
+        let value = param(function, 1)?.into_int_value();
+        let raw = self.helper("venti_write_raw")?;
+        let snprintf = self.libc("snprintf")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let text = self
+            .builder
+            .build_alloca(self.context.i8_type().array_type(24), "text")?;
+        let format = self.builder.build_global_string_ptr("%ld", "format")?;
+        let len = self
+            .call(
+                snprintf,
+                &[
+                    text.into(),
+                    i64_type.const_int(24, false).into(),
+                    format.as_pointer_value().into(),
+                    value.into(),
+                ],
+                "len",
+            )?
+            .into_int_value();
+        let len = self.builder.build_int_s_extend(len, i64_type, "len")?;
+        self.builder
+            .build_call(raw, &[writer.into(), text.into(), len.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_json_float(ptr writer, double value)`: writes a float the way
+    /// `printventi` prints it, or `null` for an infinity or NaN.
+    fn json_float(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let f64_type = self.context.f64_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare_write("venti_json_float", &[f64_type.into()]);
+        let writer = param(function, 0)?.into_pointer_value();
+        let value = param(function, 1)?.into_float_value();
+        let raw = self.helper("venti_write_raw")?;
+        let snprintf = self.libc("snprintf")?;
+        let malloc = self.libc("malloc")?;
+        let free = self.libc("free")?;
+        let entry = self.block(function, "entry");
+        let finite = self.block(function, "finite");
+        let null = self.block(function, "null");
+
+        // Subtracting a finite value from itself gives zero, and anything else NaN
+        self.builder.position_at_end(entry);
+        let difference = self.builder.build_float_sub(value, value, "difference")?;
+        let is_finite = self.builder.build_float_compare(
+            FloatPredicate::OEQ,
+            difference,
+            f64_type.const_zero(),
+            "is_finite",
+        )?;
+        self.builder
+            .build_conditional_branch(is_finite, finite, null)?;
+
+        self.builder.position_at_end(null);
+        self.write_text(writer, "null")?;
+        self.builder.build_return(None)?;
+
+        // Measuring the text first lets floats of any size be written
+        self.builder.position_at_end(finite);
+        let format = self
+            .builder
+            .build_global_string_ptr("%f", "format")?
+            .as_pointer_value();
+        let len = self
+            .call(
+                snprintf,
+                &[
+                    self.ptr_type().const_null().into(),
+                    i64_type.const_zero().into(),
+                    format.into(),
+                    value.into(),
+                ],
+                "len",
+            )?
+            .into_int_value();
+        let len = self.builder.build_int_s_extend(len, i64_type, "len")?;
+        let size = self.add_const(len, 1, "size")?;
+        let text = self
+            .call(malloc, &[size.into()], "text")?
+            .into_pointer_value();
+        self.builder.build_call(
+            snprintf,
+            &[text.into(), size.into(), format.into(), value.into()],
+            "",
+        )?;
+        self.builder
+            .build_call(raw, &[writer.into(), text.into(), len.into()], "")?;
+        self.builder.build_call(free, &[text.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_json_map(ptr writer, ptr header)`: writes a map as an object with its
+    /// keys in byte order, found by picking the least key after the last one written on
+    /// each pass.
+    fn json_map(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let function = self.declare_write("venti_json_map", &[self.ptr_type().into()]);
+        let writer = param(function, 0)?.into_pointer_value();
+        let header = param(function, 1)?.into_pointer_value();
+        let quote = self.helper("venti_json_quote")?;
+        let int = self.helper("venti_json_int")?;
+        let strcmp = self.libc("strcmp")?;
+        let strlen = self.libc("strlen")?;
+        let entry = self.block(function, "entry");
+        let outer = self.block(function, "outer");
+        let scan_init = self.block(function, "scan_init");
+        let scan = self.block(function, "scan");
+        let scan_key = self.block(function, "scan_key");
+        let check_last = self.block(function, "check_last");
+        let compare_last = self.block(function, "compare_last");
+        let check_next = self.block(function, "check_next");
+        let compare_next = self.block(function, "compare_next");
+        let take = self.block(function, "take");
+        let scan_next = self.block(function, "scan_next");
+        let emit = self.block(function, "emit");
+        let comma = self.block(function, "comma");
+        let member = self.block(function, "member");
+        let close = self.block(function, "close");
+
+        self.builder.position_at_end(entry);
+        let last_slot = self.builder.build_alloca(self.ptr_type(), "last_slot")?;
+        let next_slot = self.builder.build_alloca(i64_type, "next_slot")?;
+        self.builder
+            .build_store(last_slot, self.ptr_type().const_null())?;
+        let capacity = self
+            .load_header_field(header, 0, "capacity")?
+            .into_int_value();
+        let len = self.load_header_field(header, 1, "len")?.into_int_value();
+        let keys = self
+            .load_header_field(header, 2, "keys")?
+            .into_pointer_value();
+        let values = self
+            .load_header_field(header, 3, "values")?
+            .into_pointer_value();
+        self.write_text(writer, "{")?;
+        self.builder.build_unconditional_branch(outer)?;
+
+        self.builder.position_at_end(outer);
+        let written = self.builder.build_phi(i64_type, "written")?;
+        let written_value = written.as_basic_value().into_int_value();
+        let has_more =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, written_value, len, "has_more")?;
+        self.builder
+            .build_conditional_branch(has_more, scan_init, close)?;
+
+        self.builder.position_at_end(scan_init);
+        self.builder
+            .build_store(next_slot, i64_type.const_all_ones())?;
+        self.builder.build_unconditional_branch(scan)?;
+
+        self.builder.position_at_end(scan);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let in_range =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, index_value, capacity, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, scan_key, emit)?;
+
+        self.builder.position_at_end(scan_key);
+        let key_slot = self.slot_ptr(keys, index_value, "key_slot")?;
+        let key = self
+            .builder
+            .build_load(self.ptr_type(), key_slot, "key")?
+            .into_pointer_value();
+        let is_empty = self.builder.build_is_null(key, "is_empty")?;
+        self.builder
+            .build_conditional_branch(is_empty, scan_next, check_last)?;
+
+        // Only keys after the last one written are left to write
+        self.builder.position_at_end(check_last);
+        let last = self
+            .builder
+            .build_load(self.ptr_type(), last_slot, "last")?
+            .into_pointer_value();
+        let is_first = self.builder.build_is_null(last, "is_first")?;
+        self.builder
+            .build_conditional_branch(is_first, check_next, compare_last)?;
+
+        self.builder.position_at_end(compare_last);
+        let order = self
+            .call(strcmp, &[key.into(), last.into()], "order")?
+            .into_int_value();
+        let is_after = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            order,
+            i32_type.const_zero(),
+            "is_after",
+        )?;
+        self.builder
+            .build_conditional_branch(is_after, check_next, scan_next)?;
+
+        self.builder.position_at_end(check_next);
+        let next = self
+            .builder
+            .build_load(i64_type, next_slot, "next")?
+            .into_int_value();
+        let is_unset = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            next,
+            i64_type.const_zero(),
+            "is_unset",
+        )?;
+        self.builder
+            .build_conditional_branch(is_unset, take, compare_next)?;
+
+        self.builder.position_at_end(compare_next);
+        let next_key_slot = self.slot_ptr(keys, next, "next_key_slot")?;
+        let next_key = self
+            .builder
+            .build_load(self.ptr_type(), next_key_slot, "next_key")?;
+        let order = self
+            .call(strcmp, &[key.into(), next_key.into()], "order")?
+            .into_int_value();
+        let is_before = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            order,
+            i32_type.const_zero(),
+            "is_before",
+        )?;
+        self.builder
+            .build_conditional_branch(is_before, take, scan_next)?;
+
+        self.builder.position_at_end(take);
+        self.builder.build_store(next_slot, index_value)?;
+        self.builder.build_unconditional_branch(scan_next)?;
+
+        self.builder.position_at_end(scan_next);
+        let next_index = self.add_const(index_value, 1, "next_index")?;
+        self.builder.build_unconditional_branch(scan)?;
+        index.add_incoming(&[
+            (&i64_type.const_zero(), scan_init),
+            (&next_index, scan_next),
+        ]);
+
+        self.builder.position_at_end(emit);
+        let is_later = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            written_value,
+            i64_type.const_zero(),
+            "is_later",
+        )?;
+        self.builder
+            .build_conditional_branch(is_later, comma, member)?;
+
+        self.builder.position_at_end(comma);
+        self.write_text(writer, ",")?;
+        self.builder.build_unconditional_branch(member)?;
+
+        self.builder.position_at_end(member);
+        let next = self
+            .builder
+            .build_load(i64_type, next_slot, "next")?
+            .into_int_value();
+        let key_slot = self.slot_ptr(keys, next, "key_slot")?;
+        let key = self
+            .builder
+            .build_load(self.ptr_type(), key_slot, "key")?
+            .into_pointer_value();
+        self.builder.build_store(last_slot, key)?;
+        let key_len = self.call(strlen, &[key.into()], "key_len")?;
+        self.builder
+            .build_call(quote, &[writer.into(), key.into(), key_len.into()], "")?;
+        self.write_text(writer, ":")?;
+        let value_slot = self.slot_ptr(values, next, "value_slot")?;
+        let value = self.builder.build_load(i64_type, value_slot, "value")?;
+        self.builder
+            .build_call(int, &[writer.into(), value.into()], "")?;
+        let next_written = self.add_const(written_value, 1, "next_written")?;
+        self.builder.build_unconditional_branch(outer)?;
+        written.add_incoming(&[(&i64_type.const_zero(), entry), (&next_written, member)]);
+
+        self.builder.position_at_end(close);
+        self.write_text(writer, "}")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_json_write(ptr writer, ptr node)`: writes the value at `node` as JSON,
+    /// with its numbers as the document had them.
+    fn json_write(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare_write("venti_json_write", &[self.ptr_type().into()]);
+        let writer = param(function, 0)?.into_pointer_value();
+        let node = param(function, 1)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let null = self.block(function, "null");
+        let bool_block = self.block(function, "bool");
+        let true_block = self.block(function, "true");
+        let false_block = self.block(function, "false");
+        let number = self.block(function, "number");
+        let string = self.block(function, "string");
+        let array = self.block(function, "array");
+        let object = self.block(function, "object");
+
+        self.builder.position_at_end(entry);
+        let kind = self
+            .call(self.helper("venti_json_kind")?, &[node.into()], "kind")?
+            .into_int_value();
+        self.builder.build_switch(
+            kind,
+            null,
+            &[
+                (i64_type.const_int(JSON_BOOL, false), bool_block),
+                (i64_type.const_int(JSON_NUMBER, false), number),
+                (i64_type.const_int(JSON_STRING, false), string),
+                (i64_type.const_int(JSON_ARRAY, false), array),
+                (i64_type.const_int(JSON_OBJECT, false), object),
+            ],
+        )?;
+
+        for (block, literal) in [(null, "null"), (true_block, "true"), (false_block, "false")] {
+            self.builder.position_at_end(block);
+            self.write_text(writer, literal)?;
+            self.builder.build_return(None)?;
+        }
+
+        self.builder.position_at_end(bool_block);
+        let value = self.json_field(node, 1, "value")?.into_int_value();
+        let is_false = self.is_const(value, 0, "is_false")?;
+        self.builder
+            .build_conditional_branch(is_false, false_block, true_block)?;
+
+        for (block, helper) in [(number, "venti_write_raw"), (string, "venti_json_quote")] {
+            self.builder.position_at_end(block);
+            let len = self.json_field(node, 1, "len")?;
+            let data = self.json_field(node, 2, "data")?;
+            self.builder.build_call(
+                self.helper(helper)?,
+                &[writer.into(), data.into(), len.into()],
+                "",
+            )?;
+            self.builder.build_return(None)?;
+        }
+
+        // An object's items are its keys and values in turn
+        for (block, open, close, step) in [(array, "[", "]", 1), (object, "{", "}", 2)] {
+            let loop_block = self.block(function, "loop");
+            let member = self.block(function, "member");
+            let comma = self.block(function, "comma");
+            let item = self.block(function, "item");
+            let done = self.block(function, "done");
+
+            self.builder.position_at_end(block);
+            self.write_text(writer, open)?;
+            let len = self.json_field(node, 1, "len")?.into_int_value();
+            let items = self.json_field(node, 2, "items")?.into_pointer_value();
+            let slots =
+                self.builder
+                    .build_int_mul(len, i64_type.const_int(step, false), "slots")?;
+            self.builder.build_unconditional_branch(loop_block)?;
+
+            self.builder.position_at_end(loop_block);
+            let index = self.builder.build_phi(i64_type, "index")?;
+            let index_value = index.as_basic_value().into_int_value();
+            let is_done =
+                self.builder
+                    .build_int_compare(IntPredicate::EQ, index_value, slots, "is_done")?;
+            self.builder
+                .build_conditional_branch(is_done, done, member)?;
+
+            self.builder.position_at_end(member);
+            let is_first = self.is_const(index_value, 0, "is_first")?;
+            self.builder
+                .build_conditional_branch(is_first, item, comma)?;
+
+            self.builder.position_at_end(comma);
+            self.write_text(writer, ",")?;
+            self.builder.build_unconditional_branch(item)?;
+
+            self.builder.position_at_end(item);
+            for offset in 0..step {
+                if offset > 0 {
+                    self.write_text(writer, ":")?;
+                }
+                let slot_index = self.add_const(index_value, offset, "slot_index")?;
+                let slot = unsafe {
+                    self.builder.build_in_bounds_gep(
+                        self.ptr_type(),
+                        items,
+                        &[slot_index],
+                        "slot",
+                    )?
+                };
+                let value = self.builder.build_load(self.ptr_type(), slot, "value")?;
+                self.builder
+                    .build_call(function, &[writer.into(), value.into()], "")?;
+            }
+            let next = self.add_const(index_value, step, "next")?;
+            self.builder.build_unconditional_branch(loop_block)?;
+            index.add_incoming(&[(&i64_type.const_zero(), block), (&next, item)]);
+
+            self.builder.position_at_end(done);
+            self.write_text(writer, close)?;
+            self.builder.build_return(None)?;
+        }
+        Ok(function)
+    }
+
+    /// `venti.str venti_json_text(ptr node)`: the value at `node` as `printventi` prints it:
+    /// a string as its text, and any other value as JSON in a new string.
+    fn json_text(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let writer_type = writer_type(self.context);
+        let string_type = string_type(self.context);
+        let function = self.declare(
+            "venti_json_text",
+            string_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let node = param(function, 0)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let string = self.block(function, "string");
+        let other = self.block(function, "other");
+
+        self.builder.position_at_end(entry);
+        let writer = self.builder.build_alloca(writer_type, "writer")?;
+        let kind = self
+            .call(self.helper("venti_json_kind")?, &[node.into()], "kind")?
+            .into_int_value();
+        let is_string = self.is_const(kind, JSON_STRING, "is_string")?;
+        self.builder
+            .build_conditional_branch(is_string, string, other)?;
+
+        self.builder.position_at_end(string);
+        let text = self.call(self.helper("venti_json_to_str")?, &[node.into()], "text")?;
+        self.builder.build_return(Some(&text))?;
+
+        self.builder.position_at_end(other);
+        self.builder.build_store(writer, writer_type.const_zero())?;
+        self.builder.build_call(
+            self.helper("venti_json_write")?,
+            &[writer.into(), node.into()],
+            "",
+        )?;
+        let text = self.call(self.helper("venti_write_finish")?, &[writer.into()], "text")?;
+        self.builder.build_return(Some(&text))?;
+        Ok(function)
+    }
+
+    /// `venti.str venti_write_finish(ptr writer)`: the text written, as a new string, freeing
+    /// the writer's buffer.
+    fn write_finish(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_write_finish",
+            string_type(self.context).fn_type(&[self.ptr_type().into()], false),
+        );
+        let writer = param(function, 0)?.into_pointer_value();
+        let copy = self.helper("venti_str_copy")?;
+        let free = self.libc("free")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let data_field = self.writer_field(writer, 0, "data_field")?;
+        let len_field = self.writer_field(writer, 1, "len_field")?;
+        let data = self
+            .builder
+            .build_load(self.ptr_type(), data_field, "data")?;
+        let len = self
+            .builder
+            .build_load(i64_type, len_field, "len")?
+            .into_int_value();
+        let text = self
+            .call(copy, &[data.into(), len.into()], "text")?
+            .into_pointer_value();
+        self.builder.build_call(free, &[data.into()], "")?;
+        let text = self.string_value(text, len)?;
+        self.builder.build_return(Some(&text))?;
+        Ok(function)
+    }
+
+    /// `void venti_rc_retain(ptr count)`: adds a reference to the count at `count`, unless
+    /// it is negative.
+    fn rc_retain(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_rc_retain",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let count = param(function, 0)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let counted = self.block(function, "counted");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let is_counted = self.is_counted(count)?;
+        self.builder
+            .build_conditional_branch(is_counted, counted, done)?;
+
+        self.builder.position_at_end(counted);
+        self.builder.build_atomicrmw(
+            AtomicRMWBinOp::Add,
+            count,
+            i64_type.const_int(1, false),
+            AtomicOrdering::Monotonic,
+        )?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `i1 venti_rc_release(ptr count)`: drops a reference from the count at `count`,
+    /// unless it is negative, and returns whether that was the last one.
+    fn rc_release(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let bool_type = self.context.bool_type();
+        let function = self.declare(
+            "venti_rc_release",
+            bool_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let count = param(function, 0)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let counted = self.block(function, "counted");
+        let immortal = self.block(function, "immortal");
+
+        self.builder.position_at_end(entry);
+        let is_counted = self.is_counted(count)?;
+        self.builder
+            .build_conditional_branch(is_counted, counted, immortal)?;
+
+        self.builder.position_at_end(counted);
+        let previous = self.builder.build_atomicrmw(
+            AtomicRMWBinOp::Sub,
+            count,
+            i64_type.const_int(1, false),
+            AtomicOrdering::AcquireRelease,
+        )?;
+        let is_last = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            previous,
+            i64_type.const_int(1, false),
+            "is_last",
+        )?;
+        self.builder.build_return(Some(&is_last))?;
+
+        self.builder.position_at_end(immortal);
+        self.builder.build_return(Some(&bool_type.const_zero()))?;
+        Ok(function)
+    }
+
+    /// Returns whether the reference count at `count` is counted rather than immortal.
+    fn is_counted(&self, count: PointerValue<'ctx>) -> Result<IntValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        // Immortal counts never change, so a plain load tells them apart
+        let current = self
+            .builder
+            .build_load(i64_type, count, "current")?
+            .into_int_value();
+        Ok(self.builder.build_int_compare(
+            IntPredicate::SGE,
+            current,
+            i64_type.const_zero(),
+            "is_counted",
+        )?)
+    }
+
+    /// Returns a pointer to the reference count stored in the 8 bytes before string `data`.
+    fn str_count(&self, data: PointerValue<'ctx>) -> Result<PointerValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        Ok(unsafe {
+            self.builder.build_in_bounds_gep(
+                i64_type,
+                data,
+                &[i64_type.const_all_ones()],
+                "count",
+            )?
+        })
+    }
+
+    /// `void venti_str_retain(ptr data)`: adds a reference to a string; `nothing` is ignored.
+    fn str_retain(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_str_retain",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let rc_retain = self.helper("venti_rc_retain")?;
+        let entry = self.block(function, "entry");
+        let present = self.block(function, "present");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let is_null = self.builder.build_is_null(data, "is_null")?;
+        self.builder
+            .build_conditional_branch(is_null, done, present)?;
+
+        self.builder.position_at_end(present);
+        let count = self.str_count(data)?;
+        self.builder.build_call(rc_retain, &[count.into()], "")?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_str_release(ptr data)`: drops a reference to a string, freeing it with
+    /// the last one; `nothing` is ignored.
+    fn str_release(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_str_release",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let rc_release = self.helper("venti_rc_release")?;
+        let free = self.libc("free")?;
+        let entry = self.block(function, "entry");
+        let present = self.block(function, "present");
+        let last = self.block(function, "last");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let is_null = self.builder.build_is_null(data, "is_null")?;
+        self.builder
+            .build_conditional_branch(is_null, done, present)?;
+
+        self.builder.position_at_end(present);
+        let count = self.str_count(data)?;
+        let is_last = self
+            .call(rc_release, &[count.into()], "is_last")?
+            .into_int_value();
+        self.builder.build_conditional_branch(is_last, last, done)?;
+
+        // The count starts the allocation holding the string
+        self.builder.position_at_end(last);
+        self.builder.build_call(free, &[count.into()], "")?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_map_retain(ptr header)`: adds a reference to a map.
+    fn map_retain(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_map_retain",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_retain = self.helper("venti_rc_retain")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let count = self
+            .builder
+            .build_struct_gep(self.map_header_type(), header, 4, "count")?;
+        self.builder.build_call(rc_retain, &[count.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_map_release(ptr header)`: drops a reference to a map; the last one
+    /// releases its keys and frees the table. A null header is ignored.
+    fn map_release(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_map_release",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_release = self.helper("venti_rc_release")?;
+        let str_release = self.helper("venti_str_release")?;
+        let free = self.libc("free")?;
+        let entry = self.block(function, "entry");
+        let last = self.block(function, "last");
+        let loop_block = self.block(function, "loop");
+        let body = self.block(function, "body");
+        let exit = self.block(function, "exit");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        self.skip_null(entry, header, done)?;
+        let count = self
+            .builder
+            .build_struct_gep(self.map_header_type(), header, 4, "count")?;
+        let is_last = self
+            .call(rc_release, &[count.into()], "is_last")?
+            .into_int_value();
+        self.builder.build_conditional_branch(is_last, last, done)?;
+
+        self.builder.position_at_end(last);
+        let capacity = self
+            .load_header_field(header, 0, "capacity")?
+            .into_int_value();
+        let keys = self
+            .load_header_field(header, 2, "keys")?
+            .into_pointer_value();
+        let values = self.load_header_field(header, 3, "values")?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let in_range =
+            self.builder
+                .build_int_compare(IntPredicate::ULT, index_value, capacity, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, body, exit)?;
+
+        // Empty slots hold null keys, which venti_str_release ignores
+        self.builder.position_at_end(body);
+        let key_slot = self.slot_ptr(keys, index_value, "key_slot")?;
+        let key = self.builder.build_load(self.ptr_type(), key_slot, "key")?;
+        self.builder.build_call(str_release, &[key.into()], "")?;
+        let next_index =
+            self.builder
+                .build_int_add(index_value, i64_type.const_int(1, false), "next_index")?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        index.add_incoming(&[(&i64_type.const_zero(), last), (&next_index, body)]);
+
+        self.builder.position_at_end(exit);
+        self.builder.build_call(free, &[keys.into()], "")?;
+        self.builder.build_call(free, &[values.into()], "")?;
+        self.builder.build_call(free, &[header.into()], "")?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// Raises a runtime error whose message is built from the printf `format` and `args`,
+    /// which the innermost `try_venti` block catches, or which otherwise ends the program.
+    /// The current block ends in `unreachable`.
+    fn runtime_error(
+        &self,
+        format: &str,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) -> Result<(), VentiError> {
+        let i64_type = self.context.i64_type();
+        let snprintf = self.libc("snprintf")?;
+        let malloc = self.libc("malloc")?;
+        let raise = self.helper("venti_raise")?;
+        let format = self
+            .builder
+            .build_global_string_ptr(format, "error")?
+            .as_pointer_value();
+
+        // Measuring the message first lets keys of any length appear in it
+        let mut measure_args = vec![
+            self.ptr_type().const_null().into(),
+            i64_type.const_zero().into(),
+            format.into(),
+        ];
+        measure_args.extend_from_slice(args);
+        let len = self.call(snprintf, &measure_args, "len")?.into_int_value();
+        let len = self.builder.build_int_s_extend(len, i64_type, "len")?;
+        let size = self
+            .builder
+            .build_int_add(len, i64_type.const_int(1, false), "size")?;
+        let message = self
+            .call(malloc, &[size.into()], "message")?
+            .into_pointer_value();
+        let mut format_args = vec![message.into(), size.into(), format.into()];
+        format_args.extend_from_slice(args);
+        self.builder.build_call(snprintf, &format_args, "")?;
+        self.builder.build_call(raise, &[message.into()], "")?;
+        self.builder.build_unreachable()?;
+        Ok(())
+    }
+
+    /// `i64 venti_int_div(i64 left, i64 right)`: signed division, where dividing by zero is
+    /// a runtime error and dividing the smallest integer by -1 wraps.
+    fn int_div(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_int_div",
+            i64_type.fn_type(&[i64_type.into(), i64_type.into()], false),
+        );
+        let left = param(function, 0)?.into_int_value();
+        let right = param(function, 1)?.into_int_value();
+        let entry = self.block(function, "entry");
+        let check = self.block(function, "check");
+        let negate = self.block(function, "negate");
+        let divide = self.block(function, "divide");
+        let failed = self.block(function, "failed");
+
+        self.builder.position_at_end(entry);
+        let is_zero = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            right,
+            i64_type.const_zero(),
+            "is_zero",
+        )?;
+        self.builder
+            .build_conditional_branch(is_zero, failed, check)?;
+
+        // `sdiv` of the smallest integer by -1 overflows, which traps on x86
+        self.builder.position_at_end(check);
+        let is_minus_one = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            right,
+            i64_type.const_all_ones(),
+            "is_minus_one",
+        )?;
+        self.builder
+            .build_conditional_branch(is_minus_one, negate, divide)?;
+
+        self.builder.position_at_end(negate);
+        let negated = self.builder.build_int_neg(left, "negated")?;
+        self.builder.build_return(Some(&negated))?;
+
+        self.builder.position_at_end(divide);
+        let quotient = self.builder.build_int_signed_div(left, right, "quotient")?;
+        self.builder.build_return(Some(&quotient))?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error("division by zero", &[])?;
+        Ok(function)
+    }
+
+    /// `ptr venti_buffer_alloc(i64 len)`: allocates `len` zeroed slots; a negative length
+    /// or running out of memory is a runtime error.
+    fn buffer_alloc(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_buffer_alloc",
+            self.ptr_type().fn_type(&[i64_type.into()], false),
+        );
+        let len = param(function, 0)?.into_int_value();
+        let calloc = self.libc("calloc")?;
+        let entry = self.block(function, "entry");
+        let allocate = self.block(function, "allocate");
+        let check = self.block(function, "check");
+        let failed = self.block(function, "failed");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let is_negative = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            len,
+            i64_type.const_zero(),
+            "is_negative",
+        )?;
+        self.builder
+            .build_conditional_branch(is_negative, failed, allocate)?;
+
+        self.builder.position_at_end(allocate);
+        let data = self
+            .call(
+                calloc,
+                &[len.into(), i64_type.const_int(8, false).into()],
+                "data",
+            )?
+            .into_pointer_value();
+        let is_null = self.builder.build_is_null(data, "is_null")?;
+        self.builder
+            .build_conditional_branch(is_null, check, done)?;
+
+        // calloc may return null for an empty allocation without failing
+        self.builder.position_at_end(check);
+        let is_empty = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            len,
+            i64_type.const_zero(),
+            "is_empty",
+        )?;
+        self.builder
+            .build_conditional_branch(is_empty, done, failed)?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error("could not allocate a buffer of %ld slots", &[len.into()])?;
+
+        self.builder.position_at_end(done);
+        let result = self.builder.build_phi(self.ptr_type(), "result")?;
+        result.add_incoming(&[(&data, allocate), (&self.ptr_type().const_null(), check)]);
+        self.builder.build_return(Some(&result.as_basic_value()))?;
+        Ok(function)
+    }
+
+    /// `ptr venti_buffer_slot(ptr data, i64 len, i64 index)`: the address of slot `index`;
+    /// an index out of range is a runtime error.
+    fn buffer_slot(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_buffer_slot",
+            self.ptr_type().fn_type(
+                &[self.ptr_type().into(), i64_type.into(), i64_type.into()],
+                false,
+            ),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let index = param(function, 2)?.into_int_value();
+        let entry = self.block(function, "entry");
+        let in_range = self.block(function, "in_range");
+        let out_of_range = self.block(function, "out_of_range");
+
+        self.builder.position_at_end(entry);
+        // Compared unsigned, a negative index is past the end too
+        let is_in_range =
+            self.builder
+                .build_int_compare(IntPredicate::ULT, index, len, "is_in_range")?;
+        self.builder
+            .build_conditional_branch(is_in_range, in_range, out_of_range)?;
+
+        self.builder.position_at_end(in_range);
+        let slot = self.slot_ptr(data, index, "slot")?;
+        self.builder.build_return(Some(&slot))?;
+
+        self.builder.position_at_end(out_of_range);
+        self.runtime_error(
+            "index %ld is out of bounds for a buffer of length %ld",
+            &[index.into(), len.into()],
+        )?;
+        Ok(function)
+    }
+
+    /// Returns a pointer to field `index` of the string vector header behind `header`.
+    fn string_vector_field(
+        &self,
+        header: PointerValue<'ctx>,
+        index: u32,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        Ok(self.builder.build_struct_gep(
+            string_vector_header_type(self.context),
+            header,
+            index,
+            name,
+        )?)
+    }
+
+    /// `ptr venti_strvec_new(i64 len)`: allocates a string vector with room for `len`
+    /// strings, which the caller stores with `string_vector_store`.
+    fn strvec_new(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_strvec_new",
+            self.ptr_type().fn_type(&[i64_type.into()], false),
+        );
+        let len = param(function, 0)?.into_int_value();
+        let malloc = self.libc("malloc")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let header_size = string_vector_header_type(self.context)
+            .size_of()
+            .ok_or_else(|| {
+                VentiError::CodegenError("String vector header is unsized".to_string(), None)
+            })?;
+        let header = self
+            .call(malloc, &[header_size.into()], "header")?
+            .into_pointer_value();
+        // A string is a pointer and a length, 16 bytes
+        let slots_size =
+            self.builder
+                .build_int_mul(len, i64_type.const_int(16, false), "slots_size")?;
+        let slots = self.call(malloc, &[slots_size.into()], "slots")?;
+        let fields: [(u32, &str, BasicValueEnum<'ctx>); 3] = [
+            (0, "len", len.into()),
+            (1, "slots", slots),
+            // The vector is unowned until it is stored in a variable
+            (2, "count", i64_type.const_zero().into()),
+        ];
+        for (index, name, value) in fields {
+            let field = self.string_vector_field(header, index, name)?;
+            self.builder.build_store(field, value)?;
+        }
+        self.builder.build_return(Some(&header))?;
+        Ok(function)
+    }
+
+    /// Stores `string` in slot `index` of the string vector `slots`, which takes a
+    /// reference to it.
+    fn string_vector_store(
+        &self,
+        slots: PointerValue<'ctx>,
+        index: IntValue<'ctx>,
+        string: BasicValueEnum<'ctx>,
+    ) -> Result<(), VentiError> {
+        let retain = self.helper("venti_str_retain")?;
+        let slot = unsafe {
+            self.builder
+                .build_in_bounds_gep(string_type(self.context), slots, &[index], "slot")?
+        };
+        self.builder.build_store(slot, string)?;
+        let data = self
+            .builder
+            .build_extract_value(string.into_struct_value(), 0, "data")?;
+        self.builder.build_call(retain, &[data.into()], "")?;
+        Ok(())
+    }
+
+    /// `ptr venti_strvec_slot(ptr header, i64 index)`: the address of string `index` of a
+    /// string vector; an index out of range is a runtime error.
+    fn strvec_slot(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_strvec_slot",
+            self.ptr_type()
+                .fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let index = param(function, 1)?.into_int_value();
+        let entry = self.block(function, "entry");
+        let in_range = self.block(function, "in_range");
+        let out_of_range = self.block(function, "out_of_range");
+
+        self.builder.position_at_end(entry);
+        let len_field = self.string_vector_field(header, 0, "len_field")?;
+        let len = self
+            .builder
+            .build_load(i64_type, len_field, "len")?
+            .into_int_value();
+        // Compared unsigned, a negative index is past the end too
+        let is_in_range =
+            self.builder
+                .build_int_compare(IntPredicate::ULT, index, len, "is_in_range")?;
+        self.builder
+            .build_conditional_branch(is_in_range, in_range, out_of_range)?;
+
+        self.builder.position_at_end(in_range);
+        let slots_field = self.string_vector_field(header, 1, "slots_field")?;
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?
+            .into_pointer_value();
+        let slot = unsafe {
+            self.builder
+                .build_in_bounds_gep(string_type(self.context), slots, &[index], "slot")?
+        };
+        self.builder.build_return(Some(&slot))?;
+
+        self.builder.position_at_end(out_of_range);
+        self.runtime_error(
+            "index %ld is out of bounds for a vector of length %ld",
+            &[index.into(), len.into()],
+        )?;
+        Ok(function)
+    }
+
+    /// `void venti_strvec_retain(ptr header)`: adds a reference to a string vector.
+    fn strvec_retain(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_strvec_retain",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_retain = self.helper("venti_rc_retain")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let count = self.string_vector_field(header, 2, "count")?;
+        self.builder.build_call(rc_retain, &[count.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_strvec_release(ptr header)`: drops a reference to a string vector; the
+    /// last one releases each of its strings and frees the slots. A null header is ignored.
+    fn strvec_release(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_strvec_release",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_release = self.helper("venti_rc_release")?;
+        let str_release = self.helper("venti_str_release")?;
+        let free = self.libc("free")?;
+        let entry = self.block(function, "entry");
+        let last = self.block(function, "last");
+        let loop_block = self.block(function, "loop");
+        let body = self.block(function, "body");
+        let freed = self.block(function, "freed");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        self.skip_null(entry, header, done)?;
+        let count = self.string_vector_field(header, 2, "count")?;
+        let is_last = self
+            .call(rc_release, &[count.into()], "is_last")?
+            .into_int_value();
+        self.builder.build_conditional_branch(is_last, last, done)?;
+
+        self.builder.position_at_end(last);
+        let len_field = self.string_vector_field(header, 0, "len_field")?;
+        let len = self
+            .builder
+            .build_load(i64_type, len_field, "len")?
+            .into_int_value();
+        let slots_field = self.string_vector_field(header, 1, "slots_field")?;
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?
+            .into_pointer_value();
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let has_next =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, index_value, len, "has_next")?;
+        self.builder
+            .build_conditional_branch(has_next, body, freed)?;
+
+        self.builder.position_at_end(body);
+        let slot = unsafe {
+            self.builder.build_in_bounds_gep(
+                string_type(self.context),
+                slots,
+                &[index_value],
+                "slot",
+            )?
+        };
+        let data = self.builder.build_load(self.ptr_type(), slot, "data")?;
+        self.builder.build_call(str_release, &[data.into()], "")?;
+        let next = self
+            .builder
+            .build_int_add(index_value, i64_type.const_int(1, false), "next")?;
+        self.builder.build_unconditional_branch(loop_block)?;
+        index.add_incoming(&[(&i64_type.const_zero(), last), (&next, body)]);
+
+        self.builder.position_at_end(freed);
+        self.builder.build_call(free, &[slots.into()], "")?;
+        self.builder.build_call(free, &[header.into()], "")?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// Returns a pointer to field `index` of the task header at the start of `frame`.
+    fn task_field(
+        &self,
+        frame: PointerValue<'ctx>,
+        index: u32,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_struct_gep(task_type(self.context), frame, index, name)?)
+    }
+
+    /// `void venti_task_spawn(ptr frame, ptr entry)`: starts a thread running `entry(frame)`;
+    /// failing to start one is a runtime error.
+    fn task_spawn(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_task_spawn",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into(), self.ptr_type().into()], false),
+        );
+        let frame = param(function, 0)?.into_pointer_value();
+        let entry_point = param(function, 1)?.into_pointer_value();
+        let pthread_create = self.libc("pthread_create")?;
+
+        let entry = self.block(function, "entry");
+        let failed = self.block(function, "failed");
+        let started = self.block(function, "started");
+
+        self.builder.position_at_end(entry);
+        let thread = self.task_field(frame, 0, "thread")?;
+        let status = self
+            .call(
+                pthread_create,
+                &[
+                    thread.into(),
+                    self.ptr_type().const_null().into(),
+                    entry_point.into(),
+                    frame.into(),
+                ],
+                "status",
+            )?
+            .into_int_value();
+        let is_failed = self.builder.build_int_compare(
+            IntPredicate::NE,
+            status,
+            status.get_type().const_zero(),
+            "is_failed",
+        )?;
+        self.builder
+            .build_conditional_branch(is_failed, failed, started)?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error("could not start task", &[])?;
+
+        self.builder.position_at_end(started);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `i64 venti_task_await(ptr frame)`: waits for the task to finish and returns its result.
+    fn task_await(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_task_await",
+            i64_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let frame = param(function, 0)?.into_pointer_value();
+        let pthread_join = self.libc("pthread_join")?;
+
+        let entry = self.block(function, "entry");
+        let join = self.block(function, "join");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let joined_ptr = self.task_field(frame, 2, "joined_ptr")?;
+        let joined = self
+            .builder
+            .build_load(i64_type, joined_ptr, "joined")?
+            .into_int_value();
+        let is_joined = self.builder.build_int_compare(
+            IntPredicate::NE,
+            joined,
+            i64_type.const_zero(),
+            "is_joined",
+        )?;
+        self.builder
+            .build_conditional_branch(is_joined, done, join)?;
+
+        // A thread can only be joined once, so later awaits just read the result
+        self.builder.position_at_end(join);
+        let thread_ptr = self.task_field(frame, 0, "thread_ptr")?;
+        let thread = self.builder.build_load(i64_type, thread_ptr, "thread")?;
+        self.builder.build_call(
+            pthread_join,
+            &[thread.into(), self.ptr_type().const_null().into()],
+            "",
+        )?;
+        self.builder
+            .build_store(joined_ptr, i64_type.const_int(1, false))?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        let result_ptr = self.task_field(frame, 1, "result_ptr")?;
+        let result = self.builder.build_load(i64_type, result_ptr, "result")?;
+        self.builder.build_return(Some(&result))?;
+        Ok(function)
+    }
+
+    /// Returns a pointer to field `index` of the channel header behind `header`.
+    fn channel_field(
+        &self,
+        header: PointerValue<'ctx>,
+        index: u32,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_struct_gep(channel_header_type(self.context), header, index, name)?)
+    }
+
+    /// `ptr venti_channel_new()`: allocates an empty channel.
+    fn channel_new(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare("venti_channel_new", self.ptr_type().fn_type(&[], false));
+        let malloc = self.libc("malloc")?;
+        let mutex_init = self.libc("pthread_mutex_init")?;
+        let cond_init = self.libc("pthread_cond_init")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let header_size = channel_header_type(self.context).size_of().ok_or_else(|| {
+            VentiError::CodegenError("Channel header is unsized".to_string(), None)
+        })?;
+        let header = self
+            .call(malloc, &[header_size.into()], "header")?
+            .into_pointer_value();
+        let initial_capacity = 8;
+        let slots = self.call(
+            malloc,
+            &[i64_type.const_int(initial_capacity * 8, false).into()],
+            "slots",
+        )?;
+        for (index, name, value) in [
+            (0, "len", i64_type.const_zero()),
+            (1, "head", i64_type.const_zero()),
+            (2, "capacity", i64_type.const_int(initial_capacity, false)),
+            // The channel is unowned until it is stored in a variable
+            (4, "count", i64_type.const_zero()),
+        ] {
+            let field = self.channel_field(header, index, name)?;
+            self.builder.build_store(field, value)?;
+        }
+        let slots_field = self.channel_field(header, 3, "slots_field")?;
+        self.builder.build_store(slots_field, slots)?;
+        let null = self.ptr_type().const_null();
+        let lock = self.channel_field(header, 5, "lock")?;
+        self.builder
+            .build_call(mutex_init, &[lock.into(), null.into()], "")?;
+        let ready = self.channel_field(header, 6, "ready")?;
+        self.builder
+            .build_call(cond_init, &[ready.into(), null.into()], "")?;
+        self.builder.build_return(Some(&header))?;
+        Ok(function)
+    }
+
+    /// `void venti_channel_send(ptr header, i64 value)`: adds `value` to the back of a
+    /// channel's queue, growing it when it is full, and wakes a task waiting to receive.
+    fn channel_send(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_channel_send",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let value = param(function, 1)?.into_int_value();
+        let lock_fn = self.libc("pthread_mutex_lock")?;
+        let unlock_fn = self.libc("pthread_mutex_unlock")?;
+        let signal_fn = self.libc("pthread_cond_signal")?;
+        let realloc = self.libc("realloc")?;
+        let entry = self.block(function, "entry");
+        let grow = self.block(function, "grow");
+        let store = self.block(function, "store");
+
+        self.builder.position_at_end(entry);
+        let lock = self.channel_field(header, 5, "lock")?;
+        self.builder.build_call(lock_fn, &[lock.into()], "")?;
+        let len_field = self.channel_field(header, 0, "len_field")?;
+        let head_field = self.channel_field(header, 1, "head_field")?;
+        let capacity_field = self.channel_field(header, 2, "capacity_field")?;
+        let slots_field = self.channel_field(header, 3, "slots_field")?;
+        let len = self
+            .builder
+            .build_load(i64_type, len_field, "len")?
+            .into_int_value();
+        let head = self
+            .builder
+            .build_load(i64_type, head_field, "head")?
+            .into_int_value();
+        let capacity = self
+            .builder
+            .build_load(i64_type, capacity_field, "capacity")?
+            .into_int_value();
+        let is_full = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, len, capacity, "is_full")?;
+        self.builder
+            .build_conditional_branch(is_full, grow, store)?;
+
+        // A full queue wraps around from `head` to the slot before it, so once the slots
+        // double, moving the wrapped part past the old end puts the queue back in order
+        self.builder.position_at_end(grow);
+        let slot_size = i64_type.const_int(8, false);
+        let old_slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "old_slots")?;
+        let new_capacity = self
+            .builder
+            .build_int_add(capacity, capacity, "new_capacity")?;
+        let new_size = self
+            .builder
+            .build_int_mul(new_capacity, slot_size, "new_size")?;
+        let new_slots = self
+            .call(realloc, &[old_slots.into(), new_size.into()], "new_slots")?
+            .into_pointer_value();
+        let wrapped_end = self.slot_ptr(new_slots, capacity, "wrapped_end")?;
+        let wrapped_size = self
+            .builder
+            .build_int_mul(head, slot_size, "wrapped_size")?;
+        self.builder
+            .build_memcpy(wrapped_end, 8, new_slots, 8, wrapped_size)?;
+        self.builder.build_store(slots_field, new_slots)?;
+        self.builder.build_store(capacity_field, new_capacity)?;
+        self.builder.build_unconditional_branch(store)?;
+
+        self.builder.position_at_end(store);
+        let capacity_phi = self.builder.build_phi(i64_type, "current_capacity")?;
+        capacity_phi.add_incoming(&[(&capacity, entry), (&new_capacity, grow)]);
+        let current_capacity = capacity_phi.as_basic_value().into_int_value();
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?
+            .into_pointer_value();
+        let back = self.builder.build_int_add(head, len, "back")?;
+        let mask =
+            self.builder
+                .build_int_sub(current_capacity, i64_type.const_int(1, false), "mask")?;
+        let back = self.builder.build_and(back, mask, "back_slot")?;
+        let slot = self.slot_ptr(slots, back, "slot")?;
+        self.builder.build_store(slot, value)?;
+        let new_len = self
+            .builder
+            .build_int_add(len, i64_type.const_int(1, false), "new_len")?;
+        self.builder.build_store(len_field, new_len)?;
+        let ready = self.channel_field(header, 6, "ready")?;
+        self.builder.build_call(signal_fn, &[ready.into()], "")?;
+        self.builder.build_call(unlock_fn, &[lock.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `i64 venti_channel_recv(ptr header)`: takes the value at the front of a channel's
+    /// queue, waiting until another task sends one if it is empty.
+    fn channel_recv(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_channel_recv",
+            i64_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let lock_fn = self.libc("pthread_mutex_lock")?;
+        let unlock_fn = self.libc("pthread_mutex_unlock")?;
+        let wait_fn = self.libc("pthread_cond_wait")?;
+        let entry = self.block(function, "entry");
+        let check = self.block(function, "check");
+        let wait = self.block(function, "wait");
+        let take = self.block(function, "take");
+
+        self.builder.position_at_end(entry);
+        let lock = self.channel_field(header, 5, "lock")?;
+        let ready = self.channel_field(header, 6, "ready")?;
+        let len_field = self.channel_field(header, 0, "len_field")?;
+        self.builder.build_call(lock_fn, &[lock.into()], "")?;
+        self.builder.build_unconditional_branch(check)?;
+
+        // A wait can end without a send, so the queue is checked again after each one
+        self.builder.position_at_end(check);
+        let len = self
+            .builder
+            .build_load(i64_type, len_field, "len")?
+            .into_int_value();
+        let is_empty = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            len,
+            i64_type.const_zero(),
+            "is_empty",
+        )?;
+        self.builder
+            .build_conditional_branch(is_empty, wait, take)?;
+
+        self.builder.position_at_end(wait);
+        self.builder
+            .build_call(wait_fn, &[ready.into(), lock.into()], "")?;
+        self.builder.build_unconditional_branch(check)?;
+
+        self.builder.position_at_end(take);
+        let head_field = self.channel_field(header, 1, "head_field")?;
+        let capacity_field = self.channel_field(header, 2, "capacity_field")?;
+        let slots_field = self.channel_field(header, 3, "slots_field")?;
+        let head = self
+            .builder
+            .build_load(i64_type, head_field, "head")?
+            .into_int_value();
+        let capacity = self
+            .builder
+            .build_load(i64_type, capacity_field, "capacity")?
+            .into_int_value();
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?
+            .into_pointer_value();
+        let slot = self.slot_ptr(slots, head, "slot")?;
+        let value = self.builder.build_load(i64_type, slot, "value")?;
+        let one = i64_type.const_int(1, false);
+        let next_head = self.builder.build_int_add(head, one, "next_head")?;
+        let mask = self.builder.build_int_sub(capacity, one, "mask")?;
+        let next_head = self.builder.build_and(next_head, mask, "next_slot")?;
+        self.builder.build_store(head_field, next_head)?;
+        let new_len = self.builder.build_int_sub(len, one, "new_len")?;
+        self.builder.build_store(len_field, new_len)?;
+        self.builder.build_call(unlock_fn, &[lock.into()], "")?;
+        self.builder.build_return(Some(&value))?;
+        Ok(function)
+    }
+
+    /// `void venti_channel_retain(ptr header)`: adds a reference to a channel.
+    fn channel_retain(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_channel_retain",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_retain = self.helper("venti_rc_retain")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let count = self.channel_field(header, 4, "count")?;
+        self.builder.build_call(rc_retain, &[count.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_channel_release(ptr header)`: drops a reference to a channel; the last
+    /// one frees its queue, whatever values are still in it. A null header is ignored.
+    fn channel_release(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_channel_release",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_release = self.helper("venti_rc_release")?;
+        let mutex_destroy = self.libc("pthread_mutex_destroy")?;
+        let cond_destroy = self.libc("pthread_cond_destroy")?;
+        let free = self.libc("free")?;
+        let entry = self.block(function, "entry");
+        let last = self.block(function, "last");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        self.skip_null(entry, header, done)?;
+        let count = self.channel_field(header, 4, "count")?;
+        let is_last = self
+            .call(rc_release, &[count.into()], "is_last")?
+            .into_int_value();
+        self.builder.build_conditional_branch(is_last, last, done)?;
+
+        self.builder.position_at_end(last);
+        let lock = self.channel_field(header, 5, "lock")?;
+        self.builder.build_call(mutex_destroy, &[lock.into()], "")?;
+        let ready = self.channel_field(header, 6, "ready")?;
+        self.builder.build_call(cond_destroy, &[ready.into()], "")?;
+        let slots_field = self.channel_field(header, 3, "slots_field")?;
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?;
+        self.builder.build_call(free, &[slots.into()], "")?;
+        self.builder.build_call(free, &[header.into()], "")?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// Returns a pointer to field `index` of the vector header behind `header`.
+    fn vector_field(
+        &self,
+        header: PointerValue<'ctx>,
+        index: u32,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        Ok(self
+            .builder
+            .build_struct_gep(vector_header_type(self.context), header, index, name)?)
+    }
+
+    /// `ptr venti_vec_new()`: allocates an empty vector.
+    fn vec_new(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare("venti_vec_new", self.ptr_type().fn_type(&[], false));
+        let malloc = self.libc("malloc")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let header_size = vector_header_type(self.context).size_of().ok_or_else(|| {
+            VentiError::CodegenError("Vector header is unsized".to_string(), None)
+        })?;
+        let header = self
+            .call(malloc, &[header_size.into()], "header")?
+            .into_pointer_value();
+        let initial_capacity = 8;
+        let slots = self.call(
+            malloc,
+            &[i64_type.const_int(initial_capacity * 8, false).into()],
+            "slots",
+        )?;
+        for (index, name, value) in [
+            (0, "len", i64_type.const_zero()),
+            (1, "capacity", i64_type.const_int(initial_capacity, false)),
+            // The vector is unowned until it is stored in a variable
+            (3, "count", i64_type.const_zero()),
+        ] {
+            let field = self.vector_field(header, index, name)?;
+            self.builder.build_store(field, value)?;
+        }
+        let slots_field = self.vector_field(header, 2, "slots_field")?;
+        self.builder.build_store(slots_field, slots)?;
+        self.builder.build_return(Some(&header))?;
+        Ok(function)
+    }
+
+    /// `void venti_vec_push(ptr header, i64 value)`: adds `value` to the end of a vector,
+    /// doubling its slots when they are full; running out of memory is a runtime error.
+    fn vec_push(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_vec_push",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let value = param(function, 1)?.into_int_value();
+        let realloc = self.libc("realloc")?;
+        let entry = self.block(function, "entry");
+        let grow = self.block(function, "grow");
+        let grown = self.block(function, "grown");
+        let failed = self.block(function, "failed");
+        let store = self.block(function, "store");
+
+        self.builder.position_at_end(entry);
+        let len_field = self.vector_field(header, 0, "len_field")?;
+        let capacity_field = self.vector_field(header, 1, "capacity_field")?;
+        let slots_field = self.vector_field(header, 2, "slots_field")?;
+        let len = self
+            .builder
+            .build_load(i64_type, len_field, "len")?
+            .into_int_value();
+        let capacity = self
+            .builder
+            .build_load(i64_type, capacity_field, "capacity")?
+            .into_int_value();
+        let is_full = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, len, capacity, "is_full")?;
+        self.builder
+            .build_conditional_branch(is_full, grow, store)?;
+
+        self.builder.position_at_end(grow);
+        let old_slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "old_slots")?;
+        let new_capacity = self
+            .builder
+            .build_int_add(capacity, capacity, "new_capacity")?;
+        let new_size =
+            self.builder
+                .build_int_mul(new_capacity, i64_type.const_int(8, false), "new_size")?;
+        let new_slots = self
+            .call(realloc, &[old_slots.into(), new_size.into()], "new_slots")?
+            .into_pointer_value();
+        let is_null = self.builder.build_is_null(new_slots, "is_null")?;
+        self.builder
+            .build_conditional_branch(is_null, failed, grown)?;
+
+        self.builder.position_at_end(grown);
+        self.builder.build_store(slots_field, new_slots)?;
+        self.builder.build_store(capacity_field, new_capacity)?;
+        self.builder.build_unconditional_branch(store)?;
+
+        self.builder.position_at_end(store);
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?
+            .into_pointer_value();
+        let slot = self.slot_ptr(slots, len, "slot")?;
+        self.builder.build_store(slot, value)?;
+        let new_len = self
+            .builder
+            .build_int_add(len, i64_type.const_int(1, false), "new_len")?;
+        self.builder.build_store(len_field, new_len)?;
+        self.builder.build_return(None)?;
+
+        self.builder.position_at_end(failed);
+        self.runtime_error(
+            "could not grow a vector to %ld elements",
+            &[new_capacity.into()],
+        )?;
+        Ok(function)
+    }
+
+    /// `i64 venti_vec_pop(ptr header)`: removes the last element of a vector and returns
+    /// it; popping an empty vector is a runtime error.
+    fn vec_pop(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_vec_pop",
+            i64_type.fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let take = self.block(function, "take");
+        let empty = self.block(function, "empty");
+
+        self.builder.position_at_end(entry);
+        let len_field = self.vector_field(header, 0, "len_field")?;
+        let len = self
+            .builder
+            .build_load(i64_type, len_field, "len")?
+            .into_int_value();
+        let is_empty = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            len,
+            i64_type.const_zero(),
+            "is_empty",
+        )?;
+        self.builder
+            .build_conditional_branch(is_empty, empty, take)?;
+
+        self.builder.position_at_end(take);
+        let last = self
+            .builder
+            .build_int_sub(len, i64_type.const_int(1, false), "last")?;
+        let slots_field = self.vector_field(header, 2, "slots_field")?;
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?
+            .into_pointer_value();
+        let slot = self.slot_ptr(slots, last, "slot")?;
+        let value = self.builder.build_load(i64_type, slot, "value")?;
+        self.builder.build_store(len_field, last)?;
+        self.builder.build_return(Some(&value))?;
+
+        self.builder.position_at_end(empty);
+        self.runtime_error("cannot pop from an empty vector", &[])?;
+        Ok(function)
+    }
+
+    /// `ptr venti_vec_slot(ptr header, i64 index)`: the address of element `index` of a
+    /// vector; an index out of range is a runtime error.
+    fn vec_slot(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_vec_slot",
+            self.ptr_type()
+                .fn_type(&[self.ptr_type().into(), i64_type.into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let index = param(function, 1)?.into_int_value();
+        let entry = self.block(function, "entry");
+        let in_range = self.block(function, "in_range");
+        let out_of_range = self.block(function, "out_of_range");
+
+        self.builder.position_at_end(entry);
+        let len_field = self.vector_field(header, 0, "len_field")?;
+        let len = self
+            .builder
+            .build_load(i64_type, len_field, "len")?
+            .into_int_value();
+        // Compared unsigned, a negative index is past the end too
+        let is_in_range =
+            self.builder
+                .build_int_compare(IntPredicate::ULT, index, len, "is_in_range")?;
+        self.builder
+            .build_conditional_branch(is_in_range, in_range, out_of_range)?;
+
+        self.builder.position_at_end(in_range);
+        let slots_field = self.vector_field(header, 2, "slots_field")?;
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?
+            .into_pointer_value();
+        let slot = self.slot_ptr(slots, index, "slot")?;
+        self.builder.build_return(Some(&slot))?;
+
+        self.builder.position_at_end(out_of_range);
+        self.runtime_error(
+            "index %ld is out of bounds for a vector of length %ld",
+            &[index.into(), len.into()],
+        )?;
+        Ok(function)
+    }
+
+    /// `void venti_vec_extend(ptr header, ptr source, i64 start, i64 end)`: pushes the
+    /// elements of `source` from `start` up to `end`, which must lie within it, onto a
+    /// vector.
+    fn vec_extend(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.ptr_type();
+        let function = self.declare(
+            "venti_vec_extend",
+            self.context.void_type().fn_type(
+                &[
+                    ptr_type.into(),
+                    ptr_type.into(),
+                    i64_type.into(),
+                    i64_type.into(),
+                ],
+                false,
+            ),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let source = param(function, 1)?.into_pointer_value();
+        let start = param(function, 2)?.into_int_value();
+        let end = param(function, 3)?.into_int_value();
+        let push = self.helper("venti_vec_push")?;
+        let entry = self.block(function, "entry");
+        let check = self.block(function, "check");
+        let body = self.block(function, "body");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        self.builder.build_unconditional_branch(check)?;
+
+        self.builder.position_at_end(check);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let has_more =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, index_value, end, "has_more")?;
+        self.builder
+            .build_conditional_branch(has_more, body, done)?;
+
+        self.builder.position_at_end(body);
+        // Reloaded each time, since pushing a vector onto itself may move its slots
+        let slots_field = self.vector_field(source, 2, "slots_field")?;
+        let slots = self
+            .builder
+            .build_load(ptr_type, slots_field, "slots")?
+            .into_pointer_value();
+        let slot = self.slot_ptr(slots, index_value, "slot")?;
+        let value = self.builder.build_load(i64_type, slot, "value")?;
+        self.builder
+            .build_call(push, &[header.into(), value.into()], "")?;
+        let next = self
+            .builder
+            .build_int_add(index_value, i64_type.const_int(1, false), "next")?;
+        self.builder.build_unconditional_branch(check)?;
+
+        index.add_incoming(&[(&start, entry), (&next, body)]);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `ptr venti_vec_range(ptr header, i64 start, i64 end)`: a new vector holding the
+    /// elements from `start` up to `end`, as `v[start..end]` gives. Offsets outside the
+    /// vector and a start past the end are runtime errors.
+    fn vec_range(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_vec_range",
+            self.ptr_type().fn_type(
+                &[self.ptr_type().into(), i64_type.into(), i64_type.into()],
+                false,
+            ),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let start = param(function, 1)?.into_int_value();
+        let end = param(function, 2)?.into_int_value();
+        let new = self.helper("venti_vec_new")?;
+        let extend = self.helper("venti_vec_extend")?;
+        let entry = self.block(function, "entry");
+        let copy = self.block(function, "copy");
+        let out_of_range = self.block(function, "out_of_range");
+
+        self.builder.position_at_end(entry);
+        let len_field = self.vector_field(header, 0, "len_field")?;
+        let len = self
+            .builder
+            .build_load(i64_type, len_field, "len")?
+            .into_int_value();
+        let checks = [
+            (
+                IntPredicate::SLT,
+                start,
+                i64_type.const_zero(),
+                "starts_before",
+            ),
+            (IntPredicate::SGT, start, end, "is_reversed"),
+            (IntPredicate::SGT, end, len, "ends_after"),
+        ];
+        let mut is_invalid = self.context.bool_type().const_zero();
+        for (predicate, left, right, name) in checks {
+            let check = self
+                .builder
+                .build_int_compare(predicate, left, right, name)?;
+            is_invalid = self.builder.build_or(is_invalid, check, "is_invalid")?;
+        }
+        self.builder
+            .build_conditional_branch(is_invalid, out_of_range, copy)?;
+
+        self.builder.position_at_end(copy);
+        let slice = self.call(new, &[], "slice")?;
+        self.builder.build_call(
+            extend,
+            &[slice.into(), header.into(), start.into(), end.into()],
+            "",
+        )?;
+        self.builder.build_return(Some(&slice))?;
+
+        self.builder.position_at_end(out_of_range);
+        self.runtime_error(
+            "vector slice %ld..%ld is out of range for a vector of length %ld",
+            &[start.into(), end.into(), len.into()],
+        )?;
+        Ok(function)
+    }
+
+    /// `ptr venti_vec_concat(ptr left, ptr right)`: a new vector holding the elements of
+    /// `left` followed by those of `right`, as `left + right` gives.
+    fn vec_concat(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_vec_concat",
+            self.ptr_type()
+                .fn_type(&[self.ptr_type().into(), self.ptr_type().into()], false),
+        );
+        let new = self.helper("venti_vec_new")?;
+        let extend = self.helper("venti_vec_extend")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let joined = self.call(new, &[], "joined")?;
+        for index in 0..2 {
+            let source = param(function, index)?.into_pointer_value();
+            let len_field = self.vector_field(source, 0, "len_field")?;
+            let len = self.builder.build_load(i64_type, len_field, "len")?;
+            self.builder.build_call(
+                extend,
+                &[
+                    joined.into(),
+                    source.into(),
+                    i64_type.const_zero().into(),
+                    len.into(),
+                ],
+                "",
+            )?;
+        }
+        self.builder.build_return(Some(&joined))?;
+        Ok(function)
+    }
+
+    /// `void venti_vec_retain(ptr header)`: adds a reference to a vector.
+    fn vec_retain(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_vec_retain",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_retain = self.helper("venti_rc_retain")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let count = self.vector_field(header, 3, "count")?;
+        self.builder.build_call(rc_retain, &[count.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_vec_release(ptr header)`: drops a reference to a vector; the last one
+    /// frees its slots. A null header is ignored.
+    fn vec_release(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_vec_release",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_release = self.helper("venti_rc_release")?;
+        let free = self.libc("free")?;
+        let entry = self.block(function, "entry");
+        let last = self.block(function, "last");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        self.skip_null(entry, header, done)?;
+        let count = self.vector_field(header, 3, "count")?;
+        let is_last = self
+            .call(rc_release, &[count.into()], "is_last")?
+            .into_int_value();
+        self.builder.build_conditional_branch(is_last, last, done)?;
+
+        self.builder.position_at_end(last);
+        let slots_field = self.vector_field(header, 2, "slots_field")?;
+        let slots = self
+            .builder
+            .build_load(self.ptr_type(), slots_field, "slots")?;
+        self.builder.build_call(free, &[slots.into()], "")?;
+        self.builder.build_call(free, &[header.into()], "")?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_vec_disown(ptr header)`: drops a reference to a vector without freeing
+    /// it, leaving it unowned for whoever it is returned to.
+    fn vec_disown(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_vec_disown",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_release = self.helper("venti_rc_release")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let count = self.vector_field(header, 3, "count")?;
+        self.builder.build_call(rc_release, &[count.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_sort_merge(ptr from, ptr to, i64 lo, i64 mid, i64 hi, ptr function,
+    /// ptr env)`: merges the sorted runs `from[lo..mid]` and `from[mid..hi]` into
+    /// `to[lo..hi]`. The right element of a pair goes first only when it belongs strictly
+    /// before the left, which keeps equal elements in order: when the closure `function` is
+    /// null that is when the left is greater, and otherwise when the closure gives a positive
+    /// result for the pair.
+    fn sort_merge(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.ptr_type();
+        let function = self.declare(
+            "venti_sort_merge",
+            self.context.void_type().fn_type(
+                &[
+                    ptr_type.into(),
+                    ptr_type.into(),
+                    i64_type.into(),
+                    i64_type.into(),
+                    i64_type.into(),
+                    ptr_type.into(),
+                    ptr_type.into(),
+                ],
+                false,
+            ),
+        );
+        let from = param(function, 0)?.into_pointer_value();
+        let to = param(function, 1)?.into_pointer_value();
+        let lo = param(function, 2)?.into_int_value();
+        let mid = param(function, 3)?.into_int_value();
+        let hi = param(function, 4)?.into_int_value();
+        let closure_fn = param(function, 5)?.into_pointer_value();
+        let env = param(function, 6)?;
+        let entry = self.block(function, "entry");
+        let check = self.block(function, "check");
+        let decide = self.block(function, "decide");
+        let check_right = self.block(function, "check_right");
+        let compare = self.block(function, "compare");
+        let by_value = self.block(function, "by_value");
+        let by_closure = self.block(function, "by_closure");
+        let take_left = self.block(function, "take_left");
+        let take_right = self.block(function, "take_right");
+        let store = self.block(function, "store");
+        let done = self.block(function, "done");
+        let one = i64_type.const_int(1, false);
+
+        self.builder.position_at_end(entry);
+        self.builder.build_unconditional_branch(check)?;
+
+        self.builder.position_at_end(check);
+        let k = self.builder.build_phi(i64_type, "k")?;
+        let i = self.builder.build_phi(i64_type, "i")?;
+        let j = self.builder.build_phi(i64_type, "j")?;
+        let (k_value, i_value, j_value) = (
+            k.as_basic_value().into_int_value(),
+            i.as_basic_value().into_int_value(),
+            j.as_basic_value().into_int_value(),
+        );
+        let has_more =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, k_value, hi, "has_more")?;
+        self.builder
+            .build_conditional_branch(has_more, decide, done)?;
+
+        self.builder.position_at_end(decide);
+        let left_done =
+            self.builder
+                .build_int_compare(IntPredicate::SGE, i_value, mid, "left_done")?;
+        self.builder
+            .build_conditional_branch(left_done, take_right, check_right)?;
+
+        self.builder.position_at_end(check_right);
+        let right_done =
+            self.builder
+                .build_int_compare(IntPredicate::SGE, j_value, hi, "right_done")?;
+        self.builder
+            .build_conditional_branch(right_done, take_left, compare)?;
+
+        self.builder.position_at_end(compare);
+        let left_slot = self.slot_ptr(from, i_value, "left_slot")?;
+        let left = self
+            .builder
+            .build_load(i64_type, left_slot, "left")?
+            .into_int_value();
+        let right_slot = self.slot_ptr(from, j_value, "right_slot")?;
+        let right = self
+            .builder
+            .build_load(i64_type, right_slot, "right")?
+            .into_int_value();
+        let is_default = self.builder.build_is_null(closure_fn, "is_default")?;
+        self.builder
+            .build_conditional_branch(is_default, by_value, by_closure)?;
+
+        self.builder.position_at_end(by_value);
+        let is_after =
+            self.builder
+                .build_int_compare(IntPredicate::SGT, left, right, "is_after")?;
+        self.builder
+            .build_conditional_branch(is_after, take_right, take_left)?;
+
+        self.builder.position_at_end(by_closure);
+        let order = self.call_closure(closure_fn, env, &[left, right], "order")?;
+        let is_after = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            order,
+            i64_type.const_zero(),
+            "is_after",
+        )?;
+        self.builder
+            .build_conditional_branch(is_after, take_right, take_left)?;
+
+        self.builder.position_at_end(take_left);
+        let slot = self.slot_ptr(from, i_value, "slot")?;
+        let left_value = self.builder.build_load(i64_type, slot, "left_value")?;
+        let next_i = self.builder.build_int_add(i_value, one, "next_i")?;
+        self.builder.build_unconditional_branch(store)?;
+
+        self.builder.position_at_end(take_right);
+        let slot = self.slot_ptr(from, j_value, "slot")?;
+        let right_value = self.builder.build_load(i64_type, slot, "right_value")?;
+        let next_j = self.builder.build_int_add(j_value, one, "next_j")?;
+        self.builder.build_unconditional_branch(store)?;
+
+        self.builder.position_at_end(store);
+        let value = self.builder.build_phi(i64_type, "value")?;
+        value.add_incoming(&[(&left_value, take_left), (&right_value, take_right)]);
+        let new_i = self.builder.build_phi(i64_type, "new_i")?;
+        new_i.add_incoming(&[(&next_i, take_left), (&i_value, take_right)]);
+        let new_j = self.builder.build_phi(i64_type, "new_j")?;
+        new_j.add_incoming(&[(&j_value, take_left), (&next_j, take_right)]);
+        let slot = self.slot_ptr(to, k_value, "slot")?;
+        self.builder.build_store(slot, value.as_basic_value())?;
+        let next_k = self.builder.build_int_add(k_value, one, "next_k")?;
+        self.builder.build_unconditional_branch(check)?;
+
+        k.add_incoming(&[(&lo, entry), (&next_k, store)]);
+        i.add_incoming(&[(&lo, entry), (&new_i.as_basic_value(), store)]);
+        j.add_incoming(&[(&mid, entry), (&new_j.as_basic_value(), store)]);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_sort(ptr slots, i64 len, ptr function, ptr env)`: sorts `len` slots in
+    /// place with a stable bottom-up merge sort, merging runs of doubling width back and
+    /// forth between the slots and a scratch copy. With a null `function` the slots are
+    /// sorted in ascending order, and otherwise by the closure's answers, as
+    /// `venti_sort_merge` asks them.
+    fn sort(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.ptr_type();
+        let function = self.declare(
+            "venti_sort",
+            self.context.void_type().fn_type(
+                &[
+                    ptr_type.into(),
+                    i64_type.into(),
+                    ptr_type.into(),
+                    ptr_type.into(),
+                ],
+                false,
+            ),
+        );
+        let slots = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let closure_fn = param(function, 2)?;
+        let env = param(function, 3)?;
+        let merge = self.helper("venti_sort_merge")?;
+        let malloc = self.libc("malloc")?;
+        let free = self.libc("free")?;
+        let entry = self.block(function, "entry");
+        let setup = self.block(function, "setup");
+        let pass = self.block(function, "pass");
+        let run = self.block(function, "run");
+        let merge_run = self.block(function, "merge_run");
+        let next_pass = self.block(function, "next_pass");
+        let finish = self.block(function, "finish");
+        let copy_back = self.block(function, "copy_back");
+        let release = self.block(function, "release");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let size = self
+            .builder
+            .build_int_mul(len, i64_type.const_int(8, false), "size")?;
+        let is_sorted = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            len,
+            i64_type.const_int(2, false),
+            "is_sorted",
+        )?;
+        self.builder
+            .build_conditional_branch(is_sorted, done, setup)?;
+
+        self.builder.position_at_end(setup);
+        let scratch = self
+            .call(malloc, &[size.into()], "scratch")?
+            .into_pointer_value();
+        self.builder.build_unconditional_branch(pass)?;
+
+        self.builder.position_at_end(pass);
+        let width = self.builder.build_phi(i64_type, "width")?;
+        let source = self.builder.build_phi(ptr_type, "source")?;
+        let target = self.builder.build_phi(ptr_type, "target")?;
+        let width_value = width.as_basic_value().into_int_value();
+        let double_width = self
+            .builder
+            .build_int_add(width_value, width_value, "double_width")?;
+        let has_pass =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, width_value, len, "has_pass")?;
+        self.builder
+            .build_conditional_branch(has_pass, run, finish)?;
+
+        self.builder.position_at_end(run);
+        let lo = self.builder.build_phi(i64_type, "lo")?;
+        let lo_value = lo.as_basic_value().into_int_value();
+        let has_run =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, lo_value, len, "has_run")?;
+        self.builder
+            .build_conditional_branch(has_run, merge_run, next_pass)?;
+
+        self.builder.position_at_end(merge_run);
+        let capped = |offset: IntValue<'ctx>, name: &str| -> Result<IntValue<'ctx>, VentiError> {
+            let end = self.builder.build_int_add(lo_value, offset, name)?;
+            let is_past = self
+                .builder
+                .build_int_compare(IntPredicate::SGT, end, len, "is_past")?;
+            Ok(self
+                .builder
+                .build_select(is_past, len, end, name)?
+                .into_int_value())
+        };
+        let mid = capped(width_value, "mid")?;
+        let hi = capped(double_width, "hi")?;
+        self.builder.build_call(
+            merge,
+            &[
+                source.as_basic_value().into(),
+                target.as_basic_value().into(),
+                lo_value.into(),
+                mid.into(),
+                hi.into(),
+                closure_fn.into(),
+                env.into(),
+            ],
+            "",
+        )?;
+        self.builder.build_unconditional_branch(run)?;
+        lo.add_incoming(&[(&i64_type.const_zero(), pass), (&hi, merge_run)]);
+
+        self.builder.position_at_end(next_pass);
+        // The merged runs are in the target, which the next pass reads from
+        self.builder.build_unconditional_branch(pass)?;
+        width.add_incoming(&[
+            (&i64_type.const_int(1, false), setup),
+            (&double_width, next_pass),
+        ]);
+        source.add_incoming(&[(&slots, setup), (&target.as_basic_value(), next_pass)]);
+        target.add_incoming(&[(&scratch, setup), (&source.as_basic_value(), next_pass)]);
+
+        self.builder.position_at_end(finish);
+        let source_value = source.as_basic_value().into_pointer_value();
+        let is_moved =
+            self.builder
+                .build_int_compare(IntPredicate::NE, source_value, slots, "is_moved")?;
+        self.builder
+            .build_conditional_branch(is_moved, copy_back, release)?;
+
+        self.builder.position_at_end(copy_back);
+        self.builder.build_memcpy(slots, 8, source_value, 8, size)?;
+        self.builder.build_unconditional_branch(release)?;
+
+        self.builder.position_at_end(release);
+        self.builder.build_call(free, &[scratch.into()], "")?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `i64 venti_binary_search(ptr slots, i64 len, i64 value)`: the index of the first of
+    /// `len` slots in ascending order holding `value`, or -1 if none does.
+    fn binary_search(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_binary_search",
+            i64_type.fn_type(
+                &[self.ptr_type().into(), i64_type.into(), i64_type.into()],
+                false,
+            ),
+        );
+        let slots = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let value = param(function, 2)?.into_int_value();
+        let entry = self.block(function, "entry");
+        let check = self.block(function, "check");
+        let probe = self.block(function, "probe");
+        let found_end = self.block(function, "found_end");
+        let compare = self.block(function, "compare");
+        let found = self.block(function, "found");
+        let missing = self.block(function, "missing");
+
+        self.builder.position_at_end(entry);
+        self.builder.build_unconditional_branch(check)?;
+
+        // The search narrows `lo..hi` to the first slot not less than `value`
+        self.builder.position_at_end(check);
+        let lo = self.builder.build_phi(i64_type, "lo")?;
+        let hi = self.builder.build_phi(i64_type, "hi")?;
+        let (lo_value, hi_value) = (
+            lo.as_basic_value().into_int_value(),
+            hi.as_basic_value().into_int_value(),
+        );
+        let is_open =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, lo_value, hi_value, "is_open")?;
+        self.builder
+            .build_conditional_branch(is_open, probe, found_end)?;
+
+        self.builder.position_at_end(probe);
+        let span = self.builder.build_int_sub(hi_value, lo_value, "span")?;
+        let half = self
+            .builder
+            .build_int_signed_div(span, i64_type.const_int(2, false), "half")?;
+        let mid = self.builder.build_int_add(lo_value, half, "mid")?;
+        let slot = self.slot_ptr(slots, mid, "slot")?;
+        let probed = self
+            .builder
+            .build_load(i64_type, slot, "probed")?
+            .into_int_value();
+        let is_less =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, probed, value, "is_less")?;
+        let after_mid =
+            self.builder
+                .build_int_add(mid, i64_type.const_int(1, false), "after_mid")?;
+        let new_lo = self
+            .builder
+            .build_select(is_less, after_mid, lo_value, "new_lo")?;
+        let new_hi = self
+            .builder
+            .build_select(is_less, hi_value, mid, "new_hi")?;
+        self.builder.build_unconditional_branch(check)?;
+        lo.add_incoming(&[(&i64_type.const_zero(), entry), (&new_lo, probe)]);
+        hi.add_incoming(&[(&len, entry), (&new_hi, probe)]);
+
+        self.builder.position_at_end(found_end);
+        let in_range =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, lo_value, len, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, compare, missing)?;
+
+        self.builder.position_at_end(compare);
+        let slot = self.slot_ptr(slots, lo_value, "slot")?;
+        let candidate = self
+            .builder
+            .build_load(i64_type, slot, "candidate")?
+            .into_int_value();
+        let is_match =
+            self.builder
+                .build_int_compare(IntPredicate::EQ, candidate, value, "is_match")?;
+        self.builder
+            .build_conditional_branch(is_match, found, missing)?;
+
+        self.builder.position_at_end(found);
+        self.builder.build_return(Some(&lo_value))?;
+
+        self.builder.position_at_end(missing);
+        self.builder
+            .build_return(Some(&i64_type.const_int(u64::MAX, true)))?;
+        Ok(function)
+    }
+
+    /// Returns a pointer to field `index` of the iterator header behind `header`.
+    fn iter_field(
+        &self,
+        header: PointerValue<'ctx>,
+        index: u32,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, VentiError> {
+        Ok(self.builder.build_struct_gep(
+            iterator_header_type(self.context),
+            header,
+            index,
+            name,
+        )?)
+    }
+
+    /// Loads field `index` of the iterator header behind `header`.
+    fn load_iter_field(
+        &self,
+        header: PointerValue<'ctx>,
+        index: u32,
+        name: &str,
+    ) -> Result<BasicValueEnum<'ctx>, VentiError> {
+        let field_type = iterator_header_type(self.context)
+            .get_field_type_at_index(index)
+            .ok_or_else(|| {
+                VentiError::CodegenError(format!("Iterator has no field {}", index), None)
+            })?;
+        let field = self.iter_field(header, index, name)?;
+        Ok(self.builder.build_load(field_type, field, name)?)
+    }
+
+    /// Returns from the helper being emitted with the `{ value, done }` pair `venti_iter_next`
+    /// gives.
+    fn return_next(&self, value: IntValue<'ctx>, done: bool) -> Result<(), VentiError> {
+        let pair = iterator_next_type(self.context).get_undef();
+        let pair = self.builder.build_insert_value(pair, value, 0, "pair")?;
+        let done = self.context.bool_type().const_int(done as u64, false);
+        let pair = self.builder.build_insert_value(pair, done, 1, "pair")?;
+        self.builder
+            .build_return(Some(&pair.as_basic_value_enum()))?;
+        Ok(())
+    }
+
+    /// Calls the closure whose function is `function` and environment `env` with `args`.
+    fn call_closure(
+        &self,
+        function: PointerValue<'ctx>,
+        env: BasicValueEnum<'ctx>,
+        args: &[IntValue<'ctx>],
+        name: &str,
+    ) -> Result<IntValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let mut param_types: Vec<BasicMetadataTypeEnum> = vec![self.ptr_type().into()];
+        param_types.extend(args.iter().map(|_| BasicMetadataTypeEnum::from(i64_type)));
+        let mut call_args: Vec<BasicMetadataValueEnum> = vec![env.into()];
+        call_args.extend(args.iter().map(|&arg| BasicMetadataValueEnum::from(arg)));
+        self.builder
+            .build_indirect_call(
+                i64_type.fn_type(&param_types, false),
+                function,
+                &call_args,
+                name,
+            )?
+            .try_as_basic_value()
+            .left()
+            .map(|value| value.into_int_value())
+            .ok_or_else(|| VentiError::CodegenError("Closure returned no value".to_string(), None))
+    }
+
+    /// `ptr venti_iter_new(i64 kind, i64 pos, i64 end, i64 step, ptr slots, ptr function,
+    /// ptr env, ptr source)`: allocates an iterator, taking a reference to `source` if it is
+    /// not null.
+    fn iter_new(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.ptr_type();
+        let function = self.declare(
+            "venti_iter_new",
+            ptr_type.fn_type(
+                &[
+                    i64_type.into(),
+                    i64_type.into(),
+                    i64_type.into(),
+                    i64_type.into(),
+                    ptr_type.into(),
+                    ptr_type.into(),
+                    ptr_type.into(),
+                    ptr_type.into(),
+                ],
+                false,
+            ),
+        );
+        let malloc = self.libc("malloc")?;
+        let retain = self.helper("venti_iter_retain")?;
+        let entry = self.block(function, "entry");
+        let chained = self.block(function, "chained");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let header_size = iterator_header_type(self.context)
+            .size_of()
+            .ok_or_else(|| {
+                VentiError::CodegenError("Iterator header is unsized".to_string(), None)
+            })?;
+        let header = self
+            .call(malloc, &[header_size.into()], "header")?
+            .into_pointer_value();
+        for (index, name) in [
+            "kind", "pos", "end", "step", "slots", "function", "env", "source",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let field = self.iter_field(header, index as u32, name)?;
+            self.builder
+                .build_store(field, param(function, index as u32)?)?;
+        }
+        // The iterator is unowned until it is stored in a variable
+        let count = self.iter_field(header, 8, "count")?;
+        self.builder.build_store(count, i64_type.const_zero())?;
+        let source = param(function, 7)?.into_pointer_value();
+        let is_chained = self.builder.build_is_not_null(source, "is_chained")?;
+        self.builder
+            .build_conditional_branch(is_chained, chained, done)?;
+
+        self.builder.position_at_end(chained);
+        self.builder.build_call(retain, &[source.into()], "")?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(Some(&header))?;
+        Ok(function)
+    }
+
+    /// `{ i64, i1 } venti_iter_next(ptr header)`: takes the next value of an iterator,
+    /// giving it and whether the iterator had run out, in which case the value is 0.
+    fn iter_next(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let function = self.declare(
+            "venti_iter_next",
+            iterator_next_type(self.context).fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let entry = self.block(function, "entry");
+        let source = self.block(function, "source");
+        let take = self.block(function, "take");
+        let count = self.block(function, "count");
+        let slot = self.block(function, "slot");
+        let map = self.block(function, "map");
+        let apply = self.block(function, "apply");
+        let filter = self.block(function, "filter");
+        let test = self.block(function, "test");
+        let keep = self.block(function, "keep");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let kind = self.load_iter_field(header, 0, "kind")?.into_int_value();
+        self.builder.build_switch(
+            kind,
+            source,
+            &[
+                (i64_type.const_int(ITER_MAP, false), map),
+                (i64_type.const_int(ITER_FILTER, false), filter),
+            ],
+        )?;
+
+        self.builder.position_at_end(source);
+        let pos = self.load_iter_field(header, 1, "pos")?.into_int_value();
+        let end = self.load_iter_field(header, 2, "end")?.into_int_value();
+        let in_range = self
+            .builder
+            .build_int_compare(IntPredicate::SLT, pos, end, "in_range")?;
+        self.builder
+            .build_conditional_branch(in_range, take, done)?;
+
+        self.builder.position_at_end(take);
+        let step = self.load_iter_field(header, 3, "step")?.into_int_value();
+        let next_pos = self.builder.build_int_add(pos, step, "next_pos")?;
+        let pos_field = self.iter_field(header, 1, "pos_field")?;
+        self.builder.build_store(pos_field, next_pos)?;
+        let slots = self
+            .load_iter_field(header, 4, "slots")?
+            .into_pointer_value();
+        let is_counting = self.builder.build_is_null(slots, "is_counting")?;
+        self.builder
+            .build_conditional_branch(is_counting, count, slot)?;
+
+        self.builder.position_at_end(count);
+        self.return_next(pos, false)?;
+
+        self.builder.position_at_end(slot);
+        let slot_ptr = self.slot_ptr(slots, pos, "slot_ptr")?;
+        let value = self
+            .builder
+            .build_load(i64_type, slot_ptr, "value")?
+            .into_int_value();
+        self.return_next(value, false)?;
+
+        // Map and filter iterators read from their source, passing on the end of it
+        self.builder.position_at_end(map);
+        let next = self.helper("venti_iter_next")?;
+        let inner = self.load_iter_field(header, 7, "source")?;
+        let closure_fn = self
+            .load_iter_field(header, 5, "function")?
+            .into_pointer_value();
+        let env = self.load_iter_field(header, 6, "env")?;
+        let pair = self
+            .call(next, &[inner.into()], "pair")?
+            .into_struct_value();
+        let is_done = self
+            .builder
+            .build_extract_value(pair, 1, "is_done")?
+            .into_int_value();
+        self.builder
+            .build_conditional_branch(is_done, done, apply)?;
+
+        self.builder.position_at_end(apply);
+        let value = self
+            .builder
+            .build_extract_value(pair, 0, "value")?
+            .into_int_value();
+        let mapped = self.call_closure(closure_fn, env, &[value], "mapped")?;
+        self.return_next(mapped, false)?;
+
+        // Values the closure turns down are skipped until one is kept or the source ends
+        self.builder.position_at_end(filter);
+        let inner = self.load_iter_field(header, 7, "source")?;
+        let closure_fn = self
+            .load_iter_field(header, 5, "function")?
+            .into_pointer_value();
+        let env = self.load_iter_field(header, 6, "env")?;
+        let pair = self
+            .call(next, &[inner.into()], "pair")?
+            .into_struct_value();
+        let is_done = self
+            .builder
+            .build_extract_value(pair, 1, "is_done")?
+            .into_int_value();
+        self.builder.build_conditional_branch(is_done, done, test)?;
+
+        self.builder.position_at_end(test);
+        let value = self
+            .builder
+            .build_extract_value(pair, 0, "value")?
+            .into_int_value();
+        let verdict = self.call_closure(closure_fn, env, &[value], "verdict")?;
+        let is_kept = self.builder.build_int_compare(
+            IntPredicate::NE,
+            verdict,
+            i64_type.const_zero(),
+            "is_kept",
+        )?;
+        self.builder
+            .build_conditional_branch(is_kept, keep, filter)?;
+
+        self.builder.position_at_end(keep);
+        self.return_next(value, false)?;
+
+        self.builder.position_at_end(done);
+        self.return_next(i64_type.const_zero(), true)?;
+        Ok(function)
+    }
+
+    /// `i64 venti_iter_reduce(ptr header, i64 initial, ptr function, ptr env)`: folds the
+    /// values of an iterator into `initial`, calling the closure with the value so far and
+    /// each value in turn.
+    fn iter_reduce(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.ptr_type();
+        let function = self.declare(
+            "venti_iter_reduce",
+            i64_type.fn_type(
+                &[
+                    ptr_type.into(),
+                    i64_type.into(),
+                    ptr_type.into(),
+                    ptr_type.into(),
+                ],
+                false,
+            ),
+        );
+        let header = param(function, 0)?;
+        let initial = param(function, 1)?.into_int_value();
+        let closure_fn = param(function, 2)?.into_pointer_value();
+        let env = param(function, 3)?;
+        let next = self.helper("venti_iter_next")?;
+        let entry = self.block(function, "entry");
+        let check = self.block(function, "check");
+        let apply = self.block(function, "apply");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        self.builder.build_unconditional_branch(check)?;
+
+        self.builder.position_at_end(check);
+        let acc = self.builder.build_phi(i64_type, "acc")?;
+        let pair = self
+            .call(next, &[header.into()], "pair")?
+            .into_struct_value();
+        let is_done = self
+            .builder
+            .build_extract_value(pair, 1, "is_done")?
+            .into_int_value();
+        self.builder
+            .build_conditional_branch(is_done, done, apply)?;
+
+        self.builder.position_at_end(apply);
+        let value = self
+            .builder
+            .build_extract_value(pair, 0, "value")?
+            .into_int_value();
+        let current = acc.as_basic_value().into_int_value();
+        let folded = self.call_closure(closure_fn, env, &[current, value], "folded")?;
+        self.builder.build_unconditional_branch(check)?;
+        acc.add_incoming(&[(&initial, entry), (&folded, apply)]);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(Some(&acc.as_basic_value()))?;
+        Ok(function)
+    }
+
+    /// `venti.buffer venti_iter_collect(ptr header)`: reads an iterator to its end into a
+    /// new buffer, doubling its slots as they fill.
+    fn iter_collect(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.ptr_type();
+        let buffer_type = buffer_type(self.context);
+        let function = self.declare(
+            "venti_iter_collect",
+            buffer_type.fn_type(&[ptr_type.into()], false),
+        );
+        let header = param(function, 0)?;
+        let next = self.helper("venti_iter_next")?;
+        let malloc = self.libc("malloc")?;
+        let realloc = self.libc("realloc")?;
+        let entry = self.block(function, "entry");
+        let check = self.block(function, "check");
+        let append = self.block(function, "append");
+        let grow = self.block(function, "grow");
+        let store = self.block(function, "store");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let slot_size = i64_type.const_int(8, false);
+        let initial_capacity = i64_type.const_int(8, false);
+        let initial_size =
+            self.builder
+                .build_int_mul(initial_capacity, slot_size, "initial_size")?;
+        let initial_slots = self
+            .call(malloc, &[initial_size.into()], "initial_slots")?
+            .into_pointer_value();
+        self.builder.build_unconditional_branch(check)?;
+
+        self.builder.position_at_end(check);
+        let slots = self.builder.build_phi(ptr_type, "slots")?;
+        let len = self.builder.build_phi(i64_type, "len")?;
+        let capacity = self.builder.build_phi(i64_type, "capacity")?;
+        let current_slots = slots.as_basic_value().into_pointer_value();
+        let current_len = len.as_basic_value().into_int_value();
+        let current_capacity = capacity.as_basic_value().into_int_value();
+        let pair = self
+            .call(next, &[header.into()], "pair")?
+            .into_struct_value();
+        let is_done = self
+            .builder
+            .build_extract_value(pair, 1, "is_done")?
+            .into_int_value();
+        self.builder
+            .build_conditional_branch(is_done, done, append)?;
+
+        self.builder.position_at_end(append);
+        let is_full = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            current_len,
+            current_capacity,
+            "is_full",
+        )?;
+        self.builder
+            .build_conditional_branch(is_full, grow, store)?;
+
+        self.builder.position_at_end(grow);
+        let grown_capacity =
+            self.builder
+                .build_int_add(current_capacity, current_capacity, "grown_capacity")?;
+        let grown_size = self
+            .builder
+            .build_int_mul(grown_capacity, slot_size, "grown_size")?;
+        let grown_slots = self
+            .call(
+                realloc,
+                &[current_slots.into(), grown_size.into()],
+                "grown_slots",
+            )?
+            .into_pointer_value();
+        self.builder.build_unconditional_branch(store)?;
+
+        self.builder.position_at_end(store);
+        let store_slots = self.builder.build_phi(ptr_type, "store_slots")?;
+        store_slots.add_incoming(&[(&current_slots, append), (&grown_slots, grow)]);
+        let store_capacity = self.builder.build_phi(i64_type, "store_capacity")?;
+        store_capacity.add_incoming(&[(&current_capacity, append), (&grown_capacity, grow)]);
+        let store_slots = store_slots.as_basic_value().into_pointer_value();
+        let store_capacity = store_capacity.as_basic_value().into_int_value();
+        let value = self.builder.build_extract_value(pair, 0, "value")?;
+        let slot = self.slot_ptr(store_slots, current_len, "slot")?;
+        self.builder.build_store(slot, value)?;
+        let new_len =
+            self.builder
+                .build_int_add(current_len, i64_type.const_int(1, false), "new_len")?;
+        self.builder.build_unconditional_branch(check)?;
+
+        slots.add_incoming(&[(&initial_slots, entry), (&store_slots, store)]);
+        len.add_incoming(&[(&i64_type.const_zero(), entry), (&new_len, store)]);
+        capacity.add_incoming(&[(&initial_capacity, entry), (&store_capacity, store)]);
+
+        self.builder.position_at_end(done);
+        let buffer =
+            self.builder
+                .build_insert_value(buffer_type.get_undef(), current_slots, 0, "buffer")?;
+        let buffer = self
+            .builder
+            .build_insert_value(buffer, current_len, 1, "buffer")?;
+        self.builder
+            .build_return(Some(&buffer.as_basic_value_enum()))?;
+        Ok(function)
+    }
+
+    /// `ptr venti_str_chars(ptr data, i64 len)`: a source iterator over the code points of
+    /// a string, decoded from UTF-8 into slots of its own. A byte that does not start a
+    /// well-formed sequence gives U+FFFD and decoding goes on from the next byte.
+    fn str_chars(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.ptr_type();
+        let function = self.declare(
+            "venti_str_chars",
+            ptr_type.fn_type(&[ptr_type.into(), i64_type.into()], false),
+        );
+        let data = param(function, 0)?.into_pointer_value();
+        let len = param(function, 1)?.into_int_value();
+        let malloc = self.libc("malloc")?;
+        let new = self.helper("venti_iter_new")?;
+        let entry = self.block(function, "entry");
+        let loop_block = self.block(function, "loop");
+        let body = self.block(function, "body");
+        let done = self.block(function, "done");
+        let int = |value: u64| i64_type.const_int(value, false);
+
+        self.builder.position_at_end(entry);
+        // A string has no more characters than bytes
+        let size = self.builder.build_int_mul(len, int(8), "size")?;
+        let slots = self
+            .call(malloc, &[size.into()], "slots")?
+            .into_pointer_value();
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        self.builder.position_at_end(loop_block);
+        let index = self.builder.build_phi(i64_type, "index")?;
+        let count = self.builder.build_phi(i64_type, "count")?;
+        let index_value = index.as_basic_value().into_int_value();
+        let count_value = count.as_basic_value().into_int_value();
+        let has_more =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, index_value, len, "has_more")?;
+        self.builder
+            .build_conditional_branch(has_more, body, done)?;
+
+        self.builder.position_at_end(body);
+        let lead = self.text_byte(data, index_value, "lead")?;
+        let lead = self.builder.build_int_z_extend(lead, i64_type, "lead64")?;
+        // The lead byte gives the length of the sequence, the bits of the code point it
+        // holds, and the least code point a sequence that long may encode
+        let mut width = int(0);
+        let mut mask = int(0);
+        let mut least = int(0);
+        for (low, high, lead_width, lead_mask, lead_least) in [
+            (0xf0, 0xf5, 4, 0x07, 0x10000),
+            (0xe0, 0xf0, 3, 0x0f, 0x800),
+            (0xc2, 0xe0, 2, 0x1f, 0x80),
+            (0x00, 0x80, 1, 0x7f, 0),
+        ] {
+            let is_at_least =
+                self.builder
+                    .build_int_compare(IntPredicate::UGE, lead, int(low), "is_at_least")?;
+            let is_below =
+                self.builder
+                    .build_int_compare(IntPredicate::ULT, lead, int(high), "is_below")?;
+            let is_lead = self.builder.build_and(is_at_least, is_below, "is_lead")?;
+            width = self
+                .builder
+                .build_select(is_lead, int(lead_width), width, "width")?
+                .into_int_value();
+            mask = self
+                .builder
+                .build_select(is_lead, int(lead_mask), mask, "mask")?
+                .into_int_value();
+            least = self
+                .builder
+                .build_select(is_lead, int(lead_least), least, "least")?
+                .into_int_value();
+        }
+        let mut is_valid =
+            self.builder
+                .build_int_compare(IntPredicate::NE, width, int(0), "is_valid")?;
+        let mut code = self.builder.build_and(lead, mask, "code")?;
+        for offset in 1..4 {
+            let is_needed = self.builder.build_int_compare(
+                IntPredicate::ULT,
+                int(offset),
+                width,
+                "is_needed",
+            )?;
+            let at = self.builder.build_int_add(index_value, int(offset), "at")?;
+            let is_inside =
+                self.builder
+                    .build_int_compare(IntPredicate::SLT, at, len, "is_inside")?;
+            // Past the end, the lead byte is read again in place of one that is not there
+            let at = self
+                .builder
+                .build_select(is_inside, at, index_value, "at")?
+                .into_int_value();
+            let byte = self.text_byte(data, at, "byte")?;
+            let is_continuation = self.is_continuation(byte, "is_continuation")?;
+            let is_continued =
+                self.builder
+                    .build_and(is_inside, is_continuation, "is_continued")?;
+            let is_skipped = self.builder.build_not(is_needed, "is_skipped")?;
+            let is_fine = self.builder.build_or(is_skipped, is_continued, "is_fine")?;
+            is_valid = self.builder.build_and(is_valid, is_fine, "is_valid")?;
+            let byte = self.builder.build_int_z_extend(byte, i64_type, "byte64")?;
+            let bits = self.builder.build_and(byte, int(0x3f), "bits")?;
+            let shifted = self.builder.build_left_shift(code, int(6), "shifted")?;
+            let extended = self.builder.build_or(shifted, bits, "extended")?;
+            code = self
+                .builder
+                .build_select(is_needed, extended, code, "code")?
+                .into_int_value();
+        }
+        // Overlong sequences, surrogates and code points past U+10FFFF are not characters
+        let is_shortest =
+            self.builder
+                .build_int_compare(IntPredicate::UGE, code, least, "is_shortest")?;
+        let is_in_unicode = self.builder.build_int_compare(
+            IntPredicate::ULE,
+            code,
+            int(0x10ffff),
+            "is_in_unicode",
+        )?;
+        let surrogate_offset = self
+            .builder
+            .build_int_sub(code, int(0xd800), "surrogate_offset")?;
+        let is_not_surrogate = self.builder.build_int_compare(
+            IntPredicate::UGE,
+            surrogate_offset,
+            int(0x800),
+            "is_not_surrogate",
+        )?;
+        for check in [is_shortest, is_in_unicode, is_not_surrogate] {
+            is_valid = self.builder.build_and(is_valid, check, "is_valid")?;
+        }
+        let code = self
+            .builder
+            .build_select(is_valid, code, int(0xfffd), "char")?
+            .into_int_value();
+        let width = self
+            .builder
+            .build_select(is_valid, width, int(1), "width")?
+            .into_int_value();
+        let slot = self.slot_ptr(slots, count_value, "slot")?;
+        self.builder.build_store(slot, code)?;
+        let next_index = self
+            .builder
+            .build_int_add(index_value, width, "next_index")?;
+        let next_count = self
+            .builder
+            .build_int_add(count_value, int(1), "next_count")?;
+        self.builder.build_unconditional_branch(loop_block)?;
+
+        index.add_incoming(&[(&int(0), entry), (&next_index, body)]);
+        count.add_incoming(&[(&int(0), entry), (&next_count, body)]);
+
+        self.builder.position_at_end(done);
+        let null = ptr_type.const_null();
+        let iterator = self.call(
+            new,
+            &[
+                int(ITER_SOURCE).into(),
+                int(0).into(),
+                count_value.into(),
+                int(1).into(),
+                slots.into(),
+                null.into(),
+                null.into(),
+                null.into(),
+            ],
+            "iterator",
+        )?;
+        self.builder.build_return(Some(&iterator))?;
+        Ok(function)
+    }
+
+    /// `void venti_iter_retain(ptr header)`: adds a reference to an iterator.
+    fn iter_retain(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_iter_retain",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_retain = self.helper("venti_rc_retain")?;
+        let entry = self.block(function, "entry");
+
+        self.builder.position_at_end(entry);
+        let count = self.iter_field(header, 8, "count")?;
+        self.builder.build_call(rc_retain, &[count.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_iter_release(ptr header)`: drops a reference to an iterator; the last one
+    /// frees its slots and drops its reference to the iterator it reads from. A null header
+    /// is ignored.
+    fn iter_release(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_iter_release",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let header = param(function, 0)?.into_pointer_value();
+        let rc_release = self.helper("venti_rc_release")?;
+        let free = self.libc("free")?;
+        let entry = self.block(function, "entry");
+        let last = self.block(function, "last");
+        let chained = self.block(function, "chained");
+        let finish = self.block(function, "finish");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        self.skip_null(entry, header, done)?;
+        let count = self.iter_field(header, 8, "count")?;
+        let is_last = self
+            .call(rc_release, &[count.into()], "is_last")?
+            .into_int_value();
+        self.builder.build_conditional_branch(is_last, last, done)?;
+
+        self.builder.position_at_end(last);
+        let source = self
+            .load_iter_field(header, 7, "source")?
+            .into_pointer_value();
+        let is_chained = self.builder.build_is_not_null(source, "is_chained")?;
+        self.builder
+            .build_conditional_branch(is_chained, chained, finish)?;
+
+        self.builder.position_at_end(chained);
+        self.builder.build_call(function, &[source.into()], "")?;
+        self.builder.build_unconditional_branch(finish)?;
+
+        self.builder.position_at_end(finish);
+        let slots = self.load_iter_field(header, 4, "slots")?;
+        self.builder.build_call(free, &[slots.into()], "")?;
+        self.builder.build_call(free, &[header.into()], "")?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// Returns the key of the thread-specific slot holding the innermost handler, which
+    /// every module shares. Each module defines it weakly, so one definition is kept.
+    fn handler_key_global(&self) -> PointerValue<'ctx> {
+        let global = self.module.get_global(HANDLER_KEY).unwrap_or_else(|| {
+            let i64_type = self.context.i64_type();
+            let global = self.module.add_global(i64_type, None, HANDLER_KEY);
+            global.set_linkage(Linkage::WeakAny);
+            global.set_initializer(&i64_type.const_zero());
+            global
+        });
+        global.as_pointer_value()
+    }
+
+    /// `i64 venti_handler_key()`: the key of the slot holding each thread's innermost
+    /// handler, which the first thread to ask for it creates.
+    fn handler_key(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i64_type = self.context.i64_type();
+        let one = i64_type.const_int(1, false);
+        let function = self.declare("venti_handler_key", i64_type.fn_type(&[], false));
+        let global = self.handler_key_global();
+        let pthread_key_create = self.libc("pthread_key_create")?;
+        let pthread_key_delete = self.libc("pthread_key_delete")?;
+
+        let entry = self.block(function, "entry");
+        let created = self.block(function, "created");
+        let create = self.block(function, "create");
+        let won = self.block(function, "won");
+        let lost = self.block(function, "lost");
+
+        self.builder.position_at_end(entry);
+        let key_slot = self.builder.build_alloca(i64_type, "key_slot")?;
+        // Adding zero reads the key atomically
+        let stored = self.builder.build_atomicrmw(
+            AtomicRMWBinOp::Add,
+            global,
+            i64_type.const_zero(),
+            AtomicOrdering::SequentiallyConsistent,
+        )?;
+        let is_created = self.builder.build_int_compare(
+            IntPredicate::NE,
+            stored,
+            i64_type.const_zero(),
+            "is_created",
+        )?;
+        self.builder
+            .build_conditional_branch(is_created, created, create)?;
+
+        self.builder.position_at_end(created);
+        let key = self.builder.build_int_sub(stored, one, "key")?;
+        self.builder.build_return(Some(&key))?;
+
+        // Threads that create a key at the same time keep the first one stored
+        self.builder.position_at_end(create);
+        self.builder.build_store(key_slot, i64_type.const_zero())?;
+        self.builder.build_call(
+            pthread_key_create,
+            &[key_slot.into(), self.ptr_type().const_null().into()],
+            "",
+        )?;
+        let key = self
+            .builder
+            .build_load(i64_type, key_slot, "new_key")?
+            .into_int_value();
+        let stored_key = self.builder.build_int_add(key, one, "stored_key")?;
+        let exchange = self.builder.build_cmpxchg(
+            global,
+            i64_type.const_zero(),
+            stored_key,
+            AtomicOrdering::SequentiallyConsistent,
+            AtomicOrdering::SequentiallyConsistent,
+        )?;
+        let is_first = self
+            .builder
+            .build_extract_value(exchange, 1, "is_first")?
+            .into_int_value();
+        self.builder.build_conditional_branch(is_first, won, lost)?;
+
+        self.builder.position_at_end(won);
+        self.builder.build_return(Some(&key))?;
+
+        self.builder.position_at_end(lost);
+        self.builder
+            .build_call(pthread_key_delete, &[key.into()], "")?;
+        let winner = self
+            .builder
+            .build_extract_value(exchange, 0, "winner")?
+            .into_int_value();
+        let winner = self.builder.build_int_sub(winner, one, "winner_key")?;
+        self.builder.build_return(Some(&winner))?;
+        Ok(function)
+    }
+
+    /// `ptr venti_handler_get()`: the current thread's innermost handler, or null outside
+    /// every `try_venti` block.
+    fn handler_get(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare("venti_handler_get", self.ptr_type().fn_type(&[], false));
+        let handler_key = self.helper("venti_handler_key")?;
+        let pthread_getspecific = self.libc("pthread_getspecific")?;
+        let entry = self.block(function, "entry");
+        self.builder.position_at_end(entry);
+        let key = self.call(handler_key, &[], "key")?;
+        let handler = self.call(pthread_getspecific, &[key.into()], "handler")?;
+        self.builder.build_return(Some(&handler))?;
+        Ok(function)
+    }
+
+    /// `void venti_handler_set(ptr handler)`: makes `handler` the current thread's
+    /// innermost one.
+    fn handler_set(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_handler_set",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let handler = param(function, 0)?;
+        let handler_key = self.helper("venti_handler_key")?;
+        let pthread_setspecific = self.libc("pthread_setspecific")?;
+        let entry = self.block(function, "entry");
+        self.builder.position_at_end(entry);
+        let key = self.call(handler_key, &[], "key")?;
+        self.builder
+            .build_call(pthread_setspecific, &[key.into(), handler.into()], "")?;
+        self.builder.build_return(None)?;
+        Ok(function)
+    }
+
+    /// `void venti_raise(ptr message)`: hands the malloc'd `message` to the innermost
+    /// handler and jumps back into its `try_venti` block, after putting back the handler it
+    /// replaced. Without a handler the message is printed and the program exits with
+    /// status 1.
+    fn raise(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let function = self.declare(
+            "venti_raise",
+            self.context
+                .void_type()
+                .fn_type(&[self.ptr_type().into()], false),
+        );
+        let kind = Attribute::get_named_enum_kind_id("noreturn");
+        function.add_attribute(
+            AttributeLoc::Function,
+            self.context.create_enum_attribute(kind, 0),
+        );
+        let message = param(function, 0)?.into_pointer_value();
+        let handler_get = self.helper("venti_handler_get")?;
+        let handler_set = self.helper("venti_handler_set")?;
+        let longjmp = self.libc("longjmp")?;
+        let printf = self.libc("printf")?;
+        let exit = self.libc("exit")?;
+        let handler_type = handler_type(self.context);
+
+        let entry = self.block(function, "entry");
+        let caught = self.block(function, "caught");
+        let uncaught = self.block(function, "uncaught");
+
+        self.builder.position_at_end(entry);
+        let handler = self.call(handler_get, &[], "handler")?.into_pointer_value();
+        let is_uncaught = self.builder.build_is_null(handler, "is_uncaught")?;
+        self.builder
+            .build_conditional_branch(is_uncaught, uncaught, caught)?;
+
+        self.builder.position_at_end(caught);
+        let previous_ptr =
+            self.builder
+                .build_struct_gep(handler_type, handler, 0, "previous_ptr")?;
+        let previous = self
+            .builder
+            .build_load(self.ptr_type(), previous_ptr, "previous")?;
+        self.builder
+            .build_call(handler_set, &[previous.into()], "")?;
+        let message_ptr = self
+            .builder
+            .build_struct_gep(handler_type, handler, 1, "message_ptr")?;
+        self.builder.build_store(message_ptr, message)?;
+        let jump_buffer = self
+            .builder
+            .build_struct_gep(handler_type, handler, 2, "jump_buffer")?;
+        self.builder.build_call(
+            longjmp,
+            &[
+                jump_buffer.into(),
+                self.context.i32_type().const_int(1, false).into(),
+            ],
+            "",
+        )?;
+        self.builder.build_unreachable()?;
+
+        self.builder.position_at_end(uncaught);
+        let format = self
+            .builder
+            .build_global_string_ptr("Runtime Error: %s\n", "uncaught")?;
+        self.builder.build_call(
+            printf,
+            &[format.as_pointer_value().into(), message.into()],
+            "",
+        )?;
+        self.builder.build_call(
+            exit,
+            &[self.context.i32_type().const_int(1, false).into()],
+            "",
+        )?;
+        self.builder.build_unreachable()?;
+        Ok(function)
+    }
+
+    /// `void venti_panic(ptr message, ptr function, i1 backtrace)`: prints the panic's
+    /// message and the name of the function it happened in, and the stack to stderr when
+    /// `backtrace` is set, then exits with status 101.
+    fn panic(&self) -> Result<FunctionValue<'ctx>, VentiError> {
+        let i32_type = self.context.i32_type();
+        let function = self.declare(
+            "venti_panic",
+            self.context.void_type().fn_type(
+                &[
+                    self.ptr_type().into(),
+                    self.ptr_type().into(),
+                    self.context.bool_type().into(),
+                ],
+                false,
+            ),
+        );
+        let kind = Attribute::get_named_enum_kind_id("noreturn");
+        function.add_attribute(
+            AttributeLoc::Function,
+            self.context.create_enum_attribute(kind, 0),
+        );
+        let message = param(function, 0)?;
+        let panicked_in = param(function, 1)?;
+        let wants_trace = param(function, 2)?.into_int_value();
+        let printf = self.libc("printf")?;
+        let fflush = self.libc("fflush")?;
+        let backtrace = self.libc("backtrace")?;
+        let backtrace_symbols_fd = self.libc("backtrace_symbols_fd")?;
+        let exit = self.libc("exit")?;
+
+        let entry = self.block(function, "entry");
+        let trace = self.block(function, "trace");
+        let done = self.block(function, "done");
+
+        self.builder.position_at_end(entry);
+        let frames_type = self.ptr_type().array_type(64);
+        let frames = self.builder.build_alloca(frames_type, "frames")?;
+        let format = self
+            .builder
+            .build_global_string_ptr("Panic in %s: %s\n", "panic")?;
+        self.builder.build_call(
+            printf,
+            &[
+                format.as_pointer_value().into(),
+                panicked_in.into(),
+                message.into(),
+            ],
+            "",
+        )?;
+        self.builder
+            .build_conditional_branch(wants_trace, trace, done)?;
+
+        // The message is flushed first, so it comes before the stack on a terminal
+        self.builder.position_at_end(trace);
+        self.builder
+            .build_call(fflush, &[self.ptr_type().const_null().into()], "")?;
+        let depth = self.call(
+            backtrace,
+            &[frames.into(), i32_type.const_int(64, false).into()],
+            "depth",
+        )?;
+        self.builder.build_call(
+            backtrace_symbols_fd,
+            &[
+                frames.into(),
+                depth.into(),
+                i32_type.const_int(2, false).into(),
+            ],
+            "",
+        )?;
+        self.builder.build_unconditional_branch(done)?;
+
+        self.builder.position_at_end(done);
+        self.builder
+            .build_call(exit, &[i32_type.const_int(101, false).into()], "")?;
+        self.builder.build_unreachable()?;
+        Ok(function)
+    }
+}
@@ -0,0 +1,51 @@
+/*
+Functions and top-level variables are emitted under mangled symbol names, so they never
+collide with libc, the runtime helpers, string literals or each other. A symbol is `_V`,
+then the module name and the name from the source, each prefixed with its length in bytes,
+then, for functions only, the number of parameters:
+
+    fn_venti add(a, b) in module `hello`  ->  _V5hello3add2
+    venti count = 0;   in module `hello`  ->  _V5hello5count
+
+The length prefixes keep names that contain digits or underscores unambiguous. `main`, the
+program entry point, is the one symbol that is not mangled.
+*/
+
+/// Returns the symbol of the function `name` with `arity` parameters in `module`.
+pub fn mangle_function(module: &str, name: &str, arity: usize) -> String {
+    format!("{}{}", mangle_global(module, name), arity)
+}
+
+/// Returns the symbol of the top-level variable `name` in `module`.
+pub fn mangle_global(module: &str, name: &str) -> String {
+    format!("_V{}{}{}{}", module.len(), module, name.len(), name)
+}
+
+/// Recovers the source name from a symbol made by `mangle_function` or `mangle_global`.
+///
+/// # Returns
+///
+/// The name as written in the source, or `None` if `symbol` is not mangled.
+pub fn demangle(symbol: &str) -> Option<&str> {
+    let rest = symbol.strip_prefix("_V")?;
+    let (_, rest) = split_prefixed(rest)?;
+    let (name, arity) = split_prefixed(rest)?;
+    arity
+        .bytes()
+        .all(|byte| byte.is_ascii_digit())
+        .then_some(name)
+}
+
+/// Splits a length-prefixed part off the front of `text`, returning it and the rest.
+fn split_prefixed(text: &str) -> Option<(&str, &str)> {
+    let digits = text
+        .bytes()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    let len = text[..digits].parse::<usize>().ok()?;
+    let rest = &text[digits..];
+    if rest.len() < len || !rest.is_char_boundary(len) {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
@@ -0,0 +1,46 @@
+use crate::errors::VentiError;
+use std::path::Path;
+use std::process::Command;
+
+/*
+The C backend writes a single C99 file that needs nothing beyond the C standard library.
+Turning it into an executable is left to the system C compiler (`cc`, or whatever `CC`
+names), so the same file can also be handed to a cross compiler for a platform LLVM does
+not target.
+*/
+
+/// Compiles the C file at `source` into an executable at `output` with the system C
+/// compiler, optimizing at `-O<opt_level>`.
+///
+/// # Returns
+///
+/// * `Result<(), VentiError>` - Nothing, or an error if the compiler could not be run or
+///   rejected the file.
+pub fn compile_executable(source: &Path, output: &Path, opt_level: u8) -> Result<(), VentiError> {
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let result = Command::new(&compiler)
+        .arg("-std=c99")
+        .arg(format!("-O{}", opt_level.min(3)))
+        .arg(source)
+        // The math builtins call into libm
+        .arg("-lm")
+        .arg("-o")
+        .arg(output)
+        .output()
+        .map_err(|e| {
+            VentiError::IOError(
+                format!("Could not run C compiler '{}': {}", compiler, e),
+                None,
+            )
+        })?;
+    if !result.status.success() {
+        return Err(VentiError::CodegenError(
+            format!(
+                "Compiling the C source failed: {}",
+                String::from_utf8_lossy(&result.stderr).trim()
+            ),
+            None,
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,2388 @@
+use crate::codegen::builtins;
+use crate::codegen_c::runtime;
+use crate::errors::VentiError;
+use crate::ir::ir::{
+    BlockId, Constant, Extern as ExternDecl, Function, FunctionKind, Instruction, Operand, Place,
+    Program, RangePart, Rvalue, Terminator,
+};
+use crate::semantic::types::Type;
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::ast::{BinOp, InlineHint, UnaryOp};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/*
+The C backend compiles the IR of a checked program (see `ir`) to a single C99 file, for
+platforms LLVM does not reach. Values have the same representations as in the LLVM backend
+(see `runtime`): integer arithmetic wraps, comparisons yield integers, functions return
+`int64_t`, and `main` is the function the IR makes of the top-level code.
+
+Each IR function becomes a C function that declares all of its locals at the top and
+then runs its basic blocks, each under a `bb<n>:` label that the terminators `goto`; a
+block that only falls through to the next one leaves out its jump. The IR has already
+stored everything computed in a temporary, so each instruction is one C statement reading
+names and constants, and runs where the lowering put it. The C compiler folds the
+temporaries away again.
+
+Names are spelled so they cannot collide with C keywords, libc or each other: functions
+get an `f_` prefix, top-level variables `g_` and named locals `v_`, each followed by the
+escaped source name, and a local that shadows another gets a numeric suffix. Temporaries
+are `t` followed by the number of their IR local, and the few values the backend needs
+of its own are `tmp` followed by a count. C functions declared with `extern_venti` keep
+their own names.
+
+What differs from the LLVM backend: async functions run to completion when they are
+called, as in the interpreter, so `await` and `join` only hand back the result; lambdas,
+iterators, spawned blocks, `try_venti` blocks and parameters taking a trait are not
+supported, though methods called on a value of known type are; tests are left out, since
+only `venti test` runs them; and nothing is reference counted, so maps and the strings C
+functions return live until the program exits.
+*/
+
+/// The type of a value in the generated C.
+#[derive(Debug, Clone, PartialEq)]
+enum CType {
+    Int,
+    Float,
+    Bool,
+    Str,
+    Map,
+    Buffer,
+    Range,
+    /// The result of an async call, which already holds its integer result.
+    Task,
+    Channel,
+    Vector,
+    /// The strings `split` and `args` give, a pointer to their `venti_strvec_header`.
+    StringVector,
+    /// A value read by `json_parse`, a pointer to its node or `NULL` for null.
+    Json,
+    Array(Box<CType>, usize),
+}
+
+impl CType {
+    /// Maps an IR type to its type, or to `None` for the types the C backend has no
+    /// values of: lambdas, iterators, traits and types only known once compiled.
+    fn from_type(ty: &Type) -> Option<Self> {
+        let ty = match ty {
+            Type::Int => CType::Int,
+            Type::Float => CType::Float,
+            Type::Bool => CType::Bool,
+            Type::String | Type::Nothing => CType::Str,
+            Type::Map => CType::Map,
+            Type::Buffer => CType::Buffer,
+            Type::Range => CType::Range,
+            Type::Task => CType::Task,
+            Type::Channel(_) => CType::Channel,
+            Type::Vector => CType::Vector,
+            Type::StringVector => CType::StringVector,
+            Type::Json => CType::Json,
+            Type::Array(element, len) => CType::Array(Box::new(CType::from_type(element)?), *len),
+            Type::Lambda(_) | Type::Iterator | Type::Trait(_) | Type::Unknown => return None,
+        };
+        Some(ty)
+    }
+
+    /// Whether a value of this type is or holds a JSON value.
+    fn holds_json(&self) -> bool {
+        match self {
+            CType::Json => true,
+            CType::Array(element, _) => element.holds_json(),
+            _ => false,
+        }
+    }
+}
+
+/// A compiled value: C that computes it, and its type. The C of an operand reads its
+/// value without side effects, so it may be repeated.
+#[derive(Debug, Clone)]
+struct CValue {
+    code: String,
+    ty: CType,
+}
+
+impl CValue {
+    fn new(code: impl Into<String>, ty: CType) -> Self {
+        CValue {
+            code: code.into(),
+            ty,
+        }
+    }
+}
+
+/// A variable or temporary of the program, under its name in the generated C, with its
+/// type in the IR and its C type, which is `None` when C has no values of that type.
+#[derive(Debug, Clone)]
+struct Variable {
+    name: String,
+    ir_type: Type,
+    ty: Option<CType>,
+}
+
+impl Variable {
+    fn new(name: String, ir_type: &Type) -> Self {
+        Variable {
+            name,
+            ir_type: ir_type.clone(),
+            ty: CType::from_type(ir_type),
+        }
+    }
+}
+
+/// A function the program defines.
+struct Signature {
+    symbol: String,
+    params: Vec<CType>,
+    returns: CType,
+    is_async: bool,
+}
+
+/// A C function the program declares with `extern_venti`, with its parameter and result
+/// types; `None` is a `void` result.
+struct Extern {
+    params: Vec<CType>,
+    returns: Option<CType>,
+}
+
+/// Generates a C99 file from the IR of a program.
+pub struct CCodeGen {
+    // The runtime helpers the program uses, in the order they must be emitted
+    helpers: Vec<&'static str>,
+    // The array types the program uses, each the `venti_array<index>` typedef in `typedefs`
+    array_types: Vec<CType>,
+    typedefs: Vec<String>,
+    // File-scope declarations of C functions and top-level variables, then the prototypes
+    // and definitions of the program's functions, with `main` last
+    declarations: Vec<String>,
+    prototypes: Vec<String>,
+    definitions: Vec<String>,
+    functions: HashMap<String, Signature>,
+    externs: HashMap<String, Extern>,
+    globals: Vec<Variable>,
+    // The locals of the function being generated, by their number in the IR
+    locals: Vec<Variable>,
+    // The declarations of the values the backend needs of its own in the function being
+    // generated, which go at its top with the locals
+    temps: Vec<String>,
+    temp_count: usize,
+    // The lines of the function being generated
+    lines: Vec<String>,
+    // The name of the function being compiled, which a panic in it reports, and its
+    // result type, which is `None` for `main`
+    function: String,
+    returns: Option<CType>,
+    // The span of the statement being compiled, whose line is 0 when spans are not recorded
+    span: Span,
+}
+
+impl CCodeGen {
+    /// Creates a new `CCodeGen` with nothing generated yet.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `CCodeGen`.
+    pub fn new() -> Self {
+        CCodeGen {
+            helpers: Vec::new(),
+            array_types: Vec::new(),
+            typedefs: Vec::new(),
+            declarations: Vec::new(),
+            prototypes: Vec::new(),
+            definitions: Vec::new(),
+            functions: HashMap::new(),
+            externs: HashMap::new(),
+            globals: Vec::new(),
+            locals: Vec::new(),
+            temps: Vec::new(),
+            temp_count: 0,
+            lines: Vec::new(),
+            function: "main".to_string(),
+            returns: None,
+            span: Span::default(),
+        }
+    }
+
+    /// Compiles a whole program.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The program, lowered from the syntax tree the analyzer checked.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), VentiError>` - Nothing, or the first error found while generating code.
+    pub fn compile(&mut self, program: Program) -> Result<(), VentiError> {
+        for declared in &program.externs {
+            self.declare_extern(declared)?;
+        }
+        // Every function gets a prototype first, so bodies can call functions defined later
+        for function in &program.functions {
+            if is_generated(function) {
+                self.declare_function(function)?;
+            }
+        }
+        let mut global_names = HashSet::new();
+        self.globals = program
+            .globals
+            .iter()
+            .map(|global| {
+                let name = unique_name(&mut global_names, format!("g_{}", escape(&global.name)));
+                Variable::new(name, &global.ty)
+            })
+            .collect();
+
+        let mut main = None;
+        for function in &program.functions {
+            if function.kind == FunctionKind::Main {
+                main = Some(self.compile_function(function)?);
+            } else if is_generated(function) {
+                let definition = self.compile_function(function)?;
+                self.definitions.push(definition);
+            }
+        }
+        self.definitions.extend(main);
+
+        // A top-level variable of a type C has no values of was never assigned, or
+        // generating the assignment would have failed
+        for global in std::mem::take(&mut self.globals) {
+            if let Some(ty) = &global.ty {
+                let c_type = self.c_type(ty);
+                self.declarations
+                    .push(format!("static {} {};", c_type, global.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the generated C file.
+    pub fn source(&self) -> String {
+        let mut source = String::from(runtime::PRELUDE);
+        for typedef in &self.typedefs {
+            source.push('\n');
+            source.push_str(typedef);
+        }
+        for name in &self.helpers {
+            let (helper, _) = runtime::helper(name).expect("only known helpers are recorded");
+            source.push('\n');
+            source.push_str(helper);
+        }
+        for section in [&self.declarations, &self.prototypes] {
+            if !section.is_empty() {
+                source.push('\n');
+                section.iter().for_each(|line| {
+                    source.push_str(line);
+                    source.push('\n');
+                });
+            }
+        }
+        for definition in &self.definitions {
+            source.push('\n');
+            source.push_str(definition);
+        }
+        source
+    }
+
+    /// Writes the generated C file to `path`.
+    pub fn write_source(&self, path: &Path) -> Result<(), VentiError> {
+        fs::write(path, self.source()).map_err(|e| {
+            VentiError::IOError(format!("Could not write {}: {}", path.display(), e), None)
+        })
+    }
+
+    /// Records the function `function` and writes its prototype.
+    ///
+    /// # Returns
+    ///
+    /// Nothing, or a `VentiError` if the function takes a trait or another function has
+    /// the same name.
+    fn declare_function(&mut self, function: &Function) -> Result<(), VentiError> {
+        let mut params = Vec::new();
+        for param in &function.params {
+            let ty = &function.local(*param).ty;
+            params.push(CType::from_type(ty).ok_or_else(|| unsupported_type(ty))?);
+        }
+        let returns = CType::from_type(&function.returns)
+            .ok_or_else(|| unsupported_type(&function.returns))?;
+        if self.functions.contains_key(&function.name) {
+            return Err(VentiError::CodegenError(
+                format!("Function '{}' is defined more than once", function.name),
+                None,
+            ));
+        }
+        let symbol = format!("f_{}", escape(&function.name));
+        let param_list = self.param_list(params.iter().map(|ty| (ty, None)));
+        // C99 has no way to forbid inlining, so only the hint to inline carries over
+        let inline = match function.inline {
+            Some(InlineHint::Inline) => "inline ",
+            _ => "",
+        };
+        let return_type = self.c_type(&returns);
+        self.prototypes.push(format!(
+            "static {}{} {}({});",
+            inline, return_type, symbol, param_list
+        ));
+        self.functions.insert(
+            function.name.clone(),
+            Signature {
+                symbol,
+                params,
+                returns,
+                is_async: function.kind == FunctionKind::Async,
+            },
+        );
+        Ok(())
+    }
+
+    /// Records the C function `declared` and declares it under its own name.
+    ///
+    /// The types are C's: `int` is an `int`, `float` a `double`, `bool` a `bool` and
+    /// `string` a `const char *` parameter or a `char *` result, and a function with no
+    /// result type returns `void`.
+    fn declare_extern(&mut self, declared: &ExternDecl) -> Result<(), VentiError> {
+        let params = declared
+            .params
+            .iter()
+            .map(|ty| CType::from_type(ty).ok_or_else(|| unsupported_type(ty)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let returns = declared
+            .returns
+            .as_ref()
+            .map(|ty| CType::from_type(ty).ok_or_else(|| unsupported_type(ty)))
+            .transpose()?;
+        let param_list = params
+            .iter()
+            .map(|ty| extern_type(Some(ty), false))
+            .collect::<Vec<_>>();
+        let param_list = match param_list.is_empty() {
+            true => "void".to_string(),
+            false => param_list.join(", "),
+        };
+        self.declarations.push(format!(
+            "extern {} {}({});",
+            extern_type(returns.as_ref(), true),
+            declared.name,
+            param_list
+        ));
+        self.externs
+            .insert(declared.name.clone(), Extern { params, returns });
+        Ok(())
+    }
+
+    /// Appends a line to the function being generated, inside its body.
+    fn line(&mut self, code: impl AsRef<str>) {
+        self.lines.push(format!("    {}", code.as_ref()));
+    }
+
+    /// Returns the C spelling of `ty`, declaring a typedef for an array type on first use.
+    fn c_type(&mut self, ty: &CType) -> String {
+        match ty {
+            CType::Int | CType::Task => "int64_t".to_string(),
+            CType::Float => "double".to_string(),
+            CType::Bool => "bool".to_string(),
+            CType::Str => "venti_str".to_string(),
+            CType::Map => "venti_map".to_string(),
+            CType::Channel => "venti_channel".to_string(),
+            CType::Vector => "venti_vec".to_string(),
+            CType::StringVector => "venti_strvec".to_string(),
+            CType::Json => "venti_json".to_string(),
+            CType::Buffer => "venti_buffer".to_string(),
+            CType::Range => "venti_range".to_string(),
+            CType::Array(element, len) => {
+                if let Some(index) = self.array_types.iter().position(|known| known == ty) {
+                    return format!("venti_array{}", index);
+                }
+                // The element type is declared first, so the typedefs come in order
+                let element = self.c_type(element);
+                let name = format!("venti_array{}", self.array_types.len());
+                // C has no empty arrays, so an empty one keeps a slot it never uses
+                self.typedefs.push(format!(
+                    "typedef struct {{\n    {} items[{}];\n}} {};\n",
+                    element,
+                    (*len).max(1),
+                    name
+                ));
+                self.array_types.push(ty.clone());
+                name
+            }
+        }
+    }
+
+    /// Spells a parameter list of `params`, each with its type and, when given, its name.
+    fn param_list<'a>(
+        &mut self,
+        params: impl Iterator<Item = (&'a CType, Option<&'a str>)>,
+    ) -> String {
+        let params = params
+            .map(|(ty, name)| match name {
+                Some(name) => format!("{} {}", self.c_type(ty), name),
+                None => self.c_type(ty),
+            })
+            .collect::<Vec<_>>();
+        if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        }
+    }
+
+    /// Adds the runtime helper `name` to the file, after the helpers it calls.
+    fn helper(&mut self, name: &'static str) -> Result<(), VentiError> {
+        if self.helpers.contains(&name) {
+            return Ok(());
+        }
+        let (_, calls) = runtime::helper(name).ok_or_else(|| {
+            VentiError::CodegenError(format!("Unknown runtime helper '{}'", name), None)
+        })?;
+        for call in calls {
+            self.helper(call)?;
+        }
+        self.helpers.push(name);
+        Ok(())
+    }
+
+    /// Stores `code`, of type `ty`, in a new value of the backend's own, declared at the
+    /// top of the function, so it can be read more than once.
+    fn temp(&mut self, ty: CType, code: impl AsRef<str>) -> CValue {
+        self.temp_count += 1;
+        let name = format!("tmp{}", self.temp_count);
+        let c_type = self.c_type(&ty);
+        self.temps.push(format!("{} {};", c_type, name));
+        self.line(format!("{} = {};", name, code.as_ref()));
+        CValue::new(name, ty)
+    }
+
+    /// Reads the variable or temporary `place`.
+    fn place(&self, place: Place) -> Result<CValue, VentiError> {
+        let variable = match place {
+            Place::Local(local) => &self.locals[local.0 as usize],
+            Place::Global(global) => &self.globals[global.0 as usize],
+        };
+        match &variable.ty {
+            Some(ty) => Ok(CValue::new(variable.name.clone(), ty.clone())),
+            None => Err(unsupported_type(&variable.ir_type)),
+        }
+    }
+
+    /// The IR type of an operand, which for a channel says what it carries.
+    fn operand_type(&self, operand: &Operand) -> Type {
+        match operand {
+            Operand::Place(Place::Local(local)) => self.locals[local.0 as usize].ir_type.clone(),
+            Operand::Place(Place::Global(global)) => {
+                self.globals[global.0 as usize].ir_type.clone()
+            }
+            Operand::Constant(constant) => constant.ty(),
+        }
+    }
+
+    /// Reads an operand: a variable, a temporary or a constant.
+    fn operand(&self, operand: &Operand) -> Result<CValue, VentiError> {
+        match operand {
+            Operand::Place(place) => self.place(*place),
+            Operand::Constant(constant) => Ok(constant_value(constant)),
+        }
+    }
+
+    /// Compiles a function into the definition of a C function; `main` takes the
+    /// program's arguments and keeps them for `args`.
+    ///
+    /// Blocks nothing jumps to, such as the code after a return, are left out.
+    fn compile_function(&mut self, function: &Function) -> Result<String, VentiError> {
+        let is_main = function.kind == FunctionKind::Main;
+        self.function = function.name.clone();
+        self.returns = match is_main {
+            true => None,
+            false => Some(self.functions[&function.name].returns.clone()),
+        };
+        self.temps.clear();
+        self.temp_count = 0;
+        self.lines.clear();
+        let mut names = HashSet::new();
+        self.locals = function
+            .locals
+            .iter()
+            .enumerate()
+            .map(|(index, local)| {
+                let name = match &local.name {
+                    Some(name) => unique_name(&mut names, format!("v_{}", escape(name))),
+                    None => format!("t{}", index),
+                };
+                Variable::new(name, &local.ty)
+            })
+            .collect();
+
+        if is_main {
+            self.line("venti_argc = argc;");
+            self.line("venti_argv = argv;");
+        }
+        let reachable = reachable_blocks(function);
+        for (position, &id) in reachable.iter().enumerate() {
+            self.lines.push(format!("bb{}:", id.0));
+            let block = function.block(id);
+            for instruction in &block.instructions {
+                self.compile_instruction(instruction)
+                    .map_err(|error| error.at(self.span))?;
+            }
+            let next = reachable.get(position + 1).copied();
+            self.compile_terminator(&block.terminator, next)
+                .map_err(|error| error.at(self.span))?;
+        }
+        // Only the labels something jumps to are kept, as C warns of the others
+        let jumps = self
+            .lines
+            .iter()
+            .filter_map(|line| line.split("goto ").nth(1))
+            .map(|target| format!("{}:", target.trim_end_matches(';')))
+            .collect::<HashSet<_>>();
+        self.lines
+            .retain(|line| !line.starts_with("bb") || jumps.contains(line));
+
+        // Parameters are declared by the parameter list, and locals of a type C has no
+        // values of are never assigned, or generating the assignment would have failed
+        let params = function
+            .params
+            .iter()
+            .map(|param| param.0 as usize)
+            .collect::<HashSet<_>>();
+        let mut declarations = Vec::new();
+        for (index, local) in self.locals.clone().iter().enumerate() {
+            if let (false, Some(ty)) = (params.contains(&index), &local.ty) {
+                let c_type = self.c_type(ty);
+                declarations.push(format!("    {} {};", c_type, local.name));
+            }
+        }
+        declarations.extend(self.temps.iter().map(|temp| format!("    {}", temp)));
+        let body = declarations
+            .into_iter()
+            .chain(std::mem::take(&mut self.lines))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if is_main {
+            return Ok(format!(
+                "int main(int argc, char **argv) {{\n{}\n}}\n",
+                body
+            ));
+        }
+
+        let signature = &self.functions[&function.name];
+        let (symbol, param_types, returns) = (
+            signature.symbol.clone(),
+            signature.params.clone(),
+            signature.returns.clone(),
+        );
+        let names = function
+            .params
+            .iter()
+            .map(|param| self.locals[param.0 as usize].name.clone())
+            .collect::<Vec<_>>();
+        let param_list = self.param_list(
+            param_types
+                .iter()
+                .zip(names.iter().map(|name| Some(name.as_str()))),
+        );
+        let return_type = self.c_type(&returns);
+        Ok(format!(
+            "static {} {}({}) {{\n{}\n}}\n",
+            return_type, symbol, param_list, body
+        ))
+    }
+
+    fn compile_instruction(&mut self, instruction: &Instruction) -> Result<(), VentiError> {
+        match instruction {
+            Instruction::Assign { place, value } => {
+                let value = self.compile_rvalue(value)?;
+                let target = self.place(*place)?;
+                let integral = |ty: &CType| matches!(ty, CType::Int | CType::Bool);
+                if value.ty != target.ty && !(integral(&value.ty) && integral(&target.ty)) {
+                    return Err(VentiError::CodegenError(
+                        "Cannot assign a value of a different type".to_string(),
+                        None,
+                    ));
+                }
+                self.line(format!("{} = {};", target.code, value.code));
+                Ok(())
+            }
+            Instruction::Eval(value) => {
+                let value = self.compile_rvalue(value)?;
+                // A name or a literal has no effects to keep
+                if !value
+                    .code
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    self.line(format!("{};", value.code));
+                }
+                Ok(())
+            }
+            Instruction::SetIndex {
+                target,
+                index,
+                value,
+            } => self.compile_set_index(target, index, value),
+            Instruction::Print { value, newline } => {
+                let value = self.operand(value)?;
+                self.compile_print(value, *newline)
+            }
+            Instruction::Assert(condition) => self.compile_assert(condition),
+            // Runtime errors exit straight from the helpers in `runtime`
+            Instruction::EndTry => Err(unsupported("try_venti blocks")),
+            Instruction::Line(span) => {
+                self.span = *span;
+                Ok(())
+            }
+        }
+    }
+
+    /// Ends a block; `next` is the block written after it, which it can fall through to.
+    fn compile_terminator(
+        &mut self,
+        terminator: &Terminator,
+        next: Option<BlockId>,
+    ) -> Result<(), VentiError> {
+        match terminator {
+            Terminator::Goto(block) => {
+                self.goto(*block, next);
+                Ok(())
+            }
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let condition = self.operand(condition)?;
+                if !matches!(condition.ty, CType::Int | CType::Bool) {
+                    return Err(VentiError::CodegenError(
+                        "A condition must be an int or bool".to_string(),
+                        None,
+                    ));
+                }
+                if Some(*then) == next {
+                    self.line(format!("if (!{}) goto bb{};", condition.code, otherwise.0));
+                } else {
+                    self.line(format!("if ({}) goto bb{};", condition.code, then.0));
+                    self.goto(*otherwise, next);
+                }
+                Ok(())
+            }
+            Terminator::Switch {
+                value,
+                cases,
+                otherwise,
+            } => self.compile_switch(value, cases, *otherwise, next),
+            Terminator::Try { .. } => Err(unsupported("try_venti blocks")),
+            Terminator::Return(value) => {
+                let value = value
+                    .as_ref()
+                    .map(|value| self.operand(value))
+                    .transpose()?;
+                self.compile_return(value)
+            }
+        }
+    }
+
+    /// Jumps to `block`, unless it is `next`, the block written after this one.
+    fn goto(&mut self, block: BlockId, next: Option<BlockId>) {
+        if Some(block) != next {
+            self.line(format!("goto bb{};", block.0));
+        }
+    }
+
+    /// Lowers the switch of a match.
+    ///
+    /// Integer scrutinees become a C `switch`; string scrutinees test each case in order
+    /// with `strcmp`.
+    fn compile_switch(
+        &mut self,
+        value: &Operand,
+        cases: &[(Constant, BlockId)],
+        otherwise: BlockId,
+        next: Option<BlockId>,
+    ) -> Result<(), VentiError> {
+        let scrutinee = self.operand(value)?;
+        match scrutinee.ty {
+            CType::Int | CType::Bool => {
+                self.line(format!("switch ({}) {{", scrutinee.code));
+                for (case, block) in cases {
+                    let Constant::Int(n) = case else {
+                        return Err(VentiError::CodegenError(
+                            "Cannot match an integer against a string pattern".to_string(),
+                            None,
+                        ));
+                    };
+                    self.line(format!("case {}: goto bb{};", int_literal(*n), block.0));
+                }
+                self.line("}");
+            }
+            CType::Str => {
+                for (case, block) in cases {
+                    let Constant::String(pattern) = case else {
+                        return Err(VentiError::CodegenError(
+                            "Cannot match a string against an integer pattern".to_string(),
+                            None,
+                        ));
+                    };
+                    self.line(format!(
+                        "if (strcmp({}.data, {}) == 0) goto bb{};",
+                        scrutinee.code,
+                        string_literal(pattern),
+                        block.0
+                    ));
+                }
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Only integers and strings can be matched".to_string(),
+                    None,
+                ))
+            }
+        }
+        self.goto(otherwise, next);
+        Ok(())
+    }
+
+    /// Returns from the current function; a bare `return_venti;` returns zero, or an empty
+    /// vector from a function returning one, and the result of top-level code is narrowed
+    /// to `main`'s `int` exit code.
+    fn compile_return(&mut self, value: Option<CValue>) -> Result<(), VentiError> {
+        let returns = self.returns.clone().unwrap_or(CType::Int);
+        let code = match (value, returns) {
+            (None, CType::Vector) => {
+                self.helper("venti_vec_new")?;
+                "venti_vec_new()".to_string()
+            }
+            (None, _) => "0".to_string(),
+            (
+                Some(CValue {
+                    code,
+                    ty: CType::Vector,
+                }),
+                CType::Vector,
+            )
+            | (
+                Some(CValue {
+                    code,
+                    ty: CType::Int | CType::Bool,
+                }),
+                CType::Int,
+            ) => code,
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Functions can only return integers, or vectors when they return vec"
+                        .to_string(),
+                    None,
+                ))
+            }
+        };
+        if self.returns.is_some() || code == "0" {
+            self.line(format!("return {};", code));
+        } else {
+            self.line(format!("return (int){};", code));
+        }
+        Ok(())
+    }
+
+    /// Prints `value` with a single `printf` call whose format string follows its type.
+    fn compile_print(&mut self, value: CValue, newline: bool) -> Result<(), VentiError> {
+        let mut format = String::new();
+        let mut args = Vec::new();
+        if value.ty.holds_json() {
+            self.helper("venti_json_text")?;
+        }
+        format_value(&value.code, &value.ty, &mut format, &mut args)?;
+        if newline {
+            format.push('\n');
+        }
+        let mut printf_args = vec![string_literal(&format)];
+        printf_args.extend(args);
+        self.line(format!("printf({});", printf_args.join(", ")));
+        Ok(())
+    }
+
+    /// Compiles `assert_venti`, which prints where a false condition is without stopping
+    /// the program.
+    fn compile_assert(&mut self, condition: &Operand) -> Result<(), VentiError> {
+        let condition = self.operand(condition)?;
+        if !matches!(condition.ty, CType::Int | CType::Bool) {
+            return Err(VentiError::CodegenError(
+                "assert_venti needs an int or bool condition".to_string(),
+                None,
+            ));
+        }
+        let message = match self.span.line {
+            0 => "Assertion failed\n".to_string(),
+            line => format!("Assertion failed on line {}\n", line),
+        };
+        self.line(format!(
+            "if (!{}) printf({});",
+            condition.code,
+            string_literal(&message)
+        ));
+        Ok(())
+    }
+
+    /// Stores into a map entry, a buffer slot or a vector element.
+    fn compile_set_index(
+        &mut self,
+        target: &Operand,
+        index: &Operand,
+        value: &Operand,
+    ) -> Result<(), VentiError> {
+        let target = self.operand(target)?;
+        match target.ty {
+            CType::Buffer => {
+                let slot = self.buffer_slot(&target, index)?;
+                let value = self.slot_value(value)?;
+                self.line(format!("*{} = {};", slot, value));
+            }
+            CType::Vector => {
+                let index = self.vector_index(index)?;
+                let value = self.slot_value(value)?;
+                self.helper("venti_vec_slot")?;
+                self.line(format!(
+                    "*venti_vec_slot({}, {}) = {};",
+                    target.code, index, value
+                ));
+            }
+            CType::Map => {
+                let key = self.map_key(index)?;
+                let value = self.slot_value(value)?;
+                self.helper("venti_map_insert")?;
+                self.line(format!(
+                    "venti_map_insert({}, {}, {});",
+                    target.code, key, value
+                ));
+            }
+            _ => return Err(VentiError::CodegenError(
+                "Only map entries, buffer slots and vector elements can be assigned; arrays are immutable"
+                    .to_string(),
+                None,
+            )),
+        }
+        Ok(())
+    }
+
+    /// Compiles the computation of a value. The C it gives may have effects, such as a
+    /// call, so it is only written once.
+    fn compile_rvalue(&mut self, value: &Rvalue) -> Result<CValue, VentiError> {
+        match value {
+            Rvalue::Use(operand) => self.operand(operand),
+            Rvalue::Binary(op, left, right) => {
+                let left = self.operand(left)?;
+                let right = self.operand(right)?;
+                self.compile_binary_op(left, *op, right)
+            }
+            Rvalue::Unary(op, operand) => {
+                let operand = self.operand(operand)?;
+                let code = match (op, &operand.ty) {
+                    (UnaryOp::Negate, CType::Int) => {
+                        format!("(int64_t)(0 - (uint64_t){})", operand.code)
+                    }
+                    (UnaryOp::Negate, CType::Float) => format!("-{}", operand.code),
+                    (UnaryOp::Not, CType::Bool) => format!("!{}", operand.code),
+                    (op, _) => {
+                        return Err(VentiError::CodegenError(
+                            format!("Operator {:?} is not supported on this operand", op),
+                            None,
+                        ))
+                    }
+                };
+                Ok(CValue::new(code, operand.ty))
+            }
+            Rvalue::Cast(value, target) => self.compile_cast(value, target),
+            Rvalue::IsNothing(value) => {
+                let value = self.operand(value)?;
+                Ok(CValue::new(
+                    format!("{}.data == NULL", value.code),
+                    CType::Bool,
+                ))
+            }
+            Rvalue::Call { function, args } => self.compile_call(function, args),
+            Rvalue::CallClosure { .. } | Rvalue::Closure { .. } => Err(unsupported("Lambdas")),
+            Rvalue::Spawn { .. } => Err(unsupported("Spawned blocks")),
+            Rvalue::Await(task) => await_task(self.operand(task)?),
+            Rvalue::Array(elements) => self.compile_array(elements),
+            Rvalue::Map(entries) => self.compile_map(entries),
+            Rvalue::Range {
+                start,
+                end,
+                inclusive,
+            } => self.compile_range(start, end, *inclusive),
+            Rvalue::RangePart(range, part) => {
+                let range = self.operand(range)?;
+                let field = match part {
+                    RangePart::Start => "start",
+                    RangePart::End => "end",
+                    RangePart::Step => "step",
+                };
+                Ok(CValue::new(format!("{}.{}", range.code, field), CType::Int))
+            }
+            Rvalue::Next(_) | Rvalue::NextPart(..) => Err(unsupported("Iterators")),
+            Rvalue::Len(value) => {
+                let value = self.operand(value)?;
+                self.compile_len(value)
+            }
+            Rvalue::Index { target, index } => self.compile_index(target, index),
+            Rvalue::Method {
+                receiver,
+                name,
+                args,
+            } => self.compile_method_call(receiver, name, args),
+        }
+    }
+
+    /// Calls a function the program defines, a C function or a builtin.
+    fn compile_call(&mut self, identifier: &str, args: &[Operand]) -> Result<CValue, VentiError> {
+        if self.externs.contains_key(identifier) {
+            return self.compile_extern_call(identifier, args);
+        }
+        let Some(function) = self.functions.get(identifier) else {
+            if builtins::is_builtin(identifier) {
+                return self.compile_builtin(identifier, args);
+            }
+            return Err(VentiError::CodegenError(
+                format!("Undefined function '{}'", identifier),
+                None,
+            ));
+        };
+        let (symbol, arity, returns, is_async) = (
+            function.symbol.clone(),
+            function.params.len(),
+            function.returns.clone(),
+            function.is_async,
+        );
+        if arity != args.len() {
+            return Err(VentiError::CodegenError(
+                format!(
+                    "Function '{}' expects {} arguments but {} were given",
+                    identifier,
+                    arity,
+                    args.len()
+                ),
+                None,
+            ));
+        }
+        let args = args
+            .iter()
+            .map(|arg| self.operand(arg).map(|arg| arg.code))
+            .collect::<Result<Vec<_>, _>>()?;
+        // An async call runs to completion here, and its task holds the result
+        let ty = if is_async { CType::Task } else { returns };
+        Ok(CValue::new(format!("{}({})", symbol, args.join(", ")), ty))
+    }
+
+    /// Calls a C function declared with `extern_venti`, converting each argument to its C
+    /// type and the result back.
+    ///
+    /// A string is passed as its NUL-terminated data, or null for `nothing`, and a string
+    /// the function returns is copied, with null becoming `nothing`. Ints are narrowed to
+    /// C's `int` and widened back, and a function returning `void` gives 0.
+    fn compile_extern_call(
+        &mut self,
+        identifier: &str,
+        args: &[Operand],
+    ) -> Result<CValue, VentiError> {
+        let function = &self.externs[identifier];
+        let (params, returns) = (function.params.clone(), function.returns.clone());
+        if params.len() != args.len() {
+            return Err(VentiError::CodegenError(
+                format!(
+                    "C function '{}' expects {} arguments but {} were given",
+                    identifier,
+                    params.len(),
+                    args.len()
+                ),
+                None,
+            ));
+        }
+        let mut compiled_args = Vec::new();
+        for (arg, param) in args.iter().zip(&params) {
+            let value = self.operand(arg)?;
+            compiled_args.push(match (param, &value.ty) {
+                (CType::Str, CType::Str) => format!("{}.data", value.code),
+                (CType::Int, _) => format!("(int){}", value.code),
+                _ => value.code,
+            });
+        }
+        let call = format!("{}({})", identifier, compiled_args.join(", "));
+        Ok(match returns {
+            None => {
+                self.line(format!("{};", call));
+                CValue::new("0", CType::Int)
+            }
+            Some(CType::Str) => {
+                self.helper("venti_c_string")?;
+                CValue::new(format!("venti_c_string({})", call), CType::Str)
+            }
+            Some(CType::Int) => CValue::new(format!("(int64_t){}", call), CType::Int),
+            Some(ty) => CValue::new(call, ty),
+        })
+    }
+
+    /// Lowers a call to one of the built-in functions.
+    ///
+    /// `join(task)` hands back the result of an async call, like `await`.
+    fn compile_builtin(
+        &mut self,
+        identifier: &str,
+        args: &[Operand],
+    ) -> Result<CValue, VentiError> {
+        let arity = builtins::arity(identifier).ok_or_else(|| {
+            VentiError::CodegenError(format!("Unknown builtin '{}'", identifier), None)
+        })?;
+        if args.len() != arity {
+            return Err(VentiError::CodegenError(
+                format!(
+                    "Builtin '{}' expects {} argument(s) but {} were given",
+                    identifier,
+                    arity,
+                    args.len()
+                ),
+                None,
+            ));
+        }
+        let mut args = args.iter();
+        let mut next_arg = || {
+            args.next().ok_or_else(|| {
+                VentiError::CodegenError("Missing builtin argument".to_string(), None)
+            })
+        };
+        match identifier {
+            "free" => self.compile_free(next_arg()?),
+            // `len(x)` is the same as `x.len()`, including the static length of arrays
+            "len" => {
+                let value = self.operand(next_arg()?)?;
+                self.compile_len(value)
+            }
+            // Iterators call lambdas, which C has no closures for
+            "map" | "filter" | "reduce" | "collect" | "chars" => Err(unsupported("Iterators")),
+            "sort_by" => Err(unsupported("Lambdas")),
+            "sort" => {
+                let slots = self.slots(next_arg()?, identifier)?;
+                self.helper("venti_sort")?;
+                Ok(CValue::new(format!("venti_sort({})", slots), CType::Int))
+            }
+            "binary_search" => {
+                let slots = self.slots(next_arg()?, identifier)?;
+                let value = self.operand(next_arg()?)?;
+                expect_arg(&value, CType::Int, identifier)?;
+                self.helper("venti_binary_search")?;
+                Ok(CValue::new(
+                    format!("venti_binary_search({}, {})", slots, value.code),
+                    CType::Int,
+                ))
+            }
+            "join" => await_task(self.operand(next_arg()?)?),
+            "alloc" => {
+                let len = self.operand(next_arg()?)?;
+                if len.ty != CType::Int {
+                    return Err(VentiError::CodegenError(
+                        "'alloc' expects an integer length".to_string(),
+                        None,
+                    ));
+                }
+                self.helper("venti_buffer_alloc")?;
+                Ok(CValue::new(
+                    format!("venti_buffer_alloc({})", len.code),
+                    CType::Buffer,
+                ))
+            }
+            "byte_at" => {
+                let string = self.operand(next_arg()?)?;
+                let index = self.operand(next_arg()?)?;
+                if string.ty != CType::Str {
+                    return Err(VentiError::CodegenError(
+                        "'byte_at' expects a string".to_string(),
+                        None,
+                    ));
+                }
+                if index.ty != CType::Int {
+                    return Err(VentiError::CodegenError(
+                        "'byte_at' expects an integer index".to_string(),
+                        None,
+                    ));
+                }
+                self.helper("venti_str_byte_at")?;
+                Ok(CValue::new(
+                    format!("venti_str_byte_at({}, {})", string.code, index.code),
+                    CType::Int,
+                ))
+            }
+            "input_venti" => {
+                let prompt = self.operand(next_arg()?)?;
+                expect_arg(&prompt, CType::Str, identifier)?;
+                self.helper("venti_read_line")?;
+                Ok(CValue::new(
+                    format!("venti_read_line({})", prompt.code),
+                    CType::Str,
+                ))
+            }
+            "read_file" => {
+                let path = self.operand(next_arg()?)?;
+                expect_arg(&path, CType::Str, identifier)?;
+                self.helper("venti_file_read")?;
+                Ok(CValue::new(
+                    format!("venti_file_read({})", path.code),
+                    CType::Str,
+                ))
+            }
+            "write_file" | "append_file" => {
+                let path = self.operand(next_arg()?)?;
+                let contents = self.operand(next_arg()?)?;
+                expect_arg(&path, CType::Str, identifier)?;
+                expect_arg(&contents, CType::Str, identifier)?;
+                let mode = if identifier == "write_file" {
+                    "wb"
+                } else {
+                    "ab"
+                };
+                self.helper("venti_file_write")?;
+                Ok(CValue::new(
+                    format!(
+                        "venti_file_write({}, {}, \"{}\")",
+                        path.code, contents.code, mode
+                    ),
+                    CType::Int,
+                ))
+            }
+            "random" => {
+                self.helper("venti_random_float")?;
+                Ok(CValue::new("venti_random_float()", CType::Float))
+            }
+            "random_int" => {
+                let low = self.operand(next_arg()?)?;
+                let high = self.operand(next_arg()?)?;
+                expect_arg(&low, CType::Int, identifier)?;
+                expect_arg(&high, CType::Int, identifier)?;
+                self.helper("venti_random_int")?;
+                Ok(CValue::new(
+                    format!("venti_random_int({}, {})", low.code, high.code),
+                    CType::Int,
+                ))
+            }
+            "seed_random" => {
+                let seed = self.operand(next_arg()?)?;
+                expect_arg(&seed, CType::Int, identifier)?;
+                self.helper("venti_random_seed")?;
+                Ok(CValue::new(
+                    format!("venti_random_seed({})", seed.code),
+                    CType::Int,
+                ))
+            }
+            "exit_venti" => {
+                let code = self.operand(next_arg()?)?;
+                expect_arg(&code, CType::Int, identifier)?;
+                // `exit` flushes what the program printed
+                self.line(format!("exit((int){});", code.code));
+                Ok(CValue::new("0", CType::Int))
+            }
+            "args" => {
+                self.helper("venti_args")?;
+                Ok(CValue::new("venti_args()", CType::StringVector))
+            }
+            "env" => {
+                let name = self.operand(next_arg()?)?;
+                expect_arg(&name, CType::Str, identifier)?;
+                self.helper("venti_env")?;
+                Ok(CValue::new(format!("venti_env({})", name.code), CType::Str))
+            }
+            "exec" => {
+                let command = self.operand(next_arg()?)?;
+                expect_arg(&command, CType::Str, identifier)?;
+                self.helper("venti_exec")?;
+                Ok(CValue::new(
+                    format!("venti_exec({})", command.code),
+                    CType::Str,
+                ))
+            }
+            "http_get" | "http_post" => {
+                let url = self.operand(next_arg()?)?;
+                expect_arg(&url, CType::Str, identifier)?;
+                let (method, body) = if identifier == "http_post" {
+                    let body = self.operand(next_arg()?)?;
+                    expect_arg(&body, CType::Str, identifier)?;
+                    ("POST", body.code)
+                } else {
+                    ("GET", "VENTI_NOTHING".to_string())
+                };
+                self.helper("venti_http")?;
+                Ok(CValue::new(
+                    format!("venti_http(\"{}\", {}, {})", method, url.code, body),
+                    CType::Str,
+                ))
+            }
+            "vec" => {
+                self.helper("venti_vec_new")?;
+                Ok(CValue::new("venti_vec_new()", CType::Vector))
+            }
+            "channel" => {
+                self.helper("venti_channel_new")?;
+                Ok(CValue::new("venti_channel_new()", CType::Channel))
+            }
+            "send" => {
+                let channel = self.operand(next_arg()?)?;
+                let value = self.operand(next_arg()?)?;
+                expect_arg(&channel, CType::Channel, identifier)?;
+                // The channel keeps each value in an integer slot
+                let slot = match value.ty {
+                    CType::Float => {
+                        self.helper("venti_float_bits")?;
+                        format!("venti_float_bits({})", value.code)
+                    }
+                    CType::Bool => format!("(int64_t){}", value.code),
+                    _ => {
+                        expect_arg(&value, CType::Int, identifier)?;
+                        value.code
+                    }
+                };
+                self.helper("venti_channel_send")?;
+                Ok(CValue::new(
+                    format!("venti_channel_send({}, {})", channel.code, slot),
+                    CType::Int,
+                ))
+            }
+            "recv" => {
+                let operand = next_arg()?;
+                let channel = self.operand(operand)?;
+                expect_arg(&channel, CType::Channel, identifier)?;
+                self.helper("venti_channel_recv")?;
+                let slot = format!("venti_channel_recv({})", channel.code);
+                match self.operand_type(operand) {
+                    Type::Channel(element) if *element == Type::Float => {
+                        self.helper("venti_bits_float")?;
+                        Ok(CValue::new(
+                            format!("venti_bits_float({})", slot),
+                            CType::Float,
+                        ))
+                    }
+                    Type::Channel(element) if *element == Type::Bool => {
+                        Ok(CValue::new(format!("({} != 0)", slot), CType::Bool))
+                    }
+                    _ => Ok(CValue::new(slot, CType::Int)),
+                }
+            }
+            "tcp_connect" => {
+                let host = self.operand(next_arg()?)?;
+                let port = self.operand(next_arg()?)?;
+                expect_arg(&host, CType::Str, identifier)?;
+                expect_arg(&port, CType::Int, identifier)?;
+                self.helper("venti_tcp_connect")?;
+                Ok(CValue::new(
+                    format!(
+                        "venti_tcp_connect({0}.len > 0 ? {0}.data : \"\", {1})",
+                        host.code, port.code
+                    ),
+                    CType::Int,
+                ))
+            }
+            "tcp_listen" | "tcp_accept" | "tcp_port" | "tcp_close" => {
+                let socket = self.operand(next_arg()?)?;
+                expect_arg(&socket, CType::Int, identifier)?;
+                let helper = match identifier {
+                    "tcp_listen" => "venti_tcp_listen",
+                    "tcp_accept" => "venti_tcp_accept",
+                    "tcp_port" => "venti_tcp_port",
+                    _ => "venti_tcp_close",
+                };
+                self.helper(helper)?;
+                Ok(CValue::new(
+                    format!("{}({})", helper, socket.code),
+                    CType::Int,
+                ))
+            }
+            "tcp_send" => {
+                let socket = self.operand(next_arg()?)?;
+                let data = self.operand(next_arg()?)?;
+                expect_arg(&socket, CType::Int, identifier)?;
+                expect_arg(&data, CType::Str, identifier)?;
+                self.helper("venti_tcp_send")?;
+                self.line(format!(
+                    "venti_tcp_send({}, {1}.data, {1}.len);",
+                    socket.code, data.code
+                ));
+                Ok(CValue::new(format!("{}.len", data.code), CType::Int))
+            }
+            "tcp_recv" => {
+                let socket = self.operand(next_arg()?)?;
+                expect_arg(&socket, CType::Int, identifier)?;
+                self.helper("venti_tcp_recv")?;
+                Ok(CValue::new(
+                    format!("venti_tcp_recv({})", socket.code),
+                    CType::Str,
+                ))
+            }
+            "exec_status" => {
+                self.helper("venti_exec_status")?;
+                Ok(CValue::new("venti_exec_status()", CType::Int))
+            }
+            "json_parse" => {
+                let json = self.operand(next_arg()?)?;
+                expect_arg(&json, CType::Str, identifier)?;
+                self.helper("venti_json_parse")?;
+                Ok(CValue::new(
+                    format!("venti_json_parse({})", json.code),
+                    CType::Json,
+                ))
+            }
+            "json_kind" => {
+                let json = self.operand(next_arg()?)?;
+                expect_arg(&json, CType::Json, identifier)?;
+                self.helper("venti_json_kind")?;
+                Ok(CValue::new(
+                    format!("venti_json_kind({})", json.code),
+                    CType::Str,
+                ))
+            }
+            "json_stringify" => {
+                let value = self.operand(next_arg()?)?;
+                self.helper("venti_write_finish")?;
+                self.temp_count += 1;
+                let writer = format!("json{}", self.temp_count);
+                self.temps.push(format!("venti_writer {};", writer));
+                self.line(format!("{} = (venti_writer){{NULL, 0, 0}};", writer));
+                self.write_json(&writer, &value.code, &value.ty)?;
+                Ok(CValue::new(
+                    format!("venti_write_finish(&{})", writer),
+                    CType::Str,
+                ))
+            }
+            "now_millis" => {
+                self.helper("venti_now_millis")?;
+                Ok(CValue::new("venti_now_millis()", CType::Int))
+            }
+            "timer" => {
+                self.helper("venti_timer")?;
+                Ok(CValue::new("venti_timer()", CType::Int))
+            }
+            "elapsed" => {
+                let start = self.operand(next_arg()?)?;
+                expect_arg(&start, CType::Int, identifier)?;
+                self.helper("venti_elapsed")?;
+                Ok(CValue::new(
+                    format!("venti_elapsed({})", start.code),
+                    CType::Float,
+                ))
+            }
+            "sleep_ms" => {
+                let millis = self.operand(next_arg()?)?;
+                expect_arg(&millis, CType::Int, identifier)?;
+                self.helper("venti_sleep_ms")?;
+                Ok(CValue::new(
+                    format!("venti_sleep_ms({})", millis.code),
+                    CType::Int,
+                ))
+            }
+            "sqrt" | "floor" | "ceil" | "sin" | "cos" => {
+                let value = self.operand(next_arg()?)?;
+                let value = as_double(&value, identifier)?;
+                Ok(CValue::new(
+                    format!("{}({})", identifier, value),
+                    CType::Float,
+                ))
+            }
+            "pow" => {
+                let base = self.operand(next_arg()?)?;
+                let exponent = self.operand(next_arg()?)?;
+                let base = as_double(&base, "pow")?;
+                let exponent = as_double(&exponent, "pow")?;
+                Ok(CValue::new(
+                    format!("pow({}, {})", base, exponent),
+                    CType::Float,
+                ))
+            }
+            "abs" => {
+                let value = self.operand(next_arg()?)?;
+                if value.ty == CType::Int {
+                    // Negating through unsigned wraps the smallest integer back to itself
+                    return Ok(CValue::new(
+                        format!("({0} < 0 ? (int64_t)(0 - (uint64_t){0}) : {0})", value.code),
+                        CType::Int,
+                    ));
+                }
+                let value = as_double(&value, "abs")?;
+                Ok(CValue::new(format!("fabs({})", value), CType::Float))
+            }
+            "min" | "max" => {
+                let left = self.operand(next_arg()?)?;
+                let right = self.operand(next_arg()?)?;
+                if left.ty == CType::Int && right.ty == CType::Int {
+                    let op = if identifier == "min" { "<" } else { ">" };
+                    return Ok(CValue::new(
+                        format!("({0} {1} {2} ? {0} : {2})", left.code, op, right.code),
+                        CType::Int,
+                    ));
+                }
+                let left = as_double(&left, identifier)?;
+                let right = as_double(&right, identifier)?;
+                let function = if identifier == "min" { "fmin" } else { "fmax" };
+                Ok(CValue::new(
+                    format!("{}({}, {})", function, left, right),
+                    CType::Float,
+                ))
+            }
+            "upper" | "lower" | "trim" | "to_int" | "to_float" => {
+                let string = self.operand(next_arg()?)?;
+                expect_arg(&string, CType::Str, identifier)?;
+                let (helper, ty) = match identifier {
+                    "upper" => ("venti_str_upper", CType::Str),
+                    "lower" => ("venti_str_lower", CType::Str),
+                    "trim" => ("venti_str_trim", CType::Str),
+                    "to_int" => ("venti_str_to_int", CType::Int),
+                    _ => ("venti_str_to_float", CType::Float),
+                };
+                self.helper(helper)?;
+                Ok(CValue::new(format!("{}({})", helper, string.code), ty))
+            }
+            "contains" => {
+                let string = self.operand(next_arg()?)?;
+                let needle = self.operand(next_arg()?)?;
+                expect_arg(&string, CType::Str, identifier)?;
+                expect_arg(&needle, CType::Str, identifier)?;
+                self.helper("venti_str_contains")?;
+                Ok(CValue::new(
+                    format!("venti_str_contains({}, {})", string.code, needle.code),
+                    CType::Int,
+                ))
+            }
+            "substring" => {
+                let string = self.operand(next_arg()?)?;
+                let start = self.operand(next_arg()?)?;
+                let end = self.operand(next_arg()?)?;
+                expect_arg(&string, CType::Str, identifier)?;
+                expect_arg(&start, CType::Int, identifier)?;
+                expect_arg(&end, CType::Int, identifier)?;
+                self.helper("venti_str_substring")?;
+                Ok(CValue::new(
+                    format!(
+                        "venti_str_substring({}, {}, {})",
+                        string.code, start.code, end.code
+                    ),
+                    CType::Str,
+                ))
+            }
+            "split" => {
+                let string = self.operand(next_arg()?)?;
+                let separator = self.operand(next_arg()?)?;
+                expect_arg(&string, CType::Str, identifier)?;
+                expect_arg(&separator, CType::Str, identifier)?;
+                self.helper("venti_str_split")?;
+                Ok(CValue::new(
+                    format!("venti_str_split({}, {})", string.code, separator.code),
+                    CType::StringVector,
+                ))
+            }
+            "panic_venti" => {
+                let message = self.operand(next_arg()?)?;
+                if message.ty != CType::Str {
+                    return Err(VentiError::CodegenError(
+                        "'panic_venti' expects a string message".to_string(),
+                        None,
+                    ));
+                }
+                self.helper("venti_panic")?;
+                self.line(format!(
+                    "venti_panic({}.data, {});",
+                    message.code,
+                    string_literal(&self.function)
+                ));
+                Ok(CValue::new("0", CType::Int))
+            }
+            _ => Err(VentiError::CodegenError(
+                format!("Unknown builtin '{}'", identifier),
+                None,
+            )),
+        }
+    }
+
+    /// Emits the lines writing `code`, of type `ty`, as JSON to the `venti_writer`
+    /// named `writer`, spelling out the elements of an array one by one.
+    fn write_json(&mut self, writer: &str, code: &str, ty: &CType) -> Result<(), VentiError> {
+        let (helper, arg) = match ty {
+            CType::Int => ("venti_json_int", code.to_string()),
+            CType::Float => ("venti_json_float", code.to_string()),
+            CType::Str => ("venti_json_quote", code.to_string()),
+            CType::Map => ("venti_json_map", code.to_string()),
+            CType::Json => ("venti_json_write", code.to_string()),
+            CType::Bool => (
+                "venti_write_raw",
+                format!("{0} ? \"true\" : \"false\", {0} ? 4 : 5", code),
+            ),
+            CType::Array(element, len) => {
+                self.line(format!("venti_write_raw(&{}, \"[\", 1);", writer));
+                for index in 0..*len {
+                    if index > 0 {
+                        self.line(format!("venti_write_raw(&{}, \",\", 1);", writer));
+                    }
+                    self.write_json(writer, &format!("{}.items[{}]", code, index), element)?;
+                }
+                self.line(format!("venti_write_raw(&{}, \"]\", 1);", writer));
+                return Ok(());
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "This value cannot be written as JSON".to_string(),
+                    None,
+                ))
+            }
+        };
+        self.helper(helper)?;
+        self.line(format!("{}(&{}, {});", helper, writer, arg));
+        Ok(())
+    }
+
+    /// Lowers `free(buffer)`, releasing the buffer's slots.
+    ///
+    /// When the buffer is held in a variable, the variable is reset to the empty buffer,
+    /// so using it afterwards is a bounds error and freeing it again does nothing.
+    fn compile_free(&mut self, arg: &Operand) -> Result<CValue, VentiError> {
+        let buffer = self.operand(arg)?;
+        if buffer.ty != CType::Buffer {
+            return Err(VentiError::CodegenError(
+                "'free' expects a buffer".to_string(),
+                None,
+            ));
+        }
+        self.line(format!("free({}.data);", buffer.code));
+        if let Operand::Place(_) = arg {
+            self.line(format!("{} = (venti_buffer){{NULL, 0}};", buffer.code));
+        }
+        Ok(CValue::new("0", CType::Int))
+    }
+
+    /// Returns the address of slot `index` of `buffer`, checking it against the length.
+    fn buffer_slot(&mut self, buffer: &CValue, index: &Operand) -> Result<String, VentiError> {
+        let index = self.operand(index)?;
+        if !matches!(index.ty, CType::Int | CType::Bool) {
+            return Err(VentiError::CodegenError(
+                "Buffer index must be an integer".to_string(),
+                None,
+            ));
+        }
+        self.helper("venti_buffer_slot")?;
+        Ok(format!(
+            "venti_buffer_slot({}, {})",
+            buffer.code, index.code
+        ))
+    }
+
+    /// Reads the index of a vector element, which must be an integer.
+    fn vector_index(&self, index: &Operand) -> Result<String, VentiError> {
+        let index = self.operand(index)?;
+        match index.ty {
+            CType::Int | CType::Bool => Ok(index.code),
+            _ => Err(VentiError::CodegenError(
+                "Vector index must be an integer".to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Reads the vector or buffer argument of the builtin `name` as the slots and length
+    /// the sorting helpers take, as two comma-separated arguments.
+    fn slots(&self, value: &Operand, name: &str) -> Result<String, VentiError> {
+        let value = self.operand(value)?;
+        match value.ty {
+            CType::Vector => Ok(format!("{0}->slots, {0}->len", value.code)),
+            CType::Buffer => Ok(format!("{0}.data, {0}.len", value.code)),
+            _ => Err(VentiError::CodegenError(
+                format!("'{}' expects a vector or buffer", name),
+                None,
+            )),
+        }
+    }
+
+    /// Reads a map key, which must be a string, as its data.
+    fn map_key(&self, key: &Operand) -> Result<String, VentiError> {
+        let key = self.operand(key)?;
+        match key.ty {
+            CType::Str => Ok(format!("{}.data", key.code)),
+            _ => Err(VentiError::CodegenError(
+                "Map keys must be strings".to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Reads a value stored in a map entry or buffer slot, which must be an integer.
+    fn slot_value(&self, value: &Operand) -> Result<String, VentiError> {
+        let value = self.operand(value)?;
+        match value.ty {
+            CType::Int => Ok(value.code),
+            CType::Bool => Ok(format!("(int64_t){}", value.code)),
+            _ => Err(VentiError::CodegenError(
+                "Map and buffer values must be integers".to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Lowers `value as target` between ints, floats and bools, or out of a JSON value.
+    ///
+    /// Floats convert to integers by truncating toward zero and saturating at the ends of
+    /// the `int` range, with NaN becoming 0, and anything nonzero converts to `true`.
+    fn compile_cast(&mut self, value: &Operand, target: &Type) -> Result<CValue, VentiError> {
+        let value = self.operand(value)?;
+        let code = &value.code;
+        if value.ty == CType::Json {
+            let (helper, ty) = match target {
+                Type::Int => ("venti_json_to_int", CType::Int),
+                Type::Float => ("venti_json_to_float", CType::Float),
+                Type::Bool => ("venti_json_to_bool", CType::Bool),
+                Type::String => ("venti_json_to_str", CType::Str),
+                _ => {
+                    return Err(VentiError::CodegenError(
+                        format!("Cannot cast json to {}", target),
+                        None,
+                    ))
+                }
+            };
+            self.helper(helper)?;
+            return Ok(CValue::new(format!("{}({})", helper, code), ty));
+        }
+        let (code, ty) = match (&value.ty, target) {
+            (CType::Int, Type::Int) | (CType::Float, Type::Float) => return Ok(value),
+            (CType::Bool, Type::Bool) => return Ok(value),
+            (CType::Bool, Type::Int) => (format!("(int64_t){}", code), CType::Int),
+            (CType::Int | CType::Bool, Type::Float) => (format!("(double){}", code), CType::Float),
+            (CType::Int, Type::Bool) => (format!("{} != 0", code), CType::Bool),
+            (CType::Float, Type::Int) => {
+                self.helper("venti_float_to_int")?;
+                (format!("venti_float_to_int({})", code), CType::Int)
+            }
+            (CType::Float, Type::Bool) => (format!("{} != 0.0", code), CType::Bool),
+            (_, Type::Trait(_)) => return Err(unsupported("Parameters taking a trait")),
+            _ => {
+                return Err(VentiError::CodegenError(
+                    "Only numbers and bools can be cast".to_string(),
+                    None,
+                ))
+            }
+        };
+        Ok(CValue::new(code, ty))
+    }
+
+    /// Builds an array literal as a value of a struct type holding the elements.
+    ///
+    /// All elements must share one type, and an empty array is an array of integers.
+    fn compile_array(&mut self, elements: &[Operand]) -> Result<CValue, VentiError> {
+        let values = elements
+            .iter()
+            .map(|element| self.operand(element))
+            .collect::<Result<Vec<_>, _>>()?;
+        let element_type = values
+            .first()
+            .map(|value| value.ty.clone())
+            .unwrap_or(CType::Int);
+        if values.iter().any(|value| value.ty != element_type) {
+            return Err(VentiError::CodegenError(
+                "All elements of an array must have the same type".to_string(),
+                None,
+            ));
+        }
+        let ty = CType::Array(Box::new(element_type), values.len());
+        let c_type = self.c_type(&ty);
+        let elements = match values.is_empty() {
+            true => "0".to_string(),
+            false => values
+                .into_iter()
+                .map(|value| value.code)
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        Ok(CValue::new(format!("({}){{{{{}}}}}", c_type, elements), ty))
+    }
+
+    /// Builds a map literal by allocating a runtime table and inserting every entry.
+    ///
+    /// Keys must be strings and values integers. The table starts large enough that the
+    /// literal's own entries never trigger a resize.
+    fn compile_map(&mut self, entries: &[(Operand, Operand)]) -> Result<CValue, VentiError> {
+        let capacity = (entries.len() * 2).max(8).next_power_of_two();
+        self.helper("venti_map_new")?;
+        let map = self.temp(CType::Map, format!("venti_map_new({})", capacity));
+        for (key, value) in entries {
+            let key = self.map_key(key)?;
+            let value = self.slot_value(value)?;
+            self.helper("venti_map_insert")?;
+            self.line(format!(
+                "venti_map_insert({}, {}, {});",
+                map.code, key, value
+            ));
+        }
+        Ok(map)
+    }
+
+    /// Builds a range; an inclusive range is stored with its end bumped by one.
+    fn compile_range(
+        &mut self,
+        start: &Operand,
+        end: &Operand,
+        inclusive: bool,
+    ) -> Result<CValue, VentiError> {
+        let mut bounds = Vec::new();
+        for bound in [start, end] {
+            let bound = self.operand(bound)?;
+            if bound.ty != CType::Int {
+                return Err(VentiError::CodegenError(
+                    "Range bounds must be integers".to_string(),
+                    None,
+                ));
+            }
+            bounds.push(bound.code);
+        }
+        let end = match inclusive {
+            true => format!("(int64_t)((uint64_t){} + 1)", bounds[1]),
+            false => bounds[1].clone(),
+        };
+        Ok(CValue::new(
+            format!("(venti_range){{{}, {}, 1}}", bounds[0], end),
+            CType::Range,
+        ))
+    }
+
+    /// Lowers `target[index]` on an array, map, buffer, vector, string or JSON value.
+    ///
+    /// A constant index past the end of an array is an error; other array indices are
+    /// not checked, as in the LLVM backend.
+    fn compile_index(&mut self, target: &Operand, index: &Operand) -> Result<CValue, VentiError> {
+        let target = self.operand(target)?;
+        match target.ty.clone() {
+            CType::Map => {
+                let key = self.map_key(index)?;
+                self.helper("venti_map_get")?;
+                Ok(CValue::new(
+                    format!("venti_map_get({}, {})", target.code, key),
+                    CType::Int,
+                ))
+            }
+            CType::Buffer => {
+                let slot = self.buffer_slot(&target, index)?;
+                Ok(CValue::new(format!("*{}", slot), CType::Int))
+            }
+            CType::Vector => {
+                let index = self.operand(index)?;
+                match index.ty {
+                    CType::Range => {
+                        self.helper("venti_vec_range")?;
+                        Ok(CValue::new(
+                            format!("venti_vec_range({}, {})", target.code, index.code),
+                            CType::Vector,
+                        ))
+                    }
+                    CType::Int | CType::Bool => {
+                        self.helper("venti_vec_slot")?;
+                        Ok(CValue::new(
+                            format!("*venti_vec_slot({}, {})", target.code, index.code),
+                            CType::Int,
+                        ))
+                    }
+                    _ => Err(VentiError::CodegenError(
+                        "Vector index must be an integer or a range".to_string(),
+                        None,
+                    )),
+                }
+            }
+            CType::StringVector => {
+                let index = self.vector_index(index)?;
+                self.helper("venti_strvec_slot")?;
+                Ok(CValue::new(
+                    format!("*venti_strvec_slot({}, {})", target.code, index),
+                    CType::Str,
+                ))
+            }
+            CType::Array(element, len) => {
+                if let Operand::Constant(Constant::Int(constant)) = index {
+                    if *constant < 0 || *constant as u64 >= len as u64 {
+                        return Err(VentiError::CodegenError(
+                            format!(
+                                "Index {} is out of bounds for an array of length {}",
+                                constant, len
+                            ),
+                            None,
+                        ));
+                    }
+                }
+                let index = self.operand(index)?;
+                if !matches!(index.ty, CType::Int | CType::Bool) {
+                    return Err(VentiError::CodegenError(
+                        "Array index must be an integer".to_string(),
+                        None,
+                    ));
+                }
+                Ok(CValue::new(
+                    format!("{}.items[{}]", target.code, index.code),
+                    *element,
+                ))
+            }
+            CType::Str => {
+                let range = self.operand(index)?;
+                if range.ty != CType::Range {
+                    return Err(VentiError::CodegenError(
+                        "Strings can only be sliced with a range".to_string(),
+                        None,
+                    ));
+                }
+                self.helper("venti_str_range")?;
+                Ok(CValue::new(
+                    format!("venti_str_range({}, {})", target.code, range.code),
+                    CType::Str,
+                ))
+            }
+            CType::Json => {
+                let index = self.operand(index)?;
+                let helper = match index.ty {
+                    CType::Str => "venti_json_member",
+                    CType::Int | CType::Bool => "venti_json_element",
+                    _ => {
+                        return Err(VentiError::CodegenError(
+                            "JSON values are indexed by a string key or an integer".to_string(),
+                            None,
+                        ))
+                    }
+                };
+                self.helper(helper)?;
+                Ok(CValue::new(
+                    format!("{}({}, {})", helper, target.code, index.code),
+                    CType::Json,
+                ))
+            }
+            _ => Err(VentiError::CodegenError(
+                "Only arrays, maps, buffers, vectors, strings and JSON values can be indexed"
+                    .to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Lowers the length of `value`, which an array knows without being read.
+    fn compile_len(&mut self, value: CValue) -> Result<CValue, VentiError> {
+        match value.ty {
+            CType::Array(_, len) => Ok(CValue::new(len.to_string(), CType::Int)),
+            CType::Map | CType::Vector | CType::StringVector => {
+                Ok(CValue::new(format!("{}->len", value.code), CType::Int))
+            }
+            CType::Str | CType::Buffer => {
+                Ok(CValue::new(format!("{}.len", value.code), CType::Int))
+            }
+            CType::Json => {
+                self.helper("venti_json_len")?;
+                Ok(CValue::new(
+                    format!("venti_json_len({})", value.code),
+                    CType::Int,
+                ))
+            }
+            _ => Err(VentiError::CodegenError(
+                "'len' can only be called on arrays, maps, strings, buffers, vectors and JSON values"
+                    .to_string(),
+                None,
+            )),
+        }
+    }
+
+    /// Lowers a call to one of the built-in methods.
+    ///
+    /// Only methods with a fixed lowering are supported; the IR has already made a call of
+    /// a trait's method on a value of known type a call of the function implementing it,
+    /// so anything else is reported as a codegen error naming the method.
+    fn compile_method_call(
+        &mut self,
+        receiver: &Operand,
+        name: &str,
+        args: &[Operand],
+    ) -> Result<CValue, VentiError> {
+        let receiver = self.operand(receiver)?;
+        match (name, args) {
+            ("len", []) => self.compile_len(receiver),
+            ("push", [value]) => {
+                if receiver.ty != CType::Vector {
+                    return Err(VentiError::CodegenError(
+                        "'push' is not supported on fixed-size arrays".to_string(),
+                        None,
+                    ));
+                }
+                let value = self.operand(value)?;
+                if !matches!(value.ty, CType::Int | CType::Bool) {
+                    return Err(VentiError::CodegenError(
+                        "'push' expects an integer".to_string(),
+                        None,
+                    ));
+                }
+                self.helper("venti_vec_push")?;
+                Ok(CValue::new(
+                    format!("venti_vec_push({}, {})", receiver.code, value.code),
+                    CType::Int,
+                ))
+            }
+            ("pop", []) => {
+                expect_arg(&receiver, CType::Vector, "pop")?;
+                self.helper("venti_vec_pop")?;
+                Ok(CValue::new(
+                    format!("venti_vec_pop({})", receiver.code),
+                    CType::Int,
+                ))
+            }
+            ("has", [key]) => {
+                if receiver.ty != CType::Map {
+                    return Err(VentiError::CodegenError(
+                        "'has' can only be called on maps".to_string(),
+                        None,
+                    ));
+                }
+                let key = self.map_key(key)?;
+                self.helper("venti_map_has")?;
+                Ok(CValue::new(
+                    format!("venti_map_has({}, {})", receiver.code, key),
+                    CType::Int,
+                ))
+            }
+            ("step", [step]) => {
+                if receiver.ty != CType::Range {
+                    return Err(VentiError::CodegenError(
+                        "'step' can only be called on ranges".to_string(),
+                        None,
+                    ));
+                }
+                // Ranges only count upwards, so a constant step must be positive
+                if let Operand::Constant(Constant::Int(constant)) = step {
+                    if *constant <= 0 {
+                        return Err(VentiError::CodegenError(
+                            "Range step must be positive".to_string(),
+                            None,
+                        ));
+                    }
+                }
+                let step = self.operand(step)?;
+                if step.ty != CType::Int {
+                    return Err(VentiError::CodegenError(
+                        "'step' expects an integer".to_string(),
+                        None,
+                    ));
+                }
+                Ok(CValue::new(
+                    format!(
+                        "(venti_range){{{0}.start, {0}.end, {1}}}",
+                        receiver.code, step.code
+                    ),
+                    CType::Range,
+                ))
+            }
+            ("len" | "push" | "pop" | "has" | "step", _) => Err(VentiError::CodegenError(
+                format!("Wrong number of arguments to method '{}'", name),
+                None,
+            )),
+            _ => Err(VentiError::CodegenError(
+                format!("Unknown method '{}'", name),
+                None,
+            )),
+        }
+    }
+
+    fn compile_binary_op(
+        &mut self,
+        left: CValue,
+        op: BinOp,
+        right: CValue,
+    ) -> Result<CValue, VentiError> {
+        let (l, r) = (&left.code, &right.code);
+        let (code, ty) = match (&left.ty, &right.ty) {
+            (CType::Int, CType::Int) if is_comparison(op) => {
+                (format!("{} {} {}", l, comparison(op), r), CType::Bool)
+            }
+            (CType::Int, CType::Int) => {
+                let wrapping = |operator: &str| {
+                    format!("(int64_t)((uint64_t){} {} (uint64_t){})", l, operator, r)
+                };
+                let code = match op {
+                    BinOp::Add => wrapping("+"),
+                    BinOp::Subtract => wrapping("-"),
+                    BinOp::Multiply => wrapping("*"),
+                    BinOp::Divide => {
+                        self.helper("venti_int_div")?;
+                        format!("venti_int_div({}, {})", l, r)
+                    }
+                    BinOp::BitAnd => format!("{} & {}", l, r),
+                    BinOp::BitOr => format!("{} | {}", l, r),
+                    BinOp::BitXor => format!("{} ^ {}", l, r),
+                    BinOp::ShiftLeft => format!("(int64_t)((uint64_t){} << {})", l, r),
+                    // `>>` is an arithmetic shift, matching the signed integer type
+                    BinOp::ShiftRight => format!("{} >> {}", l, r),
+                    BinOp::And | BinOp::Or | BinOp::Coalesce => {
+                        unreachable!("the IR lowers `&&`, `||` and `??` to blocks")
+                    }
+                    _ => unreachable!("comparisons are matched above"),
+                };
+                (code, CType::Int)
+            }
+            (CType::Bool, CType::Bool) => {
+                let code = match op {
+                    BinOp::BitAnd => format!("(bool)({} & {})", l, r),
+                    BinOp::BitOr => format!("(bool)({} | {})", l, r),
+                    BinOp::BitXor => format!("(bool)({} ^ {})", l, r),
+                    BinOp::Equal
+                    | BinOp::NotEqual
+                    | BinOp::Less
+                    | BinOp::LessEqual
+                    | BinOp::Greater
+                    | BinOp::GreaterEqual => format!("{} {} {}", l, comparison(op), r),
+                    _ => {
+                        return Err(VentiError::CodegenError(
+                            format!("Operator {:?} is not supported on bools", op),
+                            None,
+                        ))
+                    }
+                };
+                (code, CType::Bool)
+            }
+            (CType::Str, CType::Str) => match op {
+                BinOp::Equal
+                | BinOp::NotEqual
+                | BinOp::Less
+                | BinOp::LessEqual
+                | BinOp::Greater
+                | BinOp::GreaterEqual => (
+                    format!("strcmp({}.data, {}.data) {} 0", l, r, comparison(op)),
+                    CType::Bool,
+                ),
+                _ => {
+                    return Err(VentiError::CodegenError(
+                        format!("Operator {:?} is not supported on strings", op),
+                        None,
+                    ))
+                }
+            },
+            (CType::Vector, CType::Vector) => {
+                if op != BinOp::Add {
+                    return Err(VentiError::CodegenError(
+                        format!("Operator {:?} is not supported on vectors", op),
+                        None,
+                    ));
+                }
+                self.helper("venti_vec_concat")?;
+                (format!("venti_vec_concat({}, {})", l, r), CType::Vector)
+            }
+            (CType::Float | CType::Int, CType::Float | CType::Int) => {
+                let promote = |value: &CValue| match value.ty {
+                    CType::Int => format!("(double){}", value.code),
+                    _ => value.code.clone(),
+                };
+                let (l, r) = (promote(&left), promote(&right));
+                match op {
+                    BinOp::Add => (format!("{} + {}", l, r), CType::Float),
+                    BinOp::Subtract => (format!("{} - {}", l, r), CType::Float),
+                    BinOp::Multiply => (format!("{} * {}", l, r), CType::Float),
+                    BinOp::Divide => (format!("{} / {}", l, r), CType::Float),
+                    BinOp::Equal
+                    | BinOp::NotEqual
+                    | BinOp::Less
+                    | BinOp::LessEqual
+                    | BinOp::Greater
+                    | BinOp::GreaterEqual => {
+                        (format!("{} {} {}", l, comparison(op), r), CType::Bool)
+                    }
+                    _ => {
+                        return Err(VentiError::CodegenError(
+                            format!("Operator {:?} is not supported on floats", op),
+                            None,
+                        ))
+                    }
+                }
+            }
+            _ => {
+                return Err(VentiError::CodegenError(
+                    format!("Operator {:?} needs numeric operands", op),
+                    None,
+                ))
+            }
+        };
+        Ok(CValue::new(code, ty))
+    }
+}
+
+impl Default for CCodeGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `function` becomes a C function; tests only run under `venti test`, and
+/// lambdas and spawned blocks need closures, so their bodies are never reached.
+fn is_generated(function: &Function) -> bool {
+    matches!(function.kind, FunctionKind::Function | FunctionKind::Async)
+}
+
+/// The blocks of `function` reachable from its entry, in the order they are written.
+fn reachable_blocks(function: &Function) -> Vec<BlockId> {
+    let mut reached = vec![false; function.blocks.len()];
+    let mut pending = vec![BlockId(0)];
+    while let Some(block) = pending.pop() {
+        if !std::mem::replace(&mut reached[block.0 as usize], true) {
+            pending.extend(successors(&function.block(block).terminator));
+        }
+    }
+    (0..function.blocks.len() as u32)
+        .map(BlockId)
+        .filter(|block| reached[block.0 as usize])
+        .collect()
+}
+
+/// The blocks `terminator` can jump to.
+fn successors(terminator: &Terminator) -> Vec<BlockId> {
+    match terminator {
+        Terminator::Goto(block) => vec![*block],
+        Terminator::Branch {
+            then, otherwise, ..
+        } => vec![*then, *otherwise],
+        Terminator::Switch {
+            cases, otherwise, ..
+        } => cases
+            .iter()
+            .map(|(_, block)| *block)
+            .chain([*otherwise])
+            .collect(),
+        Terminator::Try { body, handler, .. } => vec![*body, *handler],
+        Terminator::Return(_) => Vec::new(),
+    }
+}
+
+/// The C of a constant.
+fn constant_value(constant: &Constant) -> CValue {
+    match constant {
+        Constant::Int(n) => CValue::new(int_literal(*n), CType::Int),
+        Constant::Float(x) => CValue::new(float_literal(*x), CType::Float),
+        Constant::Bool(b) => CValue::new(b.to_string(), CType::Bool),
+        Constant::String(text) => CValue::new(
+            format!("((venti_str){{{}, {}}})", string_literal(text), text.len()),
+            CType::Str,
+        ),
+        Constant::Nothing => CValue::new("VENTI_NOTHING", CType::Str),
+    }
+}
+
+/// The error for a value whose type C has no values of.
+fn unsupported_type(ty: &Type) -> VentiError {
+    match ty {
+        Type::Lambda(_) => unsupported("Lambdas"),
+        Type::Iterator => unsupported("Iterators"),
+        Type::Trait(_) => unsupported("Parameters taking a trait"),
+        ty => VentiError::CodegenError(
+            format!("Values of type {} are not supported by the C backend", ty),
+            None,
+        ),
+    }
+}
+
+/// Returns the C expression of the argument `value` of the math builtin `name` as a
+/// `double`, converting an integer.
+fn as_double(value: &CValue, name: &str) -> Result<String, VentiError> {
+    match value.ty {
+        CType::Float => Ok(value.code.clone()),
+        CType::Int => Ok(format!("(double){}", value.code)),
+        _ => Err(VentiError::CodegenError(
+            format!("'{}' expects a number", name),
+            None,
+        )),
+    }
+}
+
+/// Checks that the argument `value` of the builtin `name` has the type `expected`.
+fn expect_arg(value: &CValue, expected: CType, name: &str) -> Result<(), VentiError> {
+    if value.ty == expected {
+        return Ok(());
+    }
+    let kind = match expected {
+        CType::Str => "a string",
+        CType::Channel => "a channel",
+        CType::Vector => "a vector",
+        CType::Json => "a JSON value",
+        _ => "an integer",
+    };
+    Err(VentiError::CodegenError(
+        format!("'{}' expects {}", name, kind),
+        None,
+    ))
+}
+
+/// Returns the result of a finished task, which is the integer it already holds.
+fn await_task(task: CValue) -> Result<CValue, VentiError> {
+    match task.ty {
+        CType::Task => Ok(CValue::new(task.code, CType::Int)),
+        _ => Err(VentiError::CodegenError(
+            "Only tasks returned by async functions can be awaited".to_string(),
+            None,
+        )),
+    }
+}
+
+/// The error for a construct the C backend cannot generate.
+fn unsupported(what: &str) -> VentiError {
+    VentiError::CodegenError(format!("{} are not supported by the C backend", what), None)
+}
+
+/// Appends the `printf` conversions that print the value `code` of type `ty` to `format`,
+/// and the arguments they take to `args`.
+fn format_value(
+    code: &str,
+    ty: &CType,
+    format: &mut String,
+    args: &mut Vec<String>,
+) -> Result<(), VentiError> {
+    match ty {
+        CType::Int => {
+            format.push_str("%lld");
+            args.push(format!("(long long){}", code));
+        }
+        CType::Float => {
+            format.push_str("%f");
+            args.push(code.to_string());
+        }
+        CType::Bool => {
+            format.push_str("%s");
+            args.push(format!("{} ? \"true\" : \"false\"", code));
+        }
+        CType::Str => {
+            // The length bounds the output, so printf never reads past the string
+            format.push_str("%.*s");
+            args.push(format!("{0}.data ? (int){0}.len : 7", code));
+            args.push(format!("{0}.data ? {0}.data : \"nothing\"", code));
+        }
+        CType::Array(element, len) => {
+            format.push('[');
+            for index in 0..*len {
+                if index > 0 {
+                    format.push_str(", ");
+                }
+                format_value(&format!("{}.items[{}]", code, index), element, format, args)?;
+            }
+            format.push(']');
+        }
+        CType::Json => {
+            format.push_str("%s");
+            args.push(format!("venti_json_text({})", code));
+        }
+        CType::Range => {
+            format.push_str("%lld..%lld");
+            args.push(format!("(long long){}.start", code));
+            args.push(format!("(long long){}.end", code));
+        }
+        _ => {
+            return Err(VentiError::CodegenError(
+                "This value cannot be printed".to_string(),
+                None,
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if `op` compares its operands, giving a bool.
+fn is_comparison(op: BinOp) -> bool {
+    matches!(
+        op,
+        BinOp::Equal
+            | BinOp::NotEqual
+            | BinOp::Less
+            | BinOp::LessEqual
+            | BinOp::Greater
+            | BinOp::GreaterEqual
+    )
+}
+
+/// Returns the C operator of the comparison `op`.
+fn comparison(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Equal => "==",
+        BinOp::NotEqual => "!=",
+        BinOp::Less => "<",
+        BinOp::LessEqual => "<=",
+        BinOp::Greater => ">",
+        _ => ">=",
+    }
+}
+
+/// Returns the C type of a parameter or, when `result` is true, the result of a C function,
+/// where `None` is `void`.
+///
+/// A string parameter is a `const char *` but a string result a `char *`, as libc declares
+/// them, so the declaration agrees with the headers the file includes.
+fn extern_type(ty: Option<&CType>, result: bool) -> &'static str {
+    match ty {
+        None => "void",
+        Some(CType::Float) => "double",
+        Some(CType::Bool) => "bool",
+        Some(CType::Str) if result => "char *",
+        Some(CType::Str) => "const char *",
+        Some(_) => "int",
+    }
+}
+
+/// Spells `name` with only the characters C allows in identifiers: letters and digits
+/// stay, `_` is doubled and any other byte becomes `_` and two hex digits, so distinct
+/// names stay distinct.
+fn escape(name: &str) -> String {
+    let mut escaped = String::new();
+    for byte in name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => escaped.push(byte as char),
+            b'_' => escaped.push_str("__"),
+            _ => escaped.push_str(&format!("_{:02x}", byte)),
+        }
+    }
+    escaped
+}
+
+/// Returns `name`, or `name` with the first numeric suffix that is not in `used`, and
+/// records it as used.
+fn unique_name(used: &mut HashSet<String>, name: String) -> String {
+    let mut candidate = name.clone();
+    let mut suffix = 1;
+    while used.contains(&candidate) {
+        candidate = format!("{}_{}", name, suffix);
+        suffix += 1;
+    }
+    used.insert(candidate.clone());
+    candidate
+}
+
+/// Returns the C spelling of the integer `n`.
+fn int_literal(n: i64) -> String {
+    match n {
+        // The smallest integer has no literal, since its magnitude is out of range
+        i64::MIN => "INT64_MIN".to_string(),
+        n if n < 0 => format!("(-{})", n.unsigned_abs()),
+        n => n.to_string(),
+    }
+}
+
+/// Returns the C spelling of the float `x`, which keeps every bit of it.
+fn float_literal(x: f64) -> String {
+    if x.is_nan() {
+        "NAN".to_string()
+    } else if x.is_infinite() {
+        if x < 0.0 { "(-INFINITY)" } else { "INFINITY" }.to_string()
+    } else if x.is_sign_negative() {
+        format!("(-{:?})", -x)
+    } else {
+        // Debug formatting is the shortest spelling that reads back as the same float
+        format!("{:?}", x)
+    }
+}
+
+/// Returns `text` as a C string literal, escaping quotes, backslashes and every byte that
+/// is not printable ASCII.
+fn string_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b'\n' => literal.push_str("\\n"),
+            b'\t' => literal.push_str("\\t"),
+            // `?` is escaped so no trigraph can form
+            b'?' => literal.push_str("\\?"),
+            0x20..=0x7e => literal.push(byte as char),
+            // Octal escapes take at most three digits, so the next byte cannot extend one
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
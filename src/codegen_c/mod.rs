@@ -0,0 +1,4 @@
+pub mod backend;
+#[allow(clippy::module_inception)]
+pub mod codegen;
+pub mod runtime;
@@ -0,0 +1,1777 @@
+/*
+The C runtime is the C counterpart of `codegen::runtime`: the types every generated file
+starts with, and helper functions written as C source that the C backend copies into the
+file the first time the program needs them. Each helper is emitted at most once, after the
+helpers it calls, and is `static` so the file needs nothing beyond the C standard library.
+
+Strings are `venti_str` values `{ data, len }` whose bytes are NUL-terminated, so the data
+can be handed straight to libc, and `nothing` is `{ NULL, 0 }`. Buffers are `venti_buffer`
+values `{ data, len }` over zeroed `int64_t` slots, ranges `venti_range` values
+`{ start, end, step }` with an exclusive end, and maps pointers to a `venti_map_header`:
+string-keyed hash tables using open addressing with linear probing, which double once they
+are half full. Channels are pointers to a `venti_channel_header`, a queue of `int64_t`
+slots in a ring that doubles when it fills, with floats kept by their bits and bools as 0
+or 1, and vectors pointers to a `venti_vec_header`, growable arrays of integers whose
+slots double when a push finds them full. The strings `split` and `args` give are a
+`venti_strvec_header` holding them, which never changes; like maps, none of these is ever
+freed. Runtime errors print `Runtime Error: ...` and exit with 1, as they do in the LLVM
+backend, and panics print the function they happened in and exit with 101, without the
+LLVM backend's backtrace. `main` keeps its `argc` and `argv` in two statics for `args`.
+
+A few helpers need more than standard C. The time builtins use POSIX's `clock_gettime` and
+`nanosleep`, which the prelude asks for by defining `_POSIX_C_SOURCE` before any header is
+included; `exec` needs it too, for `popen` and `pclose`, and the network helpers for
+sockets, whose headers are only included once a program uses one. A socket is the
+`int64_t` of its file descriptor, as it is in the LLVM backend.
+
+The JSON helpers are the same scanner and writer as the LLVM backend's, so both accept the
+same documents and write the same text. `json_parse` builds the document into a tree of
+`venti_json_node`s: a bool keeps its value in `len`, a number its text and a string its
+decoded bytes in `text`, and an array its elements in `items`, which for an object holds
+each member's key and value in turn. Null is the `NULL` pointer, and like maps, nodes are
+never freed.
+*/
+
+/// The includes and types every generated file starts with.
+pub const PRELUDE: &str = r#"#define _POSIX_C_SOURCE 200112L
+
+#include <ctype.h>
+#include <math.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+
+typedef struct {
+    const char *data;
+    int64_t len;
+} venti_str;
+
+typedef struct {
+    int64_t start;
+    int64_t end;
+    int64_t step;
+} venti_range;
+
+typedef struct {
+    int64_t *data;
+    int64_t len;
+} venti_buffer;
+
+typedef struct venti_map_header {
+    int64_t capacity;
+    int64_t len;
+    const char **keys;
+    int64_t *values;
+} *venti_map;
+
+typedef struct venti_channel_header {
+    int64_t len;
+    int64_t head;
+    int64_t capacity;
+    int64_t *slots;
+} *venti_channel;
+
+typedef struct venti_vec_header {
+    int64_t len;
+    int64_t capacity;
+    int64_t *slots;
+} *venti_vec;
+
+typedef struct venti_strvec_header {
+    int64_t len;
+    venti_str *slots;
+} *venti_strvec;
+
+typedef struct venti_json_node {
+    int64_t kind;
+    int64_t len;
+    const char *text;
+    struct venti_json_node **items;
+} *venti_json;
+
+enum {
+    VENTI_JSON_NULL,
+    VENTI_JSON_BOOL,
+    VENTI_JSON_NUMBER,
+    VENTI_JSON_STRING,
+    VENTI_JSON_ARRAY,
+    VENTI_JSON_OBJECT,
+};
+
+#define VENTI_NOTHING ((venti_str){NULL, 0})
+
+static int venti_argc;
+static char **venti_argv;
+"#;
+
+/// Returns the C source of the helper `name` and the names of the helpers it calls, or
+/// `None` if there is no such helper.
+pub fn helper(name: &str) -> Option<(&'static str, &'static [&'static str])> {
+    let helper = match name {
+        "venti_alloc" => (ALLOC, &[][..]),
+        "venti_int_div" => (INT_DIV, &[][..]),
+        "venti_float_to_int" => (FLOAT_TO_INT, &[][..]),
+        "venti_str_byte_at" => (STR_BYTE_AT, &[][..]),
+        "venti_c_string" => (C_STRING, &["venti_alloc"][..]),
+        "venti_str_slice" => (STR_SLICE, &["venti_alloc"][..]),
+        "venti_str_upper" => (STR_UPPER, &["venti_str_slice"][..]),
+        "venti_str_lower" => (STR_LOWER, &["venti_str_slice"][..]),
+        "venti_str_trim" => (STR_TRIM, &["venti_str_slice"][..]),
+        "venti_str_substring" => (STR_SUBSTRING, &["venti_str_slice"][..]),
+        "venti_str_range" => (STR_RANGE, &["venti_str_slice"][..]),
+        "venti_str_contains" => (STR_CONTAINS, &[][..]),
+        "venti_str_split" => (STR_SPLIT, &["venti_alloc", "venti_str_slice"][..]),
+        "venti_str_to_int" => (STR_TO_INT, &[][..]),
+        "venti_str_to_float" => (STR_TO_FLOAT, &[][..]),
+        "venti_read_line" => (READ_LINE, &["venti_alloc"][..]),
+        "venti_file_read" => (FILE_READ, &["venti_alloc"][..]),
+        "venti_file_write" => (FILE_WRITE, &[][..]),
+        "venti_random_next" => (RANDOM_NEXT, &[][..]),
+        "venti_random_float" => (RANDOM_FLOAT, &["venti_random_next"][..]),
+        "venti_random_int" => (RANDOM_INT, &["venti_random_next"][..]),
+        "venti_random_seed" => (RANDOM_SEED, &["venti_random_next"][..]),
+        "venti_now_millis" => (NOW_MILLIS, &[][..]),
+        "venti_timer" => (TIMER, &[][..]),
+        "venti_elapsed" => (ELAPSED, &["venti_timer"][..]),
+        "venti_sleep_ms" => (SLEEP_MS, &[][..]),
+        "venti_args" => (ARGS, &["venti_alloc", "venti_c_string"][..]),
+        "venti_env" => (ENV, &["venti_c_string"][..]),
+        "venti_exec_status" => (EXEC_STATUS, &[][..]),
+        "venti_exec" => (EXEC, &["venti_alloc", "venti_exec_status"][..]),
+        "venti_net_headers" => (NET_HEADERS, &[][..]),
+        "venti_tcp_open" => (TCP_OPEN, &["venti_net_headers"][..]),
+        "venti_tcp_connect" => (TCP_CONNECT, &["venti_tcp_open"][..]),
+        "venti_tcp_listen" => (TCP_LISTEN, &["venti_tcp_open"][..]),
+        "venti_tcp_accept" => (TCP_ACCEPT, &["venti_net_headers"][..]),
+        "venti_tcp_port" => (TCP_PORT, &["venti_net_headers"][..]),
+        "venti_tcp_send" => (TCP_SEND, &["venti_net_headers"][..]),
+        "venti_tcp_recv" => (TCP_RECV, &["venti_net_headers", "venti_alloc"][..]),
+        "venti_tcp_close" => (TCP_CLOSE, &["venti_net_headers"][..]),
+        "venti_tcp_read_all" => (TCP_READ_ALL, &["venti_net_headers", "venti_write_raw"][..]),
+        "venti_http" => (
+            HTTP,
+            &[
+                "venti_alloc",
+                "venti_tcp_connect",
+                "venti_tcp_send",
+                "venti_tcp_read_all",
+                "venti_write_raw",
+            ][..],
+        ),
+        "venti_json_space" => (JSON_SPACE, &[][..]),
+        "venti_json_digits" => (JSON_DIGITS, &[][..]),
+        "venti_json_skip_string" => (JSON_SKIP_STRING, &[][..]),
+        "venti_json_skip_number" => (JSON_SKIP_NUMBER, &["venti_json_digits"][..]),
+        "venti_json_skip" => (
+            JSON_SKIP,
+            &[
+                "venti_json_space",
+                "venti_json_skip_string",
+                "venti_json_skip_number",
+            ][..],
+        ),
+        "venti_json_check" => (JSON_CHECK, &["venti_json_skip"][..]),
+        "venti_json_hex" => (JSON_HEX, &[][..]),
+        "venti_json_unescape" => (JSON_UNESCAPE, &["venti_json_hex"][..]),
+        "venti_json_build" => (
+            JSON_BUILD,
+            &[
+                "venti_alloc",
+                "venti_json_space",
+                "venti_json_skip_string",
+                "venti_json_skip_number",
+                "venti_json_unescape",
+            ][..],
+        ),
+        "venti_json_parse" => (JSON_PARSE, &["venti_json_check", "venti_json_build"][..]),
+        "venti_json_kind" => (JSON_KIND, &[][..]),
+        "venti_json_member" => (JSON_MEMBER, &[][..]),
+        "venti_json_element" => (JSON_ELEMENT, &[][..]),
+        "venti_json_len" => (JSON_LEN, &[][..]),
+        "venti_json_expect" => (JSON_EXPECT, &["venti_json_kind"][..]),
+        "venti_json_to_int" => (
+            JSON_TO_INT,
+            &["venti_json_expect", "venti_float_to_int"][..],
+        ),
+        "venti_json_to_float" => (JSON_TO_FLOAT, &["venti_json_expect"][..]),
+        "venti_json_to_bool" => (JSON_TO_BOOL, &["venti_json_expect"][..]),
+        "venti_json_to_str" => (JSON_TO_STR, &["venti_json_expect"][..]),
+        "venti_write_raw" => (WRITE_RAW, &[][..]),
+        "venti_json_quote" => (JSON_QUOTE, &["venti_write_raw"][..]),
+        "venti_json_int" => (JSON_INT, &["venti_write_raw"][..]),
+        "venti_json_float" => (JSON_FLOAT, &["venti_alloc", "venti_write_raw"][..]),
+        "venti_json_map" => (JSON_MAP, &["venti_json_quote", "venti_json_int"][..]),
+        "venti_json_write" => (JSON_WRITE, &["venti_json_quote"][..]),
+        "venti_json_text" => (JSON_TEXT, &["venti_json_write", "venti_write_finish"][..]),
+        "venti_write_finish" => (WRITE_FINISH, &["venti_write_raw"][..]),
+        "venti_buffer_alloc" => (BUFFER_ALLOC, &[][..]),
+        "venti_buffer_slot" => (BUFFER_SLOT, &[][..]),
+        "venti_strvec_slot" => (STRVEC_SLOT, &[][..]),
+        "venti_hash_str" => (HASH_STR, &[][..]),
+        "venti_map_new" => (MAP_NEW, &["venti_alloc"][..]),
+        "venti_map_find_slot" => (MAP_FIND_SLOT, &["venti_hash_str"][..]),
+        "venti_map_grow" => (MAP_GROW, &["venti_alloc", "venti_map_find_slot"][..]),
+        "venti_map_insert" => (MAP_INSERT, &["venti_map_find_slot", "venti_map_grow"][..]),
+        "venti_map_get" => (MAP_GET, &["venti_map_find_slot"][..]),
+        "venti_map_has" => (MAP_HAS, &["venti_map_find_slot"][..]),
+        "venti_channel_new" => (CHANNEL_NEW, &["venti_alloc"][..]),
+        "venti_channel_send" => (CHANNEL_SEND, &["venti_alloc"][..]),
+        "venti_channel_recv" => (CHANNEL_RECV, &[][..]),
+        "venti_float_bits" => (FLOAT_BITS, &[][..]),
+        "venti_bits_float" => (BITS_FLOAT, &[][..]),
+        "venti_vec_new" => (VEC_NEW, &["venti_alloc"][..]),
+        "venti_vec_push" => (VEC_PUSH, &[][..]),
+        "venti_vec_pop" => (VEC_POP, &[][..]),
+        "venti_vec_slot" => (VEC_SLOT, &[][..]),
+        "venti_vec_copy" => (VEC_COPY, &["venti_alloc"][..]),
+        "venti_vec_extend" => (VEC_EXTEND, &["venti_vec_push"][..]),
+        "venti_vec_range" => (VEC_RANGE, &["venti_vec_new", "venti_vec_extend"][..]),
+        "venti_vec_concat" => (VEC_CONCAT, &["venti_vec_new", "venti_vec_extend"][..]),
+        "venti_sort" => (SORT, &["venti_alloc"][..]),
+        "venti_binary_search" => (BINARY_SEARCH, &[][..]),
+        "venti_panic" => (PANIC, &[][..]),
+        _ => return None,
+    };
+    Some(helper)
+}
+
+/// `void *venti_alloc(size_t size)`: zeroed memory; running out is a runtime error.
+const ALLOC: &str = r#"static void *venti_alloc(size_t size) {
+    void *memory = calloc(size > 0 ? size : 1, 1);
+    if (memory == NULL) {
+        printf("Runtime Error: out of memory\n");
+        exit(1);
+    }
+    return memory;
+}
+"#;
+
+/// `int64_t venti_int_div(int64_t left, int64_t right)`: signed division, where dividing by
+/// zero is a runtime error and dividing the smallest integer by -1 wraps.
+const INT_DIV: &str = r#"static int64_t venti_int_div(int64_t left, int64_t right) {
+    if (right == 0) {
+        printf("Runtime Error: division by zero\n");
+        exit(1);
+    }
+    if (right == -1) {
+        return (int64_t)(0 - (uint64_t)left);
+    }
+    return left / right;
+}
+"#;
+
+/// `int64_t venti_float_to_int(double value)`: truncates toward zero, saturating at the
+/// ends of the `int` range, with NaN becoming 0, like `llvm.fptosi.sat`.
+const FLOAT_TO_INT: &str = r#"static int64_t venti_float_to_int(double value) {
+    if (value != value) {
+        return 0;
+    }
+    if (value >= 9223372036854775807.0) {
+        return INT64_MAX;
+    }
+    if (value <= -9223372036854775808.0) {
+        return INT64_MIN;
+    }
+    return (int64_t)value;
+}
+"#;
+
+/// `int64_t venti_str_byte_at(venti_str text, int64_t index)`: the byte at `index`, or -1
+/// when `index` is out of range.
+const STR_BYTE_AT: &str = r#"static int64_t venti_str_byte_at(venti_str text, int64_t index) {
+    if (index < 0 || index >= text.len) {
+        return -1;
+    }
+    return (unsigned char)text.data[index];
+}
+"#;
+
+/// `venti_str venti_c_string(const char *text)`: a copy of a string a C function returned,
+/// or `nothing` for a null pointer.
+const C_STRING: &str = r#"static venti_str venti_c_string(const char *text) {
+    venti_str copy = VENTI_NOTHING;
+    if (text != NULL) {
+        size_t len = strlen(text);
+        char *data = venti_alloc(len + 1);
+        memcpy(data, text, len);
+        copy.data = data;
+        copy.len = (int64_t)len;
+    }
+    return copy;
+}
+"#;
+
+/// `venti_str venti_str_slice(venti_str text, int64_t start, int64_t end)`: a copy of the
+/// bytes from `start` up to `end`, which must be within the string.
+const STR_SLICE: &str = r#"static venti_str venti_str_slice(venti_str text, int64_t start, int64_t end) {
+    char *data = venti_alloc((size_t)(end - start) + 1);
+    if (end > start) {
+        memcpy(data, text.data + start, (size_t)(end - start));
+    }
+    venti_str slice = {data, end - start};
+    return slice;
+}
+"#;
+
+/// `venti_str venti_str_upper(venti_str text)`: a copy with ASCII letters in upper case.
+const STR_UPPER: &str = r#"static venti_str venti_str_upper(venti_str text) {
+    venti_str copy = venti_str_slice(text, 0, text.len);
+    char *data = (char *)copy.data;
+    for (int64_t i = 0; i < copy.len; i++) {
+        data[i] = (char)toupper((unsigned char)data[i]);
+    }
+    return copy;
+}
+"#;
+
+/// `venti_str venti_str_lower(venti_str text)`: a copy with ASCII letters in lower case.
+const STR_LOWER: &str = r#"static venti_str venti_str_lower(venti_str text) {
+    venti_str copy = venti_str_slice(text, 0, text.len);
+    char *data = (char *)copy.data;
+    for (int64_t i = 0; i < copy.len; i++) {
+        data[i] = (char)tolower((unsigned char)data[i]);
+    }
+    return copy;
+}
+"#;
+
+/// `venti_str venti_str_trim(venti_str text)`: a copy without the whitespace at either end.
+const STR_TRIM: &str = r#"static venti_str venti_str_trim(venti_str text) {
+    int64_t start = 0;
+    int64_t end = text.len;
+    while (start < end && isspace((unsigned char)text.data[start])) {
+        start++;
+    }
+    while (end > start && isspace((unsigned char)text.data[end - 1])) {
+        end--;
+    }
+    return venti_str_slice(text, start, end);
+}
+"#;
+
+/// `venti_str venti_str_substring(venti_str text, int64_t start, int64_t end)`: a copy of
+/// the bytes from `start` up to `end`, after limiting both to the string and `start` to
+/// `end`.
+const STR_SUBSTRING: &str = r#"static venti_str venti_str_substring(venti_str text, int64_t start, int64_t end) {
+    end = end < 0 ? 0 : end > text.len ? text.len : end;
+    start = start < 0 ? 0 : start > end ? end : start;
+    return venti_str_slice(text, start, end);
+}
+"#;
+
+/// `venti_str venti_str_range(venti_str text, venti_range range)`: a copy of the bytes the
+/// range covers, as `s[start..end]` gives. Offsets outside the string, a start past the end
+/// and an offset inside a character are runtime errors.
+const STR_RANGE: &str = r#"static venti_str venti_str_range(venti_str text, venti_range range) {
+    if (range.start < 0 || range.start > range.end || range.end > text.len) {
+        printf("Runtime Error: string slice %lld..%lld is out of range for a string of %lld bytes\n",
+               (long long)range.start, (long long)range.end, (long long)text.len);
+        exit(1);
+    }
+    /* A byte of the form 10xxxxxx continues a UTF-8 sequence; the end of the string never does */
+    if ((range.start < text.len && ((unsigned char)text.data[range.start] & 0xc0) == 0x80) ||
+        (range.end < text.len && ((unsigned char)text.data[range.end] & 0xc0) == 0x80)) {
+        printf("Runtime Error: string slice %lld..%lld does not start and end on character boundaries\n",
+               (long long)range.start, (long long)range.end);
+        exit(1);
+    }
+    return venti_str_slice(text, range.start, range.end);
+}
+"#;
+
+/// `int64_t venti_str_contains(venti_str text, venti_str needle)`: 1 if `needle` occurs in
+/// the string, else 0.
+const STR_CONTAINS: &str = r#"static int64_t venti_str_contains(venti_str text, venti_str needle) {
+    if (needle.len == 0) {
+        return 1;
+    }
+    return text.len > 0 && strstr(text.data, needle.data) != NULL;
+}
+"#;
+
+/// `venti_strvec venti_str_split(venti_str text, venti_str separator)`: the pieces of the
+/// string cut at every `separator`, counted first so the slots are allocated once; an
+/// empty separator is a runtime error.
+const STR_SPLIT: &str = r#"static venti_strvec venti_str_split(venti_str text, venti_str separator) {
+    if (separator.len == 0) {
+        printf("Runtime Error: cannot split a string at an empty separator\n");
+        exit(1);
+    }
+    venti_strvec pieces = venti_alloc(sizeof *pieces);
+    const char *match;
+    pieces->len = 1;
+    for (int64_t start = 0;
+         start < text.len && (match = strstr(text.data + start, separator.data)) != NULL;
+         start = match - text.data + separator.len) {
+        pieces->len++;
+    }
+    pieces->slots = venti_alloc((size_t)pieces->len * sizeof *pieces->slots);
+    int64_t start = 0;
+    for (int64_t piece = 0; piece < pieces->len; piece++) {
+        match = start < text.len ? strstr(text.data + start, separator.data) : NULL;
+        int64_t end = match != NULL ? match - text.data : text.len;
+        pieces->slots[piece] = venti_str_slice(text, start, end);
+        start = end + separator.len;
+    }
+    return pieces;
+}
+"#;
+
+/// `int64_t venti_str_to_int(venti_str text)`: the string read as a decimal integer; a
+/// string that is not one is a runtime error.
+const STR_TO_INT: &str = r#"static int64_t venti_str_to_int(venti_str text) {
+    char *end = NULL;
+    long long value = 0;
+    if (text.len > 0 && !isspace((unsigned char)text.data[0])) {
+        value = strtoll(text.data, &end, 10);
+    }
+    if (end != text.data + text.len || text.len == 0) {
+        printf("Runtime Error: could not parse '%s' as an int\n", text.len > 0 ? text.data : "");
+        exit(1);
+    }
+    return (int64_t)value;
+}
+"#;
+
+/// `double venti_str_to_float(venti_str text)`: the string read as a floating-point
+/// number; a string that is not one is a runtime error.
+const STR_TO_FLOAT: &str = r#"static double venti_str_to_float(venti_str text) {
+    char *end = NULL;
+    double value = 0.0;
+    if (text.len > 0 && !isspace((unsigned char)text.data[0])) {
+        value = strtod(text.data, &end);
+    }
+    if (end != text.data + text.len || text.len == 0) {
+        printf("Runtime Error: could not parse '%s' as a float\n", text.len > 0 ? text.data : "");
+        exit(1);
+    }
+    return value;
+}
+"#;
+
+/// `venti_str venti_read_line(venti_str prompt)`: prints `prompt`, then reads a line from
+/// standard input and returns it without its line ending, or `nothing` at the end of the
+/// input.
+const READ_LINE: &str = r#"static venti_str venti_read_line(venti_str prompt) {
+    printf("%s", prompt.len > 0 ? prompt.data : "");
+    fflush(stdout);
+    size_t capacity = 64;
+    size_t len = 0;
+    char *data = venti_alloc(capacity);
+    int byte;
+    while ((byte = getchar()) != EOF && byte != '\n') {
+        if (len + 1 == capacity) {
+            capacity *= 2;
+            data = realloc(data, capacity);
+            if (data == NULL) {
+                printf("Runtime Error: out of memory\n");
+                exit(1);
+            }
+        }
+        data[len++] = (char)byte;
+    }
+    if (byte == EOF && len == 0) {
+        free(data);
+        return VENTI_NOTHING;
+    }
+    if (len > 0 && data[len - 1] == '\r') {
+        len--;
+    }
+    data[len] = '\0';
+    venti_str line = {data, (int64_t)len};
+    return line;
+}
+"#;
+
+/// `venti_str venti_file_read(venti_str path)`: the whole contents of the file at `path`;
+/// a file that cannot be opened or read is a runtime error.
+const FILE_READ: &str = r#"static venti_str venti_file_read(venti_str path) {
+    const char *name = path.len > 0 ? path.data : "";
+    FILE *file = fopen(name, "rb");
+    long size = -1;
+    if (file != NULL && fseek(file, 0, SEEK_END) == 0) {
+        size = ftell(file);
+        fseek(file, 0, SEEK_SET);
+    }
+    if (size < 0) {
+        if (file != NULL) {
+            fclose(file);
+        }
+        printf("Runtime Error: could not read file '%s'\n", name);
+        exit(1);
+    }
+    char *data = venti_alloc((size_t)size + 1);
+    size_t len = fread(data, 1, (size_t)size, file);
+    fclose(file);
+    venti_str contents = {data, (int64_t)len};
+    return contents;
+}
+"#;
+
+/// `int64_t venti_file_write(venti_str path, venti_str contents, const char *mode)`:
+/// writes `contents` to the file at `path`, opened with the `fopen` mode `mode`, and
+/// returns how many bytes were written; a file that cannot be written is a runtime error.
+const FILE_WRITE: &str = r#"static int64_t venti_file_write(venti_str path, venti_str contents, const char *mode) {
+    const char *name = path.len > 0 ? path.data : "";
+    FILE *file = fopen(name, mode);
+    int written = file != NULL;
+    if (file != NULL) {
+        written = contents.len == 0
+            || fwrite(contents.data, 1, (size_t)contents.len, file) == (size_t)contents.len;
+        written = fclose(file) == 0 && written;
+    }
+    if (!written) {
+        printf("Runtime Error: could not write to file '%s'\n", name);
+        exit(1);
+    }
+    return contents.len;
+}
+"#;
+
+/// `uint64_t venti_random_next(void)`: the next 64 random bits from the splitmix64
+/// generator the interpreter and the LLVM backend use, seeding it from the clock if nothing
+/// has seeded it yet. The generator's state is defined along with it.
+const RANDOM_NEXT: &str = r#"static uint64_t venti_random_state;
+
+static uint64_t venti_random_next(void) {
+    if (venti_random_state == 0) {
+        venti_random_state = (uint64_t)time(NULL) * 0x9e3779b97f4a7c15ULL | 1;
+    }
+    venti_random_state += 0x9e3779b97f4a7c15ULL;
+    uint64_t z = venti_random_state;
+    z = (z ^ (z >> 30)) * 0xbf58476d1ce4e5b9ULL;
+    z = (z ^ (z >> 27)) * 0x94d049bb133111ebULL;
+    return z ^ (z >> 31);
+}
+"#;
+
+/// `double venti_random_float(void)`: a random float from 0 up to but not including 1,
+/// made from the top 53 bits of a draw.
+const RANDOM_FLOAT: &str = r#"static double venti_random_float(void) {
+    return (double)(venti_random_next() >> 11) / 9007199254740992.0;
+}
+"#;
+
+/// `int64_t venti_random_int(int64_t low, int64_t high)`: a random integer from `low` to
+/// `high`, both included; `high` below `low` is a runtime error.
+const RANDOM_INT: &str = r#"static int64_t venti_random_int(int64_t low, int64_t high) {
+    if (high < low) {
+        printf("Runtime Error: random_int needs a range, but %lld is above %lld\n",
+               (long long)low, (long long)high);
+        exit(1);
+    }
+    uint64_t count = (uint64_t)high - (uint64_t)low + 1;
+    uint64_t bits = venti_random_next();
+    uint64_t offset = count == 0 ? bits : bits % count;
+    return (int64_t)((uint64_t)low + offset);
+}
+"#;
+
+/// `int64_t venti_random_seed(int64_t seed)`: seeds the generator, so the numbers drawn
+/// after it are the same on every run, and returns 0.
+const RANDOM_SEED: &str = r#"static int64_t venti_random_seed(int64_t seed) {
+    venti_random_state = (uint64_t)seed ^ 0x9e3779b97f4a7c15ULL;
+    return 0;
+}
+"#;
+
+/// `int64_t venti_now_millis(void)`: the milliseconds since the Unix epoch.
+const NOW_MILLIS: &str = r#"static int64_t venti_now_millis(void) {
+    struct timespec now;
+    clock_gettime(CLOCK_REALTIME, &now);
+    return (int64_t)now.tv_sec * 1000 + now.tv_nsec / 1000000;
+}
+"#;
+
+/// `int64_t venti_timer(void)`: a reading of the monotonic clock in nanoseconds, which only
+/// means something to `venti_elapsed`.
+const TIMER: &str = r#"static int64_t venti_timer(void) {
+    struct timespec now;
+    clock_gettime(CLOCK_MONOTONIC, &now);
+    return (int64_t)now.tv_sec * 1000000000 + now.tv_nsec;
+}
+"#;
+
+/// `double venti_elapsed(int64_t start)`: the milliseconds since `venti_timer` returned
+/// `start`.
+const ELAPSED: &str = r#"static double venti_elapsed(int64_t start) {
+    return (double)(venti_timer() - start) / 1000000.0;
+}
+"#;
+
+/// `int64_t venti_sleep_ms(int64_t millis)`: pauses for `millis` milliseconds, or not at
+/// all when `millis` is not positive, and returns 0.
+const SLEEP_MS: &str = r#"static int64_t venti_sleep_ms(int64_t millis) {
+    if (millis > 0) {
+        struct timespec duration;
+        duration.tv_sec = (time_t)(millis / 1000);
+        duration.tv_nsec = (long)(millis % 1000) * 1000000;
+        nanosleep(&duration, NULL);
+    }
+    return 0;
+}
+"#;
+
+/// `venti_strvec venti_args(void)`: the arguments of the program after its own name, as
+/// a string vector.
+const ARGS: &str = r#"static venti_strvec venti_args(void) {
+    venti_strvec args = venti_alloc(sizeof *args);
+    args->len = venti_argc > 0 ? venti_argc - 1 : 0;
+    args->slots = venti_alloc((size_t)args->len * sizeof *args->slots);
+    for (int64_t index = 0; index < args->len; index++) {
+        args->slots[index] = venti_c_string(venti_argv[index + 1]);
+    }
+    return args;
+}
+"#;
+
+/// `venti_str venti_env(venti_str name)`: the value of the environment variable `name`, or
+/// `nothing` if it is not set.
+const ENV: &str = r#"static venti_str venti_env(venti_str name) {
+    return venti_c_string(getenv(name.len > 0 ? name.data : ""));
+}
+"#;
+
+/// `int64_t venti_exec_status(void)`: the exit status of the last command `venti_exec`
+/// ran, or 0 before the first.
+const EXEC_STATUS: &str = r#"static int64_t venti_exec_status_code;
+
+static int64_t venti_exec_status(void) {
+    return venti_exec_status_code;
+}
+"#;
+
+/// `venti_str venti_exec(venti_str command)`: runs `command` with the shell and returns
+/// what it wrote to stdout, keeping its exit status for `venti_exec_status`; a command the
+/// shell cannot be started for is a runtime error.
+const EXEC: &str = r#"static venti_str venti_exec(venti_str command) {
+    const char *text = command.len > 0 ? command.data : "";
+    fflush(NULL);
+    FILE *pipe = popen(text, "r");
+    if (pipe == NULL) {
+        printf("Runtime Error: could not run command '%s'\n", text);
+        exit(1);
+    }
+    size_t capacity = 4096;
+    size_t len = 0;
+    char *data = venti_alloc(capacity + 1);
+    size_t read;
+    while ((read = fread(data + len, 1, capacity - len, pipe)) > 0) {
+        len += read;
+        if (len == capacity) {
+            capacity *= 2;
+            data = realloc(data, capacity + 1);
+            if (data == NULL) {
+                printf("Runtime Error: out of memory\n");
+                exit(1);
+            }
+        }
+    }
+    int status = pclose(pipe);
+    int signal = status & 0x7f;
+    venti_exec_status_code = signal == 0 ? (status >> 8) & 0xff : 128 + signal;
+    data[len] = '\0';
+    venti_str output = {data, (int64_t)len};
+    return output;
+}
+"#;
+
+/// The headers of the socket calls. Only the network helpers need them, so other programs
+/// still build with nothing beyond standard C.
+const NET_HEADERS: &str = r#"#include <netdb.h>
+#include <signal.h>
+#include <sys/socket.h>
+#include <unistd.h>
+"#;
+
+/// `int64_t venti_tcp_open(const char *host, int64_t port, bool passive)`: tries each
+/// address `host`, a null pointer for any of this machine's, resolves to, connecting to
+/// `port` there or, if `passive`, listening on it, and returns the first socket that works,
+/// or -1 if none does.
+const TCP_OPEN: &str = r#"static int64_t venti_tcp_open(const char *host, int64_t port, bool passive) {
+    char service[24];
+    struct addrinfo hints;
+    struct addrinfo *addresses;
+    int one = 1;
+    /* A peer closing the connection makes sending fail instead of ending the program */
+    signal(SIGPIPE, SIG_IGN);
+    snprintf(service, sizeof service, "%lld", (long long)port);
+    memset(&hints, 0, sizeof hints);
+    hints.ai_flags = passive ? AI_PASSIVE : 0;
+    hints.ai_socktype = SOCK_STREAM;
+    if (getaddrinfo(host, service, &hints, &addresses) != 0) {
+        return -1;
+    }
+    for (struct addrinfo *address = addresses; address != NULL; address = address->ai_next) {
+        int fd = socket(address->ai_family, address->ai_socktype, address->ai_protocol);
+        if (fd < 0) {
+            continue;
+        }
+        int opened;
+        if (passive) {
+            /* The port can be listened on again straight after a program that did exits */
+            setsockopt(fd, SOL_SOCKET, SO_REUSEADDR, &one, sizeof one);
+            opened = bind(fd, address->ai_addr, address->ai_addrlen) == 0 &&
+                     listen(fd, 128) == 0;
+        } else {
+            opened = connect(fd, address->ai_addr, address->ai_addrlen) == 0;
+        }
+        if (opened) {
+            freeaddrinfo(addresses);
+            return fd;
+        }
+        close(fd);
+    }
+    freeaddrinfo(addresses);
+    return -1;
+}
+"#;
+
+/// `int64_t venti_tcp_connect(const char *host, int64_t port)`: opens a TCP connection to
+/// `port` on `host` and returns its socket; a host that cannot be resolved or reached is a
+/// runtime error.
+const TCP_CONNECT: &str = r#"static int64_t venti_tcp_connect(const char *host, int64_t port) {
+    int64_t fd = venti_tcp_open(host, port, false);
+    if (fd < 0) {
+        printf("Runtime Error: could not connect to %s:%lld\n", host, (long long)port);
+        exit(1);
+    }
+    return fd;
+}
+"#;
+
+/// `int64_t venti_tcp_listen(int64_t port)`: opens a socket listening for TCP connections
+/// to `port` and returns it; a port that cannot be listened on is a runtime error.
+const TCP_LISTEN: &str = r#"static int64_t venti_tcp_listen(int64_t port) {
+    int64_t fd = venti_tcp_open(NULL, port, true);
+    if (fd < 0) {
+        printf("Runtime Error: could not listen on port %lld\n", (long long)port);
+        exit(1);
+    }
+    return fd;
+}
+"#;
+
+/// `int64_t venti_tcp_accept(int64_t listener)`: waits for a connection to the listening
+/// socket `listener` and returns its socket; a socket that is not listening is a runtime
+/// error.
+const TCP_ACCEPT: &str = r#"static int64_t venti_tcp_accept(int64_t listener) {
+    int fd = accept((int)listener, NULL, NULL);
+    if (fd < 0) {
+        printf("Runtime Error: could not accept a connection\n");
+        exit(1);
+    }
+    return fd;
+}
+"#;
+
+/// `int64_t venti_tcp_port(int64_t listener)`: the port the socket `listener` listens on,
+/// which is the one the system picked when it was asked to listen on port 0; a socket that
+/// is not open is a runtime error.
+const TCP_PORT: &str = r#"static int64_t venti_tcp_port(int64_t listener) {
+    struct sockaddr_storage address;
+    socklen_t address_len = sizeof address;
+    if (getsockname((int)listener, (struct sockaddr *)&address, &address_len) != 0) {
+        printf("Runtime Error: could not find the port of socket %lld\n", (long long)listener);
+        exit(1);
+    }
+    /* The port follows the family in every address, big-endian */
+    const unsigned char *port = (const unsigned char *)&address + 2;
+    return ((int64_t)port[0] << 8) | port[1];
+}
+"#;
+
+/// `void venti_tcp_send(int64_t fd, const char *data, int64_t len)`: sends `len` bytes over
+/// a connection; a connection that was closed or failed is a runtime error.
+const TCP_SEND: &str = r#"static void venti_tcp_send(int64_t fd, const char *data, int64_t len) {
+    int64_t sent = 0;
+    while (sent < len) {
+        ssize_t count = send((int)fd, data + sent, (size_t)(len - sent), 0);
+        if (count <= 0) {
+            printf("Runtime Error: could not send: the connection is closed\n");
+            exit(1);
+        }
+        sent += count;
+    }
+}
+"#;
+
+/// `void venti_tcp_read_all(int64_t fd, venti_writer *writer)`: writes everything received
+/// over a connection to `writer`, until the peer closes it; a connection that fails is a
+/// runtime error.
+const TCP_READ_ALL: &str = r#"static void venti_tcp_read_all(int64_t fd, venti_writer *writer) {
+    char chunk[4096];
+    ssize_t count;
+    while ((count = recv((int)fd, chunk, sizeof chunk, 0)) > 0) {
+        venti_write_raw(writer, chunk, (size_t)count);
+    }
+    if (count < 0) {
+        printf("Runtime Error: could not receive: the connection failed\n");
+        exit(1);
+    }
+}
+"#;
+
+/// `venti_str venti_tcp_recv(int64_t fd)`: the next bytes received over a connection, at
+/// most 4096 of them, or `nothing` once the peer has closed it; a connection that fails is
+/// a runtime error.
+const TCP_RECV: &str = r#"static venti_str venti_tcp_recv(int64_t fd) {
+    char chunk[4096];
+    ssize_t count = recv((int)fd, chunk, sizeof chunk, 0);
+    if (count < 0) {
+        printf("Runtime Error: could not receive: the connection failed\n");
+        exit(1);
+    }
+    if (count == 0) {
+        return VENTI_NOTHING;
+    }
+    char *data = venti_alloc((size_t)count + 1);
+    memcpy(data, chunk, (size_t)count);
+    venti_str received = {data, (int64_t)count};
+    return received;
+}
+"#;
+
+/// `int64_t venti_tcp_close(int64_t fd)`: closes a connection or listening socket.
+const TCP_CLOSE: &str = r#"static int64_t venti_tcp_close(int64_t fd) {
+    close((int)fd);
+    return 0;
+}
+"#;
+
+/// `venti_str venti_http(const char *method, venti_str url, venti_str body)`: sends an
+/// HTTP/1.0 request with `method` and `body` to `url`, which must be an `http://` URL, and
+/// returns the body of the response, whatever its status; a URL that cannot be requested or
+/// a response that is not HTTP is a runtime error.
+const HTTP: &str = r#"static venti_str venti_http(const char *method, venti_str url, venti_str body) {
+    const char *text = url.len > 0 ? url.data : "";
+    if (strncmp(text, "http://", 7) != 0) {
+        printf("Runtime Error: only http:// URLs can be requested, not '%s'\n", text);
+        exit(1);
+    }
+    const char *authority = text + 7;
+    size_t authority_len = strcspn(authority, "/");
+    size_t host_len = strcspn(authority, ":/");
+    char *host = venti_alloc(host_len + 1);
+    memcpy(host, authority, host_len);
+    int64_t port = authority[host_len] == ':' ? strtoll(authority + host_len + 1, NULL, 10) : 80;
+    const char *path = authority[authority_len] != '\0' ? authority + authority_len : "/";
+    int64_t fd = venti_tcp_connect(host, port);
+    free(host);
+    const char *format = "%s %s HTTP/1.0\r\nHost: %.*s\r\nUser-Agent: venti\r\n"
+                         "Content-Length: %lld\r\nConnection: close\r\n\r\n";
+    int header_len = snprintf(NULL, 0, format, method, path, (int)authority_len, authority,
+                              (long long)body.len);
+    char *header = venti_alloc((size_t)header_len + 1);
+    snprintf(header, (size_t)header_len + 1, format, method, path, (int)authority_len,
+             authority, (long long)body.len);
+    venti_tcp_send(fd, header, header_len);
+    free(header);
+    venti_tcp_send(fd, body.data, body.len);
+    venti_writer response = {NULL, 0, 0};
+    venti_tcp_read_all(fd, &response);
+    close((int)fd);
+    venti_write_raw(&response, "", 0);
+    response.data[response.len] = '\0';
+    const char *content = strstr(response.data, "\r\n\r\n");
+    if (content == NULL) {
+        printf("Runtime Error: invalid HTTP response from '%s'\n", text);
+        exit(1);
+    }
+    content += 4;
+    size_t len = response.len - (size_t)(content - response.data);
+    memmove(response.data, content, len + 1);
+    venti_str result = {response.data, (int64_t)len};
+    return result;
+}
+"#;
+
+/// `int64_t venti_json_space(const char *text, int64_t pos)`: the position after the JSON
+/// whitespace starting at `pos`.
+const JSON_SPACE: &str = r#"static int64_t venti_json_space(const char *text, int64_t pos) {
+    while (text[pos] == ' ' || text[pos] == '\t' || text[pos] == '\n' || text[pos] == '\r') {
+        pos++;
+    }
+    return pos;
+}
+"#;
+
+/// `int64_t venti_json_digits(const char *text, int64_t pos)`: the position after the
+/// decimal digits starting at `pos`.
+const JSON_DIGITS: &str = r#"static int64_t venti_json_digits(const char *text, int64_t pos) {
+    while (text[pos] >= '0' && text[pos] <= '9') {
+        pos++;
+    }
+    return pos;
+}
+"#;
+
+/// `int64_t venti_json_skip_string(const char *text, int64_t pos)`: the end of the string
+/// literal starting with the quote at `pos`, or -1 if it is not a valid one.
+const JSON_SKIP_STRING: &str = r#"static int64_t venti_json_skip_string(const char *text, int64_t pos) {
+    pos++;
+    for (;;) {
+        unsigned char c = (unsigned char)text[pos];
+        if (c == '"') {
+            return pos + 1;
+        }
+        if (c < 0x20) {
+            return -1;
+        }
+        if (c != '\\') {
+            pos++;
+        } else if (text[pos + 1] == 'u') {
+            for (int i = 2; i < 6; i++) {
+                if (!isxdigit((unsigned char)text[pos + i])) {
+                    return -1;
+                }
+            }
+            pos += 6;
+        } else if (text[pos + 1] != '\0' && strchr("\"\\/bfnrt", text[pos + 1]) != NULL) {
+            pos += 2;
+        } else {
+            return -1;
+        }
+    }
+}
+"#;
+
+/// `int64_t venti_json_skip_number(const char *text, int64_t pos)`: the end of the number
+/// starting at `pos`, or -1 if it is not a valid one.
+const JSON_SKIP_NUMBER: &str = r#"static int64_t venti_json_skip_number(const char *text, int64_t pos) {
+    if (text[pos] == '-') {
+        pos++;
+    }
+    int64_t end = venti_json_digits(text, pos);
+    if (end == pos || (text[pos] == '0' && end > pos + 1)) {
+        return -1;
+    }
+    pos = end;
+    if (text[pos] == '.') {
+        end = venti_json_digits(text, pos + 1);
+        if (end == pos + 1) {
+            return -1;
+        }
+        pos = end;
+    }
+    if (text[pos] == 'e' || text[pos] == 'E') {
+        pos++;
+        if (text[pos] == '+' || text[pos] == '-') {
+            pos++;
+        }
+        end = venti_json_digits(text, pos);
+        if (end == pos) {
+            return -1;
+        }
+        pos = end;
+    }
+    return pos;
+}
+"#;
+
+/// `int64_t venti_json_skip(const char *text, int64_t pos, int64_t depth)`: the end of the
+/// value starting at `pos`, inside `depth` arrays and objects, or -1 if there is no valid
+/// value there.
+const JSON_SKIP: &str = r#"static int64_t venti_json_skip(const char *text, int64_t pos, int64_t depth) {
+    char open = text[pos];
+    if (open == '[' || open == '{') {
+        char close = open == '[' ? ']' : '}';
+        if (depth == 128) {
+            return -1;
+        }
+        pos = venti_json_space(text, pos + 1);
+        if (text[pos] == close) {
+            return pos + 1;
+        }
+        for (;;) {
+            if (open == '{') {
+                if (text[pos] != '"') {
+                    return -1;
+                }
+                pos = venti_json_skip_string(text, pos);
+                if (pos < 0) {
+                    return -1;
+                }
+                pos = venti_json_space(text, pos);
+                if (text[pos] != ':') {
+                    return -1;
+                }
+                pos = venti_json_space(text, pos + 1);
+            }
+            pos = venti_json_skip(text, pos, depth + 1);
+            if (pos < 0) {
+                return -1;
+            }
+            pos = venti_json_space(text, pos);
+            if (text[pos] == close) {
+                return pos + 1;
+            }
+            if (text[pos] != ',') {
+                return -1;
+            }
+            pos = venti_json_space(text, pos + 1);
+        }
+    }
+    if (open == '"') {
+        return venti_json_skip_string(text, pos);
+    }
+    if (open == '-' || (open >= '0' && open <= '9')) {
+        return venti_json_skip_number(text, pos);
+    }
+    if (strncmp(text + pos, "true", 4) == 0 || strncmp(text + pos, "null", 4) == 0) {
+        return pos + 4;
+    }
+    if (strncmp(text + pos, "false", 5) == 0) {
+        return pos + 5;
+    }
+    return -1;
+}
+"#;
+
+/// `int64_t venti_json_check(venti_str json)`: the position the value of the document
+/// `json` starts at; a document that is not valid JSON is a runtime error.
+const JSON_CHECK: &str = r#"static int64_t venti_json_check(venti_str json) {
+    const char *text = json.len > 0 ? json.data : "";
+    int64_t start = venti_json_space(text, 0);
+    int64_t end = venti_json_skip(text, start, 0);
+    if (end < 0 || venti_json_space(text, end) != json.len) {
+        printf("Runtime Error: invalid JSON\n");
+        exit(1);
+    }
+    return start;
+}
+"#;
+
+/// `unsigned venti_json_hex(const char *text, int64_t pos)`: the four hex digits at `pos`.
+const JSON_HEX: &str = r#"static unsigned venti_json_hex(const char *text, int64_t pos) {
+    unsigned code = 0;
+    for (int i = 0; i < 4; i++) {
+        int c = tolower((unsigned char)text[pos + i]);
+        code = code * 16 + (unsigned)(c <= '9' ? c - '0' : c - 'a' + 10);
+    }
+    return code;
+}
+"#;
+
+/// `int64_t venti_json_unescape(const char *text, int64_t pos, char *out)`: decodes the
+/// valid string literal starting with the quote at `pos` into `out`, which must have room
+/// for the literal, and returns the length of the result; a lone surrogate becomes U+FFFD.
+const JSON_UNESCAPE: &str = r#"static int64_t venti_json_unescape(const char *text, int64_t pos, char *out) {
+    int64_t len = 0;
+    pos++;
+    while (text[pos] != '"') {
+        if (text[pos] != '\\') {
+            out[len++] = text[pos++];
+            continue;
+        }
+        char escaped = text[pos + 1];
+        pos += 2;
+        if (escaped != 'u') {
+            out[len++] = escaped == 'b' ? '\b'
+                : escaped == 'f' ? '\f'
+                : escaped == 'n' ? '\n'
+                : escaped == 'r' ? '\r'
+                : escaped == 't' ? '\t'
+                : escaped;
+            continue;
+        }
+        unsigned code = venti_json_hex(text, pos);
+        pos += 4;
+        if (code >= 0xd800 && code < 0xdc00 && text[pos] == '\\' && text[pos + 1] == 'u') {
+            unsigned low = venti_json_hex(text, pos + 2);
+            if (low >= 0xdc00 && low < 0xe000) {
+                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                pos += 6;
+            }
+        }
+        if (code >= 0xd800 && code < 0xe000) {
+            code = 0xfffd;
+        }
+        if (code < 0x80) {
+            out[len++] = (char)code;
+        } else if (code < 0x800) {
+            out[len++] = (char)(0xc0 | code >> 6);
+            out[len++] = (char)(0x80 | (code & 0x3f));
+        } else if (code < 0x10000) {
+            out[len++] = (char)(0xe0 | code >> 12);
+            out[len++] = (char)(0x80 | (code >> 6 & 0x3f));
+            out[len++] = (char)(0x80 | (code & 0x3f));
+        } else {
+            out[len++] = (char)(0xf0 | code >> 18);
+            out[len++] = (char)(0x80 | (code >> 12 & 0x3f));
+            out[len++] = (char)(0x80 | (code >> 6 & 0x3f));
+            out[len++] = (char)(0x80 | (code & 0x3f));
+        }
+    }
+    return len;
+}
+"#;
+
+/// `venti_json venti_json_build(const char *text, int64_t *pos)`: the value starting at
+/// `*pos` in a valid document, moving `*pos` past it.
+const JSON_BUILD: &str = r#"static venti_json venti_json_build(const char *text, int64_t *pos) {
+    char open = text[*pos];
+    if (open == 'n') {
+        *pos += 4;
+        return NULL;
+    }
+    venti_json node = venti_alloc(sizeof *node);
+    if (open == 't' || open == 'f') {
+        node->kind = VENTI_JSON_BOOL;
+        node->len = open == 't';
+        *pos += open == 't' ? 4 : 5;
+    } else if (open == '"') {
+        int64_t end = venti_json_skip_string(text, *pos);
+        char *data = venti_alloc((size_t)(end - *pos));
+        node->kind = VENTI_JSON_STRING;
+        node->len = venti_json_unescape(text, *pos, data);
+        node->text = data;
+        *pos = end;
+    } else if (open == '[' || open == '{') {
+        int64_t used = 0;
+        int64_t capacity = 0;
+        node->kind = open == '[' ? VENTI_JSON_ARRAY : VENTI_JSON_OBJECT;
+        *pos = venti_json_space(text, *pos + 1);
+        while (text[*pos] != ']' && text[*pos] != '}') {
+            if (used + 2 > capacity) {
+                capacity = capacity * 2 + 4;
+                node->items = realloc(node->items, (size_t)capacity * sizeof *node->items);
+                if (node->items == NULL) {
+                    printf("Runtime Error: out of memory\n");
+                    exit(1);
+                }
+            }
+            if (open == '{') {
+                node->items[used++] = venti_json_build(text, pos);
+                *pos = venti_json_space(text, venti_json_space(text, *pos) + 1);
+            }
+            node->items[used++] = venti_json_build(text, pos);
+            node->len++;
+            *pos = venti_json_space(text, *pos);
+            if (text[*pos] == ',') {
+                *pos = venti_json_space(text, *pos + 1);
+            }
+        }
+        *pos += 1;
+    } else {
+        int64_t end = venti_json_skip_number(text, *pos);
+        char *data = venti_alloc((size_t)(end - *pos) + 1);
+        memcpy(data, text + *pos, (size_t)(end - *pos));
+        node->kind = VENTI_JSON_NUMBER;
+        node->len = end - *pos;
+        node->text = data;
+        *pos = end;
+    }
+    return node;
+}
+"#;
+
+/// `venti_json venti_json_parse(venti_str json)`: the value of the document `json`.
+const JSON_PARSE: &str = r#"static venti_json venti_json_parse(venti_str json) {
+    const char *text = json.len > 0 ? json.data : "";
+    int64_t pos = venti_json_check(json);
+    return venti_json_build(text, &pos);
+}
+"#;
+
+/// `venti_str venti_json_kind(venti_json json)`: the kind of `json` as `json_kind` gives
+/// it.
+const JSON_KIND: &str = r#"static venti_str venti_json_kind(venti_json json) {
+    static const char *const names[] = {"null", "bool", "number", "string", "array", "object"};
+    const char *name = names[json == NULL ? VENTI_JSON_NULL : json->kind];
+    venti_str kind = {name, (int64_t)strlen(name)};
+    return kind;
+}
+"#;
+
+/// `venti_json venti_json_member(venti_json json, venti_str key)`: the last member named
+/// `key` of the object `json`, or null if it has none or is not an object.
+const JSON_MEMBER: &str = r#"static venti_json venti_json_member(venti_json json, venti_str key) {
+    if (json == NULL || json->kind != VENTI_JSON_OBJECT) {
+        return NULL;
+    }
+    for (int64_t i = json->len - 1; i >= 0; i--) {
+        venti_json name = json->items[2 * i];
+        if (name->len == key.len && (key.len == 0 || memcmp(name->text, key.data, (size_t)key.len) == 0)) {
+            return json->items[2 * i + 1];
+        }
+    }
+    return NULL;
+}
+"#;
+
+/// `venti_json venti_json_element(venti_json json, int64_t index)`: element `index` of the
+/// array `json`, or null if it has none or is not an array.
+const JSON_ELEMENT: &str = r#"static venti_json venti_json_element(venti_json json, int64_t index) {
+    if (json == NULL || json->kind != VENTI_JSON_ARRAY || index < 0 || index >= json->len) {
+        return NULL;
+    }
+    return json->items[index];
+}
+"#;
+
+/// `int64_t venti_json_len(venti_json json)`: the number of elements of the array or
+/// members of the object `json`, and 0 for any other value.
+const JSON_LEN: &str = r#"static int64_t venti_json_len(venti_json json) {
+    if (json == NULL || (json->kind != VENTI_JSON_ARRAY && json->kind != VENTI_JSON_OBJECT)) {
+        return 0;
+    }
+    return json->len;
+}
+"#;
+
+/// `void venti_json_expect(venti_json json, int64_t kind, const char *target)`: converting
+/// `json` to `target` when it is not of kind `kind` is a runtime error.
+const JSON_EXPECT: &str = r#"static void venti_json_expect(venti_json json, int64_t kind, const char *target) {
+    if (json == NULL || json->kind != kind) {
+        printf("Runtime Error: Cannot convert a JSON %s to %s\n", venti_json_kind(json).data, target);
+        exit(1);
+    }
+}
+"#;
+
+/// `int64_t venti_json_to_int(venti_json json)`: the number `json` as an int, keeping every
+/// digit of an integer and truncating and saturating any other number like a float.
+const JSON_TO_INT: &str = r#"#include <errno.h>
+
+static int64_t venti_json_to_int(venti_json json) {
+    venti_json_expect(json, VENTI_JSON_NUMBER, "int");
+    char *end;
+    errno = 0;
+    long long value = strtoll(json->text, &end, 10);
+    if (errno != 0 || end != json->text + json->len) {
+        return venti_float_to_int(strtod(json->text, NULL));
+    }
+    return (int64_t)value;
+}
+"#;
+
+/// `double venti_json_to_float(venti_json json)`: the number `json` as a float.
+const JSON_TO_FLOAT: &str = r#"static double venti_json_to_float(venti_json json) {
+    venti_json_expect(json, VENTI_JSON_NUMBER, "float");
+    return strtod(json->text, NULL);
+}
+"#;
+
+/// `bool venti_json_to_bool(venti_json json)`: the bool `json`.
+const JSON_TO_BOOL: &str = r#"static bool venti_json_to_bool(venti_json json) {
+    venti_json_expect(json, VENTI_JSON_BOOL, "bool");
+    return json->len != 0;
+}
+"#;
+
+/// `venti_str venti_json_to_str(venti_json json)`: the string `json`, or `nothing` for
+/// null.
+const JSON_TO_STR: &str = r#"static venti_str venti_json_to_str(venti_json json) {
+    if (json == NULL) {
+        return VENTI_NOTHING;
+    }
+    venti_json_expect(json, VENTI_JSON_STRING, "string");
+    venti_str text = {json->text, json->len};
+    return text;
+}
+"#;
+
+/// `void venti_write_raw(venti_writer *writer, const char *bytes, size_t len)`: adds
+/// `len` bytes to the text being written, which `venti_writer` holds as a buffer with
+/// room for a NUL after it.
+const WRITE_RAW: &str = r#"typedef struct {
+    char *data;
+    size_t len;
+    size_t capacity;
+} venti_writer;
+
+static void venti_write_raw(venti_writer *writer, const char *bytes, size_t len) {
+    if (writer->len + len + 1 > writer->capacity) {
+        while (writer->len + len + 1 > writer->capacity) {
+            writer->capacity = writer->capacity * 2 + 16;
+        }
+        writer->data = realloc(writer->data, writer->capacity);
+        if (writer->data == NULL) {
+            printf("Runtime Error: out of memory\n");
+            exit(1);
+        }
+    }
+    memcpy(writer->data + writer->len, bytes, len);
+    writer->len += len;
+}
+"#;
+
+/// `void venti_json_quote(venti_writer *writer, venti_str text)`: writes `text` as a
+/// JSON string, or `null` for `nothing`.
+const JSON_QUOTE: &str = r#"static void venti_json_quote(venti_writer *writer, venti_str text) {
+    if (text.data == NULL) {
+        venti_write_raw(writer, "null", 4);
+        return;
+    }
+    venti_write_raw(writer, "\"", 1);
+    for (int64_t i = 0; i < text.len; i++) {
+        unsigned char c = (unsigned char)text.data[i];
+        char escaped[8];
+        if (c == '"') {
+            venti_write_raw(writer, "\\\"", 2);
+        } else if (c == '\\') {
+            venti_write_raw(writer, "\\\\", 2);
+        } else if (c == '\n') {
+            venti_write_raw(writer, "\\n", 2);
+        } else if (c == '\r') {
+            venti_write_raw(writer, "\\r", 2);
+        } else if (c == '\t') {
+            venti_write_raw(writer, "\\t", 2);
+        } else if (c < 0x20) {
+            snprintf(escaped, sizeof escaped, "\\u%04x", c);
+            venti_write_raw(writer, escaped, 6);
+        } else {
+            venti_write_raw(writer, text.data + i, 1);
+        }
+    }
+    venti_write_raw(writer, "\"", 1);
+}
+"#;
+
+/// `void venti_json_int(venti_writer *writer, int64_t value)`: writes an integer.
+const JSON_INT: &str = r#"static void venti_json_int(venti_writer *writer, int64_t value) {
+    char text[24];
+    int len = snprintf(text, sizeof text, "%lld", (long long)value);
+    venti_write_raw(writer, text, (size_t)len);
+}
+"#;
+
+/// `void venti_json_float(venti_writer *writer, double value)`: writes a float the way
+/// `printventi` prints it, or `null` for an infinity or NaN.
+const JSON_FLOAT: &str = r#"static void venti_json_float(venti_writer *writer, double value) {
+    if (!isfinite(value)) {
+        venti_write_raw(writer, "null", 4);
+        return;
+    }
+    int len = snprintf(NULL, 0, "%f", value);
+    char *text = venti_alloc((size_t)len + 1);
+    snprintf(text, (size_t)len + 1, "%f", value);
+    venti_write_raw(writer, text, (size_t)len);
+    free(text);
+}
+"#;
+
+/// `void venti_json_map(venti_writer *writer, venti_map map)`: writes a map as an
+/// object with its keys in byte order, found by picking the least key after the last one
+/// written on each pass.
+const JSON_MAP: &str = r#"static void venti_json_map(venti_writer *writer, venti_map map) {
+    const char *last = NULL;
+    venti_write_raw(writer, "{", 1);
+    for (int64_t n = 0; n < map->len; n++) {
+        int64_t next = -1;
+        for (int64_t i = 0; i < map->capacity; i++) {
+            const char *key = map->keys[i];
+            if (key != NULL && (last == NULL || strcmp(key, last) > 0)
+                && (next < 0 || strcmp(key, map->keys[next]) < 0)) {
+                next = i;
+            }
+        }
+        if (n > 0) {
+            venti_write_raw(writer, ",", 1);
+        }
+        last = map->keys[next];
+        venti_str key = {last, (int64_t)strlen(last)};
+        venti_json_quote(writer, key);
+        venti_write_raw(writer, ":", 1);
+        venti_json_int(writer, map->values[next]);
+    }
+    venti_write_raw(writer, "}", 1);
+}
+"#;
+
+/// `void venti_json_write(venti_writer *writer, venti_json json)`: writes `json` as JSON,
+/// with its numbers as they were written.
+const JSON_WRITE: &str = r#"static void venti_json_write(venti_writer *writer, venti_json json) {
+    if (json == NULL) {
+        venti_write_raw(writer, "null", 4);
+    } else if (json->kind == VENTI_JSON_BOOL) {
+        venti_write_raw(writer, json->len ? "true" : "false", json->len ? 4 : 5);
+    } else if (json->kind == VENTI_JSON_NUMBER) {
+        venti_write_raw(writer, json->text, (size_t)json->len);
+    } else if (json->kind == VENTI_JSON_STRING) {
+        venti_str text = {json->text, json->len};
+        venti_json_quote(writer, text);
+    } else {
+        int object = json->kind == VENTI_JSON_OBJECT;
+        venti_write_raw(writer, object ? "{" : "[", 1);
+        for (int64_t i = 0; i < json->len; i++) {
+            if (i > 0) {
+                venti_write_raw(writer, ",", 1);
+            }
+            if (object) {
+                venti_json_write(writer, json->items[2 * i]);
+                venti_write_raw(writer, ":", 1);
+                venti_json_write(writer, json->items[2 * i + 1]);
+            } else {
+                venti_json_write(writer, json->items[i]);
+            }
+        }
+        venti_write_raw(writer, object ? "}" : "]", 1);
+    }
+}
+"#;
+
+/// `const char *venti_json_text(venti_json json)`: `json` the way `printventi` prints it: a
+/// string as its text, and any other value as compact JSON.
+const JSON_TEXT: &str = r#"static const char *venti_json_text(venti_json json) {
+    if (json != NULL && json->kind == VENTI_JSON_STRING) {
+        return json->text;
+    }
+    venti_writer writer = {NULL, 0, 0};
+    venti_json_write(&writer, json);
+    return venti_write_finish(&writer).data;
+}
+"#;
+
+/// `venti_str venti_write_finish(venti_writer *writer)`: the text written, as a string.
+const WRITE_FINISH: &str = r#"static venti_str venti_write_finish(venti_writer *writer) {
+    venti_write_raw(writer, "", 0);
+    writer->data[writer->len] = '\0';
+    venti_str text = {writer->data, (int64_t)writer->len};
+    return text;
+}
+"#;
+
+/// `venti_buffer venti_buffer_alloc(int64_t len)`: `len` zeroed slots; a negative length or
+/// running out of memory is a runtime error.
+const BUFFER_ALLOC: &str = r#"static venti_buffer venti_buffer_alloc(int64_t len) {
+    venti_buffer buffer = {NULL, len};
+    if (len > 0) {
+        buffer.data = calloc((size_t)len, sizeof(int64_t));
+    }
+    if (len < 0 || (len > 0 && buffer.data == NULL)) {
+        printf("Runtime Error: could not allocate a buffer of %lld slots\n", (long long)len);
+        exit(1);
+    }
+    return buffer;
+}
+"#;
+
+/// `int64_t *venti_buffer_slot(venti_buffer buffer, int64_t index)`: the address of slot
+/// `index`; an index out of range is a runtime error.
+const BUFFER_SLOT: &str = r#"static int64_t *venti_buffer_slot(venti_buffer buffer, int64_t index) {
+    if (index < 0 || index >= buffer.len) {
+        printf("Runtime Error: index %lld is out of bounds for a buffer of length %lld\n",
+               (long long)index, (long long)buffer.len);
+        exit(1);
+    }
+    return &buffer.data[index];
+}
+"#;
+
+/// `venti_str *venti_strvec_slot(venti_strvec pieces, int64_t index)`: the address of
+/// string `index`; an index out of range is a runtime error.
+const STRVEC_SLOT: &str = r#"static venti_str *venti_strvec_slot(venti_strvec pieces, int64_t index) {
+    if (index < 0 || index >= pieces->len) {
+        printf("Runtime Error: index %lld is out of bounds for a vector of length %lld\n",
+               (long long)index, (long long)pieces->len);
+        exit(1);
+    }
+    return &pieces->slots[index];
+}
+"#;
+
+/// `uint64_t venti_hash_str(const char *key)`: FNV-1a over a NUL-terminated string.
+const HASH_STR: &str = r#"static uint64_t venti_hash_str(const char *key) {
+    uint64_t hash = UINT64_C(14695981039346656037);
+    for (; *key != '\0'; key++) {
+        hash ^= (unsigned char)*key;
+        hash *= UINT64_C(1099511628211);
+    }
+    return hash;
+}
+"#;
+
+/// `venti_map venti_map_new(int64_t capacity)`: an empty map; `capacity` must be a power of
+/// two.
+const MAP_NEW: &str = r#"static venti_map venti_map_new(int64_t capacity) {
+    venti_map map = venti_alloc(sizeof *map);
+    map->capacity = capacity;
+    map->keys = venti_alloc((size_t)capacity * sizeof *map->keys);
+    map->values = venti_alloc((size_t)capacity * sizeof *map->values);
+    return map;
+}
+"#;
+
+/// `int64_t venti_map_find_slot(venti_map map, const char *key)`: the slot holding `key`,
+/// or the empty slot where it would go.
+const MAP_FIND_SLOT: &str = r#"static int64_t venti_map_find_slot(venti_map map, const char *key) {
+    uint64_t mask = (uint64_t)map->capacity - 1;
+    uint64_t slot = venti_hash_str(key) & mask;
+    while (map->keys[slot] != NULL && strcmp(map->keys[slot], key) != 0) {
+        slot = (slot + 1) & mask;
+    }
+    return (int64_t)slot;
+}
+"#;
+
+/// `void venti_map_grow(venti_map map)`: doubles the capacity and rehashes every entry.
+const MAP_GROW: &str = r#"static void venti_map_grow(venti_map map) {
+    int64_t old_capacity = map->capacity;
+    const char **old_keys = map->keys;
+    int64_t *old_values = map->values;
+    int64_t index;
+    map->capacity = old_capacity * 2;
+    map->keys = venti_alloc((size_t)map->capacity * sizeof *map->keys);
+    map->values = venti_alloc((size_t)map->capacity * sizeof *map->values);
+    for (index = 0; index < old_capacity; index++) {
+        if (old_keys[index] != NULL) {
+            int64_t slot = venti_map_find_slot(map, old_keys[index]);
+            map->keys[slot] = old_keys[index];
+            map->values[slot] = old_values[index];
+        }
+    }
+    free(old_keys);
+    free(old_values);
+}
+"#;
+
+/// `void venti_map_insert(venti_map map, const char *key, int64_t value)`: inserts or
+/// overwrites `key`.
+const MAP_INSERT: &str = r#"static void venti_map_insert(venti_map map, const char *key, int64_t value) {
+    int64_t slot = venti_map_find_slot(map, key);
+    map->values[slot] = value;
+    if (map->keys[slot] == NULL) {
+        map->keys[slot] = key;
+        map->len++;
+        if (map->len * 2 >= map->capacity) {
+            venti_map_grow(map);
+        }
+    }
+}
+"#;
+
+/// `int64_t venti_map_get(venti_map map, const char *key)`: the value stored under `key`;
+/// a missing key is a runtime error.
+const MAP_GET: &str = r#"static int64_t venti_map_get(venti_map map, const char *key) {
+    int64_t slot = venti_map_find_slot(map, key);
+    if (map->keys[slot] == NULL) {
+        printf("Runtime Error: key %s not found in map\n", key);
+        exit(1);
+    }
+    return map->values[slot];
+}
+"#;
+
+/// `int64_t venti_map_has(venti_map map, const char *key)`: 1 if `key` is present, else 0.
+const MAP_HAS: &str = r#"static int64_t venti_map_has(venti_map map, const char *key) {
+    return map->keys[venti_map_find_slot(map, key)] != NULL;
+}
+"#;
+
+/// `venti_channel venti_channel_new(void)`: an empty channel.
+const CHANNEL_NEW: &str = r#"static venti_channel venti_channel_new(void) {
+    venti_channel channel = venti_alloc(sizeof *channel);
+    channel->capacity = 8;
+    channel->slots = venti_alloc((size_t)channel->capacity * sizeof *channel->slots);
+    return channel;
+}
+"#;
+
+/// `int64_t venti_channel_send(venti_channel channel, int64_t value)`: adds `value` to the
+/// back of the queue, doubling its slots when they are full, and returns 0.
+const CHANNEL_SEND: &str = r#"static int64_t venti_channel_send(venti_channel channel, int64_t value) {
+    if (channel->len == channel->capacity) {
+        /* The queue wraps around from head, so the slots are copied over in order */
+        int64_t *slots = venti_alloc((size_t)channel->capacity * 2 * sizeof *slots);
+        for (int64_t index = 0; index < channel->len; index++) {
+            slots[index] = channel->slots[(channel->head + index) & (channel->capacity - 1)];
+        }
+        free(channel->slots);
+        channel->slots = slots;
+        channel->head = 0;
+        channel->capacity *= 2;
+    }
+    channel->slots[(channel->head + channel->len) & (channel->capacity - 1)] = value;
+    channel->len++;
+    return 0;
+}
+"#;
+
+/// `int64_t venti_channel_recv(venti_channel channel)`: takes the value at the front of the
+/// queue. Async calls have run to completion by the time they return, so an empty queue
+/// would never fill and is a runtime error.
+const CHANNEL_RECV: &str = r#"static int64_t venti_channel_recv(venti_channel channel) {
+    if (channel->len == 0) {
+        printf("Runtime Error: could not receive: the channel is empty and no task can send to it\n");
+        exit(1);
+    }
+    int64_t value = channel->slots[channel->head];
+    channel->head = (channel->head + 1) & (channel->capacity - 1);
+    channel->len--;
+    return value;
+}
+"#;
+
+/// `int64_t venti_float_bits(double value)`: the bits of `value`, which is how a channel
+/// keeps a float.
+const FLOAT_BITS: &str = r#"static int64_t venti_float_bits(double value) {
+    int64_t bits;
+    memcpy(&bits, &value, sizeof bits);
+    return bits;
+}
+"#;
+
+/// `double venti_bits_float(int64_t bits)`: the float whose bits `venti_float_bits` gave.
+const BITS_FLOAT: &str = r#"static double venti_bits_float(int64_t bits) {
+    double value;
+    memcpy(&value, &bits, sizeof value);
+    return value;
+}
+"#;
+
+/// `venti_vec venti_vec_new(void)`: an empty vector.
+const VEC_NEW: &str = r#"static venti_vec venti_vec_new(void) {
+    venti_vec vector = venti_alloc(sizeof *vector);
+    vector->capacity = 8;
+    vector->slots = venti_alloc((size_t)vector->capacity * sizeof *vector->slots);
+    return vector;
+}
+"#;
+
+/// `int64_t venti_vec_push(venti_vec vector, int64_t value)`: adds `value` to the end of
+/// the vector, doubling its slots when they are full.
+const VEC_PUSH: &str = r#"static int64_t venti_vec_push(venti_vec vector, int64_t value) {
+    if (vector->len == vector->capacity) {
+        int64_t *slots = realloc(vector->slots, (size_t)vector->capacity * 2 * sizeof *slots);
+        if (slots == NULL) {
+            printf("Runtime Error: could not grow a vector to %lld elements\n",
+                   (long long)vector->capacity * 2);
+            exit(1);
+        }
+        vector->slots = slots;
+        vector->capacity *= 2;
+    }
+    vector->slots[vector->len++] = value;
+    return 0;
+}
+"#;
+
+/// `int64_t venti_vec_pop(venti_vec vector)`: removes the last element of the vector and
+/// returns it; popping an empty vector is a runtime error.
+const VEC_POP: &str = r#"static int64_t venti_vec_pop(venti_vec vector) {
+    if (vector->len == 0) {
+        printf("Runtime Error: cannot pop from an empty vector\n");
+        exit(1);
+    }
+    return vector->slots[--vector->len];
+}
+"#;
+
+/// `int64_t *venti_vec_slot(venti_vec vector, int64_t index)`: the address of element
+/// `index`; an index out of range is a runtime error.
+const VEC_SLOT: &str = r#"static int64_t *venti_vec_slot(venti_vec vector, int64_t index) {
+    if (index < 0 || index >= vector->len) {
+        printf("Runtime Error: index %lld is out of bounds for a vector of length %lld\n",
+               (long long)index, (long long)vector->len);
+        exit(1);
+    }
+    return &vector->slots[index];
+}
+"#;
+
+/// `venti_buffer venti_vec_copy(venti_vec vector)`: a buffer holding a copy of the
+/// vector's elements.
+const VEC_COPY: &str = r#"static venti_buffer venti_vec_copy(venti_vec vector) {
+    int64_t *data = venti_alloc((size_t)vector->len * sizeof *data);
+    memcpy(data, vector->slots, (size_t)vector->len * sizeof *data);
+    return (venti_buffer){data, vector->len};
+}
+"#;
+
+/// `void venti_vec_extend(venti_vec vector, venti_vec source, int64_t start, int64_t end)`:
+/// pushes the elements of `source` from `start` up to `end` onto the vector.
+const VEC_EXTEND: &str = r#"static void venti_vec_extend(venti_vec vector, venti_vec source, int64_t start, int64_t end) {
+    /* Indexed afresh each time, since pushing a vector onto itself may move its slots */
+    for (int64_t index = start; index < end; index++) {
+        venti_vec_push(vector, source->slots[index]);
+    }
+}
+"#;
+
+/// `venti_vec venti_vec_range(venti_vec vector, venti_range range)`: a new vector holding
+/// the elements the range covers, as `v[start..end]` gives. Offsets outside the vector and
+/// a start past the end are runtime errors.
+const VEC_RANGE: &str = r#"static venti_vec venti_vec_range(venti_vec vector, venti_range range) {
+    if (range.start < 0 || range.start > range.end || range.end > vector->len) {
+        printf("Runtime Error: vector slice %lld..%lld is out of range for a vector of length %lld\n",
+               (long long)range.start, (long long)range.end, (long long)vector->len);
+        exit(1);
+    }
+    venti_vec slice = venti_vec_new();
+    venti_vec_extend(slice, vector, range.start, range.end);
+    return slice;
+}
+"#;
+
+/// `venti_vec venti_vec_concat(venti_vec left, venti_vec right)`: a new vector holding the
+/// elements of `left` followed by those of `right`, as `left + right` gives.
+const VEC_CONCAT: &str = r#"static venti_vec venti_vec_concat(venti_vec left, venti_vec right) {
+    venti_vec joined = venti_vec_new();
+    venti_vec_extend(joined, left, 0, left->len);
+    venti_vec_extend(joined, right, 0, right->len);
+    return joined;
+}
+"#;
+
+/// `int64_t venti_sort(int64_t *slots, int64_t len)`: sorts the slots in ascending order
+/// with the stable bottom-up merge sort the LLVM runtime uses, and returns 0.
+const SORT: &str = r#"static int64_t venti_sort(int64_t *slots, int64_t len) {
+    if (len < 2) {
+        return 0;
+    }
+    int64_t *scratch = venti_alloc((size_t)len * sizeof *scratch);
+    int64_t *source = slots, *target = scratch;
+    for (int64_t width = 1; width < len; width *= 2) {
+        for (int64_t lo = 0; lo < len; lo += 2 * width) {
+            int64_t mid = lo + width < len ? lo + width : len;
+            int64_t hi = lo + 2 * width < len ? lo + 2 * width : len;
+            int64_t i = lo, j = mid;
+            for (int64_t k = lo; k < hi; k++) {
+                /* The right element goes first only when it is smaller, keeping equal ones in order */
+                if (i >= mid || (j < hi && source[i] > source[j])) {
+                    target[k] = source[j++];
+                } else {
+                    target[k] = source[i++];
+                }
+            }
+        }
+        int64_t *merged = target;
+        target = source;
+        source = merged;
+    }
+    if (source != slots) {
+        memcpy(slots, source, (size_t)len * sizeof *slots);
+    }
+    free(scratch);
+    return 0;
+}
+"#;
+
+/// `int64_t venti_binary_search(int64_t *slots, int64_t len, int64_t value)`: the index of
+/// the first of the slots, in ascending order, holding `value`, or -1 if none does.
+const BINARY_SEARCH: &str = r#"static int64_t venti_binary_search(int64_t *slots, int64_t len, int64_t value) {
+    int64_t lo = 0, hi = len;
+    while (lo < hi) {
+        int64_t mid = lo + (hi - lo) / 2;
+        if (slots[mid] < value) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    return lo < len && slots[lo] == value ? lo : -1;
+}
+"#;
+
+/// `void venti_panic(const char *message, const char *function)`: prints the message of
+/// `panic_venti` and the function it was called in, then exits with status 101.
+const PANIC: &str = r#"static void venti_panic(const char *message, const char *function) {
+    printf("Panic in %s: %s\n", function, message != NULL ? message : "");
+    exit(101);
+}
+"#;
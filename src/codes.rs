@@ -0,0 +1,365 @@
+/*
+Every error has a code, such as V0042 for an undefined variable, which is printed with it
+and stays the same from release to release, so it can be searched for and matched on by
+tools. `venti explain V0042` prints what the error means and how it is usually fixed.
+
+The errors that come up most have codes of their own; every other error has the code of
+its kind, such as V0040 for a type error no more specific code describes.
+*/
+
+/// The code of a class of errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    UnexpectedToken,
+    UnexpectedCharacter,
+    MissingSemicolon,
+    UnclosedBlock,
+    ImportCycle,
+    ImportNotFound,
+    ImportNameClash,
+    TypeMismatch,
+    UndefinedVariable,
+    UndefinedFunction,
+    ArgumentCount,
+    DuplicateDefinition,
+    NotCallable,
+    DeniedLint,
+    Runtime,
+    Panic,
+    Codegen,
+    Io,
+}
+
+impl ErrorCode {
+    /// Every code, in numeric order.
+    pub const ALL: [ErrorCode; 18] = [
+        ErrorCode::UnexpectedToken,
+        ErrorCode::UnexpectedCharacter,
+        ErrorCode::MissingSemicolon,
+        ErrorCode::UnclosedBlock,
+        ErrorCode::ImportCycle,
+        ErrorCode::ImportNotFound,
+        ErrorCode::ImportNameClash,
+        ErrorCode::TypeMismatch,
+        ErrorCode::UndefinedVariable,
+        ErrorCode::UndefinedFunction,
+        ErrorCode::ArgumentCount,
+        ErrorCode::DuplicateDefinition,
+        ErrorCode::NotCallable,
+        ErrorCode::DeniedLint,
+        ErrorCode::Runtime,
+        ErrorCode::Panic,
+        ErrorCode::Codegen,
+        ErrorCode::Io,
+    ];
+
+    /// The code as it is printed, such as "V0042".
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedToken => "V0001",
+            ErrorCode::UnexpectedCharacter => "V0002",
+            ErrorCode::MissingSemicolon => "V0003",
+            ErrorCode::UnclosedBlock => "V0004",
+            ErrorCode::ImportCycle => "V0010",
+            ErrorCode::ImportNotFound => "V0011",
+            ErrorCode::ImportNameClash => "V0012",
+            ErrorCode::TypeMismatch => "V0040",
+            ErrorCode::UndefinedVariable => "V0042",
+            ErrorCode::UndefinedFunction => "V0043",
+            ErrorCode::ArgumentCount => "V0044",
+            ErrorCode::DuplicateDefinition => "V0045",
+            ErrorCode::NotCallable => "V0046",
+            ErrorCode::DeniedLint => "V0050",
+            ErrorCode::Runtime => "V0080",
+            ErrorCode::Panic => "V0081",
+            ErrorCode::Codegen => "V0090",
+            ErrorCode::Io => "V0095",
+        }
+    }
+
+    /// Maps a printed code to its `ErrorCode`, ignoring case, or `None` for an unknown one.
+    pub fn from_code(code: &str) -> Option<Self> {
+        ErrorCode::ALL
+            .into_iter()
+            .find(|known| known.as_str().eq_ignore_ascii_case(code))
+    }
+
+    /// A short note on how the error is usually fixed, shown under it.
+    pub fn help(self) -> Option<&'static str> {
+        match self {
+            ErrorCode::MissingSemicolon => Some("every statement ends with `;`"),
+            ErrorCode::UnclosedBlock => Some("each `{` needs a `}` after the block's statements"),
+            ErrorCode::ImportCycle => {
+                Some("move what the files share into another file that each of them imports")
+            }
+            ErrorCode::UndefinedVariable => {
+                Some("variables are declared with `venti name = value;` before they are used")
+            }
+            ErrorCode::UndefinedFunction => Some(
+                "functions are declared with `fn_venti name(params) { ... }` or imported with `import_venti`",
+            ),
+            ErrorCode::DeniedLint => Some(
+                "fix the code the lint points to, or pass `--warn <lint>` to only warn about it",
+            ),
+            _ => None,
+        }
+    }
+
+    /// The description `venti explain` prints: what the error means and an example fix.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedToken => {
+                "\
+A token appeared where the grammar does not allow it, such as an operator with nothing
+after it or a keyword in the middle of an expression.
+
+    venti total = 1 + ;
+
+Finish the expression or remove the stray token:
+
+    venti total = 1 + 2;
+"
+            }
+            ErrorCode::UnexpectedCharacter => {
+                "\
+The source contains a character that is not part of any token, such as `$` or `@`
+outside a string.
+
+    venti price = 5$;
+
+Remove the character, or put it inside a string literal:
+
+    venti price = 5;
+    printventi(\"5$\");
+"
+            }
+            ErrorCode::MissingSemicolon => {
+                "\
+A statement is not followed by `;`. Every statement ends with one, including the last
+one in a block.
+
+    venti x = 1
+    printventi(x);
+
+Add the `;`:
+
+    venti x = 1;
+    printventi(x);
+"
+            }
+            ErrorCode::UnclosedBlock => {
+                "\
+A block or a match was opened with `{` but the file ends before its `}`.
+
+    fn_venti double(n) {
+        return_venti n * 2;
+
+Close the block:
+
+    fn_venti double(n) {
+        return_venti n * 2;
+    }
+"
+            }
+            ErrorCode::ImportCycle => {
+                "\
+A file imports itself, either directly or through the files it imports, so there is no
+order to load them in.
+
+    # a.venti
+    import_venti \"b.venti\";
+    # b.venti
+    import_venti \"a.venti\";
+
+Move the functions both files need into a third file, and import that from each:
+
+    # a.venti
+    import_venti \"shared.venti\";
+    # b.venti
+    import_venti \"shared.venti\";
+"
+            }
+            ErrorCode::ImportNotFound => {
+                "\
+An `import_venti` names a file that cannot be read. The path is relative to the
+directory of the file the import is in, not to the current directory.
+
+    import_venti \"utils\";
+
+Spell out the file name, extension included:
+
+    import_venti \"utils.venti\";
+"
+            }
+            ErrorCode::ImportNameClash => {
+                "\
+Two imported files have the same name, so calls such as `utils.add()` could mean
+either of them.
+
+    import_venti \"math/utils.venti\";
+    import_venti \"text/utils.venti\";
+
+Rename one of the files:
+
+    import_venti \"math/utils.venti\";
+    import_venti \"text/strings.venti\";
+"
+            }
+            ErrorCode::TypeMismatch => {
+                "\
+A value has a type the code using it cannot work with, such as a string added to a
+number or a float stored in an int variable.
+
+    venti count = 1;
+    count = \"one\";
+
+Use a value of the right type, converting with `as` where that makes sense:
+
+    venti count = 1;
+    count = 1.5 as int;
+"
+            }
+            ErrorCode::UndefinedVariable => {
+                "\
+A variable is used that has not been declared, or is declared in a scope the use
+cannot see, such as another function.
+
+    printventi(count);
+
+Declare it with `venti` before it is used:
+
+    venti count = 0;
+    printventi(count);
+"
+            }
+            ErrorCode::UndefinedFunction => {
+                "\
+A function is called that is not declared in the program, not imported and not a
+builtin.
+
+    printventi(square(4));
+
+Declare the function, or import the file that declares it:
+
+    fn_venti square(n) { return_venti n * n; }
+    printventi(square(4));
+"
+            }
+            ErrorCode::ArgumentCount => {
+                "\
+A function, lambda or method is called with more or fewer arguments than it takes.
+
+    fn_venti add(a, b) { return_venti a + b; }
+    printventi(add(1));
+
+Pass one argument for each parameter:
+
+    printventi(add(1, 2));
+"
+            }
+            ErrorCode::DuplicateDefinition => {
+                "\
+A function or top-level variable is defined twice. The error names the lines of both
+definitions.
+
+    fn_venti area(w, h) { return_venti w * h; }
+    fn_venti area(s) { return_venti s * s; }
+
+Give one of them another name:
+
+    fn_venti area(w, h) { return_venti w * h; }
+    fn_venti square_area(s) { return_venti s * s; }
+"
+            }
+            ErrorCode::NotCallable => {
+                "\
+Something that is not a function or a lambda is called.
+
+    venti limit = 10;
+    printventi(limit(2));
+
+Call a function, or drop the parentheses to use the value:
+
+    venti limit = 10;
+    printventi(limit);
+"
+            }
+            ErrorCode::DeniedLint => {
+                "\
+A lint found code that is probably a mistake, and the lint is denied with `--deny` or
+`--deny-warnings`, so what would be a warning is an error. The message ends with the
+name of the lint.
+
+    fn_venti total(values) {
+        venti sum = 0;
+        return_venti 0;
+    }
+
+Fix the code, here by using `sum`:
+
+    fn_venti total(values) {
+        venti sum = 0;
+        return_venti sum;
+    }
+
+or stop denying the lint with `--warn unused-variable` or `--allow unused-variable`.
+"
+            }
+            ErrorCode::Runtime => {
+                "\
+The program did something that has no result while running under the interpreter, such
+as dividing by zero or indexing past the end of an array.
+
+    venti values = [1, 2];
+    printventi(values[2]);
+
+Check the value before using it, for example against `len`:
+
+    venti values = [1, 2];
+    printventi(values[len(values) - 1]);
+"
+            }
+            ErrorCode::Panic => {
+                "\
+The program called `panic_venti`, which stops it with the message it was given and the
+name of the function that called it. A panic is not caught by `try_venti`, and the
+program exits with status 101.
+
+    fn_venti average(total, count) {
+        match_venti count {
+            0 => {
+                panic_venti(\"no values to average\");
+            }
+        }
+        return_venti total / count;
+    }
+
+Panics mark states the program cannot go on from. Where the caller can recover, return
+a value it can check instead.
+"
+            }
+            ErrorCode::Codegen => {
+                "\
+LLVM could not generate code for the program, or the program uses something the
+compiler cannot yet compile, such as an index it can tell is out of bounds.
+
+    venti values = [1, 2];
+    printventi(values[5]);
+
+Fix the code the error points to. An error with no location is a bug in the compiler,
+and is worth reporting with the program that caused it.
+"
+            }
+            ErrorCode::Io => {
+                "\
+A file could not be read or written, or a tool such as the linker could not be run.
+
+    venti build missing.venti
+
+Check that the path exists and is readable, and that the directory the output goes to
+can be written to.
+"
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::codegen::backend;
+use crate::codegen::codegen::CodeGen;
+use crate::driver::{Driver, Options, Session};
+use crate::errors::VentiError;
+use crate::modules::resolver::Resolver;
+use crate::semantic::analyzer::Analyzer;
+use crate::source::SourceMap;
+use crate::venti_parser::arena::Arena;
+use crate::venti_parser::ast::{Expr, Statement};
+use inkwell::context::Context;
+use inkwell::targets::{CodeModel, FileType};
+use std::path::{Path, PathBuf};
+
+/*
+`compile_str` runs the whole compiler on a string, for Rust programs that embed Venti:
+
+    let options = CompileOptions::new().with_opt_level(2);
+    let module = venti::compile_str("printventi 1 + 2;", &options)?;
+    std::fs::write("program.o", module.object())?;
+
+It lexes, parses, checks, folds, generates and optimizes the program as `venti build`
+does, and returns the module as LLVM IR, bitcode and an object file for the target, so
+the caller needs no LLVM context of its own. Imports are relative to the directory set
+in the options, the current directory by default. Lints are not run.
+*/
+
+/// How `compile_str` compiles a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileOptions {
+    /// The name of the module, which is part of the symbol of everything in it.
+    pub module_name: String,
+    /// The optimization level, from 0 to 3.
+    pub opt_level: u8,
+    /// Whether constants are folded before code generation.
+    pub fold: bool,
+    /// The target triple, or `None` for the host.
+    pub target: Option<String>,
+    /// The directory `import_venti` paths are relative to.
+    pub import_dir: PathBuf,
+}
+
+impl CompileOptions {
+    /// Creates the options `venti build` uses without flags: no optimization, with
+    /// constant folding, for the host.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `CompileOptions`.
+    pub fn new() -> Self {
+        CompileOptions {
+            module_name: "main".to_string(),
+            opt_level: 0,
+            fold: true,
+            target: None,
+            import_dir: PathBuf::from("."),
+        }
+    }
+
+    /// Names the module.
+    pub fn with_module_name(mut self, name: &str) -> Self {
+        self.module_name = name.to_string();
+        self
+    }
+
+    /// Sets the optimization level, which is clamped to 3.
+    pub fn with_opt_level(mut self, level: u8) -> Self {
+        self.opt_level = level.min(3);
+        self
+    }
+
+    /// Skips constant folding.
+    pub fn without_fold(mut self) -> Self {
+        self.fold = false;
+        self
+    }
+
+    /// Compiles for `triple` instead of the host, accepting `wasm32` and `wasm64`.
+    pub fn with_target(mut self, triple: &str) -> Self {
+        self.target = Some(backend::normalize_triple(triple));
+        self
+    }
+
+    /// Resolves imports relative to `directory`.
+    pub fn with_import_dir(mut self, directory: &Path) -> Self {
+        self.import_dir = directory.to_path_buf();
+        self
+    }
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A compiled and optimized program, in each of the forms the compiler produces without
+/// linking.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledModule {
+    name: String,
+    triple: String,
+    ir: String,
+    bitcode: Vec<u8>,
+    object: Vec<u8>,
+}
+
+impl CompiledModule {
+    /// The name of the module.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The target triple the module was compiled for.
+    pub fn triple(&self) -> &str {
+        &self.triple
+    }
+
+    /// The module as textual LLVM IR.
+    pub fn ir(&self) -> &str {
+        &self.ir
+    }
+
+    /// The module as LLVM bitcode.
+    pub fn bitcode(&self) -> &[u8] {
+        &self.bitcode
+    }
+
+    /// The module as an object file for its target, ready to be linked.
+    pub fn object(&self) -> &[u8] {
+        &self.object
+    }
+}
+
+/// Compiles the program in `source`.
+///
+/// # Arguments
+///
+/// * `source` - The text of the program.
+/// * `options` - How to compile it.
+///
+/// # Returns
+///
+/// * `Result<CompiledModule, VentiError>` - The compiled module, or the first error found.
+///   Errors in the program carry the line and column they are at.
+pub fn compile_str(source: &str, options: &CompileOptions) -> Result<CompiledModule, VentiError> {
+    let mut driver_options = Options::new()
+        .without_lints()
+        .with_opt_level(options.opt_level);
+    if !options.fold {
+        driver_options = driver_options.without_fold();
+    }
+    let mut session = Session::new(driver_options);
+    let mut driver = Driver::new(&mut session);
+    let arena = Arena::new();
+    let ast = driver.frontend(
+        Path::new("<string>"),
+        source,
+        Resolver::new(&options.import_dir),
+        &arena,
+    )?;
+
+    let level = backend::optimization_level(options.opt_level);
+    let machine =
+        backend::target_machine(options.target.as_deref(), level, None, CodeModel::Default)?;
+    let wasm = options.target.as_deref().is_some_and(backend::is_wasm);
+    let context = Context::create();
+    let mut codegen = CodeGen::new(&context, level)
+        .with_module_name(&options.module_name)
+        .with_target_triple(&machine.get_triple())
+        .with_symbols(driver.session().symbols.clone());
+    if wasm {
+        codegen = codegen.with_print_imports();
+    }
+    driver.generate(ast, |ast| codegen.compile(ast))?;
+    let module = codegen.module();
+    backend::configure_module(module, &machine);
+    driver.optimize(module, &machine)?;
+
+    let object = machine
+        .write_to_memory_buffer(module, FileType::Object)
+        .map_err(|e| VentiError::CodegenError(e.to_string(), None))?;
+    Ok(CompiledModule {
+        name: options.module_name.clone(),
+        triple: machine.get_triple().as_str().to_string_lossy().into_owned(),
+        ir: module.print_to_string().to_string(),
+        bitcode: module.write_bitcode_to_memory().as_slice().to_vec(),
+        object: object.as_slice().to_vec(),
+    })
+}
+
+/// Lexes, parses and type checks the program in `source`, merging in the files it imports
+/// from `import_dir`. The expressions of the program are put in `arena`.
+///
+/// # Returns
+///
+/// * `Result<Vec<Statement>, VentiError>` - The checked program, or the first error found.
+pub fn check_str<'a>(
+    source: &str,
+    import_dir: &Path,
+    arena: &'a Arena<Expr<'a>>,
+    sources: &mut SourceMap,
+) -> Result<Vec<Statement<'a>>, VentiError> {
+    check_str_with(source, import_dir, arena, &mut Analyzer::new(), sources)
+}
+
+/// Checks `source` like `check_str`, with an `analyzer` that may already know functions
+/// and variables defined outside it, and which learns those defined in it.
+pub fn check_str_with<'a>(
+    source: &str,
+    import_dir: &Path,
+    arena: &'a Arena<Expr<'a>>,
+    analyzer: &mut Analyzer,
+    sources: &mut SourceMap,
+) -> Result<Vec<Statement<'a>>, VentiError> {
+    // The session adds the program and its imports to the caller's source map, which it
+    // hands back whether or not the program checks
+    let mut session = Session::new(Options::new().without_lints());
+    session.sources = std::mem::take(sources);
+    let ast = Driver::new(&mut session).frontend_with(
+        Path::new("<string>"),
+        source,
+        Resolver::new(import_dir),
+        arena,
+        analyzer,
+    );
+    *sources = session.sources;
+    ast
+}
@@ -0,0 +1,362 @@
+use crate::errors::VentiError;
+use crate::semantic::lints::Warning;
+use crate::source::SourceMap;
+use crate::style::{Painter, Style};
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::dump::write_json_string;
+use std::fmt::{self, Write};
+
+/*
+Errors are reported the way rustc reports them: the kind of error, its code and its
+message, where it is, the line of source it is on with the span underlined, and a note on
+how to fix it for the errors that have an obvious fix:
+
+    Type Error[V0042]: Undefined variable 'count'
+     --> hello.venti:2:12
+      |
+    2 | printventi(count + 1);
+      |            ^^^^^
+      = help: variables are declared with `venti name = value;` before they are used
+    For more about this error, run `venti explain V0042`
+
+An error without a span, such as a file that cannot be read, shows no source. One whose
+file is not in the source map names the line and column without showing the source.
+Several errors found together are shown one after another, with a blank line between
+them and a count at the end.
+
+With `--error-format json` each error is instead written as a JSON object on a line of
+its own, for editors and CI tools:
+
+    {"severity": "error", "code": "V0042", "kind": "type-error",
+     "message": "Undefined variable 'count'", "file": "hello.venti",
+     "span": {"start": 22, "end": 27, "line": 2, "column": 12},
+     "help": "variables are declared with ..."}
+
+`file`, `span` and `help` are null when the error has none. JSON is never colored.
+
+Warnings are written the same way, with `Warning[unused-variable]` in place of the kind and
+code of an error and, in JSON, a severity of "warning", the lint's name as the code and a
+kind of "lint".
+*/
+
+/// How errors are written, chosen with `--error-format`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+impl ErrorFormat {
+    /// Maps an `--error-format` value to its format, or `None` for an unknown name.
+    pub fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// An error ready to be printed with the source it points to.
+pub struct Diagnostic<'a> {
+    error: &'a VentiError,
+    renderer: Renderer<'a>,
+}
+
+impl<'a> Diagnostic<'a> {
+    /// Creates a `Diagnostic` for `error`, whose span is looked up in `sources`.
+    pub fn new(error: &'a VentiError, sources: &'a SourceMap) -> Self {
+        Diagnostic {
+            error,
+            renderer: Renderer {
+                sources,
+                painter: Painter::plain(),
+            },
+        }
+    }
+
+    /// Colors the human-readable output when `enabled` is true.
+    pub fn with_color(mut self, enabled: bool) -> Self {
+        self.renderer.painter = Painter::new(enabled);
+        self
+    }
+
+    /// Writes the diagnostic in `format`, ending with a newline.
+    pub fn render(&self, format: ErrorFormat) -> String {
+        match format {
+            ErrorFormat::Human => self.to_string(),
+            ErrorFormat::Json => self
+                .error
+                .errors()
+                .iter()
+                .map(|error| self.renderer.json(&error_entry(error)) + "\n")
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let VentiError::Multiple(errors) = self.error {
+            for error in errors {
+                self.renderer.write(f, &error_entry(error))?;
+                writeln!(f)?;
+            }
+            writeln!(f, "Found {} errors", errors.len())?;
+            return writeln!(
+                f,
+                "For more about an error, run `venti explain` with its code"
+            );
+        }
+        self.renderer.write(f, &error_entry(self.error))?;
+        writeln!(
+            f,
+            "For more about this error, run `venti explain {}`",
+            self.error.code().as_str()
+        )
+    }
+}
+
+/// Warnings ready to be printed with the source they point to.
+pub struct WarningReport<'a> {
+    warnings: &'a [Warning],
+    renderer: Renderer<'a>,
+}
+
+impl<'a> WarningReport<'a> {
+    /// Creates a `WarningReport` for `warnings`, whose spans are looked up in `sources`.
+    pub fn new(warnings: &'a [Warning], sources: &'a SourceMap) -> Self {
+        WarningReport {
+            warnings,
+            renderer: Renderer {
+                sources,
+                painter: Painter::plain(),
+            },
+        }
+    }
+
+    /// Colors the human-readable output when `enabled` is true.
+    pub fn with_color(mut self, enabled: bool) -> Self {
+        self.renderer.painter = Painter::new(enabled);
+        self
+    }
+
+    /// Writes the warnings in `format`, ending with a newline, or nothing when there are
+    /// none.
+    pub fn render(&self, format: ErrorFormat) -> String {
+        match format {
+            ErrorFormat::Human => self.to_string(),
+            ErrorFormat::Json => self
+                .warnings
+                .iter()
+                .map(|warning| self.renderer.json(&warning_entry(warning)) + "\n")
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for WarningReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for warning in self.warnings {
+            self.renderer.write(f, &warning_entry(warning))?;
+            writeln!(f)?;
+        }
+        if self.warnings.len() > 1 {
+            writeln!(f, "Found {} warnings", self.warnings.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether an entry stops the compilation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+/// What is printed for one error or warning.
+struct Entry<'a> {
+    severity: Severity,
+    /// The first line up to the message, such as "Type Error[V0042]"
+    header: String,
+    code: &'a str,
+    kind: &'static str,
+    message: &'a str,
+    span: Option<Span>,
+    help: Option<&'static str>,
+}
+
+/// Describes a single error.
+fn error_entry(error: &VentiError) -> Entry<'_> {
+    let code = error.code().as_str();
+    Entry {
+        severity: Severity::Error,
+        header: format!("{}[{}]", error.kind(), code),
+        code,
+        kind: kind_name(error),
+        message: error.message(),
+        span: error.span(),
+        help: error.code().help(),
+    }
+}
+
+/// Describes a warning, whose code is the name of its lint.
+fn warning_entry(warning: &Warning) -> Entry<'_> {
+    let code = warning.lint.name();
+    Entry {
+        severity: Severity::Warning,
+        header: format!("Warning[{}]", code),
+        code,
+        kind: "lint",
+        message: &warning.message,
+        span: warning.span,
+        help: warning.lint.help(),
+    }
+}
+
+/// Writes entries, with the source their spans point to when they are for people.
+#[derive(Clone, Copy)]
+struct Renderer<'a> {
+    sources: &'a SourceMap,
+    painter: Painter,
+}
+
+impl Renderer<'_> {
+    /// Writes `entry` as a one-line JSON object.
+    fn json(&self, entry: &Entry) -> String {
+        let mut out = String::from("{\"severity\": ");
+        write_json_string(
+            match entry.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            &mut out,
+        );
+        out.push_str(", \"code\": ");
+        write_json_string(entry.code, &mut out);
+        out.push_str(", \"kind\": ");
+        write_json_string(entry.kind, &mut out);
+        out.push_str(", \"message\": ");
+        write_json_string(entry.message, &mut out);
+        out.push_str(", \"file\": ");
+        match entry.span.and_then(|span| self.sources.get(span.file)) {
+            Some(file) => write_json_string(&file.path.display().to_string(), &mut out),
+            None => out.push_str("null"),
+        }
+        out.push_str(", \"span\": ");
+        match entry.span {
+            Some(span) => {
+                let _ = write!(
+                    out,
+                    "{{\"start\": {}, \"end\": {}, \"line\": {}, \"column\": {}}}",
+                    span.start, span.end, span.line, span.column
+                );
+            }
+            None => out.push_str("null"),
+        }
+        out.push_str(", \"help\": ");
+        match entry.help {
+            Some(help) => write_json_string(help, &mut out),
+            None => out.push_str("null"),
+        }
+        out.push('}');
+        out
+    }
+
+    /// Writes a single entry, from its first line to its help note.
+    fn write(&self, f: &mut fmt::Formatter, entry: &Entry) -> fmt::Result {
+        let paint = |style, text: &str| self.painter.paint(style, text);
+        let style = match entry.severity {
+            Severity::Error => Style::Error,
+            Severity::Warning => Style::Warning,
+        };
+        writeln!(
+            f,
+            "{}{}",
+            paint(style, &entry.header),
+            paint(Style::Emphasis, &format!(": {}", entry.message))
+        )?;
+        // The gutter is as wide as the line number, as in rustc
+        let gutter = entry.span.map_or(String::new(), |span| {
+            " ".repeat(span.line.to_string().len())
+        });
+        if let Some(span) = entry.span {
+            self.snippet(f, span, &gutter, style)?;
+        }
+        if let Some(help) = entry.help {
+            writeln!(f, "{} {} {}", gutter, paint(Style::Note, "= help:"), help)?;
+        }
+        Ok(())
+    }
+
+    /// Writes where `span` is and, when its file is known, the line it is on with the span
+    /// underlined in `style`.
+    fn snippet(
+        &self,
+        f: &mut fmt::Formatter,
+        span: Span,
+        gutter: &str,
+        style: Style,
+    ) -> fmt::Result {
+        let paint = |style, text: &str| self.painter.paint(style, text);
+        let file = self.sources.get(span.file);
+        // The map finds the line and column from the offset of the span, and a span of
+        // text it does not hold keeps its own
+        let (line, column) = self
+            .sources
+            .location(span)
+            .map_or((span.line, span.column), |location| {
+                (location.line, location.column)
+            });
+        let source_line = file.and_then(|file| file.line(line));
+        let arrow = paint(Style::Gutter, "-->");
+        match file {
+            Some(file) => writeln!(
+                f,
+                "{}{} {}:{}:{}",
+                gutter,
+                arrow,
+                file.path.display(),
+                line,
+                column
+            )?,
+            None => writeln!(f, "{}{} {}:{}", gutter, arrow, line, column)?,
+        }
+
+        if let (Some(file), Some(source_line)) = (file, source_line) {
+            let before = source_line.chars().take(column.saturating_sub(1) as usize);
+            // Tabs are kept so the carets line up with the text above them
+            let padding = before
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            // A span over several lines is underlined to the end of its first one
+            let width = file
+                .text
+                .get(span.start..span.end)
+                .and_then(|text| text.lines().next())
+                .map_or(0, |text| text.chars().count())
+                .max(1);
+            let bar = paint(Style::Gutter, &format!("{} |", gutter));
+            let number = paint(Style::Gutter, &format!("{} |", line));
+            writeln!(f, "{}", bar)?;
+            writeln!(f, "{} {}", number, source_line)?;
+            writeln!(f, "{} {}{}", bar, padding, paint(style, &"^".repeat(width)))?;
+        }
+        Ok(())
+    }
+}
+
+/// The stable name of the kind of `error`, which tools can match on.
+fn kind_name(error: &VentiError) -> &'static str {
+    match error {
+        VentiError::SyntaxError(..) => "syntax-error",
+        VentiError::TypeError(..) => "type-error",
+        VentiError::RuntimeError(..) => "runtime-error",
+        VentiError::CodegenError(..) => "codegen-error",
+        VentiError::IOError(..) => "io-error",
+        VentiError::LintError(..) => "lint-error",
+        VentiError::Multiple(errors) => kind_name(&errors[0]),
+        VentiError::Coded(_, error) => kind_name(error),
+    }
+}
@@ -0,0 +1,445 @@
+use crate::codegen::backend;
+use crate::errors::VentiError;
+use crate::ir::ir::Program;
+use crate::ir::lower::lower;
+use crate::modules::resolver::Resolver;
+use crate::optimizer::fold::Folder;
+use crate::semantic::analyzer::Analyzer;
+use crate::semantic::infer::infer;
+use crate::semantic::lints::{LintLevels, Linter, Warning};
+use crate::semantic::types::Type;
+use crate::source::SourceMap;
+use crate::symbols::SymbolTable;
+use crate::timing::PassTimes;
+use crate::venti_lexer::lexer::SpannedToken;
+use crate::venti_parser::arena::Arena;
+use crate::venti_parser::ast::{Expr, Statement};
+use crate::venti_parser::parser::Parser;
+use inkwell::module::Module;
+use inkwell::targets::TargetMachine;
+use std::path::Path;
+use std::time::Instant;
+
+/*
+A `Driver` takes a program through the phases of the compiler, in order:
+
+    Parse -> Resolve -> Infer -> Check -> Lint -> Fold -> Lower -> Codegen -> Optimize
+
+It runs them for a `Session`, which holds what outlives one program: the options, the
+source map every file read is added to, the names the checker resolved, the warnings the
+lints found and the time each phase took. The command line keeps one session per command and renders errors from its
+source map once the command is done; `compile_str` keeps one per call.
+
+    let mut session = Session::new(Options::new().with_stop_after(Phase::Check));
+    let arena = Arena::new();
+    let mut driver = Driver::new(&mut session);
+    let ast = driver.frontend(path, &text, Resolver::for_file(path), &arena)?;
+
+The expressions of the program and of the files it imports are put in the caller's arena,
+which the syntax tree borrows for as long as it is used.
+
+A session stops after the phase its options name, so `venti check`, an editor asking
+for errors or a tool that only wants the syntax tree run as much of the compiler as they
+need. A phase after that one is skipped and hands its input on unchanged. Hooks see the
+program after a phase and may fail the compilation, which is how `--dump-ast` prints the
+merged program before it is checked; codegen consumes the program, so hooks on the
+phases from `Codegen` on never run.
+
+The code generator is built by the caller, which knows the target and the flags it
+takes, and given as the closure that compiles the program, so the LLVM and C backends
+are driven alike. The LLVM backend still compiles the syntax tree, through `generate`;
+the C backend compiles the IR, through `generate_ir`, which lowers the program first.
+`lower` gives the IR of a checked program to whatever else reads it, such as `--dump-ir`.
+*/
+
+/// The phases of the compiler, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Phase {
+    /// Lexing and parsing, which run together as the parser pulls tokens.
+    Parse,
+    /// Merging in the files the program imports.
+    Resolve,
+    /// Inferring the types of untyped parameters, which annotates them.
+    Infer,
+    /// Type checking.
+    Check,
+    Lint,
+    /// Constant folding.
+    Fold,
+    /// Lowering into the IR.
+    Lower,
+    Codegen,
+    Optimize,
+}
+
+impl Phase {
+    /// The name the phase is reported by in `--time-passes`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::Resolve => "resolve imports",
+            Phase::Infer => "infer types",
+            Phase::Check => "check types",
+            Phase::Lint => "lint",
+            Phase::Fold => "fold constants",
+            Phase::Lower => "lower",
+            Phase::Codegen => "generate code",
+            Phase::Optimize => "optimize",
+        }
+    }
+}
+
+/// How a session compiles programs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// The level of each lint, which decides whether its warnings are errors.
+    pub lint_levels: LintLevels,
+    /// Whether the lints run at all.
+    pub lint: bool,
+    /// Whether constants are folded before code generation.
+    pub fold: bool,
+    /// The optimization level, from 0 to 3.
+    pub opt_level: u8,
+    /// The last phase that runs.
+    pub stop_after: Phase,
+}
+
+impl Options {
+    /// Creates the options `venti build` uses without flags: every phase runs, every lint
+    /// warns and nothing is optimized.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Options`.
+    pub fn new() -> Self {
+        Options {
+            lint_levels: LintLevels::new(),
+            lint: true,
+            fold: true,
+            opt_level: 0,
+            stop_after: Phase::Optimize,
+        }
+    }
+
+    /// Sets the level of each lint.
+    pub fn with_lint_levels(mut self, levels: LintLevels) -> Self {
+        self.lint_levels = levels;
+        self
+    }
+
+    /// Skips the lints.
+    pub fn without_lints(mut self) -> Self {
+        self.lint = false;
+        self
+    }
+
+    /// Skips constant folding.
+    pub fn without_fold(mut self) -> Self {
+        self.fold = false;
+        self
+    }
+
+    /// Sets the optimization level, which is clamped to 3.
+    pub fn with_opt_level(mut self, level: u8) -> Self {
+        self.opt_level = level.min(3);
+        self
+    }
+
+    /// Stops after `phase`.
+    pub fn with_stop_after(mut self, phase: Phase) -> Self {
+        self.stop_after = phase;
+        self
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the compiler keeps while it compiles: its options, the files it read, the names
+/// it resolved, the warnings it found and how long each phase took.
+#[derive(Debug, Default)]
+pub struct Session {
+    pub options: Options,
+    /// The text of every file read, so errors can show the source they are about.
+    pub sources: SourceMap,
+    /// The names the last program checked by `frontend` defines and uses, whose spans
+    /// point into `sources`, for tools such as go-to-definition.
+    pub symbols: SymbolTable<Type>,
+    /// The warnings of the lints that warn, in the order they were found.
+    pub warnings: Vec<Warning>,
+    pub times: PassTimes,
+}
+
+impl Session {
+    /// Creates a session that compiles with `options`.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Session`.
+    pub fn new(options: Options) -> Self {
+        Session {
+            options,
+            ..Session::default()
+        }
+    }
+}
+
+/// Looks at the program after a phase, and fails the compilation by returning an error.
+type Hook<'s> = Box<dyn FnMut(&[Statement]) -> Result<(), VentiError> + 's>;
+
+/// Looks at each token of the program as it is lexed.
+type TokenHook<'s> = Box<dyn FnMut(&SpannedToken) + 's>;
+
+/// Runs programs through the phases of the compiler for a session.
+pub struct Driver<'s> {
+    session: &'s mut Session,
+    hooks: Vec<(Phase, Hook<'s>)>,
+    token_hook: Option<TokenHook<'s>>,
+}
+
+impl<'s> Driver<'s> {
+    /// Creates a driver that compiles with the options of `session` and keeps what it
+    /// finds there.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Driver`.
+    pub fn new(session: &'s mut Session) -> Self {
+        Driver {
+            session,
+            hooks: Vec::new(),
+            token_hook: None,
+        }
+    }
+
+    /// Runs `hook` on the program each time `phase` has run on it. Hooks on the same
+    /// phase run in the order they were added.
+    pub fn with_hook(
+        mut self,
+        phase: Phase,
+        hook: impl FnMut(&[Statement]) -> Result<(), VentiError> + 's,
+    ) -> Self {
+        self.hooks.push((phase, Box::new(hook)));
+        self
+    }
+
+    /// Runs `hook` on each token of the program as the parser pulls it, so a lexer error
+    /// comes after the tokens before it. Tokens of imported files are not included.
+    pub fn with_token_hook(mut self, hook: impl FnMut(&SpannedToken) + 's) -> Self {
+        self.token_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// The session the driver compiles for.
+    pub fn session(&mut self) -> &mut Session {
+        self.session
+    }
+
+    /// Whether the session's options let `phase` run.
+    pub fn runs(&self, phase: Phase) -> bool {
+        phase <= self.session.options.stop_after
+    }
+
+    /// Runs the front end on a program: parses it, merges in its imports, infers and
+    /// checks its types and lints it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path the program is known by.
+    /// * `text` - The text of the program.
+    /// * `resolver` - Loads the files the program imports.
+    /// * `arena` - Holds the expressions of the program and its imports, for as long as
+    ///   the caller uses the syntax tree.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Statement>, VentiError>` - The program as the last phase that ran
+    ///   left it, or the first error found, which is a warning of a denied lint when the
+    ///   program has no other errors.
+    pub fn frontend<'a>(
+        &mut self,
+        path: &Path,
+        text: &str,
+        resolver: Resolver,
+        arena: &'a Arena<Expr<'a>>,
+    ) -> Result<Vec<Statement<'a>>, VentiError> {
+        let mut analyzer = Analyzer::new();
+        let ast = self.frontend_with(path, text, resolver, arena, &mut analyzer);
+        self.session.symbols = analyzer.into_symbols();
+        ast
+    }
+
+    /// Runs the front end like `frontend`, with an `analyzer` that may already know
+    /// functions and variables defined outside the program, and which learns those
+    /// defined in it. The names it resolves stay in the analyzer rather than the session.
+    pub fn frontend_with<'a>(
+        &mut self,
+        path: &Path,
+        text: &str,
+        mut resolver: Resolver,
+        arena: &'a Arena<Expr<'a>>,
+        analyzer: &mut Analyzer,
+    ) -> Result<Vec<Statement<'a>>, VentiError> {
+        let file = self.session.sources.add(path, text, 1);
+        let token_hook = &mut self.token_hook;
+        let tokens = self.session.sources.lexer(file).inspect(|token| {
+            if let (Some(hook), Ok(token)) = (token_hook.as_mut(), token) {
+                hook(token);
+            }
+        });
+        let start = Instant::now();
+        let ast = Parser::new(tokens, arena).parse();
+        self.session.times.add(Phase::Parse.name(), start.elapsed());
+        let ast = ast?;
+        self.after(Phase::Parse, &ast)?;
+
+        if !self.runs(Phase::Resolve) {
+            return Ok(ast);
+        }
+        let sources = &mut self.session.sources;
+        let mut ast = self.session.times.time(Phase::Resolve.name(), || {
+            resolver.resolve(ast, arena, sources)
+        })?;
+        self.after(Phase::Resolve, &ast)?;
+
+        if !self.runs(Phase::Infer) {
+            return Ok(ast);
+        }
+        self.session
+            .times
+            .time(Phase::Infer.name(), || infer(&mut ast))?;
+        self.after(Phase::Infer, &ast)?;
+
+        if !self.runs(Phase::Check) {
+            return Ok(ast);
+        }
+        self.session
+            .times
+            .time(Phase::Check.name(), || analyzer.analyze(&ast))?;
+        self.after(Phase::Check, &ast)?;
+
+        if !self.runs(Phase::Lint) || !self.session.options.lint {
+            return Ok(ast);
+        }
+        let warnings = self
+            .session
+            .times
+            .time(Phase::Lint.name(), || Linter::new().lint(&ast));
+        let warnings = self.session.options.lint_levels.apply(warnings)?;
+        self.session.warnings.extend(warnings);
+        self.after(Phase::Lint, &ast)?;
+        Ok(ast)
+    }
+
+    /// Folds the constants of a checked program, unless the options skip folding, and
+    /// generates code for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `ast` - The checked program.
+    /// * `compile` - Compiles the program with the caller's code generator, such as
+    ///   `|ast| codegen.compile(ast)`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), VentiError>` - Nothing, or the error that stopped code generation.
+    pub fn generate<'a>(
+        &mut self,
+        ast: Vec<Statement<'a>>,
+        compile: impl FnOnce(Vec<Statement<'a>>) -> Result<(), VentiError>,
+    ) -> Result<(), VentiError> {
+        let ast = self.fold(ast)?;
+        if !self.runs(Phase::Codegen) {
+            return Ok(());
+        }
+        self.session
+            .times
+            .time(Phase::Codegen.name(), || compile(ast))
+    }
+
+    /// Folds the constants of a checked program, unless the options skip folding, lowers
+    /// it into the IR and generates code for that.
+    ///
+    /// # Arguments
+    ///
+    /// * `ast` - The checked program.
+    /// * `compile` - Compiles the IR with the caller's code generator, such as
+    ///   `|program| codegen.compile(program)`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), VentiError>` - Nothing, or the error that stopped code generation.
+    pub fn generate_ir(
+        &mut self,
+        ast: Vec<Statement>,
+        compile: impl FnOnce(Program) -> Result<(), VentiError>,
+    ) -> Result<(), VentiError> {
+        let ast = self.fold(ast)?;
+        let Some(program) = self.lower(&ast) else {
+            return Ok(());
+        };
+        if !self.runs(Phase::Codegen) {
+            return Ok(());
+        }
+        self.session
+            .times
+            .time(Phase::Codegen.name(), || compile(program))
+    }
+
+    /// Folds the constants of a checked program, unless the options skip folding.
+    fn fold<'a>(&mut self, ast: Vec<Statement<'a>>) -> Result<Vec<Statement<'a>>, VentiError> {
+        if !self.runs(Phase::Fold) || !self.session.options.fold {
+            return Ok(ast);
+        }
+        let ast = self
+            .session
+            .times
+            .time(Phase::Fold.name(), || Folder::new(&ast).fold(ast));
+        self.after(Phase::Fold, &ast)?;
+        Ok(ast)
+    }
+
+    /// Lowers a checked program into the IR.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Program>` - The lowered program, or `None` if the options stop before
+    ///   lowering.
+    pub fn lower(&mut self, ast: &[Statement]) -> Option<Program> {
+        if !self.runs(Phase::Lower) {
+            return None;
+        }
+        let symbols = &self.session.symbols;
+        Some(
+            self.session
+                .times
+                .time(Phase::Lower.name(), || lower(ast, symbols)),
+        )
+    }
+
+    /// Optimizes a generated module for `machine` at the options' optimization level.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), VentiError>` - Nothing, or an error if LLVM rejects the pipeline.
+    pub fn optimize(&mut self, module: &Module, machine: &TargetMachine) -> Result<(), VentiError> {
+        if !self.runs(Phase::Optimize) {
+            return Ok(());
+        }
+        let opt_level = self.session.options.opt_level;
+        self.session.times.time(Phase::Optimize.name(), || {
+            backend::optimize(module, machine, opt_level)
+        })
+    }
+
+    /// Runs the hooks on `phase`.
+    fn after(&mut self, phase: Phase, ast: &[Statement]) -> Result<(), VentiError> {
+        for (_, hook) in self.hooks.iter_mut().filter(|(on, _)| *on == phase) {
+            hook(ast)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+use crate::codegen::codegen::{CodeGen, HOST_CALL};
+use crate::codegen::{backend, runtime};
+use crate::codes::ErrorCode;
+use crate::compile::check_str_with;
+use crate::embed::host::{call_host, HostFn, HostFunction};
+use crate::embed::value::{Signature, Value, ValueType};
+use crate::errors::VentiError;
+use crate::semantic::analyzer::Analyzer;
+use crate::semantic::types::{FunctionSignature, Type};
+use crate::source::SourceMap;
+use crate::venti_parser::arena::Arena;
+use inkwell::context::Context;
+use inkwell::types::BasicTypeEnum;
+use inkwell::OptimizationLevel;
+use std::path::Path;
+
+/*
+An `Engine` compiles programs with the JIT and lets Rust call their functions, and lets
+them call Rust:
+
+    let context = Context::create();
+    let mut engine = Engine::new(&context)?;
+    engine.register_fn("shout", |text: &str| text.to_uppercase())?;
+    engine.load("fn_venti greet(name: string, times) { ... }")?;
+    engine.call("greet", &[Value::from("world"), Value::Int(3)])?;
+
+Each `load` compiles a program into the engine and runs its top-level statements once,
+like an entry of the REPL, so later programs can use what earlier ones defined. After
+that `call` runs any function defined so far, as often as the caller likes, checking the
+arguments against the function's signature first. Signatures come from the compiled
+functions themselves: a parameter annotated `float`, `bool` or `string` takes that type
+and any other takes an int, and functions written in Venti return an int. Host functions
+(see `host`) must be registered before the programs that call them are loaded.
+
+Values are handed to generated code in 64-bit slots (see `CodeGen::call_function`). A
+string argument is copied into a buffer laid out as the runtime lays out a string literal,
+whose negative reference count keeps generated code from freeing it, and the buffer is
+dropped once the call returns.
+*/
+
+/// Compiled programs whose functions can be called from Rust.
+pub struct Engine<'ctx> {
+    context: &'ctx Context,
+    codegen: CodeGen<'ctx>,
+    // Knows the functions and top-level variables of everything loaded so far
+    analyzer: Analyzer,
+    sources: SourceMap,
+    // Boxed so the addresses generated code was given stay put
+    #[allow(clippy::vec_box)]
+    hosts: Vec<Box<HostFunction>>,
+}
+
+impl<'ctx> Engine<'ctx> {
+    /// Creates an engine with nothing loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The LLVM context programs are compiled in, which outlives the engine.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Engine, VentiError>` - The engine, or an error if the JIT is unavailable.
+    pub fn new(context: &'ctx Context) -> Result<Self, VentiError> {
+        backend::initialize_host()?;
+        let mut codegen = CodeGen::new(context, OptimizationLevel::None).with_module_name("engine");
+        codegen.map_symbol(HOST_CALL, call_host as *const () as usize);
+        Ok(Engine {
+            context,
+            codegen,
+            analyzer: Analyzer::new(),
+            sources: SourceMap::new(),
+            hosts: Vec::new(),
+        })
+    }
+
+    /// Compiles the program in `source` into the engine and runs its top-level statements.
+    ///
+    /// Imports are relative to the current directory. A program that does not compile leaves
+    /// the engine as it was, while one whose statements raise a runtime error stays loaded.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), VentiError>` - Nothing, or the first error found in the program.
+    pub fn load(&mut self, source: &str) -> Result<(), VentiError> {
+        let mut analyzer = self.analyzer.clone();
+        let arena = Arena::new();
+        let ast = check_str_with(
+            source,
+            Path::new("."),
+            &arena,
+            &mut analyzer,
+            &mut self.sources,
+        )?;
+        self.codegen.set_symbols(analyzer.symbols().clone());
+        let ran = self.codegen.run_entry(ast);
+        if let Ok(()) | Err(VentiError::RuntimeError(..)) = ran {
+            self.analyzer = analyzer;
+        }
+        ran
+    }
+
+    /// Makes the Rust closure `function` callable from programs loaded after it as `name`.
+    ///
+    /// The closure's parameter and result types give the function its signature; see
+    /// `HostFn` for the types it may use.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), VentiError>` - Nothing, or an error if `name` is already defined.
+    pub fn register_fn<M, F>(&mut self, name: &str, function: F) -> Result<(), VentiError>
+    where
+        F: HostFn<M> + Sync + 'static,
+    {
+        let host = Box::new(HostFunction::new(function));
+        let signature = host.signature();
+        let mut analyzer = self.analyzer.clone();
+        analyzer.declare_host_function(
+            name,
+            FunctionSignature {
+                params: signature
+                    .params
+                    .iter()
+                    .map(|&param| analyzer_type(param))
+                    .collect(),
+                returns: analyzer_type(signature.returns),
+            },
+        )?;
+        let param_types = signature
+            .params
+            .iter()
+            .map(|&param| self.llvm_type(param))
+            .collect::<Vec<_>>();
+        let address = &*host as *const HostFunction as usize;
+        self.codegen.define_host_function(
+            name,
+            &param_types,
+            self.llvm_type(signature.returns),
+            address,
+        )?;
+        self.analyzer = analyzer;
+        self.hosts.push(host);
+        Ok(())
+    }
+
+    /// Returns the files the programs were read from, to show errors against.
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Returns the signature of the function `name`, or `None` if there is none.
+    pub fn signature(&self, name: &str) -> Option<Signature> {
+        let function_type = self.codegen.function_type(name)?;
+        let params = function_type
+            .get_param_types()
+            .into_iter()
+            .map(value_type)
+            .collect();
+        let returns = function_type
+            .get_return_type()
+            .map_or(ValueType::Int, value_type);
+        Some(Signature { params, returns })
+    }
+
+    /// Calls the function `name` with `args`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Value, VentiError>` - What the function returned, or an error if there is
+    ///   no such function or the arguments do not match its parameters.
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, VentiError> {
+        let signature = self.signature(name).ok_or_else(|| {
+            VentiError::RuntimeError(format!("Undefined function '{}'", name), None)
+                .with_code(ErrorCode::UndefinedFunction)
+        })?;
+        if args.len() != signature.params.len() {
+            return Err(VentiError::TypeError(
+                format!(
+                    "Function '{}' takes {} arguments, got {}",
+                    name,
+                    signature.params.len(),
+                    args.len()
+                ),
+                None,
+            )
+            .with_code(ErrorCode::ArgumentCount));
+        }
+
+        let mut slots = Vec::new();
+        // The strings' buffers, which must live until the call returns
+        let mut buffers = Vec::new();
+        for (index, (arg, &expected)) in args.iter().zip(&signature.params).enumerate() {
+            match arg {
+                Value::Int(n) if expected == ValueType::Int => slots.push(*n as u64),
+                Value::Float(x) if expected == ValueType::Float => slots.push(x.to_bits()),
+                Value::Bool(b) if expected == ValueType::Bool => slots.push(*b as u64),
+                Value::Str(s) if expected == ValueType::Str => {
+                    let buffer = string_buffer(s);
+                    slots.push(buffer[1..].as_ptr() as u64);
+                    slots.push(s.len() as u64);
+                    buffers.push(buffer);
+                }
+                _ => {
+                    return Err(VentiError::TypeError(
+                        format!(
+                            "Argument {} of '{}' must be {}, found {}",
+                            index + 1,
+                            name,
+                            expected,
+                            arg.value_type()
+                        ),
+                        None,
+                    )
+                    .with_code(ErrorCode::TypeMismatch))
+                }
+            }
+        }
+        let [first, second] = self.codegen.call_function(name, &slots)?;
+        drop(buffers);
+        Ok(match signature.returns {
+            ValueType::Int => Value::Int(first as i64),
+            ValueType::Float => Value::Float(f64::from_bits(first)),
+            ValueType::Bool => Value::Bool(first != 0),
+            ValueType::Str => {
+                // Safety: the slots hold the data and length of a string generated code
+                // returned, which it no longer owns
+                let bytes =
+                    unsafe { std::slice::from_raw_parts(first as *const u8, second as usize) };
+                Value::Str(String::from_utf8_lossy(bytes).into_owned())
+            }
+        })
+    }
+
+    /// Returns the LLVM type values of `value_type` have in generated code.
+    fn llvm_type(&self, value_type: ValueType) -> BasicTypeEnum<'ctx> {
+        match value_type {
+            ValueType::Int => self.context.i64_type().into(),
+            ValueType::Float => self.context.f64_type().into(),
+            ValueType::Bool => self.context.bool_type().into(),
+            ValueType::Str => runtime::string_type(self.context).into(),
+        }
+    }
+}
+
+/// Returns the type of values of the LLVM type `llvm_type` in generated code.
+fn value_type(llvm_type: BasicTypeEnum) -> ValueType {
+    match llvm_type {
+        BasicTypeEnum::FloatType(_) => ValueType::Float,
+        BasicTypeEnum::IntType(int_type) if int_type.get_bit_width() == 1 => ValueType::Bool,
+        BasicTypeEnum::StructType(_) => ValueType::Str,
+        _ => ValueType::Int,
+    }
+}
+
+/// Returns the type the analyzer gives values of `value_type`.
+fn analyzer_type(value_type: ValueType) -> Type {
+    match value_type {
+        ValueType::Int => Type::Int,
+        ValueType::Float => Type::Float,
+        ValueType::Bool => Type::Bool,
+        ValueType::Str => Type::String,
+    }
+}
+
+/// Copies `text` into words laid out as a string literal: a negative reference count,
+/// then the bytes and a NUL, padded to a whole word. The data starts at the second word,
+/// which keeps the count aligned for the runtime's atomic updates.
+fn string_buffer(text: &str) -> Vec<u64> {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.push(0);
+    let mut words = vec![runtime::IMMORTAL_COUNT as u64];
+    words.extend(bytes.chunks(8).map(|chunk| {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        u64::from_ne_bytes(word)
+    }));
+    words
+}
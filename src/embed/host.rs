@@ -0,0 +1,270 @@
+use crate::embed::value::{Signature, Value, ValueType};
+use std::cell::RefCell;
+use std::marker::PhantomData;
+
+/*
+Host functions are Rust closures a program calls like its own functions:
+
+    engine.register_fn("shout", |text: &str, times: i64| text.repeat(times as usize))?;
+
+The types of a closure's parameters and result give the function its signature, which
+the analyzer checks calls against. Parameters may be `i64`, `f64`, `bool`, `String` or
+`&str`, up to three of them, and results `i64`, `f64`, `bool` or `String`.
+
+Generated code calls every host function through the one `venti_host_call` symbol, which
+the JIT maps to `call_host`, passing it the address of the `HostFunction` along with the
+arguments in slots (see `CodeGen::call_function`). `call_host` turns the slots into
+`Value`s, calls the closure and packs its result. A string result is kept in a
+thread-local buffer until generated code has copied it, which it does before calling the
+host again. A closure that panics aborts the program, since the panic cannot unwind
+through generated code.
+*/
+
+/// A registered closure, taking its arguments as `Value`s.
+type Closure = dyn Fn(&[Value]) -> Option<Value> + Sync;
+
+/// A Rust function that a program can call, with the signature it was registered with.
+pub struct HostFunction {
+    signature: Signature,
+    function: Box<Closure>,
+}
+
+impl HostFunction {
+    /// Wraps `function`, whose signature follows from its parameter and result types.
+    pub fn new<M, F: HostFn<M> + Sync + 'static>(function: F) -> Self {
+        HostFunction {
+            signature: Signature {
+                params: F::params(),
+                returns: F::returns(),
+            },
+            function: Box::new(move |args| function.call(args)),
+        }
+    }
+
+    /// Returns the types of the function's parameters and result.
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+}
+
+thread_local! {
+    // The last string a host function returned on this thread, until it is copied
+    static RETURNED: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Calls the host function at `host` with the argument slots at `args`, writing its
+/// result to the two slots at `result`. The JIT resolves `venti_host_call` to this.
+///
+/// # Safety
+///
+/// `host` must point to a live `HostFunction`, `args` to as many slots as its parameters
+/// take and `result` to two writable slots. String arguments must point to valid UTF-8.
+pub unsafe extern "C" fn call_host(host: *const HostFunction, args: *const u64, result: *mut u64) {
+    let host = &*host;
+    let mut next = args;
+    let mut take = || {
+        let slot = *next;
+        next = next.add(1);
+        slot
+    };
+    let values = host
+        .signature
+        .params
+        .iter()
+        .map(|param| match param {
+            ValueType::Int => Value::Int(take() as i64),
+            ValueType::Float => Value::Float(f64::from_bits(take())),
+            ValueType::Bool => Value::Bool(take() != 0),
+            ValueType::Str => {
+                let data = take() as *const u8;
+                let len = take() as usize;
+                let bytes = std::slice::from_raw_parts(data, len);
+                Value::Str(String::from_utf8_lossy(bytes).into_owned())
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // The analyzer has checked the arguments against the signature the values follow
+    let value = (host.function)(&values).expect("host function arguments match its signature");
+    match value {
+        Value::Int(n) => *result = n as u64,
+        Value::Float(x) => *result = x.to_bits(),
+        Value::Bool(b) => *result = b as u64,
+        Value::Str(s) => RETURNED.with(|returned| {
+            let mut returned = returned.borrow_mut();
+            *returned = s;
+            *result = returned.as_ptr() as u64;
+            *result.add(1) = returned.len() as u64;
+        }),
+    }
+}
+
+/// A Rust closure that can be registered as a host function. `M` marks how it takes each
+/// parameter, which keeps the implementations for `&str` and owned parameters apart; it
+/// is always inferred.
+pub trait HostFn<M> {
+    /// The types of the closure's parameters.
+    fn params() -> Vec<ValueType>;
+
+    /// The type of the closure's result.
+    fn returns() -> ValueType;
+
+    /// Calls the closure, or returns `None` if `args` do not match its parameters.
+    fn call(&self, args: &[Value]) -> Option<Value>;
+}
+
+/// A type a host function can take by value.
+pub trait HostArg: Sized {
+    const TYPE: ValueType;
+
+    /// Converts `value`, or returns `None` if it is of another type.
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl HostArg for i64 {
+    const TYPE: ValueType = ValueType::Int;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_int()
+    }
+}
+
+impl HostArg for f64 {
+    const TYPE: ValueType = ValueType::Float;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_float()
+    }
+}
+
+impl HostArg for bool {
+    const TYPE: ValueType = ValueType::Bool;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+impl HostArg for String {
+    const TYPE: ValueType = ValueType::Str;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_str().map(str::to_string)
+    }
+}
+
+/// A type a host function can return.
+pub trait HostResult {
+    const TYPE: ValueType;
+
+    fn into_value(self) -> Value;
+}
+
+impl HostResult for i64 {
+    const TYPE: ValueType = ValueType::Int;
+
+    fn into_value(self) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl HostResult for f64 {
+    const TYPE: ValueType = ValueType::Float;
+
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl HostResult for bool {
+    const TYPE: ValueType = ValueType::Bool;
+
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl HostResult for String {
+    const TYPE: ValueType = ValueType::Str;
+
+    fn into_value(self) -> Value {
+        Value::Str(self)
+    }
+}
+
+/// Marks a parameter of a `HostFn` taken by value.
+pub struct Owned<A>(PhantomData<A>);
+
+/// Marks a `&str` parameter of a `HostFn`.
+pub struct Borrowed;
+
+/// How a parameter of a `HostFn` is taken from its argument.
+pub trait Param {
+    type Out<'a>;
+    const TYPE: ValueType;
+
+    fn get(value: &Value) -> Option<Self::Out<'_>>;
+}
+
+impl<A: HostArg> Param for Owned<A> {
+    type Out<'a> = A;
+    const TYPE: ValueType = A::TYPE;
+
+    fn get(value: &Value) -> Option<A> {
+        A::from_value(value)
+    }
+}
+
+impl Param for Borrowed {
+    type Out<'a> = &'a str;
+    const TYPE: ValueType = ValueType::Str;
+
+    fn get(value: &Value) -> Option<&str> {
+        value.as_str()
+    }
+}
+
+/// Implements `HostFn` for closures whose parameters are marked by the `$marker`s, taking
+/// the `$param` types.
+macro_rules! impl_host_fn {
+    ([$($generic:ident),*] $($marker:ty => $param:ty),*) => {
+        impl<F, R $(, $generic: HostArg)*> HostFn<($($marker,)*)> for F
+        where
+            F: Fn($($param),*) -> R,
+            R: HostResult,
+        {
+            fn params() -> Vec<ValueType> {
+                vec![$(<$marker as Param>::TYPE),*]
+            }
+
+            fn returns() -> ValueType {
+                R::TYPE
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            fn call(&self, args: &[Value]) -> Option<Value> {
+                if args.len() != Self::params().len() {
+                    return None;
+                }
+                let mut args = args.iter();
+                Some(self($(<$marker as Param>::get(args.next()?)?),*).into_value())
+            }
+        }
+    };
+}
+
+impl_host_fn!([]);
+impl_host_fn!([A] Owned<A> => A);
+impl_host_fn!([] Borrowed => &str);
+impl_host_fn!([A, B] Owned<A> => A, Owned<B> => B);
+impl_host_fn!([A] Owned<A> => A, Borrowed => &str);
+impl_host_fn!([B] Borrowed => &str, Owned<B> => B);
+impl_host_fn!([] Borrowed => &str, Borrowed => &str);
+impl_host_fn!([A, B, C] Owned<A> => A, Owned<B> => B, Owned<C> => C);
+impl_host_fn!([A, B] Owned<A> => A, Owned<B> => B, Borrowed => &str);
+impl_host_fn!([A, C] Owned<A> => A, Borrowed => &str, Owned<C> => C);
+impl_host_fn!([A] Owned<A> => A, Borrowed => &str, Borrowed => &str);
+impl_host_fn!([B, C] Borrowed => &str, Owned<B> => B, Owned<C> => C);
+impl_host_fn!([B] Borrowed => &str, Owned<B> => B, Borrowed => &str);
+impl_host_fn!([C] Borrowed => &str, Borrowed => &str, Owned<C> => C);
+impl_host_fn!([] Borrowed => &str, Borrowed => &str, Borrowed => &str);
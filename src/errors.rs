@@ -1,24 +1,168 @@
+use crate::codes::ErrorCode;
+use crate::venti_lexer::span::Span;
 use std::fmt;
 
+/// An error, with the span of source it is about when it has one.
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum VentiError {
-    SyntaxError(String),
-    TypeError(String),
-    RuntimeError(String),
-    CodegenError(String),
-    IOError(String),
+    SyntaxError(String, Option<Span>),
+    TypeError(String, Option<Span>),
+    RuntimeError(String, Option<Span>),
+    CodegenError(String, Option<Span>),
+    IOError(String, Option<Span>),
+    /// A warning of a lint that was denied.
+    LintError(String, Option<Span>),
+    /// Several errors found in one pass, in source order. Never empty; most methods
+    /// answer for the first error.
+    Multiple(Vec<VentiError>),
+    /// An error given a code more specific than the one of its kind.
+    Coded(ErrorCode, Box<VentiError>),
+}
+
+impl VentiError {
+    /// Returns the span the error is about, if it has one.
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            VentiError::SyntaxError(_, span)
+            | VentiError::TypeError(_, span)
+            | VentiError::RuntimeError(_, span)
+            | VentiError::CodegenError(_, span)
+            | VentiError::IOError(_, span)
+            | VentiError::LintError(_, span) => span,
+            VentiError::Multiple(ref errors) => errors[0].span(),
+            VentiError::Coded(_, ref error) => error.span(),
+        }
+    }
+
+    /// Gives the error `code`, in place of the code of its kind.
+    pub fn with_code(self, code: ErrorCode) -> Self {
+        VentiError::Coded(code, Box::new(self))
+    }
+
+    /// Returns the code of the error, which is that of its kind unless it was given one.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            VentiError::SyntaxError(..) => ErrorCode::UnexpectedToken,
+            VentiError::TypeError(..) => ErrorCode::TypeMismatch,
+            VentiError::RuntimeError(..) => ErrorCode::Runtime,
+            VentiError::CodegenError(..) => ErrorCode::Codegen,
+            VentiError::IOError(..) => ErrorCode::Io,
+            VentiError::LintError(..) => ErrorCode::DeniedLint,
+            VentiError::Multiple(errors) => errors[0].code(),
+            VentiError::Coded(code, _) => *code,
+        }
+    }
+
+    /// Returns the errors reported by this one: those of a `Multiple`, or the error itself.
+    pub fn errors(&self) -> &[VentiError] {
+        match self {
+            VentiError::Multiple(errors) => errors,
+            error => std::slice::from_ref(error),
+        }
+    }
+
+    /// Returns where the span of a single error is kept, or `None` for a `Multiple`.
+    fn span_slot(&mut self) -> Option<&mut Option<Span>> {
+        match self {
+            VentiError::SyntaxError(_, slot)
+            | VentiError::TypeError(_, slot)
+            | VentiError::RuntimeError(_, slot)
+            | VentiError::CodegenError(_, slot)
+            | VentiError::IOError(_, slot)
+            | VentiError::LintError(_, slot) => Some(slot),
+            VentiError::Multiple(_) => None,
+            VentiError::Coded(_, error) => error.span_slot(),
+        }
+    }
+
+    /// Places the error at `span`, unless it already has a span of its own. Called on
+    /// the way out of each node, so the error keeps the innermost span.
+    pub fn at(mut self, span: Span) -> Self {
+        if let Some(slot) = self.span_slot() {
+            // A span from a parser without spans points nowhere
+            if slot.is_none() && span.line != 0 {
+                *slot = Some(span);
+            }
+        }
+        self
+    }
+
+    /// Moves the error's span into `file`, for an error in text that was lexed without
+    /// being given its file.
+    pub fn in_file(mut self, file: u32) -> Self {
+        match self {
+            VentiError::Multiple(errors) => {
+                return VentiError::Multiple(
+                    errors
+                        .into_iter()
+                        .map(|error| error.in_file(file))
+                        .collect(),
+                )
+            }
+            VentiError::Coded(code, error) => return error.in_file(file).with_code(code),
+            _ => {}
+        }
+        if let Some(Some(span)) = self.span_slot() {
+            span.file = file;
+        }
+        self
+    }
+
+    /// Returns the name of the kind of error, such as "Syntax Error".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            VentiError::SyntaxError(..) => "Syntax Error",
+            VentiError::TypeError(..) => "Type Error",
+            VentiError::RuntimeError(..) => "Runtime Error",
+            VentiError::CodegenError(..) => "Codegen Error",
+            VentiError::IOError(..) => "IO Error",
+            VentiError::LintError(..) => "Lint Error",
+            VentiError::Multiple(errors) => errors[0].kind(),
+            VentiError::Coded(_, error) => error.kind(),
+        }
+    }
+
+    /// Returns the message of the error, without its kind or span.
+    pub fn message(&self) -> &str {
+        match self {
+            VentiError::SyntaxError(msg, _)
+            | VentiError::TypeError(msg, _)
+            | VentiError::RuntimeError(msg, _)
+            | VentiError::CodegenError(msg, _)
+            | VentiError::IOError(msg, _)
+            | VentiError::LintError(msg, _) => msg,
+            VentiError::Multiple(errors) => errors[0].message(),
+            VentiError::Coded(_, error) => error.message(),
+        }
+    }
 }
 
 impl fmt::Display for VentiError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            VentiError::SyntaxError(ref msg) => write!(f, "Syntax Error: {}", msg),
-            VentiError::TypeError(ref msg) => write!(f, "Type Error: {}", msg),
-            VentiError::RuntimeError(ref msg) => write!(f, "Runtime Error: {}", msg),
-            VentiError::CodegenError(ref msg) => write!(f, "Codegen Error: {}", msg),
-            VentiError::IOError(ref msg) => write!(f, "IO Error: {}", msg),
+        match self {
+            VentiError::Multiple(errors) => {
+                for (index, error) in errors.iter().enumerate() {
+                    if index > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+            VentiError::Coded(_, error) => write!(f, "{}", error),
+            _ => match self.span() {
+                Some(span) => write!(f, "{} on {}: {}", self.kind(), span, self.message()),
+                None => write!(f, "{}: {}", self.kind(), self.message()),
+            },
         }
     }
 }
 
 impl std::error::Error for VentiError {}
+
+impl From<inkwell::builder::BuilderError> for VentiError {
+    fn from(err: inkwell::builder::BuilderError) -> Self {
+        VentiError::CodegenError(err.to_string(), None)
+    }
+}
@@ -0,0 +1,570 @@
+use crate::errors::VentiError;
+use crate::venti_lexer::lexer::Lexer;
+use crate::venti_lexer::token::Token;
+use crate::venti_parser::arena::Arena;
+use crate::venti_parser::ast::{
+    BinOp, Expr, ExprKind, InlineHint, MatchArm, Parameter, Pattern, Statement, UnaryOp, VarType,
+};
+use crate::venti_parser::parser::{self, Parser, CAST_BINDING_POWER, PREFIX_BINDING_POWER};
+use std::collections::VecDeque;
+
+/*
+`venti fmt` parses a file and prints its syntax tree back out in one canonical layout:
+
+    fn_venti add(a: int, b: int) {
+        return_venti a + b;
+    }
+
+    venti total = add(1, 2); # 3
+
+Blocks are indented by four spaces and open on the line of the statement they belong to.
+Operators and commas are spaced the same way everywhere, and parentheses are kept only
+where the binding powers of the parser need them, so `(a * b) + c` becomes `a * b + c`.
+Spellings the tree does not record are put back from the source: the comments, which the
+parser keeps as statements, and the digits of number literals, so `1.50` stays `1.50`.
+One blank line between statements is kept, and longer runs shrink to one.
+
+The printer emits the shortest of the spellings the parser accepts for each statement:
+`fn_venti` rather than `funcVenti`, and `x++` for `x = x + 1`. Printing the output again
+gives the same text, which is what `venti fmt --check` relies on.
+*/
+
+/// The width of one level of indentation.
+const INDENT: &str = "    ";
+
+/// The binding power of an expression that never needs parentheses, such as a literal or
+/// a call.
+const ATOM: u8 = u8::MAX;
+
+/// Formats the program `source` in the canonical layout.
+///
+/// # Arguments
+///
+/// * `source` - The text of the program.
+///
+/// # Returns
+///
+/// * `Result<String, VentiError>` - The formatted program, or the error that kept it from
+///   being parsed.
+pub fn format_source(source: &str) -> Result<String, VentiError> {
+    let mut lexer = Lexer::new(source);
+    // The comments are only all known once the whole file is lexed
+    let mut tokens = Vec::new();
+    let mut literals = VecDeque::new();
+    while let Some(token) = lexer.next_token() {
+        let token = token?;
+        if matches!(token, Token::NumberLiteral(_) | Token::FloatLiteral(_)) {
+            literals.push_back(lexer.slice().to_string());
+        }
+        tokens.push(Ok((token, lexer.span())));
+    }
+    let arena = Arena::new();
+    let statements = Parser::new(tokens, &arena)
+        .with_comments(lexer.take_comments())
+        .parse()?;
+
+    let mut printer = Printer {
+        source_lines: source.lines().collect(),
+        literals,
+        output: String::new(),
+        indent: 0,
+        in_impl: false,
+    };
+    printer.statements(&statements);
+    Ok(printer.output)
+}
+
+/// Prints a syntax tree that was not parsed from source, such as one built by a program,
+/// in the canonical layout. Without a source there are no blank lines to keep, and
+/// numbers are spelled the shortest way.
+///
+/// Parsing the output of a tree the parser could have produced gives that tree back, apart
+/// from its spans and `Line` markers.
+pub fn print_statements(statements: &[Statement]) -> String {
+    let mut printer = Printer {
+        source_lines: Vec::new(),
+        literals: VecDeque::new(),
+        output: String::new(),
+        indent: 0,
+        in_impl: false,
+    };
+    printer.statements(statements);
+    printer.output
+}
+
+/// Prints a syntax tree as formatted source.
+struct Printer<'a> {
+    // Whether the functions printed are the methods of an `impl_venti` block, whose
+    // `self` is printed without the type the parser gives it
+    in_impl: bool,
+    // The lines of the source, to find the blank lines between statements
+    source_lines: Vec<&'a str>,
+    // The spellings of the number literals not printed yet, in source order
+    literals: VecDeque<String>,
+    output: String,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    /// Writes `text` as a line at the current indentation.
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.output.push_str(INDENT);
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    /// Whether the source line before `line` is blank.
+    fn follows_blank(&self, line: u32) -> bool {
+        line >= 2
+            && self
+                .source_lines
+                .get(line as usize - 2)
+                .is_some_and(|text| text.trim().is_empty())
+    }
+
+    /// Prints the statements of a file or a block, keeping single blank lines between
+    /// them.
+    fn statements(&mut self, statements: &[Statement]) {
+        let mut first = true;
+        let mut blank = false;
+        for statement in statements {
+            match statement {
+                Statement::Line(span) => blank = !first && self.follows_blank(span.line),
+                Statement::Comment {
+                    text,
+                    trailing: true,
+                } => {
+                    // Moves the comment onto the end of the line printed last
+                    self.output.pop();
+                    self.output.push_str(" #");
+                    self.output.push_str(text);
+                    self.output.push('\n');
+                }
+                statement => {
+                    if blank {
+                        self.output.push('\n');
+                        blank = false;
+                    }
+                    self.statement(statement);
+                    first = false;
+                }
+            }
+        }
+    }
+
+    /// Prints `header` followed by a braced block of `body`.
+    fn block(&mut self, header: &str, body: &[Statement]) {
+        if body
+            .iter()
+            .all(|statement| matches!(statement, Statement::Line(_)))
+        {
+            self.line(&format!("{} {{}}", header));
+            return;
+        }
+        self.line(&format!("{} {{", header));
+        self.indent += 1;
+        self.statements(body);
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::VariableDeclaration { identifier, value } => {
+                let value = self.expr(value, 0);
+                self.line(&format!("venti {} = {};", identifier, value));
+            }
+            Statement::VariableAssignment { identifier, value } => match &value.kind {
+                // The parser reads `x++` as `x = x + 1`
+                ExprKind::BinaryOp(left, op @ (BinOp::Add | BinOp::Subtract), right)
+                    if matches!(&left.kind, ExprKind::Identifier(name) if name == identifier)
+                        && matches!(right.kind, ExprKind::Number(1)) =>
+                {
+                    let step = if *op == BinOp::Add { "++" } else { "--" };
+                    self.line(&format!("{}{};", identifier, step));
+                }
+                _ => {
+                    let value = self.expr(value, 0);
+                    self.line(&format!("{} = {};", identifier, value));
+                }
+            },
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => {
+                let target = self.expr(target, ATOM);
+                let index = self.expr(index, 0);
+                let value = self.expr(value, 0);
+                self.line(&format!("{}[{}] = {};", target, index, value));
+            }
+            Statement::FunctionCall { identifier, args } => {
+                let args = self.list(args);
+                self.line(&format!("{}({});", identifier, args));
+            }
+            Statement::Print { value, newline } => {
+                let keyword = if *newline {
+                    "printventi"
+                } else {
+                    "printventi_inline"
+                };
+                let value = self.expr(value, 0);
+                self.line(&format!("{}({});", keyword, value));
+            }
+            // A spawn statement ends with its block, without a ';'
+            Statement::Expression(Expr {
+                kind: ExprKind::Spawn(body),
+                ..
+            }) => self.block("spawn_venti", body),
+            Statement::Expression(expr) => {
+                let expr = self.expr(expr, 0);
+                self.line(&format!("{};", expr));
+            }
+            Statement::Function {
+                identifier,
+                params,
+                returns,
+                body,
+                inline,
+            } => self.function(identifier, params, returns.as_ref(), body, *inline, false),
+            Statement::AsyncFunction {
+                identifier,
+                params,
+                body,
+                inline,
+            } => self.function(identifier, params, None, body, *inline, true),
+            Statement::Return(None) => self.line("return_venti;"),
+            Statement::Return(Some(value)) => {
+                let value = self.expr(value, 0);
+                self.line(&format!("return_venti {};", value));
+            }
+            Statement::Match { scrutinee, arms } => self.match_statement(scrutinee, arms),
+            Statement::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                let iterable = self.expr(iterable, 0);
+                self.block(&format!("for_venti {} in {}", variable, iterable), body);
+            }
+            Statement::Test { name, body } => self.block(&format!("test_venti \"{}\"", name), body),
+            Statement::Try {
+                body,
+                error,
+                handler,
+            } => {
+                self.line("try_venti {");
+                self.indent += 1;
+                self.statements(body);
+                self.indent -= 1;
+                self.line(&format!("}} catch_venti ({}) {{", error));
+                self.indent += 1;
+                self.statements(handler);
+                self.indent -= 1;
+                self.line("}");
+            }
+            Statement::Assert(condition) => {
+                let condition = self.expr(condition, 0);
+                self.line(&format!("assert_venti {};", condition));
+            }
+            Statement::Comment { text, .. } => self.line(&format!("#{}", text)),
+            Statement::Import(path) => self.line(&format!("import_venti \"{}\";", path)),
+            Statement::Extern {
+                identifier,
+                params,
+                returns,
+            } => {
+                let returns = returns
+                    .as_ref()
+                    .map_or(String::new(), |var_type| format!(" -> {}", var_type.name()));
+                self.line(&format!(
+                    "extern_venti fn {}({}){};",
+                    identifier,
+                    parameters(params),
+                    returns
+                ));
+            }
+            Statement::Trait {
+                identifier,
+                methods,
+            } => {
+                if methods.is_empty() {
+                    self.line(&format!("trait_venti {} {{}}", identifier));
+                    return;
+                }
+                self.line(&format!("trait_venti {} {{", identifier));
+                self.indent += 1;
+                for method in methods {
+                    let params = match parameters(&method.params) {
+                        params if params.is_empty() => "self".to_string(),
+                        params => format!("self, {}", params),
+                    };
+                    self.line(&format!("fn {}({});", method.name, params));
+                }
+                self.indent -= 1;
+                self.line("}");
+            }
+            Statement::Impl {
+                trait_name,
+                target,
+                methods,
+            } => {
+                self.in_impl = true;
+                self.block(
+                    &format!("impl_venti {} for {}", trait_name, target.name()),
+                    methods,
+                );
+                self.in_impl = false;
+            }
+            Statement::Line(_) => {}
+        }
+    }
+
+    fn function(
+        &mut self,
+        identifier: &str,
+        params: &[Parameter],
+        returns: Option<&VarType>,
+        body: &[Statement],
+        inline: Option<InlineHint>,
+        is_async: bool,
+    ) {
+        // The functions inside a method are not methods
+        let in_impl = std::mem::take(&mut self.in_impl);
+        let mut params = params.to_vec();
+        if in_impl {
+            params[0].var_type = None;
+        }
+        let mut header = String::new();
+        match inline {
+            Some(InlineHint::Inline) => header.push_str("inline_venti "),
+            Some(InlineHint::NoInline) => header.push_str("noinline_venti "),
+            None => {}
+        }
+        if is_async {
+            header.push_str("async ");
+        }
+        header.push_str(&format!("fn_venti {}({})", identifier, parameters(&params)));
+        if let Some(returns) = returns {
+            header.push_str(&format!(" -> {}", returns.name()));
+        }
+        self.block(&header, body);
+        self.in_impl = in_impl;
+    }
+
+    fn match_statement(&mut self, scrutinee: &Expr, arms: &[MatchArm]) {
+        let scrutinee = self.expr(scrutinee, 0);
+        self.line(&format!("match_venti {} {{", scrutinee));
+        self.indent += 1;
+        for arm in arms {
+            let pattern = match &arm.pattern {
+                Pattern::Number(n) if *n < 0 => format!("-{}", self.literal(n.unsigned_abs())),
+                Pattern::Number(n) => self.literal(*n as u64),
+                Pattern::String(s) => format!("\"{}\"", s),
+                Pattern::Wildcard => "_".to_string(),
+            };
+            self.block(&format!("{} =>", pattern), &arm.body);
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    /// Returns the source spelling of the integer literal `value`.
+    fn literal(&mut self, value: u64) -> String {
+        self.spelling(|spelling| spelling.parse::<u64>() == Ok(value))
+            .unwrap_or_else(|| value.to_string())
+    }
+
+    /// Takes the next literal spelling if it is the one `matches` expects. A literal the
+    /// parser made up, such as the `1` of `x++`, has no spelling and leaves the queue as
+    /// it is.
+    fn spelling(&mut self, matches: impl Fn(&str) -> bool) -> Option<String> {
+        let spelling = self.literals.front()?;
+        if !matches(spelling) {
+            return None;
+        }
+        self.literals.pop_front()
+    }
+
+    /// Prints a comma-separated list of expressions.
+    fn list(&mut self, exprs: &[Expr]) -> String {
+        exprs
+            .iter()
+            .map(|expr| self.expr(expr, 0))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Prints `expr`, parenthesized if its binding power is below `min_bp`.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - The expression to print.
+    /// * `min_bp` - The binding power the position `expr` is printed in needs, as the
+    ///   parser would parse that position.
+    ///
+    /// # Returns
+    ///
+    /// The expression as source text.
+    fn expr(&mut self, expr: &Expr, min_bp: u8) -> String {
+        let (text, bp) = match &expr.kind {
+            ExprKind::Number(n) if *n < 0 => (n.to_string(), PREFIX_BINDING_POWER),
+            ExprKind::Number(n) => (self.literal(*n as u64), ATOM),
+            ExprKind::Float(f) => {
+                let value = *f;
+                let text = self
+                    .spelling(|spelling| spelling.parse::<f64>() == Ok(value))
+                    .unwrap_or_else(|| format!("{:?}", value.abs()));
+                if value < 0.0 {
+                    (format!("-{}", text), PREFIX_BINDING_POWER)
+                } else {
+                    (text, ATOM)
+                }
+            }
+            ExprKind::String(s) => (format!("\"{}\"", s), ATOM),
+            ExprKind::Boolean(b) => (b.to_string(), ATOM),
+            ExprKind::Null => ("nothing".to_string(), ATOM),
+            ExprKind::Identifier(name) => (name.clone(), ATOM),
+            ExprKind::BinaryOp(left, op, right) => {
+                let (left_bp, right_bp) = parser::binding_power(*op);
+                let left = self.expr(left, left_bp);
+                let right = self.expr(right, right_bp);
+                (format!("{} {} {}", left, operator(*op), right), left_bp)
+            }
+            ExprKind::UnaryOp(op, operand) => {
+                let symbol = match op {
+                    UnaryOp::Negate => "-",
+                    UnaryOp::Not => "!",
+                };
+                let operand = self.expr(operand, PREFIX_BINDING_POWER);
+                // `--x` would lex as a decrement
+                let separator = if operand.starts_with('-') { " " } else { "" };
+                (
+                    format!("{}{}{}", symbol, separator, operand),
+                    PREFIX_BINDING_POWER,
+                )
+            }
+            ExprKind::Await(operand) => {
+                let operand = self.expr(operand, PREFIX_BINDING_POWER);
+                (format!("await {}", operand), PREFIX_BINDING_POWER)
+            }
+            ExprKind::Async(operand) => {
+                let operand = self.expr(operand, PREFIX_BINDING_POWER);
+                (format!("async {}", operand), PREFIX_BINDING_POWER)
+            }
+            ExprKind::Cast { value, target } => {
+                let value = self.expr(value, CAST_BINDING_POWER);
+                (
+                    format!("{} as {}", value, target.name()),
+                    CAST_BINDING_POWER,
+                )
+            }
+            // A lambda's body and a range's end reach as far right as they can
+            ExprKind::Lambda { params, body } => {
+                let body = self.expr(body, 0);
+                let text = if params.is_empty() {
+                    format!("|| {}", body)
+                } else {
+                    format!("|{}| {}", parameters(params), body)
+                };
+                (text, 0)
+            }
+            ExprKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let start = self.expr(start, 1);
+                let end = self.expr(end, 1);
+                let symbol = if *inclusive { "..=" } else { ".." };
+                (format!("{}{}{}", start, symbol, end), 0)
+            }
+            ExprKind::Call { callee, args } => {
+                let callee = self.expr(callee, ATOM);
+                let args = self.list(args);
+                (format!("{}({})", callee, args), ATOM)
+            }
+            ExprKind::MethodCall {
+                receiver,
+                name,
+                args,
+            } => {
+                let receiver = self.expr(receiver, ATOM);
+                let args = self.list(args);
+                (format!("{}.{}({})", receiver, name, args), ATOM)
+            }
+            ExprKind::Index { target, index } => {
+                let target = self.expr(target, ATOM);
+                let index = self.expr(index, 0);
+                (format!("{}[{}]", target, index), ATOM)
+            }
+            ExprKind::Array(elements) => (format!("[{}]", self.list(elements)), ATOM),
+            ExprKind::Map(entries) if entries.is_empty() => ("{}".to_string(), ATOM),
+            ExprKind::Map(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = self.expr(key, 0);
+                        let value = self.expr(value, 0);
+                        format!("{}: {}", key, value)
+                    })
+                    .collect::<Vec<_>>();
+                (format!("{{ {} }}", entries.join(", ")), ATOM)
+            }
+            ExprKind::Spawn(body) => (self.spawn(body), ATOM),
+        };
+        if bp < min_bp {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    /// Prints a spawn expression, whose block spans lines of its own below the line the
+    /// expression starts on.
+    fn spawn(&mut self, body: &[Statement]) -> String {
+        let enclosing = std::mem::take(&mut self.output);
+        self.block("spawn_venti", body);
+        let block = std::mem::replace(&mut self.output, enclosing);
+        // The block comes back indented as a line of its own, and the caller indents the
+        // line it starts
+        block.trim().to_string()
+    }
+}
+
+/// Prints a parameter list, without the parentheses or bars around it.
+fn parameters(params: &[Parameter]) -> String {
+    params
+        .iter()
+        .map(|param| match &param.var_type {
+            Some(var_type) => format!("{}: {}", param.name, var_type.name()),
+            None => param.name.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn operator(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Subtract => "-",
+        BinOp::Multiply => "*",
+        BinOp::Divide => "/",
+        BinOp::Equal => "==",
+        BinOp::NotEqual => "!=",
+        BinOp::Less => "<",
+        BinOp::LessEqual => "<=",
+        BinOp::Greater => ">",
+        BinOp::GreaterEqual => ">=",
+        BinOp::And => "&&",
+        BinOp::Or => "||",
+        BinOp::BitAnd => "&",
+        BinOp::BitOr => "|",
+        BinOp::BitXor => "^",
+        BinOp::ShiftLeft => "<<",
+        BinOp::ShiftRight => ">>",
+        BinOp::Coalesce => "??",
+    }
+}
@@ -0,0 +1,46 @@
+use crate::semantic::analyzer::Analyzer;
+use crate::venti_lexer::lexer::Lexer;
+use crate::venti_parser::arena::Arena;
+use crate::venti_parser::parser::Parser;
+
+/*
+Entry points for the cargo-fuzz targets in `fuzz/`, built with the `fuzz` feature:
+
+    cargo +nightly fuzz run lex
+    cargo +nightly fuzz run parse
+
+Each takes whatever input the fuzzer generates and runs a part of the front end over it.
+Malformed input must come back as a `VentiError`; a panic, an overflowed stack or a hang
+is a bug, and the fuzzer saves the input that caused it under `fuzz/artifacts/`. The
+targets start from the seeds in `fuzz/corpus/`, which are copies of the programs in
+`examples/`.
+
+Beyond not panicking, the lexer is held to the spans it reports: each lies within the
+input, on character boundaries, after the span of the token before it.
+*/
+
+/// Lexes `data` to the end, if it is UTF-8, and checks the span of every token.
+pub fn fuzz_lex(data: &[u8]) {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    let mut previous_end = 0;
+    for token in Lexer::new(source) {
+        let Ok((_, span)) = token else {
+            continue;
+        };
+        assert!(span.start <= span.end && span.end <= source.len());
+        assert!(source.is_char_boundary(span.start) && source.is_char_boundary(span.end));
+        assert!(span.start >= previous_end);
+        assert!(span.line >= 1 && span.column >= 1);
+        previous_end = span.end;
+    }
+}
+
+/// Parses `source` and, if it parses, checks it.
+pub fn fuzz_parse(source: &str) {
+    let arena = Arena::new();
+    if let Ok(ast) = Parser::new(Lexer::new(source), &arena).parse() {
+        let _ = Analyzer::new().analyze(&ast);
+    }
+}
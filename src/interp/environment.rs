@@ -0,0 +1,113 @@
+use crate::interp::value::Value;
+use std::collections::HashMap;
+
+/// The variables visible to the code being interpreted, as a chain of scopes.
+///
+/// The first scope holds the top-level variables, which stay visible inside function
+/// bodies; every other scope belongs to a function, lambda or block and disappears when it
+/// ends. This is the same layout the analyzer uses for types.
+pub struct Environment<'a> {
+    scopes: Vec<HashMap<String, Value<'a>>>,
+}
+
+impl<'a> Environment<'a> {
+    /// Creates an `Environment` with an empty global scope.
+    pub fn new() -> Self {
+        Environment {
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    /// Returns true if `name` is bound outside the global scope.
+    pub fn is_local(&self, name: &str) -> bool {
+        self.scopes[1..]
+            .iter()
+            .any(|scope| scope.contains_key(name))
+    }
+
+    /// Opens a block scope that starts out with `bindings`.
+    pub fn push_scope(&mut self, bindings: Vec<(String, Value<'a>)>) {
+        self.scopes.push(bindings.into_iter().collect());
+    }
+
+    /// Closes the innermost block scope.
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// Enters a function body, where only the top-level variables and `bindings` are
+    /// visible.
+    ///
+    /// # Returns
+    ///
+    /// The caller's scopes, to be handed back to `leave_function`.
+    pub fn enter_function(
+        &mut self,
+        bindings: Vec<(String, Value<'a>)>,
+    ) -> Vec<HashMap<String, Value<'a>>> {
+        let caller = self.scopes.split_off(1);
+        self.push_scope(bindings);
+        caller
+    }
+
+    /// Leaves a function body, restoring the scopes `enter_function` returned.
+    pub fn leave_function(&mut self, caller: Vec<HashMap<String, Value<'a>>>) {
+        self.scopes.truncate(1);
+        self.scopes.extend(caller);
+    }
+
+    /// Copies every local variable, innermost binding first, for a lambda or spawned
+    /// block that captures them by value.
+    pub fn locals(&self) -> Vec<(String, Value<'a>)> {
+        let mut captured: HashMap<&str, &Value<'a>> = HashMap::new();
+        for scope in &self.scopes[1..] {
+            for (name, value) in scope {
+                captured.insert(name, value);
+            }
+        }
+        captured
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    /// Binds `name` in the innermost scope, shadowing any earlier binding.
+    pub fn define(&mut self, name: &str, value: Value<'a>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), value);
+        }
+    }
+
+    /// Finds the value of the innermost variable called `name`.
+    pub fn lookup(&self, name: &str) -> Option<&Value<'a>> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Stores `value` in the innermost variable called `name`.
+    ///
+    /// # Returns
+    ///
+    /// `false` if there is no such variable.
+    pub fn assign(&mut self, name: &str, value: Value<'a>) -> bool {
+        match self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+        {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for Environment<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
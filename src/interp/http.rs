@@ -0,0 +1,91 @@
+use crate::errors::VentiError;
+use crate::interp::net;
+use async_std::io::{ReadExt, WriteExt};
+use async_std::task;
+
+/*
+`http_get` and `http_post` speak HTTP/1.0 over a plain TCP connection, like the LLVM and C
+runtimes: the request asks the server to close the connection after its response, so the
+response is everything read until then, and its body is returned whatever the status. Only
+`http://` URLs can be requested.
+
+The connection is made with async-std. The interpreter runs async calls to completion when
+they are made, so each request is blocked on until it is done.
+*/
+
+/// The parts of an `http://` URL a request needs.
+struct Target<'u> {
+    /// The host and port, as the URL writes them.
+    authority: &'u str,
+    host: &'u str,
+    port: i64,
+    path: &'u str,
+}
+
+/// Returns a runtime error with `message`.
+fn failure(message: String) -> VentiError {
+    VentiError::RuntimeError(message, None)
+}
+
+/// Splits `url` into the parts a request needs.
+///
+/// # Returns
+///
+/// The parts, or a runtime error if `url` is not an `http://` URL.
+fn target(url: &str) -> Result<Target<'_>, VentiError> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| failure(format!("only http:// URLs can be requested, not '{}'", url)))?;
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let (host, port) = match authority.split_once(':') {
+        // Like `strtoll`, the port is read from the digits it starts with
+        Some((host, port)) => {
+            let digits = port.bytes().take_while(u8::is_ascii_digit).count();
+            (host, port[..digits].parse().unwrap_or(0))
+        }
+        None => (authority, 80),
+    };
+    let path = if path.is_empty() { "/" } else { path };
+    Ok(Target {
+        authority,
+        host,
+        port,
+        path,
+    })
+}
+
+/// Sends an HTTP request with `method` and `body` to `url`.
+///
+/// # Returns
+///
+/// The body of the response, or a runtime error if the request could not be made or the
+/// response is not HTTP.
+pub fn request(method: &str, url: &str, body: &str) -> Result<String, VentiError> {
+    let target = target(url)?;
+    let header = format!(
+        "{} {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: venti\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        method,
+        target.path,
+        target.authority,
+        body.len()
+    );
+    let response = task::block_on(async {
+        let mut stream = net::connect(target.host, target.port).await?;
+        let sent = match stream.write_all(header.as_bytes()).await {
+            Ok(()) => stream.write_all(body.as_bytes()).await,
+            error => error,
+        };
+        sent.map_err(|_| failure("could not send: the connection is closed".to_string()))?;
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .await
+            .map_err(|_| failure("could not receive: the connection failed".to_string()))?;
+        Ok::<_, VentiError>(response)
+    })?;
+    let headers_len = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| failure(format!("invalid HTTP response from '{}'", url)))?;
+    Ok(String::from_utf8_lossy(&response[headers_len + 4..]).into_owned())
+}
@@ -0,0 +1,1909 @@
+use crate::codegen::builtins;
+use crate::codes::ErrorCode;
+use crate::errors::VentiError;
+use crate::interp::environment::Environment;
+use crate::interp::http;
+use crate::interp::json;
+use crate::interp::net::Sockets;
+use crate::interp::random::Random;
+use crate::interp::value::{Closure, Iter, Value};
+use crate::traits::{self, Traits};
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::ast::{
+    self, BinOp, Expr, ExprKind, MatchArm, Parameter, Pattern, Statement, UnaryOp, VarType,
+};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::io::{self, Write};
+use std::process::{self, Command, ExitStatus, Stdio};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/*
+The interpreter runs a checked program by walking its AST, as an alternative to compiling
+it with LLVM. It follows the semantics of the generated code: integers wrap, comparisons
+yield integers, functions return integers, lambdas and spawned blocks capture the locals
+they see by value, and maps and buffers are shared by every copy.
+
+A value passed for a trait parameter is the value itself, and a method called on it runs
+the implementation for the type the value has, so trait objects need no tables here.
+
+Async calls and spawned blocks run to completion as soon as they start, so `await` and
+`join` only hand back the result. That is one of the orders a threaded run may take.
+Where the generated code has undefined behaviour, such as dividing by zero or indexing an
+array past its end, the interpreter reports a runtime error instead.
+*/
+
+/// A function defined by the program.
+struct Function<'a> {
+    params: &'a [Parameter],
+    body: &'a [Statement<'a>],
+    is_async: bool,
+    returns: Option<&'a VarType>,
+}
+
+/// Evaluates a program directly from its AST.
+pub struct Interpreter<'a> {
+    env: Environment<'a>,
+    // The functions by name, the methods of implementations by the name `traits` gives them
+    functions: HashMap<String, Function<'a>>,
+    traits: Traits,
+    // The C functions the program declares, which only compiled code can call
+    externs: HashSet<&'a str>,
+    // The span of the statement being run, empty when spans are not recorded
+    span: Span,
+    // The name of the function being run, which a panic reports
+    function: String,
+    // The generator `random`, `random_int` and `seed_random` draw from
+    random: Random,
+    // When the interpreter started, which the readings `timer` returns count from
+    started: Instant,
+    // The program's arguments, without its name
+    args: Vec<String>,
+    // The exit status of the last command `exec` ran, which `exec_status` returns
+    exec_status: i64,
+    // The sockets the TCP builtins opened
+    sockets: Sockets,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Creates a new `Interpreter` with no variables or functions defined.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Interpreter`.
+    pub fn new() -> Self {
+        Interpreter {
+            env: Environment::new(),
+            functions: HashMap::new(),
+            traits: Traits::new(),
+            externs: HashSet::new(),
+            span: Span::default(),
+            function: "main".to_string(),
+            random: Random::default(),
+            started: Instant::now(),
+            args: Vec::new(),
+            exec_status: 0,
+            sockets: Sockets::default(),
+        }
+    }
+
+    /// Gives the program the command-line arguments `args`, which `arg` returns.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Runs a whole program.
+    ///
+    /// Every function is declared before the program starts, so a function can be called
+    /// before its definition just like in compiled code.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The statements of the program, as checked by the analyzer.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<i32, VentiError>` - The program's exit code, or the runtime error that stopped it.
+    pub fn run(&mut self, statements: &'a [Statement<'a>]) -> Result<i32, VentiError> {
+        self.declare_functions(statements);
+        // An error propagates as soon as it is raised, so the span is the statement at fault
+        // when the error has no span of its own
+        let result = self
+            .execute_program(statements)
+            .map_err(|error| error.at(self.span));
+        let _ = io::stdout().flush();
+        // A top-level `return_venti` is the exit code, narrowed like `main`'s result
+        Ok(result?.and_then(|code| code.as_int()).unwrap_or(0) as i32)
+    }
+
+    /// Executes the top-level statements of a program.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Value<'a>>, VentiError>` - The exit code, if the program returned one or
+    ///   ends in an integer expression.
+    fn execute_program(
+        &mut self,
+        statements: &'a [Statement<'a>],
+    ) -> Result<Option<Value<'a>>, VentiError> {
+        let exit = ast::exit_expression(statements);
+        for (index, statement) in statements.iter().enumerate() {
+            match statement {
+                Statement::Expression(expr) if Some(index) == exit => {
+                    if let code @ Value::Int(_) = self.evaluate(expr)? {
+                        return Ok(Some(code));
+                    }
+                }
+                statement => {
+                    if let Some(value) = self.execute(statement)? {
+                        return Ok(Some(value));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Records every function defined in `statements`, including those nested in other
+    /// bodies.
+    fn declare_functions(&mut self, statements: &'a [Statement<'a>]) {
+        for statement in statements {
+            match statement {
+                Statement::Function {
+                    identifier,
+                    params,
+                    body,
+                    returns,
+                    ..
+                } => {
+                    self.functions.insert(
+                        identifier.clone(),
+                        Function {
+                            params,
+                            body,
+                            is_async: false,
+                            returns: returns.as_ref(),
+                        },
+                    );
+                    self.declare_functions(body);
+                }
+                Statement::AsyncFunction {
+                    identifier,
+                    params,
+                    body,
+                    ..
+                } => {
+                    self.functions.insert(
+                        identifier.clone(),
+                        Function {
+                            params,
+                            body,
+                            is_async: true,
+                            returns: None,
+                        },
+                    );
+                    self.declare_functions(body);
+                }
+                Statement::Extern { identifier, .. } => {
+                    self.externs.insert(identifier);
+                }
+                Statement::For { body, .. } => self.declare_functions(body),
+                Statement::Try { body, handler, .. } => {
+                    self.declare_functions(body);
+                    self.declare_functions(handler);
+                }
+                Statement::Match { arms, .. } => {
+                    for arm in arms {
+                        self.declare_functions(&arm.body);
+                    }
+                }
+                Statement::Trait { .. } => self.traits.declare(statement),
+                Statement::Impl {
+                    trait_name,
+                    target,
+                    methods,
+                } => {
+                    for method in methods {
+                        if let Statement::Function {
+                            identifier,
+                            params,
+                            body,
+                            ..
+                        } = method
+                        {
+                            let function =
+                                traits::method_function(trait_name, target.name(), identifier);
+                            self.functions.insert(
+                                function,
+                                Function {
+                                    params,
+                                    body,
+                                    is_async: false,
+                                    returns: None,
+                                },
+                            );
+                            self.declare_functions(body);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Executes statements in order until one of them returns.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Value<'a>>, VentiError>` - The value returned, if a `return_venti` ran.
+    fn execute_block(
+        &mut self,
+        statements: &'a [Statement<'a>],
+    ) -> Result<Option<Value<'a>>, VentiError> {
+        for statement in statements {
+            if let Some(value) = self.execute(statement)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Executes `body` in a new scope that starts out with `bindings`.
+    fn execute_scoped(
+        &mut self,
+        body: &'a [Statement<'a>],
+        bindings: Vec<(String, Value<'a>)>,
+    ) -> Result<Option<Value<'a>>, VentiError> {
+        self.env.push_scope(bindings);
+        let result = self.execute_block(body);
+        self.env.pop_scope();
+        result
+    }
+
+    /// Executes one statement.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Value<'a>>, VentiError>` - The value returned, if the statement returned.
+    fn execute(&mut self, statement: &'a Statement<'a>) -> Result<Option<Value<'a>>, VentiError> {
+        match statement {
+            Statement::VariableDeclaration { identifier, value } => {
+                let value = self.evaluate(value)?;
+                self.env.define(identifier, value);
+            }
+            Statement::VariableAssignment { identifier, value } => {
+                let value = self.evaluate(value)?;
+                if !self.env.assign(identifier, value) {
+                    return Err(VentiError::RuntimeError(
+                        format!("Undefined variable '{}'", identifier),
+                        None,
+                    )
+                    .with_code(ErrorCode::UndefinedVariable));
+                }
+            }
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => {
+                let target = self.evaluate(target)?;
+                let index = self.evaluate(index)?;
+                let value = self.slot_value(value)?;
+                match target {
+                    Value::Map(map) => {
+                        map.borrow_mut().insert(map_key(&index)?, value);
+                    }
+                    Value::Buffer(buffer) => {
+                        let mut buffer = buffer.borrow_mut();
+                        let slot = slot_index(&index, buffer.len(), "buffer")?;
+                        buffer[slot] = value;
+                    }
+                    Value::Vector(vector) => {
+                        let mut vector = vector.borrow_mut();
+                        let slot = slot_index(&index, vector.len(), "vector")?;
+                        vector[slot] = value;
+                    }
+                    _ => return Err(VentiError::RuntimeError(
+                        "Only map entries, buffer slots and vector elements can be assigned; arrays are immutable"
+                            .to_string(),
+                        None,
+                    )),
+                }
+            }
+            Statement::FunctionCall { identifier, args } => {
+                self.call(identifier, args)?;
+            }
+            Statement::Print { value, newline } => {
+                let value = self.evaluate(value)?;
+                if *newline {
+                    println!("{}", value);
+                } else {
+                    print!("{}", value);
+                }
+            }
+            Statement::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            // Functions are declared before the program runs
+            Statement::Function { .. } | Statement::AsyncFunction { .. } => {}
+            Statement::Return(value) => {
+                let value = match value {
+                    Some(value) => match self.evaluate(value)? {
+                        vector @ Value::Vector(_) => vector,
+                        value => Value::Int(value.as_int().ok_or_else(|| {
+                            VentiError::RuntimeError(
+                                format!(
+                                    "Functions can only return integers and vectors, found {}",
+                                    value.type_name()
+                                ),
+                                None,
+                            )
+                        })?),
+                    },
+                    None => Value::Int(0),
+                };
+                return Ok(Some(value));
+            }
+            Statement::Match { scrutinee, arms } => return self.execute_match(scrutinee, arms),
+            Statement::For {
+                variable,
+                iterable,
+                body,
+            } => return self.execute_for(variable, iterable, body),
+            Statement::Try {
+                body,
+                error,
+                handler,
+            } => {
+                // Whatever scopes the body was in when it failed have been left already
+                return match self.execute_scoped(body, Vec::new()) {
+                    Err(VentiError::RuntimeError(message, _)) => {
+                        let binding = vec![(error.clone(), Value::Str(Some(message.into())))];
+                        self.execute_scoped(handler, binding)
+                    }
+                    result => result,
+                };
+            }
+            Statement::Assert(condition) => {
+                let value = self.evaluate(condition)?;
+                if value.as_int() == Some(0) {
+                    println!("{}", assertion_failure(self.span.line));
+                }
+            }
+            // Tests only run under `venti test`
+            Statement::Test { .. }
+            | Statement::Extern { .. }
+            | Statement::Trait { .. }
+            | Statement::Impl { .. }
+            | Statement::Comment { .. }
+            | Statement::Import(_) => {}
+            Statement::Line(span) => self.span = *span,
+        }
+        Ok(None)
+    }
+
+    /// Runs the first arm of a match whose pattern equals the scrutinee, if any.
+    fn execute_match(
+        &mut self,
+        scrutinee: &'a Expr<'a>,
+        arms: &'a [MatchArm<'a>],
+    ) -> Result<Option<Value<'a>>, VentiError> {
+        if let Some(position) = arms
+            .iter()
+            .position(|arm| matches!(arm.pattern, Pattern::Wildcard))
+        {
+            if position != arms.len() - 1 {
+                return Err(VentiError::RuntimeError(
+                    "The '_' arm must be the last arm of a match".to_string(),
+                    None,
+                ));
+            }
+        }
+
+        let scrutinee = self.evaluate(scrutinee)?;
+        for arm in arms {
+            let is_match = match (&arm.pattern, &scrutinee) {
+                (Pattern::Wildcard, _) => true,
+                (Pattern::Number(n), Value::Int(_) | Value::Bool(_)) => {
+                    scrutinee.as_int() == Some(*n)
+                }
+                (Pattern::String(pattern), Value::Str(text)) => {
+                    text.as_deref() == Some(pattern.as_str())
+                }
+                _ => {
+                    return Err(VentiError::RuntimeError(
+                        format!(
+                            "Cannot match a value of type {} against this pattern",
+                            scrutinee.type_name()
+                        ),
+                        None,
+                    ))
+                }
+            };
+            if is_match {
+                return self.execute_scoped(&arm.body, Vec::new());
+            }
+        }
+        Ok(None)
+    }
+
+    /// Runs `for_venti variable in iterable { ... }`.
+    ///
+    /// The loop variable is bound in a fresh scope on every iteration, so assigning to it
+    /// does not change which values the loop visits.
+    fn execute_for(
+        &mut self,
+        variable: &str,
+        iterable: &'a Expr<'a>,
+        body: &'a [Statement<'a>],
+    ) -> Result<Option<Value<'a>>, VentiError> {
+        let (start, end, step) = match self.evaluate(iterable)? {
+            Value::Int(count) => (0, count, 1),
+            Value::Range { start, end, step } => (start, end, step),
+            Value::Array(elements) => {
+                for element in elements.iter() {
+                    let binding = vec![(variable.to_string(), element.clone())];
+                    if let Some(value) = self.execute_scoped(body, binding)? {
+                        return Ok(Some(value));
+                    }
+                }
+                return Ok(None);
+            }
+            Value::Buffer(buffer) => {
+                // The length is read once, but every element when its iteration starts
+                let len = buffer.borrow().len();
+                for index in 0..len {
+                    let element = buffer.borrow().get(index).copied().ok_or_else(|| {
+                        out_of_bounds(index as i64, buffer.borrow().len(), "buffer")
+                    })?;
+                    let binding = vec![(variable.to_string(), Value::Int(element))];
+                    if let Some(value) = self.execute_scoped(body, binding)? {
+                        return Ok(Some(value));
+                    }
+                }
+                return Ok(None);
+            }
+            // A vector is looped over through a copy, so the body may push to it
+            Value::Vector(vector) => {
+                let elements = vector.borrow().clone();
+                for element in elements {
+                    let binding = vec![(variable.to_string(), Value::Int(element))];
+                    if let Some(value) = self.execute_scoped(body, binding)? {
+                        return Ok(Some(value));
+                    }
+                }
+                return Ok(None);
+            }
+            Value::Iterator(iterator) => {
+                while let Some(element) = self.next_value(&iterator)? {
+                    let binding = vec![(variable.to_string(), Value::Int(element))];
+                    if let Some(value) = self.execute_scoped(body, binding)? {
+                        return Ok(Some(value));
+                    }
+                }
+                return Ok(None);
+            }
+            Value::StringVector(pieces) => {
+                for piece in pieces.iter() {
+                    let binding = vec![(variable.to_string(), Value::Str(Some(piece.clone())))];
+                    if let Some(value) = self.execute_scoped(body, binding)? {
+                        return Ok(Some(value));
+                    }
+                }
+                return Ok(None);
+            }
+            found => {
+                return Err(VentiError::RuntimeError(
+                    format!(
+                "for_venti can only iterate over ranges, integers, arrays, buffers, vectors and iterators, found {}",
+                found.type_name()
+            ),
+                    None,
+                ))
+            }
+        };
+        let mut current = start;
+        while current < end {
+            let binding = vec![(variable.to_string(), Value::Int(current))];
+            if let Some(value) = self.execute_scoped(body, binding)? {
+                return Ok(Some(value));
+            }
+            current = current.wrapping_add(step);
+        }
+        Ok(None)
+    }
+
+    /// Calls the function or builtin `identifier`.
+    fn call(&mut self, identifier: &str, args: &'a [Expr<'a>]) -> Result<Value<'a>, VentiError> {
+        let Some(function) = self.functions.get(identifier) else {
+            if self.externs.contains(identifier) {
+                return Err(VentiError::RuntimeError(
+                    format!(
+                        "C function '{}' can only be called from compiled programs",
+                        identifier
+                    ),
+                    None,
+                ));
+            }
+            if builtins::is_builtin(identifier) {
+                return self.call_builtin(identifier, args);
+            }
+            return Err(VentiError::RuntimeError(
+                format!("Undefined function '{}'", identifier),
+                None,
+            )
+            .with_code(ErrorCode::UndefinedFunction));
+        };
+        let (params, body, is_async, returns) = (
+            function.params,
+            function.body,
+            function.is_async,
+            function.returns,
+        );
+        if params.len() != args.len() {
+            return Err(VentiError::RuntimeError(
+                format!(
+                    "Function '{}' expects {} arguments but {} were given",
+                    identifier,
+                    params.len(),
+                    args.len()
+                ),
+                None,
+            ));
+        }
+        let mut bindings = Vec::new();
+        for (param, arg) in params.iter().zip(args) {
+            bindings.push((param.name.clone(), self.evaluate(arg)?));
+        }
+        let result = self.execute_function(identifier, body, bindings)?;
+        Ok(match result {
+            result if is_async => Value::Task(result.as_int().unwrap_or(0)),
+            // A function returning vec that returns nothing returns an empty vector
+            Value::Vector(_) => result,
+            _ if returns == Some(&VarType::Vec) => Value::Vector(Rc::new(RefCell::new(Vec::new()))),
+            result => result,
+        })
+    }
+
+    /// Runs the body of the function `name`, which sees the top-level variables and
+    /// `bindings`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Value<'a>, VentiError>` - The value returned; falling off the end returns 0.
+    fn execute_function(
+        &mut self,
+        name: &str,
+        body: &'a [Statement<'a>],
+        bindings: Vec<(String, Value<'a>)>,
+    ) -> Result<Value<'a>, VentiError> {
+        let caller = self.env.enter_function(bindings);
+        let calling = std::mem::replace(&mut self.function, name.to_string());
+        let result = self.execute_block(body);
+        self.function = calling;
+        self.env.leave_function(caller);
+        Ok(result?.unwrap_or(Value::Int(0)))
+    }
+
+    /// Calls one of the built-in functions.
+    fn call_builtin(
+        &mut self,
+        identifier: &str,
+        args: &'a [Expr<'a>],
+    ) -> Result<Value<'a>, VentiError> {
+        if builtins::arity(identifier) != Some(args.len()) {
+            return Err(VentiError::RuntimeError(
+                format!(
+                    "Builtin '{}' expects {:?} argument(s) but {} were given",
+                    identifier,
+                    builtins::arity(identifier),
+                    args.len()
+                ),
+                None,
+            ));
+        }
+        match identifier {
+            "len" => self.call_method(&args[0], "len", &[]),
+            "join" => {
+                let task = self.evaluate(&args[0])?;
+                await_task(task)
+            }
+            "alloc" => {
+                let len = self.evaluate(&args[0])?;
+                match len {
+                    Value::Int(len) if len >= 0 => {
+                        Ok(Value::Buffer(Rc::new(RefCell::new(vec![0; len as usize]))))
+                    }
+                    len => Err(VentiError::RuntimeError(
+                        format!("could not allocate a buffer of {} slots", len),
+                        None,
+                    )),
+                }
+            }
+            "free" => {
+                let Value::Buffer(buffer) = self.evaluate(&args[0])? else {
+                    return Err(VentiError::RuntimeError(
+                        "'free' expects a buffer".to_string(),
+                        None,
+                    ));
+                };
+                buffer.borrow_mut().clear();
+                // The variable holding the buffer is reset to the empty buffer
+                if let ExprKind::Identifier(name) = &args[0].kind {
+                    self.env
+                        .assign(name, Value::Buffer(Rc::new(RefCell::new(Vec::new()))));
+                }
+                Ok(Value::Int(0))
+            }
+            "byte_at" => {
+                let string = self.evaluate(&args[0])?;
+                let index = self.evaluate(&args[1])?;
+                let (Value::Str(text), Value::Int(index)) = (string, index) else {
+                    return Err(VentiError::RuntimeError(
+                        "'byte_at' expects a string and an integer index".to_string(),
+                        None,
+                    ));
+                };
+                let byte = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| text.as_deref()?.as_bytes().get(index).copied());
+                Ok(Value::Int(byte.map_or(-1, i64::from)))
+            }
+            "panic_venti" => {
+                let Value::Str(message) = self.evaluate(&args[0])? else {
+                    return Err(VentiError::RuntimeError(
+                        "'panic_venti' expects a string message".to_string(),
+                        None,
+                    ));
+                };
+                // The code keeps `try_venti` from catching it
+                Err(VentiError::RuntimeError(
+                    format!(
+                        "Panic in {}: {}",
+                        self.function,
+                        message.as_deref().unwrap_or_default()
+                    ),
+                    None,
+                )
+                .with_code(ErrorCode::Panic))
+            }
+            "abs" => match self.evaluate(&args[0])? {
+                Value::Int(value) => Ok(Value::Int(value.wrapping_abs())),
+                value => Ok(Value::Float(number(&value, identifier)?.abs())),
+            },
+            "min" | "max" => {
+                let left = self.evaluate(&args[0])?;
+                let right = self.evaluate(&args[1])?;
+                if let (Value::Int(left), Value::Int(right)) = (&left, &right) {
+                    let value = if identifier == "min" {
+                        left.min(right)
+                    } else {
+                        left.max(right)
+                    };
+                    return Ok(Value::Int(*value));
+                }
+                let (left, right) = (number(&left, identifier)?, number(&right, identifier)?);
+                let value = if identifier == "min" {
+                    left.min(right)
+                } else {
+                    left.max(right)
+                };
+                Ok(Value::Float(value))
+            }
+            "sqrt" | "floor" | "ceil" | "sin" | "cos" => {
+                let value = number(&self.evaluate(&args[0])?, identifier)?;
+                Ok(Value::Float(match identifier {
+                    "sqrt" => value.sqrt(),
+                    "floor" => value.floor(),
+                    "ceil" => value.ceil(),
+                    "sin" => value.sin(),
+                    _ => value.cos(),
+                }))
+            }
+            "pow" => {
+                let base = number(&self.evaluate(&args[0])?, identifier)?;
+                let exponent = number(&self.evaluate(&args[1])?, identifier)?;
+                Ok(Value::Float(base.powf(exponent)))
+            }
+            "random" => Ok(Value::Float(self.random.float())),
+            "random_int" => {
+                let low = self.int_arg(&args[0], identifier)?;
+                let high = self.int_arg(&args[1], identifier)?;
+                self.random.int(low, high).map(Value::Int).ok_or_else(|| {
+                    VentiError::RuntimeError(
+                        format!("random_int needs a range, but {} is above {}", low, high),
+                        None,
+                    )
+                })
+            }
+            "seed_random" => {
+                let seed = self.int_arg(&args[0], identifier)?;
+                self.random.seed(seed);
+                Ok(Value::Int(0))
+            }
+            "exit_venti" => {
+                let code = self.int_arg(&args[0], identifier)?;
+                // Compiled programs exit through libc, which flushes what they printed
+                let _ = io::stdout().flush();
+                process::exit(code as i32)
+            }
+            "args" => {
+                let args = self.args.iter().map(|arg| Rc::from(arg.as_str())).collect();
+                Ok(Value::StringVector(Rc::new(args)))
+            }
+            "env" => {
+                let name = self.text_arg(&args[0], identifier)?;
+                // No variable has such a name, and `var_os` may panic when asked for one
+                let value = if name.is_empty() || name.contains(['=', '\0']) {
+                    None
+                } else {
+                    env::var_os(name).map(|value| value.to_string_lossy().into_owned())
+                };
+                Ok(value.map_or(Value::Str(None), |value| string_value(&value)))
+            }
+            "now_millis" => {
+                let millis = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_millis());
+                Ok(Value::Int(millis as i64))
+            }
+            "sleep_ms" => {
+                let millis = self.int_arg(&args[0], identifier)?;
+                thread::sleep(Duration::from_millis(millis.max(0) as u64));
+                Ok(Value::Int(0))
+            }
+            "timer" => Ok(Value::Int(self.started.elapsed().as_nanos() as i64)),
+            "elapsed" => {
+                let start = self.int_arg(&args[0], identifier)?;
+                let now = self.started.elapsed().as_nanos() as i64;
+                Ok(Value::Float(now.wrapping_sub(start) as f64 / 1e6))
+            }
+            "input_venti" => {
+                let prompt = self.text_arg(&args[0], identifier)?;
+                print!("{}", prompt);
+                let _ = io::stdout().flush();
+                let mut line = String::new();
+                // End of input gives `nothing`, so a loop reading lines can stop
+                match io::stdin().read_line(&mut line) {
+                    Ok(0) | Err(_) => Ok(Value::Str(None)),
+                    Ok(_) => {
+                        let line = line.strip_suffix('\n').unwrap_or(&line);
+                        Ok(string_value(line.strip_suffix('\r').unwrap_or(line)))
+                    }
+                }
+            }
+            "exec" => {
+                let command = self.text_arg(&args[0], identifier)?;
+                // What the program printed so far comes before anything the command writes
+                let _ = io::stdout().flush();
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(&command)
+                    .stdin(Stdio::inherit())
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .map_err(|_| {
+                        VentiError::RuntimeError(
+                            format!("could not run command '{}'", command),
+                            None,
+                        )
+                    })?;
+                self.exec_status = exit_code(output.status);
+                Ok(string_value(&String::from_utf8_lossy(&output.stdout)))
+            }
+            "exec_status" => Ok(Value::Int(self.exec_status)),
+            "http_get" | "http_post" => {
+                let url = self.text_arg(&args[0], identifier)?;
+                let (method, body) = match args.get(1) {
+                    Some(body) => ("POST", self.text_arg(body, identifier)?),
+                    None => ("GET", String::new()),
+                };
+                Ok(string_value(&http::request(method, &url, &body)?))
+            }
+            "channel" => Ok(Value::Channel(Rc::new(RefCell::new(VecDeque::new())))),
+            "vec" => Ok(Value::Vector(Rc::new(RefCell::new(Vec::new())))),
+            "send" => {
+                let channel = self.channel_arg(&args[0], identifier)?;
+                let value = self.evaluate(&args[1])?;
+                channel.borrow_mut().push_back(value);
+                Ok(Value::Int(0))
+            }
+            "recv" => {
+                let channel = self.channel_arg(&args[0], identifier)?;
+                // Tasks have all run by the time they are started, so nothing can send to an
+                // empty channel any more and waiting on it would never end
+                let value = channel.borrow_mut().pop_front();
+                value.ok_or_else(|| {
+                    VentiError::RuntimeError(
+                        "could not receive: the channel is empty and no task can send to it"
+                            .to_string(),
+                        None,
+                    )
+                })
+            }
+            "map" | "filter" => {
+                let source = self.iterator_arg(&args[0], identifier)?;
+                let function = self.closure_arg(&args[1], identifier)?;
+                let iterator = if identifier == "map" {
+                    Iter::Map { source, function }
+                } else {
+                    Iter::Filter { source, function }
+                };
+                Ok(Value::Iterator(Rc::new(RefCell::new(iterator))))
+            }
+            "reduce" => {
+                let iterator = self.iterator_arg(&args[0], identifier)?;
+                let mut acc = self.int_arg(&args[1], identifier)?;
+                let function = self.closure_arg(&args[2], identifier)?;
+                while let Some(value) = self.next_value(&iterator)? {
+                    acc = self.apply_int_closure(&function, &[acc, value])?;
+                }
+                Ok(Value::Int(acc))
+            }
+            "collect" => {
+                let iterator = self.iterator_arg(&args[0], identifier)?;
+                let mut values = Vec::new();
+                while let Some(value) = self.next_value(&iterator)? {
+                    values.push(value);
+                }
+                Ok(Value::Buffer(Rc::new(RefCell::new(values))))
+            }
+            "sort" | "sort_by" => {
+                let slots = self.slots_arg(&args[0], identifier)?;
+                let function = match args.get(1) {
+                    Some(arg) => Some(self.closure_arg(arg, identifier)?),
+                    None => None,
+                };
+                // The copy is sorted, so the lambda may read the vector or buffer meanwhile
+                let values = slots.borrow().clone();
+                let sorted = self.merge_sort(values, function.as_deref())?;
+                *slots.borrow_mut() = sorted;
+                Ok(Value::Int(0))
+            }
+            "binary_search" => {
+                let slots = self.slots_arg(&args[0], identifier)?;
+                let value = self.int_arg(&args[1], identifier)?;
+                let slots = slots.borrow();
+                let first = slots.partition_point(|&slot| slot < value);
+                let found = slots.get(first) == Some(&value);
+                Ok(Value::Int(if found { first as i64 } else { -1 }))
+            }
+            "chars" => {
+                let text = self.text_arg(&args[0], identifier)?;
+                let chars = text.chars().map(|c| c as i64).collect();
+                Ok(Value::Iterator(Rc::new(RefCell::new(Iter::Values(chars)))))
+            }
+            "tcp_connect" => {
+                let host = self.text_arg(&args[0], identifier)?;
+                let port = self.int_arg(&args[1], identifier)?;
+                Ok(Value::Int(self.sockets.connect(&host, port)?))
+            }
+            "tcp_listen" => {
+                let port = self.int_arg(&args[0], identifier)?;
+                Ok(Value::Int(self.sockets.listen(port)?))
+            }
+            "tcp_accept" => {
+                let listener = self.int_arg(&args[0], identifier)?;
+                Ok(Value::Int(self.sockets.accept(listener)?))
+            }
+            "tcp_port" => {
+                let listener = self.int_arg(&args[0], identifier)?;
+                Ok(Value::Int(self.sockets.port(listener)?))
+            }
+            "tcp_send" => {
+                let socket = self.int_arg(&args[0], identifier)?;
+                let data = self.text_arg(&args[1], identifier)?;
+                self.sockets.send(socket, data.as_bytes())?;
+                Ok(Value::Int(data.len() as i64))
+            }
+            "tcp_recv" => {
+                let socket = self.int_arg(&args[0], identifier)?;
+                let received = self.sockets.recv(socket)?;
+                if received.is_empty() {
+                    Ok(Value::Str(None))
+                } else {
+                    Ok(string_value(&String::from_utf8_lossy(&received)))
+                }
+            }
+            "tcp_close" => {
+                let socket = self.int_arg(&args[0], identifier)?;
+                self.sockets.close(socket);
+                Ok(Value::Int(0))
+            }
+            "json_parse" => {
+                let text = self.text_arg(&args[0], identifier)?;
+                Ok(Value::Json(json::parse(&text)?))
+            }
+            "json_kind" => match self.evaluate(&args[0])? {
+                Value::Json(json) => Ok(string_value(json.kind())),
+                found => Err(VentiError::RuntimeError(
+                    format!(
+                        "'json_kind' expects a JSON value, found {}",
+                        found.type_name()
+                    ),
+                    None,
+                )),
+            },
+            "json_stringify" => {
+                let value = self.evaluate(&args[0])?;
+                Ok(string_value(&json::stringify(&value)?))
+            }
+            "read_file" => {
+                let path = self.text_arg(&args[0], identifier)?;
+                let bytes = std::fs::read(&path).map_err(|_| {
+                    VentiError::RuntimeError(format!("could not read file '{}'", path), None)
+                })?;
+                Ok(string_value(&String::from_utf8_lossy(&bytes)))
+            }
+            "write_file" | "append_file" => {
+                let path = self.text_arg(&args[0], identifier)?;
+                let contents = self.text_arg(&args[1], identifier)?;
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(identifier == "append_file")
+                    .truncate(identifier == "write_file")
+                    .open(&path)
+                    .and_then(|mut file| file.write_all(contents.as_bytes()))
+                    .map_err(|_| {
+                        VentiError::RuntimeError(
+                            format!("could not write to file '{}'", path),
+                            None,
+                        )
+                    })?;
+                Ok(Value::Int(contents.len() as i64))
+            }
+            "upper" => {
+                let text = self.text_arg(&args[0], identifier)?;
+                Ok(string_value(&text.to_ascii_uppercase()))
+            }
+            "lower" => {
+                let text = self.text_arg(&args[0], identifier)?;
+                Ok(string_value(&text.to_ascii_lowercase()))
+            }
+            "trim" => {
+                let text = self.text_arg(&args[0], identifier)?;
+                Ok(string_value(text.trim_matches(is_c_space)))
+            }
+            "contains" => {
+                let text = self.text_arg(&args[0], identifier)?;
+                let needle = self.text_arg(&args[1], identifier)?;
+                Ok(Value::Int(i64::from(text.contains(needle.as_str()))))
+            }
+            "substring" => {
+                let text = self.text_arg(&args[0], identifier)?;
+                let start = self.int_arg(&args[1], identifier)?;
+                let end = self.int_arg(&args[2], identifier)?;
+                // Indices are clamped to the string, as the runtime helper does
+                let len = text.len() as i64;
+                let end = end.clamp(0, len) as usize;
+                let start = (start.clamp(0, len) as usize).min(end);
+                Ok(string_value(&String::from_utf8_lossy(
+                    &text.as_bytes()[start..end],
+                )))
+            }
+            "split" => {
+                let text = self.text_arg(&args[0], identifier)?;
+                let separator = self.text_arg(&args[1], identifier)?;
+                if separator.is_empty() {
+                    return Err(VentiError::RuntimeError(
+                        "cannot split a string at an empty separator".to_string(),
+                        None,
+                    ));
+                }
+                let pieces = text.split(separator.as_str()).map(Rc::from).collect();
+                Ok(Value::StringVector(Rc::new(pieces)))
+            }
+            "to_int" => {
+                let text = self.text_arg(&args[0], identifier)?;
+                text.parse().map(Value::Int).map_err(|_| {
+                    VentiError::RuntimeError(format!("could not parse '{}' as an int", text), None)
+                })
+            }
+            "to_float" => {
+                let text = self.text_arg(&args[0], identifier)?;
+                text.parse().map(Value::Float).map_err(|_| {
+                    VentiError::RuntimeError(format!("could not parse '{}' as a float", text), None)
+                })
+            }
+            _ => Err(VentiError::RuntimeError(
+                format!("Unknown builtin '{}'", identifier),
+                None,
+            )),
+        }
+    }
+
+    /// Evaluates a string argument of the builtin `name`, where `nothing` is empty.
+    fn text_arg(&mut self, arg: &'a Expr<'a>, name: &str) -> Result<String, VentiError> {
+        match self.evaluate(arg)? {
+            Value::Str(text) => Ok(text.as_deref().unwrap_or_default().to_string()),
+            value => Err(VentiError::RuntimeError(
+                format!("'{}' expects a string, found {}", name, value.type_name()),
+                None,
+            )),
+        }
+    }
+
+    /// Evaluates a channel argument of the builtin `name`.
+    fn channel_arg(
+        &mut self,
+        arg: &'a Expr<'a>,
+        name: &str,
+    ) -> Result<Rc<RefCell<VecDeque<Value<'a>>>>, VentiError> {
+        match self.evaluate(arg)? {
+            Value::Channel(channel) => Ok(channel),
+            value => Err(VentiError::RuntimeError(
+                format!("'{}' expects a channel, found {}", name, value.type_name()),
+                None,
+            )),
+        }
+    }
+
+    /// Evaluates an argument of the builtin `name` that gives it values, turning it into an
+    /// iterator over them.
+    ///
+    /// An iterator is returned as it is, and the elements of an array or buffer are copied
+    /// into the new iterator.
+    fn iterator_arg(
+        &mut self,
+        arg: &'a Expr<'a>,
+        name: &str,
+    ) -> Result<Rc<RefCell<Iter<'a>>>, VentiError> {
+        let mismatch = |found: &str| {
+            VentiError::RuntimeError(
+                format!(
+                    "'{}' expects a range, buffer, iterator or array of ints, found {}",
+                    name, found
+                ),
+                None,
+            )
+        };
+        let iterator = match self.evaluate(arg)? {
+            Value::Iterator(iterator) => return Ok(iterator),
+            Value::Range { start, end, step } => Iter::Count {
+                next: start,
+                end,
+                step,
+            },
+            Value::Buffer(buffer) | Value::Vector(buffer) => {
+                Iter::Values(buffer.borrow().iter().copied().collect())
+            }
+            Value::Array(elements) => Iter::Values(
+                elements
+                    .iter()
+                    .map(|element| match element {
+                        Value::Int(n) => Ok(*n),
+                        element => Err(mismatch(&format!("an array of {}", element.type_name()))),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            value => return Err(mismatch(value.type_name())),
+        };
+        Ok(Rc::new(RefCell::new(iterator)))
+    }
+
+    /// Evaluates the vector or buffer argument of the builtin `name`, giving its slots.
+    fn slots_arg(
+        &mut self,
+        arg: &'a Expr<'a>,
+        name: &str,
+    ) -> Result<Rc<RefCell<Vec<i64>>>, VentiError> {
+        match self.evaluate(arg)? {
+            Value::Vector(slots) | Value::Buffer(slots) => Ok(slots),
+            value => Err(VentiError::RuntimeError(
+                format!(
+                    "'{}' expects a vector or buffer, found {}",
+                    name,
+                    value.type_name()
+                ),
+                None,
+            )),
+        }
+    }
+
+    /// Evaluates a lambda argument of the builtin `name`.
+    fn closure_arg(
+        &mut self,
+        arg: &'a Expr<'a>,
+        name: &str,
+    ) -> Result<Rc<Closure<'a>>, VentiError> {
+        match self.evaluate(arg)? {
+            Value::Lambda(closure) => Ok(closure),
+            value => Err(VentiError::RuntimeError(
+                format!("'{}' expects a lambda, found {}", name, value.type_name()),
+                None,
+            )),
+        }
+    }
+
+    /// Takes the next value of `iterator`.
+    ///
+    /// # Returns
+    ///
+    /// The value, or `None` once the iterator has run out.
+    fn next_value(&mut self, iterator: &Rc<RefCell<Iter<'a>>>) -> Result<Option<i64>, VentiError> {
+        let (source, function, filtering) = match &mut *iterator.borrow_mut() {
+            Iter::Count { next, end, step } => {
+                if *next >= *end {
+                    return Ok(None);
+                }
+                let value = *next;
+                *next = next.wrapping_add(*step);
+                return Ok(Some(value));
+            }
+            Iter::Values(values) => return Ok(values.pop_front()),
+            Iter::Map { source, function } => (source.clone(), function.clone(), false),
+            Iter::Filter { source, function } => (source.clone(), function.clone(), true),
+        };
+        // The iterator is not borrowed while the lambda runs, so the lambda may use it too
+        while let Some(value) = self.next_value(&source)? {
+            let result = self.apply_int_closure(&function, &[value])?;
+            if !filtering {
+                return Ok(Some(result));
+            }
+            if result != 0 {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Evaluates an integer argument of the builtin `name`.
+    fn int_arg(&mut self, arg: &'a Expr<'a>, name: &str) -> Result<i64, VentiError> {
+        match self.evaluate(arg)? {
+            Value::Int(value) => Ok(value),
+            value => Err(VentiError::RuntimeError(
+                format!("'{}' expects an integer, found {}", name, value.type_name()),
+                None,
+            )),
+        }
+    }
+
+    /// Evaluates a value stored in a map entry or buffer slot, which must be an integer.
+    fn slot_value(&mut self, value: &'a Expr<'a>) -> Result<i64, VentiError> {
+        let value = self.evaluate(value)?;
+        value.as_int().ok_or_else(|| {
+            VentiError::RuntimeError(
+                format!(
+                    "Map and buffer values must be integers, found {}",
+                    value.type_name()
+                ),
+                None,
+            )
+        })
+    }
+
+    /// Evaluates an expression.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Value, VentiError>` - The value of `expr`, or the runtime error it raised.
+    fn evaluate(&mut self, expr: &'a Expr<'a>) -> Result<Value<'a>, VentiError> {
+        self.evaluate_kind(&expr.kind)
+            .map_err(|error| error.at(expr.span))
+    }
+
+    fn evaluate_kind(&mut self, kind: &'a ExprKind<'a>) -> Result<Value<'a>, VentiError> {
+        match kind {
+            ExprKind::Number(n) => Ok(Value::Int(*n)),
+            ExprKind::Float(x) => Ok(Value::Float(*x)),
+            ExprKind::String(text) => Ok(Value::Str(Some(Rc::from(text.as_str())))),
+            ExprKind::Boolean(b) => Ok(Value::Bool(*b)),
+            ExprKind::Null => Ok(Value::Str(None)),
+            ExprKind::Identifier(id) => self
+                .env
+                .lookup(id)
+                .cloned()
+                .or_else(|| builtins::constant(id).map(Value::Float))
+                .ok_or_else(|| {
+                    VentiError::RuntimeError(format!("Undefined variable '{}'", id), None)
+                        .with_code(ErrorCode::UndefinedVariable)
+                }),
+            ExprKind::BinaryOp(left, BinOp::Coalesce, right) => match self.evaluate(left)? {
+                Value::Str(None) => self.evaluate(right),
+                left => Ok(left),
+            },
+            ExprKind::BinaryOp(left, op @ (BinOp::And | BinOp::Or), right) => {
+                match self.evaluate(left)? {
+                    Value::Bool(decided) if decided == (*op == BinOp::Or) => {
+                        Ok(Value::Bool(decided))
+                    }
+                    Value::Bool(_) => self.evaluate(right),
+                    left => {
+                        let right = self.evaluate(right)?;
+                        binary_op(left, *op, right)
+                    }
+                }
+            }
+            ExprKind::BinaryOp(left, op, right) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                binary_op(left, *op, right)
+            }
+            ExprKind::UnaryOp(op, operand) => match (op, self.evaluate(operand)?) {
+                (UnaryOp::Negate, Value::Int(n)) => Ok(Value::Int(n.wrapping_neg())),
+                (UnaryOp::Negate, Value::Float(x)) => Ok(Value::Float(-x)),
+                (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+                (op, operand) => Err(VentiError::RuntimeError(
+                    format!(
+                        "Operator '{}' cannot be applied to {}",
+                        match op {
+                            UnaryOp::Negate => "-",
+                            UnaryOp::Not => "!",
+                        },
+                        operand.type_name()
+                    ),
+                    None,
+                )),
+            },
+            ExprKind::Array(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.evaluate(element))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Array(Rc::new(elements)))
+            }
+            ExprKind::Map(entries) => {
+                let mut map = HashMap::new();
+                for (key, value) in entries {
+                    let key = map_key(&self.evaluate(key)?)?;
+                    map.insert(key, self.slot_value(value)?);
+                }
+                Ok(Value::Map(Rc::new(RefCell::new(map))))
+            }
+            ExprKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let start = self.evaluate(start)?;
+                let end = self.evaluate(end)?;
+                let (Value::Int(start), Value::Int(end)) = (start, end) else {
+                    return Err(VentiError::RuntimeError(
+                        "Range bounds must be integers".to_string(),
+                        None,
+                    ));
+                };
+                // An inclusive range is stored with its end bumped by one, as in codegen
+                let end = if *inclusive { end.wrapping_add(1) } else { end };
+                Ok(Value::Range {
+                    start,
+                    end,
+                    step: 1,
+                })
+            }
+            ExprKind::Index { target, index } => {
+                let target = self.evaluate(target)?;
+                let index = self.evaluate(index)?;
+                index_value(target, index)
+            }
+            ExprKind::Call { callee, args } => {
+                if let ExprKind::Identifier(identifier) = &callee.kind {
+                    let is_function = !self.env.is_local(identifier)
+                        && (self.functions.contains_key(identifier.as_str())
+                            || self.externs.contains(identifier.as_str())
+                            || builtins::is_builtin(identifier));
+                    if is_function {
+                        return self.call(identifier, args);
+                    }
+                }
+                let callee = self.evaluate(callee)?;
+                self.call_closure(callee, args)
+            }
+            ExprKind::Lambda { params, body } => Ok(Value::Lambda(Rc::new(Closure {
+                params,
+                body,
+                captures: self.env.locals(),
+            }))),
+            ExprKind::MethodCall {
+                receiver,
+                name,
+                args,
+            } => self.call_method(receiver, name, args),
+            ExprKind::Cast { value, target } => {
+                let value = self.evaluate(value)?;
+                cast(value, target)
+            }
+            ExprKind::Async(inner) => self.evaluate(inner),
+            ExprKind::Await(task) => {
+                let task = self.evaluate(task)?;
+                await_task(task)
+            }
+            ExprKind::Spawn(body) => {
+                // The block works on a snapshot of the locals, while globals stay shared
+                let captures = self.env.locals();
+                let result = self.execute_function("<spawn>", body, captures)?;
+                Ok(Value::Task(result.as_int().unwrap_or(0)))
+            }
+        }
+    }
+
+    /// Calls a lambda value with `args`.
+    fn call_closure(
+        &mut self,
+        callee: Value<'a>,
+        args: &'a [Expr<'a>],
+    ) -> Result<Value<'a>, VentiError> {
+        let Value::Lambda(closure) = callee else {
+            return Err(VentiError::RuntimeError(
+                format!("A value of type {} is not callable", callee.type_name()),
+                None,
+            ));
+        };
+        if closure.params.len() != args.len() {
+            return Err(VentiError::RuntimeError(
+                format!(
+                    "Lambda expects {} arguments but {} were given",
+                    closure.params.len(),
+                    args.len()
+                ),
+                None,
+            ));
+        }
+        let values = args
+            .iter()
+            .map(|arg| self.evaluate(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.apply_closure(&closure, values)
+    }
+
+    /// Sorts `values` the way the compiled runtime does, with a stable bottom-up merge sort,
+    /// so that a lambda is called with the same pairs in the same order. The right element
+    /// of a pair goes first only when it belongs strictly before the left: when the left is
+    /// greater, or when `function` gives a positive result for the pair.
+    ///
+    /// # Returns
+    ///
+    /// The sorted values, or the error the lambda raised.
+    fn merge_sort(
+        &mut self,
+        values: Vec<i64>,
+        function: Option<&Closure<'a>>,
+    ) -> Result<Vec<i64>, VentiError> {
+        let len = values.len();
+        let mut source = values;
+        let mut target = vec![0; len];
+        let mut width = 1;
+        while width < len {
+            let mut lo = 0;
+            while lo < len {
+                let mid = (lo + width).min(len);
+                let hi = (lo + 2 * width).min(len);
+                let (mut i, mut j) = (lo, mid);
+                for slot in &mut target[lo..hi] {
+                    let take_right = i >= mid
+                        || (j < hi
+                            && match function {
+                                Some(function) => {
+                                    self.apply_int_closure(function, &[source[i], source[j]])? > 0
+                                }
+                                None => source[i] > source[j],
+                            });
+                    if take_right {
+                        *slot = source[j];
+                        j += 1;
+                    } else {
+                        *slot = source[i];
+                        i += 1;
+                    }
+                }
+                lo = hi;
+            }
+            std::mem::swap(&mut source, &mut target);
+            width *= 2;
+        }
+        Ok(source)
+    }
+
+    /// Calls the lambda `closure` with integer `args`, as the iterator builtins do.
+    fn apply_int_closure(
+        &mut self,
+        closure: &Closure<'a>,
+        args: &[i64],
+    ) -> Result<i64, VentiError> {
+        let args = args.iter().map(|&arg| Value::Int(arg)).collect();
+        let result = self.apply_closure(closure, args)?;
+        Ok(result.as_int().unwrap_or_default())
+    }
+
+    /// Calls the lambda `closure` with the values of its arguments.
+    fn apply_closure(
+        &mut self,
+        closure: &Closure<'a>,
+        args: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, VentiError> {
+        let mut bindings = closure.captures.clone();
+        for (param, arg) in closure.params.iter().zip(args) {
+            bindings.push((param.name.clone(), arg));
+        }
+        let caller = self.env.enter_function(bindings);
+        let calling = std::mem::replace(&mut self.function, "<lambda>".to_string());
+        let result = self.evaluate(closure.body);
+        self.function = calling;
+        self.env.leave_function(caller);
+        let result = result?;
+        result.as_int().map(Value::Int).ok_or_else(|| {
+            VentiError::RuntimeError(
+                format!(
+                    "Lambdas must return an integer value, found {}",
+                    result.type_name()
+                ),
+                None,
+            )
+        })
+    }
+
+    /// Calls one of the built-in methods, or the method of a trait the receiver's type
+    /// implements.
+    fn call_method(
+        &mut self,
+        receiver: &'a Expr<'a>,
+        name: &str,
+        args: &'a [Expr<'a>],
+    ) -> Result<Value<'a>, VentiError> {
+        let receiver = self.evaluate(receiver)?;
+        let args = args
+            .iter()
+            .map(|arg| self.evaluate(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        match (name, &receiver, args.as_slice()) {
+            ("len", Value::Array(elements), []) => Ok(Value::Int(elements.len() as i64)),
+            ("len", Value::Map(map), []) => Ok(Value::Int(map.borrow().len() as i64)),
+            ("len", Value::Str(text), []) => {
+                Ok(Value::Int(text.as_deref().map_or(0, str::len) as i64))
+            }
+            ("len", Value::Buffer(buffer) | Value::Vector(buffer), []) => {
+                Ok(Value::Int(buffer.borrow().len() as i64))
+            }
+            ("len", Value::StringVector(pieces), []) => Ok(Value::Int(pieces.len() as i64)),
+            ("len", Value::Json(json), []) => Ok(Value::Int(json.count())),
+            ("push", Value::Vector(vector), [value]) => {
+                let value = value.as_int().ok_or_else(|| {
+                    VentiError::RuntimeError("'push' expects an integer".to_string(), None)
+                })?;
+                vector.borrow_mut().push(value);
+                Ok(Value::Int(0))
+            }
+            ("pop", Value::Vector(vector), []) => {
+                let value = vector.borrow_mut().pop().ok_or_else(|| {
+                    VentiError::RuntimeError("cannot pop from an empty vector".to_string(), None)
+                })?;
+                Ok(Value::Int(value))
+            }
+            ("has", Value::Map(map), [key]) => {
+                let key = map_key(key)?;
+                Ok(Value::Int(map.borrow().contains_key(&key) as i64))
+            }
+            ("step", Value::Range { start, end, .. }, [Value::Int(step)]) => {
+                // Ranges only count upwards, so the step must be positive
+                if *step <= 0 {
+                    return Err(VentiError::RuntimeError(
+                        "Range step must be positive".to_string(),
+                        None,
+                    ));
+                }
+                Ok(Value::Range {
+                    start: *start,
+                    end: *end,
+                    step: *step,
+                })
+            }
+            _ => {
+                let is_defined = |function: &str| self.functions.contains_key(function);
+                let Some(function) = self.traits.resolve(receiver.type_name(), name, is_defined)
+                else {
+                    return Err(VentiError::RuntimeError(
+                        format!(
+                            "Type {} has no method '{}' taking {} arguments",
+                            receiver.type_name(),
+                            name,
+                            args.len()
+                        ),
+                        None,
+                    ));
+                };
+                let method = &self.functions[&function];
+                let body = method.body;
+                let bindings = method
+                    .params
+                    .iter()
+                    .map(|param| param.name.clone())
+                    .zip(std::iter::once(receiver.clone()).chain(args.iter().cloned()))
+                    .collect();
+                self.execute_function(&function, body, bindings)
+            }
+        }
+    }
+}
+
+impl Default for Interpreter<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads an argument of the math builtin `name` as a float, converting an integer.
+fn number(value: &Value, name: &str) -> Result<f64, VentiError> {
+    match value {
+        Value::Int(value) => Ok(*value as f64),
+        Value::Float(value) => Ok(*value),
+        value => Err(VentiError::RuntimeError(
+            format!("'{}' expects a number, found {}", name, value.type_name()),
+            None,
+        )),
+    }
+}
+
+/// Wraps `text` as a string value.
+fn string_value<'a>(text: &str) -> Value<'a> {
+    Value::Str(Some(Rc::from(text)))
+}
+
+/// Returns the exit status the shell would give for a command that ended with `status`: its
+/// exit code, or 128 plus the signal that killed it.
+fn exit_code(status: ExitStatus) -> i64 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + i64::from(signal);
+    }
+    status.code().map_or(1, i64::from)
+}
+
+/// Returns true for the bytes C's `isspace` accepts, which `trim` removes.
+fn is_c_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\x0b' | '\x0c' | '\r')
+}
+
+/// Returns the result of a finished task.
+fn await_task(task: Value) -> Result<Value, VentiError> {
+    match task {
+        Value::Task(result) => Ok(Value::Int(result)),
+        found => Err(VentiError::RuntimeError(
+            format!(
+                "Only tasks returned by async functions can be awaited, found {}",
+                found.type_name()
+            ),
+            None,
+        )),
+    }
+}
+
+/// Returns the text of a map key, which must be a string.
+fn map_key(key: &Value) -> Result<String, VentiError> {
+    match key {
+        Value::Str(Some(text)) => Ok(text.to_string()),
+        found => Err(VentiError::RuntimeError(
+            format!("Map keys must be strings, found {}", found.type_name()),
+            None,
+        )),
+    }
+}
+
+/// Checks `index` against the length of a buffer or vector, which `kind` names.
+fn slot_index(index: &Value, len: usize, kind: &str) -> Result<usize, VentiError> {
+    let Value::Int(index) = index else {
+        return Err(VentiError::RuntimeError(
+            format!(
+                "{}{} index must be an integer, found {}",
+                kind[..1].to_uppercase(),
+                &kind[1..],
+                index.type_name()
+            ),
+            None,
+        ));
+    };
+    usize::try_from(*index)
+        .ok()
+        .filter(|slot| *slot < len)
+        .ok_or_else(|| out_of_bounds(*index, len, kind))
+}
+
+/// The runtime error for indexing past the end of an array, buffer or vector.
+fn out_of_bounds(index: i64, len: usize, kind: &str) -> VentiError {
+    VentiError::RuntimeError(
+        format!(
+            "index {} is out of bounds for a{} {} of length {}",
+            index,
+            if kind == "array" { "n" } else { "" },
+            kind,
+            len
+        ),
+        None,
+    )
+}
+
+/// Evaluates `target[index]` on an array, map, buffer, vector, string or JSON value.
+fn index_value<'a>(target: Value<'a>, index: Value<'a>) -> Result<Value<'a>, VentiError> {
+    match target {
+        Value::Map(map) => {
+            let key = map_key(&index)?;
+            let value = map.borrow().get(&key).copied();
+            value.map(Value::Int).ok_or_else(|| {
+                VentiError::RuntimeError(format!("key {} not found in map", key), None)
+            })
+        }
+        Value::Buffer(buffer) => {
+            let buffer = buffer.borrow();
+            let slot = slot_index(&index, buffer.len(), "buffer")?;
+            Ok(Value::Int(buffer[slot]))
+        }
+        Value::Vector(vector) => {
+            let vector = vector.borrow();
+            if let Value::Range { start, end, .. } = index {
+                return slice_vector(&vector, start, end);
+            }
+            let slot = slot_index(&index, vector.len(), "vector")?;
+            Ok(Value::Int(vector[slot]))
+        }
+        Value::StringVector(pieces) => {
+            let slot = slot_index(&index, pieces.len(), "vector")?;
+            Ok(Value::Str(Some(pieces[slot].clone())))
+        }
+        Value::Array(elements) => {
+            let index = index.as_int().ok_or_else(|| {
+                VentiError::RuntimeError(
+                    format!(
+                        "Array index must be an integer, found {}",
+                        index.type_name()
+                    ),
+                    None,
+                )
+            })?;
+            usize::try_from(index)
+                .ok()
+                .and_then(|slot| elements.get(slot).cloned())
+                .ok_or_else(|| out_of_bounds(index, elements.len(), "array"))
+        }
+        Value::Str(text) => {
+            let Value::Range { start, end, .. } = index else {
+                return Err(VentiError::RuntimeError(
+                    format!(
+                        "Strings can only be sliced with a range, found {}",
+                        index.type_name()
+                    ),
+                    None,
+                ));
+            };
+            slice(text.as_deref().unwrap_or_default(), start, end)
+        }
+        Value::Json(json) => match index {
+            Value::Str(key) => Ok(Value::Json(json.member(key.as_deref().unwrap_or_default()))),
+            index => match index.as_int() {
+                Some(index) => Ok(Value::Json(json.element(index))),
+                None => Err(VentiError::RuntimeError(
+                    format!(
+                        "JSON values are indexed by a string key or an integer, found {}",
+                        index.type_name()
+                    ),
+                    None,
+                )),
+            },
+        },
+        found => Err(VentiError::RuntimeError(
+            format!(
+                "Only arrays, maps, buffers, strings and JSON values can be indexed, found {}",
+                found.type_name()
+            ),
+            None,
+        )),
+    }
+}
+
+/// Returns the bytes of `text` from `start` up to `end`, as `text[start..end]` gives.
+///
+/// # Returns
+///
+/// The slice, or a runtime error if the offsets are outside `text`, `start` is past `end`,
+/// or either falls inside a character.
+fn slice<'a>(text: &str, start: i64, end: i64) -> Result<Value<'a>, VentiError> {
+    if start < 0 || start > end || end > text.len() as i64 {
+        return Err(VentiError::RuntimeError(
+            format!(
+                "string slice {}..{} is out of range for a string of {} bytes",
+                start,
+                end,
+                text.len()
+            ),
+            None,
+        ));
+    }
+    let (start, end) = (start as usize, end as usize);
+    if !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+        return Err(VentiError::RuntimeError(
+            format!(
+                "string slice {}..{} does not start and end on character boundaries",
+                start, end
+            ),
+            None,
+        ));
+    }
+    Ok(string_value(&text[start..end]))
+}
+
+/// Returns a new vector with the elements of `vector` from `start` up to `end`, as
+/// `vector[start..end]` gives.
+///
+/// # Returns
+///
+/// The slice, or a runtime error if the offsets are outside `vector` or `start` is past
+/// `end`.
+fn slice_vector<'a>(vector: &[i64], start: i64, end: i64) -> Result<Value<'a>, VentiError> {
+    if start < 0 || start > end || end > vector.len() as i64 {
+        return Err(VentiError::RuntimeError(
+            format!(
+                "vector slice {}..{} is out of range for a vector of length {}",
+                start,
+                end,
+                vector.len()
+            ),
+            None,
+        ));
+    }
+    let slice = vector[start as usize..end as usize].to_vec();
+    Ok(Value::Vector(Rc::new(RefCell::new(slice))))
+}
+
+/// Converts `value` to `target` the way `as` does in compiled code.
+///
+/// Floats convert to integers by truncating toward zero and saturating at the ends of the
+/// `int` range, with NaN becoming 0, and anything nonzero converts to `true`.
+fn cast<'a>(value: Value<'a>, target: &VarType) -> Result<Value<'a>, VentiError> {
+    let result = match (value, target) {
+        (Value::Int(n), VarType::Int) => Value::Int(n),
+        (Value::Int(n), VarType::Float) => Value::Float(n as f64),
+        (Value::Int(n), VarType::Bool) => Value::Bool(n != 0),
+        (Value::Bool(b), VarType::Int) => Value::Int(b as i64),
+        (Value::Bool(b), VarType::Float) => Value::Float(b as i64 as f64),
+        (Value::Bool(b), VarType::Bool) => Value::Bool(b),
+        // Rust's float to integer casts saturate and map NaN to 0, like `llvm.fptosi.sat`
+        (Value::Float(x), VarType::Int) => Value::Int(x as i64),
+        (Value::Float(x), VarType::Float) => Value::Float(x),
+        (Value::Float(x), VarType::Bool) => Value::Bool(x != 0.0),
+        (Value::Json(json), target) => return json.cast(target),
+        (found, _) => {
+            return Err(VentiError::RuntimeError(
+                format!(
+                    "Only numbers and bools can be cast, found {}",
+                    found.type_name()
+                ),
+                None,
+            ))
+        }
+    };
+    Ok(result)
+}
+
+/// Applies a binary operator other than `??` to two values.
+///
+/// Integer arithmetic wraps, mixing an integer with a float converts the integer, and
+/// adding two vectors makes a new one holding the elements of both.
+/// Comparisons yield a bool, and so do the bitwise operators on two bools. `&&` and `||`
+/// short-circuit in `evaluate`, so they only reach here with operands they reject.
+fn binary_op<'a>(left: Value<'a>, op: BinOp, right: Value<'a>) -> Result<Value<'a>, VentiError> {
+    let mismatch = |left: &Value, right: &Value| {
+        VentiError::RuntimeError(
+            format!(
+                "Operator {:?} cannot be applied to {} and {}",
+                op,
+                left.type_name(),
+                right.type_name()
+            ),
+            None,
+        )
+    };
+    match (&left, &right) {
+        (Value::Int(l), Value::Int(r)) => {
+            int_op(*l, op, *r).unwrap_or_else(|| Err(mismatch(&left, &right)))
+        }
+        (Value::Bool(l), Value::Bool(r)) => {
+            let result = match op {
+                BinOp::Equal => l == r,
+                BinOp::NotEqual => l != r,
+                BinOp::Less => l < r,
+                BinOp::LessEqual => l <= r,
+                BinOp::Greater => l > r,
+                BinOp::GreaterEqual => l >= r,
+                BinOp::BitAnd => l & r,
+                BinOp::BitOr => l | r,
+                BinOp::BitXor => l ^ r,
+                _ => return Err(mismatch(&left, &right)),
+            };
+            Ok(Value::Bool(result))
+        }
+        (Value::Str(Some(l)), Value::Str(Some(r))) => {
+            compare(op, l.cmp(r)).ok_or_else(|| mismatch(&left, &right))
+        }
+        (Value::Vector(l), Value::Vector(r)) if op == BinOp::Add => {
+            let joined = l
+                .borrow()
+                .iter()
+                .chain(r.borrow().iter())
+                .copied()
+                .collect();
+            Ok(Value::Vector(Rc::new(RefCell::new(joined))))
+        }
+        (Value::Float(_) | Value::Int(_), Value::Float(_) | Value::Int(_)) => {
+            let as_float = |value: &Value| match value {
+                Value::Int(n) => *n as f64,
+                Value::Float(x) => *x,
+                _ => unreachable!("matched as a number"),
+            };
+            let (l, r) = (as_float(&left), as_float(&right));
+            let result = match op {
+                BinOp::Add => Value::Float(l + r),
+                BinOp::Subtract => Value::Float(l - r),
+                BinOp::Multiply => Value::Float(l * r),
+                BinOp::Divide => Value::Float(l / r),
+                BinOp::Equal => Value::Bool(l == r),
+                BinOp::NotEqual => Value::Bool(l != r),
+                BinOp::Less => Value::Bool(l < r),
+                BinOp::LessEqual => Value::Bool(l <= r),
+                BinOp::Greater => Value::Bool(l > r),
+                BinOp::GreaterEqual => Value::Bool(l >= r),
+                _ => return Err(mismatch(&left, &right)),
+            };
+            Ok(result)
+        }
+        _ => Err(mismatch(&left, &right)),
+    }
+}
+
+/// Applies a binary operator to two integers, or returns `None` if it does not apply.
+///
+/// Division by zero and shifts by 64 bits or more are reported as errors rather than left
+/// undefined.
+fn int_op<'a>(left: i64, op: BinOp, right: i64) -> Option<Result<Value<'a>, VentiError>> {
+    let result = match op {
+        BinOp::Add => left.wrapping_add(right),
+        BinOp::Subtract => left.wrapping_sub(right),
+        BinOp::Multiply => left.wrapping_mul(right),
+        BinOp::Divide if right == 0 => {
+            return Some(Err(VentiError::RuntimeError(
+                "division by zero".to_string(),
+                None,
+            )))
+        }
+        BinOp::Divide => left.wrapping_div(right),
+        BinOp::BitAnd => left & right,
+        BinOp::BitOr => left | right,
+        BinOp::BitXor => left ^ right,
+        BinOp::ShiftLeft | BinOp::ShiftRight if !(0..64).contains(&right) => {
+            return Some(Err(VentiError::RuntimeError(
+                format!("shift by {} bits is out of range", right),
+                None,
+            )))
+        }
+        BinOp::ShiftLeft => left << right,
+        // `>>` is an arithmetic shift, matching the signed integer type
+        BinOp::ShiftRight => left >> right,
+        _ => return compare(op, left.cmp(&right)).map(Ok),
+    };
+    Some(Ok(Value::Int(result)))
+}
+
+/// Turns an ordering into the bool a comparison operator yields, or `None` if `op` is not
+/// a comparison.
+fn compare<'a>(op: BinOp, order: std::cmp::Ordering) -> Option<Value<'a>> {
+    let result = match op {
+        BinOp::Equal => order.is_eq(),
+        BinOp::NotEqual => order.is_ne(),
+        BinOp::Less => order.is_lt(),
+        BinOp::LessEqual => order.is_le(),
+        BinOp::Greater => order.is_gt(),
+        BinOp::GreaterEqual => order.is_ge(),
+        _ => return None,
+    };
+    Some(Value::Bool(result))
+}
+
+/// The message printed for a failed `assert_venti`, as the compiled code prints it.
+fn assertion_failure(line: u32) -> String {
+    match line {
+        0 => "Assertion failed".to_string(),
+        line => format!("Assertion failed on line {}", line),
+    }
+}
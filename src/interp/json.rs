@@ -0,0 +1,477 @@
+use crate::errors::VentiError;
+use crate::interp::value::Value;
+use crate::venti_parser::ast::VarType;
+use std::fmt;
+use std::rc::Rc;
+
+/*
+Venti's maps only hold integers and its arrays have a fixed length, so `json_parse` reads a
+document into a `Json` tree rather than into Venti values, after checking that the whole
+document is valid JSON, which is a runtime error otherwise. A program walks the tree by
+indexing: `doc["key"]` is the last member of an object named `key` and `doc[i]` an element
+of an array, and asking a value for something it does not have, such as a member of an
+array, gives null rather than an error, so a lookup can go several levels deep before its
+result is checked. `as` then turns a value into an int, float, bool or string, and is a
+runtime error for a value of another kind, except that null becomes `nothing` as a string.
+
+Objects keep their members in the order the document has them, duplicates included, and
+numbers keep the text they were written as, so printing a value or writing it back with
+`json_stringify` gives out what came in, less the whitespace.
+
+The scanner follows the JSON grammar to the letter and is the same in the LLVM and C
+runtimes, so all three accept the same documents. Arrays and objects may nest at most
+`MAX_DEPTH` deep, which bounds its recursion.
+
+`json_stringify` goes the other way, writing ints, floats, bools, strings, `nothing`, maps,
+JSON values and arrays of them as JSON. Floats are written the way `printventi` writes them, and maps
+with their keys in byte order, so the output is the same however the program is run.
+*/
+
+/// How deeply arrays and objects may nest in a document the JSON builtins read.
+pub const MAX_DEPTH: usize = 128;
+
+/// A value read from a JSON document.
+#[derive(Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// A number, as the text it was written as.
+    Number(String),
+    String(String),
+    Array(Vec<Rc<Json>>),
+    /// The members of an object, in the order the document has them.
+    Object(Vec<(String, Rc<Json>)>),
+}
+
+/// Returns the error for a document that is not valid JSON.
+fn invalid() -> VentiError {
+    VentiError::RuntimeError("invalid JSON".to_string(), None)
+}
+
+/// Returns the byte at `pos` of `text`, or 0 past its end, which no valid document has.
+fn byte(text: &[u8], pos: usize) -> u8 {
+    text.get(pos).copied().unwrap_or(0)
+}
+
+/// Returns the position after the whitespace starting at `pos`.
+fn space(text: &[u8], mut pos: usize) -> usize {
+    while matches!(byte(text, pos), b' ' | b'\t' | b'\n' | b'\r') {
+        pos += 1;
+    }
+    pos
+}
+
+/// Returns the position after the decimal digits starting at `pos`.
+fn digits(text: &[u8], mut pos: usize) -> usize {
+    while byte(text, pos).is_ascii_digit() {
+        pos += 1;
+    }
+    pos
+}
+
+/// Returns the end of the string literal starting with the quote at `pos`, or `None` if it
+/// is not a valid one.
+fn skip_string(text: &[u8], mut pos: usize) -> Option<usize> {
+    pos += 1;
+    loop {
+        match byte(text, pos) {
+            b'"' => return Some(pos + 1),
+            b'\\' => match byte(text, pos + 1) {
+                b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => pos += 2,
+                b'u' if (pos + 2..pos + 6).all(|at| byte(text, at).is_ascii_hexdigit()) => pos += 6,
+                _ => return None,
+            },
+            // Control characters, and the end of the text, must not appear in a string
+            0..=0x1f => return None,
+            _ => pos += 1,
+        }
+    }
+}
+
+/// Returns the end of the number starting at `pos`, or `None` if it is not a valid one.
+fn skip_number(text: &[u8], mut pos: usize) -> Option<usize> {
+    if byte(text, pos) == b'-' {
+        pos += 1;
+    }
+    let end = digits(text, pos);
+    // One or more digits, without leading zeros
+    if end == pos || (byte(text, pos) == b'0' && end > pos + 1) {
+        return None;
+    }
+    pos = end;
+    if byte(text, pos) == b'.' {
+        let end = digits(text, pos + 1);
+        if end == pos + 1 {
+            return None;
+        }
+        pos = end;
+    }
+    if matches!(byte(text, pos), b'e' | b'E') {
+        pos += 1;
+        if matches!(byte(text, pos), b'+' | b'-') {
+            pos += 1;
+        }
+        let end = digits(text, pos);
+        if end == pos {
+            return None;
+        }
+        pos = end;
+    }
+    Some(pos)
+}
+
+/// Returns the end of the value starting at `pos`, or `None` if there is no valid value
+/// there.
+///
+/// # Arguments
+///
+/// * `depth` - How many arrays and objects the value is inside.
+fn skip(text: &[u8], pos: usize, depth: usize) -> Option<usize> {
+    match byte(text, pos) {
+        open @ (b'[' | b'{') => {
+            if depth == MAX_DEPTH {
+                return None;
+            }
+            let close = if open == b'[' { b']' } else { b'}' };
+            let mut pos = space(text, pos + 1);
+            if byte(text, pos) == close {
+                return Some(pos + 1);
+            }
+            loop {
+                if open == b'{' {
+                    if byte(text, pos) != b'"' {
+                        return None;
+                    }
+                    pos = space(text, skip_string(text, pos)?);
+                    if byte(text, pos) != b':' {
+                        return None;
+                    }
+                    pos = space(text, pos + 1);
+                }
+                pos = space(text, skip(text, pos, depth + 1)?);
+                match byte(text, pos) {
+                    b',' => pos = space(text, pos + 1),
+                    found if found == close => return Some(pos + 1),
+                    _ => return None,
+                }
+            }
+        }
+        b'"' => skip_string(text, pos),
+        b'-' | b'0'..=b'9' => skip_number(text, pos),
+        _ => ["true", "false", "null"]
+            .iter()
+            .find(|literal| text[pos..].starts_with(literal.as_bytes()))
+            .map(|literal| pos + literal.len()),
+    }
+}
+
+/// Checks that `text` is a valid JSON document.
+///
+/// # Returns
+///
+/// The position its value starts at, or a runtime error if it is not valid.
+fn check(text: &[u8]) -> Result<usize, VentiError> {
+    let start = space(text, 0);
+    let end = skip(text, start, 0).ok_or_else(invalid)?;
+    if space(text, end) == text.len() {
+        Ok(start)
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Reads the four hex digits at `pos`.
+fn hex(text: &[u8], pos: usize) -> u32 {
+    (pos..pos + 4).fold(0, |code, at| {
+        code * 16 + char::from(byte(text, at)).to_digit(16).unwrap_or(0)
+    })
+}
+
+/// Decodes the valid string literal starting with the quote at `pos`.
+///
+/// A `\u` escape of a lone surrogate, which no character has, decodes to U+FFFD.
+fn unescape(text: &[u8], mut pos: usize) -> String {
+    let mut bytes = Vec::new();
+    pos += 1;
+    loop {
+        match byte(text, pos) {
+            b'"' => break,
+            b'\\' => {
+                let escaped = match byte(text, pos + 1) {
+                    b'b' => '\u{8}',
+                    b'f' => '\u{c}',
+                    b'n' => '\n',
+                    b'r' => '\r',
+                    b't' => '\t',
+                    b'u' => {
+                        let mut code = hex(text, pos + 2);
+                        if (0xd800..0xdc00).contains(&code)
+                            && text[pos + 6..].starts_with(b"\\u")
+                            && (0xdc00..0xe000).contains(&hex(text, pos + 8))
+                        {
+                            code =
+                                0x10000 + ((code - 0xd800) << 10) + (hex(text, pos + 8) - 0xdc00);
+                            pos += 6;
+                        }
+                        pos += 4;
+                        char::from_u32(code).unwrap_or('\u{fffd}')
+                    }
+                    other => char::from(other),
+                };
+                let mut encoded = [0; 4];
+                bytes.extend_from_slice(escaped.encode_utf8(&mut encoded).as_bytes());
+                pos += 2;
+            }
+            other => {
+                bytes.push(other);
+                pos += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Reads the valid value starting at `pos`, leaving `pos` at its end.
+fn build(text: &[u8], pos: &mut usize) -> Rc<Json> {
+    let start = *pos;
+    let json = match byte(text, start) {
+        open @ (b'[' | b'{') => {
+            let mut elements = Vec::new();
+            let mut members = Vec::new();
+            *pos = space(text, start + 1);
+            while !matches!(byte(text, *pos), b']' | b'}') {
+                if open == b'{' {
+                    let key = unescape(text, *pos);
+                    let end = skip_string(text, *pos).unwrap_or(*pos);
+                    // Past the colon
+                    *pos = space(text, space(text, end) + 1);
+                    members.push((key, build(text, pos)));
+                } else {
+                    elements.push(build(text, pos));
+                }
+                *pos = space(text, *pos);
+                if byte(text, *pos) == b',' {
+                    *pos = space(text, *pos + 1);
+                }
+            }
+            *pos += 1;
+            return Rc::new(if open == b'[' {
+                Json::Array(elements)
+            } else {
+                Json::Object(members)
+            });
+        }
+        b'"' => Json::String(unescape(text, start)),
+        b't' => Json::Bool(true),
+        b'f' => Json::Bool(false),
+        b'n' => Json::Null,
+        _ => {
+            let end = skip_number(text, start).unwrap_or(start);
+            Json::Number(String::from_utf8_lossy(&text[start..end]).into_owned())
+        }
+    };
+    *pos = skip(text, start, 0).unwrap_or(start);
+    Rc::new(json)
+}
+
+/// Reads the document `json` into a JSON value.
+///
+/// # Returns
+///
+/// The value, or a runtime error if `json` is not valid JSON.
+pub fn parse(json: &str) -> Result<Rc<Json>, VentiError> {
+    let text = json.as_bytes();
+    let mut pos = check(text)?;
+    Ok(build(text, &mut pos))
+}
+
+impl Json {
+    /// Returns the kind of the value as `json_kind` gives it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "bool",
+            Json::Number(_) => "number",
+            Json::String(_) => "string",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    /// Returns the last member named `key` of an object, or null if it has none or is not
+    /// an object.
+    pub fn member(&self, key: &str) -> Rc<Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .rev()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.clone()),
+            _ => None,
+        }
+        .unwrap_or_else(|| Rc::new(Json::Null))
+    }
+
+    /// Returns element `index` of an array, or null if it has none or is not an array.
+    pub fn element(&self, index: i64) -> Rc<Json> {
+        match self {
+            Json::Array(elements) => usize::try_from(index)
+                .ok()
+                .and_then(|index| elements.get(index).cloned()),
+            _ => None,
+        }
+        .unwrap_or_else(|| Rc::new(Json::Null))
+    }
+
+    /// Returns the number of elements of an array or members of an object, and 0 for any
+    /// other value.
+    pub fn count(&self) -> i64 {
+        match self {
+            Json::Array(elements) => elements.len() as i64,
+            Json::Object(members) => members.len() as i64,
+            _ => 0,
+        }
+    }
+
+    /// Converts the value to `target` the way `as` does.
+    ///
+    /// # Returns
+    ///
+    /// The converted value, or a runtime error if the value is of another kind: only a
+    /// number converts to an int or float, a bool to a bool, and a string or null to a
+    /// string, null becoming `nothing`.
+    pub fn cast<'a>(&self, target: &VarType) -> Result<Value<'a>, VentiError> {
+        match (self, target) {
+            // Integers keep every digit, and other numbers truncate and saturate like floats
+            (Json::Number(text), VarType::Int) => {
+                Ok(Value::Int(text.parse().unwrap_or_else(|_| {
+                    text.parse::<f64>().unwrap_or(0.0) as i64
+                })))
+            }
+            (Json::Number(text), VarType::Float) => Ok(Value::Float(text.parse().unwrap_or(0.0))),
+            (Json::Bool(b), VarType::Bool) => Ok(Value::Bool(*b)),
+            (Json::String(text), VarType::String) => Ok(Value::Str(Some(Rc::from(text.as_str())))),
+            (Json::Null, VarType::String) => Ok(Value::Str(None)),
+            _ => Err(VentiError::RuntimeError(
+                format!("Cannot convert a JSON {} to {}", self.kind(), target.name()),
+                None,
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    /// Formats the value the way `printventi` prints it: a string as its text, and any
+    /// other value as compact JSON.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::String(text) => write!(f, "{}", text),
+            json => {
+                let mut out = String::new();
+                write_json(json, &mut out);
+                write!(f, "{}", out)
+            }
+        }
+    }
+}
+
+/// Writes `text` as a JSON string literal.
+fn quote(text: &str, out: &mut String) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0'..='\u{1f}' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Writes the JSON value `json` as JSON, with its numbers as they were written.
+fn write_json(json: &Json, out: &mut String) {
+    match json {
+        Json::Null => out.push_str("null"),
+        Json::Bool(b) => out.push_str(&b.to_string()),
+        Json::Number(text) => out.push_str(text),
+        Json::String(text) => quote(text, out),
+        Json::Array(elements) => {
+            out.push('[');
+            for (index, element) in elements.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_json(element, out);
+            }
+            out.push(']');
+        }
+        Json::Object(members) => {
+            out.push('{');
+            for (index, (key, value)) in members.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                quote(key, out);
+                out.push(':');
+                write_json(value, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Writes `value` as JSON.
+fn write(value: &Value, out: &mut String) -> Result<(), VentiError> {
+    match value {
+        Value::Int(n) => out.push_str(&n.to_string()),
+        // JSON has no infinities or NaN
+        Value::Float(x) if x.is_finite() => out.push_str(&value.to_string()),
+        Value::Float(_) | Value::Str(None) => out.push_str("null"),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Str(Some(text)) => quote(text, out),
+        Value::Json(json) => write_json(json, out),
+        Value::Array(elements) => {
+            out.push('[');
+            for (index, element) in elements.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write(element, out)?;
+            }
+            out.push(']');
+        }
+        Value::Map(map) => {
+            let map = map.borrow();
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (index, key) in keys.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                quote(key, out);
+                out.push(':');
+                out.push_str(&map[key].to_string());
+            }
+            out.push('}');
+        }
+        value => {
+            return Err(VentiError::RuntimeError(
+                format!("'json_stringify' cannot write a {}", value.type_name()),
+                None,
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Returns `value` written as JSON.
+pub fn stringify(value: &Value) -> Result<String, VentiError> {
+    let mut out = String::new();
+    write(value, &mut out)?;
+    Ok(out)
+}
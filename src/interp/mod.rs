@@ -0,0 +1,7 @@
+pub mod environment;
+pub mod http;
+pub mod interpreter;
+pub mod json;
+pub mod net;
+pub mod random;
+pub mod value;
@@ -0,0 +1,178 @@
+use crate::errors::VentiError;
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use std::collections::HashMap;
+
+/*
+The TCP builtins work on numbered sockets, as they do in the LLVM and C runtimes, where the
+number is the socket's file descriptor. The interpreter keeps its sockets in a table and
+hands out numbers from 3 up, past stdin, stdout and stderr, so a program sees the same
+kind of number whichever way it runs. A number that is not in the table fails the way a
+closed file descriptor would.
+
+The sockets are async-std's. The interpreter runs async calls to completion when they are
+made, so each operation is blocked on until it is done: a task that connects to a
+listening socket the program accepts on later still works, since the connection waits in
+the listener's backlog, but one that waits for an answer would wait forever.
+*/
+
+/// The most bytes `tcp_recv` returns at once, as in the LLVM and C runtimes.
+pub const CHUNK_SIZE: usize = 4096;
+
+/// The first number handed out for a socket.
+const FIRST_SOCKET: i64 = 3;
+
+/// Returns a runtime error with `message`.
+fn failure(message: impl Into<String>) -> VentiError {
+    VentiError::RuntimeError(message.into(), None)
+}
+
+/// Opens a TCP connection to `port` on `host`.
+///
+/// # Returns
+///
+/// The connection, or a runtime error if the host cannot be resolved or reached.
+pub async fn connect(host: &str, port: i64) -> Result<TcpStream, VentiError> {
+    let refused = || failure(format!("could not connect to {}:{}", host, port));
+    let port = u16::try_from(port).map_err(|_| refused())?;
+    TcpStream::connect((host, port))
+        .await
+        .map_err(|_| refused())
+}
+
+/// A socket a program opened.
+#[derive(Debug)]
+enum Socket {
+    Connection(TcpStream),
+    Listener(TcpListener),
+}
+
+/// The sockets behind the TCP builtins, by the numbers the program knows them by.
+#[derive(Debug)]
+pub struct Sockets {
+    open: HashMap<i64, Socket>,
+    next: i64,
+}
+
+impl Default for Sockets {
+    fn default() -> Self {
+        Sockets {
+            open: HashMap::new(),
+            next: FIRST_SOCKET,
+        }
+    }
+}
+
+impl Sockets {
+    /// Adds `socket` to the table.
+    ///
+    /// # Returns
+    ///
+    /// The number the program knows it by.
+    fn add(&mut self, socket: Socket) -> i64 {
+        let number = self.next;
+        self.next += 1;
+        self.open.insert(number, socket);
+        number
+    }
+
+    /// Returns the connection numbered `socket`, or `None` if there is no such connection.
+    fn connection(&self, socket: i64) -> Option<&TcpStream> {
+        match self.open.get(&socket) {
+            Some(Socket::Connection(stream)) => Some(stream),
+            _ => None,
+        }
+    }
+
+    /// Opens a TCP connection to `port` on `host`.
+    ///
+    /// # Returns
+    ///
+    /// The number of the connection, or a runtime error if the host cannot be resolved or
+    /// reached.
+    pub fn connect(&mut self, host: &str, port: i64) -> Result<i64, VentiError> {
+        let stream = task::block_on(connect(host, port))?;
+        Ok(self.add(Socket::Connection(stream)))
+    }
+
+    /// Opens a socket listening for TCP connections to `port` on any of this machine's
+    /// addresses.
+    ///
+    /// # Returns
+    ///
+    /// The number of the socket, or a runtime error if the port cannot be listened on.
+    pub fn listen(&mut self, port: i64) -> Result<i64, VentiError> {
+        let refused = || failure(format!("could not listen on port {}", port));
+        let port = u16::try_from(port).map_err(|_| refused())?;
+        let listener =
+            task::block_on(TcpListener::bind(("0.0.0.0", port))).map_err(|_| refused())?;
+        Ok(self.add(Socket::Listener(listener)))
+    }
+
+    /// Waits for a connection to the listening socket numbered `listener`.
+    ///
+    /// # Returns
+    ///
+    /// The number of the connection, or a runtime error if `listener` is not listening.
+    pub fn accept(&mut self, listener: i64) -> Result<i64, VentiError> {
+        let refused = || failure("could not accept a connection");
+        let stream = match self.open.get(&listener) {
+            Some(Socket::Listener(listener)) => task::block_on(listener.accept())
+                .map(|(stream, _)| stream)
+                .map_err(|_| refused())?,
+            _ => return Err(refused()),
+        };
+        Ok(self.add(Socket::Connection(stream)))
+    }
+
+    /// Returns the port the socket numbered `listener` listens on, which is the one the
+    /// system picked when it was asked to listen on port 0.
+    ///
+    /// # Returns
+    ///
+    /// The port, or a runtime error if `listener` is not open.
+    pub fn port(&self, listener: i64) -> Result<i64, VentiError> {
+        let missing = || failure(format!("could not find the port of socket {}", listener));
+        let address = match self.open.get(&listener) {
+            Some(Socket::Listener(socket)) => socket.local_addr(),
+            Some(Socket::Connection(socket)) => socket.local_addr(),
+            None => return Err(missing()),
+        };
+        address
+            .map(|address| i64::from(address.port()))
+            .map_err(|_| missing())
+    }
+
+    /// Sends `data` over the connection numbered `socket`.
+    ///
+    /// # Returns
+    ///
+    /// A runtime error if the connection was closed or failed.
+    pub fn send(&self, socket: i64, data: &[u8]) -> Result<(), VentiError> {
+        let closed = || failure("could not send: the connection is closed");
+        let mut stream = self.connection(socket).ok_or_else(closed)?;
+        task::block_on(stream.write_all(data)).map_err(|_| closed())
+    }
+
+    /// Receives the next bytes sent over the connection numbered `socket`, at most
+    /// `CHUNK_SIZE` of them.
+    ///
+    /// # Returns
+    ///
+    /// The bytes, none once the peer has closed the connection, or a runtime error if it
+    /// failed.
+    pub fn recv(&self, socket: i64) -> Result<Vec<u8>, VentiError> {
+        let failed = || failure("could not receive: the connection failed");
+        let mut stream = self.connection(socket).ok_or_else(failed)?;
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let count = task::block_on(stream.read(&mut chunk)).map_err(|_| failed())?;
+        chunk.truncate(count);
+        Ok(chunk)
+    }
+
+    /// Closes the connection or listening socket numbered `socket`, if it is open.
+    pub fn close(&mut self, socket: i64) {
+        self.open.remove(&socket);
+    }
+}
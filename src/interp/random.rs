@@ -0,0 +1,63 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*
+The random builtins use splitmix64, the same generator the LLVM and C runtimes emit, so a
+program seeded with `seed_random` draws the same numbers whichever way it runs. The state
+advances by a fixed odd step on every draw, and the draw is the new state run through a
+mixing function. A state of zero means the generator has not been seeded yet, so the
+first draw seeds it from the clock.
+*/
+
+/// The step the state advances by on every draw, the golden ratio as a 64-bit fraction.
+pub const STEP: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The random number generator behind `random`, `random_int` and `seed_random`.
+#[derive(Debug, Default)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// Seeds the generator, so the numbers drawn after it are the same on every run.
+    pub fn seed(&mut self, seed: i64) {
+        self.state = seed as u64 ^ STEP;
+    }
+
+    /// Draws the next 64 random bits, seeding the generator from the clock if it has not
+    /// been seeded.
+    pub fn bits(&mut self) -> u64 {
+        if self.state == 0 {
+            let seconds = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs());
+            self.state = seconds.wrapping_mul(STEP) | 1;
+        }
+        self.state = self.state.wrapping_add(STEP);
+        mix(self.state)
+    }
+
+    /// Draws a float from 0 up to but not including 1.
+    pub fn float(&mut self) -> f64 {
+        (self.bits() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Draws an integer from `low` to `high`, both included, or `None` when `high` is
+    /// below `low`.
+    pub fn int(&mut self, low: i64, high: i64) -> Option<i64> {
+        if high < low {
+            return None;
+        }
+        // The count wraps to zero when the range covers every integer
+        let count = (high as u64).wrapping_sub(low as u64).wrapping_add(1);
+        let bits = self.bits();
+        let offset = if count == 0 { bits } else { bits % count };
+        Some((low as u64).wrapping_add(offset) as i64)
+    }
+}
+
+/// Scrambles a state into a draw, the output function of splitmix64.
+fn mix(state: u64) -> u64 {
+    let z = (state ^ (state >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,145 @@
+use crate::interp::json::Json;
+use crate::venti_parser::ast::{Expr, Parameter};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::rc::Rc;
+
+/// A value computed by the interpreter.
+///
+/// Values follow the representations codegen uses: comparisons of integers yield `Int`,
+/// an inclusive range is stored with its end bumped by one, and maps, buffers, channels and
+/// iterators are handles, so every copy of one sees the same entries.
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// A string, or `nothing` when there is no text.
+    Str(Option<Rc<str>>),
+    Array(Rc<Vec<Value<'a>>>),
+    Map(Rc<RefCell<HashMap<String, i64>>>),
+    Buffer(Rc<RefCell<Vec<i64>>>),
+    Range {
+        start: i64,
+        end: i64,
+        step: i64,
+    },
+    Lambda(Rc<Closure<'a>>),
+    /// A finished async call or spawned block, holding its result.
+    Task(i64),
+    /// The queue of a channel, front first.
+    Channel(Rc<RefCell<VecDeque<Value<'a>>>>),
+    Iterator(Rc<RefCell<Iter<'a>>>),
+    Vector(Rc<RefCell<Vec<i64>>>),
+    /// The strings `split` and `args` give, which cannot be changed.
+    StringVector(Rc<Vec<Rc<str>>>),
+    Json(Rc<Json>),
+}
+
+/// Where the values of an iterator come from, which `next_value` takes them from one at a
+/// time.
+#[derive(Debug)]
+pub enum Iter<'a> {
+    /// Counts from `next` up to but not including `end`.
+    Count { next: i64, end: i64, step: i64 },
+    /// The elements of an array, buffer or vector still to come, copied when the iterator
+    /// was made.
+    Values(VecDeque<i64>),
+    /// The values of `source` passed through `function`.
+    Map {
+        source: Rc<RefCell<Iter<'a>>>,
+        function: Rc<Closure<'a>>,
+    },
+    /// The values of `source` that `function` keeps.
+    Filter {
+        source: Rc<RefCell<Iter<'a>>>,
+        function: Rc<Closure<'a>>,
+    },
+}
+
+/// A lambda together with the locals it captured when it was created.
+#[derive(Debug)]
+pub struct Closure<'a> {
+    pub params: &'a [Parameter],
+    pub body: &'a Expr<'a>,
+    pub captures: Vec<(String, Value<'a>)>,
+}
+
+impl<'a> Value<'a> {
+    /// Returns the name of the value's type, as the analyzer spells it.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::Str(Some(_)) => "string",
+            Value::Str(None) => "nothing",
+            Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Buffer(_) => "buffer",
+            Value::Range { .. } => "range",
+            Value::Lambda(_) => "lambda",
+            Value::Task(_) => "task",
+            Value::Channel(_) => "channel",
+            Value::Iterator(_) => "iterator",
+            Value::Vector(_) => "vec",
+            Value::StringVector(_) => "vec of string",
+            Value::Json(_) => "json",
+        }
+    }
+
+    /// Returns the integer stored for an `int` or `bool`, where `true` is 1.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Bool(b) => Some(*b as i64),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    /// Formats the value the way `printventi` prints it.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", format_float(*x)),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(Some(text)) => write!(f, "{}", text),
+            Value::Str(None) => write!(f, "nothing"),
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Value::Range { start, end, .. } => write!(f, "{}..{}", start, end),
+            Value::Map(_) => write!(f, "map"),
+            Value::Buffer(_) => write!(f, "buffer"),
+            Value::Lambda(_) => write!(f, "lambda"),
+            Value::Task(_) => write!(f, "task"),
+            Value::Channel(_) => write!(f, "channel"),
+            Value::Iterator(_) => write!(f, "iterator"),
+            Value::Vector(_) => write!(f, "vec"),
+            Value::StringVector(_) => write!(f, "vec of string"),
+            Value::Json(json) => write!(f, "{}", json),
+        }
+    }
+}
+
+/// Formats a float like printf's `%f`, including its spelling of infinities and NaN.
+fn format_float(x: f64) -> String {
+    let sign = if x.is_sign_negative() { "-" } else { "" };
+    if x.is_nan() {
+        format!("{}nan", sign)
+    } else if x.is_infinite() {
+        format!("{}inf", sign)
+    } else {
+        format!("{:.6}", x)
+    }
+}
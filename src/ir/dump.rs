@@ -0,0 +1,291 @@
+use crate::ir::ir::{
+    Constant, Function, Instruction, Local, NextPart, Operand, Place, Program, RangePart, Rvalue,
+    Terminator,
+};
+use crate::venti_parser::ast::{BinOp, UnaryOp};
+use std::fmt::Write;
+
+/*
+`--dump-ir` prints the lowered program in a text form meant for reading, not parsing
+back. Globals come first, then the externs, then each function with its blocks:
+
+    global @0: int total
+
+    fn main() -> int {
+    bb0:
+        %0: int = call f(1, 2)
+        @0 = %0
+        print @0
+        return
+    }
+
+A local is `%n` and a global `@n`, and each named local is declared with its type and
+name where it is first assigned or, for a parameter, in the function's header.
+*/
+
+/// Renders `program` as text.
+pub fn dump(program: &Program) -> String {
+    let mut out = String::new();
+    for (id, global) in program.globals.iter().enumerate() {
+        let _ = writeln!(out, "global @{}: {} {}", id, global.ty, global.name);
+    }
+    for declared in &program.externs {
+        let params = declared
+            .params
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = match &declared.returns {
+            Some(returns) => writeln!(
+                out,
+                "extern fn {}({}) -> {}",
+                declared.name, params, returns
+            ),
+            None => writeln!(out, "extern fn {}({})", declared.name, params),
+        };
+    }
+    for function in &program.functions {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        dump_function(&mut out, function);
+    }
+    out
+}
+
+fn dump_function(out: &mut String, function: &Function) {
+    let declare = |local: &Local| {
+        let decl = function.local(*local);
+        match &decl.name {
+            Some(name) => format!("%{}: {} {}", local.0, decl.ty, name),
+            None => format!("%{}: {}", local.0, decl.ty),
+        }
+    };
+    let params = function
+        .params
+        .iter()
+        .map(declare)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let _ = writeln!(
+        out,
+        "fn {}({}) -> {} {{",
+        function.name, params, function.returns
+    );
+    let mut declared = vec![false; function.locals.len()];
+    for param in &function.params {
+        declared[param.0 as usize] = true;
+    }
+    for (id, block) in function.blocks.iter().enumerate() {
+        let _ = writeln!(out, "bb{}:", id);
+        for instruction in &block.instructions {
+            let line = match instruction {
+                Instruction::Assign {
+                    place: Place::Local(local),
+                    value,
+                } if !declared[local.0 as usize] => {
+                    declared[local.0 as usize] = true;
+                    format!("{} = {}", declare(local), rvalue(value))
+                }
+                Instruction::Assign { place, value } => {
+                    format!("{} = {}", self::place(*place), rvalue(value))
+                }
+                Instruction::Eval(value) => rvalue(value),
+                Instruction::SetIndex {
+                    target,
+                    index,
+                    value,
+                } => format!(
+                    "{}[{}] = {}",
+                    operand(target),
+                    operand(index),
+                    operand(value)
+                ),
+                Instruction::Print { value, newline } => match newline {
+                    true => format!("println {}", operand(value)),
+                    false => format!("print {}", operand(value)),
+                },
+                Instruction::Assert(condition) => format!("assert {}", operand(condition)),
+                Instruction::EndTry => "end_try".to_string(),
+                Instruction::Line(span) => format!("# line {}", span.line),
+            };
+            let _ = writeln!(out, "    {}", line);
+        }
+        let line = match &block.terminator {
+            // The message is declared where the handler receives it
+            Terminator::Try {
+                body,
+                error,
+                handler,
+            } if !declared[error.0 as usize] => {
+                declared[error.0 as usize] = true;
+                format!("try bb{}, catch bb{} {}", body.0, handler.0, declare(error))
+            }
+            terminator => self::terminator(terminator),
+        };
+        let _ = writeln!(out, "    {}", line);
+    }
+    out.push_str("}\n");
+}
+
+fn place(place: Place) -> String {
+    match place {
+        Place::Local(local) => format!("%{}", local.0),
+        Place::Global(global) => format!("@{}", global.0),
+    }
+}
+
+fn constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Int(n) => n.to_string(),
+        Constant::Float(f) => format!("{:?}", f),
+        Constant::Bool(b) => b.to_string(),
+        Constant::String(s) => format!("{:?}", s),
+        Constant::Nothing => "nothing".to_string(),
+    }
+}
+
+fn operand(operand: &Operand) -> String {
+    match operand {
+        Operand::Place(place) => self::place(*place),
+        Operand::Constant(constant) => self::constant(constant),
+    }
+}
+
+fn operands(operands: &[Operand]) -> String {
+    operands.iter().map(operand).collect::<Vec<_>>().join(", ")
+}
+
+fn rvalue(value: &Rvalue) -> String {
+    match value {
+        Rvalue::Use(value) => operand(value),
+        Rvalue::Binary(op, left, right) => {
+            format!("{} {}, {}", binary(*op), operand(left), operand(right))
+        }
+        Rvalue::Unary(op, value) => {
+            let name = match op {
+                UnaryOp::Negate => "neg",
+                UnaryOp::Not => "not",
+            };
+            format!("{} {}", name, operand(value))
+        }
+        Rvalue::Cast(value, ty) => format!("cast {} to {}", operand(value), ty),
+        Rvalue::IsNothing(value) => format!("is_nothing {}", operand(value)),
+        Rvalue::Call { function, args } => format!("call {}({})", function, operands(args)),
+        Rvalue::CallClosure { callee, args } => {
+            format!("call_closure {}({})", operand(callee), operands(args))
+        }
+        Rvalue::Closure { function, captures } => {
+            format!("closure {}[{}]", function, operands(captures))
+        }
+        Rvalue::Spawn { function, captures } => {
+            format!("spawn {}[{}]", function, operands(captures))
+        }
+        Rvalue::Await(task) => format!("await {}", operand(task)),
+        Rvalue::Array(elements) => format!("array [{}]", operands(elements)),
+        Rvalue::Map(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", operand(key), operand(value)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("map {{{}}}", entries)
+        }
+        Rvalue::Range {
+            start,
+            end,
+            inclusive,
+        } => {
+            let dots = if *inclusive { "..=" } else { ".." };
+            format!("range {}{}{}", operand(start), dots, operand(end))
+        }
+        Rvalue::RangePart(range, part) => {
+            let part = match part {
+                RangePart::Start => "start",
+                RangePart::End => "end",
+                RangePart::Step => "step",
+            };
+            format!("{} {}", part, operand(range))
+        }
+        Rvalue::Next(iterator) => format!("next {}", operand(iterator)),
+        Rvalue::NextPart(pair, part) => {
+            let part = match part {
+                NextPart::Value => "value",
+                NextPart::Done => "done",
+            };
+            format!("{} {}", part, operand(pair))
+        }
+        Rvalue::Len(value) => format!("len {}", operand(value)),
+        Rvalue::Index { target, index } => format!("{}[{}]", operand(target), operand(index)),
+        Rvalue::Method {
+            receiver,
+            name,
+            args,
+        } => format!("{}.{}({})", operand(receiver), name, operands(args)),
+    }
+}
+
+/// The name of a binary operator in the IR.
+fn binary(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "add",
+        BinOp::Subtract => "sub",
+        BinOp::Multiply => "mul",
+        BinOp::Divide => "div",
+        BinOp::Equal => "eq",
+        BinOp::NotEqual => "ne",
+        BinOp::Less => "lt",
+        BinOp::LessEqual => "le",
+        BinOp::Greater => "gt",
+        BinOp::GreaterEqual => "ge",
+        BinOp::And => "and",
+        BinOp::Or => "or",
+        BinOp::BitAnd => "bitand",
+        BinOp::BitOr => "bitor",
+        BinOp::BitXor => "xor",
+        BinOp::ShiftLeft => "shl",
+        BinOp::ShiftRight => "shr",
+        BinOp::Coalesce => "coalesce",
+    }
+}
+
+fn terminator(terminator: &Terminator) -> String {
+    match terminator {
+        Terminator::Goto(block) => format!("goto bb{}", block.0),
+        Terminator::Branch {
+            condition,
+            then,
+            otherwise,
+        } => format!(
+            "branch {}, bb{}, bb{}",
+            operand(condition),
+            then.0,
+            otherwise.0
+        ),
+        Terminator::Switch {
+            value,
+            cases,
+            otherwise,
+        } => {
+            let cases = cases
+                .iter()
+                .map(|(case, block)| format!("{} => bb{}", constant(case), block.0))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "switch {} [{}], otherwise bb{}",
+                operand(value),
+                cases,
+                otherwise.0
+            )
+        }
+        Terminator::Try {
+            body,
+            error,
+            handler,
+        } => format!("try bb{}, catch bb{} %{}", body.0, handler.0, error.0),
+        Terminator::Return(Some(value)) => format!("return {}", operand(value)),
+        Terminator::Return(None) => "return".to_string(),
+    }
+}
@@ -0,0 +1,299 @@
+use crate::semantic::types::Type;
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::ast::{BinOp, InlineHint, UnaryOp};
+
+/*
+The IR sits between the checked syntax tree and the C backend, which is the only backend
+that generates code from it; the LLVM backend and the interpreter work on the syntax tree,
+and constants are folded in the tree before it is lowered. Where the tree follows what
+people write, the IR follows what a machine does:
+
+    - every value has a type, worked out once by the lowering instead of again by the
+      backend as it generates code
+    - every operand is a constant or a place, so nested expressions become a sequence of
+      assignments to numbered temporaries
+    - control flow is basic blocks ending in a branch, switch, try or return, so
+      `for_venti` and `match_venti` are loops and switches, and `??`, `&&` and `||`
+      evaluate their right side only when they need to
+    - lambdas and spawned blocks are functions of their own, which take the values they
+      capture before their parameters, and nested functions are lifted to the top level
+
+A function's locals are numbered in one list, in the order the lowering needed them, so
+the captures of a closure, found as its body uses them, may come after its parameters; a
+local with a name is a variable of the program. The top-level statements
+are the function `main`, whose top-level variables are globals, since every function
+sees them.
+
+    fn add(%0: int a, %1: int b) -> int {
+    bb0:
+        %2: int = add %0, %1
+        return %2
+    }
+*/
+
+/// A local of a function, by its position in `Function::locals`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Local(pub u32);
+
+/// A basic block of a function, by its position in `Function::blocks`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockId(pub u32);
+
+/// A variable defined at the top level, by its position in `Program::globals`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlobalId(pub u32);
+
+/// A lowered program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub globals: Vec<Global>,
+    /// The C functions the program declares.
+    pub externs: Vec<Extern>,
+    /// Every function, `main` first, then the others, each after the lambdas and spawned
+    /// blocks in it.
+    pub functions: Vec<Function>,
+}
+
+impl Program {
+    /// The function named `name`, if the program has one.
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extern {
+    pub name: String,
+    pub params: Vec<Type>,
+    /// The result type, or `None` for a C function returning `void`, which gives 0.
+    pub returns: Option<Type>,
+}
+
+/// What a function was written as, which decides how the backends call it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionKind {
+    /// The top-level statements of the program.
+    Main,
+    Function,
+    /// An `async_venti` function, which returns a task.
+    Async,
+    /// A `test_venti` block.
+    Test,
+    Lambda,
+    /// The body of a `spawn_venti` block, which runs on a thread of its own.
+    Spawn,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub kind: FunctionKind,
+    /// The locals holding the arguments, in order. Those of a lambda or spawned block
+    /// start with the values it captures.
+    pub params: Vec<Local>,
+    /// How many of `params` are captured values.
+    pub captures: usize,
+    pub returns: Type,
+    pub inline: Option<InlineHint>,
+    pub locals: Vec<LocalDecl>,
+    /// The basic blocks, starting with the entry block.
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    /// The declaration of `local`.
+    pub fn local(&self, local: Local) -> &LocalDecl {
+        &self.locals[local.0 as usize]
+    }
+
+    /// The block `block`.
+    pub fn block(&self, block: BlockId) -> &Block {
+        &self.blocks[block.0 as usize]
+    }
+}
+
+/// A local's type and, when it is a variable of the program, its name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalDecl {
+    /// The name of the variable, or `None` for a temporary.
+    pub name: Option<String>,
+    pub ty: Type,
+}
+
+/// A straight run of instructions, left only by its terminator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+/// Where a value is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Place {
+    Local(Local),
+    Global(GlobalId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Nothing,
+}
+
+impl Constant {
+    /// The type of the constant.
+    pub fn ty(&self) -> Type {
+        match self {
+            Constant::Int(_) => Type::Int,
+            Constant::Float(_) => Type::Float,
+            Constant::Bool(_) => Type::Bool,
+            Constant::String(_) => Type::String,
+            Constant::Nothing => Type::Nothing,
+        }
+    }
+}
+
+/// A value an instruction reads, which is never computed in place.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Place(Place),
+    Constant(Constant),
+}
+
+/// The parts of a range a loop reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangePart {
+    Start,
+    /// The end, one past the last value of an inclusive range too.
+    End,
+    Step,
+}
+
+/// The parts of the pair an iterator's `next` gives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NextPart {
+    Value,
+    /// Whether the iterator had run out, in which case there is no value.
+    Done,
+}
+
+/// A computation whose result an instruction stores.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rvalue {
+    Use(Operand),
+    Binary(BinOp, Operand, Operand),
+    Unary(UnaryOp, Operand),
+    /// Converts a number or bool, or makes a value of a type implementing a trait into a
+    /// value of the trait, which carries the table of its methods.
+    Cast(Operand, Type),
+    /// Whether a string is `nothing`.
+    IsNothing(Operand),
+    /// A call of a function the program defines or declares, or of a builtin.
+    Call {
+        function: String,
+        args: Vec<Operand>,
+    },
+    /// A call of a lambda value.
+    CallClosure {
+        callee: Operand,
+        args: Vec<Operand>,
+    },
+    /// A lambda value: the lifted function and the values it captures.
+    Closure {
+        function: String,
+        captures: Vec<Operand>,
+    },
+    /// Starts the lifted body of a `spawn_venti` block on a thread, with the values it
+    /// captures, giving the task to join.
+    Spawn {
+        function: String,
+        captures: Vec<Operand>,
+    },
+    Await(Operand),
+    Array(Vec<Operand>),
+    Map(Vec<(Operand, Operand)>),
+    Range {
+        start: Operand,
+        end: Operand,
+        inclusive: bool,
+    },
+    RangePart(Operand, RangePart),
+    /// Takes the next value of an iterator, giving the pair of it and whether the iterator
+    /// had run out.
+    Next(Operand),
+    NextPart(Operand, NextPart),
+    /// The number of elements of an array, buffer or map, or of bytes of a string.
+    Len(Operand),
+    Index {
+        target: Operand,
+        index: Operand,
+    },
+    /// A call of a built-in method, or of a method of a trait on a value of the trait,
+    /// which goes through the value's table. A trait method on a value of a known type
+    /// is a `Call` of the function implementing it.
+    Method {
+        receiver: Operand,
+        name: String,
+        args: Vec<Operand>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Assign {
+        place: Place,
+        value: Rvalue,
+    },
+    /// Computes a value for its effects, such as a call whose result is not used.
+    Eval(Rvalue),
+    /// Stores into a map entry or a buffer slot.
+    SetIndex {
+        target: Operand,
+        index: Operand,
+        value: Operand,
+    },
+    Print {
+        value: Operand,
+        newline: bool,
+    },
+    Assert(Operand),
+    /// Takes down the handler of the innermost `Try`, at the end of its body.
+    EndTry,
+    /// Marks where in the source the instructions after it come from.
+    Line(Span),
+}
+
+/// How a block ends.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Goto(BlockId),
+    Branch {
+        condition: Operand,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    /// Jumps to the block of the first case equal to `value`, or to `otherwise`.
+    Switch {
+        value: Operand,
+        cases: Vec<(Constant, BlockId)>,
+        otherwise: BlockId,
+    },
+    /// Jumps to `body` with a handler installed. A runtime error raised before the handler
+    /// is taken down, by `EndTry` or a return, stores its message in `error` and jumps to
+    /// `handler` instead.
+    Try {
+        body: BlockId,
+        error: Local,
+        handler: BlockId,
+    },
+    Return(Option<Operand>),
+}
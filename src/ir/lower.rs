@@ -0,0 +1,1106 @@
+use crate::codegen::builtins;
+use crate::ir::ir::{
+    Block, BlockId, Constant, Extern, Function, FunctionKind, Global, GlobalId, Instruction, Local,
+    LocalDecl, NextPart, Operand, Place, Program, RangePart, Rvalue, Terminator,
+};
+use crate::semantic::analyzer::{binary_result, builtin_signature, math_result};
+use crate::semantic::types::{FunctionSignature, Type};
+use crate::symbols::SymbolTable;
+use crate::traits::{self, Traits};
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::ast::{
+    self, BinOp, Expr, ExprKind, InlineHint, MatchArm, Parameter, Pattern, Statement,
+};
+use std::collections::HashMap;
+
+/*
+Lowering turns a checked program into the IR, for the C backend and `--dump-ir`. It runs
+after the analyzer, so it never fails: the types it gives values follow the analyzer's
+rules, and a value the analyzer could not type is `Unknown` here too.
+
+Each function is built block by block. The block being filled is left by the statement
+that ends it, a return or the start of a loop or match, and what follows goes into the
+next block; statements after a return go into a block nothing jumps to.
+
+A lambda or spawned block is lowered while the function around it is being built. A name
+it does not define is looked up in that function, and when found there becomes a
+capture: a parameter of the lifted function, and an argument of the closure or spawn
+that creates it. Captures are copies, taken when the closure is created, as in codegen.
+*/
+
+/// Lowers a checked program into the IR.
+///
+/// # Arguments
+///
+/// * `statements` - The checked program.
+/// * `symbols` - The names the analyzer resolved in it, which say what each channel
+///   carries.
+///
+/// # Returns
+///
+/// The program, with `main` holding its top-level statements.
+pub fn lower(statements: &[Statement], symbols: &SymbolTable<Type>) -> Program {
+    Lowerer::new(statements, symbols).lower(statements)
+}
+
+/// A function being built.
+struct Builder {
+    function: Function,
+    // The blocks, with the terminators of those that have one
+    blocks: Vec<(Vec<Instruction>, Option<Terminator>)>,
+    current: BlockId,
+    scopes: Vec<HashMap<String, Local>>,
+    // For a lambda or spawned block: the values it captures, as the function around it
+    // names them, each with the parameter that holds it
+    captured: Vec<(Operand, Local)>,
+}
+
+impl Builder {
+    fn new(name: &str, kind: FunctionKind, returns: Type) -> Self {
+        Builder {
+            function: Function {
+                name: name.to_string(),
+                kind,
+                params: Vec::new(),
+                captures: 0,
+                returns,
+                inline: None,
+                locals: Vec::new(),
+                blocks: Vec::new(),
+            },
+            blocks: vec![(Vec::new(), None)],
+            current: BlockId(0),
+            scopes: vec![HashMap::new()],
+            captured: Vec::new(),
+        }
+    }
+
+    fn is_closure(&self) -> bool {
+        matches!(
+            self.function.kind,
+            FunctionKind::Lambda | FunctionKind::Spawn
+        )
+    }
+
+    /// Adds a local, named when it is a variable of the program.
+    fn local(&mut self, name: Option<&str>, ty: Type) -> Local {
+        let local = Local(self.function.locals.len() as u32);
+        self.function.locals.push(LocalDecl {
+            name: name.map(str::to_string),
+            ty,
+        });
+        local
+    }
+
+    /// Adds a variable to the innermost scope, shadowing any earlier one.
+    fn bind(&mut self, name: &str, ty: Type) -> Local {
+        let local = self.local(Some(name), ty);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), local);
+        }
+        local
+    }
+
+    /// Finds the innermost variable called `name` of this function.
+    fn lookup(&self, name: &str) -> Option<Local> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((Vec::new(), None));
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    fn push(&mut self, instruction: Instruction) {
+        // Code after a return starts a block nothing jumps to
+        if self.blocks[self.current.0 as usize].1.is_some() {
+            self.current = self.new_block();
+        }
+        self.blocks[self.current.0 as usize].0.push(instruction);
+    }
+
+    /// Ends the current block, unless a return already has.
+    fn terminate(&mut self, terminator: Terminator) {
+        let block = &mut self.blocks[self.current.0 as usize];
+        if block.1.is_none() {
+            block.1 = Some(terminator);
+        }
+    }
+
+    /// Ends the current block with a jump to `block`, and carries on in `block`.
+    fn goto(&mut self, block: BlockId) {
+        self.terminate(Terminator::Goto(block));
+        self.current = block;
+    }
+
+    fn finish(mut self) -> Function {
+        // The captures come before the parameters the function was written with
+        let captures = self.captured.iter().map(|(_, local)| *local);
+        self.function.params = captures.chain(self.function.params).collect();
+        self.function.captures = self.captured.len();
+        self.function.blocks = self
+            .blocks
+            .into_iter()
+            .map(|(instructions, terminator)| Block {
+                instructions,
+                terminator: terminator.unwrap_or(Terminator::Return(None)),
+            })
+            .collect();
+        self.function
+    }
+}
+
+struct Lowerer<'a> {
+    symbols: &'a SymbolTable<Type>,
+    signatures: HashMap<String, FunctionSignature>,
+    traits: Traits,
+    globals: Vec<Global>,
+    global_names: HashMap<String, GlobalId>,
+    externs: Vec<Extern>,
+    functions: Vec<Function>,
+    // The functions being built, innermost last
+    builders: Vec<Builder>,
+    // How many lambdas and spawned blocks have been lifted, which numbers the next
+    lambdas: usize,
+    spawns: usize,
+}
+
+impl<'a> Lowerer<'a> {
+    /// Creates a lowerer that knows the signature of every function `statements` define,
+    /// nested ones included.
+    fn new(statements: &[Statement], symbols: &'a SymbolTable<Type>) -> Self {
+        let mut lowerer = Lowerer {
+            symbols,
+            signatures: HashMap::new(),
+            traits: Traits::new(),
+            globals: Vec::new(),
+            global_names: HashMap::new(),
+            externs: Vec::new(),
+            functions: Vec::new(),
+            builders: Vec::new(),
+            lambdas: 0,
+            spawns: 0,
+        };
+        lowerer.declare(statements);
+        lowerer
+    }
+
+    fn declare(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            let (identifier, params, returns) = match statement {
+                Statement::Function {
+                    identifier,
+                    params,
+                    returns,
+                    body,
+                    ..
+                } => {
+                    self.declare(body);
+                    (identifier, params, Type::from_annotation(returns.as_ref()))
+                }
+                Statement::AsyncFunction {
+                    identifier,
+                    params,
+                    body,
+                    ..
+                } => {
+                    self.declare(body);
+                    (identifier, params, Type::Task)
+                }
+                Statement::Extern {
+                    identifier,
+                    params,
+                    returns,
+                } => (identifier, params, Type::from_annotation(returns.as_ref())),
+                Statement::For { body, .. } | Statement::Test { body, .. } => {
+                    self.declare(body);
+                    continue;
+                }
+                Statement::Try { body, handler, .. } => {
+                    self.declare(body);
+                    self.declare(handler);
+                    continue;
+                }
+                Statement::Match { arms, .. } => {
+                    arms.iter().for_each(|arm| self.declare(&arm.body));
+                    continue;
+                }
+                Statement::Trait { .. } => {
+                    self.traits.declare(statement);
+                    continue;
+                }
+                Statement::Impl {
+                    trait_name,
+                    target,
+                    methods,
+                } => {
+                    for method in methods {
+                        if let Statement::Function {
+                            identifier,
+                            params,
+                            returns,
+                            body,
+                            ..
+                        } = method
+                        {
+                            self.declare(body);
+                            let signature = FunctionSignature {
+                                params: params.iter().map(parameter_type).collect(),
+                                returns: Type::from_annotation(returns.as_ref()),
+                            };
+                            let function =
+                                traits::method_function(trait_name, target.name(), identifier);
+                            self.signatures.insert(function, signature);
+                        }
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            let signature = FunctionSignature {
+                params: params.iter().map(parameter_type).collect(),
+                returns,
+            };
+            self.signatures.insert(identifier.clone(), signature);
+        }
+    }
+
+    fn lower(mut self, statements: &[Statement]) -> Program {
+        self.builders
+            .push(Builder::new("main", FunctionKind::Main, Type::Int));
+        // A program ending in an integer expression exits with its value
+        let exit = ast::exit_expression(statements);
+        for (index, statement) in statements.iter().enumerate() {
+            match statement {
+                Statement::Expression(expr) if Some(index) == exit => {
+                    let (value, ty) = self.operand(expr);
+                    if ty == Type::Int {
+                        self.builder().terminate(Terminator::Return(Some(value)));
+                    }
+                }
+                statement => self.statement(statement),
+            }
+        }
+        let main = self.builders.pop().expect("main is built last").finish();
+        self.functions.insert(0, main);
+        Program {
+            globals: self.globals,
+            externs: self.externs,
+            functions: self.functions,
+        }
+    }
+
+    fn builder(&mut self) -> &mut Builder {
+        self.builders.last_mut().expect("a function is being built")
+    }
+
+    fn push(&mut self, instruction: Instruction) {
+        self.builder().push(instruction);
+    }
+
+    /// Stores `value` in a new temporary of type `ty`.
+    fn temporary(&mut self, value: Rvalue, ty: Type) -> (Operand, Type) {
+        let local = self.builder().local(None, ty.clone());
+        self.push(Instruction::Assign {
+            place: Place::Local(local),
+            value,
+        });
+        (Operand::Place(Place::Local(local)), ty)
+    }
+
+    /// The type of the value kept in `place`.
+    fn place_type(&self, place: Place) -> Type {
+        match place {
+            Place::Local(local) => {
+                let builder = self.builders.last().expect("a function is being built");
+                builder.function.local(local).ty.clone()
+            }
+            Place::Global(global) => self.globals[global.0 as usize].ty.clone(),
+        }
+    }
+
+    /// Gives the channel variable at `place`, used at `span`, the type of the values the
+    /// analyzer found sent on it, which a channel made by `channel()` starts out without.
+    fn settle_channel(&mut self, place: Place, span: Span) {
+        if self.place_type(place) != Type::Channel(Box::new(Type::Unknown)) {
+            return;
+        }
+        let Some(ty @ Type::Channel(_)) = self
+            .symbols
+            .resolve(span)
+            .map(|id| self.symbols.definition(id).info.clone())
+        else {
+            return;
+        };
+        match place {
+            Place::Local(local) => {
+                let builder = self.builders.last_mut().expect("a function is being built");
+                builder.function.locals[local.0 as usize].ty = ty;
+            }
+            Place::Global(global) => self.globals[global.0 as usize].ty = ty,
+        }
+    }
+
+    /// Finds the variable called `name` as the innermost function being built sees it.
+    fn lookup(&mut self, name: &str) -> Option<Place> {
+        let depth = self.builders.len() - 1;
+        self.lookup_in(depth, name)
+            .or_else(|| self.global_names.get(name).map(|&id| Place::Global(id)))
+    }
+
+    /// Finds `name` among the variables of the function at `depth`, capturing it from the
+    /// functions around it when that function is a closure.
+    fn lookup_in(&mut self, depth: usize, name: &str) -> Option<Place> {
+        let builder = &self.builders[depth];
+        if let Some(local) = builder.lookup(name) {
+            return Some(Place::Local(local));
+        }
+        if !builder.is_closure() || depth == 0 {
+            return None;
+        }
+        let outer = self.lookup_in(depth - 1, name)?;
+        let ty = match outer {
+            Place::Local(local) => self.builders[depth - 1].function.local(local).ty.clone(),
+            // Top-level variables are seen directly rather than captured
+            Place::Global(_) => return Some(outer),
+        };
+        let builder = &mut self.builders[depth];
+        let capture = builder.local(Some(name), ty);
+        builder.captured.push((Operand::Place(outer), capture));
+        // Later uses find the capture without looking outside again
+        builder.scopes[0].insert(name.to_string(), capture);
+        Some(Place::Local(capture))
+    }
+
+    /// Defines the variable `name`, as a global when it is defined at the top level.
+    fn define(&mut self, name: &str, ty: Type) -> Place {
+        let builder = self.builder();
+        if builder.function.kind != FunctionKind::Main || builder.scopes.len() > 1 {
+            return Place::Local(builder.bind(name, ty));
+        }
+        let id = GlobalId(self.globals.len() as u32);
+        self.globals.push(Global {
+            name: name.to_string(),
+            ty,
+        });
+        self.global_names.insert(name.to_string(), id);
+        Place::Global(id)
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Lowers `body` in a new scope that starts out with `bindings`.
+    fn block(&mut self, body: &[Statement], bindings: Vec<(String, Local)>) {
+        self.builder().scopes.push(bindings.into_iter().collect());
+        self.statements(body);
+        self.builder().scopes.pop();
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::VariableDeclaration { identifier, value } => {
+                let (value, ty) = self.operand(value);
+                let place = self.define(identifier, ty);
+                self.push(Instruction::Assign {
+                    place,
+                    value: Rvalue::Use(value),
+                });
+            }
+            Statement::VariableAssignment { identifier, value } => {
+                let (value, _) = self.operand(value);
+                if let Some(place) = self.lookup(identifier) {
+                    self.push(Instruction::Assign {
+                        place,
+                        value: Rvalue::Use(value),
+                    });
+                }
+            }
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => {
+                let (target, _) = self.operand(target);
+                let (index, _) = self.operand(index);
+                let (value, _) = self.operand(value);
+                self.push(Instruction::SetIndex {
+                    target,
+                    index,
+                    value,
+                });
+            }
+            Statement::FunctionCall { identifier, args } => {
+                let (call, _) = self.call(identifier, args);
+                self.push(Instruction::Eval(call));
+            }
+            Statement::Print { value, newline } => {
+                let (value, _) = self.operand(value);
+                self.push(Instruction::Print {
+                    value,
+                    newline: *newline,
+                });
+            }
+            Statement::Expression(expr) => match self.rvalue(expr) {
+                // A variable or constant on its own does nothing
+                (Rvalue::Use(_), _) => {}
+                (value, _) => self.push(Instruction::Eval(value)),
+            },
+            Statement::Function {
+                identifier,
+                params,
+                returns,
+                body,
+                inline,
+            } => self.function(
+                identifier,
+                FunctionKind::Function,
+                params,
+                Type::from_annotation(returns.as_ref()),
+                body,
+                *inline,
+            ),
+            Statement::AsyncFunction {
+                identifier,
+                params,
+                body,
+                inline,
+            } => self.function(
+                identifier,
+                FunctionKind::Async,
+                params,
+                Type::Int,
+                body,
+                *inline,
+            ),
+            Statement::Test { name, body } => {
+                self.function(name, FunctionKind::Test, &[], Type::Int, body, None)
+            }
+            Statement::Return(value) => {
+                let value = value.as_ref().map(|value| self.operand(value).0);
+                self.builder().terminate(Terminator::Return(value));
+            }
+            Statement::Match { scrutinee, arms } => self.match_arms(scrutinee, arms),
+            Statement::For {
+                variable,
+                iterable,
+                body,
+            } => self.for_loop(variable, iterable, body),
+            Statement::Assert(condition) => {
+                let (condition, _) = self.operand(condition);
+                self.push(Instruction::Assert(condition));
+            }
+            Statement::Try {
+                body,
+                error,
+                handler,
+            } => self.try_block(body, error, handler),
+            Statement::Extern {
+                identifier,
+                params,
+                returns,
+            } => {
+                if self
+                    .externs
+                    .iter()
+                    .all(|declared| declared.name != *identifier)
+                {
+                    self.externs.push(Extern {
+                        name: identifier.clone(),
+                        params: params.iter().map(parameter_type).collect(),
+                        returns: returns
+                            .as_ref()
+                            .map(|returns| Type::from_annotation(Some(returns))),
+                    });
+                }
+            }
+            // The methods of an implementation are functions of their own
+            Statement::Impl {
+                trait_name,
+                target,
+                methods,
+            } => {
+                for method in methods {
+                    if let Statement::Function {
+                        identifier,
+                        params,
+                        returns,
+                        body,
+                        inline,
+                    } = method
+                    {
+                        let function =
+                            traits::method_function(trait_name, target.name(), identifier);
+                        self.function(
+                            &function,
+                            FunctionKind::Function,
+                            params,
+                            Type::from_annotation(returns.as_ref()),
+                            body,
+                            *inline,
+                        );
+                    }
+                }
+            }
+            Statement::Trait { .. } => {}
+            Statement::Line(span) => self.push(Instruction::Line(*span)),
+            // Comments only matter to the formatter, and the resolver has replaced imports
+            Statement::Comment { .. } | Statement::Import(_) => {}
+        }
+    }
+
+    /// Lowers a named function, which sees the top-level variables and its parameters.
+    fn function(
+        &mut self,
+        name: &str,
+        kind: FunctionKind,
+        params: &[Parameter],
+        returns: Type,
+        body: &[Statement],
+        inline: Option<InlineHint>,
+    ) {
+        let mut builder = Builder::new(name, kind, returns);
+        builder.function.inline = inline;
+        builder.function.params = params
+            .iter()
+            .map(|param| builder.bind(&param.name, parameter_type(param)))
+            .collect();
+        self.builders.push(builder);
+        self.statements(body);
+        let function = self.builders.pop().expect("the function is being built");
+        self.functions.push(function.finish());
+    }
+
+    /// Lowers `try_venti` into a block for the body, which takes the handler down when it
+    /// finishes, and one for the handler, which the two join after.
+    fn try_block(&mut self, body: &[Statement], error: &str, handler: &[Statement]) {
+        let body_block = self.builder().new_block();
+        let handler_block = self.builder().new_block();
+        let exit = self.builder().new_block();
+        let message = self.builder().local(Some(error), Type::String);
+        self.builder().terminate(Terminator::Try {
+            body: body_block,
+            error: message,
+            handler: handler_block,
+        });
+
+        self.builder().current = body_block;
+        self.block(body, Vec::new());
+        self.push(Instruction::EndTry);
+        self.builder().terminate(Terminator::Goto(exit));
+
+        self.builder().current = handler_block;
+        self.block(handler, vec![(error.to_string(), message)]);
+        self.builder().goto(exit);
+    }
+
+    /// Lowers `match_venti` into a switch on the scrutinee, with a block for each arm and
+    /// one the arms join in.
+    fn match_arms(&mut self, scrutinee: &Expr, arms: &[MatchArm]) {
+        let (value, _) = self.operand(scrutinee);
+        let join = self.builder().new_block();
+        let mut cases: Vec<(Constant, BlockId)> = Vec::new();
+        let mut otherwise = join;
+        let mut bodies = Vec::new();
+        for arm in arms {
+            let block = self.builder().new_block();
+            let constant = match &arm.pattern {
+                Pattern::Number(n) => Constant::Int(*n),
+                Pattern::String(s) => Constant::String(s.clone()),
+                Pattern::Wildcard => {
+                    otherwise = block;
+                    bodies.push((block, &arm.body));
+                    // The arms after a wildcard can never be reached
+                    break;
+                }
+            };
+            // Only the first arm for a value is taken
+            if cases.iter().all(|(case, _)| *case != constant) {
+                cases.push((constant, block));
+            }
+            bodies.push((block, &arm.body));
+        }
+        self.builder().terminate(Terminator::Switch {
+            value,
+            cases,
+            otherwise,
+        });
+        for (block, body) in bodies {
+            self.builder().current = block;
+            self.block(body, Vec::new());
+            self.builder().terminate(Terminator::Goto(join));
+        }
+        self.builder().current = join;
+    }
+
+    /// Lowers `for_venti` into a loop over a counter, which is the loop variable for
+    /// integers and ranges and the index of the element bound to it for arrays, buffers
+    /// and vectors.
+    fn for_loop(&mut self, variable: &str, iterable: &Expr, body: &[Statement]) {
+        let (iterable, iterable_type) = self.operand(iterable);
+        if iterable_type == Type::Iterator {
+            return self.iterator_loop(variable, iterable, body);
+        }
+        let one = Operand::Constant(Constant::Int(1));
+        let (start, end, step, element) = match &iterable_type {
+            Type::Range => {
+                let part = |lowerer: &mut Self, part| {
+                    lowerer
+                        .temporary(Rvalue::RangePart(iterable.clone(), part), Type::Int)
+                        .0
+                };
+                let start = part(self, RangePart::Start);
+                let end = part(self, RangePart::End);
+                let step = part(self, RangePart::Step);
+                (start, end, step, None)
+            }
+            Type::Array(element, _) => {
+                let (end, _) = self.temporary(Rvalue::Len(iterable.clone()), Type::Int);
+                let zero = Operand::Constant(Constant::Int(0));
+                (zero, end, one, Some((**element).clone()))
+            }
+            Type::Buffer | Type::Vector => {
+                let (end, _) = self.temporary(Rvalue::Len(iterable.clone()), Type::Int);
+                let zero = Operand::Constant(Constant::Int(0));
+                (zero, end, one, Some(Type::Int))
+            }
+            Type::StringVector => {
+                let (end, _) = self.temporary(Rvalue::Len(iterable.clone()), Type::Int);
+                let zero = Operand::Constant(Constant::Int(0));
+                (zero, end, one, Some(Type::String))
+            }
+            // An integer counts from zero up to it
+            _ => (
+                Operand::Constant(Constant::Int(0)),
+                iterable.clone(),
+                one,
+                None,
+            ),
+        };
+
+        let counter = self.builder().local(None, Type::Int);
+        let counter_operand = Operand::Place(Place::Local(counter));
+        self.push(Instruction::Assign {
+            place: Place::Local(counter),
+            value: Rvalue::Use(start),
+        });
+        let condition = self.builder().new_block();
+        let body_block = self.builder().new_block();
+        let next = self.builder().new_block();
+        let exit = self.builder().new_block();
+
+        self.builder().goto(condition);
+        let (in_range, _) = self.temporary(
+            Rvalue::Binary(BinOp::Less, counter_operand.clone(), end),
+            Type::Bool,
+        );
+        self.builder().terminate(Terminator::Branch {
+            condition: in_range,
+            then: body_block,
+            otherwise: exit,
+        });
+
+        self.builder().current = body_block;
+        let value = match element {
+            Some(ty) => {
+                let index = Rvalue::Index {
+                    target: iterable,
+                    index: counter_operand.clone(),
+                };
+                (index, ty)
+            }
+            None => (Rvalue::Use(counter_operand.clone()), Type::Int),
+        };
+        self.builder().scopes.push(HashMap::new());
+        let bound = self.builder().bind(variable, value.1);
+        self.push(Instruction::Assign {
+            place: Place::Local(bound),
+            value: value.0,
+        });
+        self.statements(body);
+        self.builder().scopes.pop();
+        self.builder().goto(next);
+
+        self.push(Instruction::Assign {
+            place: Place::Local(counter),
+            value: Rvalue::Binary(BinOp::Add, counter_operand, step),
+        });
+        self.builder().terminate(Terminator::Goto(condition));
+        self.builder().current = exit;
+    }
+
+    /// Lowers `for_venti` over an iterator into a loop that takes the iterator's next value
+    /// until it runs out.
+    fn iterator_loop(&mut self, variable: &str, iterator: Operand, body: &[Statement]) {
+        let condition = self.builder().new_block();
+        let body_block = self.builder().new_block();
+        let exit = self.builder().new_block();
+
+        self.builder().goto(condition);
+        let (next, _) = self.temporary(Rvalue::Next(iterator), Type::Unknown);
+        let (done, _) = self.temporary(Rvalue::NextPart(next.clone(), NextPart::Done), Type::Bool);
+        self.builder().terminate(Terminator::Branch {
+            condition: done,
+            then: exit,
+            otherwise: body_block,
+        });
+
+        self.builder().current = body_block;
+        self.builder().scopes.push(HashMap::new());
+        let bound = self.builder().bind(variable, Type::Int);
+        self.push(Instruction::Assign {
+            place: Place::Local(bound),
+            value: Rvalue::NextPart(next, NextPart::Value),
+        });
+        self.statements(body);
+        self.builder().scopes.pop();
+        self.builder().terminate(Terminator::Goto(condition));
+        self.builder().current = exit;
+    }
+
+    /// Lowers an expression into an operand, storing anything but a constant or a local
+    /// variable in a temporary.
+    fn operand(&mut self, expr: &Expr) -> (Operand, Type) {
+        let (value, ty) = self.rvalue(expr);
+        self.read(value, ty)
+    }
+
+    /// Makes the operand holding `value`, computing it into a temporary unless it is
+    /// already a constant or a local.
+    fn read(&mut self, value: Rvalue, ty: Type) -> (Operand, Type) {
+        match (value, ty) {
+            // A function called later in the same expression may assign the global, so
+            // its value is read now, where the expression reads it
+            (value @ Rvalue::Use(Operand::Place(Place::Global(_))), ty) => {
+                self.temporary(value, ty)
+            }
+            (Rvalue::Use(operand), ty) => (operand, ty),
+            (value, ty) => self.temporary(value, ty),
+        }
+    }
+
+    fn operands(&mut self, exprs: &[Expr]) -> Vec<Operand> {
+        exprs.iter().map(|expr| self.operand(expr).0).collect()
+    }
+
+    /// Lowers an expression into the computation of its value, with its operands lowered
+    /// before it.
+    fn rvalue(&mut self, expr: &Expr) -> (Rvalue, Type) {
+        let constant = |constant: Constant| {
+            let ty = constant.ty();
+            (Rvalue::Use(Operand::Constant(constant)), ty)
+        };
+        match &expr.kind {
+            ExprKind::Number(n) => constant(Constant::Int(*n)),
+            ExprKind::Float(f) => constant(Constant::Float(*f)),
+            ExprKind::String(s) => constant(Constant::String(s.clone())),
+            ExprKind::Boolean(b) => constant(Constant::Bool(*b)),
+            ExprKind::Null => constant(Constant::Nothing),
+            ExprKind::Identifier(name) => match (self.lookup(name), builtins::constant(name)) {
+                (Some(place), _) => {
+                    self.settle_channel(place, expr.span);
+                    (Rvalue::Use(Operand::Place(place)), self.place_type(place))
+                }
+                (None, Some(value)) => constant(Constant::Float(value)),
+                (None, None) => (
+                    Rvalue::Use(Operand::Constant(Constant::Nothing)),
+                    Type::Unknown,
+                ),
+            },
+            ExprKind::BinaryOp(left, BinOp::Coalesce, right) => self.coalesce(left, right),
+            ExprKind::BinaryOp(left, op @ (BinOp::And | BinOp::Or), right) => {
+                self.logical(left, *op, right)
+            }
+            ExprKind::BinaryOp(left, op, right) => {
+                let (left, left_type) = self.operand(left);
+                let (right, right_type) = self.operand(right);
+                let ty = binary_result(&left_type, *op, &right_type).unwrap_or(Type::Unknown);
+                (Rvalue::Binary(*op, left, right), ty)
+            }
+            ExprKind::UnaryOp(op, operand) => {
+                let (operand, ty) = self.operand(operand);
+                (Rvalue::Unary(*op, operand), ty)
+            }
+            ExprKind::Array(elements) => {
+                // An empty array is of integers, as the analyzer takes it
+                let mut element_type = None;
+                let mut operands = Vec::new();
+                for element in elements {
+                    let (operand, ty) = self.operand(element);
+                    element_type.get_or_insert(ty);
+                    operands.push(operand);
+                }
+                let ty = Type::Array(Box::new(element_type.unwrap_or(Type::Int)), elements.len());
+                (Rvalue::Array(operands), ty)
+            }
+            ExprKind::Map(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| (self.operand(key).0, self.operand(value).0))
+                    .collect();
+                (Rvalue::Map(entries), Type::Map)
+            }
+            ExprKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let (start, _) = self.operand(start);
+                let (end, _) = self.operand(end);
+                let range = Rvalue::Range {
+                    start,
+                    end,
+                    inclusive: *inclusive,
+                };
+                (range, Type::Range)
+            }
+            ExprKind::Index { target, index } => {
+                let (target, target_type) = self.operand(target);
+                let (index, index_type) = self.operand(index);
+                let ty = match target_type {
+                    Type::Array(element, _) => *element,
+                    Type::Vector if index_type == Type::Range => Type::Vector,
+                    Type::Map | Type::Buffer | Type::Vector => Type::Int,
+                    Type::String | Type::StringVector => Type::String,
+                    Type::Json => Type::Json,
+                    _ => Type::Unknown,
+                };
+                (Rvalue::Index { target, index }, ty)
+            }
+            ExprKind::Call { callee, args } => {
+                if let ExprKind::Identifier(identifier) = &callee.kind {
+                    let is_variable = matches!(self.lookup(identifier), Some(Place::Local(_)));
+                    let is_function = self.signatures.contains_key(identifier)
+                        || builtins::is_builtin(identifier);
+                    if !is_variable && is_function {
+                        return self.call(identifier, args);
+                    }
+                }
+                let (callee, _) = self.operand(callee);
+                let args = self.operands(args);
+                (Rvalue::CallClosure { callee, args }, Type::Int)
+            }
+            ExprKind::Lambda { params, body } => self.lambda(params, body),
+            ExprKind::MethodCall {
+                receiver,
+                name,
+                args,
+            } => {
+                let (receiver, receiver_type) = self.operand(receiver);
+                let args = self.operands(args);
+                if let Some(function) = self.method_function(&receiver_type, name) {
+                    let args = std::iter::once(receiver).chain(args).collect();
+                    return (Rvalue::Call { function, args }, Type::Int);
+                }
+                let ty = match (name.as_str(), receiver_type) {
+                    (_, Type::Unknown) => Type::Unknown,
+                    ("len" | "has" | "push" | "pop", _) => Type::Int,
+                    ("step", _) => Type::Range,
+                    (_, Type::Trait(_)) => Type::Int,
+                    _ => Type::Unknown,
+                };
+                let method = Rvalue::Method {
+                    receiver,
+                    name: name.clone(),
+                    args,
+                };
+                (method, ty)
+            }
+            ExprKind::Async(inner) => self.rvalue(inner),
+            ExprKind::Spawn(body) => self.spawn(body),
+            ExprKind::Cast { value, target } => {
+                let (value, _) = self.operand(value);
+                let ty = Type::from_annotation(Some(target));
+                (Rvalue::Cast(value, ty.clone()), ty)
+            }
+            ExprKind::Await(task) => {
+                let (task, ty) = self.operand(task);
+                let ty = match ty {
+                    Type::Unknown => Type::Unknown,
+                    _ => Type::Int,
+                };
+                (Rvalue::Await(task), ty)
+            }
+        }
+    }
+
+    /// Lowers a call of the function or builtin `identifier`, casting each value passed
+    /// for a trait parameter to the trait.
+    fn call(&mut self, identifier: &str, args: &[Expr]) -> (Rvalue, Type) {
+        let signature = self.signatures.get(identifier).cloned();
+        let mut operands = Vec::with_capacity(args.len());
+        let mut arg_types = Vec::with_capacity(args.len());
+        for (position, arg) in args.iter().enumerate() {
+            // `free` empties the variable it is given, so it takes the variable itself
+            let (arg, ty) = match self.rvalue(arg) {
+                (Rvalue::Use(operand), ty) if identifier == "free" => (operand, ty),
+                (value, ty) => self.read(value, ty),
+            };
+            arg_types.push(ty.clone());
+            let param = signature
+                .as_ref()
+                .and_then(|signature| signature.params.get(position));
+            operands.push(match param {
+                Some(param @ Type::Trait(_)) if *param != ty => {
+                    let cast = Rvalue::Cast(arg, param.clone());
+                    self.temporary(cast, param.clone()).0
+                }
+                _ => arg,
+            });
+        }
+        let args = operands;
+        let ty = match signature {
+            Some(signature) => signature.returns,
+            None if identifier == "len" => Type::Int,
+            None if identifier == "json_stringify" => Type::String,
+            // A channel nothing was sent on carries integers
+            None if identifier == "recv" => match arg_types.first() {
+                Some(Type::Channel(element)) if **element != Type::Unknown => (**element).clone(),
+                _ => Type::Int,
+            },
+            None if builtins::is_math(identifier) => {
+                math_result(identifier, &arg_types).unwrap_or(Type::Unknown)
+            }
+            None => builtin_signature(identifier).map_or(Type::Unknown, |builtin| builtin.returns),
+        };
+        let call = Rvalue::Call {
+            function: identifier.to_string(),
+            args,
+        };
+        (call, ty)
+    }
+
+    /// The function implementing the method `name` of a trait the type `receiver`
+    /// implements, unless the method is one of the built-in methods of the type.
+    fn method_function(&self, receiver: &Type, name: &str) -> Option<String> {
+        match (receiver, name) {
+            (Type::String, "len") => None,
+            (Type::Int | Type::Float | Type::Bool | Type::String, _) => {
+                self.traits
+                    .resolve(&receiver.to_string(), name, |function| {
+                        self.signatures.contains_key(function)
+                    })
+            }
+            _ => None,
+        }
+    }
+
+    /// Lowers `left ?? right`, which only evaluates `right` when `left` is `nothing`.
+    fn coalesce(&mut self, left: &Expr, right: &Expr) -> (Rvalue, Type) {
+        let (left, left_type) = self.operand(left);
+        // Only strings can be nothing, so anything else is its own value
+        if !left_type.is_nullable() {
+            return (Rvalue::Use(left), left_type);
+        }
+        let result = self.builder().local(None, Type::String);
+        self.push(Instruction::Assign {
+            place: Place::Local(result),
+            value: Rvalue::Use(left.clone()),
+        });
+        let (is_nothing, _) = self.temporary(Rvalue::IsNothing(left), Type::Bool);
+        let right_block = self.builder().new_block();
+        let join = self.builder().new_block();
+        self.builder().terminate(Terminator::Branch {
+            condition: is_nothing,
+            then: right_block,
+            otherwise: join,
+        });
+        self.builder().current = right_block;
+        let (right, _) = self.operand(right);
+        self.push(Instruction::Assign {
+            place: Place::Local(result),
+            value: Rvalue::Use(right),
+        });
+        self.builder().goto(join);
+        (
+            Rvalue::Use(Operand::Place(Place::Local(result))),
+            Type::String,
+        )
+    }
+
+    /// Lowers `left && right` or `left || right`, which only evaluate `right` when `left`
+    /// does not already decide the result.
+    fn logical(&mut self, left: &Expr, op: BinOp, right: &Expr) -> (Rvalue, Type) {
+        let (left, _) = self.operand(left);
+        let result = self.builder().local(None, Type::Bool);
+        self.push(Instruction::Assign {
+            place: Place::Local(result),
+            value: Rvalue::Use(left.clone()),
+        });
+        let right_block = self.builder().new_block();
+        let join = self.builder().new_block();
+        let (then, otherwise) = match op {
+            BinOp::And => (right_block, join),
+            _ => (join, right_block),
+        };
+        self.builder().terminate(Terminator::Branch {
+            condition: left,
+            then,
+            otherwise,
+        });
+        self.builder().current = right_block;
+        let (right, _) = self.operand(right);
+        self.push(Instruction::Assign {
+            place: Place::Local(result),
+            value: Rvalue::Use(right),
+        });
+        self.builder().goto(join);
+        (
+            Rvalue::Use(Operand::Place(Place::Local(result))),
+            Type::Bool,
+        )
+    }
+
+    /// Lifts a lambda into a function of its own.
+    fn lambda(&mut self, params: &[Parameter], body: &Expr) -> (Rvalue, Type) {
+        let name = format!("lambda.{}", self.lambdas);
+        self.lambdas += 1;
+        let mut builder = Builder::new(&name, FunctionKind::Lambda, Type::Int);
+        builder.function.params = params
+            .iter()
+            .map(|param| builder.bind(&param.name, parameter_type(param)))
+            .collect();
+        self.builders.push(builder);
+        let (value, _) = self.operand(body);
+        self.builder().terminate(Terminator::Return(Some(value)));
+        let (function, captures) = self.finish_closure();
+        let closure = Rvalue::Closure { function, captures };
+        (closure, Type::Lambda(params.len()))
+    }
+
+    /// Lifts the body of a `spawn_venti` block into a function of its own.
+    fn spawn(&mut self, body: &[Statement]) -> (Rvalue, Type) {
+        let name = format!("spawn.{}", self.spawns);
+        self.spawns += 1;
+        self.builders
+            .push(Builder::new(&name, FunctionKind::Spawn, Type::Int));
+        self.statements(body);
+        let (function, captures) = self.finish_closure();
+        (Rvalue::Spawn { function, captures }, Type::Task)
+    }
+
+    /// Finishes the closure being built, returning its name and the values it captures.
+    fn finish_closure(&mut self) -> (String, Vec<Operand>) {
+        let builder = self.builders.pop().expect("the closure is being built");
+        let captures = builder
+            .captured
+            .iter()
+            .map(|(outer, _)| outer.clone())
+            .collect();
+        let function = builder.finish();
+        let name = function.name.clone();
+        self.functions.push(function);
+        (name, captures)
+    }
+}
+
+/// The type of a parameter, which is an integer unless it is annotated.
+fn parameter_type(param: &Parameter) -> Type {
+    Type::from_annotation(param.var_type.as_ref())
+}
@@ -0,0 +1,4 @@
+pub mod dump;
+#[allow(clippy::module_inception)]
+pub mod ir;
+pub mod lower;
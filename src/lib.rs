@@ -0,0 +1,56 @@
+/*
+The compiler as a library, for Rust programs that embed Venti. The `venti` binary is a
+command line on top of it.
+
+The passes can be driven one at a time, as the binary does:
+
+    let arena = Arena::new();
+    let mut ast = Parser::new(Lexer::new(source), &arena).parse()?;
+    infer(&mut ast)?;
+    let mut analyzer = Analyzer::new();
+    analyzer.analyze(&ast)?;
+    CodeGen::new(&context, level).with_symbols(analyzer.into_symbols()).compile(ast)?;
+
+through a `Driver`, which runs them in order for a `Session` and can stop after any of
+them, or all at once with `compile_str`, which needs no LLVM context from the caller. An
+`Engine` instead runs programs with the JIT, calls their functions with `Value`s and lets
+them call Rust closures registered as host functions.
+*/
+
+pub mod cache;
+pub mod codegen;
+pub mod codegen_c;
+pub mod codes;
+pub mod compile;
+pub mod diagnostics;
+pub mod driver;
+pub mod embed;
+pub mod errors;
+pub mod formatter;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+pub mod interp;
+pub mod ir;
+pub mod modules;
+pub mod optimizer;
+pub mod project;
+pub mod semantic;
+pub mod source;
+pub mod style;
+pub mod symbols;
+pub mod timing;
+pub mod traits;
+pub mod venti_lexer;
+pub mod venti_parser;
+
+pub use crate::codegen::codegen::CodeGen;
+pub use crate::compile::{compile_str, CompileOptions, CompiledModule};
+pub use crate::embed::engine::Engine;
+pub use crate::embed::host::HostFn;
+pub use crate::embed::value::{Signature, Value, ValueType};
+pub use crate::errors::VentiError;
+pub use crate::semantic::analyzer::Analyzer;
+pub use crate::venti_lexer::lexer::Lexer;
+pub use crate::venti_parser::ast;
+pub use crate::venti_parser::parser::Parser;
+pub use inkwell::context::Context;
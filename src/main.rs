@@ -1,43 +1,1134 @@
-mod codegen;
-mod errors;
-mod venti_lexer;
-mod venti_parser;
-
-use crate::codegen::codegen::CodeGen;
-use crate::errors::VentiError;
-use clap::{Arg, Command};
+mod repl;
+mod test_runner;
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use inkwell::context::Context;
+use inkwell::module::Module;
+use inkwell::targets::{CodeModel, RelocMode, TargetMachine};
+use inkwell::OptimizationLevel;
+use std::env;
 use std::fs;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process;
+use venti::cache::{Cache, CacheKey};
+use venti::codegen::backend::{self, OutputKind};
+use venti::codegen::codegen::CodeGen;
+use venti::codegen_c::{self, codegen::CCodeGen};
+use venti::codes::ErrorCode;
+use venti::diagnostics::{Diagnostic, ErrorFormat, WarningReport};
+use venti::driver::{Driver, Phase, Session};
+use venti::errors::VentiError;
+use venti::formatter::printer::format_source;
+use venti::interp::interpreter::Interpreter;
+use venti::ir;
+use venti::modules::resolver::Resolver;
+use venti::project::manifest::{Manifest, MANIFEST_FILE};
+use venti::project::scaffold;
+use venti::semantic::lints::{Level, Lint, LintLevels};
+use venti::source::Source;
+use venti::style::ColorChoice;
+use venti::venti_parser::arena::Arena;
+use venti::venti_parser::ast::{Expr, Statement};
+use venti::venti_parser::dump::{self, AstFormat};
+
+/*
+The compiler is driven through subcommands:
+
+    venti build <file>   compiles the program and writes the artifact chosen with --emit,
+                         or C source when given --backend c
+    venti run <file>     compiles the program and runs it, with the JIT or as an executable,
+                         or runs it with the interpreter when given --backend interp
+    venti check <file>   lexes, parses and type checks the program without generating code
+    venti test <file>    runs the program's test_venti blocks with the JIT
+    venti fmt <files>    rewrites programs in the canonical layout, or with --check lists
+                         the ones that are not in it
+    venti repl           runs statements typed on stdin one entry at a time
+    venti new <path>     creates a project in a new directory
+    venti init           turns the current directory into a project
+    venti explain <code> describes an error code, such as V0042, and how to fix the error
+
+In place of <file>, `-` reads the program from stdin and `-e <code>` takes it from the
+command line. `venti -e <code>` and `venti -` on their own are short for `venti run`.
+Without any of them, build, run, check and test use the project the current directory is in:
+its Venti.toml names the program and gives defaults for the flags, which flags on the
+command line override.
 
-fn main() -> Result<(), VentiError> {
-    let matches = Command::new("Venti")
+Each exits with a code CI can act on: 0 on success, EXIT_SOURCE for errors in the program
+being compiled, EXIT_BUILD when code generation or linking fails and EXIT_IO when a file
+cannot be read or written. Invalid command lines exit with 2, as clap does. `run` exits
+with the code the program itself returned.
+*/
+
+/// The program has a syntax or type error.
+const EXIT_SOURCE: i32 = 1;
+/// Code generation, optimization or linking failed.
+const EXIT_BUILD: i32 = 3;
+/// A file could not be read, written or executed.
+const EXIT_IO: i32 = 4;
+/// The program could not be started.
+const EXIT_RUNTIME: i32 = 5;
+/// The program called `panic_venti`, as compiled programs exit when they do.
+const EXIT_PANIC: i32 = 101;
+
+fn main() {
+    let mut argv = env::args_os().collect::<Vec<_>>();
+    if matches!(argv.get(1).and_then(|arg| arg.to_str()), Some("-e" | "-")) {
+        argv.insert(1, "run".into());
+    }
+    let matches = cli().get_matches_from(argv);
+    let color = color(&matches);
+    // The files read and the time each pass took, so errors can show the source they are
+    // about and `--time-passes` can report after the command
+    let mut session = Session::default();
+    let result = match matches.subcommand() {
+        Some(("build", args)) => build(args, &mut session),
+        Some(("run", args)) => run(args, &mut session),
+        Some(("check", args)) => check(args, &mut session),
+        Some(("test", args)) => test(args, &mut session),
+        Some(("fmt", args)) => fmt(args, &mut session),
+        Some(("repl", _)) => repl::run(color),
+        Some(("new", args)) => new(args),
+        Some(("init", _)) => init(),
+        Some(("explain", args)) => explain(args),
+        _ => unreachable!("clap requires a subcommand"),
+    };
+    // Printed whether or not the command failed, since a failing pass was timed too
+    if matches.get_flag("time-passes") {
+        eprint!("{}", session.times.render());
+    }
+    match result {
+        Ok(code) => process::exit(code),
+        Err(error) => {
+            let format = error_format(&matches);
+            let diagnostic = Diagnostic::new(&error, &session.sources).with_color(color);
+            eprint!("{}", diagnostic.render(format));
+            process::exit(exit_code(&error));
+        }
+    }
+}
+
+/// Describes the command line: the subcommands and their flags.
+fn cli() -> Command {
+    Command::new("Venti")
         .version("0.1.0")
         .author("k m nandyka")
         .about("Simple programming language with Rust, because I love Venti")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("error-format")
+                .long("error-format")
+                .value_name("FORMAT")
+                .help("Writes errors for people to read, or as one JSON object per line for tools")
+                .value_parser(["human", "json"])
+                .default_value("human")
+                .global(true),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .value_name("WHEN")
+                .help("Colors errors when stderr is a terminal (auto), always or never")
+                .value_parser(["auto", "always", "never"])
+                .default_value("auto")
+                .global(true),
+        )
         .arg(
-            Arg::new("INPUT")
-                .help("Sets the input file to use")
-                .required(true)
-                .index(1),
+            Arg::new("time-passes")
+                .long("time-passes")
+                .help("Prints how long each pass of the compiler took to stderr")
+                .action(ArgAction::SetTrue)
+                .global(true),
         )
-        .get_matches();
+        .subcommand(
+            Command::new("build")
+                .about("Compiles a program to LLVM IR, bitcode, assembly, an object or an executable")
+                .arg(input_arg())
+                .args(frontend_args())
+                .args(compile_args())
+                .arg(Arg::new("target").long("target").value_name("TRIPLE").help(
+                    "Compiles for the given target triple instead of the host (wasm32 for WebAssembly)",
+                ))
+                .arg(
+                    Arg::new("relocation-model")
+                        .long("relocation-model")
+                        .value_name("MODEL")
+                        .help("Generates position-independent code (pic) for shared libraries and PIE executables, or static code")
+                        .value_parser(["pic", "static"]),
+                )
+                .arg(
+                    Arg::new("code-model")
+                        .long("code-model")
+                        .value_name("MODEL")
+                        .help("The code model, which bounds how far apart code and data may be placed")
+                        .value_parser(["default", "small", "kernel", "medium", "large"])
+                        .default_value("default"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .value_name("PATH")
+                        .help("Writes the output to PATH instead of a file named after the input")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("emit")
+                        .long("emit")
+                        .value_name("KIND")
+                        .help("The kind of output to produce")
+                        .value_parser(["llvm-ir", "bc", "asm", "obj", "exe", "c"])
+                        .default_value("llvm-ir"),
+                )
+                .arg(
+                    Arg::new("backend")
+                        .long("backend")
+                        .value_name("BACKEND")
+                        .help("Compiles the program with LLVM, or to portable C99 source that --emit exe hands to the system C compiler")
+                        .value_parser(["llvm", "c"])
+                        .default_value("llvm"),
+                ),
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Compiles a program and runs it, exiting with the code it returns")
+                .arg(input_arg())
+                .args(frontend_args())
+                .args(compile_args())
+                .arg(
+                    Arg::new("native")
+                        .long("native")
+                        .help("Builds a native executable and runs that instead of using the JIT")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("backend")
+                        .long("backend")
+                        .value_name("BACKEND")
+                        .help("Compiles the program with LLVM or through C, or runs it with the tree-walking interpreter")
+                        .value_parser(["llvm", "c", "interp"])
+                        .default_value("llvm"),
+                )
+                .arg(
+                    Arg::new("ARGS")
+                        .help("Arguments passed on to the program, after --")
+                        .index(2)
+                        .num_args(0..)
+                        .last(true),
+                ),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Checks a program for syntax and type errors without compiling it")
+                .arg(input_arg())
+                .args(frontend_args()),
+        )
+        .subcommand(
+            Command::new("test")
+                .about("Runs the program's tests and exits with 1 if any of them fail")
+                .arg(input_arg())
+                .args(frontend_args()),
+        )
+        .subcommand(
+            Command::new("fmt")
+                .about("Rewrites programs with canonical spacing, indentation and brace placement")
+                .arg(
+                    Arg::new("FILES")
+                        .help("The files to format, or - to format stdin to stdout. Defaults to every .venti file in the current project")
+                        .num_args(1..)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .help("Lists the files that are not formatted instead of rewriting them, and exits with 1 if there are any")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("repl")
+                .about("Reads statements from stdin and runs each one as soon as it is complete"),
+        )
+        .subcommand(
+            Command::new("new")
+                .about("Creates a project with a Venti.toml and a program to start from")
+                .arg(
+                    Arg::new("PATH")
+                        .help("The directory to create, which names the project")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Creates a project in the current directory, keeping any program already there"),
+        )
+        .subcommand(
+            Command::new("explain")
+                .about("Describes an error code printed with an error and shows how to fix the error")
+                .arg(
+                    Arg::new("CODE")
+                        .help("The code to describe, such as V0042")
+                        .required(true)
+                        .value_parser(|code: &str| {
+                            ErrorCode::from_code(code)
+                                .ok_or_else(|| format!("'{}' is not a Venti error code", code))
+                        }),
+                ),
+        )
+}
+
+/// The source file argument every subcommand takes.
+fn input_arg() -> Arg {
+    Arg::new("INPUT")
+        .help("Sets the input file to use, or - to read the program from stdin. Defaults to the entry point of the current project")
+        .index(1)
+}
+
+/// The flags shared by the subcommands that read a program.
+fn frontend_args() -> Vec<Arg> {
+    vec![
+        Arg::new("eval")
+            .short('e')
+            .long("eval")
+            .value_name("CODE")
+            .help("Uses CODE as the program instead of reading a file")
+            .conflicts_with("INPUT"),
+        Arg::new("dump-tokens")
+            .long("dump-tokens")
+            .help(
+                "Prints each token with the line and column it starts on, as the lexer produces it",
+            )
+            .action(ArgAction::SetTrue),
+        Arg::new("dump-ast")
+            .long("dump-ast")
+            .value_name("FORMAT")
+            .help(
+                "Writes the parsed program to stdout as JSON, an S-expression or Rust debug output",
+            )
+            .value_parser(["json", "sexpr", "debug"]),
+        Arg::new("ast-output")
+            .long("ast-output")
+            .value_name("PATH")
+            .help("Writes the --dump-ast output to PATH instead of stdout")
+            .requires("dump-ast")
+            .value_parser(clap::value_parser!(PathBuf)),
+        Arg::new("dump-ir")
+            .long("dump-ir")
+            .help("Prints the checked program lowered into the compiler's IR")
+            .action(ArgAction::SetTrue),
+        Arg::new("quiet")
+            .short('q')
+            .long("quiet")
+            .help("Prints nothing but errors, such as the path `build` wrote")
+            .conflicts_with_all(["dump-tokens", "dump-ast", "dump-ir"])
+            .action(ArgAction::SetTrue),
+        Arg::new("allow")
+            .short('A')
+            .long("allow")
+            .value_name("LINT")
+            .help("Turns off a lint")
+            .value_parser(Lint::ALL.map(Lint::name))
+            .action(ArgAction::Append),
+        Arg::new("warn")
+            .short('W')
+            .long("warn")
+            .value_name("LINT")
+            .help("Reports what a lint finds as a warning, which is the default")
+            .value_parser(Lint::ALL.map(Lint::name))
+            .action(ArgAction::Append),
+        Arg::new("deny")
+            .short('D')
+            .long("deny")
+            .value_name("LINT")
+            .help("Reports what a lint finds as an error")
+            .value_parser(Lint::ALL.map(Lint::name))
+            .action(ArgAction::Append),
+        Arg::new("deny-warnings")
+            .long("deny-warnings")
+            .help("Reports every warning as an error, so a program with warnings fails to build")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+/// Picks how errors and warnings are written from `--error-format`.
+fn error_format(args: &ArgMatches) -> ErrorFormat {
+    let format = args.get_one::<String>("error-format").unwrap();
+    ErrorFormat::from_flag(format).expect("clap only accepts known formats")
+}
+
+/// Decides from `--color` whether errors and warnings are colored.
+fn color(args: &ArgMatches) -> bool {
+    let choice = args.get_one::<String>("color").unwrap();
+    ColorChoice::from_flag(choice)
+        .expect("clap only accepts known choices")
+        .for_stderr()
+}
+
+/// Sets the level of each lint from `--allow`, `--warn` and `--deny`, where a later flag
+/// overrides an earlier one for the same lint, then applies `--deny-warnings` to the lints
+/// that still warn.
+fn lint_levels(args: &ArgMatches) -> LintLevels {
+    let mut flags = Vec::new();
+    for (id, level) in [
+        ("allow", Level::Allow),
+        ("warn", Level::Warn),
+        ("deny", Level::Deny),
+    ] {
+        if let (Some(indices), Some(names)) = (args.indices_of(id), args.get_many::<String>(id)) {
+            flags.extend(indices.zip(names).map(|(index, name)| (index, level, name)));
+        }
+    }
+    flags.sort_by_key(|(index, ..)| *index);
+
+    let mut levels = LintLevels::new();
+    for (_, level, name) in flags {
+        let lint = Lint::from_name(name).expect("clap only accepts known lints");
+        levels.set(lint, level);
+    }
+    if args.get_flag("deny-warnings") {
+        levels.deny_warnings();
+    }
+    levels
+}
+
+/// The flags shared by the subcommands that generate code.
+fn compile_args() -> Vec<Arg> {
+    vec![
+        Arg::new("no-fold")
+            .long("no-fold")
+            .help("Skips constant folding, to see the code generated for the program as written")
+            .action(ArgAction::SetTrue),
+        Arg::new("debug")
+            .short('g')
+            .help("Emits debug info so the program can be stepped through in gdb or lldb")
+            .action(ArgAction::SetTrue),
+        Arg::new("opt-level")
+            .short('O')
+            .value_name("LEVEL")
+            .help("Optimization level, from 0 (none) to 3")
+            .value_parser(clap::value_parser!(u8).range(0..=3))
+            .default_value("0"),
+        Arg::new("inline-threshold")
+            .long("inline-threshold")
+            .value_name("N")
+            .help("How large a function the optimizer inlines, where LLVM's default is 225")
+            .value_parser(clap::value_parser!(u32)),
+        Arg::new("jobs")
+            .short('j')
+            .long("jobs")
+            .value_name("N")
+            .help("How many threads compile independent functions at once, by default one per CPU")
+            .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..)),
+        Arg::new("no-cache")
+            .long("no-cache")
+            .help("Compiles the whole program even if parts of it were cached, and caches nothing")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+/// Maps an error to the process exit code reported for it.
+fn exit_code(error: &VentiError) -> i32 {
+    match error {
+        VentiError::SyntaxError(..) | VentiError::TypeError(..) | VentiError::LintError(..) => {
+            EXIT_SOURCE
+        }
+        VentiError::CodegenError(..) => EXIT_BUILD,
+        VentiError::IOError(..) => EXIT_IO,
+        VentiError::RuntimeError(..) => EXIT_RUNTIME,
+        VentiError::Multiple(errors) => exit_code(&errors[0]),
+        VentiError::Coded(ErrorCode::Panic, _) => EXIT_PANIC,
+        VentiError::Coded(_, error) => exit_code(error),
+    }
+}
+
+/// Runs `venti build`: compiles the program and writes the requested artifact.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The exit code, or the error that stopped the build.
+fn build(args: &ArgMatches, session: &mut Session) -> Result<i32, VentiError> {
+    let manifest = project(args)?;
+    let source = Source::from_args(args, manifest.as_ref());
+    let arena = Arena::new();
+    let ast = frontend(args, &source, session, &arena)?;
+    if args.get_one::<String>("backend").unwrap() == "c" {
+        return build_c(args, manifest.as_ref(), source.path(), ast, session);
+    }
+
+    let target = args
+        .get_one::<String>("target")
+        .or(manifest
+            .as_ref()
+            .and_then(|manifest| manifest.target.as_ref()))
+        .map(|triple| backend::normalize_triple(triple));
+    let wasm = target.as_deref().is_some_and(backend::is_wasm);
+    let reloc = args
+        .get_one::<String>("relocation-model")
+        .map(|flag| backend::relocation_model(flag).expect("clap only accepts known models"));
+    let code_model = args
+        .get_one::<String>("code-model")
+        .and_then(|flag| backend::code_model(flag))
+        .expect("clap only accepts known models");
+    let opt_level = opt_level(args, manifest.as_ref());
+    let level = backend::optimization_level(opt_level);
+    session.options.opt_level = opt_level;
+    let machine = backend::target_machine(target.as_deref(), level, reloc, code_model)?;
 
-    let input = matches.get_one::<String>("INPUT").unwrap();
-    let source = fs::read_to_string(input).map_err(|e| VentiError::IOError(e.to_string()))?;
+    let context = Context::create();
+    let codegen = compile(&context, args, source.path(), ast, &machine, wasm, session)?;
 
-    let mut lexer = venti_lexer::lexer::Lexer::new(&source);
-    let mut tokens = Vec::new();
-    while let Some(token) = lexer.next_token() {
-        tokens.push(token?);
+    let kind = emit_kind(args, manifest.as_ref());
+    let output = output_path(args, manifest.as_ref(), source.path(), kind, wasm)?;
+    let pie = reloc != Some(RelocMode::Static);
+    let written = session.times.time("emit", || {
+        emit_output(&codegen, &machine, kind, wasm, pie, &output)
+    })?;
+    if !args.get_flag("quiet") {
+        println!("{}", written.display());
     }
-    println!("Tokens: {:?}", tokens);
+    Ok(0)
+}
 
-    let mut parser = venti_parser::parser::Parser::new(tokens);
-    let ast = parser.parse()?;
-    println!("AST: {:?}", ast);
+/// Runs `venti build --backend c`: writes the program as C source, or with `--emit exe`
+/// compiles that source into an executable with the system C compiler.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The exit code, or the error that stopped the build.
+fn build_c(
+    args: &ArgMatches,
+    manifest: Option<&Manifest>,
+    input: &Path,
+    ast: Vec<Statement>,
+    session: &mut Session,
+) -> Result<i32, VentiError> {
+    // Without `--emit` the C backend writes C source, whatever kind of output the LLVM
+    // backend would default to
+    let kind = match emit_kind(args, manifest) {
+        kind @ (OutputKind::C | OutputKind::Executable) => kind,
+        _ if args.value_source("emit") != Some(ValueSource::CommandLine) => OutputKind::C,
+        _ => {
+            return Err(VentiError::CodegenError(
+                "The C backend can only emit C source (--emit c) or an executable (--emit exe)"
+                    .to_string(),
+                None,
+            ))
+        }
+    };
+    let output = output_path(args, manifest, input, kind, false)?;
+    let codegen = compile_c(args, ast, session)?;
+    session.times.time("emit", || match kind {
+        OutputKind::C => codegen.write_source(&output),
+        _ => compile_c_executable(&codegen, opt_level(args, manifest), &output),
+    })?;
+    if !args.get_flag("quiet") {
+        println!("{}", output.display());
+    }
+    Ok(0)
+}
 
-    let context = inkwell::context::Context::create();
-    let codegen = CodeGen::new(&context);
-    codegen.compile(ast)?;
+/// Picks the kind of output: `--emit` when it is on the command line, otherwise the
+/// project's `emit`, otherwise the flag's default.
+fn emit_kind(args: &ArgMatches, manifest: Option<&Manifest>) -> OutputKind {
+    let emit = args.get_one::<String>("emit").unwrap();
+    let kind = OutputKind::from_flag(emit).expect("clap only accepts known --emit values");
+    if args.value_source("emit") == Some(ValueSource::CommandLine) {
+        return kind;
+    }
+    manifest.and_then(|manifest| manifest.emit).unwrap_or(kind)
+}
 
-    Ok(())
+/// Picks the path `build` writes to: `-o` when it is given, otherwise the path the
+/// project's manifest gives, creating its directory, otherwise one named after `input`.
+fn output_path(
+    args: &ArgMatches,
+    manifest: Option<&Manifest>,
+    input: &Path,
+    kind: OutputKind,
+    wasm: bool,
+) -> Result<PathBuf, VentiError> {
+    match (args.get_one::<PathBuf>("output"), manifest) {
+        (Some(output), _) => Ok(output.clone()),
+        (None, Some(manifest)) => {
+            let output = manifest.output_path(kind, wasm);
+            // The manifest may put the artifact in a directory that does not exist yet
+            if let Some(parent) = output.parent() {
+                fs::create_dir_all(parent).map_err(|e| {
+                    VentiError::IOError(
+                        format!("Could not create {}: {}", parent.display(), e),
+                        None,
+                    )
+                })?;
+            }
+            Ok(output)
+        }
+        (None, None) => Ok(backend::default_output_path(input, kind, wasm)),
+    }
+}
+
+/// Runs `venti run`: compiles the program for the host and runs it.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The program's exit code, or the error that kept it from running.
+fn run(args: &ArgMatches, session: &mut Session) -> Result<i32, VentiError> {
+    let manifest = project(args)?;
+    let source = Source::from_args(args, manifest.as_ref());
+    let backend_name = args.get_one::<String>("backend").unwrap();
+    let opt_level = opt_level(args, manifest.as_ref());
+    let level = backend::optimization_level(opt_level);
+    session.options.opt_level = opt_level;
+    let cache = match backend_name.as_str() {
+        "llvm" => run_cache(args, &source, opt_level)?,
+        _ => None,
+    };
+    // An unchanged program runs from the module an earlier run cached, without being read
+    // any further than its text. An entry that cannot be read is compiled again.
+    if let Some(bitcode) = cache.as_ref().and_then(|(cache, key)| cache.lookup(*key)) {
+        let context = Context::create();
+        let module = backend::read_bitcode(&context, &bitcode);
+        if let Ok(module) = module {
+            let machine = backend::target_machine(None, level, None, CodeModel::Default)?;
+            return run_module(args, &source, &module, &machine, level);
+        }
+    }
+
+    let arena = Arena::new();
+    let ast = frontend(args, &source, session, &arena)?;
+    // The interpreter needs no LLVM, so the code generation flags do not apply to it
+    if backend_name == "interp" {
+        return Interpreter::new().with_args(program_args(args)).run(&ast);
+    }
+    // C can only be run by compiling it into an executable first
+    if backend_name == "c" {
+        let codegen = compile_c(args, ast, session)?;
+        let executable = std::env::temp_dir().join(format!("venti-run-{}", process::id()));
+        compile_c_executable(&codegen, opt_level, &executable)?;
+        return run_executable(&executable, &program_args(args));
+    }
+    let machine = backend::target_machine(None, level, None, CodeModel::Default)?;
+    let context = Context::create();
+    let codegen = compile(&context, args, source.path(), ast, &machine, false, session)?;
+    if let Some((cache, key)) = &cache {
+        // A cache that cannot be written only costs the next run a compile
+        let _ = cache.store(*key, &session.sources, |path| codegen.write_bitcode(path));
+    }
+    run_module(args, &source, codegen.module(), &machine, level)
+}
+
+/// Picks the cache `run` keeps compiled modules in and the key of the program in it.
+///
+/// Programs read from stdin are not cached, since stdin can only be read once, and neither
+/// are runs with `--no-cache` or one of the `--dump-*` flags, which must go through the
+/// front end.
+///
+/// # Returns
+///
+/// * `Result<Option<(Cache, CacheKey)>, VentiError>` - The cache and the key, `None` when
+///   the program is not cached, or an error if the program cannot be read.
+fn run_cache(
+    args: &ArgMatches,
+    source: &Source,
+    opt_level: u8,
+) -> Result<Option<(Cache, CacheKey)>, VentiError> {
+    let dumping = args.get_flag("dump-tokens")
+        || args.get_one::<String>("dump-ast").is_some()
+        || args.get_flag("dump-ir");
+    if args.get_flag("no-cache") || dumping || *source == Source::Stdin {
+        return Ok(None);
+    }
+    let Some(cache) = Cache::from_env() else {
+        return Ok(None);
+    };
+    let path = match source {
+        Source::File(path) => fs::canonicalize(path).unwrap_or_else(|_| path.clone()),
+        // A program given with -e imports relative to the current directory
+        _ => env::current_dir().unwrap_or_default(),
+    };
+    // The lint flags decide whether the program compiles at all
+    let mut key = CacheKey::new(&path, &source.read()?)
+        .with_option("opt-level", &opt_level.to_string())
+        .with_option("no-fold", &args.get_flag("no-fold").to_string())
+        .with_option("debug", &args.get_flag("debug").to_string())
+        .with_option(
+            "inline-threshold",
+            &format!("{:?}", args.get_one::<u32>("inline-threshold")),
+        )
+        .with_option("deny-warnings", &args.get_flag("deny-warnings").to_string());
+    for flag in ["allow", "warn", "deny"] {
+        let lints = args
+            .get_many::<String>(flag)
+            .map(|lints| lints.cloned().collect::<Vec<_>>().join(","))
+            .unwrap_or_default();
+        key = key.with_option(flag, &lints);
+    }
+    Ok(Some((cache, key)))
+}
+
+/// Runs a compiled program with the JIT or, with `--native`, as an executable.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The program's exit code, or the error that kept it from running.
+fn run_module(
+    args: &ArgMatches,
+    source: &Source,
+    module: &Module,
+    machine: &TargetMachine,
+    level: OptimizationLevel,
+) -> Result<i32, VentiError> {
+    if !args.get_flag("native") {
+        // The JIT calls `main` directly, so the program's name is passed like a shell would
+        let name = source.path().display().to_string();
+        let argv = iter::once(name)
+            .chain(program_args(args))
+            .collect::<Vec<_>>();
+        return backend::run_jit(module, level, &argv);
+    }
+    let executable = std::env::temp_dir().join(format!("venti-run-{}", process::id()));
+    link_module(module, machine, false, true, &executable)?;
+    run_executable(&executable, &program_args(args))
+}
+
+/// Returns the arguments given to `venti run` after `--`, which are the program's own.
+fn program_args(args: &ArgMatches) -> Vec<String> {
+    args.get_many::<String>("ARGS")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Runs the executable `run` built with `program_args`, deletes it and returns the code it
+/// exited with.
+fn run_executable(executable: &Path, program_args: &[String]) -> Result<i32, VentiError> {
+    let status = process::Command::new(executable)
+        .args(program_args)
+        .status();
+    let _ = fs::remove_file(executable);
+    let status = status.map_err(|e| {
+        VentiError::IOError(
+            format!("Could not run {}: {}", executable.display(), e),
+            None,
+        )
+    })?;
+    // A program killed by a signal has no exit code of its own
+    Ok(status.code().unwrap_or(EXIT_RUNTIME))
+}
+
+/// Runs `venti check`: reports the first syntax or type error in the program, if any.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The exit code, or the error found in the program.
+fn check(args: &ArgMatches, session: &mut Session) -> Result<i32, VentiError> {
+    let manifest = project(args)?;
+    let source = Source::from_args(args, manifest.as_ref());
+    frontend(args, &source, session, &Arena::new())?;
+    Ok(0)
+}
+
+/// Runs `venti test`: runs every `test_venti` block in the program and reports which
+/// passed.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The exit code, or the error that kept the tests from
+///   running.
+fn test(args: &ArgMatches, session: &mut Session) -> Result<i32, VentiError> {
+    let manifest = project(args)?;
+    let source = Source::from_args(args, manifest.as_ref());
+    let arena = Arena::new();
+    let ast = frontend(args, &source, session, &arena)?;
+    test_runner::run(ast, session.symbols.clone())
+}
+
+/// Runs `venti fmt`: rewrites programs in the canonical layout, or with `--check` lists
+/// the ones that are not in it.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The exit code, or the error that kept a file from being
+///   formatted.
+fn fmt(args: &ArgMatches, session: &mut Session) -> Result<i32, VentiError> {
+    let files = match args.get_many::<PathBuf>("FILES") {
+        Some(files) => files.cloned().collect(),
+        None => project_sources()?,
+    };
+    let check = args.get_flag("check");
+    let mut unformatted = 0;
+    for path in files {
+        let source = if path == Path::new("-") {
+            Source::Stdin
+        } else {
+            Source::File(path.clone())
+        };
+        let text = source.read()?;
+        let file = session.sources.add(source.path(), &text, 1);
+        let formatted = format_source(&text).map_err(|error| error.in_file(file))?;
+        if check {
+            if formatted != text {
+                println!("{}", path.display());
+                unformatted += 1;
+            }
+        } else if let Source::Stdin = source {
+            print!("{}", formatted);
+        } else if formatted != text {
+            fs::write(&path, formatted).map_err(|e| {
+                VentiError::IOError(format!("Could not write {}: {}", path.display(), e), None)
+            })?;
+        }
+    }
+    // An unformatted file fails the check the way an error in it would
+    Ok(if unformatted > 0 { EXIT_SOURCE } else { 0 })
+}
+
+/// Lists the `.venti` files of the project the current directory is in, leaving out
+/// hidden directories.
+///
+/// # Returns
+///
+/// * `Result<Vec<PathBuf>, VentiError>` - The files in sorted order, or an error if there
+///   is no project or a directory cannot be read.
+fn project_sources() -> Result<Vec<PathBuf>, VentiError> {
+    let Some(manifest) = Manifest::find(Path::new("."))? else {
+        return Err(VentiError::IOError(
+            format!(
+                "No files were given, and there is no {} in the current directory or its parents",
+                MANIFEST_FILE
+            ),
+            None,
+        ));
+    };
+    let mut files = Vec::new();
+    let mut directories = vec![manifest.root];
+    while let Some(directory) = directories.pop() {
+        let entries = fs::read_dir(&directory).map_err(|e| {
+            VentiError::IOError(
+                format!("Could not read {}: {}", directory.display(), e),
+                None,
+            )
+        })?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                directories.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == "venti")
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Runs `venti new`: creates a project in a new directory.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The exit code, or the error that kept the project from
+///   being created.
+fn new(args: &ArgMatches) -> Result<i32, VentiError> {
+    let path = args.get_one::<PathBuf>("PATH").unwrap();
+    let name = scaffold::new_project(path)?;
+    println!("Created project '{}' in {}", name, path.display());
+    Ok(0)
+}
+
+/// Runs `venti init`: turns the current directory into a project.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The exit code, or the error that kept the project from
+///   being created.
+fn init() -> Result<i32, VentiError> {
+    let name = scaffold::init_project(Path::new("."))?;
+    println!("Created project '{}'", name);
+    Ok(0)
+}
+
+/// Runs `venti explain`: prints what an error code means and an example fix.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The exit code, which is always 0.
+fn explain(args: &ArgMatches) -> Result<i32, VentiError> {
+    let code = *args.get_one::<ErrorCode>("CODE").unwrap();
+    println!("{}\n", code.as_str());
+    print!("{}", code.explanation());
+    Ok(0)
+}
+
+/// Loads the manifest of the current project when the subcommand is given neither a file
+/// nor `-e`.
+///
+/// # Returns
+///
+/// * `Result<Option<Manifest>, VentiError>` - The manifest, `None` if the program is named
+///   on the command line, or an error if there is no project to fall back on.
+fn project(args: &ArgMatches) -> Result<Option<Manifest>, VentiError> {
+    if args.contains_id("INPUT") || args.contains_id("eval") {
+        return Ok(None);
+    }
+    match Manifest::find(Path::new("."))? {
+        Some(manifest) => Ok(Some(manifest)),
+        None => Err(VentiError::IOError(
+            format!(
+            "No input file was given, and there is no {} in the current directory or its parents",
+            MANIFEST_FILE
+        ),
+            None,
+        )),
+    }
+}
+
+/// Picks the optimization level: `-O` when it is on the command line, otherwise the
+/// project's `opt-level`, otherwise the flag's default.
+fn opt_level(args: &ArgMatches, manifest: Option<&Manifest>) -> u8 {
+    let flag = *args.get_one::<u8>("opt-level").unwrap();
+    if args.value_source("opt-level") == Some(ValueSource::CommandLine) {
+        return flag;
+    }
+    manifest
+        .and_then(|manifest| manifest.opt_level)
+        .unwrap_or(flag)
+}
+
+/// Reads, lexes, parses, type checks and lints the program in `input`, merging in the
+/// files it imports.
+///
+/// With `--dump-tokens` the tokens are printed as they are lexed, and with `--dump-ast` the
+/// merged program is written out before it is checked, so programs with type errors can be
+/// dumped too. With `--dump-ir` the checked program is lowered and printed. Without these
+/// flags only the warnings of the lints are printed, to stderr.
+///
+/// # Arguments
+///
+/// * `args` - The subcommand's flags, including those from `frontend_args`.
+/// * `input` - Where the program comes from.
+/// * `session` - The session the program and its imports are added to.
+/// * `arena` - Holds the expressions of the program and its imports.
+///
+/// # Returns
+///
+/// * `Result<Vec<Statement>, VentiError>` - The checked program, or the first error found,
+///   which is a warning of a denied lint when the program has no other errors.
+fn frontend<'a>(
+    args: &ArgMatches,
+    input: &Source,
+    session: &mut Session,
+    arena: &'a Arena<Expr<'a>>,
+) -> Result<Vec<Statement<'a>>, VentiError> {
+    let source = input.read()?;
+    session.options.lint_levels = lint_levels(args);
+    // A program read from stdin or given with -e imports relative to the current directory
+    let resolver = match input {
+        Source::File(path) => Resolver::for_file(path),
+        _ => Resolver::new(Path::new(".")),
+    };
+
+    let mut driver = Driver::new(session);
+    if args.get_flag("dump-tokens") {
+        // Printed as the parser pulls them, so a lexer error shows what came before it
+        driver = driver.with_token_hook(|(token, span)| {
+            println!("{}:{}: {:?}", span.line, span.column, token);
+        });
+    }
+    if let Some(format) = args.get_one::<String>("dump-ast") {
+        let format = AstFormat::from_flag(format).unwrap();
+        let output = args.get_one::<PathBuf>("ast-output");
+        driver = driver.with_hook(Phase::Resolve, move |ast| {
+            let text = dump::dump(ast, format);
+            match output {
+                Some(path) => fs::write(path, text).map_err(|e| {
+                    VentiError::IOError(format!("Could not write {}: {}", path.display(), e), None)
+                }),
+                None => {
+                    print!("{}", text);
+                    Ok(())
+                }
+            }
+        });
+    }
+    let ast = driver.frontend(input.path(), &source, resolver, arena)?;
+    if args.get_flag("dump-ir") {
+        if let Some(program) = driver.lower(&ast) {
+            print!("{}", ir::dump::dump(&program));
+        }
+    }
+
+    let session = driver.session();
+    let warnings = std::mem::take(&mut session.warnings);
+    if !warnings.is_empty() && !args.get_flag("quiet") {
+        let report = WarningReport::new(&warnings, &session.sources).with_color(color(args));
+        eprint!("{}", report.render(error_format(args)));
+    }
+    Ok(ast)
+}
+
+/// Generates and optimizes the module for a checked program.
+///
+/// # Arguments
+///
+/// * `context` - The LLVM context that owns the module.
+/// * `args` - The subcommand's flags, including those from `compile_args`.
+/// * `input` - The path the source is known by, which names the module.
+/// * `ast` - The checked program.
+/// * `machine` - The target machine the module is compiled for.
+/// * `wasm` - Whether the target is WebAssembly, which prints through host imports.
+/// * `session` - The session, whose options give the optimization level.
+///
+/// # Returns
+///
+/// * `Result<CodeGen, VentiError>` - The code generator holding the module, or an error.
+fn compile<'ctx>(
+    context: &'ctx Context,
+    args: &ArgMatches,
+    input: &Path,
+    ast: Vec<Statement>,
+    machine: &TargetMachine,
+    wasm: bool,
+    session: &mut Session,
+) -> Result<CodeGen<'ctx>, VentiError> {
+    session.options.fold = !args.get_flag("no-fold");
+    let level = backend::optimization_level(session.options.opt_level);
+    let module_name = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "main".to_string());
+    let jobs = args
+        .get_one::<usize>("jobs")
+        .copied()
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+    let mut codegen = CodeGen::new(context, level)
+        .with_module_name(&module_name)
+        .with_target_triple(&machine.get_triple())
+        .with_symbols(session.symbols.clone())
+        .with_jobs(jobs);
+    if wasm {
+        codegen = codegen.with_print_imports();
+    }
+    if args.get_flag("debug") {
+        let pointer_bits = machine.get_target_data().get_pointer_byte_size(None) as u64 * 8;
+        codegen = codegen.with_debug_info(input, pointer_bits);
+    } else if !args.get_flag("no-cache") {
+        // Functions are cached before they are optimized, so only the target matters
+        if let Some(cache) = Cache::from_env() {
+            let triple = machine.get_triple();
+            let key =
+                CacheKey::compiler().with_option("target", &triple.as_str().to_string_lossy());
+            codegen = codegen.with_function_cache(cache, key);
+        }
+    }
+    let mut driver = Driver::new(session);
+    driver.generate(ast, |ast| codegen.compile(ast))?;
+
+    backend::configure_module(codegen.module(), machine);
+    if let Some(&threshold) = args.get_one::<u32>("inline-threshold") {
+        backend::set_inline_threshold(codegen.module(), threshold);
+    }
+    driver.optimize(codegen.module(), machine)?;
+    Ok(codegen)
+}
+
+/// Links a compiled module into an executable at `output`, through a temporary object file.
+///
+/// # Arguments
+///
+/// * `module` - The compiled module.
+/// * `machine` - The target machine the module was compiled for.
+/// * `wasm` - Whether the target is WebAssembly, which links with `wasm-ld`.
+/// * `pie` - Whether native code is position-independent, so it links as a PIE.
+/// * `output` - The path to write the executable to.
+fn link_module(
+    module: &Module,
+    machine: &TargetMachine,
+    wasm: bool,
+    pie: bool,
+    output: &Path,
+) -> Result<(), VentiError> {
+    let object = std::env::temp_dir().join(format!("venti-{}.o", std::process::id()));
+    backend::write_object(module, machine, &object)?;
+    let linked = if wasm {
+        backend::link_wasm(&object, output)
+    } else {
+        backend::link_executable(&object, output, pie)
+    };
+    let _ = fs::remove_file(&object);
+    linked
+}
+
+/// Generates C source from the IR of a checked program, folding constants first unless
+/// `--no-fold` is given.
+///
+/// # Returns
+///
+/// * `Result<CCodeGen, VentiError>` - The code generator holding the C source, or an error.
+fn compile_c(
+    args: &ArgMatches,
+    ast: Vec<Statement>,
+    session: &mut Session,
+) -> Result<CCodeGen, VentiError> {
+    session.options.fold = !args.get_flag("no-fold");
+    let mut codegen = CCodeGen::new();
+    Driver::new(session).generate_ir(ast, |program| codegen.compile(program))?;
+    Ok(codegen)
+}
+
+/// Compiles the C source held by `codegen` into an executable at `output` with the system
+/// C compiler, through a temporary `.c` file.
+fn compile_c_executable(
+    codegen: &CCodeGen,
+    opt_level: u8,
+    output: &Path,
+) -> Result<(), VentiError> {
+    let source = std::env::temp_dir().join(format!("venti-{}.c", process::id()));
+    codegen.write_source(&source)?;
+    let compiled = codegen_c::backend::compile_executable(&source, output, opt_level);
+    let _ = fs::remove_file(&source);
+    compiled
+}
+
+/// Writes the compiled module to `output` in the requested form.
+///
+/// # Arguments
+///
+/// * `codegen` - The code generator holding the compiled module.
+/// * `machine` - The target machine used for native and WebAssembly output.
+/// * `kind` - The kind of artifact to produce.
+/// * `wasm` - Whether the target is WebAssembly, which links with `wasm-ld`.
+/// * `pie` - Whether native code is position-independent, so it links as a PIE.
+/// * `output` - The path to write to.
+///
+/// # Returns
+///
+/// * `Result<PathBuf, VentiError>` - The path that was written, or an error.
+fn emit_output(
+    codegen: &CodeGen,
+    machine: &TargetMachine,
+    kind: OutputKind,
+    wasm: bool,
+    pie: bool,
+    output: &Path,
+) -> Result<PathBuf, VentiError> {
+    match kind {
+        OutputKind::LlvmIr => codegen.write_ir(output)?,
+        OutputKind::Bitcode => codegen.write_bitcode(output)?,
+        OutputKind::Assembly => backend::write_assembly(codegen.module(), machine, output)?,
+        OutputKind::Object => backend::write_object(codegen.module(), machine, output)?,
+        OutputKind::Executable => link_module(codegen.module(), machine, wasm, pie, output)?,
+        OutputKind::C => {
+            return Err(VentiError::CodegenError(
+                "Only the C backend writes C source; build with --backend c".to_string(),
+                None,
+            ))
+        }
+    }
+    Ok(output.to_path_buf())
 }
@@ -0,0 +1,2 @@
+pub mod qualify;
+pub mod resolver;
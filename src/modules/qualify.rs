@@ -0,0 +1,326 @@
+use crate::venti_parser::ast::{Expr, ExprKind, Parameter, Statement};
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+/// Returns the name `name`, declared at the top level of the file qualified with `prefix`,
+/// is compiled under.
+///
+/// The `.` cannot appear in an identifier, so a qualified name never collides with a name
+/// declared in the root program.
+pub fn qualified_name(prefix: &str, name: &str) -> String {
+    format!("{}.{}", prefix, name)
+}
+
+/// Renames the declarations of one file to their qualified names, and turns calls such
+/// as `utils.add(1, 2)` into calls of the function an import brought in.
+///
+/// Locals, parameters and loop variables keep their names, so a local that shadows a
+/// top-level variable is left alone. Every function is renamed, even nested ones, since
+/// codegen puts all functions in one namespace.
+pub struct Qualifier<'a> {
+    // What the file's names are qualified with, or None for the root program
+    prefix: Option<&'a str>,
+    // The file's top-level variables, and the functions it declares at any depth
+    globals: HashSet<String>,
+    functions: HashSet<String>,
+    // The file's import aliases, mapped to the prefix of the file each one names
+    aliases: &'a HashMap<String, String>,
+    // The local scopes around the code being renamed, innermost last
+    scopes: Vec<HashSet<String>>,
+}
+
+impl<'a> Qualifier<'a> {
+    /// Creates a `Qualifier` for the file made of `statements`.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the file, or `None` for the root program, whose names are
+    ///   kept as written.
+    /// * `aliases` - The aliases the file's imports bound.
+    /// * `statements` - The file's top-level statements, to collect its declarations from.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Qualifier`.
+    pub fn new(
+        prefix: Option<&'a str>,
+        aliases: &'a HashMap<String, String>,
+        statements: &[Statement],
+    ) -> Self {
+        let globals = statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::VariableDeclaration { identifier, .. } => Some(identifier.clone()),
+                _ => None,
+            })
+            .collect();
+        let mut functions = HashSet::new();
+        collect_functions(statements, &mut functions);
+        Qualifier {
+            prefix,
+            globals,
+            functions,
+            aliases,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Renames the names in `statements` in place.
+    pub fn qualify(&mut self, statements: &mut [Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    fn qualify_name(&self, name: &mut String) {
+        if let Some(prefix) = self.prefix {
+            *name = qualified_name(prefix, name);
+        }
+    }
+
+    /// Qualifies a use of the variable `name`, unless a local shadows it.
+    fn variable(&self, name: &mut String) {
+        if !self.is_local(name) && self.globals.contains(name.as_str()) {
+            self.qualify_name(name);
+        }
+    }
+
+    /// Qualifies a function name, whether it is being defined or called.
+    fn function(&self, name: &mut String) {
+        if self.functions.contains(name.as_str()) {
+            self.qualify_name(name);
+        }
+    }
+
+    /// Renames a block, which opens a scope starting out with `bindings`.
+    fn block(&mut self, body: &mut [Statement], bindings: HashSet<String>) {
+        self.scopes.push(bindings);
+        self.qualify(body);
+        self.scopes.pop();
+    }
+
+    /// Renames a function body, which sees the top-level variables and its parameters but
+    /// none of the enclosing locals.
+    fn function_body(&mut self, params: &[Parameter], body: &mut [Statement]) {
+        let params = params.iter().map(|param| param.name.clone()).collect();
+        let enclosing = mem::replace(&mut self.scopes, vec![params]);
+        self.qualify(body);
+        self.scopes = enclosing;
+    }
+
+    fn statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::VariableDeclaration { identifier, value } => {
+                self.expr(value);
+                match self.scopes.last_mut() {
+                    Some(scope) => {
+                        scope.insert(identifier.clone());
+                    }
+                    None => self.qualify_name(identifier),
+                }
+            }
+            Statement::VariableAssignment { identifier, value } => {
+                self.expr(value);
+                self.variable(identifier);
+            }
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => {
+                self.expr(target);
+                self.expr(index);
+                self.expr(value);
+            }
+            // A call statement always names a function, never a lambda
+            Statement::FunctionCall { identifier, args } => {
+                args.iter_mut().for_each(|arg| self.expr(arg));
+                self.function(identifier);
+            }
+            Statement::Print { value, .. } => self.expr(value),
+            Statement::Expression(expr) => self.expr(expr),
+            Statement::Function {
+                identifier,
+                params,
+                body,
+                ..
+            }
+            | Statement::AsyncFunction {
+                identifier,
+                params,
+                body,
+                ..
+            } => {
+                self.function(identifier);
+                self.function_body(params, body);
+            }
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Statement::Match { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.block(&mut arm.body, HashSet::new());
+                }
+            }
+            Statement::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                self.expr(iterable);
+                self.block(body, HashSet::from([variable.clone()]));
+            }
+            Statement::Try {
+                body,
+                error,
+                handler,
+            } => {
+                self.block(body, HashSet::new());
+                self.block(handler, HashSet::from([error.clone()]));
+            }
+            Statement::Test { name, body } => {
+                self.qualify_name(name);
+                self.function_body(&[], body);
+            }
+            Statement::Assert(condition) => self.expr(condition),
+            // Traits and their methods are named the same in every file, like C functions
+            Statement::Impl { methods, .. } => {
+                for method in methods {
+                    if let Statement::Function { params, body, .. } = method {
+                        self.function_body(params, body);
+                    }
+                }
+            }
+            // C functions are called by their C names, whichever file declares them
+            Statement::Extern { .. }
+            | Statement::Trait { .. }
+            | Statement::Comment { .. }
+            | Statement::Import(_)
+            | Statement::Line(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Identifier(name) => self.variable(name),
+            ExprKind::BinaryOp(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::UnaryOp(_, operand) => self.expr(operand),
+            ExprKind::Call { callee, args } => {
+                args.iter_mut().for_each(|arg| self.expr(arg));
+                match &mut callee.kind {
+                    // A local lambda shadows a function of the same name, as in the analyzer
+                    ExprKind::Identifier(name)
+                        if !self.is_local(name) && self.functions.contains(name.as_str()) =>
+                    {
+                        self.qualify_name(name)
+                    }
+                    _ => self.expr(callee),
+                }
+            }
+            ExprKind::Lambda { params, body } => {
+                let params = params.iter().map(|param| param.name.clone()).collect();
+                self.scopes.push(params);
+                self.expr(body);
+                self.scopes.pop();
+            }
+            ExprKind::MethodCall {
+                receiver,
+                name,
+                args,
+            } => {
+                args.iter_mut().for_each(|arg| self.expr(arg));
+                let prefix = match &receiver.kind {
+                    ExprKind::Identifier(alias)
+                        if !self.is_local(alias) && !self.globals.contains(alias) =>
+                    {
+                        self.aliases.get(alias)
+                    }
+                    _ => None,
+                };
+                match prefix {
+                    Some(prefix) => {
+                        // The receiver's node becomes the callee
+                        receiver.kind = ExprKind::Identifier(qualified_name(prefix, name));
+                        if let ExprKind::MethodCall { receiver, args, .. } =
+                            mem::replace(&mut expr.kind, ExprKind::Null)
+                        {
+                            expr.kind = ExprKind::Call {
+                                callee: receiver,
+                                args,
+                            };
+                        }
+                    }
+                    None => self.expr(receiver),
+                }
+            }
+            ExprKind::Array(elements) => elements.iter_mut().for_each(|element| self.expr(element)),
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            ExprKind::Range { start, end, .. } => {
+                self.expr(start);
+                self.expr(end);
+            }
+            ExprKind::Index { target, index } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            ExprKind::Cast { value, .. } => self.expr(value),
+            ExprKind::Async(value) | ExprKind::Await(value) => self.expr(value),
+            ExprKind::Spawn(body) => self.block(body, HashSet::new()),
+            ExprKind::Number(_)
+            | ExprKind::Float(_)
+            | ExprKind::String(_)
+            | ExprKind::Boolean(_)
+            | ExprKind::Null => {}
+        }
+    }
+}
+
+/// Adds the name of every function declared in `statements`, however deeply nested.
+fn collect_functions(statements: &[Statement], functions: &mut HashSet<String>) {
+    for statement in statements {
+        match statement {
+            Statement::Function {
+                identifier, body, ..
+            }
+            | Statement::AsyncFunction {
+                identifier, body, ..
+            } => {
+                functions.insert(identifier.clone());
+                collect_functions(body, functions);
+            }
+            Statement::Match { arms, .. } => {
+                for arm in arms {
+                    collect_functions(&arm.body, functions);
+                }
+            }
+            Statement::For { body, .. } => collect_functions(body, functions),
+            Statement::Try { body, handler, .. } => {
+                collect_functions(body, functions);
+                collect_functions(handler, functions);
+            }
+            Statement::Impl { methods, .. } => {
+                for method in methods {
+                    if let Statement::Function { body, .. } = method {
+                        collect_functions(body, functions);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use crate::codes::ErrorCode;
+use crate::errors::VentiError;
+use crate::modules::qualify::Qualifier;
+use crate::source::SourceMap;
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::arena::Arena;
+use crate::venti_parser::ast::{Expr, Statement};
+use crate::venti_parser::parser::Parser;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+A program can pull in the functions of other files with `import_venti "path";` at its top
+level. The path is relative to the file the import is in. The resolver loads, lexes and
+parses each imported file, and merges its declarations into the importing program, so
+the later passes see one program:
+
+    import_venti "utils.venti";
+    printventi utils.add(1, 2);
+
+Everything an imported file declares at its top level is renamed to `utils.add` and the
+like, after the file's name, so two files may declare the same names. Its functions are
+then called as `utils.add(...)`, while its top-level variables are only visible to its
+own code. A file imported more than once is merged once, and a file that imports itself,
+directly or through others, is an error.
+*/
+
+/// Loads the files a program imports and merges their declarations into it.
+///
+/// The resolver keeps the files it has loaded, so a file imported from several places is
+/// merged once, and the repl can resolve one entry at a time.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    // The directory the root program's imports are relative to
+    directory: PathBuf,
+    // The prefix each loaded file's names are qualified with, by canonical path
+    modules: HashMap<PathBuf, String>,
+    // The files being loaded, outermost first, so an import cycle can be reported
+    loading: Vec<PathBuf>,
+    // The aliases bound by the root program's imports
+    aliases: HashMap<String, String>,
+}
+
+impl Resolver {
+    /// Creates a `Resolver` for a program that is not read from a file, such as one typed
+    /// into the repl.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory the program's imports are relative to.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Resolver`.
+    pub fn new(directory: &Path) -> Self {
+        Resolver {
+            directory: directory.to_path_buf(),
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            aliases: HashMap::new(),
+        }
+    }
+
+    /// Creates a `Resolver` for the program in the file at `path`, whose imports are
+    /// relative to the directory it is in.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Resolver`, for which a file importing `path` is an import cycle.
+    pub fn for_file(path: &Path) -> Self {
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut resolver = Resolver::new(directory);
+        if let Ok(canonical) = fs::canonicalize(path) {
+            resolver.loading.push(canonical);
+        }
+        resolver
+    }
+
+    /// Replaces the imports at the top level of a program with the declarations of the
+    /// files they name.
+    ///
+    /// Each file's declarations take the place of the first import of it, so its top-level
+    /// code runs before the code after that import.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The parsed program.
+    /// * `arena` - Holds the expressions of the loaded files, like the program's.
+    /// * `sources` - The source map the text of each loaded file is added to.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Statement>, VentiError>` - The merged program, or the error that kept
+    ///   an import from loading.
+    pub fn resolve<'a>(
+        &mut self,
+        statements: Vec<Statement<'a>>,
+        arena: &'a Arena<Expr<'a>>,
+        sources: &mut SourceMap,
+    ) -> Result<Vec<Statement<'a>>, VentiError> {
+        let mut aliases = std::mem::take(&mut self.aliases);
+        let directory = self.directory.clone();
+        let merged = self.merge(statements, &directory, None, &mut aliases, arena, sources);
+        self.aliases = aliases;
+        merged
+    }
+
+    /// Loads the imports of one file and qualifies the file's own declarations.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The file's parsed statements.
+    /// * `directory` - The directory the file's imports are relative to.
+    /// * `prefix` - What the file's names are qualified with, or `None` for the root program.
+    /// * `aliases` - The aliases bound by the file's imports, added to as imports are loaded.
+    /// * `arena` - Holds the expressions of the loaded files.
+    /// * `sources` - The source map the text of each loaded file is added to.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Statement>, VentiError>` - The file's statements with every import
+    ///   replaced, or an error.
+    fn merge<'a>(
+        &mut self,
+        statements: Vec<Statement<'a>>,
+        directory: &Path,
+        prefix: Option<&str>,
+        aliases: &mut HashMap<String, String>,
+        arena: &'a Arena<Expr<'a>>,
+        sources: &mut SourceMap,
+    ) -> Result<Vec<Statement<'a>>, VentiError> {
+        // The statements the file's imports load, with how many of the file's own
+        // statements come before each
+        let mut imported = Vec::new();
+        let mut own = Vec::new();
+        // The span of the statement being looked at, which errors about an import point to
+        let mut span = Span::default();
+        for statement in statements {
+            match statement {
+                Statement::Import(path) => {
+                    let (alias, module, body) = self
+                        .load(&directory.join(&path), &path, arena, sources)
+                        .map_err(|error| error.at(span))?;
+                    if aliases.get(&alias).is_some_and(|bound| *bound != module) {
+                        return Err(VentiError::SyntaxError(
+                            format!("Two imported files are named '{}'", alias),
+                            Some(span),
+                        )
+                        .with_code(ErrorCode::ImportNameClash));
+                    }
+                    aliases.insert(alias, module);
+                    imported.push((own.len(), body));
+                }
+                Statement::Line(line) => {
+                    span = line;
+                    own.push(statement);
+                }
+                statement => own.push(statement),
+            }
+        }
+        Qualifier::new(prefix, aliases, &own).qualify(&mut own);
+
+        let mut merged = Vec::new();
+        let mut own = own.into_iter();
+        let mut taken = 0;
+        for (position, body) in imported {
+            merged.extend(own.by_ref().take(position - taken));
+            merged.extend(body);
+            taken = position;
+        }
+        merged.extend(own);
+        Ok(merged)
+    }
+
+    /// Loads the imported file at `path`, unless it has been merged already.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file, relative to the current directory.
+    /// * `written` - The path as the import spells it, for error messages.
+    /// * `arena` - Holds the expressions of the file.
+    /// * `sources` - The source map the text of the file is added to.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(String, String, Vec<Statement>), VentiError>` - The alias the import
+    ///   binds, the prefix of the file and its merged statements, which are empty if it
+    ///   was loaded before; or an error if it cannot be read or parsed, or imports itself.
+    fn load<'a>(
+        &mut self,
+        path: &Path,
+        written: &str,
+        arena: &'a Arena<Expr<'a>>,
+        sources: &mut SourceMap,
+    ) -> Result<(String, String, Vec<Statement<'a>>), VentiError> {
+        let alias = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let canonical = fs::canonicalize(path).map_err(|e| {
+            VentiError::IOError(format!("Could not import {}: {}", written, e), None)
+                .with_code(ErrorCode::ImportNotFound)
+        })?;
+        if let Some(start) = self.loading.iter().position(|file| *file == canonical) {
+            let cycle = self.loading[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|file| file_name(file))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(
+                VentiError::SyntaxError(format!("Import cycle: {}", cycle), None)
+                    .with_code(ErrorCode::ImportCycle),
+            );
+        }
+        if let Some(module) = self.modules.get(&canonical) {
+            return Ok((alias, module.clone(), Vec::new()));
+        }
+
+        let source = fs::read_to_string(&canonical).map_err(|e| {
+            VentiError::IOError(format!("Could not import {}: {}", written, e), None)
+                .with_code(ErrorCode::ImportNotFound)
+        })?;
+        // Errors name the file as `utils.venti` rather than `./utils.venti`
+        let shown = path.strip_prefix(".").unwrap_or(path);
+        let file = sources.add(shown, &source, 1);
+        let statements = Parser::new(sources.lexer(file), arena).parse()?;
+
+        let module = self.unique_prefix(&alias);
+        self.modules.insert(canonical.clone(), module.clone());
+        let directory = canonical.parent().unwrap_or(Path::new(".")).to_path_buf();
+        self.loading.push(canonical);
+        let merged = self.merge(
+            statements,
+            &directory,
+            Some(&module),
+            &mut HashMap::new(),
+            arena,
+            sources,
+        );
+        self.loading.pop();
+        Ok((alias, module, merged?))
+    }
+
+    /// Picks the prefix for a newly loaded file named `alias`, numbering it if another
+    /// file of the same name was loaded first.
+    fn unique_prefix(&self, alias: &str) -> String {
+        let taken = |prefix: &str| self.modules.values().any(|module| module == prefix);
+        if !taken(alias) {
+            return alias.to_string();
+        }
+        (2..)
+            .map(|n| format!("{}{}", alias, n))
+            .find(|prefix| !taken(prefix))
+            .unwrap()
+    }
+}
+
+/// The name a file is shown by in an import cycle.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
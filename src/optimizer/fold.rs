@@ -0,0 +1,584 @@
+use crate::venti_parser::ast::{
+    BinOp, Expr, ExprKind, MatchArm, Pattern, Statement, UnaryOp, VarType,
+};
+use std::collections::HashMap;
+
+/// Folds constant expressions and removes dead code before codegen.
+///
+/// Variables declared exactly once with a literal initializer and never reassigned are
+/// treated as constants, and their uses after the declaration are replaced by the literal.
+/// A `match_venti` on a literal keeps only the arm that runs, and statements after a
+/// `return_venti` in the same block are dropped.
+pub struct Folder {
+    constants: HashMap<String, ExprKind<'static>>,
+    bindings: HashMap<String, Binding>,
+}
+
+/// How often a name is bound and assigned anywhere in the program.
+#[derive(Default)]
+struct Binding {
+    declarations: usize,
+    assigned: bool,
+}
+
+impl Folder {
+    /// Creates a new `Folder` for `statements`, recording how each name is bound.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The program that will be folded.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Folder`.
+    pub fn new(statements: &[Statement]) -> Self {
+        let mut folder = Folder {
+            constants: HashMap::new(),
+            bindings: HashMap::new(),
+        };
+        for statement in statements {
+            folder.record_statement(statement);
+        }
+        folder
+    }
+
+    /// Folds a whole program.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The statements of the program, as produced by the parser.
+    ///
+    /// # Returns
+    ///
+    /// The program with constant expressions folded and dead code removed.
+    pub fn fold<'a>(&mut self, statements: Vec<Statement<'a>>) -> Vec<Statement<'a>> {
+        self.fold_block(statements)
+    }
+
+    fn record_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::VariableDeclaration { identifier, value } => {
+                self.binding(identifier).declarations += 1;
+                self.record_expr(value);
+            }
+            Statement::VariableAssignment { identifier, value } => {
+                self.binding(identifier).assigned = true;
+                self.record_expr(value);
+            }
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => {
+                self.record_expr(target);
+                self.record_expr(index);
+                self.record_expr(value);
+            }
+            Statement::FunctionCall { args, .. } => {
+                args.iter().for_each(|arg| self.record_expr(arg))
+            }
+            Statement::Print { value, .. }
+            | Statement::Expression(value)
+            | Statement::Assert(value) => self.record_expr(value),
+            Statement::Test { body, .. } | Statement::Impl { methods: body, .. } => body
+                .iter()
+                .for_each(|statement| self.record_statement(statement)),
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.record_expr(value);
+                }
+            }
+            Statement::Function { params, body, .. }
+            | Statement::AsyncFunction { params, body, .. } => {
+                for param in params {
+                    self.binding(&param.name).declarations += 1;
+                }
+                body.iter()
+                    .for_each(|statement| self.record_statement(statement));
+            }
+            Statement::Match { scrutinee, arms } => {
+                self.record_expr(scrutinee);
+                for arm in arms {
+                    arm.body
+                        .iter()
+                        .for_each(|statement| self.record_statement(statement));
+                }
+            }
+            Statement::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                self.binding(variable).declarations += 1;
+                self.record_expr(iterable);
+                body.iter()
+                    .for_each(|statement| self.record_statement(statement));
+            }
+            Statement::Try {
+                body,
+                error,
+                handler,
+            } => {
+                self.binding(error).declarations += 1;
+                body.iter()
+                    .chain(handler)
+                    .for_each(|statement| self.record_statement(statement));
+            }
+            Statement::Extern { .. }
+            | Statement::Trait { .. }
+            | Statement::Comment { .. }
+            | Statement::Import(_)
+            | Statement::Line(_) => {}
+        }
+    }
+
+    fn record_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Lambda { params, body } => {
+                for param in params {
+                    self.binding(&param.name).declarations += 1;
+                }
+                self.record_expr(body);
+            }
+            ExprKind::BinaryOp(left, _, right) => {
+                self.record_expr(left);
+                self.record_expr(right);
+            }
+            ExprKind::UnaryOp(_, operand)
+            | ExprKind::Async(operand)
+            | ExprKind::Await(operand)
+            | ExprKind::Cast { value: operand, .. } => self.record_expr(operand),
+            ExprKind::Call { callee, args } => {
+                self.record_expr(callee);
+                args.iter().for_each(|arg| self.record_expr(arg));
+            }
+            ExprKind::MethodCall { receiver, args, .. } => {
+                self.record_expr(receiver);
+                args.iter().for_each(|arg| self.record_expr(arg));
+            }
+            ExprKind::Array(elements) => elements
+                .iter()
+                .for_each(|element| self.record_expr(element)),
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.record_expr(key);
+                    self.record_expr(value);
+                }
+            }
+            ExprKind::Range { start, end, .. } => {
+                self.record_expr(start);
+                self.record_expr(end);
+            }
+            ExprKind::Index { target, index } => {
+                self.record_expr(target);
+                self.record_expr(index);
+            }
+            ExprKind::Spawn(body) => body
+                .iter()
+                .for_each(|statement| self.record_statement(statement)),
+            ExprKind::Number(_)
+            | ExprKind::Float(_)
+            | ExprKind::String(_)
+            | ExprKind::Boolean(_)
+            | ExprKind::Null
+            | ExprKind::Identifier(_) => {}
+        }
+    }
+
+    fn binding(&mut self, name: &str) -> &mut Binding {
+        self.bindings.entry(name.to_string()).or_default()
+    }
+
+    /// Folds the statements of one block, dropping everything after a `return_venti`.
+    fn fold_block<'a>(&mut self, statements: Vec<Statement<'a>>) -> Vec<Statement<'a>> {
+        let mut folded = Vec::new();
+        for statement in statements {
+            let returns = matches!(statement, Statement::Return(_));
+            if let Some(statement) = self.fold_statement(statement) {
+                folded.push(statement);
+            }
+            if returns {
+                break;
+            }
+        }
+        folded
+    }
+
+    /// Folds one statement.
+    ///
+    /// # Returns
+    ///
+    /// The folded statement, or `None` if it can never have an effect.
+    fn fold_statement<'a>(&mut self, statement: Statement<'a>) -> Option<Statement<'a>> {
+        let statement = match statement {
+            Statement::VariableDeclaration { identifier, value } => {
+                let value = self.fold_expr(value);
+                let binding = &self.bindings[&identifier];
+                if binding.declarations == 1 && !binding.assigned {
+                    if let Some(literal) = literal_copy(&value.kind) {
+                        self.constants.insert(identifier.clone(), literal);
+                    }
+                }
+                Statement::VariableDeclaration { identifier, value }
+            }
+            Statement::VariableAssignment { identifier, value } => Statement::VariableAssignment {
+                identifier,
+                value: self.fold_expr(value),
+            },
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => Statement::IndexAssignment {
+                target: self.fold_expr(target),
+                index: self.fold_expr(index),
+                value: self.fold_expr(value),
+            },
+            Statement::FunctionCall { identifier, args } => Statement::FunctionCall {
+                identifier,
+                args: self.fold_exprs(args),
+            },
+            Statement::Print { value, newline } => Statement::Print {
+                value: self.fold_expr(value),
+                newline,
+            },
+            Statement::Expression(expr) => Statement::Expression(self.fold_expr(expr)),
+            Statement::Function {
+                identifier,
+                params,
+                returns,
+                body,
+                inline,
+            } => Statement::Function {
+                identifier,
+                params,
+                returns,
+                body: self.fold_block(body),
+                inline,
+            },
+            Statement::AsyncFunction {
+                identifier,
+                params,
+                body,
+                inline,
+            } => Statement::AsyncFunction {
+                identifier,
+                params,
+                body: self.fold_block(body),
+                inline,
+            },
+            Statement::Return(value) => Statement::Return(value.map(|value| self.fold_expr(value))),
+            Statement::Match { scrutinee, arms } => return self.fold_match(scrutinee, arms),
+            Statement::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                let iterable = self.fold_expr(iterable);
+                let is_empty = match &iterable.kind {
+                    ExprKind::Number(count) => *count <= 0,
+                    ExprKind::Range {
+                        start,
+                        end,
+                        inclusive,
+                    } => match (&start.kind, &end.kind) {
+                        (ExprKind::Number(start), ExprKind::Number(end)) if *inclusive => {
+                            start > end
+                        }
+                        (ExprKind::Number(start), ExprKind::Number(end)) => start >= end,
+                        _ => false,
+                    },
+                    _ => false,
+                };
+                if is_empty {
+                    return None;
+                }
+                Statement::For {
+                    variable,
+                    iterable,
+                    body: self.fold_block(body),
+                }
+            }
+            Statement::Test { name, body } => Statement::Test {
+                name,
+                body: self.fold_block(body),
+            },
+            Statement::Try {
+                body,
+                error,
+                handler,
+            } => Statement::Try {
+                body: self.fold_block(body),
+                error,
+                handler: self.fold_block(handler),
+            },
+            Statement::Assert(condition) => Statement::Assert(self.fold_expr(condition)),
+            Statement::Comment { .. } => return None,
+            Statement::Import(path) => Statement::Import(path),
+            statement @ (Statement::Extern { .. } | Statement::Trait { .. }) => statement,
+            Statement::Impl {
+                trait_name,
+                target,
+                methods,
+            } => Statement::Impl {
+                trait_name,
+                target,
+                methods: self.fold_block(methods),
+            },
+            Statement::Line(line) => Statement::Line(line),
+        };
+        Some(statement)
+    }
+
+    /// Folds a match, keeping only the arm that runs when the scrutinee is a literal.
+    ///
+    /// The chosen arm becomes the only, wildcard arm so its body keeps its own scope.
+    fn fold_match<'a>(
+        &mut self,
+        scrutinee: Expr<'a>,
+        arms: Vec<MatchArm<'a>>,
+    ) -> Option<Statement<'a>> {
+        let scrutinee = self.fold_expr(scrutinee);
+        let chosen = match &scrutinee.kind {
+            ExprKind::Number(value) => Some(arms.iter().position(|arm| match &arm.pattern {
+                Pattern::Number(n) => n == value,
+                Pattern::Wildcard => true,
+                Pattern::String(_) => false,
+            })),
+            ExprKind::String(value) => Some(arms.iter().position(|arm| match &arm.pattern {
+                Pattern::String(s) => s == value,
+                Pattern::Wildcard => true,
+                Pattern::Number(_) => false,
+            })),
+            _ => None,
+        };
+        let arms = match chosen {
+            // No arm matches, so the match does nothing
+            Some(None) => return None,
+            Some(Some(position)) => {
+                let arm = arms.into_iter().nth(position)?;
+                vec![MatchArm {
+                    pattern: Pattern::Wildcard,
+                    body: self.fold_block(arm.body),
+                }]
+            }
+            None => arms
+                .into_iter()
+                .map(|arm| MatchArm {
+                    pattern: arm.pattern,
+                    body: self.fold_block(arm.body),
+                })
+                .collect(),
+        };
+        Some(Statement::Match { scrutinee, arms })
+    }
+
+    fn fold_exprs<'a>(&mut self, exprs: Vec<Expr<'a>>) -> Vec<Expr<'a>> {
+        exprs.into_iter().map(|expr| self.fold_expr(expr)).collect()
+    }
+
+    /// Folds an operand in place, reusing its node in the arena.
+    fn fold_node<'a>(&mut self, expr: &'a mut Expr<'a>) -> &'a mut Expr<'a> {
+        *expr = self.fold_expr(expr.take());
+        expr
+    }
+
+    /// Folds an expression bottom-up, replacing constant subtrees with literals that keep
+    /// the span of the subtree.
+    fn fold_expr<'a>(&mut self, expr: Expr<'a>) -> Expr<'a> {
+        Expr::new(self.fold_kind(expr.kind), expr.span)
+    }
+
+    fn fold_kind<'a>(&mut self, kind: ExprKind<'a>) -> ExprKind<'a> {
+        match kind {
+            ExprKind::Identifier(id) => match self.constants.get(&id).and_then(literal_copy) {
+                Some(literal) => literal,
+                None => ExprKind::Identifier(id),
+            },
+            ExprKind::BinaryOp(left, op, right) => {
+                let left = self.fold_node(left);
+                let right = self.fold_node(right);
+                fold_binary(left, op, right)
+            }
+            ExprKind::UnaryOp(op, operand) => {
+                let operand = self.fold_node(operand);
+                match (op, &operand.kind) {
+                    (UnaryOp::Negate, ExprKind::Number(n)) => ExprKind::Number(n.wrapping_neg()),
+                    (UnaryOp::Negate, ExprKind::Float(f)) => ExprKind::Float(-f),
+                    (UnaryOp::Not, ExprKind::Boolean(b)) => ExprKind::Boolean(!b),
+                    _ => ExprKind::UnaryOp(op, operand),
+                }
+            }
+            ExprKind::Call { callee, args } => ExprKind::Call {
+                callee: self.fold_node(callee),
+                args: self.fold_exprs(args),
+            },
+            ExprKind::Lambda { params, body } => ExprKind::Lambda {
+                params,
+                body: self.fold_node(body),
+            },
+            ExprKind::MethodCall {
+                receiver,
+                name,
+                args,
+            } => ExprKind::MethodCall {
+                receiver: self.fold_node(receiver),
+                name,
+                args: self.fold_exprs(args),
+            },
+            ExprKind::Array(elements) => ExprKind::Array(self.fold_exprs(elements)),
+            ExprKind::Map(entries) => ExprKind::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (self.fold_expr(key), self.fold_expr(value)))
+                    .collect(),
+            ),
+            ExprKind::Range {
+                start,
+                end,
+                inclusive,
+            } => ExprKind::Range {
+                start: self.fold_node(start),
+                end: self.fold_node(end),
+                inclusive,
+            },
+            ExprKind::Index { target, index } => ExprKind::Index {
+                target: self.fold_node(target),
+                index: self.fold_node(index),
+            },
+            ExprKind::Cast { value, target } => fold_cast(self.fold_node(value), target),
+            ExprKind::Async(inner) => ExprKind::Async(self.fold_node(inner)),
+            ExprKind::Await(inner) => ExprKind::Await(self.fold_node(inner)),
+            ExprKind::Spawn(body) => ExprKind::Spawn(self.fold_block(body)),
+            literal => literal,
+        }
+    }
+}
+
+/// Returns a copy of `kind` if it is a literal that can be substituted for a constant.
+fn literal_copy<'a>(kind: &ExprKind) -> Option<ExprKind<'a>> {
+    match kind {
+        ExprKind::Number(n) => Some(ExprKind::Number(*n)),
+        ExprKind::Float(f) => Some(ExprKind::Float(*f)),
+        ExprKind::Boolean(b) => Some(ExprKind::Boolean(*b)),
+        ExprKind::String(s) => Some(ExprKind::String(s.clone())),
+        _ => None,
+    }
+}
+
+/// Folds `left op right` when both operands are literals, with the same results codegen
+/// would compute at run time.
+///
+/// Integer arithmetic wraps, comparisons yield a bool, and operations whose result
+/// is undefined or a run-time error, like division by zero, are left alone.
+fn fold_binary<'a>(left: &'a mut Expr<'a>, op: BinOp, right: &'a mut Expr<'a>) -> ExprKind<'a> {
+    let folded = match (&left.kind, &right.kind) {
+        (ExprKind::Number(l), ExprKind::Number(r)) => fold_int(*l, op, *r),
+        (ExprKind::Float(l), ExprKind::Float(r)) => fold_float(*l, op, *r),
+        (ExprKind::Float(l), ExprKind::Number(r)) => fold_float(*l, op, *r as f64),
+        (ExprKind::Number(l), ExprKind::Float(r)) => fold_float(*l as f64, op, *r),
+        (ExprKind::Boolean(l), ExprKind::Boolean(r)) => fold_bool(*l, op, *r),
+        (ExprKind::String(l), ExprKind::String(r)) if op != BinOp::Coalesce => {
+            fold_string(l, op, r)
+        }
+        // `??` only evaluates its right side for `nothing`
+        (ExprKind::Null, _) if op == BinOp::Coalesce => return right.take().kind,
+        (ExprKind::String(_), _) if op == BinOp::Coalesce => return left.take().kind,
+        // `&&` and `||` skip their right side once the left decides the result
+        (ExprKind::Boolean(false), _) if op == BinOp::And => return ExprKind::Boolean(false),
+        (ExprKind::Boolean(true), _) if op == BinOp::Or => return ExprKind::Boolean(true),
+        (ExprKind::Boolean(_), _) if matches!(op, BinOp::And | BinOp::Or) => {
+            return right.take().kind
+        }
+        _ => None,
+    };
+    folded.unwrap_or(ExprKind::BinaryOp(left, op, right))
+}
+
+/// Folds a comparison of two string literals, which codegen orders byte by byte like
+/// `strcmp`.
+fn fold_string<'a>(left: &str, op: BinOp, right: &str) -> Option<ExprKind<'a>> {
+    let result = match op {
+        BinOp::Equal => left == right,
+        BinOp::NotEqual => left != right,
+        BinOp::Less => left < right,
+        BinOp::LessEqual => left <= right,
+        BinOp::Greater => left > right,
+        BinOp::GreaterEqual => left >= right,
+        _ => return None,
+    };
+    Some(ExprKind::Boolean(result))
+}
+
+/// Folds `value as target` when `value` is a literal.
+///
+/// Rust's `as` truncates floats toward zero and saturates them at the ends of the `i64`
+/// range, with NaN becoming 0, just like the conversion codegen emits.
+fn fold_cast<'a>(value: &'a mut Expr<'a>, target: VarType) -> ExprKind<'a> {
+    match (&value.kind, &target) {
+        (ExprKind::Number(n), VarType::Float) => ExprKind::Float(*n as f64),
+        (ExprKind::Number(n), VarType::Bool) => ExprKind::Boolean(*n != 0),
+        (ExprKind::Float(f), VarType::Int) => ExprKind::Number(*f as i64),
+        (ExprKind::Float(f), VarType::Bool) => ExprKind::Boolean(*f != 0.0),
+        (ExprKind::Boolean(b), VarType::Int) => ExprKind::Number(*b as i64),
+        (ExprKind::Boolean(b), VarType::Float) => ExprKind::Float(*b as i64 as f64),
+        (ExprKind::Number(_), VarType::Int)
+        | (ExprKind::Float(_), VarType::Float)
+        | (ExprKind::Boolean(_), VarType::Bool) => value.take().kind,
+        _ => ExprKind::Cast { value, target },
+    }
+}
+
+fn fold_int<'a>(left: i64, op: BinOp, right: i64) -> Option<ExprKind<'a>> {
+    let compare = |result: bool| Some(ExprKind::Boolean(result));
+    let value = match op {
+        BinOp::Add => left.wrapping_add(right),
+        BinOp::Subtract => left.wrapping_sub(right),
+        BinOp::Multiply => left.wrapping_mul(right),
+        BinOp::Divide => left.checked_div(right)?,
+        BinOp::Equal => return compare(left == right),
+        BinOp::NotEqual => return compare(left != right),
+        BinOp::Less => return compare(left < right),
+        BinOp::LessEqual => return compare(left <= right),
+        BinOp::Greater => return compare(left > right),
+        BinOp::GreaterEqual => return compare(left >= right),
+        BinOp::BitAnd => left & right,
+        BinOp::BitOr => left | right,
+        BinOp::BitXor => left ^ right,
+        // Shifting by 64 or more is undefined in LLVM, so only fold in-range amounts
+        BinOp::ShiftLeft if (0..64).contains(&right) => left << right,
+        BinOp::ShiftRight if (0..64).contains(&right) => left >> right,
+        BinOp::ShiftLeft | BinOp::ShiftRight | BinOp::And | BinOp::Or | BinOp::Coalesce => {
+            return None
+        }
+    };
+    Some(ExprKind::Number(value))
+}
+
+fn fold_float<'a>(left: f64, op: BinOp, right: f64) -> Option<ExprKind<'a>> {
+    let compare = |result: bool| Some(ExprKind::Boolean(result));
+    match op {
+        BinOp::Add => Some(ExprKind::Float(left + right)),
+        BinOp::Subtract => Some(ExprKind::Float(left - right)),
+        BinOp::Multiply => Some(ExprKind::Float(left * right)),
+        BinOp::Divide => Some(ExprKind::Float(left / right)),
+        BinOp::Equal => compare(left == right),
+        BinOp::NotEqual => compare(left != right),
+        BinOp::Less => compare(left < right),
+        BinOp::LessEqual => compare(left <= right),
+        BinOp::Greater => compare(left > right),
+        BinOp::GreaterEqual => compare(left >= right),
+        _ => None,
+    }
+}
+
+fn fold_bool<'a>(left: bool, op: BinOp, right: bool) -> Option<ExprKind<'a>> {
+    let value = match op {
+        BinOp::And | BinOp::BitAnd => left & right,
+        BinOp::Or | BinOp::BitOr => left | right,
+        BinOp::BitXor | BinOp::NotEqual => left ^ right,
+        BinOp::Equal => left == right,
+        _ => return None,
+    };
+    Some(ExprKind::Boolean(value))
+}
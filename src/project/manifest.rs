@@ -0,0 +1,219 @@
+use crate::codegen::backend::{self, OutputKind};
+use crate::errors::VentiError;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+A project is a directory with a `Venti.toml` manifest, which records what `venti build`
+and `venti run` would otherwise be told with flags:
+
+    [package]
+    name = "hello"
+
+    [build]
+    entry = "src/main.venti"
+    output = "hello"
+    opt-level = 2
+    target = "wasm32"
+    emit = "exe"
+
+Only `name` is required. Paths are relative to the directory the manifest is in. The
+manifest is read with a small TOML reader that knows tables, strings, integers and `#`
+comments, which is all a manifest uses.
+*/
+
+/// The name of the manifest file at the root of a project.
+pub const MANIFEST_FILE: &str = "Venti.toml";
+
+/// The program a project builds when its manifest names no entry point.
+pub const DEFAULT_ENTRY: &str = "src/main.venti";
+
+/// The settings read from a project's `Venti.toml`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    /// The directory the manifest is in, which its paths are relative to.
+    pub root: PathBuf,
+    pub name: String,
+    pub entry: PathBuf,
+    /// The path of the built artifact, without the extension its kind adds.
+    pub output: Option<PathBuf>,
+    pub opt_level: Option<u8>,
+    pub target: Option<String>,
+    pub emit: Option<OutputKind>,
+}
+
+/// A value on the right of a `key = value` line.
+enum Value {
+    String(String),
+    Integer(i64),
+}
+
+impl Manifest {
+    /// Finds the manifest of the project `directory` is in, looking in it and then in each
+    /// of its parents.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Manifest>, VentiError>` - The manifest, `None` if there is no
+    ///   project, or an error if the manifest cannot be read or is invalid.
+    pub fn find(directory: &Path) -> Result<Option<Manifest>, VentiError> {
+        let directory = fs::canonicalize(directory).unwrap_or_else(|_| directory.to_path_buf());
+        for candidate in directory.ancestors() {
+            let path = candidate.join(MANIFEST_FILE);
+            if path.is_file() {
+                return Manifest::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads and parses the manifest at `path`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Manifest, VentiError>` - The manifest, or an error if it cannot be read or
+    ///   is invalid.
+    pub fn load(path: &Path) -> Result<Manifest, VentiError> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            VentiError::IOError(format!("Could not read {}: {}", path.display(), e), None)
+        })?;
+        let root = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        Manifest::parse(&text, root)
+    }
+
+    /// Parses the text of a manifest.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The contents of `Venti.toml`.
+    /// * `root` - The directory the manifest is in.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Manifest, VentiError>` - The manifest, or a `VentiError::SyntaxError`
+    ///   naming the line that is invalid.
+    pub fn parse(text: &str, root: &Path) -> Result<Manifest, VentiError> {
+        let mut manifest = Manifest {
+            root: root.to_path_buf(),
+            name: String::new(),
+            entry: PathBuf::from(DEFAULT_ENTRY),
+            output: None,
+            opt_level: None,
+            target: None,
+            emit: None,
+        };
+        let mut table = String::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| {
+                VentiError::SyntaxError(
+                    format!("{} line {}: {}", MANIFEST_FILE, index + 1, message),
+                    None,
+                )
+            };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| error("Expected ']' after the table name".to_string()))?;
+                table = name.trim().to_string();
+                if table != "package" && table != "build" {
+                    return Err(error(format!("Unknown table [{}]", table)));
+                }
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("Expected 'key = value'".to_string()))?;
+            let key = key.trim();
+            let value = parse_value(value.trim()).ok_or_else(|| {
+                error(format!(
+                    "The value of '{}' must be a quoted string or an integer",
+                    key
+                ))
+            })?;
+            match (table.as_str(), key, value) {
+                ("package", "name", Value::String(name)) => manifest.name = name,
+                ("build", "entry", Value::String(entry)) => manifest.entry = PathBuf::from(entry),
+                ("build", "output", Value::String(output)) => {
+                    manifest.output = Some(PathBuf::from(output))
+                }
+                ("build", "opt-level", Value::Integer(level @ 0..=3)) => {
+                    manifest.opt_level = Some(level as u8)
+                }
+                ("build", "opt-level", _) => {
+                    return Err(error("opt-level must be 0, 1, 2 or 3".to_string()))
+                }
+                ("build", "target", Value::String(target)) => manifest.target = Some(target),
+                ("build", "emit", Value::String(emit)) => {
+                    manifest.emit = Some(OutputKind::from_flag(&emit).ok_or_else(|| {
+                        error(format!(
+                            "Unknown emit kind '{}', expected llvm-ir, bc, asm, obj, exe or c",
+                            emit
+                        ))
+                    })?)
+                }
+                ("package", "name", Value::Integer(_))
+                | ("build", "entry" | "output" | "target" | "emit", Value::Integer(_)) => {
+                    return Err(error(format!("The value of '{}' must be a string", key)))
+                }
+                ("", _, _) => return Err(error(format!("'{}' must be inside a table", key))),
+                (table, key, _) => {
+                    return Err(error(format!("Unknown key '{}' in [{}]", key, table)))
+                }
+            }
+        }
+        if manifest.name.is_empty() {
+            return Err(VentiError::SyntaxError(
+                format!("{} has no 'name' in its [package] table", MANIFEST_FILE),
+                None,
+            ));
+        }
+        Ok(manifest)
+    }
+
+    /// The path of the program the project builds.
+    pub fn entry_path(&self) -> PathBuf {
+        self.root.join(&self.entry)
+    }
+
+    /// The path `venti build` writes an artifact of `kind` to when `-o` is not given: the
+    /// manifest's `output`, or the project's name, in the project directory.
+    pub fn output_path(&self, kind: OutputKind, wasm: bool) -> PathBuf {
+        let output = self
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(&self.name));
+        let file_name = backend::default_output_path(&output, kind, wasm);
+        let directory = output.parent().unwrap_or(Path::new(""));
+        self.root.join(directory).join(file_name)
+    }
+}
+
+/// Removes a `#` comment from the end of a line, unless the `#` is inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parses a quoted string or an integer.
+fn parse_value(text: &str) -> Option<Value> {
+    if let Some(quoted) = text.strip_prefix('"') {
+        let string = quoted.strip_suffix('"')?;
+        return (!string.contains('"')).then(|| Value::String(string.to_string()));
+    }
+    text.parse().ok().map(Value::Integer)
+}
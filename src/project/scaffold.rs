@@ -0,0 +1,87 @@
+use crate::errors::VentiError;
+use crate::project::manifest::{DEFAULT_ENTRY, MANIFEST_FILE};
+use std::fs;
+use std::path::Path;
+
+/// The program a new project starts with.
+const HELLO: &str = "printventi(\"Hello, Venti!\");\n";
+
+/// Creates the directory `path` and a project in it named after the directory.
+///
+/// # Returns
+///
+/// * `Result<String, VentiError>` - The name of the project, or an error if `path` exists
+///   or cannot be created.
+pub fn new_project(path: &Path) -> Result<String, VentiError> {
+    if path.exists() {
+        return Err(VentiError::IOError(
+            format!("{} already exists", path.display()),
+            None,
+        ));
+    }
+    fs::create_dir_all(path).map_err(|e| write_error(path, e))?;
+    init_project(path)
+}
+
+/// Turns the existing directory `directory` into a project named after it, writing a
+/// manifest and, unless there is one already, a program at the default entry point.
+///
+/// # Returns
+///
+/// * `Result<String, VentiError>` - The name of the project, or an error if the directory
+///   already has a manifest or a file cannot be written.
+pub fn init_project(directory: &Path) -> Result<String, VentiError> {
+    let manifest = directory.join(MANIFEST_FILE);
+    if manifest.exists() {
+        return Err(VentiError::IOError(
+            format!("{} already exists", manifest.display()),
+            None,
+        ));
+    }
+    let name = project_name(directory);
+
+    let entry = directory.join(DEFAULT_ENTRY);
+    if !entry.exists() {
+        if let Some(parent) = entry.parent() {
+            fs::create_dir_all(parent).map_err(|e| write_error(parent, e))?;
+        }
+        fs::write(&entry, HELLO).map_err(|e| write_error(&entry, e))?;
+    }
+    let text = format!(
+        "[package]\nname = \"{}\"\n\n[build]\nentry = \"{}\"\nopt-level = 0\n",
+        name, DEFAULT_ENTRY
+    );
+    fs::write(&manifest, text).map_err(|e| write_error(&manifest, e))?;
+    Ok(name)
+}
+
+/// Names a project after its directory, replacing the characters that are awkward in a
+/// file name.
+fn project_name(directory: &Path) -> String {
+    let directory = fs::canonicalize(directory).unwrap_or_else(|_| directory.to_path_buf());
+    let name = directory
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if name.is_empty() {
+        "main".to_string()
+    } else {
+        name
+    }
+}
+
+fn write_error(path: &Path, error: std::io::Error) -> VentiError {
+    VentiError::IOError(
+        format!("Could not write {}: {}", path.display(), error),
+        None,
+    )
+}
@@ -0,0 +1,239 @@
+use inkwell::context::Context;
+use inkwell::OptimizationLevel;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use venti::codegen::backend;
+use venti::codegen::codegen::CodeGen;
+use venti::diagnostics::Diagnostic;
+use venti::errors::VentiError;
+use venti::modules::resolver::Resolver;
+use venti::semantic::analyzer::Analyzer;
+use venti::semantic::infer::infer;
+use venti::source::SourceMap;
+use venti::venti_lexer::lexer::Lexer;
+use venti::venti_lexer::span::Span;
+use venti::venti_lexer::token::Token;
+use venti::venti_parser::arena::Arena;
+use venti::venti_parser::ast::{Expr, ExprKind, Statement};
+use venti::venti_parser::parser::Parser;
+
+/*
+`venti repl` reads a program from stdin one entry at a time and runs each entry as soon as
+it is complete. An entry is usually one line; a line that leaves a `{` open continues on
+the next ones until the braces balance, so functions and loops can be typed in as usual.
+The `;` at the end of an entry may be left out.
+
+The session keeps its analyzer and code generator between entries, so top-level variables
+and functions defined by one entry can be used by all the later ones. An entry that fails
+to parse, check or compile is reported and leaves the session unchanged. A runtime error
+an entry does not catch is reported too, and stops the entry where it was raised, keeping
+whatever it defined. When an entry ends with an expression, or a call, its value is
+printed. The session ends at the end of the input.
+*/
+
+const PROMPT: &str = "venti> ";
+const CONTINUATION_PROMPT: &str = "  ...> ";
+
+/// The name errors give the session's entries in place of a file.
+const REPL_FILE: &str = "repl";
+
+/// Runs an interactive session on stdin until the input ends, coloring errors when
+/// `color` is true.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - The exit code, or an error if stdin or stdout fail.
+pub fn run(color: bool) -> Result<i32, VentiError> {
+    backend::initialize_host()?;
+    let context = Context::create();
+    let mut codegen = CodeGen::new(&context, OptimizationLevel::None).with_module_name("repl");
+    let mut analyzer = Analyzer::new();
+    let mut resolver = Resolver::new(Path::new("."));
+    let mut sources = SourceMap::new();
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut entry = String::new();
+    // The line the next entry starts on, so errors name lines of the whole session
+    let mut first_line = 1;
+    loop {
+        prompt(if entry.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        })?;
+        let mut line = String::new();
+        let read = input
+            .read_line(&mut line)
+            .map_err(|e| VentiError::IOError(e.to_string(), None))?;
+        if read == 0 {
+            println!();
+            return Ok(0);
+        }
+        entry.push_str(&line);
+        if entry.trim().is_empty() {
+            entry.clear();
+            first_line += 1;
+            continue;
+        }
+        if open_braces(&entry) > 0 {
+            continue;
+        }
+
+        let lines = entry.matches('\n').count() as u32;
+        if let Err(error) = run_entry(
+            &mut codegen,
+            &mut analyzer,
+            &mut resolver,
+            &mut sources,
+            &entry,
+            first_line,
+        ) {
+            eprint!("{}", Diagnostic::new(&error, &sources).with_color(color));
+        }
+        first_line += lines;
+        entry.clear();
+    }
+}
+
+/// Prints `text` and flushes it, since prompts do not end with a newline.
+fn prompt(text: &str) -> Result<(), VentiError> {
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", text)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| VentiError::IOError(e.to_string(), None))
+}
+
+/// Counts the braces `source` opens but does not close.
+///
+/// Source that does not lex counts as complete, so the lexer error is reported for it.
+fn open_braces(source: &str) -> i64 {
+    let mut lexer = Lexer::new(source);
+    let mut depth = 0;
+    while let Some(token) = lexer.next_token() {
+        match token {
+            Ok(Token::LBrace) => depth += 1,
+            Ok(Token::RBrace) => depth -= 1,
+            Ok(_) => {}
+            Err(_) => return 0,
+        }
+    }
+    depth
+}
+
+/// Parses, checks and runs one entry of the session.
+///
+/// # Arguments
+///
+/// * `codegen` - The session's code generator, which keeps the compiled entries.
+/// * `analyzer` - The session's analyzer, updated only if the entry checks.
+/// * `resolver` - The session's resolver, which knows the files imported so far.
+/// * `sources` - The source map the entry and the files it imports are added to.
+/// * `source` - The text of the entry.
+/// * `first_line` - The session line the entry starts on.
+///
+/// # Returns
+///
+/// * `Result<(), VentiError>` - Nothing, or the first error found in the entry.
+fn run_entry(
+    codegen: &mut CodeGen,
+    analyzer: &mut Analyzer,
+    resolver: &mut Resolver,
+    sources: &mut SourceMap,
+    source: &str,
+    first_line: u32,
+) -> Result<(), VentiError> {
+    let source = source.trim_end();
+    let file = sources.add(Path::new(REPL_FILE), source, first_line);
+    let arena = Arena::new();
+    let statements = if source.ends_with(';') {
+        parse(source, first_line, file, &arena)?
+    } else {
+        // An entry ending in `}` may be a block, which takes no `;`, or a map literal
+        match parse(&format!("{};", source), first_line, file, &arena) {
+            Err(_) if source.ends_with('}') => parse(source, first_line, file, &arena)?,
+            statements => statements?,
+        }
+    };
+
+    // Resolve and check copies, so an entry with an error leaves no trace in the session
+    let mut resolved = resolver.clone();
+    let mut statements = resolved.resolve(statements, &arena, sources)?;
+    infer(&mut statements)?;
+    let mut checked = analyzer.clone();
+    checked.analyze(&statements)?;
+    if let Some(last) = statements.pop() {
+        let span = match statements.last() {
+            Some(Statement::Line(span)) => *span,
+            _ => Span::default(),
+        };
+        let last = print_result(&mut checked, last, span, &arena)?;
+        statements.push(last);
+    }
+    codegen.set_symbols(checked.symbols().clone());
+    let ran = codegen.run_entry(statements);
+    // An entry stopped by a runtime error still defined everything it declares
+    if let Ok(()) | Err(VentiError::RuntimeError(..)) = ran {
+        *analyzer = checked;
+        *resolver = resolved;
+    }
+    ran
+}
+
+/// Lexes and parses the text of an entry, numbering its lines from `first_line` and putting
+/// its spans in `file` and its expressions in `arena`.
+fn parse<'a>(
+    source: &str,
+    first_line: u32,
+    file: u32,
+    arena: &'a Arena<Expr<'a>>,
+) -> Result<Vec<Statement<'a>>, VentiError> {
+    let lexer = Lexer::new(source)
+        .with_first_line(first_line)
+        .with_file(file);
+    Parser::new(lexer, arena).parse()
+}
+
+/// Turns a final expression or call into a statement that prints its value, when the
+/// value can be printed. `span` is the span of the statement, and a callee made for a call
+/// goes in `arena`.
+fn print_result<'a>(
+    analyzer: &mut Analyzer,
+    statement: Statement<'a>,
+    span: Span,
+    arena: &'a Arena<Expr<'a>>,
+) -> Result<Statement<'a>, VentiError> {
+    let value = match statement {
+        Statement::Expression(expr) => expr,
+        Statement::FunctionCall { identifier, args } => {
+            let callee = Expr::new(ExprKind::Identifier(identifier), span);
+            Expr::new(
+                ExprKind::Call {
+                    callee: arena.alloc(callee),
+                    args,
+                },
+                span,
+            )
+        }
+        statement => return Ok(statement),
+    };
+    if analyzer.expression_type(&value)?.is_printable() {
+        return Ok(Statement::Print {
+            value,
+            newline: true,
+        });
+    }
+    match value.kind {
+        ExprKind::Call { callee, args } => {
+            if let ExprKind::Identifier(identifier) = &mut callee.kind {
+                let identifier = std::mem::take(identifier);
+                return Ok(Statement::FunctionCall { identifier, args });
+            }
+            Ok(Statement::Expression(Expr::new(
+                ExprKind::Call { callee, args },
+                value.span,
+            )))
+        }
+        kind => Ok(Statement::Expression(Expr::new(kind, value.span))),
+    }
+}
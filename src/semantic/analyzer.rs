@@ -0,0 +1,1801 @@
+use crate::codegen::builtins;
+use crate::codes::ErrorCode;
+use crate::errors::VentiError;
+use crate::semantic::types::{FunctionSignature, Type};
+use crate::symbols::{DefKind, ScopeId, SymbolTable};
+use crate::traits;
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::ast::{
+    BinOp, Expr, ExprKind, MatchArm, Parameter, Pattern, Statement, TraitMethod, UnaryOp, VarType,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Walks the AST before codegen and checks that every expression is well typed.
+///
+/// The analyzer records the names of the program in a `SymbolTable`, with the type of
+/// each variable, and which definition each name it checks refers to. The root scope
+/// holds the functions and top-level variables, which stay visible inside function
+/// bodies; every other scope belongs to a function, lambda or block.
+#[derive(Clone)]
+pub struct Analyzer {
+    symbols: SymbolTable<Type>,
+    // The scope of the statement being checked
+    scope: ScopeId,
+    functions: HashMap<String, FunctionSignature>,
+    // The methods of each trait, with their parameters after `self`, in declaration order
+    traits: HashMap<String, Vec<(String, Vec<Type>)>>,
+    // The trait and type of each implementation
+    implementations: HashSet<(String, String)>,
+    // The span of the statement being checked, empty when spans are not recorded
+    span: Span,
+    // The type the function being checked returns
+    returns: Type,
+}
+
+impl Analyzer {
+    /// Creates a new `Analyzer` with an empty global scope.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Analyzer`.
+    pub fn new() -> Self {
+        Analyzer {
+            symbols: SymbolTable::new(),
+            scope: ScopeId::ROOT,
+            functions: HashMap::new(),
+            traits: HashMap::new(),
+            implementations: HashSet::new(),
+            span: Span::default(),
+            returns: Type::Int,
+        }
+    }
+
+    /// The names the analyzer has seen defined and used so far.
+    pub fn symbols(&self) -> &SymbolTable<Type> {
+        &self.symbols
+    }
+
+    /// Consumes the analyzer, keeping the names it has seen defined and used.
+    pub fn into_symbols(self) -> SymbolTable<Type> {
+        self.symbols
+    }
+
+    /// Checks a whole program.
+    ///
+    /// Top-level functions are registered first, so they can be called before their
+    /// definition just like in codegen. Defining a function or top-level variable twice is
+    /// an error that names the lines of both definitions.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The statements of the program, as produced by the parser.
+    ///
+    /// # Returns
+    ///
+    /// An empty `Result`, or a `VentiError::TypeError` describing the first problem found.
+    pub fn analyze(&mut self, statements: &[Statement]) -> Result<(), VentiError> {
+        for statement in statements {
+            match statement {
+                Statement::Function {
+                    identifier,
+                    params,
+                    returns,
+                    ..
+                } => self.declare_function(
+                    identifier,
+                    params,
+                    Type::from_annotation(returns.as_ref()),
+                )?,
+                Statement::AsyncFunction {
+                    identifier, params, ..
+                } => self.declare_function(identifier, params, Type::Task)?,
+                Statement::Extern {
+                    identifier,
+                    params,
+                    returns,
+                } => self.declare_extern(identifier, params, returns.as_ref())?,
+                Statement::Trait {
+                    identifier,
+                    methods,
+                } => self.declare_trait(identifier, methods)?,
+                Statement::Impl {
+                    trait_name,
+                    target,
+                    methods,
+                } => self.declare_impl(trait_name, target, methods)?,
+                Statement::Line(span) => self.span = *span,
+                _ => {}
+            }
+        }
+        self.span = Span::default();
+        for statement in statements {
+            // An error propagates as soon as it is found, so the span is the statement at
+            // fault when the error has no span of its own
+            self.check_statement(statement)
+                .map_err(|error| error.at(self.span))?;
+        }
+        Ok(())
+    }
+
+    /// Records a function's signature, defined on the current line.
+    fn declare_function(
+        &mut self,
+        identifier: &str,
+        params: &[Parameter],
+        returns: Type,
+    ) -> Result<(), VentiError> {
+        self.record_definition("Function", identifier)?;
+        let signature = FunctionSignature {
+            params: params
+                .iter()
+                .map(|param| Type::from_annotation(param.var_type.as_ref()))
+                .collect(),
+            returns,
+        };
+        self.define_function(identifier, signature);
+        Ok(())
+    }
+
+    /// Records a C function's signature, defined on the current line.
+    ///
+    /// Several files may declare the same C function, as long as they agree on its
+    /// signature.
+    fn declare_extern(
+        &mut self,
+        identifier: &str,
+        params: &[Parameter],
+        returns: Option<&VarType>,
+    ) -> Result<(), VentiError> {
+        let signature = FunctionSignature {
+            params: params
+                .iter()
+                .map(|param| Type::from_annotation(param.var_type.as_ref()))
+                .collect(),
+            returns: Type::from_annotation(returns),
+        };
+        if self.functions.get(identifier) == Some(&signature) {
+            return Ok(());
+        }
+        self.record_definition("Function", identifier)?;
+        self.define_function(identifier, signature);
+        Ok(())
+    }
+
+    /// Records the methods of a trait.
+    fn declare_trait(
+        &mut self,
+        identifier: &str,
+        methods: &[TraitMethod],
+    ) -> Result<(), VentiError> {
+        if self.traits.contains_key(identifier) {
+            return Err(VentiError::TypeError(
+                format!("Trait '{}' is defined more than once", identifier),
+                None,
+            )
+            .with_code(ErrorCode::DuplicateDefinition));
+        }
+        let methods = methods
+            .iter()
+            .map(|method| {
+                let params = method
+                    .params
+                    .iter()
+                    .map(|param| Type::from_annotation(param.var_type.as_ref()))
+                    .collect();
+                (method.name.clone(), params)
+            })
+            .collect();
+        self.traits.insert(identifier.to_string(), methods);
+        Ok(())
+    }
+
+    /// Records an implementation of a trait and declares its methods as functions named
+    /// by `traits::method_function`. Whether they match the trait is checked with the
+    /// implementation, once every trait is known.
+    fn declare_impl(
+        &mut self,
+        trait_name: &str,
+        target: &VarType,
+        methods: &[Statement],
+    ) -> Result<(), VentiError> {
+        let implementation = (trait_name.to_string(), target.name().to_string());
+        if !self.implementations.insert(implementation) {
+            return Err(VentiError::TypeError(
+                format!(
+                    "Trait '{}' is implemented for {} more than once",
+                    trait_name,
+                    target.name()
+                ),
+                None,
+            )
+            .with_code(ErrorCode::DuplicateDefinition));
+        }
+        for method in methods {
+            match method {
+                Statement::Function {
+                    identifier, params, ..
+                } => {
+                    let function = traits::method_function(trait_name, target.name(), identifier);
+                    self.declare_function(&function, params, Type::Int)?;
+                }
+                Statement::Line(span) => self.span = *span,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the signature of a function the host program provides, which the program
+    /// calls like its own.
+    ///
+    /// # Returns
+    ///
+    /// An empty `Result`, or a `VentiError::TypeError` if `name` is already defined.
+    pub fn declare_host_function(
+        &mut self,
+        name: &str,
+        signature: FunctionSignature,
+    ) -> Result<(), VentiError> {
+        self.record_definition("Function", name)?;
+        self.define_function(name, signature);
+        Ok(())
+    }
+
+    /// Defines a function in the root scope, wherever it is written, since every function
+    /// can call it.
+    fn define_function(&mut self, name: &str, signature: FunctionSignature) {
+        let returns = signature.returns.clone();
+        self.symbols
+            .define(ScopeId::ROOT, name, DefKind::Function, self.span, returns);
+        self.functions.insert(name.to_string(), signature);
+    }
+
+    /// Checks that `name` is not defined at the top level yet, since it is about to be
+    /// defined on the current line, which the error points at.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - What `name` is, to describe it in the error.
+    /// * `name` - The function or top-level variable being defined.
+    fn record_definition(&mut self, kind: &str, name: &str) -> Result<(), VentiError> {
+        let Some(first) = self.symbols.lookup_in(ScopeId::ROOT, name) else {
+            return Ok(());
+        };
+        let first = self.symbols.definition(first).span.line;
+        if first == 0 || self.span.line == 0 {
+            return Err(VentiError::TypeError(
+                format!("{} '{}' is defined more than once", kind, name),
+                None,
+            )
+            .with_code(ErrorCode::DuplicateDefinition));
+        }
+        Err(VentiError::TypeError(
+            format!(
+                "{} '{}' is defined on line {} and again on line {}",
+                kind, name, first, self.span.line
+            ),
+            Some(self.span),
+        )
+        .with_code(ErrorCode::DuplicateDefinition))
+    }
+
+    fn check_statement(&mut self, statement: &Statement) -> Result<(), VentiError> {
+        match statement {
+            Statement::VariableDeclaration { identifier, value } => {
+                let value_type = self.check_expr(value)?;
+                if self.scope == ScopeId::ROOT {
+                    self.record_definition("Variable", identifier)?;
+                }
+                self.define(identifier, DefKind::Variable, value_type);
+                Ok(())
+            }
+            Statement::VariableAssignment { identifier, value } => {
+                let expected = self.lookup(identifier, self.span).ok_or_else(|| {
+                    VentiError::TypeError(format!("Undefined variable '{}'", identifier), None)
+                        .with_code(ErrorCode::UndefinedVariable)
+                })?;
+                let found = self.check_expr(value)?;
+                if !expected.accepts(&found) {
+                    return Err(VentiError::TypeError(
+                        format!(
+                            "Cannot assign a value of type {} to '{}' of type {}",
+                            found, identifier, expected
+                        ),
+                        None,
+                    ));
+                }
+                Ok(())
+            }
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => {
+                let target_type = self.check_expr(target)?;
+                match target_type {
+                    Type::Buffer => return self.check_slot("Buffer", index, value),
+                    Type::Vector => return self.check_slot("Vector", index, value),
+                    _ => {}
+                }
+                if !Type::Map.accepts(&target_type) {
+                    return Err(VentiError::TypeError(
+                        format!(
+                            "Only map entries, buffer slots and vector elements can be assigned, found {}",
+                            target_type
+                        ),
+                        None,
+                    ));
+                }
+                self.check_map_entry(index, value)
+            }
+            Statement::FunctionCall { identifier, args } => {
+                self.check_function_call(identifier, args, self.span)?;
+                Ok(())
+            }
+            Statement::Print { value, .. } => {
+                let value_type = self.check_expr(value)?;
+                if !value_type.is_printable() {
+                    return Err(VentiError::TypeError(
+                        format!("A value of type {} cannot be printed", value_type),
+                        None,
+                    ));
+                }
+                Ok(())
+            }
+            Statement::Expression(expr) => {
+                self.check_expr(expr)?;
+                Ok(())
+            }
+            Statement::Function {
+                identifier,
+                params,
+                returns,
+                body,
+                ..
+            } => {
+                let returns = Type::from_annotation(returns.as_ref());
+                if !matches!(returns, Type::Int | Type::Vector) {
+                    return Err(VentiError::TypeError(
+                        format!(
+                            "Function '{}' cannot return {}; functions return integers and vectors",
+                            identifier, returns
+                        ),
+                        None,
+                    ));
+                }
+                self.check_function(identifier, params, body, returns)
+            }
+            Statement::AsyncFunction {
+                identifier,
+                params,
+                body,
+                ..
+            } => self.check_function(identifier, params, body, Type::Task),
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    let value_type = self.check_expr(value)?;
+                    if self.returns == Type::Vector {
+                        if !Type::Vector.accepts(&value_type) {
+                            return Err(VentiError::TypeError(
+                                format!(
+                                    "A function returning vec must return a vector, found {}",
+                                    value_type
+                                ),
+                                None,
+                            ));
+                        }
+                    } else if !value_type.is_integral() {
+                        return Err(VentiError::TypeError(
+                            format!("Functions can only return integers, found {}", value_type),
+                            None,
+                        ));
+                    }
+                }
+                Ok(())
+            }
+            Statement::Match { scrutinee, arms } => self.check_match(scrutinee, arms),
+            Statement::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                let element = match self.check_expr(iterable)? {
+                    Type::Int | Type::Range | Type::Buffer | Type::Vector | Type::Iterator => {
+                        Type::Int
+                    }
+                    Type::Array(element, _) => *element,
+                    Type::StringVector => Type::String,
+                    Type::Unknown => Type::Unknown,
+                    found => {
+                        return Err(VentiError::TypeError(format!(
+                            "for_venti can only iterate over ranges, integers, arrays, buffers, vectors and iterators, found {}",
+                            found
+                        ), None))
+                    }
+                };
+                self.check_block(body, vec![(variable.clone(), DefKind::Variable, element)])
+            }
+            Statement::Test { name, body } => {
+                if self.scope != ScopeId::ROOT {
+                    return Err(VentiError::SyntaxError(
+                        format!("Test '{}' must be defined at the top level", name),
+                        None,
+                    ));
+                }
+                // A test body is checked like the body of a function without parameters
+                self.check_function(name, &[], body, Type::Int)
+            }
+            Statement::Try {
+                body,
+                error,
+                handler,
+            } => {
+                self.check_block(body, Vec::new())?;
+                self.check_block(
+                    handler,
+                    vec![(error.clone(), DefKind::Variable, Type::String)],
+                )
+            }
+            Statement::Assert(condition) => {
+                let condition_type = self.check_expr(condition)?;
+                if !condition_type.is_integral() {
+                    return Err(VentiError::TypeError(
+                        format!(
+                            "assert_venti needs an int or bool condition, found {}",
+                            condition_type
+                        ),
+                        None,
+                    ));
+                }
+                Ok(())
+            }
+            Statement::Comment { .. } => Ok(()),
+            Statement::Extern { .. } if self.scope != ScopeId::ROOT => {
+                Err(VentiError::SyntaxError(
+                    "extern_venti can only be used at the top level of a file".to_string(),
+                    None,
+                ))
+            }
+            Statement::Extern {
+                identifier,
+                params,
+                returns,
+            } => {
+                if let Some(param) = params
+                    .iter()
+                    .find(|param| matches!(param.var_type, Some(VarType::Trait(_))))
+                {
+                    return Err(VentiError::TypeError(
+                        format!(
+                            "Parameter '{}' of extern function '{}' cannot take a trait",
+                            param.name, identifier
+                        ),
+                        None,
+                    ));
+                }
+                let takes_vector = params
+                    .iter()
+                    .any(|param| param.var_type == Some(VarType::Vec));
+                if takes_vector || *returns == Some(VarType::Vec) {
+                    return Err(VentiError::TypeError(
+                        format!(
+                            "Extern function '{}' cannot take or return a vector",
+                            identifier
+                        ),
+                        None,
+                    ));
+                }
+                Ok(())
+            }
+            Statement::Trait { identifier, .. }
+            | Statement::Impl {
+                trait_name: identifier,
+                ..
+            } if self.scope != ScopeId::ROOT => Err(VentiError::SyntaxError(
+                format!(
+                    "Trait '{}' can only be declared and implemented at the top level of a file",
+                    identifier
+                ),
+                None,
+            )),
+            Statement::Trait { methods, .. } => methods
+                .iter()
+                .try_for_each(|method| self.check_annotations(&method.params)),
+            Statement::Impl {
+                trait_name,
+                target,
+                methods,
+            } => self.check_impl(trait_name, target, methods),
+            // The resolver replaces the imports at the top level before the program is checked
+            Statement::Import(_) => Err(VentiError::SyntaxError(
+                "import_venti can only be used at the top level of a file".to_string(),
+                None,
+            )),
+            Statement::Line(span) => {
+                self.span = *span;
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks a function body in a scope that sees only the top-level variables.
+    ///
+    /// Top-level functions are declared before checking starts; functions defined inside
+    /// another are declared here.
+    fn check_function(
+        &mut self,
+        identifier: &str,
+        params: &[Parameter],
+        body: &[Statement],
+        returns: Type,
+    ) -> Result<(), VentiError> {
+        if self.scope != ScopeId::ROOT {
+            self.declare_function(identifier, params, returns.clone())?;
+        }
+        self.check_annotations(params)?;
+        let enclosing = std::mem::replace(&mut self.scope, ScopeId::ROOT);
+        let enclosing_returns = std::mem::replace(&mut self.returns, returns);
+        let bindings = params
+            .iter()
+            .map(|param| {
+                (
+                    param.name.clone(),
+                    DefKind::Parameter,
+                    Type::from_annotation(param.var_type.as_ref()),
+                )
+            })
+            .collect();
+        let result = self.check_block(body, bindings);
+        self.scope = enclosing;
+        self.returns = enclosing_returns;
+        result
+    }
+
+    /// Checks that every trait the parameters are annotated with is defined.
+    fn check_annotations(&self, params: &[Parameter]) -> Result<(), VentiError> {
+        for param in params {
+            if let Some(VarType::Trait(name)) = &param.var_type {
+                if !self.traits.contains_key(name) {
+                    return Err(VentiError::TypeError(
+                        format!("Undefined trait '{}' for parameter '{}'", name, param.name),
+                        None,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that an implementation provides exactly the methods of its trait, with the
+    /// same parameters, and checks the body of each.
+    fn check_impl(
+        &mut self,
+        trait_name: &str,
+        target: &VarType,
+        methods: &[Statement],
+    ) -> Result<(), VentiError> {
+        let required = self.traits.get(trait_name).cloned().ok_or_else(|| {
+            VentiError::TypeError(format!("Undefined trait '{}'", trait_name), None)
+        })?;
+        let mut provided = Vec::new();
+        for method in methods {
+            let Statement::Function {
+                identifier,
+                params,
+                returns,
+                body,
+                ..
+            } = method
+            else {
+                self.check_statement(method)?;
+                continue;
+            };
+            let returns = Type::from_annotation(returns.as_ref());
+            if returns != Type::Int {
+                return Err(VentiError::TypeError(
+                    format!(
+                        "Method '{}' of trait '{}' returns an integer, but is implemented returning {}",
+                        identifier, trait_name, returns
+                    ),
+                    None,
+                ));
+            }
+            let Some((_, expected)) = required.iter().find(|(name, _)| name == identifier) else {
+                return Err(VentiError::TypeError(
+                    format!(
+                        "Method '{}' is not a member of trait '{}'",
+                        identifier, trait_name
+                    ),
+                    None,
+                ));
+            };
+            let found: Vec<Type> = params[1..]
+                .iter()
+                .map(|param| Type::from_annotation(param.var_type.as_ref()))
+                .collect();
+            if &found != expected {
+                let list = |types: &[Type]| {
+                    types
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                return Err(VentiError::TypeError(
+                    format!(
+                        "Method '{}' of trait '{}' takes ({}) after self, but is implemented taking ({})",
+                        identifier,
+                        trait_name,
+                        list(expected),
+                        list(&found)
+                    ),
+                    None,
+                ));
+            }
+            let function = traits::method_function(trait_name, target.name(), identifier);
+            self.check_function(&function, params, body, Type::Int)?;
+            provided.push(identifier);
+        }
+        if let Some((missing, _)) = required.iter().find(|(name, _)| !provided.contains(&name)) {
+            return Err(VentiError::TypeError(
+                format!(
+                    "The implementation of trait '{}' for {} is missing method '{}'",
+                    trait_name,
+                    target.name(),
+                    missing
+                ),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns true if a value of type `found` can be passed where `expected` is: as
+    /// `Type::accepts` does, and a value of a type implementing a trait where the trait is.
+    fn accepts(&self, expected: &Type, found: &Type) -> bool {
+        match expected {
+            Type::Trait(name) if found != expected && *found != Type::Unknown => self
+                .implementations
+                .contains(&(name.clone(), found.to_string())),
+            _ => expected.accepts(found),
+        }
+    }
+
+    /// Finds the trait whose method `name` a call on a value of a concrete type calls.
+    ///
+    /// # Returns
+    ///
+    /// The parameters of the method after `self`, `None` if no trait the type implements
+    /// has the method, or a `VentiError::TypeError` if several do.
+    fn trait_method(&self, receiver: &Type, name: &str) -> Result<Option<Vec<Type>>, VentiError> {
+        let mut found: Vec<(&String, &Vec<Type>)> = self
+            .traits
+            .iter()
+            .filter(|(trait_name, _)| {
+                self.implementations
+                    .contains(&(trait_name.to_string(), receiver.to_string()))
+            })
+            .filter_map(|(trait_name, methods)| {
+                let (_, params) = methods.iter().find(|(method, _)| method == name)?;
+                Some((trait_name, params))
+            })
+            .collect();
+        match found.len() {
+            0 => Ok(None),
+            1 => Ok(Some(found.remove(0).1.clone())),
+            _ => {
+                let mut names: Vec<&str> = found
+                    .iter()
+                    .map(|(trait_name, _)| trait_name.as_str())
+                    .collect();
+                names.sort_unstable();
+                Err(VentiError::TypeError(
+                    format!(
+                        "Method '{}' of {} is ambiguous: traits {} all have it",
+                        name,
+                        receiver,
+                        names.join(", ")
+                    ),
+                    None,
+                ))
+            }
+        }
+    }
+
+    /// Checks `body` in a new scope inside the current one that starts out with
+    /// `bindings`.
+    fn check_block(
+        &mut self,
+        body: &[Statement],
+        bindings: Vec<(String, DefKind, Type)>,
+    ) -> Result<(), VentiError> {
+        let enclosing = self.scope;
+        self.scope = self.symbols.push_scope(enclosing);
+        for (name, kind, value_type) in bindings {
+            self.define(&name, kind, value_type);
+        }
+        let result = body
+            .iter()
+            .try_for_each(|statement| self.check_statement(statement));
+        self.scope = enclosing;
+        result
+    }
+
+    fn check_match(&mut self, scrutinee: &Expr, arms: &[MatchArm]) -> Result<(), VentiError> {
+        let scrutinee_type = self.check_expr(scrutinee)?;
+        for arm in arms {
+            match (&arm.pattern, &scrutinee_type) {
+                (Pattern::Wildcard, _)
+                | (_, Type::Unknown)
+                | (Pattern::Number(_), Type::Int | Type::Bool)
+                | (Pattern::String(_), Type::String | Type::Nothing) => {}
+                (Pattern::Number(n), found) => {
+                    return Err(VentiError::TypeError(
+                        format!(
+                            "Cannot match a value of type {} against the integer pattern {}",
+                            found, n
+                        ),
+                        None,
+                    ))
+                }
+                (Pattern::String(s), found) => {
+                    return Err(VentiError::TypeError(
+                        format!(
+                            "Cannot match a value of type {} against the string pattern \"{}\"",
+                            found, s
+                        ),
+                        None,
+                    ))
+                }
+            }
+            self.check_block(&arm.body, Vec::new())?;
+        }
+        Ok(())
+    }
+
+    /// Checks a map key and value, which must be a string and an integer.
+    fn check_map_entry(&mut self, key: &Expr, value: &Expr) -> Result<(), VentiError> {
+        let key_type = self.check_expr(key)?;
+        if !Type::String.accepts(&key_type) {
+            return Err(VentiError::TypeError(
+                format!("Map keys must be strings, found {}", key_type),
+                None,
+            ));
+        }
+        let value_type = self.check_expr(value)?;
+        if !value_type.is_integral() {
+            return Err(VentiError::TypeError(
+                format!("Map values must be integers, found {}", value_type),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the index and value of an assignment to a buffer slot or vector element.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - `Buffer` or `Vector`, to describe the target in errors.
+    fn check_slot(&mut self, kind: &str, index: &Expr, value: &Expr) -> Result<(), VentiError> {
+        let index_type = self.check_expr(index)?;
+        if !index_type.is_integral() {
+            return Err(VentiError::TypeError(
+                format!("{} index must be an integer, found {}", kind, index_type),
+                None,
+            ));
+        }
+        let value_type = self.check_expr(value)?;
+        if !value_type.is_integral() {
+            return Err(VentiError::TypeError(
+                format!("{} values must be integers, found {}", kind, value_type),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks a call to a named function against its signature, recording that `span`
+    /// uses the function.
+    fn check_function_call(
+        &mut self,
+        identifier: &str,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<Type, VentiError> {
+        if let Some(function) = self.symbols.lookup_in(ScopeId::ROOT, identifier) {
+            self.symbols.record_use(span, function);
+        }
+        // `len` takes any of the types that carry a length, so it has no single signature
+        if identifier == "len" && !self.functions.contains_key(identifier) {
+            return self.check_len(args);
+        }
+        // `json_stringify` takes any of the types JSON can hold
+        if identifier == "json_stringify" && !self.functions.contains_key(identifier) {
+            return self.check_json_stringify(args);
+        }
+        // The iterator and sorting builtins take their values from several types
+        if (builtins::is_iterator(identifier) || builtins::is_sorting(identifier))
+            && !self.functions.contains_key(identifier)
+        {
+            return self.check_iterator_call(identifier, args);
+        }
+        // The math builtins take integers and floats alike, so they have no single signature
+        if builtins::is_math(identifier) && !self.functions.contains_key(identifier) {
+            return self.check_math(identifier, args);
+        }
+        // A channel's values are of the type the first `send` on it gives them
+        if matches!(identifier, "send" | "recv") && !self.functions.contains_key(identifier) {
+            return self.check_channel_call(identifier, args);
+        }
+        let signature = self
+            .functions
+            .get(identifier)
+            .cloned()
+            .or_else(|| builtin_signature(identifier))
+            .ok_or_else(|| {
+                VentiError::TypeError(format!("Undefined function '{}'", identifier), None)
+                    .with_code(ErrorCode::UndefinedFunction)
+            })?;
+        if signature.params.len() != args.len() {
+            return Err(VentiError::TypeError(
+                format!(
+                    "Function '{}' expects {} arguments but {} were given",
+                    identifier,
+                    signature.params.len(),
+                    args.len()
+                ),
+                None,
+            )
+            .with_code(ErrorCode::ArgumentCount));
+        }
+        for (position, (expected, arg)) in signature.params.iter().zip(args).enumerate() {
+            let found = self.check_expr(arg)?;
+            if !self.accepts(expected, &found) {
+                return Err(VentiError::TypeError(
+                    format!(
+                        "Argument {} of '{}' expects {} but found {}",
+                        position + 1,
+                        identifier,
+                        expected,
+                        found
+                    ),
+                    None,
+                ));
+            }
+        }
+        Ok(signature.returns)
+    }
+
+    /// Checks a call to one of the math builtins, which accept integers and floats.
+    fn check_math(&mut self, identifier: &str, args: &[Expr]) -> Result<Type, VentiError> {
+        let arity = builtins::arity(identifier).unwrap_or_default();
+        if args.len() != arity {
+            return Err(VentiError::TypeError(
+                format!(
+                    "Function '{}' expects {} arguments but {} were given",
+                    identifier,
+                    arity,
+                    args.len()
+                ),
+                None,
+            )
+            .with_code(ErrorCode::ArgumentCount));
+        }
+        let args = args
+            .iter()
+            .map(|arg| self.check_expr(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        math_result(identifier, &args)
+    }
+
+    /// Checks a call to the `len` builtin, which accepts strings and buffers.
+    fn check_len(&mut self, args: &[Expr]) -> Result<Type, VentiError> {
+        let [arg] = args else {
+            return Err(VentiError::TypeError(
+                format!(
+                    "Function 'len' expects 1 arguments but {} were given",
+                    args.len()
+                ),
+                None,
+            )
+            .with_code(ErrorCode::ArgumentCount));
+        };
+        let found = self.check_expr(arg)?;
+        if Type::String.accepts(&found)
+            || matches!(
+                found,
+                Type::Array(..)
+                    | Type::Map
+                    | Type::Buffer
+                    | Type::Vector
+                    | Type::StringVector
+                    | Type::Json
+            )
+        {
+            Ok(Type::Int)
+        } else {
+            Err(VentiError::TypeError(
+                format!(
+                    "Argument 1 of 'len' expects an array, map, string, buffer, vector or JSON value but found {}",
+                    found
+                ),
+                None,
+            ))
+        }
+    }
+
+    /// Checks a call to the `json_stringify` builtin, which accepts the types
+    /// `Type::is_json` allows.
+    fn check_json_stringify(&mut self, args: &[Expr]) -> Result<Type, VentiError> {
+        let [arg] = args else {
+            return Err(VentiError::TypeError(
+                format!(
+                    "Function 'json_stringify' expects 1 arguments but {} were given",
+                    args.len()
+                ),
+                None,
+            )
+            .with_code(ErrorCode::ArgumentCount));
+        };
+        let found = self.check_expr(arg)?;
+        if found.is_json() {
+            Ok(Type::String)
+        } else {
+            Err(VentiError::TypeError(
+                format!(
+                    "Argument 1 of 'json_stringify' expects an int, float, bool, string, map, JSON value or array of them but found {}",
+                    found
+                ),
+                None,
+            ))
+        }
+    }
+
+    /// Checks a call to `send` or `recv`.
+    ///
+    /// A channel carries values of one type: an int, float or bool. The first `send` on a
+    /// channel variable decides which, and a `send` of any other type is an error. A
+    /// channel received from, or used any other way, before anything is sent on it carries
+    /// integers, and only a channel of integers can be passed as anything but a variable.
+    fn check_channel_call(&mut self, identifier: &str, args: &[Expr]) -> Result<Type, VentiError> {
+        let arity = if identifier == "send" { 2 } else { 1 };
+        if args.len() != arity {
+            return Err(VentiError::TypeError(
+                format!(
+                    "Function '{}' expects {} arguments but {} were given",
+                    identifier,
+                    arity,
+                    args.len()
+                ),
+                None,
+            )
+            .with_code(ErrorCode::ArgumentCount));
+        }
+        let channel = &args[0];
+        // A variable is looked up rather than checked, which would settle what it carries
+        let found = match &channel.kind {
+            ExprKind::Identifier(name) => self.lookup(name, channel.span),
+            _ => None,
+        };
+        let found = match found {
+            Some(found) => found,
+            None => self.check_expr(channel)?,
+        };
+        let is_variable = matches!(channel.kind, ExprKind::Identifier(_));
+        let element = match found {
+            Type::Channel(element) if !is_variable && !Type::Int.accepts(&element) => {
+                return Err(VentiError::TypeError(
+                    format!(
+                        "Argument 1 of '{}' must name the channel of {} as a variable",
+                        identifier, element
+                    ),
+                    None,
+                ))
+            }
+            Type::Channel(element) if is_variable => *element,
+            Type::Channel(_) | Type::Unknown => Type::Int,
+            found => {
+                return Err(VentiError::TypeError(
+                    format!(
+                        "Argument 1 of '{}' expects a channel but found {}",
+                        identifier, found
+                    ),
+                    None,
+                ))
+            }
+        };
+        if identifier == "recv" {
+            if element == Type::Unknown {
+                self.settle_channel(channel.span, Type::Int);
+                return Ok(Type::Int);
+            }
+            return Ok(element);
+        }
+        let value = self.check_expr(&args[1])?;
+        if !matches!(value, Type::Int | Type::Float | Type::Bool | Type::Unknown) {
+            return Err(VentiError::TypeError(
+                format!(
+                    "Argument 2 of 'send' expects an int, float or bool but found {}",
+                    value
+                ),
+                None,
+            ));
+        }
+        match element {
+            Type::Unknown if value != Type::Unknown => {
+                self.settle_channel(channel.span, value);
+            }
+            element if !element.accepts(&value) => {
+                return Err(VentiError::TypeError(
+                    format!(
+                        "Argument 2 of 'send' expects {}, which the channel carries, but found {}",
+                        element, value
+                    ),
+                    None,
+                ))
+            }
+            _ => {}
+        }
+        Ok(Type::Int)
+    }
+
+    /// Fixes the type of the values the channel variable used at `span` carries.
+    ///
+    /// # Returns
+    ///
+    /// The type of the channel.
+    fn settle_channel(&mut self, span: Span, element: Type) -> Type {
+        let channel = Type::Channel(Box::new(element));
+        if let Some(id) = self.symbols.resolve(span) {
+            self.symbols.definition_mut(id).info = channel.clone();
+        }
+        channel
+    }
+
+    /// Checks a call to one of the iterator builtins, whose first argument may be any of the
+    /// types `Type::is_iterable` allows, or one of the sorting builtins, whose first argument
+    /// is a vector or buffer. The lambdas of either must take integers.
+    fn check_iterator_call(&mut self, identifier: &str, args: &[Expr]) -> Result<Type, VentiError> {
+        let signature = builtin_signature(identifier).ok_or_else(|| {
+            VentiError::TypeError(format!("Undefined function '{}'", identifier), None)
+                .with_code(ErrorCode::UndefinedFunction)
+        })?;
+        if signature.params.len() != args.len() {
+            return Err(VentiError::TypeError(
+                format!(
+                    "Function '{}' expects {} arguments but {} were given",
+                    identifier,
+                    signature.params.len(),
+                    args.len()
+                ),
+                None,
+            )
+            .with_code(ErrorCode::ArgumentCount));
+        }
+        for (position, (expected, arg)) in signature.params.iter().zip(args).enumerate() {
+            let found = self.check_expr(arg)?;
+            let sorting = builtins::is_sorting(identifier);
+            if position == 0
+                && sorting
+                && !matches!(found, Type::Vector | Type::Buffer | Type::Unknown)
+            {
+                return Err(VentiError::TypeError(
+                    format!(
+                        "Argument 1 of '{}' expects a vector or buffer but found {}",
+                        identifier, found
+                    ),
+                    None,
+                ));
+            }
+            if position == 0 && !sorting && !found.is_iterable() {
+                return Err(VentiError::TypeError(
+                    format!(
+                        "Argument 1 of '{}' expects a range, buffer, iterator or array of ints but found {}",
+                        identifier, found
+                    ),
+                    None,
+                ));
+            }
+            if !self.accepts(expected, &found) {
+                return Err(VentiError::TypeError(
+                    format!(
+                        "Argument {} of '{}' expects {} but found {}",
+                        position + 1,
+                        identifier,
+                        expected,
+                        found
+                    ),
+                    None,
+                ));
+            }
+            // The values are passed to the lambda as integers
+            if let ExprKind::Lambda { params, .. } = &arg.kind {
+                for param in params {
+                    let param_type = Type::from_annotation(param.var_type.as_ref());
+                    if param_type != Type::Int {
+                        return Err(VentiError::TypeError(
+                            format!(
+                                "The lambda passed to '{}' must take ints, but its parameter '{}' is a {}",
+                                identifier, param.name, param_type
+                            ),
+                            None,
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(signature.returns)
+    }
+
+    /// Infers the type of an expression in the top-level scope, as if it were a statement
+    /// of the program after those checked so far.
+    ///
+    /// # Returns
+    ///
+    /// The type of `expr`, or a `VentiError::TypeError` describing the first problem found.
+    pub fn expression_type(&mut self, expr: &Expr) -> Result<Type, VentiError> {
+        self.check_expr(expr)
+    }
+
+    /// Infers the type of an expression, checking its operands along the way.
+    ///
+    /// # Returns
+    ///
+    /// The type of `expr`, or a `VentiError::TypeError` naming the variable or operator at fault.
+    fn check_expr(&mut self, expr: &Expr) -> Result<Type, VentiError> {
+        self.check_expr_kind(&expr.kind, expr.span)
+            .map_err(|error| error.at(expr.span))
+    }
+
+    fn check_expr_kind(&mut self, kind: &ExprKind, span: Span) -> Result<Type, VentiError> {
+        match kind {
+            ExprKind::Number(_) => Ok(Type::Int),
+            ExprKind::Float(_) => Ok(Type::Float),
+            ExprKind::String(_) => Ok(Type::String),
+            ExprKind::Boolean(_) => Ok(Type::Bool),
+            ExprKind::Null => Ok(Type::Nothing),
+            ExprKind::Identifier(id) => match self.lookup(id, span) {
+                // Copies of a channel must agree on what it carries, so using one as a value
+                // before anything is sent on it makes it a channel of integers
+                Some(Type::Channel(element)) if *element == Type::Unknown => {
+                    Ok(self.settle_channel(span, Type::Int))
+                }
+                Some(found) => Ok(found),
+                None => builtins::constant(id).map(|_| Type::Float).ok_or_else(|| {
+                    VentiError::TypeError(format!("Undefined variable '{}'", id), None)
+                        .with_code(ErrorCode::UndefinedVariable)
+                }),
+            },
+            ExprKind::BinaryOp(left, op, right) => {
+                let left = self.check_expr(left)?;
+                let right = self.check_expr(right)?;
+                binary_result(&left, *op, &right)
+            }
+            ExprKind::UnaryOp(op, operand) => {
+                let operand = self.check_expr(operand)?;
+                match (op, &operand) {
+                    (UnaryOp::Negate, operand) if operand.is_numeric() => Ok(operand.clone()),
+                    (UnaryOp::Not, operand) if Type::Bool.accepts(operand) => Ok(Type::Bool),
+                    (UnaryOp::Negate, operand) => Err(VentiError::TypeError(
+                        format!("Operator '-' cannot be applied to {}", operand),
+                        None,
+                    )),
+                    (UnaryOp::Not, operand) => Err(VentiError::TypeError(
+                        format!("Operator '!' cannot be applied to {}", operand),
+                        None,
+                    )),
+                }
+            }
+            ExprKind::Array(elements) => {
+                let mut element_type = Type::Int;
+                for (position, element) in elements.iter().enumerate() {
+                    let found = self.check_expr(element)?;
+                    if position == 0 {
+                        element_type = found;
+                    } else if !element_type.accepts(&found) {
+                        return Err(VentiError::TypeError(
+                            format!(
+                                "Array elements must share one type, found {} and {}",
+                                element_type, found
+                            ),
+                            None,
+                        ));
+                    }
+                }
+                Ok(Type::Array(Box::new(element_type), elements.len()))
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.check_map_entry(key, value)?;
+                }
+                Ok(Type::Map)
+            }
+            ExprKind::Range { start, end, .. } => {
+                for bound in [start, end] {
+                    let found = self.check_expr(bound)?;
+                    if !Type::Int.accepts(&found) {
+                        return Err(VentiError::TypeError(
+                            format!("Range bounds must be integers, found {}", found),
+                            None,
+                        ));
+                    }
+                }
+                Ok(Type::Range)
+            }
+            ExprKind::Index { target, index } => {
+                let target = self.check_expr(target)?;
+                let index = self.check_expr(index)?;
+                match target {
+                    Type::Map if Type::String.accepts(&index) => Ok(Type::Int),
+                    Type::Map => Err(VentiError::TypeError(
+                        format!("Map keys must be strings, found {}", index),
+                        None,
+                    )),
+                    Type::Buffer if index.is_integral() => Ok(Type::Int),
+                    Type::Buffer => Err(VentiError::TypeError(
+                        format!("Buffer index must be an integer, found {}", index),
+                        None,
+                    )),
+                    Type::Vector if index.is_integral() => Ok(Type::Int),
+                    Type::Vector if index == Type::Range => Ok(Type::Vector),
+                    Type::Vector => Err(VentiError::TypeError(
+                        format!("Vector index must be an integer or a range, found {}", index),
+                        None,
+                    )),
+                    Type::StringVector if index.is_integral() => Ok(Type::String),
+                    Type::StringVector => Err(VentiError::TypeError(
+                        format!("Vector index must be an integer, found {}", index),
+                        None,
+                    )),
+                    Type::Array(element, _) if index.is_integral() => Ok(*element),
+                    Type::Array(..) => Err(VentiError::TypeError(
+                        format!("Array index must be an integer, found {}", index),
+                        None,
+                    )),
+                    Type::String if Type::Range.accepts(&index) => Ok(Type::String),
+                    Type::String => Err(VentiError::TypeError(
+                        format!("Strings can only be sliced with a range, found {}", index),
+                        None,
+                    )),
+                    // A member or element that is not there is a JSON null
+                    Type::Json if Type::String.accepts(&index) || index.is_integral() => {
+                        Ok(Type::Json)
+                    }
+                    Type::Json => Err(VentiError::TypeError(
+                        format!(
+                            "JSON values are indexed by a string key or an integer, found {}",
+                            index
+                        ),
+                        None,
+                    )),
+                    Type::Unknown => Ok(Type::Unknown),
+                    target => Err(VentiError::TypeError(
+                        format!(
+                            "Only arrays, maps, buffers, vectors, strings and JSON values can be indexed, found {}",
+                            target
+                        ),
+                        None,
+                    )),
+                }
+            }
+            ExprKind::Call { callee, args } => {
+                if let ExprKind::Identifier(identifier) = &callee.kind {
+                    let is_local = self
+                        .symbols
+                        .lookup(self.scope, identifier)
+                        .is_some_and(|id| self.symbols.definition(id).scope != ScopeId::ROOT);
+                    let is_function =
+                        self.functions.contains_key(identifier) || builtins::is_builtin(identifier);
+                    if !is_local && is_function {
+                        return self.check_function_call(identifier, args, callee.span);
+                    }
+                }
+                let callee = self.check_expr(callee)?;
+                for arg in args {
+                    self.check_expr(arg)?;
+                }
+                match callee {
+                    Type::Lambda(arity) if arity != args.len() => Err(VentiError::TypeError(
+                        format!(
+                            "Lambda expects {} arguments but {} were given",
+                            arity,
+                            args.len()
+                        ),
+                        None,
+                    )
+                    .with_code(ErrorCode::ArgumentCount)),
+                    Type::Lambda(_) | Type::Unknown => Ok(Type::Int),
+                    callee => Err(VentiError::TypeError(
+                        format!("A value of type {} is not callable", callee),
+                        None,
+                    )
+                    .with_code(ErrorCode::NotCallable)),
+                }
+            }
+            ExprKind::Lambda { params, body } => {
+                if let Some(param) = params
+                    .iter()
+                    .find(|param| matches!(param.var_type, Some(VarType::Trait(_))))
+                {
+                    return Err(VentiError::TypeError(
+                        format!("Lambda parameter '{}' cannot take a trait", param.name),
+                        None,
+                    ));
+                }
+                let enclosing = self.scope;
+                self.scope = self.symbols.push_scope(enclosing);
+                for param in params {
+                    let param_type = Type::from_annotation(param.var_type.as_ref());
+                    self.define(&param.name, DefKind::Parameter, param_type);
+                }
+                let body = self.check_expr(body);
+                self.scope = enclosing;
+                let body = body?;
+                if !body.is_integral() {
+                    return Err(VentiError::TypeError(
+                        format!("Lambdas must return an integer value, found {}", body),
+                        None,
+                    ));
+                }
+                Ok(Type::Lambda(params.len()))
+            }
+            ExprKind::MethodCall {
+                receiver,
+                name,
+                args,
+            } => self.check_method_call(receiver, name, args),
+            ExprKind::Async(inner) => self.check_expr(inner),
+            ExprKind::Spawn(body) => {
+                self.check_block(body, Vec::new())?;
+                Ok(Type::Task)
+            }
+            ExprKind::Cast { value, target } => {
+                let found = self.check_expr(value)?;
+                // A JSON value is checked against the type it is cast to when the program runs
+                if found == Type::Json {
+                    match target {
+                        VarType::Int | VarType::Float | VarType::Bool | VarType::String => {
+                            Ok(Type::from_annotation(Some(target)))
+                        }
+                        _ => Err(VentiError::TypeError(
+                            format!("Cannot cast json to {}", target.name()),
+                            None,
+                        )),
+                    }
+                } else if matches!(target, VarType::String) {
+                    Err(VentiError::TypeError(
+                        format!("Cannot cast {} to string", found),
+                        None,
+                    ))
+                } else if found.is_numeric() || found.is_integral() {
+                    Ok(Type::from_annotation(Some(target)))
+                } else {
+                    Err(VentiError::TypeError(
+                        format!("Only numbers and bools can be cast, found {}", found),
+                        None,
+                    ))
+                }
+            }
+            ExprKind::Await(task) => match self.check_expr(task)? {
+                Type::Task => Ok(Type::Int),
+                Type::Unknown => Ok(Type::Unknown),
+                found => Err(VentiError::TypeError(
+                    format!(
+                        "Only tasks returned by async functions can be awaited, found {}",
+                        found
+                    ),
+                    None,
+                )),
+            },
+        }
+    }
+
+    /// Checks a call to one of the built-in methods.
+    fn check_method_call(
+        &mut self,
+        receiver: &Expr,
+        name: &str,
+        args: &[Expr],
+    ) -> Result<Type, VentiError> {
+        let receiver = self.check_expr(receiver)?;
+        let args = args
+            .iter()
+            .map(|arg| self.check_expr(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (expected, result) = match (name, &receiver) {
+            (_, Type::Unknown) => return Ok(Type::Unknown),
+            (
+                "len",
+                Type::Array(..)
+                | Type::Map
+                | Type::String
+                | Type::Buffer
+                | Type::Vector
+                | Type::StringVector
+                | Type::Json,
+            ) => (vec![], Type::Int),
+            ("push", Type::Vector) => (vec![Type::Int], Type::Int),
+            ("pop", Type::Vector) => (vec![], Type::Int),
+            ("has", Type::Map) => (vec![Type::String], Type::Int),
+            ("step", Type::Range) => (vec![Type::Int], Type::Range),
+            (_, Type::Trait(trait_name)) => {
+                let params = self
+                    .traits
+                    .get(trait_name)
+                    .and_then(|methods| methods.iter().find(|(method, _)| method == name));
+                match params {
+                    Some((_, params)) => (params.clone(), Type::Int),
+                    None => {
+                        return Err(VentiError::TypeError(
+                            format!("Trait {} has no method '{}'", trait_name, name),
+                            None,
+                        ))
+                    }
+                }
+            }
+            _ => match self.trait_method(&receiver, name)? {
+                Some(params) => (params, Type::Int),
+                None => {
+                    return Err(VentiError::TypeError(
+                        format!("Type {} has no method '{}'", receiver, name),
+                        None,
+                    ))
+                }
+            },
+        };
+        if expected.len() != args.len() {
+            return Err(VentiError::TypeError(
+                format!(
+                    "Method '{}' expects {} arguments but {} were given",
+                    name,
+                    expected.len(),
+                    args.len()
+                ),
+                None,
+            )
+            .with_code(ErrorCode::ArgumentCount));
+        }
+        for (expected, found) in expected.iter().zip(&args) {
+            if !self.accepts(expected, found) {
+                return Err(VentiError::TypeError(
+                    format!("Method '{}' expects {} but found {}", name, expected, found),
+                    None,
+                ));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Defines `name` in the current scope on the current line, shadowing any earlier
+    /// definition there.
+    fn define(&mut self, name: &str, kind: DefKind, value_type: Type) {
+        self.symbols
+            .define(self.scope, name, kind, self.span, value_type);
+    }
+
+    /// Finds the type of the innermost variable called `name`, recording that the name
+    /// at `span` refers to it.
+    fn lookup(&mut self, name: &str, span: Span) -> Option<Type> {
+        let id = self.symbols.lookup(self.scope, name)?;
+        let definition = self.symbols.definition(id);
+        // Functions are called by name, but are not values
+        if definition.kind == DefKind::Function {
+            return None;
+        }
+        let value_type = definition.info.clone();
+        self.symbols.record_use(span, id);
+        Some(value_type)
+    }
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the type of the values `channel`, an argument of `send` or `recv`, carries: the
+/// one the analyzer settled for the variable it names, or an integer.
+///
+/// # Arguments
+///
+/// * `symbols` - The names the analyzer resolved in the program.
+/// * `channel` - The channel expression.
+pub fn channel_element(symbols: &SymbolTable<Type>, channel: &Expr) -> Type {
+    if !matches!(channel.kind, ExprKind::Identifier(_)) {
+        return Type::Int;
+    }
+    let found = symbols
+        .resolve(channel.span)
+        .map(|id| &symbols.definition(id).info);
+    match found {
+        Some(Type::Channel(element)) if **element != Type::Unknown => (**element).clone(),
+        _ => Type::Int,
+    }
+}
+
+/// Returns the signature of a function the compiler provides, such as `join`.
+pub fn builtin_signature(identifier: &str) -> Option<FunctionSignature> {
+    match identifier {
+        "join" => Some(FunctionSignature {
+            params: vec![Type::Task],
+            returns: Type::Int,
+        }),
+        "alloc" => Some(FunctionSignature {
+            params: vec![Type::Int],
+            returns: Type::Buffer,
+        }),
+        "free" => Some(FunctionSignature {
+            params: vec![Type::Buffer],
+            returns: Type::Int,
+        }),
+        "byte_at" => Some(FunctionSignature {
+            params: vec![Type::String, Type::Int],
+            returns: Type::Int,
+        }),
+        "panic_venti" => Some(FunctionSignature {
+            params: vec![Type::String],
+            returns: Type::Int,
+        }),
+        "upper" | "lower" | "trim" => Some(FunctionSignature {
+            params: vec![Type::String],
+            returns: Type::String,
+        }),
+        "contains" => Some(FunctionSignature {
+            params: vec![Type::String, Type::String],
+            returns: Type::Int,
+        }),
+        "substring" => Some(FunctionSignature {
+            params: vec![Type::String, Type::Int, Type::Int],
+            returns: Type::String,
+        }),
+        "split" => Some(FunctionSignature {
+            params: vec![Type::String, Type::String],
+            returns: Type::StringVector,
+        }),
+        "to_int" => Some(FunctionSignature {
+            params: vec![Type::String],
+            returns: Type::Int,
+        }),
+        "to_float" => Some(FunctionSignature {
+            params: vec![Type::String],
+            returns: Type::Float,
+        }),
+        "random" => Some(FunctionSignature {
+            params: vec![],
+            returns: Type::Float,
+        }),
+        "random_int" => Some(FunctionSignature {
+            params: vec![Type::Int, Type::Int],
+            returns: Type::Int,
+        }),
+        "seed_random" => Some(FunctionSignature {
+            params: vec![Type::Int],
+            returns: Type::Int,
+        }),
+        "now_millis" | "timer" | "exec_status" => Some(FunctionSignature {
+            params: vec![],
+            returns: Type::Int,
+        }),
+        "sleep_ms" | "exit_venti" | "tcp_listen" | "tcp_accept" | "tcp_port" | "tcp_close" => {
+            Some(FunctionSignature {
+                params: vec![Type::Int],
+                returns: Type::Int,
+            })
+        }
+        "elapsed" => Some(FunctionSignature {
+            params: vec![Type::Int],
+            returns: Type::Float,
+        }),
+        "args" => Some(FunctionSignature {
+            params: vec![],
+            returns: Type::StringVector,
+        }),
+        "read_file" | "input_venti" | "env" | "exec" | "http_get" => Some(FunctionSignature {
+            params: vec![Type::String],
+            returns: Type::String,
+        }),
+        "http_post" => Some(FunctionSignature {
+            params: vec![Type::String, Type::String],
+            returns: Type::String,
+        }),
+        "json_parse" => Some(FunctionSignature {
+            params: vec![Type::String],
+            returns: Type::Json,
+        }),
+        "json_kind" => Some(FunctionSignature {
+            params: vec![Type::Json],
+            returns: Type::String,
+        }),
+        "write_file" | "append_file" => Some(FunctionSignature {
+            params: vec![Type::String, Type::String],
+            returns: Type::Int,
+        }),
+        "channel" => Some(FunctionSignature {
+            params: vec![],
+            returns: Type::Channel(Box::new(Type::Unknown)),
+        }),
+        "vec" => Some(FunctionSignature {
+            params: vec![],
+            returns: Type::Vector,
+        }),
+        // What a channel carries is decided by the first `send` on it, which the analyzer
+        // checks on its own
+        "send" => Some(FunctionSignature {
+            params: vec![Type::Channel(Box::new(Type::Unknown)), Type::Unknown],
+            returns: Type::Int,
+        }),
+        "recv" => Some(FunctionSignature {
+            params: vec![Type::Channel(Box::new(Type::Unknown))],
+            returns: Type::Unknown,
+        }),
+        // The values `map`, `filter`, `reduce` and `collect` take can come from any type
+        // `Type::is_iterable` allows, which the analyzer checks on its own
+        "map" | "filter" => Some(FunctionSignature {
+            params: vec![Type::Unknown, Type::Lambda(1)],
+            returns: Type::Iterator,
+        }),
+        "reduce" => Some(FunctionSignature {
+            params: vec![Type::Unknown, Type::Int, Type::Lambda(2)],
+            returns: Type::Int,
+        }),
+        "collect" => Some(FunctionSignature {
+            params: vec![Type::Unknown],
+            returns: Type::Buffer,
+        }),
+        "chars" => Some(FunctionSignature {
+            params: vec![Type::String],
+            returns: Type::Iterator,
+        }),
+        // The sorting builtins take a vector or buffer, which the analyzer checks on its own
+        "sort" => Some(FunctionSignature {
+            params: vec![Type::Unknown],
+            returns: Type::Int,
+        }),
+        "sort_by" => Some(FunctionSignature {
+            params: vec![Type::Unknown, Type::Lambda(2)],
+            returns: Type::Int,
+        }),
+        "binary_search" => Some(FunctionSignature {
+            params: vec![Type::Unknown, Type::Int],
+            returns: Type::Int,
+        }),
+        "tcp_connect" => Some(FunctionSignature {
+            params: vec![Type::String, Type::Int],
+            returns: Type::Int,
+        }),
+        "tcp_send" => Some(FunctionSignature {
+            params: vec![Type::Int, Type::String],
+            returns: Type::Int,
+        }),
+        "tcp_recv" => Some(FunctionSignature {
+            params: vec![Type::Int],
+            returns: Type::String,
+        }),
+        _ => None,
+    }
+}
+
+/// Computes the type of a call to the math builtin `identifier` with arguments of the
+/// types `args`.
+///
+/// `abs`, `min` and `max` keep integers as integers, promoting to `float` like arithmetic
+/// does, and the others always give a float.
+pub fn math_result(identifier: &str, args: &[Type]) -> Result<Type, VentiError> {
+    if let Some((position, found)) = args.iter().enumerate().find(|(_, arg)| !arg.is_numeric()) {
+        return Err(VentiError::TypeError(
+            format!(
+                "Argument {} of '{}' expects an int or float but found {}",
+                position + 1,
+                identifier,
+                found
+            ),
+            None,
+        ));
+    }
+    Ok(match (identifier, args) {
+        ("abs", [value]) => value.clone(),
+        ("min" | "max", [left, right]) => binary_result(left, BinOp::Add, right)?,
+        _ => Type::Float,
+    })
+}
+
+/// Computes the type of `left op right`, mirroring the operand rules of codegen.
+///
+/// Arithmetic promotes to `float` when either side is a float, adding two vectors
+/// concatenates them, comparisons of numbers or of two strings yield bools, `&&` and
+/// `||` need two bools, and the bitwise operators need two integers of the same kind.
+pub fn binary_result(left: &Type, op: BinOp, right: &Type) -> Result<Type, VentiError> {
+    let mismatch = || {
+        VentiError::TypeError(
+            format!(
+                "Operator '{}' cannot be applied to {} and {}",
+                operator_symbol(op),
+                left,
+                right
+            ),
+            None,
+        )
+    };
+    match op {
+        BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide => match (left, right) {
+            (Type::Int, Type::Int) => Ok(Type::Int),
+            (Type::Vector, Type::Vector) if op == BinOp::Add => Ok(Type::Vector),
+            (Type::Float, right) if right.is_numeric() => Ok(Type::Float),
+            (left, Type::Float) if left.is_numeric() => Ok(Type::Float),
+            (left, right) if left.is_numeric() && right.is_numeric() => Ok(Type::Unknown),
+            _ => Err(mismatch()),
+        },
+        BinOp::Equal
+        | BinOp::NotEqual
+        | BinOp::Less
+        | BinOp::LessEqual
+        | BinOp::Greater
+        | BinOp::GreaterEqual => {
+            let integers = left.is_integral() && right.is_integral() && left.accepts(right);
+            if integers
+                || (left.is_numeric() && right.is_numeric())
+                || (*left == Type::String && *right == Type::String)
+            {
+                Ok(Type::Bool)
+            } else {
+                Err(mismatch())
+            }
+        }
+        BinOp::And | BinOp::Or => {
+            if Type::Bool.accepts(left) && Type::Bool.accepts(right) {
+                Ok(Type::Bool)
+            } else {
+                Err(mismatch())
+            }
+        }
+        BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => {
+            if left.is_integral() && right.is_integral() && left.accepts(right) {
+                Ok(if *left == Type::Unknown {
+                    right.clone()
+                } else {
+                    left.clone()
+                })
+            } else {
+                Err(mismatch())
+            }
+        }
+        BinOp::ShiftLeft | BinOp::ShiftRight => {
+            if Type::Int.accepts(left) && Type::Int.accepts(right) {
+                Ok(Type::Int)
+            } else {
+                Err(mismatch())
+            }
+        }
+        BinOp::Coalesce => match (left, right) {
+            (left, right) if left.is_nullable() && !Type::String.accepts(right) => Err(mismatch()),
+            (left, _) if left.is_nullable() => Ok(Type::String),
+            (left, _) => Ok(left.clone()),
+        },
+    }
+}
+
+/// Returns the source spelling of a binary operator, for error messages.
+fn operator_symbol(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Subtract => "-",
+        BinOp::Multiply => "*",
+        BinOp::Divide => "/",
+        BinOp::Equal => "==",
+        BinOp::NotEqual => "!=",
+        BinOp::Less => "<",
+        BinOp::LessEqual => "<=",
+        BinOp::Greater => ">",
+        BinOp::GreaterEqual => ">=",
+        BinOp::And => "&&",
+        BinOp::Or => "||",
+        BinOp::BitAnd => "&",
+        BinOp::BitOr => "|",
+        BinOp::BitXor => "^",
+        BinOp::ShiftLeft => "<<",
+        BinOp::ShiftRight => ">>",
+        BinOp::Coalesce => "??",
+    }
+}
@@ -0,0 +1,789 @@
+use crate::codegen::builtins;
+use crate::errors::VentiError;
+use crate::semantic::analyzer::{binary_result, builtin_signature};
+use crate::semantic::types::Type;
+use crate::traits;
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::ast::{
+    BinOp, Expr, ExprKind, Parameter, Pattern, Statement, UnaryOp, VarType,
+};
+use std::collections::HashMap;
+
+/*
+Inference gives the parameters written without a type the type the program uses them
+at, before the analyzer checks it:
+
+    fn_venti greet(name) {          fn_venti greet(name: string) {
+        printventi(name);     ->        printventi(name);
+    }                               }
+    greet("Ada");                   greet("Ada");
+
+It is Hindley-Milner without generalization. Each untyped parameter starts out as a type
+variable, and every use that says what it must be binds the variable: an argument passed
+for it, a call passing it to a parameter of known type, a map key, a string pattern it
+is matched against, or an operator that would reject an integer but accepts the type on
+its other side. Variables unified with each other, such as a parameter passed straight
+on to another function's, share their type. Declarations take the type of their value,
+so after `venti x = foo();` a use of `x` is a use of what `foo` returns.
+
+A parameter has one type for the whole program, so calling a function with a string
+and, elsewhere, with an integer is an error that names the parameter. A variable nothing
+binds keeps the integer type an untyped parameter always had, and the types written back
+are those a parameter can be annotated with; any other, such as an array, is left to the
+analyzer to reject as it would without inference.
+
+A function written without a result type that returns a vector is given the `vec` result,
+and calls to it after its definition are vectors. Every other function returns an integer.
+*/
+
+/// Infers the types of the untyped parameters of `statements`, and annotates the
+/// parameters with the types it found.
+///
+/// # Returns
+///
+/// An empty `Result`, or a `VentiError::TypeError` naming a parameter the program uses
+/// at two types.
+pub fn infer(statements: &mut [Statement]) -> Result<(), VentiError> {
+    let mut inferrer = Inferrer::new();
+    inferrer.declare(statements);
+    inferrer.statements(statements)?;
+    inferrer.annotate(statements);
+    Ok(())
+}
+
+/// A type as inference knows it: known, or a variable still to be solved.
+#[derive(Debug, Clone, PartialEq)]
+enum Ty {
+    Known(Type),
+    Var(usize),
+}
+
+/// A type variable, which stands for an untyped parameter.
+struct Variable {
+    // The variable this one was unified with, or itself if it is the representative
+    parent: usize,
+    binding: Option<Type>,
+    function: String,
+    param: String,
+}
+
+struct Inferrer {
+    variables: Vec<Variable>,
+    // The type of each parameter of every function the program defines
+    signatures: HashMap<String, Vec<Ty>>,
+    // The result of each function known to return something other than an integer
+    results: HashMap<String, Type>,
+    // The function whose body is being inferred, unless it is async or a test
+    function: Option<String>,
+    // The first scope holds the top-level variables, as in the analyzer
+    scopes: Vec<HashMap<String, Ty>>,
+    // The span of the statement being inferred
+    span: Span,
+}
+
+impl Inferrer {
+    fn new() -> Self {
+        Inferrer {
+            variables: Vec::new(),
+            signatures: HashMap::new(),
+            results: HashMap::new(),
+            function: None,
+            scopes: vec![HashMap::new()],
+            span: Span::default(),
+        }
+    }
+
+    /// Gives each untyped parameter of the functions `statements` define, nested ones
+    /// included, a type variable of its own.
+    fn declare(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Function {
+                    identifier,
+                    params,
+                    body,
+                    ..
+                }
+                | Statement::AsyncFunction {
+                    identifier,
+                    params,
+                    body,
+                    ..
+                } => {
+                    if let Statement::Function {
+                        returns: Some(returns),
+                        ..
+                    } = statement
+                    {
+                        let returns = Type::from_annotation(Some(returns));
+                        self.results.insert(identifier.clone(), returns);
+                    }
+                    let types = params
+                        .iter()
+                        .map(|param| self.parameter(identifier, param))
+                        .collect();
+                    self.signatures.insert(identifier.clone(), types);
+                    self.declare(body);
+                }
+                Statement::For { body, .. } | Statement::Test { body, .. } => self.declare(body),
+                Statement::Try { body, handler, .. } => {
+                    self.declare(body);
+                    self.declare(handler);
+                }
+                Statement::Match { arms, .. } => {
+                    arms.iter().for_each(|arm| self.declare(&arm.body));
+                }
+                Statement::Impl {
+                    trait_name,
+                    target,
+                    methods,
+                } => {
+                    for method in methods {
+                        if let Statement::Function {
+                            identifier,
+                            params,
+                            body,
+                            ..
+                        } = method
+                        {
+                            let function =
+                                traits::method_function(trait_name, target.name(), identifier);
+                            let types = params
+                                .iter()
+                                .map(|param| self.parameter(&function, param))
+                                .collect();
+                            self.signatures.insert(function, types);
+                            self.declare(body);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The type of a parameter of `function`: its annotation, or a new variable.
+    fn parameter(&mut self, function: &str, param: &Parameter) -> Ty {
+        if param.var_type.is_some() {
+            return Ty::Known(Type::from_annotation(param.var_type.as_ref()));
+        }
+        let id = self.variables.len();
+        self.variables.push(Variable {
+            parent: id,
+            binding: None,
+            function: function.to_string(),
+            param: param.name.clone(),
+        });
+        Ty::Var(id)
+    }
+
+    /// Writes the type found for each untyped parameter onto it, when it has one a
+    /// parameter can be annotated with other than `int`, and the result found for each
+    /// function written without one.
+    fn annotate(&self, statements: &mut [Statement]) {
+        for statement in statements {
+            if let Statement::Function {
+                identifier,
+                returns: returns @ None,
+                ..
+            } = statement
+            {
+                if self.results.get(identifier.as_str()) == Some(&Type::Vector) {
+                    *returns = Some(VarType::Vec);
+                }
+            }
+            match statement {
+                Statement::Function {
+                    identifier,
+                    params,
+                    body,
+                    ..
+                }
+                | Statement::AsyncFunction {
+                    identifier,
+                    params,
+                    body,
+                    ..
+                } => {
+                    self.annotate_params(identifier, params);
+                    self.annotate(body);
+                }
+                Statement::For { body, .. } | Statement::Test { body, .. } => self.annotate(body),
+                Statement::Try { body, handler, .. } => {
+                    self.annotate(body);
+                    self.annotate(handler);
+                }
+                Statement::Match { arms, .. } => {
+                    arms.iter_mut().for_each(|arm| self.annotate(&mut arm.body));
+                }
+                Statement::Impl {
+                    trait_name,
+                    target,
+                    methods,
+                } => {
+                    for method in methods {
+                        if let Statement::Function {
+                            identifier,
+                            params,
+                            body,
+                            ..
+                        } = method
+                        {
+                            let function =
+                                traits::method_function(trait_name, target.name(), identifier);
+                            self.annotate_params(&function, params);
+                            self.annotate(body);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Writes the types found for the untyped parameters of `function` onto `params`.
+    fn annotate_params(&self, function: &str, params: &mut [Parameter]) {
+        let types = self.signatures.get(function).cloned().unwrap_or_default();
+        for (param, ty) in params.iter_mut().zip(types) {
+            param.var_type = param.var_type.take().or(match self.resolve(&ty) {
+                Ty::Known(Type::Float) => Some(VarType::Float),
+                Ty::Known(Type::Bool) => Some(VarType::Bool),
+                Ty::Known(Type::String) => Some(VarType::String),
+                Ty::Known(Type::Vector) => Some(VarType::Vec),
+                _ => None,
+            });
+        }
+    }
+
+    /// Finds the representative of the variables unified with `id`.
+    fn find(&self, mut id: usize) -> usize {
+        while self.variables[id].parent != id {
+            id = self.variables[id].parent;
+        }
+        id
+    }
+
+    /// Replaces a variable by its type, once it has one.
+    fn resolve(&self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Var(id) => {
+                let root = self.find(*id);
+                match &self.variables[root].binding {
+                    Some(binding) => Ty::Known(binding.clone()),
+                    None => Ty::Var(root),
+                }
+            }
+            known => known.clone(),
+        }
+    }
+
+    /// Makes two types the same, binding whichever of them is a variable, and reports a
+    /// variable whose type disagrees.
+    ///
+    /// Two known types are left to the analyzer to compare.
+    fn unify(&mut self, left: &Ty, right: &Ty) -> Result<(), VentiError> {
+        let root = |ty: &Ty| match ty {
+            Ty::Var(id) => Ty::Var(self.find(*id)),
+            known => known.clone(),
+        };
+        match (root(left), root(right)) {
+            (Ty::Var(left), Ty::Var(right)) if left == right => Ok(()),
+            (Ty::Var(left), Ty::Var(right)) => {
+                self.variables[left].parent = right;
+                match self.variables[left].binding.take() {
+                    Some(binding) => self.bind(right, binding),
+                    None => Ok(()),
+                }
+            }
+            (Ty::Var(id), Ty::Known(found)) | (Ty::Known(found), Ty::Var(id)) => {
+                self.bind(id, found)
+            }
+            (Ty::Known(_), Ty::Known(_)) => Ok(()),
+        }
+    }
+
+    /// Binds the variable `id` to `found`, which must agree with any type it already has.
+    fn bind(&mut self, id: usize, found: Type) -> Result<(), VentiError> {
+        let found = match found {
+            // A parameter passed where a trait is expected may have any type implementing it
+            Type::Unknown | Type::Trait(_) => return Ok(()),
+            // `nothing` stands in for a string
+            Type::Nothing => Type::String,
+            found => found,
+        };
+        let root = self.find(id);
+        let variable = &mut self.variables[root];
+        match &variable.binding {
+            None => {
+                variable.binding = Some(found);
+                Ok(())
+            }
+            Some(bound) if bound.accepts(&found) => Ok(()),
+            Some(bound) => Err(VentiError::TypeError(
+                format!(
+                    "Parameter '{}' of '{}' is used as {} and as {}",
+                    variable.param, variable.function, bound, found
+                ),
+                None,
+            )),
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Result<(), VentiError> {
+        for statement in statements {
+            self.statement(statement)
+                .map_err(|error| error.at(self.span))?;
+        }
+        Ok(())
+    }
+
+    /// Infers `body` in a new scope that starts out with `bindings`.
+    fn block(&mut self, body: &[Statement], bindings: Vec<(String, Ty)>) -> Result<(), VentiError> {
+        self.scopes.push(bindings.into_iter().collect());
+        let result = self.statements(body);
+        self.scopes.pop();
+        result
+    }
+
+    fn lookup(&self, name: &str) -> Ty {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .unwrap_or_else(|| match builtins::constant(name) {
+                Some(_) => Ty::Known(Type::Float),
+                None => Ty::Known(Type::Unknown),
+            })
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), VentiError> {
+        match statement {
+            Statement::VariableDeclaration { identifier, value } => {
+                let ty = self.expr(value)?;
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(identifier.clone(), ty);
+                }
+                Ok(())
+            }
+            Statement::VariableAssignment { identifier, value } => {
+                let ty = self.expr(value)?;
+                self.unify(&self.lookup(identifier), &ty)
+            }
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => {
+                let target = self.expr(target)?;
+                let index = self.expr(index)?;
+                let value = self.expr(value)?;
+                match self.resolve(&target) {
+                    Ty::Known(Type::Map) => {
+                        self.unify(&index, &Ty::Known(Type::String))?;
+                        self.unify(&value, &Ty::Known(Type::Int))
+                    }
+                    Ty::Known(Type::Buffer | Type::Vector) => {
+                        self.unify(&index, &Ty::Known(Type::Int))?;
+                        self.unify(&value, &Ty::Known(Type::Int))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Statement::FunctionCall { identifier, args } => {
+                self.call(identifier, args)?;
+                Ok(())
+            }
+            Statement::Print { value, .. } | Statement::Expression(value) => {
+                self.expr(value)?;
+                Ok(())
+            }
+            Statement::Assert(condition) => {
+                self.expr(condition)?;
+                Ok(())
+            }
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    let ty = self.expr(value)?;
+                    if let (Some(function), Ty::Known(Type::Vector)) =
+                        (&self.function, self.resolve(&ty))
+                    {
+                        self.results.entry(function.clone()).or_insert(Type::Vector);
+                    }
+                }
+                Ok(())
+            }
+            Statement::Function {
+                identifier,
+                params,
+                body,
+                ..
+            } => self.function(identifier, Some(identifier), params, body),
+            Statement::AsyncFunction {
+                identifier,
+                params,
+                body,
+                ..
+            } => self.function(identifier, None, params, body),
+            Statement::Test { body, .. } => self.function("", None, &[], body),
+            Statement::Match { scrutinee, arms } => {
+                let scrutinee = self.expr(scrutinee)?;
+                for arm in arms {
+                    match arm.pattern {
+                        Pattern::String(_) => self.unify(&scrutinee, &Ty::Known(Type::String))?,
+                        Pattern::Number(_) | Pattern::Wildcard => {}
+                    }
+                    self.block(&arm.body, Vec::new())?;
+                }
+                Ok(())
+            }
+            Statement::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                let iterable = self.expr(iterable)?;
+                let element = match self.resolve(&iterable) {
+                    Ty::Known(Type::Array(element, _)) => *element,
+                    Ty::Known(Type::StringVector) => Type::String,
+                    Ty::Known(
+                        Type::Int | Type::Range | Type::Buffer | Type::Vector | Type::Iterator,
+                    )
+                    | Ty::Var(_) => Type::Int,
+                    _ => Type::Unknown,
+                };
+                self.block(body, vec![(variable.clone(), Ty::Known(element))])
+            }
+            Statement::Try {
+                body,
+                error,
+                handler,
+            } => {
+                self.block(body, Vec::new())?;
+                self.block(handler, vec![(error.clone(), Ty::Known(Type::String))])
+            }
+            Statement::Line(span) => {
+                self.span = *span;
+                Ok(())
+            }
+            Statement::Impl {
+                trait_name,
+                target,
+                methods,
+            } => {
+                for method in methods {
+                    match method {
+                        Statement::Function {
+                            identifier,
+                            params,
+                            body,
+                            ..
+                        } => {
+                            let function =
+                                traits::method_function(trait_name, target.name(), identifier);
+                            self.function(&function, None, params, body)?;
+                        }
+                        method => self.statement(method)?,
+                    }
+                }
+                Ok(())
+            }
+            Statement::Comment { .. }
+            | Statement::Import(_)
+            | Statement::Extern { .. }
+            | Statement::Trait { .. } => Ok(()),
+        }
+    }
+
+    /// Infers a function body, which sees its parameters and the top-level variables.
+    ///
+    /// # Arguments
+    ///
+    /// * `returning` - The function whose result the body's returns give, unless the
+    ///   result cannot be anything but an integer.
+    fn function(
+        &mut self,
+        identifier: &str,
+        returning: Option<&String>,
+        params: &[Parameter],
+        body: &[Statement],
+    ) -> Result<(), VentiError> {
+        let types = match self.signatures.get(identifier) {
+            Some(types) => types.clone(),
+            None => params
+                .iter()
+                .map(|param| Ty::Known(Type::from_annotation(param.var_type.as_ref())))
+                .collect(),
+        };
+        let bindings = params
+            .iter()
+            .map(|param| param.name.clone())
+            .zip(types)
+            .collect();
+        let enclosing = self.scopes.split_off(1);
+        let enclosing_function = std::mem::replace(&mut self.function, returning.cloned());
+        let result = self.block(body, bindings);
+        self.function = enclosing_function;
+        self.scopes.truncate(1);
+        self.scopes.extend(enclosing);
+        result
+    }
+
+    /// Infers a call of the function or builtin `identifier`, unifying each argument with
+    /// its parameter.
+    ///
+    /// # Returns
+    ///
+    /// The type the function returns, or `Unknown` for a function defined outside the
+    /// program.
+    fn call(&mut self, identifier: &str, args: &[Expr]) -> Result<Ty, VentiError> {
+        let args = args
+            .iter()
+            .map(|arg| self.expr(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        if builtins::is_math(identifier) && !self.signatures.contains_key(identifier) {
+            return self.math_call(identifier, args);
+        }
+        let (params, returns) = match self.signatures.get(identifier) {
+            Some(params) => {
+                let returns = self.results.get(identifier).cloned();
+                (params.clone(), returns.unwrap_or(Type::Int))
+            }
+            None => match builtin_signature(identifier) {
+                Some(builtin) => {
+                    let params = builtin.params.into_iter().map(Ty::Known).collect();
+                    (params, builtin.returns)
+                }
+                None if identifier == "len" => (Vec::new(), Type::Int),
+                None if identifier == "json_stringify" => (Vec::new(), Type::String),
+                None => (Vec::new(), Type::Unknown),
+            },
+        };
+        for (param, arg) in params.iter().zip(&args) {
+            self.unify(param, arg)?;
+        }
+        Ok(Ty::Known(returns))
+    }
+
+    /// Infers the type of a call to a math builtin: `abs`, `min` and `max` follow their
+    /// arguments the way addition does, and the others give a float whatever they are
+    /// given.
+    fn math_call(&mut self, identifier: &str, args: Vec<Ty>) -> Result<Ty, VentiError> {
+        match (identifier, args.as_slice()) {
+            ("abs", [value]) => Ok(self.resolve(value)),
+            ("min" | "max", [left, right]) => self.binary(left.clone(), BinOp::Add, right.clone()),
+            _ => Ok(Ty::Known(Type::Float)),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Ty, VentiError> {
+        let known = |ty: Type| Ok(Ty::Known(ty));
+        match &expr.kind {
+            ExprKind::Number(_) => known(Type::Int),
+            ExprKind::Float(_) => known(Type::Float),
+            ExprKind::String(_) => known(Type::String),
+            ExprKind::Boolean(_) => known(Type::Bool),
+            ExprKind::Null => known(Type::Nothing),
+            ExprKind::Identifier(name) => Ok(self.lookup(name)),
+            ExprKind::BinaryOp(left, op, right) => {
+                let left = self.expr(left)?;
+                let right = self.expr(right)?;
+                self.binary(left, *op, right)
+            }
+            ExprKind::UnaryOp(UnaryOp::Not, operand) => {
+                let operand = self.expr(operand)?;
+                self.boolean(&operand)
+            }
+            ExprKind::UnaryOp(UnaryOp::Negate, operand) => self.expr(operand),
+            ExprKind::Call { callee, args } => {
+                if let ExprKind::Identifier(identifier) = &callee.kind {
+                    let is_local = self.scopes[1..]
+                        .iter()
+                        .any(|scope| scope.contains_key(identifier));
+                    if !is_local {
+                        return self.call(identifier, args);
+                    }
+                }
+                self.expr(callee)?;
+                for arg in args {
+                    self.expr(arg)?;
+                }
+                known(Type::Int)
+            }
+            ExprKind::Lambda { params, body } => {
+                let scope = params
+                    .iter()
+                    .map(|param| {
+                        let ty = Type::from_annotation(param.var_type.as_ref());
+                        (param.name.clone(), Ty::Known(ty))
+                    })
+                    .collect();
+                self.scopes.push(scope);
+                let body = self.expr(body);
+                self.scopes.pop();
+                body?;
+                known(Type::Lambda(params.len()))
+            }
+            ExprKind::MethodCall {
+                receiver,
+                name,
+                args,
+            } => {
+                self.expr(receiver)?;
+                for arg in args {
+                    self.expr(arg)?;
+                }
+                match name.as_str() {
+                    "len" | "has" | "push" | "pop" => known(Type::Int),
+                    "step" => known(Type::Range),
+                    _ => known(Type::Unknown),
+                }
+            }
+            ExprKind::Array(elements) => {
+                let mut element = Type::Int;
+                for (position, value) in elements.iter().enumerate() {
+                    let ty = self.expr(value)?;
+                    if position == 0 {
+                        element = match self.resolve(&ty) {
+                            Ty::Known(ty) => ty,
+                            Ty::Var(_) => Type::Unknown,
+                        };
+                    }
+                }
+                known(Type::Array(Box::new(element), elements.len()))
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    let key = self.expr(key)?;
+                    self.unify(&key, &Ty::Known(Type::String))?;
+                    let value = self.expr(value)?;
+                    self.unify(&value, &Ty::Known(Type::Int))?;
+                }
+                known(Type::Map)
+            }
+            ExprKind::Range { start, end, .. } => {
+                for bound in [start, end] {
+                    let bound = self.expr(bound)?;
+                    self.unify(&bound, &Ty::Known(Type::Int))?;
+                }
+                known(Type::Range)
+            }
+            ExprKind::Index { target, index } => {
+                let target = self.expr(target)?;
+                let index = self.expr(index)?;
+                match self.resolve(&target) {
+                    Ty::Known(Type::Array(element, _)) => known(*element),
+                    Ty::Known(Type::Map) => {
+                        self.unify(&index, &Ty::Known(Type::String))?;
+                        known(Type::Int)
+                    }
+                    Ty::Known(Type::Buffer) => known(Type::Int),
+                    Ty::Known(Type::Vector) => match self.resolve(&index) {
+                        Ty::Known(Type::Range) => known(Type::Vector),
+                        _ => known(Type::Int),
+                    },
+                    Ty::Known(Type::String | Type::StringVector) => known(Type::String),
+                    Ty::Known(Type::Json) => known(Type::Json),
+                    _ => known(Type::Unknown),
+                }
+            }
+            ExprKind::Cast { value, target } => {
+                self.expr(value)?;
+                known(Type::from_annotation(Some(target)))
+            }
+            ExprKind::Async(inner) => self.expr(inner),
+            ExprKind::Await(task) => {
+                self.expr(task)?;
+                known(Type::Int)
+            }
+            ExprKind::Spawn(body) => {
+                self.block(body, Vec::new())?;
+                known(Type::Task)
+            }
+        }
+    }
+
+    /// Binds an operand of `!`, `&&` or `||` that is still a variable to `bool`.
+    fn boolean(&mut self, operand: &Ty) -> Result<Ty, VentiError> {
+        if let Ty::Var(id) = self.resolve(operand) {
+            self.bind(id, Type::Bool)?;
+        }
+        Ok(Ty::Known(Type::Bool))
+    }
+
+    /// Infers `left op right`, binding a variable on one side when the operator would
+    /// reject an integer there but accepts the type on the other side.
+    fn binary(&mut self, left: Ty, op: BinOp, right: Ty) -> Result<Ty, VentiError> {
+        if matches!(op, BinOp::And | BinOp::Or) {
+            self.boolean(&left)?;
+            return self.boolean(&right);
+        }
+        let (left, right) = (self.resolve(&left), self.resolve(&right));
+        match (&left, &right) {
+            (Ty::Known(left), Ty::Known(right)) => {
+                return Ok(Ty::Known(
+                    binary_result(left, op, right).unwrap_or(Type::Unknown),
+                ));
+            }
+            (Ty::Var(_), Ty::Var(_)) => self.unify(&left, &right)?,
+            (Ty::Var(id), Ty::Known(other)) => {
+                if let Some(hint) = operand_hint(op, other, true) {
+                    self.bind(*id, hint)?;
+                }
+            }
+            (Ty::Known(other), Ty::Var(id)) => {
+                if let Some(hint) = operand_hint(op, other, false) {
+                    self.bind(*id, hint)?;
+                }
+            }
+        }
+        let (left, right) = (self.resolve(&left), self.resolve(&right));
+        if let (Ty::Known(left), Ty::Known(right)) = (&left, &right) {
+            return Ok(Ty::Known(
+                binary_result(left, op, right).unwrap_or(Type::Unknown),
+            ));
+        }
+        // An operand that is still a variable may yet turn out to be a float
+        let variable = match (&left, &right) {
+            (Ty::Var(_), _) => left.clone(),
+            _ => right.clone(),
+        };
+        Ok(match op {
+            BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide => {
+                match (&left, &right) {
+                    (Ty::Known(Type::Float), _) | (_, Ty::Known(Type::Float)) => {
+                        Ty::Known(Type::Float)
+                    }
+                    _ => variable,
+                }
+            }
+            BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor | BinOp::Coalesce => variable,
+            BinOp::Equal
+            | BinOp::NotEqual
+            | BinOp::Less
+            | BinOp::LessEqual
+            | BinOp::Greater
+            | BinOp::GreaterEqual => Ty::Known(Type::Bool),
+            _ => Ty::Known(Type::Int),
+        })
+    }
+}
+
+/// The type an operand must have for `op` to accept it next to `other`, when that is
+/// not the integer an untyped parameter defaults to.
+///
+/// # Arguments
+///
+/// * `op` - The operator.
+/// * `other` - The type of the other operand.
+/// * `on_left` - Whether the operand is on the left of `op`.
+fn operand_hint(op: BinOp, other: &Type, on_left: bool) -> Option<Type> {
+    let candidate = match other {
+        Type::Nothing => Type::String,
+        other => other.clone(),
+    };
+    let accepts = |operand: &Type| match on_left {
+        true => binary_result(operand, op, other).is_ok(),
+        false => binary_result(other, op, operand).is_ok(),
+    };
+    (!accepts(&Type::Int) && accepts(&candidate)).then_some(candidate)
+}
@@ -0,0 +1,581 @@
+use crate::errors::VentiError;
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::ast::{Expr, ExprKind, Parameter, Statement};
+use std::collections::HashMap;
+use std::mem;
+
+/*
+Lints point out code that compiles but is probably a mistake. Each lint is at one of three
+levels: allowed lints are not checked, warned ones are printed without stopping the
+compilation, and denied ones are errors. Every lint warns by default.
+
+    unused-variable     a variable declared with `venti` is never read
+    unused-function     a function is never called, other than by itself
+    unreachable-code    a statement follows `return_venti` in the same block
+    shadowed-variable   a variable is declared with the name of one that is already
+                        visible, which it hides from the code after it
+
+Names starting with `_` are never reported as unused. Code brought in by `import_venti` is
+linted where its names are used but not for its own declarations, since a file that is
+imported is usually used for only some of what it declares.
+*/
+
+/// A check for code that is probably a mistake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedFunction,
+    UnreachableCode,
+    ShadowedVariable,
+}
+
+impl Lint {
+    /// Every lint.
+    pub const ALL: [Lint; 4] = [
+        Lint::UnusedVariable,
+        Lint::UnusedFunction,
+        Lint::UnreachableCode,
+        Lint::ShadowedVariable,
+    ];
+
+    /// The name the lint is given on the command line and printed with.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnusedFunction => "unused-function",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::ShadowedVariable => "shadowed-variable",
+        }
+    }
+
+    /// Maps a lint's name to the lint, or `None` for an unknown name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+
+    /// A short note on how a warning of this lint is usually dealt with.
+    pub fn help(self) -> Option<&'static str> {
+        match self {
+            Lint::UnusedVariable => {
+                Some("remove it, or start its name with `_` if it is meant to be unused")
+            }
+            Lint::UnusedFunction => {
+                Some("remove it, or start its name with `_` if it is kept for later")
+            }
+            Lint::UnreachableCode => {
+                Some("remove the statements after `return_venti`, or return later")
+            }
+            Lint::ShadowedVariable => {
+                Some("give the new variable another name, or assign to the old one without `venti`")
+            }
+        }
+    }
+}
+
+/// How a lint is treated, chosen with `--allow`, `--warn` and `--deny`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// The level of every lint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintLevels {
+    levels: HashMap<Lint, Level>,
+}
+
+impl LintLevels {
+    /// Creates `LintLevels` in which every lint warns.
+    pub fn new() -> Self {
+        LintLevels {
+            levels: Lint::ALL
+                .into_iter()
+                .map(|lint| (lint, Level::Warn))
+                .collect(),
+        }
+    }
+
+    /// Sets the level of `lint`.
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    /// Denies every lint that warns, for `--deny-warnings`.
+    pub fn deny_warnings(&mut self) {
+        for level in self.levels.values_mut() {
+            if *level == Level::Warn {
+                *level = Level::Deny;
+            }
+        }
+    }
+
+    /// Returns the level of `lint`.
+    pub fn level(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+
+    /// Sorts `warnings` by the level of their lints.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Warning>, VentiError>` - The warnings to print, or the denied
+    ///   warnings as an error, which is a `VentiError::Multiple` when there are several.
+    pub fn apply(&self, warnings: Vec<Warning>) -> Result<Vec<Warning>, VentiError> {
+        let mut printed = Vec::new();
+        let mut denied = Vec::new();
+        for warning in warnings {
+            match self.level(warning.lint) {
+                Level::Allow => {}
+                Level::Warn => printed.push(warning),
+                Level::Deny => denied.push(VentiError::LintError(
+                    format!("{} ({} is denied)", warning.message, warning.lint.name()),
+                    warning.span,
+                )),
+            }
+        }
+        match denied.len() {
+            0 => Ok(printed),
+            1 => Err(denied.remove(0)),
+            _ => Err(VentiError::Multiple(denied)),
+        }
+    }
+}
+
+impl Default for LintLevels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Code a lint found, which does not stop the compilation unless the lint is denied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+/// A variable in scope, and whether it has been read.
+struct Binding {
+    name: String,
+    span: Span,
+    used: bool,
+    // Parameters and loop variables are not reported when they are unused
+    reported: bool,
+}
+
+/// A function declared anywhere in the program, and whether anything else calls it.
+struct Function {
+    span: Span,
+    called: bool,
+}
+
+/// Walks a checked program and collects the warnings of every lint.
+///
+/// Like the analyzer, the linter keeps a stack of scopes whose first scope holds the
+/// top-level variables, which function bodies see but not the rest of the scopes around
+/// them. Function bodies at the top level are linted after the other top-level
+/// statements, since they may use variables declared after them.
+pub struct Linter {
+    scopes: Vec<Vec<Binding>>,
+    functions: HashMap<String, Function>,
+    // The function whose body is being linted, whose calls to itself are not uses
+    function: Option<String>,
+    // The span of the statement being linted, empty when spans are not recorded
+    span: Span,
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    /// Creates a new `Linter` with an empty global scope.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Linter`.
+    pub fn new() -> Self {
+        Linter {
+            scopes: vec![Vec::new()],
+            functions: HashMap::new(),
+            function: None,
+            span: Span::default(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Lints a whole program, which the analyzer has checked.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Warning>` - The warnings of every lint, in source order.
+    pub fn lint(mut self, statements: &[Statement]) -> Vec<Warning> {
+        self.collect_functions(statements);
+        self.span = Span::default();
+        let mut bodies = Vec::new();
+        let mut returned = false;
+        for statement in statements {
+            match statement {
+                Statement::Function { .. }
+                | Statement::AsyncFunction { .. }
+                | Statement::Test { .. } => bodies.push((self.span, statement)),
+                statement => self.statement(statement, &mut returned),
+            }
+        }
+        for (span, statement) in bodies {
+            self.span = span;
+            self.statement(statement, &mut false);
+        }
+        self.pop_scope();
+
+        let mut unused = self
+            .functions
+            .iter()
+            .filter(|(name, function)| !function.called && !exempt(name))
+            .map(|(name, function)| Warning {
+                lint: Lint::UnusedFunction,
+                message: format!("Function '{}' is never called", name),
+                span: located(function.span),
+            })
+            .collect::<Vec<_>>();
+        self.warnings.append(&mut unused);
+        // Warnings are found scope by scope, so they are put back in source order
+        self.warnings
+            .sort_by_key(|warning| warning.span.map_or((0, 0), |span| (span.file, span.start)));
+        self.warnings
+    }
+
+    /// Records every function the program declares, at any depth, with the line it is
+    /// declared on.
+    fn collect_functions(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Line(span) => self.span = *span,
+                Statement::Function {
+                    identifier, body, ..
+                }
+                | Statement::AsyncFunction {
+                    identifier, body, ..
+                } => {
+                    self.functions.insert(
+                        identifier.clone(),
+                        Function {
+                            span: self.span,
+                            called: false,
+                        },
+                    );
+                    self.collect_functions(body);
+                }
+                Statement::Test { body, .. } | Statement::For { body, .. } => {
+                    self.collect_functions(body)
+                }
+                Statement::Try { body, handler, .. } => {
+                    self.collect_functions(body);
+                    self.collect_functions(handler);
+                }
+                Statement::Match { arms, .. } => {
+                    for arm in arms {
+                        self.collect_functions(&arm.body);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Lints `body` in a new scope that starts out with `bindings`.
+    fn block(&mut self, body: &[Statement], bindings: Vec<Binding>) {
+        self.scopes.push(bindings);
+        let mut returned = false;
+        for statement in body {
+            self.statement(statement, &mut returned);
+        }
+        self.pop_scope();
+    }
+
+    /// Lints a statement of a block, where `returned` records whether a `return_venti`
+    /// came before it.
+    fn statement(&mut self, statement: &Statement, returned: &mut bool) {
+        match statement {
+            Statement::Line(span) => {
+                self.span = *span;
+                return;
+            }
+            Statement::Comment { .. } | Statement::Import(_) => return,
+            _ => {}
+        }
+        // Only the first unreachable statement is reported, since the rest follow from it
+        if *returned {
+            self.warn(
+                Lint::UnreachableCode,
+                "Unreachable statement after return_venti".to_string(),
+                self.span,
+            );
+            *returned = false;
+        }
+
+        match statement {
+            Statement::VariableDeclaration { identifier, value } => {
+                self.expr(value);
+                self.declare(identifier, true);
+            }
+            Statement::VariableAssignment { value, .. } => self.expr(value),
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            } => {
+                self.expr(target);
+                self.expr(index);
+                self.expr(value);
+            }
+            Statement::FunctionCall { identifier, args } => {
+                self.use_name(identifier);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            Statement::Print { value, .. } | Statement::Expression(value) => self.expr(value),
+            Statement::Assert(value) => self.expr(value),
+            Statement::Function {
+                identifier,
+                params,
+                body,
+                ..
+            }
+            | Statement::AsyncFunction {
+                identifier,
+                params,
+                body,
+                ..
+            } => self.function(Some(identifier), params, body),
+            Statement::Test { body, .. } => self.function(None, &[], body),
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                *returned = true;
+            }
+            Statement::Match { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.block(&arm.body, Vec::new());
+                }
+            }
+            Statement::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                self.expr(iterable);
+                self.shadow_check(variable);
+                let binding = self.binding(variable, false);
+                self.block(body, vec![binding]);
+            }
+            // What follows is only unreachable when both blocks return, which is not
+            // looked for
+            Statement::Try {
+                body,
+                error,
+                handler,
+            } => {
+                self.block(body, Vec::new());
+                self.shadow_check(error);
+                let binding = self.binding(error, false);
+                self.block(handler, vec![binding]);
+            }
+            // Methods are called through their trait, so none of them is ever unused
+            Statement::Impl { methods, .. } => {
+                for method in methods {
+                    match method {
+                        Statement::Function { params, body, .. } => {
+                            self.function(None, params, body)
+                        }
+                        Statement::Line(span) => self.span = *span,
+                        _ => {}
+                    }
+                }
+            }
+            Statement::Extern { .. }
+            | Statement::Trait { .. }
+            | Statement::Comment { .. }
+            | Statement::Import(_)
+            | Statement::Line(_) => {}
+        }
+    }
+
+    /// Lints the body of a function, or of a test when `identifier` is `None`, in a scope
+    /// that sees only the top-level variables.
+    fn function(&mut self, identifier: Option<&String>, params: &[Parameter], body: &[Statement]) {
+        // Imported functions are linted for what they use, but not for their own names
+        let imported = identifier.is_some_and(|name| exempt(name));
+        let enclosing_scopes = self.scopes.split_off(1);
+        let enclosing = mem::replace(&mut self.function, identifier.cloned());
+        let bindings = params
+            .iter()
+            .map(|param| self.binding(&param.name, false))
+            .collect();
+        if imported {
+            let warnings = self.warnings.len();
+            self.block(body, bindings);
+            self.warnings.truncate(warnings);
+        } else {
+            self.block(body, bindings);
+        }
+        self.function = enclosing;
+        self.scopes.extend(enclosing_scopes);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Number(_)
+            | ExprKind::Float(_)
+            | ExprKind::String(_)
+            | ExprKind::Boolean(_)
+            | ExprKind::Null => {}
+            ExprKind::Identifier(name) => self.use_name(name),
+            ExprKind::BinaryOp(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::UnaryOp(_, operand) => self.expr(operand),
+            ExprKind::Call { callee, args } => {
+                self.expr(callee);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::Lambda { params, body } => {
+                let bindings = params
+                    .iter()
+                    .map(|param| self.binding(&param.name, false))
+                    .collect();
+                self.scopes.push(bindings);
+                self.expr(body);
+                self.pop_scope();
+            }
+            ExprKind::MethodCall { receiver, args, .. } => {
+                self.expr(receiver);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::Array(elements) => elements.iter().for_each(|element| self.expr(element)),
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            ExprKind::Range { start, end, .. } => {
+                self.expr(start);
+                self.expr(end);
+            }
+            ExprKind::Index { target, index } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            ExprKind::Cast { value, .. } => self.expr(value),
+            ExprKind::Async(value) | ExprKind::Await(value) => self.expr(value),
+            ExprKind::Spawn(body) => self.block(body, Vec::new()),
+        }
+    }
+
+    /// Marks the innermost variable called `name` as read, or the function called `name`
+    /// as called when no variable is.
+    fn use_name(&mut self, name: &str) {
+        let binding = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.iter_mut().rev().find(|binding| binding.name == name));
+        if let Some(binding) = binding {
+            binding.used = true;
+        } else if self.function.as_deref() != Some(name) {
+            if let Some(function) = self.functions.get_mut(name) {
+                function.called = true;
+            }
+        }
+    }
+
+    /// Declares a variable on the current line, reporting it when it hides another.
+    fn declare(&mut self, name: &str, reported: bool) {
+        self.shadow_check(name);
+        let binding = self.binding(name, reported);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(binding);
+        }
+    }
+
+    /// Reports a variable called `name` about to be declared when one is already visible.
+    fn shadow_check(&mut self, name: &str) {
+        if exempt(name) {
+            return;
+        }
+        let Some(earlier) = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.iter().rev().find(|binding| binding.name == name))
+        else {
+            return;
+        };
+        let message = if earlier.span.line == 0 {
+            format!(
+                "Variable '{}' shadows an earlier variable of the same name",
+                name
+            )
+        } else {
+            format!(
+                "Variable '{}' shadows the variable declared on line {}",
+                name, earlier.span.line
+            )
+        };
+        self.warn(Lint::ShadowedVariable, message, self.span);
+    }
+
+    /// Creates a binding for `name` on the current line.
+    fn binding(&self, name: &str, reported: bool) -> Binding {
+        Binding {
+            name: name.to_string(),
+            span: self.span,
+            used: false,
+            reported,
+        }
+    }
+
+    /// Ends the innermost scope, reporting the variables in it that were never read.
+    fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for binding in scope {
+            if binding.reported && !binding.used && !exempt(&binding.name) {
+                self.warn(
+                    Lint::UnusedVariable,
+                    format!("Variable '{}' is never used", binding.name),
+                    binding.span,
+                );
+            }
+        }
+    }
+
+    fn warn(&mut self, lint: Lint, message: String, span: Span) {
+        self.warnings.push(Warning {
+            lint,
+            message,
+            span: located(span),
+        });
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns true for names that are never reported: those starting with `_`, and the
+/// qualified names of what imported files declare.
+fn exempt(name: &str) -> bool {
+    name.starts_with('_') || name.contains('.')
+}
+
+/// Returns `span`, or `None` for the empty span of a program parsed without spans.
+fn located(span: Span) -> Option<Span> {
+    (span.line != 0).then_some(span)
+}
@@ -0,0 +1,4 @@
+pub mod analyzer;
+pub mod infer;
+pub mod lints;
+pub mod types;
@@ -0,0 +1,154 @@
+use crate::venti_parser::ast::VarType;
+use std::fmt;
+
+/// The static type of a Venti value, as tracked by the semantic analyzer.
+///
+/// Types follow the values codegen produces: comparisons yield `Int`, strings and
+/// `nothing` share one pointer representation, and arrays have a fixed length.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    String,
+    Nothing,
+    Array(Box<Type>, usize),
+    Map,
+    /// A heap block of integer slots made by `alloc`.
+    Buffer,
+    Range,
+    Lambda(usize),
+    /// A running async function call, which `await` turns into its integer result.
+    Task,
+    /// A queue that tasks pass values to each other through, made by `channel`, with the
+    /// type of the values, which the first `send` on it decides.
+    Channel(Box<Type>),
+    /// A lazy sequence of integers made by `map` or `filter`.
+    Iterator,
+    /// A growable array of integers made by `vec`.
+    Vector,
+    /// The strings `split` and `args` give, a vector that can be read but not changed.
+    StringVector,
+    /// A value read by `json_parse`, whose kind is only known when the program runs.
+    Json,
+    /// A value of any type implementing the named trait, which only a parameter can have.
+    Trait(String),
+    /// A value whose type is only known once it is compiled, such as a closure's result.
+    Unknown,
+}
+
+impl Type {
+    /// Maps an optional type annotation to its type; unannotated values are integers.
+    pub fn from_annotation(var_type: Option<&VarType>) -> Self {
+        match var_type {
+            Some(VarType::Float) => Type::Float,
+            Some(VarType::Bool) => Type::Bool,
+            Some(VarType::String) => Type::String,
+            Some(VarType::Trait(name)) => Type::Trait(name.clone()),
+            Some(VarType::Vec) => Type::Vector,
+            Some(VarType::Int) | None => Type::Int,
+        }
+    }
+
+    /// Returns true if a value of type `other` can be stored where `self` is expected.
+    ///
+    /// `Unknown` is compatible with everything, and `nothing` can stand in for a string.
+    pub fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (Type::String | Type::Nothing, Type::String | Type::Nothing) => true,
+            (Type::Array(expected, expected_len), Type::Array(found, found_len)) => {
+                expected_len == found_len && expected.accepts(found)
+            }
+            (Type::Channel(expected), Type::Channel(found)) => expected.accepts(found),
+            (expected, found) => expected == found,
+        }
+    }
+
+    /// Returns true for integers and floats.
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Float | Type::Unknown)
+    }
+
+    /// Returns true for the values stored as integers: `int` and `bool`.
+    pub fn is_integral(&self) -> bool {
+        matches!(self, Type::Int | Type::Bool | Type::Unknown)
+    }
+
+    /// Returns true for the pointer values `??` can test: strings and `nothing`.
+    pub fn is_nullable(&self) -> bool {
+        matches!(self, Type::String | Type::Nothing)
+    }
+
+    /// Returns true if `map`, `filter`, `reduce` and `collect` can take their values from
+    /// this type: ranges, buffers, vectors, iterators and arrays of integers.
+    pub fn is_iterable(&self) -> bool {
+        match self {
+            Type::Array(element, _) => matches!(**element, Type::Int | Type::Unknown),
+            Type::Range | Type::Buffer | Type::Vector | Type::Iterator | Type::Unknown => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if `json_stringify` can write this type as JSON.
+    pub fn is_json(&self) -> bool {
+        match self {
+            Type::Array(element, _) => element.is_json(),
+            Type::Int | Type::Float | Type::Bool | Type::String | Type::Nothing | Type::Map => true,
+            Type::Json | Type::Unknown => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if `printventi` knows how to display this type.
+    pub fn is_printable(&self) -> bool {
+        match self {
+            Type::Array(element, _) => element.is_printable(),
+            Type::Map
+            | Type::Buffer
+            | Type::Lambda(_)
+            | Type::Task
+            | Type::Channel(_)
+            | Type::Iterator
+            | Type::Vector
+            | Type::StringVector
+            | Type::Trait(_) => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Nothing => write!(f, "nothing"),
+            Type::Array(element, len) => write!(f, "[{}; {}]", element, len),
+            Type::Map => write!(f, "map"),
+            Type::Buffer => write!(f, "buffer"),
+            Type::Range => write!(f, "range"),
+            Type::Lambda(arity) => write!(f, "lambda of {} arguments", arity),
+            Type::Task => write!(f, "task"),
+            Type::Channel(element) if **element == Type::Unknown => write!(f, "channel"),
+            Type::Channel(element) => write!(f, "channel of {}", element),
+            Type::Iterator => write!(f, "iterator"),
+            Type::Vector => write!(f, "vec"),
+            Type::StringVector => write!(f, "vec of string"),
+            Type::Json => write!(f, "json"),
+            Type::Trait(name) => write!(f, "{}", name),
+            Type::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The signature of a named function.
+///
+/// Functions return integers and async functions return tasks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSignature {
+    pub params: Vec<Type>,
+    pub returns: Type,
+}
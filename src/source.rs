@@ -0,0 +1,244 @@
+use crate::errors::VentiError;
+use crate::project::manifest::Manifest;
+use crate::venti_lexer::lexer::Lexer;
+use crate::venti_lexer::span::Span;
+use clap::ArgMatches;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/*
+Every file a program is read from, its imports included, goes into one `SourceMap`,
+which numbers the files in the order they were loaded. The lexer takes its input from the
+map, so every span carries the number of its file, and the renderer of diagnostics finds
+the file, line and column of a span in the map again.
+
+The files are also laid end to end in one space of global offsets, with a byte between
+each, so a single number says where in the whole program a byte is:
+
+    main.venti    0..120
+    utils.venti   121..300
+
+Files with the same text, such as repl entries typed twice, share it.
+*/
+
+/// Where the program being compiled comes from.
+///
+/// Programs read from stdin or given with `-e` have no file, so they are named as if they
+/// were `stdin.venti` and `eval.venti`. The name picks the module name and the default
+/// output path, and is the file debug info points to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    File(PathBuf),
+    /// The program is read from stdin, selected with `-` as the input file.
+    Stdin,
+    /// The program is the text passed to `-e`.
+    Inline(String),
+}
+
+impl Source {
+    /// Picks the source named by a subcommand's `INPUT` and `-e` arguments, or the entry
+    /// point of `manifest` when neither is given.
+    pub fn from_args(args: &ArgMatches, manifest: Option<&Manifest>) -> Self {
+        if let Some(code) = args.get_one::<String>("eval") {
+            return Source::Inline(code.clone());
+        }
+        match (
+            args.get_one::<String>("INPUT").map(String::as_str),
+            manifest,
+        ) {
+            (Some(path), _) if path != "-" => Source::File(PathBuf::from(path)),
+            (None, Some(manifest)) => Source::File(manifest.entry_path()),
+            _ => Source::Stdin,
+        }
+    }
+
+    /// The path the program is known by.
+    pub fn path(&self) -> &Path {
+        match self {
+            Source::File(path) => path,
+            Source::Stdin => Path::new("stdin.venti"),
+            Source::Inline(_) => Path::new("eval.venti"),
+        }
+    }
+
+    /// Reads the text of the program.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String, VentiError>` - The source code, or an IO error if it cannot be read.
+    pub fn read(&self) -> Result<String, VentiError> {
+        match self {
+            Source::File(path) => fs::read_to_string(path).map_err(|e| {
+                VentiError::IOError(format!("Could not read {}: {}", path.display(), e), None)
+            }),
+            Source::Stdin => {
+                let mut text = String::new();
+                io::stdin().read_to_string(&mut text).map_err(|e| {
+                    VentiError::IOError(format!("Could not read stdin: {}", e), None)
+                })?;
+                Ok(text)
+            }
+            Source::Inline(code) => Ok(code.clone()),
+        }
+    }
+}
+
+/// Where a byte of source is, as people count: the file it is in, and the 1-based line and
+/// column, in characters, it is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub file: u32,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// The text of one file in a `SourceMap`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    /// The text, shared with every other file in the map with the same contents.
+    pub text: Arc<str>,
+    /// The line the text starts on, which is not 1 for a repl entry.
+    pub first_line: u32,
+    /// The global offset of the first byte of the text.
+    pub start: usize,
+    // The offset in the text of the start of each line
+    lines: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(path: &Path, text: Arc<str>, first_line: u32, start: usize) -> Self {
+        let lines = iter::once(0)
+            .chain(text.match_indices('\n').map(|(newline, _)| newline + 1))
+            .collect();
+        SourceFile {
+            path: path.to_path_buf(),
+            text,
+            first_line,
+            start,
+            lines,
+        }
+    }
+
+    /// Returns the text of line `line`, numbered as the spans in the file are, if the file
+    /// has that line.
+    pub fn line(&self, line: u32) -> Option<&str> {
+        let index = line.checked_sub(self.first_line)? as usize;
+        let start = *self.lines.get(index)?;
+        let end = self
+            .lines
+            .get(index + 1)
+            .map_or(self.text.len(), |next| next - 1);
+        Some(self.text[start..end].trim_end_matches('\r'))
+    }
+
+    /// The global offset just past the end of the text.
+    pub fn end(&self) -> usize {
+        self.start + self.text.len()
+    }
+}
+
+/// The files a program was read from, numbered in the order they were loaded, so an error
+/// can show the source its span points to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+    // The text of every file, held once however many files have it
+    texts: HashSet<Arc<str>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    /// Adds the text of a file, whose lines are numbered from `first_line`. A file added
+    /// again with the same text keeps the number it was given the first time.
+    ///
+    /// # Returns
+    ///
+    /// The number of the file, which its spans carry.
+    pub fn add(&mut self, path: &Path, text: &str, first_line: u32) -> u32 {
+        let added = self.files.iter().position(|file| {
+            file.path == path && file.first_line == first_line && *file.text == *text
+        });
+        if let Some(file) = added {
+            return file as u32;
+        }
+        let text = match self.texts.get(text) {
+            Some(text) => Arc::clone(text),
+            None => {
+                let text = Arc::<str>::from(text);
+                self.texts.insert(Arc::clone(&text));
+                text
+            }
+        };
+        // A byte is left between files, so the end of one is not the start of the next
+        let start = self.files.last().map_or(0, |file| file.end() + 1);
+        self.files
+            .push(SourceFile::new(path, text, first_line, start));
+        (self.files.len() - 1) as u32
+    }
+
+    /// The file numbered `file`, if it was added.
+    pub fn get(&self, file: u32) -> Option<&SourceFile> {
+        self.files.get(file as usize)
+    }
+
+    /// Every file added, in the order they were loaded.
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    /// Lexes the file numbered `file`, which must have been added, so its spans carry its
+    /// number and its lines are numbered as the map numbers them.
+    pub fn lexer(&self, file: u32) -> Lexer<'_> {
+        let source = &self.files[file as usize];
+        Lexer::new(&source.text)
+            .with_first_line(source.first_line)
+            .with_file(file)
+    }
+
+    /// The global offset of the start of `span`, which no other file's spans share.
+    pub fn offset(&self, span: Span) -> Option<usize> {
+        Some(self.get(span.file)?.start + span.start)
+    }
+
+    /// Finds the file the global `offset` is in and where in it.
+    ///
+    /// # Returns
+    ///
+    /// The location, or `None` if no file holds the offset.
+    pub fn lookup(&self, offset: usize) -> Option<Location> {
+        let file = self
+            .files
+            .partition_point(|file| file.start <= offset)
+            .checked_sub(1)?;
+        self.locate(file as u32, offset - self.files[file].start)
+    }
+
+    /// Finds where `span` starts, from its offset rather than the line and column it carries.
+    pub fn location(&self, span: Span) -> Option<Location> {
+        self.locate(span.file, span.start)
+    }
+
+    /// Finds the line and column of byte `offset` of the file numbered `file`.
+    fn locate(&self, file: u32, offset: usize) -> Option<Location> {
+        let source = self.get(file)?;
+        if offset > source.text.len() {
+            return None;
+        }
+        let line = source.lines.partition_point(|&start| start <= offset) - 1;
+        // An offset inside a character has no column
+        let column = source.text.get(source.lines[line]..offset)?.chars().count();
+        Some(Location {
+            file,
+            line: source.first_line + line as u32,
+            column: column as u32 + 1,
+        })
+    }
+}
@@ -0,0 +1,93 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+/*
+Diagnostics are colored the way rustc colors them: the first line of an error in red, of
+a warning in yellow, notes such as `= help:` in cyan and the gutter with the line numbers
+in blue, so each message stands out in a long compile log.
+
+Colors are ANSI escape codes. With `--color auto`, the default, they are used only when
+stderr is a terminal and the NO_COLOR environment variable is not set; `--color always`
+uses them even when output is piped, for CI logs that render them, and `--color never`
+turns them off.
+*/
+
+/// When diagnostics are colored, chosen with `--color`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Maps a `--color` value to its choice, or `None` for an unknown name.
+    pub fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    /// Whether text written to stderr should be colored.
+    pub fn for_stderr(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+/// What a piece of diagnostic text is, which picks its color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Error,
+    Warning,
+    Note,
+    /// The `-->`, `|` and line numbers down the left of a snippet.
+    Gutter,
+    /// The message itself, in bold.
+    Emphasis,
+}
+
+impl Style {
+    /// The SGR parameters of the style's escape code.
+    fn sgr(self) -> &'static str {
+        match self {
+            Style::Error => "1;31",
+            Style::Warning => "1;33",
+            Style::Note => "1;36",
+            Style::Gutter => "1;34",
+            Style::Emphasis => "1",
+        }
+    }
+}
+
+/// Wraps text in the escape codes of a style, or leaves it as it is when color is off.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Painter {
+    enabled: bool,
+}
+
+impl Painter {
+    /// Creates a `Painter` that colors text when `enabled` is true.
+    pub fn new(enabled: bool) -> Self {
+        Painter { enabled }
+    }
+
+    /// A `Painter` that never colors text.
+    pub fn plain() -> Self {
+        Painter::new(false)
+    }
+
+    /// Returns `text` in `style`.
+    pub fn paint(&self, style: Style, text: &str) -> String {
+        if !self.enabled || text.is_empty() {
+            return text.to_string();
+        }
+        format!("\x1b[{}m{}\x1b[0m", style.sgr(), text)
+    }
+}
@@ -0,0 +1,260 @@
+use crate::venti_lexer::span::Span;
+use std::collections::HashMap;
+
+/*
+A `SymbolTable` records the names a program defines, where it defines them, which
+definition each use of a name refers to, and what a pass knows about each definition:
+
+    let mut table = SymbolTable::new();
+    let count = table.define(ScopeId::ROOT, "count", DefKind::Variable, span, Type::Int);
+    let body = table.push_scope(ScopeId::ROOT);
+    table.lookup(body, "count")         // Some(count)
+    table.record_use(use_span, count);
+    table.definition_at(file, offset)   // the definition of the name at a position
+
+Names are interned, so a scope maps small numbers rather than strings. Scopes form a tree
+whose root holds the functions and top-level variables. A block, lambda or loop body
+hangs off the scope it is written in, so a name is looked up from the innermost scope
+outwards, while a function body hangs off the root, since a function sees only the
+top-level names wherever it is defined. Scopes are never removed: once a pass has walked
+a program, the table holds every scope of it, which is what lets an editor ask about any
+position afterwards.
+
+What a pass knows about a definition is the table's type parameter. The analyzer keeps the
+type of each variable, and hands its table on to codegen, which declares each top-level
+variable with the type found there.
+*/
+
+/// An interned name, by its position in the table's list of names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Name(pub u32);
+
+/// A scope of the table, by its position in the table's list of scopes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScopeId(pub u32);
+
+impl ScopeId {
+    /// The scope of the functions and top-level variables.
+    pub const ROOT: ScopeId = ScopeId(0);
+}
+
+/// A definition of the table, by the order it was made in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DefId(pub u32);
+
+/// What a name is defined as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    Variable,
+    Parameter,
+    /// A function the program defines, or a C or host function it declares.
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition<T> {
+    pub name: Name,
+    pub kind: DefKind,
+    /// The span of the statement that defines the name, which is empty for definitions
+    /// made outside the program, such as host functions.
+    pub span: Span,
+    pub scope: ScopeId,
+    pub info: T,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Scope {
+    parent: Option<ScopeId>,
+    bindings: HashMap<Name, DefId>,
+}
+
+/// The names of a program, the scopes they are defined in and where they are used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolTable<T> {
+    names: Vec<String>,
+    interned: HashMap<String, Name>,
+    scopes: Vec<Scope>,
+    definitions: Vec<Definition<T>>,
+    // Each use of a name, in the order they were recorded
+    uses: Vec<(Span, DefId)>,
+}
+
+impl<T> SymbolTable<T> {
+    /// Creates a table with only the root scope.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `SymbolTable`.
+    pub fn new() -> Self {
+        SymbolTable {
+            names: Vec::new(),
+            interned: HashMap::new(),
+            scopes: vec![Scope {
+                parent: None,
+                bindings: HashMap::new(),
+            }],
+            definitions: Vec::new(),
+            uses: Vec::new(),
+        }
+    }
+
+    /// Interns `name`, which gets the same number each time.
+    pub fn intern(&mut self, name: &str) -> Name {
+        if let Some(&interned) = self.interned.get(name) {
+            return interned;
+        }
+        let interned = Name(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.interned.insert(name.to_string(), interned);
+        interned
+    }
+
+    /// The text of an interned name.
+    pub fn name(&self, name: Name) -> &str {
+        &self.names[name.0 as usize]
+    }
+
+    /// Adds an empty scope inside `parent`.
+    ///
+    /// # Returns
+    ///
+    /// The new scope.
+    pub fn push_scope(&mut self, parent: ScopeId) -> ScopeId {
+        self.scopes.push(Scope {
+            parent: Some(parent),
+            bindings: HashMap::new(),
+        });
+        ScopeId(self.scopes.len() as u32 - 1)
+    }
+
+    /// The scope `scope` is inside, or `None` for the root.
+    pub fn parent(&self, scope: ScopeId) -> Option<ScopeId> {
+        self.scopes[scope.0 as usize].parent
+    }
+
+    /// Defines `name` in `scope`, shadowing any earlier definition of it there.
+    ///
+    /// # Arguments
+    ///
+    /// * `scope` - The scope the name is defined in.
+    /// * `name` - The name being defined.
+    /// * `kind` - What the name is defined as.
+    /// * `span` - The span of the statement that defines it.
+    /// * `info` - What the pass knows about the definition.
+    ///
+    /// # Returns
+    ///
+    /// The new definition.
+    pub fn define(
+        &mut self,
+        scope: ScopeId,
+        name: &str,
+        kind: DefKind,
+        span: Span,
+        info: T,
+    ) -> DefId {
+        let name = self.intern(name);
+        let id = DefId(self.definitions.len() as u32);
+        self.definitions.push(Definition {
+            name,
+            kind,
+            span,
+            scope,
+            info,
+        });
+        self.scopes[scope.0 as usize].bindings.insert(name, id);
+        id
+    }
+
+    /// Finds the definition `name` refers to in `scope`: the one in the innermost scope
+    /// from `scope` out to the root that defines it.
+    pub fn lookup(&self, scope: ScopeId, name: &str) -> Option<DefId> {
+        let name = *self.interned.get(name)?;
+        let mut scope = Some(scope);
+        while let Some(id) = scope {
+            let found = &self.scopes[id.0 as usize];
+            if let Some(&definition) = found.bindings.get(&name) {
+                return Some(definition);
+            }
+            scope = found.parent;
+        }
+        None
+    }
+
+    /// Finds the definition of `name` in `scope` itself, ignoring the scopes around it.
+    pub fn lookup_in(&self, scope: ScopeId, name: &str) -> Option<DefId> {
+        let name = self.interned.get(name)?;
+        self.scopes[scope.0 as usize].bindings.get(name).copied()
+    }
+
+    /// The definition `id`.
+    pub fn definition(&self, id: DefId) -> &Definition<T> {
+        &self.definitions[id.0 as usize]
+    }
+
+    /// The definition `id`, to change what the pass knows about it.
+    pub fn definition_mut(&mut self, id: DefId) -> &mut Definition<T> {
+        &mut self.definitions[id.0 as usize]
+    }
+
+    /// Every definition, in the order they were made.
+    pub fn definitions(&self) -> impl Iterator<Item = (DefId, &Definition<T>)> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .map(|(id, definition)| (DefId(id as u32), definition))
+    }
+
+    /// Records that the name at `span` refers to `definition`.
+    pub fn record_use(&mut self, span: Span, definition: DefId) {
+        self.uses.push((span, definition));
+    }
+
+    /// The spans of the uses of `definition`, in the order they were recorded.
+    pub fn uses(&self, definition: DefId) -> impl Iterator<Item = Span> + '_ {
+        self.uses
+            .iter()
+            .filter(move |(_, used)| *used == definition)
+            .map(|(span, _)| *span)
+    }
+
+    /// Finds the definition the name used at exactly `span` refers to.
+    pub fn resolve(&self, span: Span) -> Option<DefId> {
+        self.uses
+            .iter()
+            .rev()
+            .find(|(used, _)| *used == span)
+            .map(|(_, definition)| *definition)
+    }
+
+    /// Finds the definition of the name at a position, for go-to-definition: the one the
+    /// innermost use around the position refers to, or else the innermost definition
+    /// around it.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The number of the file in the source map.
+    /// * `offset` - The byte offset of the position in the file.
+    pub fn definition_at(&self, file: u32, offset: usize) -> Option<DefId> {
+        let contains = |span: &Span| span.file == file && span.start <= offset && offset < span.end;
+        let width = |span: &Span| span.end - span.start;
+        let used = self
+            .uses
+            .iter()
+            .filter(|(span, _)| contains(span))
+            .min_by_key(|(span, _)| width(span))
+            .map(|(_, definition)| *definition);
+        used.or_else(|| {
+            self.definitions()
+                .filter(|(_, definition)| contains(&definition.span))
+                .min_by_key(|(_, definition)| width(&definition.span))
+                .map(|(id, _)| id)
+        })
+    }
+}
+
+impl<T> Default for SymbolTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,89 @@
+use inkwell::context::Context;
+use inkwell::OptimizationLevel;
+use venti::codegen::backend;
+use venti::codegen::codegen::CodeGen;
+use venti::errors::VentiError;
+use venti::semantic::types::Type;
+use venti::symbols::SymbolTable;
+use venti::venti_parser::ast::Statement;
+
+/*
+`venti test` runs the `test_venti` blocks of a checked program with the JIT, one at a time
+and in the order they are written:
+
+    fn_venti add(a, b) { return_venti a + b; }
+
+    test_venti "adds small numbers" {
+        assert_venti add(1, 2) == 3;
+    }
+
+The program's functions and top-level variables are compiled first, as one entry of a JIT
+session like the repl's; its other top-level statements do not run. Each test then runs
+as an entry of its own, so the tests see the same top-level variables, including whatever
+earlier tests stored in them. A failing `assert_venti` prints its line and lets the test
+carry on, and a test fails if any of its assertions did. A runtime error the test does not
+catch stops it and fails it, and the tests after it still run.
+*/
+
+/// The exit code when at least one test fails.
+const EXIT_FAILED: i32 = 1;
+
+/// Runs the tests of a checked program and prints a line for each, then a summary.
+///
+/// # Arguments
+///
+/// * `statements` - The checked program.
+/// * `symbols` - The names the analyzer resolved in it.
+///
+/// # Returns
+///
+/// * `Result<i32, VentiError>` - 0 if every test passed, `EXIT_FAILED` otherwise, or the
+///   error that kept the tests from being compiled.
+pub fn run(statements: Vec<Statement>, symbols: SymbolTable<Type>) -> Result<i32, VentiError> {
+    let mut setup = Vec::new();
+    let mut tests = Vec::new();
+    for statement in statements {
+        match statement {
+            Statement::Test { name, body } => tests.push((name, body)),
+            Statement::Function { .. }
+            | Statement::AsyncFunction { .. }
+            | Statement::VariableDeclaration { .. }
+            | Statement::Trait { .. }
+            | Statement::Impl { .. }
+            | Statement::Line(_) => setup.push(statement),
+            _ => {}
+        }
+    }
+
+    backend::initialize_host()?;
+    let context = Context::create();
+    let mut codegen = CodeGen::new(&context, OptimizationLevel::None)
+        .with_module_name("test")
+        .with_symbols(symbols);
+    codegen.run_entry(setup)?;
+
+    let mut failed = 0;
+    let count = tests.len();
+    for (name, body) in tests {
+        let failures = match codegen.run_test(body) {
+            Ok(failures) => failures,
+            Err(VentiError::RuntimeError(message, _)) => {
+                println!("test {} ... FAILED ({})", name, message);
+                failed += 1;
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        match failures {
+            0 => println!("test {} ... ok", name),
+            1 => println!("test {} ... FAILED (1 assertion)", name),
+            failures => println!("test {} ... FAILED ({} assertions)", name, failures),
+        }
+        if failures > 0 {
+            failed += 1;
+        }
+    }
+    println!();
+    println!("{} passed, {} failed", count - failed, failed);
+    Ok(if failed > 0 { EXIT_FAILED } else { 0 })
+}
@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+/*
+`--time-passes` reports how long each pass of the compiler took once the command is done,
+on stderr so it does not mix with what a program prints:
+
+    pass                   time
+    parse               3.412ms   21.0%
+    resolve imports     0.051ms    0.3%
+    check types         1.208ms    7.4%
+    ...
+    total              16.240ms
+
+The lexer runs as the parser pulls tokens from it, so lexing is timed as part of parsing.
+A pass that runs more than once, such as codegen for each test, adds up to one line. The
+total is the sum of the passes, which leaves out reading files and starting up.
+*/
+
+/// How long each pass took, in the order the passes first ran.
+#[derive(Debug, Default)]
+pub struct PassTimes {
+    passes: Vec<(&'static str, Duration)>,
+}
+
+impl PassTimes {
+    /// Creates an empty `PassTimes`.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `PassTimes`.
+    pub fn new() -> Self {
+        PassTimes::default()
+    }
+
+    /// Runs `pass` and adds the time it took to the pass named `name`.
+    ///
+    /// # Returns
+    ///
+    /// What `pass` returns.
+    pub fn time<T>(&mut self, name: &'static str, pass: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = pass();
+        self.add(name, start.elapsed());
+        result
+    }
+
+    /// Adds `elapsed` to the time of the pass named `name`.
+    pub fn add(&mut self, name: &'static str, elapsed: Duration) {
+        match self.passes.iter_mut().find(|(pass, _)| *pass == name) {
+            Some((_, total)) => *total += elapsed,
+            None => self.passes.push((name, elapsed)),
+        }
+    }
+
+    /// Returns each pass with the time it took, in the order the passes first ran.
+    pub fn passes(&self) -> &[(&'static str, Duration)] {
+        &self.passes
+    }
+
+    /// Renders the table `--time-passes` prints, or nothing if no pass ran.
+    pub fn render(&self) -> String {
+        if self.passes.is_empty() {
+            return String::new();
+        }
+        let total = self.passes.iter().map(|(_, time)| *time).sum::<Duration>();
+        let width = self
+            .passes
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0)
+            .max("total".len());
+        let millis = |time: Duration| format!("{:.3}ms", time.as_secs_f64() * 1000.0);
+
+        let mut table = format!("{:<width$} {:>12}\n", "pass", "time");
+        for (name, time) in &self.passes {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                time.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            table.push_str(&format!(
+                "{:<width$} {:>12} {:>7.1}%\n",
+                name,
+                millis(*time),
+                share
+            ));
+        }
+        table.push_str(&format!("{:<width$} {:>12}\n", "total", millis(total)));
+        table
+    }
+}
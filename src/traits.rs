@@ -0,0 +1,139 @@
+use crate::venti_parser::ast::{Statement, TraitMethod};
+use std::collections::HashMap;
+
+/*
+A trait names the methods a type provides, and an `impl_venti` block provides them for one
+of the built-in types:
+
+    trait_venti Shape { fn area(self); }
+    impl_venti Shape for float { fn_venti area(self) { return self as int * 2; } }
+
+Every method is an ordinary function once the checker has seen the program: `flatten`
+lifts the methods out of their blocks under a name made of the trait, the type and the
+method, `Shape.float.area`, which has a `.` in it so no function of the program can clash
+with it. A call whose receiver has a known type, `1.5.area()`, is then a call of that
+function, which is all a backend needs to resolve it statically.
+
+A parameter annotated with a trait, `fn_venti report(s: Shape)`, takes a value of any type
+implementing it, so which function `s.area()` calls is only known when the program runs.
+The backends that compile such calls pass the value with a table of the functions that
+implement the trait for its type, in the order the trait declares its methods, and call
+through the table: `Traits` gives each method its slot in it.
+*/
+
+/// The name of the function implementing `method` of `trait_name` for `type_name`.
+///
+/// # Arguments
+///
+/// * `trait_name` - The trait the method belongs to.
+/// * `type_name` - The type implementing it, as written in the source.
+/// * `method` - The name of the method.
+pub fn method_function(trait_name: &str, type_name: &str, method: &str) -> String {
+    format!("{}.{}.{}", trait_name, type_name, method)
+}
+
+/// Replaces each `impl_venti` block of a program with its methods, as functions named by
+/// `method_function`, and keeps every other statement as it is.
+///
+/// # Returns
+///
+/// The top-level statements with the implementations lifted out.
+pub fn flatten(statements: Vec<Statement>) -> Vec<Statement> {
+    let mut flat = Vec::with_capacity(statements.len());
+    for statement in statements {
+        let Statement::Impl {
+            trait_name,
+            target,
+            methods,
+        } = statement
+        else {
+            flat.push(statement);
+            continue;
+        };
+        for method in methods {
+            match method {
+                Statement::Function {
+                    identifier,
+                    params,
+                    returns,
+                    body,
+                    inline,
+                } => flat.push(Statement::Function {
+                    identifier: method_function(&trait_name, target.name(), &identifier),
+                    params,
+                    returns,
+                    body,
+                    inline,
+                }),
+                Statement::Line(span) => flat.push(Statement::Line(span)),
+                _ => {}
+            }
+        }
+    }
+    flat
+}
+
+/// The traits of a program and the order of their methods.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Traits {
+    methods: HashMap<String, Vec<TraitMethod>>,
+}
+
+impl Traits {
+    /// Creates an empty table.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Traits`.
+    pub fn new() -> Self {
+        Traits::default()
+    }
+
+    /// Records a `Statement::Trait`, and ignores any other statement.
+    pub fn declare(&mut self, statement: &Statement) {
+        if let Statement::Trait {
+            identifier,
+            methods,
+        } = statement
+        {
+            self.methods.insert(identifier.clone(), methods.clone());
+        }
+    }
+
+    /// The methods of `trait_name` in the order it declares them, which is the order of
+    /// the table a value of the trait is passed with.
+    pub fn methods(&self, trait_name: &str) -> Option<&[TraitMethod]> {
+        self.methods.get(trait_name).map(Vec::as_slice)
+    }
+
+    /// The position of `method` in the table of `trait_name`.
+    pub fn slot(&self, trait_name: &str, method: &str) -> Option<usize> {
+        self.methods(trait_name)?
+            .iter()
+            .position(|declared| declared.name == method)
+    }
+
+    /// Finds the function a call of `method` on a value of `type_name` calls.
+    ///
+    /// # Arguments
+    ///
+    /// * `type_name` - The type of the receiver, as written in the source.
+    /// * `method` - The name of the method called.
+    /// * `is_defined` - Whether the program defines a function of a given name.
+    ///
+    /// # Returns
+    ///
+    /// The name of the function, or `None` if no trait the type implements has the method.
+    pub fn resolve(
+        &self,
+        type_name: &str,
+        method: &str,
+        is_defined: impl Fn(&str) -> bool,
+    ) -> Option<String> {
+        self.methods
+            .iter()
+            .filter(|(_, methods)| methods.iter().any(|declared| declared.name == method))
+            .map(|(trait_name, _)| method_function(trait_name, type_name, method))
+            .find(|function| is_defined(function))
+    }
+}
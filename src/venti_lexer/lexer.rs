@@ -1,22 +1,126 @@
+use crate::codes::ErrorCode;
 use crate::errors::VentiError;
+use crate::venti_lexer::span::Span;
 use crate::venti_lexer::token::Token;
 use logos::Logos;
 
+/// A `#` comment, with the text after the `#`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub span: Span,
+    pub text: String,
+}
+
+/// A token and the span of source it was lexed from.
+pub type SpannedToken = (Token, Span);
+
 pub struct Lexer<'a> {
     lexer: logos::Lexer<'a, Token>,
+    // The file the spans are in
+    file: u32,
+    // The line of the most recent token, the offset lines were counted up to, and the
+    // 0-based column of that offset
+    line: u32,
+    counted: usize,
+    column: u32,
+    // The comments skipped so far, in source order
+    comments: Vec<Comment>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
         Lexer {
             lexer: Token::lexer(input),
+            file: 0,
+            line: 1,
+            counted: 0,
+            column: 0,
+            comments: Vec::new(),
         }
     }
 
-    pub fn next_token(&mut self) -> Option<Result<Token, ()>> {
-        match self.lexer.next() {
-            Some(token) => Some(Ok(token)),
+    /// Numbers the lines of the input from `line` instead of 1, for input that continues
+    /// earlier text, such as a repl entry.
+    pub fn with_first_line(mut self, line: u32) -> Self {
+        self.line = line;
+        self
+    }
+
+    /// Puts the spans of the input in `file`, the number a `SourceMap` gave its text.
+    pub fn with_file(mut self, file: u32) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Returns the source text of the token most recently returned by `next_token`.
+    pub fn slice(&self) -> &'a str {
+        self.lexer.slice()
+    }
+
+    /// Hands over the comments skipped so far, which the formatter puts back.
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
+    }
+
+    /// Returns the 1-based source line of the token most recently returned by `next_token`.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Returns the span of the token most recently returned by `next_token`.
+    pub fn span(&self) -> Span {
+        let range = self.lexer.span();
+        Span {
+            file: self.file,
+            start: range.start,
+            end: range.end,
+            line: self.line,
+            column: self.column + 1,
+        }
+    }
+
+    /// Returns the next token, skipping comments.
+    pub fn next_token(&mut self) -> Option<Result<Token, VentiError>> {
+        loop {
+            let token = self.lexer.next();
+            let start = self.lexer.span().start;
+            let skipped = &self.lexer.source()[self.counted..start];
+            self.line += skipped.matches('\n').count() as u32;
+            // Columns are counted on from the last token, so a long line is only read once
+            match skipped.rfind('\n') {
+                Some(newline) => self.column = skipped[newline + 1..].chars().count() as u32,
+                None => self.column += skipped.chars().count() as u32,
+            }
+            self.counted = start;
+            match token {
+                Some(Ok(Token::Comment(text))) => self.comments.push(Comment {
+                    span: self.span(),
+                    text,
+                }),
+                token => return self.finish(token),
+            }
+        }
+    }
+
+    fn finish(&self, token: Option<Result<Token, ()>>) -> Option<Result<Token, VentiError>> {
+        match token {
+            Some(Ok(token)) => Some(Ok(token)),
+            Some(Err(())) => Some(Err(VentiError::SyntaxError(
+                format!("Unexpected character '{}'", self.lexer.slice()),
+                Some(self.span()),
+            )
+            .with_code(ErrorCode::UnexpectedCharacter))),
             None => None,
         }
     }
 }
+
+/// Lexes lazily, one spanned token at a time, skipping comments like `next_token`.
+impl Iterator for Lexer<'_> {
+    type Item = Result<SpannedToken, VentiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.next_token()?;
+        Some(token.map(|token| (token, self.span())))
+    }
+}
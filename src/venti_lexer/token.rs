@@ -1,34 +1,78 @@
 use logos::Logos;
 
-#[derive(Logos, Debug, PartialEq)]
+#[derive(Logos, Debug, Clone, PartialEq)]
 pub enum Token {
     #[regex(r"[ \t\n\f]+", logos::skip)]
     //#[error]
     Error,
 
+    /// A `#` comment running to the end of the line, which `Lexer` sets aside.
+    #[regex(r"#[^\n]*", |lex| lex.slice()[1..].trim_end().to_string())]
+    Comment(String),
+
     #[token("venti")]
     Venti,
 
+    #[token("nothing")]
+    Nothing,
+
     #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_string())]
     Identifier(String),
 
-    #[regex(r#""[^"]*""#, |lex| lex.slice().to_string())]
+    #[regex(r#""[^"]*""#, |lex| lex.slice()[1..lex.slice().len() - 1].to_string())]
     StringLiteral(String),
 
-    #[regex(r"[0-9]+", |lex| lex.slice().to_string())]
+    #[regex(r"[0-9]+", |lex| lex.slice().parse::<i64>().ok())]
     NumberLiteral(i64),
 
-    #[regex(r"true|false", |lex| lex.slice().to_string())]
+    #[regex(r"[0-9]+\.[0-9]+", |lex| lex.slice().parse::<f64>().ok())]
+    FloatLiteral(f64),
+
+    #[regex(r"true|false", |lex| lex.slice() == "true")]
     BooleanLiteral(bool),
 
     #[token("+")]
     Plus,
     #[token("-")]
     Minus,
+    #[token("++")]
+    PlusPlus,
+    #[token("--")]
+    MinusMinus,
     #[token("*")]
     Star,
     #[token("/")]
     Slash,
+    #[token("==")]
+    EqualEqual,
+    #[token("!=")]
+    NotEqual,
+    #[token("<")]
+    Less,
+    #[token("<=")]
+    LessEqual,
+    #[token(">")]
+    Greater,
+    #[token(">=")]
+    GreaterEqual,
+    #[token("&&")]
+    AndAnd,
+    #[token("||")]
+    OrOr,
+    #[token("!")]
+    Bang,
+    #[token("|")]
+    Pipe,
+    #[token("&")]
+    Ampersand,
+    #[token("^")]
+    Caret,
+    #[token("<<")]
+    LessLess,
+    #[token(">>")]
+    GreaterGreater,
+    #[token("??")]
+    QuestionQuestion,
     #[token("(")]
     LParen,
     #[token(")")]
@@ -43,24 +87,71 @@ pub enum Token {
     RBracket,
     #[token(",")]
     Comma,
+    #[token(".")]
+    Dot,
+    #[token("..")]
+    DotDot,
+    #[token("..=")]
+    DotDotEqual,
     #[token(";")]
     Semicolon,
+    #[token(":")]
+    Colon,
     #[token("=")]
     Equals,
+    #[token("->")]
+    Arrow,
+    #[token("=>")]
+    FatArrow,
     #[token("if_venti")]
     If,
     #[token("else_venti")]
     Else,
     #[token("for_venti")]
     For,
+    #[token("in")]
+    In,
+    #[token("as")]
+    As,
     #[token("while_venti")]
     While,
+    #[token("match_venti")]
+    Match,
     #[token("printventi")]
     Print,
+    #[token("printventi_inline")]
+    PrintInline,
+    #[token("funcVenti")]
+    #[token("fn_venti")]
+    Func,
+    #[token("return_venti")]
+    Return,
     #[token("async")]
     Async,
+    #[token("inline_venti")]
+    Inline,
+    #[token("noinline_venti")]
+    NoInline,
     #[token("await")]
     Await,
+    #[token("spawn_venti")]
+    Spawn,
+    #[token("extern_venti")]
+    Extern,
+    #[token("import_venti")]
+    Import,
+    #[token("test_venti")]
+    Test,
+    #[token("assert_venti")]
+    Assert,
+    #[token("trait_venti")]
+    Trait,
+    #[token("impl_venti")]
+    Impl,
+    #[token("try_venti")]
+    Try,
+    #[token("catch_venti")]
+    Catch,
 
     #[token("int")]
     Int,
@@ -68,5 +159,6 @@ pub enum Token {
     Float,
     #[token("bool")]
     Bool,
+    #[token("string")]
+    StringType,
 }
-
@@ -0,0 +1,83 @@
+use std::cell::RefCell;
+
+/*
+An `Arena` holds the nodes of a syntax tree, so a node the parser builds costs a slot in a
+chunk rather than an allocation of its own, and the whole tree is freed at once when the
+arena is dropped:
+
+    let arena = Arena::new();
+    let ast = Parser::new(Lexer::new(source), &arena).parse()?;
+
+A node borrows the arena for as long as the tree is used, which is the lifetime every AST
+type carries. Nodes go into the last chunk until it is full, and then into a new chunk
+twice its size. A chunk never grows past the capacity it was made with, so its values are
+never moved, and the references handed out stay valid while the arena lives.
+
+Values are dropped with the arena, in no particular order, and the arena has no `Drop` of
+its own, so nodes that borrow it, as the children of an `Expr` do, may be kept in it.
+*/
+
+/// How many values the first chunk holds.
+const FIRST_CHUNK: usize = 64;
+
+/// Holds values of one type for as long as the arena lives, handing out a reference to
+/// each.
+#[derive(Debug)]
+pub struct Arena<T> {
+    chunks: RefCell<Vec<Vec<T>>>,
+}
+
+impl<T> Arena<T> {
+    /// Creates an empty arena, which allocates nothing until a value is put in it.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Arena`.
+    pub fn new() -> Self {
+        Arena {
+            chunks: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Moves `value` into the arena.
+    ///
+    /// # Returns
+    ///
+    /// The only reference to the value, which lives as long as the arena.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc(&self, value: T) -> &mut T {
+        let mut chunks = self.chunks.borrow_mut();
+        let capacity = match chunks.last() {
+            Some(chunk) if chunk.len() < chunk.capacity() => None,
+            Some(chunk) => Some(chunk.capacity() * 2),
+            None => Some(FIRST_CHUNK),
+        };
+        if let Some(capacity) = capacity {
+            chunks.push(Vec::with_capacity(capacity));
+        }
+        let chunk = chunks.last_mut().expect("a chunk with room was just made");
+        chunk.push(value);
+        let value: *mut T = chunk.last_mut().expect("the value was just pushed");
+        // SAFETY: the chunk had room, so the push did not move its values, and no push
+        // ever will. Pushing further chunks moves their `Vec`s but not the values they
+        // hold, which stay put until the arena is dropped. Each value is handed out once,
+        // so the reference is the only one to it.
+        unsafe { &mut *value }
+    }
+
+    /// The number of values in the arena.
+    pub fn len(&self) -> usize {
+        self.chunks.borrow().iter().map(Vec::len).sum()
+    }
+
+    /// Whether no value has been put in the arena.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
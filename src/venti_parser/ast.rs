@@ -1,39 +1,279 @@
-#[derive(Debug)]
+use crate::venti_lexer::span::Span;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum VarType {
     Int,
     Float,
     Bool,
+    /// Only parameters may be annotated as strings, and only a JSON value can be cast to one.
+    String,
+    /// A parameter annotated with a trait takes a value of any type implementing it.
+    Trait(String),
+    /// A growable array of integers, which parameters and function results can be.
+    Vec,
+}
+
+impl VarType {
+    /// The type as it is written in the source.
+    pub fn name(&self) -> &str {
+        match self {
+            VarType::Int => "int",
+            VarType::Float => "float",
+            VarType::Bool => "bool",
+            VarType::String => "string",
+            VarType::Trait(name) => name,
+            VarType::Vec => "vec",
+        }
+    }
 }
 
+/// An expression, with the span of source it was parsed from. Its children are in the
+/// `Arena` the parser was given, which lives for `'a`.
 #[derive(Debug)]
-pub enum Expr {
+pub struct Expr<'a> {
+    pub kind: ExprKind<'a>,
+    pub span: Span,
+}
+
+impl<'a> Expr<'a> {
+    pub fn new(kind: ExprKind<'a>, span: Span) -> Self {
+        Expr { kind, span }
+    }
+
+    /// Moves the expression out of the tree, leaving `null` with its span in its place,
+    /// for a pass that consumes the nodes its arena holds.
+    pub fn take(&mut self) -> Expr<'a> {
+        let kind = std::mem::replace(&mut self.kind, ExprKind::Null);
+        Expr::new(kind, self.span)
+    }
+}
+
+/// Expressions are equal when their trees are, wherever in the source they came from.
+impl PartialEq for Expr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ExprKind<'a> {
     Number(i64),
+    Float(f64),
     String(String),
     Boolean(bool),
+    Null,
     Identifier(String),
-    BinaryOp(Box<Expr>, BinOp, Box<Expr>),
-    Array(Vec<Expr>),
-    Async(Box<Expr>),
-    Await(Box<Expr>),
+    BinaryOp(&'a mut Expr<'a>, BinOp, &'a mut Expr<'a>),
+    UnaryOp(UnaryOp, &'a mut Expr<'a>),
+    Call {
+        callee: &'a mut Expr<'a>,
+        args: Vec<Expr<'a>>,
+    },
+    Lambda {
+        params: Vec<Parameter>,
+        body: &'a mut Expr<'a>,
+    },
+    MethodCall {
+        receiver: &'a mut Expr<'a>,
+        name: String,
+        args: Vec<Expr<'a>>,
+    },
+    Array(Vec<Expr<'a>>),
+    Map(Vec<(Expr<'a>, Expr<'a>)>),
+    Range {
+        start: &'a mut Expr<'a>,
+        end: &'a mut Expr<'a>,
+        inclusive: bool,
+    },
+    Index {
+        target: &'a mut Expr<'a>,
+        index: &'a mut Expr<'a>,
+    },
+    Cast {
+        value: &'a mut Expr<'a>,
+        target: VarType,
+    },
+    Async(&'a mut Expr<'a>),
+    Await(&'a mut Expr<'a>),
+    Spawn(Vec<Statement<'a>>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinOp {
     Add,
     Subtract,
     Multiply,
     Divide,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Coalesce,
 }
 
-#[derive(Debug)]
-pub enum Statement {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+/// An annotation on a function definition asking the optimizer to inline its calls, or
+/// never to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InlineHint {
+    Inline,
+    NoInline,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub var_type: Option<VarType>,
+}
+
+/// A method a trait requires, such as `fn area(self);`, with the parameters after `self`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraitMethod {
+    pub name: String,
+    pub params: Vec<Parameter>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Pattern {
+    Number(i64),
+    String(String),
+    Wildcard,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MatchArm<'a> {
+    pub pattern: Pattern,
+    pub body: Vec<Statement<'a>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Statement<'a> {
     VariableDeclaration {
         identifier: String,
-        value: Expr,
+        value: Expr<'a>,
+    },
+    VariableAssignment {
+        identifier: String,
+        value: Expr<'a>,
+    },
+    IndexAssignment {
+        target: Expr<'a>,
+        index: Expr<'a>,
+        value: Expr<'a>,
+    },
+    FunctionCall {
+        identifier: String,
+        args: Vec<Expr<'a>>,
+    },
+    Print {
+        value: Expr<'a>,
+        newline: bool,
+    },
+    Expression(Expr<'a>),
+    Function {
+        identifier: String,
+        params: Vec<Parameter>,
+        /// The type of the result, which is an integer when none is written.
+        returns: Option<VarType>,
+        body: Vec<Statement<'a>>,
+        inline: Option<InlineHint>,
+    },
+    Return(Option<Expr<'a>>),
+    Match {
+        scrutinee: Expr<'a>,
+        arms: Vec<MatchArm<'a>>,
+    },
+    For {
+        variable: String,
+        iterable: Expr<'a>,
+        body: Vec<Statement<'a>>,
     },
-    Print(Expr),
     AsyncFunction {
         identifier: String,
-        body: Vec<Statement>,
+        params: Vec<Parameter>,
+        body: Vec<Statement<'a>>,
+        inline: Option<InlineHint>,
+    },
+    /// A named test, which only runs under `venti test`.
+    Test {
+        name: String,
+        body: Vec<Statement<'a>>,
+    },
+    /// Reports a failed test assertion when the condition is false, then carries on.
+    Assert(Expr<'a>),
+    /// A `#` comment kept for the formatter, which either trails the line before it or
+    /// has a line of its own.
+    Comment {
+        text: String,
+        trailing: bool,
+    },
+    /// Brings in the declarations of the file at a path relative to the importing file.
+    Import(String),
+    /// Declares a C function the program calls by its C name, such as
+    /// `extern_venti fn puts(s: string) -> int;`. Every parameter is typed, and a function
+    /// declared without a result type returns nothing in C and 0 to the program.
+    Extern {
+        identifier: String,
+        params: Vec<Parameter>,
+        returns: Option<VarType>,
     },
+    /// Declares the methods a type implementing the trait provides, such as
+    /// `trait_venti Shape { fn area(self); }`.
+    Trait {
+        identifier: String,
+        methods: Vec<TraitMethod>,
+    },
+    /// Implements a trait for one of the built-in types. Each method is a
+    /// `Statement::Function` whose first parameter is `self`, annotated with `target`.
+    Impl {
+        trait_name: String,
+        target: VarType,
+        methods: Vec<Statement<'a>>,
+    },
+    /// Runs `body`, and if a runtime error stops it, runs `handler` with the error's
+    /// message bound to `error` as a string, such as
+    /// `try_venti { ... } catch_venti (e) { ... }`.
+    Try {
+        body: Vec<Statement<'a>>,
+        error: String,
+        handler: Vec<Statement<'a>>,
+    },
+    /// Marks the span of the statement after it, whose line the statements after it
+    /// start on, for debug info and error messages.
+    Line(Span),
+}
+
+/// Returns the index of the statement whose value a program exits with: its last
+/// statement, when that is an expression such as `status;`. Comments, line markers and
+/// declarations do not run, so they may follow it.
+pub fn exit_expression(statements: &[Statement]) -> Option<usize> {
+    let (index, statement) = statements.iter().enumerate().rev().find(|(_, statement)| {
+        !matches!(
+            statement,
+            Statement::Comment { .. }
+                | Statement::Line(_)
+                | Statement::Function { .. }
+                | Statement::AsyncFunction { .. }
+                | Statement::Test { .. }
+                | Statement::Import(_)
+                | Statement::Extern { .. }
+                | Statement::Trait { .. }
+                | Statement::Impl { .. }
+        )
+    })?;
+    matches!(statement, Statement::Expression(_)).then_some(index)
 }
@@ -0,0 +1,585 @@
+use crate::venti_lexer::span::Span;
+use crate::venti_parser::ast::{
+    BinOp, Expr, ExprKind, InlineHint, MatchArm, Parameter, Pattern, Statement, UnaryOp, VarType,
+};
+use std::fmt::Write;
+
+/*
+`--dump-ast` writes the parsed program for external tools. The JSON form follows serde's
+default layout for the AST types, so it stays readable by anything built on serde:
+
+    enum variants without data      "Add"
+    variants with one value         {"Number": 1}
+    variants with several values    {"BinaryOp": [{"Identifier": "a"}, "Add", {"Number": 1}]}
+    variants with named fields      {"For": {"variable": "i", ...}}
+    structs                         {"name": "a", "var_type": null}
+    options                         null, or the value itself
+
+The S-expression form has the same structure, written as `(Variant values...)` with named
+fields as `:name value`, lists as `(...)` and a missing option as `nil`.
+*/
+
+/// The formats `--dump-ast` can write the AST in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AstFormat {
+    Json,
+    Sexpr,
+    /// Rust's `Debug` output for the AST types.
+    Debug,
+}
+
+impl AstFormat {
+    /// Maps a `--dump-ast` value to its format, or `None` for an unknown name.
+    pub fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "json" => Some(AstFormat::Json),
+            "sexpr" => Some(AstFormat::Sexpr),
+            "debug" => Some(AstFormat::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// Writes `statements` in `format`, ending with a newline.
+pub fn dump(statements: &[Statement], format: AstFormat) -> String {
+    let program = Node::List(statements.iter().map(statement).collect());
+    let mut out = String::new();
+    match format {
+        AstFormat::Json => write_json(&program, 0, &mut out),
+        AstFormat::Sexpr => {
+            // One top-level statement per line keeps the output diffable
+            for node in statements.iter().map(statement) {
+                write_sexpr(&node, &mut out);
+                out.push('\n');
+            }
+            return out;
+        }
+        AstFormat::Debug => {
+            let _ = write!(out, "{:#?}", statements);
+        }
+    }
+    out.push('\n');
+    out
+}
+
+/// The shape of a serialized AST value, following serde's data model.
+enum Node {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Vec<Node>),
+    /// An enum variant without data.
+    Unit(&'static str),
+    /// An enum variant holding one or more unnamed values.
+    Tuple(&'static str, Vec<Node>),
+    /// An enum variant with named fields.
+    Variant(&'static str, Vec<(&'static str, Node)>),
+    /// A struct, which is written without its name.
+    Struct(Vec<(&'static str, Node)>),
+}
+
+fn statement(statement: &Statement) -> Node {
+    match statement {
+        Statement::VariableDeclaration { identifier, value } => Node::Variant(
+            "VariableDeclaration",
+            vec![
+                ("identifier", Node::Str(identifier.clone())),
+                ("value", expr(value)),
+            ],
+        ),
+        Statement::VariableAssignment { identifier, value } => Node::Variant(
+            "VariableAssignment",
+            vec![
+                ("identifier", Node::Str(identifier.clone())),
+                ("value", expr(value)),
+            ],
+        ),
+        Statement::IndexAssignment {
+            target,
+            index,
+            value,
+        } => Node::Variant(
+            "IndexAssignment",
+            vec![
+                ("target", expr(target)),
+                ("index", expr(index)),
+                ("value", expr(value)),
+            ],
+        ),
+        Statement::FunctionCall { identifier, args } => Node::Variant(
+            "FunctionCall",
+            vec![
+                ("identifier", Node::Str(identifier.clone())),
+                ("args", exprs(args)),
+            ],
+        ),
+        Statement::Print { value, newline } => Node::Variant(
+            "Print",
+            vec![("value", expr(value)), ("newline", Node::Bool(*newline))],
+        ),
+        Statement::Expression(value) => Node::Tuple("Expression", vec![expr(value)]),
+        Statement::Function {
+            identifier,
+            params,
+            returns,
+            body,
+            inline,
+        } => function(
+            "Function",
+            identifier,
+            params,
+            returns.as_ref(),
+            body,
+            *inline,
+        ),
+        Statement::AsyncFunction {
+            identifier,
+            params,
+            body,
+            inline,
+        } => function("AsyncFunction", identifier, params, None, body, *inline),
+        Statement::Return(value) => {
+            Node::Tuple("Return", vec![value.as_ref().map_or(Node::Null, expr)])
+        }
+        Statement::Match { scrutinee, arms } => Node::Variant(
+            "Match",
+            vec![
+                ("scrutinee", expr(scrutinee)),
+                ("arms", Node::List(arms.iter().map(match_arm).collect())),
+            ],
+        ),
+        Statement::For {
+            variable,
+            iterable,
+            body,
+        } => Node::Variant(
+            "For",
+            vec![
+                ("variable", Node::Str(variable.clone())),
+                ("iterable", expr(iterable)),
+                ("body", statements(body)),
+            ],
+        ),
+        Statement::Test { name, body } => Node::Variant(
+            "Test",
+            vec![
+                ("name", Node::Str(name.clone())),
+                ("body", statements(body)),
+            ],
+        ),
+        Statement::Try {
+            body,
+            error,
+            handler,
+        } => Node::Variant(
+            "Try",
+            vec![
+                ("body", statements(body)),
+                ("error", Node::Str(error.clone())),
+                ("handler", statements(handler)),
+            ],
+        ),
+        Statement::Assert(condition) => Node::Tuple("Assert", vec![expr(condition)]),
+        Statement::Comment { text, trailing } => Node::Variant(
+            "Comment",
+            vec![
+                ("text", Node::Str(text.clone())),
+                ("trailing", Node::Bool(*trailing)),
+            ],
+        ),
+        Statement::Import(path) => Node::Tuple("Import", vec![Node::Str(path.clone())]),
+        Statement::Extern {
+            identifier,
+            params,
+            returns,
+        } => Node::Variant(
+            "Extern",
+            vec![
+                ("identifier", Node::Str(identifier.clone())),
+                ("params", parameters(params)),
+                ("returns", returns.as_ref().map_or(Node::Null, var_type)),
+            ],
+        ),
+        Statement::Trait {
+            identifier,
+            methods,
+        } => Node::Variant(
+            "Trait",
+            vec![
+                ("identifier", Node::Str(identifier.clone())),
+                (
+                    "methods",
+                    Node::List(
+                        methods
+                            .iter()
+                            .map(|method| {
+                                Node::Struct(vec![
+                                    ("name", Node::Str(method.name.clone())),
+                                    ("params", parameters(&method.params)),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                ),
+            ],
+        ),
+        Statement::Impl {
+            trait_name,
+            target,
+            methods,
+        } => Node::Variant(
+            "Impl",
+            vec![
+                ("trait_name", Node::Str(trait_name.clone())),
+                ("target", var_type(target)),
+                ("methods", statements(methods)),
+            ],
+        ),
+        Statement::Line(line) => Node::Tuple("Line", vec![span(line)]),
+    }
+}
+
+fn statements(body: &[Statement]) -> Node {
+    Node::List(body.iter().map(statement).collect())
+}
+
+fn function(
+    kind: &'static str,
+    identifier: &str,
+    params: &[Parameter],
+    returns: Option<&VarType>,
+    body: &[Statement],
+    inline: Option<InlineHint>,
+) -> Node {
+    let inline = match inline {
+        Some(InlineHint::Inline) => Node::Unit("Inline"),
+        Some(InlineHint::NoInline) => Node::Unit("NoInline"),
+        None => Node::Null,
+    };
+    Node::Variant(
+        kind,
+        vec![
+            ("identifier", Node::Str(identifier.to_string())),
+            ("params", parameters(params)),
+            ("returns", returns.map_or(Node::Null, var_type)),
+            ("body", statements(body)),
+            ("inline", inline),
+        ],
+    )
+}
+
+fn parameters(params: &[Parameter]) -> Node {
+    Node::List(
+        params
+            .iter()
+            .map(|param| {
+                Node::Struct(vec![
+                    ("name", Node::Str(param.name.clone())),
+                    (
+                        "var_type",
+                        param.var_type.as_ref().map_or(Node::Null, var_type),
+                    ),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn match_arm(arm: &MatchArm) -> Node {
+    let pattern = match &arm.pattern {
+        Pattern::Number(n) => Node::Tuple("Number", vec![Node::Int(*n)]),
+        Pattern::String(s) => Node::Tuple("String", vec![Node::Str(s.clone())]),
+        Pattern::Wildcard => Node::Unit("Wildcard"),
+    };
+    Node::Struct(vec![("pattern", pattern), ("body", statements(&arm.body))])
+}
+
+fn var_type(var_type: &VarType) -> Node {
+    match var_type {
+        VarType::Int => Node::Unit("Int"),
+        VarType::Float => Node::Unit("Float"),
+        VarType::Bool => Node::Unit("Bool"),
+        VarType::String => Node::Unit("String"),
+        VarType::Trait(name) => Node::Tuple("Trait", vec![Node::Str(name.clone())]),
+        VarType::Vec => Node::Unit("Vec"),
+    }
+}
+
+fn exprs(values: &[Expr]) -> Node {
+    Node::List(values.iter().map(expr).collect())
+}
+
+fn boxed(name: &'static str, value: &Expr) -> Node {
+    Node::Tuple(name, vec![expr(value)])
+}
+
+fn span(span: &Span) -> Node {
+    Node::Struct(vec![
+        ("file", Node::Int(span.file as i64)),
+        ("start", Node::Int(span.start as i64)),
+        ("end", Node::Int(span.end as i64)),
+        ("line", Node::Int(span.line as i64)),
+        ("column", Node::Int(span.column as i64)),
+    ])
+}
+
+fn expr(value: &Expr) -> Node {
+    Node::Struct(vec![
+        ("kind", expr_kind(&value.kind)),
+        ("span", span(&value.span)),
+    ])
+}
+
+fn expr_kind(kind: &ExprKind) -> Node {
+    match kind {
+        ExprKind::Number(n) => Node::Tuple("Number", vec![Node::Int(*n)]),
+        ExprKind::Float(x) => Node::Tuple("Float", vec![Node::Float(*x)]),
+        ExprKind::String(s) => Node::Tuple("String", vec![Node::Str(s.clone())]),
+        ExprKind::Boolean(b) => Node::Tuple("Boolean", vec![Node::Bool(*b)]),
+        ExprKind::Null => Node::Unit("Null"),
+        ExprKind::Identifier(id) => Node::Tuple("Identifier", vec![Node::Str(id.clone())]),
+        ExprKind::BinaryOp(left, op, right) => Node::Tuple(
+            "BinaryOp",
+            vec![expr(left), Node::Unit(bin_op(*op)), expr(right)],
+        ),
+        ExprKind::UnaryOp(op, operand) => {
+            let op = match op {
+                UnaryOp::Negate => "Negate",
+                UnaryOp::Not => "Not",
+            };
+            Node::Tuple("UnaryOp", vec![Node::Unit(op), expr(operand)])
+        }
+        ExprKind::Call { callee, args } => Node::Variant(
+            "Call",
+            vec![("callee", expr(callee)), ("args", exprs(args))],
+        ),
+        ExprKind::Lambda { params, body } => Node::Variant(
+            "Lambda",
+            vec![("params", parameters(params)), ("body", expr(body))],
+        ),
+        ExprKind::MethodCall {
+            receiver,
+            name,
+            args,
+        } => Node::Variant(
+            "MethodCall",
+            vec![
+                ("receiver", expr(receiver)),
+                ("name", Node::Str(name.clone())),
+                ("args", exprs(args)),
+            ],
+        ),
+        ExprKind::Array(elements) => Node::Tuple("Array", vec![exprs(elements)]),
+        ExprKind::Map(entries) => Node::Tuple(
+            "Map",
+            vec![Node::List(
+                entries
+                    .iter()
+                    .map(|(key, value)| Node::List(vec![expr(key), expr(value)]))
+                    .collect(),
+            )],
+        ),
+        ExprKind::Range {
+            start,
+            end,
+            inclusive,
+        } => Node::Variant(
+            "Range",
+            vec![
+                ("start", expr(start)),
+                ("end", expr(end)),
+                ("inclusive", Node::Bool(*inclusive)),
+            ],
+        ),
+        ExprKind::Index { target, index } => Node::Variant(
+            "Index",
+            vec![("target", expr(target)), ("index", expr(index))],
+        ),
+        ExprKind::Cast { value, target } => Node::Variant(
+            "Cast",
+            vec![("value", expr(value)), ("target", var_type(target))],
+        ),
+        ExprKind::Async(inner) => boxed("Async", inner),
+        ExprKind::Await(task) => boxed("Await", task),
+        ExprKind::Spawn(body) => Node::Tuple("Spawn", vec![statements(body)]),
+    }
+}
+
+fn bin_op(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "Add",
+        BinOp::Subtract => "Subtract",
+        BinOp::Multiply => "Multiply",
+        BinOp::Divide => "Divide",
+        BinOp::Equal => "Equal",
+        BinOp::NotEqual => "NotEqual",
+        BinOp::Less => "Less",
+        BinOp::LessEqual => "LessEqual",
+        BinOp::Greater => "Greater",
+        BinOp::GreaterEqual => "GreaterEqual",
+        BinOp::And => "And",
+        BinOp::Or => "Or",
+        BinOp::BitAnd => "BitAnd",
+        BinOp::BitOr => "BitOr",
+        BinOp::BitXor => "BitXor",
+        BinOp::ShiftLeft => "ShiftLeft",
+        BinOp::ShiftRight => "ShiftRight",
+        BinOp::Coalesce => "Coalesce",
+    }
+}
+
+/// Writes `node` as indented JSON, with nested values `depth` levels deep.
+fn write_json(node: &Node, depth: usize, out: &mut String) {
+    match node {
+        Node::Null => out.push_str("null"),
+        Node::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Node::Int(n) => {
+            let _ = write!(out, "{}", n);
+        }
+        // JSON has no infinities or NaN, so those become null as in serde_json
+        Node::Float(x) if !x.is_finite() => out.push_str("null"),
+        Node::Float(x) => {
+            let _ = write!(out, "{:?}", x);
+        }
+        Node::Str(s) => write_json_string(s, out),
+        Node::Unit(name) => write_json_string(name, out),
+        Node::List(items) => write_json_list(items, depth, out),
+        Node::Tuple(name, items) => {
+            out.push('{');
+            write_json_string(name, out);
+            out.push_str(": ");
+            match items.as_slice() {
+                [item] => write_json(item, depth, out),
+                // A tuple variant's values are written as a list
+                items => write_json_list(items, depth, out),
+            }
+            out.push('}');
+        }
+        Node::Variant(name, fields) => {
+            out.push('{');
+            write_json_string(name, out);
+            out.push_str(": ");
+            write_json_fields(fields, depth, out);
+            out.push('}');
+        }
+        Node::Struct(fields) => write_json_fields(fields, depth, out),
+    }
+}
+
+/// Writes `items` as a JSON array.
+fn write_json_list(items: &[Node], depth: usize, out: &mut String) {
+    if items.is_empty() {
+        out.push_str("[]");
+        return;
+    }
+    out.push('[');
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        newline(depth + 1, out);
+        write_json(item, depth + 1, out);
+    }
+    newline(depth, out);
+    out.push(']');
+}
+
+/// Writes named fields as a JSON object.
+fn write_json_fields(fields: &[(&'static str, Node)], depth: usize, out: &mut String) {
+    out.push('{');
+    for (index, (name, value)) in fields.iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        newline(depth + 1, out);
+        write_json_string(name, out);
+        out.push_str(": ");
+        write_json(value, depth + 1, out);
+    }
+    newline(depth, out);
+    out.push('}');
+}
+
+/// Starts a new line indented `depth` levels.
+fn newline(depth: usize, out: &mut String) {
+    out.push('\n');
+    out.push_str(&"  ".repeat(depth));
+}
+
+/// Writes `text` as a quoted string with JSON's escapes, which S-expressions share.
+pub fn write_json_string(text: &str, out: &mut String) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Writes `node` as an S-expression on one line.
+fn write_sexpr(node: &Node, out: &mut String) {
+    match node {
+        Node::Null => out.push_str("nil"),
+        Node::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Node::Int(n) => {
+            let _ = write!(out, "{}", n);
+        }
+        Node::Float(x) => {
+            let _ = write!(out, "{:?}", x);
+        }
+        Node::Str(s) => write_json_string(s, out),
+        Node::Unit(name) => out.push_str(name),
+        Node::List(items) => {
+            out.push('(');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(' ');
+                }
+                write_sexpr(item, out);
+            }
+            out.push(')');
+        }
+        Node::Tuple(name, items) => {
+            out.push('(');
+            out.push_str(name);
+            for item in items {
+                out.push(' ');
+                write_sexpr(item, out);
+            }
+            out.push(')');
+        }
+        Node::Variant(name, fields) => {
+            out.push('(');
+            out.push_str(name);
+            write_sexpr_fields(fields, out);
+            out.push(')');
+        }
+        Node::Struct(fields) => {
+            out.push('(');
+            // Drop the space before the first field
+            let start = out.len();
+            write_sexpr_fields(fields, out);
+            out.remove(start);
+            out.push(')');
+        }
+    }
+}
+
+/// Writes named fields as ` :name value` pairs.
+fn write_sexpr_fields(fields: &[(&'static str, Node)], out: &mut String) {
+    for (name, value) in fields {
+        let _ = write!(out, " :{} ", name);
+        write_sexpr(value, out);
+    }
+}
@@ -1,2 +1,4 @@
+pub mod arena;
 pub mod ast;
+pub mod dump;
 pub mod parser;
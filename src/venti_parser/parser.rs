@@ -1,35 +1,302 @@
+use crate::codes::ErrorCode;
 use crate::errors::VentiError;
+use crate::venti_lexer::lexer::{Comment, SpannedToken};
+use crate::venti_lexer::span::Span;
 use crate::venti_lexer::token::Token;
-use crate::venti_parser::ast::{BinOp, Expr, Statement};
+use crate::venti_parser::arena::Arena;
+use crate::venti_parser::ast::{
+    BinOp, Expr, ExprKind, InlineHint, MatchArm, Parameter, Pattern, Statement, TraitMethod,
+    UnaryOp, VarType,
+};
 use std::iter::Peekable;
 use std::vec::IntoIter;
 
+/// Binding power of prefix operators, tighter than every infix operator.
+pub const PREFIX_BINDING_POWER: u8 = 70;
+
+/// Binding power of `as`, between the infix operators and the prefix ones, so
+/// `-x as float * 2` is `((-x) as float) * 2`.
+pub const CAST_BINDING_POWER: u8 = 65;
+
+/// How deeply expressions and blocks may nest, which keeps the parser and the passes after
+/// it, which all recurse over the AST, from running out of stack.
+pub const MAX_NESTING: usize = 256;
+
+/// The binding-power table for infix operators.
+///
+/// Each entry is `(operator, left binding power, right binding power)`. A higher power
+/// binds tighter, and a right power above the left power makes the operator left-associative.
+///
+/// # Returns
+///
+/// `Some` entry if `token` is an infix operator, otherwise `None`.
+fn infix_binding_power(token: &Token) -> Option<(BinOp, u8, u8)> {
+    let entry = match token {
+        Token::QuestionQuestion => (BinOp::Coalesce, 5, 6),
+        Token::OrOr => (BinOp::Or, 10, 11),
+        Token::AndAnd => (BinOp::And, 20, 21),
+        Token::EqualEqual => (BinOp::Equal, 30, 31),
+        Token::NotEqual => (BinOp::NotEqual, 30, 31),
+        Token::Less => (BinOp::Less, 40, 41),
+        Token::LessEqual => (BinOp::LessEqual, 40, 41),
+        Token::Greater => (BinOp::Greater, 40, 41),
+        Token::GreaterEqual => (BinOp::GreaterEqual, 40, 41),
+        Token::Pipe => (BinOp::BitOr, 42, 43),
+        Token::Caret => (BinOp::BitXor, 44, 45),
+        Token::Ampersand => (BinOp::BitAnd, 46, 47),
+        Token::LessLess => (BinOp::ShiftLeft, 48, 49),
+        Token::GreaterGreater => (BinOp::ShiftRight, 48, 49),
+        Token::Plus => (BinOp::Add, 50, 51),
+        Token::Minus => (BinOp::Subtract, 50, 51),
+        Token::Star => (BinOp::Multiply, 60, 61),
+        Token::Slash => (BinOp::Divide, 60, 61),
+        _ => return None,
+    };
+    Some(entry)
+}
+
+/// Returns the token of a binary operator, the reverse of `infix_binding_power`.
+pub fn operator_token(op: BinOp) -> Token {
+    match op {
+        BinOp::Coalesce => Token::QuestionQuestion,
+        BinOp::Or => Token::OrOr,
+        BinOp::And => Token::AndAnd,
+        BinOp::Equal => Token::EqualEqual,
+        BinOp::NotEqual => Token::NotEqual,
+        BinOp::Less => Token::Less,
+        BinOp::LessEqual => Token::LessEqual,
+        BinOp::Greater => Token::Greater,
+        BinOp::GreaterEqual => Token::GreaterEqual,
+        BinOp::BitOr => Token::Pipe,
+        BinOp::BitXor => Token::Caret,
+        BinOp::BitAnd => Token::Ampersand,
+        BinOp::ShiftLeft => Token::LessLess,
+        BinOp::ShiftRight => Token::GreaterGreater,
+        BinOp::Add => Token::Plus,
+        BinOp::Subtract => Token::Minus,
+        BinOp::Multiply => Token::Star,
+        BinOp::Divide => Token::Slash,
+    }
+}
+
+/// Returns the left and right binding powers the parser gives `op`.
+pub fn binding_power(op: BinOp) -> (u8, u8) {
+    let (_, left_bp, right_bp) = infix_binding_power(&operator_token(op))
+        .expect("every binary operator has a binding power");
+    (left_bp, right_bp)
+}
+
 /// A Parser For the Venti Programming Lang
 ///
-/// The `parser` is responsible for converting a sequence of tokens into an AST
-pub struct Parser {
-    tokens: Peekable<IntoIter<Token>>,
+/// The `parser` is responsible for converting a sequence of tokens into an AST. It pulls
+/// the tokens one at a time, each with its span, so it can parse straight from a `Lexer`
+/// without the whole file being lexed first.
+pub struct Parser<'a, I: Iterator> {
+    tokens: Peekable<I>,
+    // Where the children of the expressions are put
+    arena: &'a Arena<Expr<'a>>,
+    // The span of the token consumed last, empty before the first
+    last_span: Span,
+    // The comments not yet placed, when the parser keeps them for the formatter
+    comments: Peekable<IntoIter<Comment>>,
+    // The errors of the statements skipped so far
+    errors: Vec<VentiError>,
+    // How many expressions and blocks enclose the current token
+    depth: usize,
 }
 
-impl Parser {
+impl<'a, I: Iterator<Item = Result<SpannedToken, VentiError>>> Parser<'a, I> {
     /// Crate a new `Parser` with the given tokens
     ///
+    /// Every expression gets the span it was parsed from and every statement is preceded
+    /// by a `Statement::Line` marking its span, for debug info and error messages. A
+    /// lexing error is recorded when the parser reaches it, and parsing goes on as if the
+    /// text it covers were not there.
+    ///
     /// # Arguments
     ///
-    /// * `tokens` - A vector of `Token` objects representing the token to parse
+    /// * `tokens` - The tokens to parse, each with its span, such as a `Lexer`.
+    /// * `arena` - Holds the children of the expressions parsed, for as long as the
+    ///   syntax tree is used.
     ///
     /// # Returns
     ///
     /// A new instance of `Parser`.
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: impl IntoIterator<IntoIter = I>, arena: &'a Arena<Expr<'a>>) -> Self {
         Parser {
             tokens: tokens.into_iter().peekable(),
+            arena,
+            last_span: Span::default(),
+            comments: Vec::new().into_iter().peekable(),
+            errors: Vec::new(),
+            depth: 0,
         }
     }
 
+    /// Keeps the lexer's comments as `Statement::Comment`s between the statements they sit
+    /// among, so the formatter can write them back.
+    ///
+    /// A comment inside a statement that spans lines ends up after that statement.
+    ///
+    /// # Arguments
+    ///
+    /// * `comments` - The comments of the source, in the order they appear.
+    ///
+    /// # Returns
+    ///
+    /// The `Parser`, now keeping comments.
+    pub fn with_comments(mut self, comments: Vec<Comment>) -> Self {
+        self.comments = comments.into_iter().peekable();
+        self
+    }
+
     /// Advance the current token without advancing the iterator
     fn advance(&mut self) {
-        self.tokens.next();
+        self.skip_lexing_errors();
+        if let Some(Ok((_, span))) = self.tokens.next() {
+            self.last_span = span;
+        }
+    }
+
+    /// Records the lexing errors ahead of the current token and moves past them.
+    fn skip_lexing_errors(&mut self) {
+        // Only peeking in the usual case, since moving a token out and back is not free
+        while let Some(Err(_)) = self.tokens.peek() {
+            if let Some(Err(error)) = self.tokens.next() {
+                self.errors.push(error);
+            }
+        }
+    }
+
+    /// Returns the span of the current token, or `None` at the end of the input.
+    fn peek_span(&mut self) -> Option<Span> {
+        self.skip_lexing_errors();
+        self.tokens
+            .peek()
+            .and_then(|token| token.as_ref().ok())
+            .map(|(_, span)| *span)
+    }
+
+    /// Returns the span of the current token, or of the last one at the end of the input.
+    fn current_span(&mut self) -> Span {
+        self.peek_span().unwrap_or(self.last_span)
+    }
+
+    /// Runs `parse` on an expression or block nested one level deeper than the current one.
+    ///
+    /// # Returns
+    ///
+    /// What `parse` returns, or a `VentiError::SyntaxError` if that would nest more than
+    /// `MAX_NESTING` levels deep.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, VentiError>,
+    ) -> Result<T, VentiError> {
+        if self.depth == MAX_NESTING {
+            return Err(VentiError::SyntaxError(
+                format!(
+                    "Expressions and blocks cannot be nested more than {} levels deep.",
+                    MAX_NESTING
+                ),
+                self.location(),
+            ));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Returns where an error at the current token is.
+    fn location(&mut self) -> Option<Span> {
+        Some(self.current_span())
+    }
+
+    /// Wraps `kind` in an expression spanning from `start` to the token consumed last.
+    fn node(&self, kind: ExprKind<'a>, start: Span) -> Expr<'a> {
+        Expr::new(kind, start.to(self.last_span))
+    }
+
+    /// Moves `expr` into the arena, as the child of another expression.
+    fn alloc(&self, expr: Expr<'a>) -> &'a mut Expr<'a> {
+        self.arena.alloc(expr)
+    }
+
+    /// Parses the next statement into `statements`, preceded by its span.
+    fn push_statement(&mut self, statements: &mut Vec<Statement<'a>>) -> Result<(), VentiError> {
+        let Some(start) = self.peek_span() else {
+            statements.push(self.statement()?);
+            return Ok(());
+        };
+        self.push_comments(statements, Some(start.line));
+        let marker = statements.len();
+        statements.push(Statement::Line(start));
+        statements.push(self.statement()?);
+        // Now that the statement is parsed, the marker can cover all of it
+        statements[marker] = Statement::Line(start.to(self.last_span));
+        Ok(())
+    }
+
+    /// Parses the next statement like `push_statement`, or records its error and skips
+    /// past it, so the statements after it are still parsed.
+    ///
+    /// # Arguments
+    ///
+    /// * `statements` - The statements the parsed statement is added to.
+    /// * `nested` - Whether the statement is inside a block, whose `}` must be left for
+    ///   `block` to consume.
+    fn recover_statement(&mut self, statements: &mut Vec<Statement<'a>>, nested: bool) {
+        if let Err(error) = self.push_statement(statements) {
+            self.errors.push(error);
+            self.synchronize(nested);
+        }
+    }
+
+    /// Skips the rest of a statement that failed to parse: up to and including the next
+    /// `;`, or the `}` closing a block the skipped tokens opened. A `}` closing the block
+    /// around the statement ends the skipping too, and is consumed only at the top level.
+    fn synchronize(&mut self, nested: bool) {
+        let mut depth = 0;
+        while let Some(token) = self.current_token() {
+            match token {
+                Token::Semicolon if depth == 0 => {
+                    self.advance(); // Consume ';'
+                    return;
+                }
+                Token::RBrace if depth == 0 => {
+                    if !nested {
+                        self.advance(); // Consume the stray '}'
+                    }
+                    return;
+                }
+                Token::RBrace if depth == 1 => {
+                    self.advance(); // Consume '}'
+                    return;
+                }
+                Token::RBrace => depth -= 1,
+                Token::LBrace => depth += 1,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Moves the kept comments from before line `before`, or all of them, into
+    /// `statements`. A comment on the line of the token consumed last trails that line.
+    fn push_comments(&mut self, statements: &mut Vec<Statement<'a>>, before: Option<u32>) {
+        while let Some(comment) = self
+            .comments
+            .next_if(|comment| before.is_none_or(|line| comment.span.line < line))
+        {
+            let trailing = comment.span.line == self.last_span.line;
+            if !trailing {
+                statements.push(Statement::Line(comment.span));
+            }
+            statements.push(Statement::Comment {
+                text: comment.text,
+                trailing,
+            });
+        }
     }
 
     /// Returns the current token without advancing the iterator
@@ -38,20 +305,55 @@ impl Parser {
     ///
     /// An `Option` containing a reference to the current `Token`, or `None` if there are no more tokens
     fn current_token(&mut self) -> Option<&Token> {
-        self.tokens.peek()
+        self.skip_lexing_errors();
+        self.tokens
+            .peek()
+            .and_then(|token| token.as_ref().ok())
+            .map(|(token, _)| token)
+    }
+
+    /// Consumes the current token if it is `expected`, otherwise reports `message`.
+    ///
+    /// # Returns
+    ///
+    /// An empty `Result`, or a `VentiError::SyntaxError` carrying `message`.
+    fn expect(&mut self, expected: Token, message: &str) -> Result<(), VentiError> {
+        if self.current_token() == Some(&expected) {
+            self.advance();
+            Ok(())
+        } else {
+            let error = VentiError::SyntaxError(
+                format!("{} Found: {:?}", message, self.current_token()),
+                self.location(),
+            );
+            if expected == Token::Semicolon {
+                return Err(error.with_code(ErrorCode::MissingSemicolon));
+            }
+            Err(error)
+        }
     }
 
     /// Parses the entire input and produces a vector of a statements
     ///
+    /// A statement that fails to parse is skipped up to the next `;` or `}`, and parsing
+    /// goes on from there, so every syntax error in the input is reported at once.
+    ///
     /// # Returns
     ///
-    /// A `Result` containing either a vector of `Statement` objects or a `VentiError` if parsing fails.
-    pub fn parse(&mut self) -> Result<Vec<Statement>, VentiError> {
+    /// A `Result` containing either a vector of `Statement` objects or a `VentiError` if parsing fails,
+    /// which is a `VentiError::Multiple` when more than one statement is invalid.
+    pub fn parse(&mut self) -> Result<Vec<Statement<'a>>, VentiError> {
         let mut statements = Vec::new();
         while self.current_token().is_some() {
-            statements.push(self.statement()?);
+            self.recover_statement(&mut statements, false);
+        }
+        self.push_comments(&mut statements, None);
+        let mut errors = std::mem::take(&mut self.errors);
+        match errors.len() {
+            0 => Ok(statements),
+            1 => Err(errors.remove(0)),
+            _ => Err(VentiError::Multiple(errors)),
         }
-        Ok(statements)
     }
 
     /// Parses a single statement
@@ -59,7 +361,7 @@ impl Parser {
     /// # Returns
     ///
     /// A `Result` containing a `Statement` or a `VentiError` if the statement is invalid.
-    fn statement(&mut self) -> Result<Statement, VentiError> {
+    fn statement(&mut self) -> Result<Statement<'a>, VentiError> {
         match self.current_token() {
             Some(Token::Venti) => {
                 self.advance(); // Consume 'venti'
@@ -67,28 +369,114 @@ impl Parser {
             }
             Some(Token::Print) => {
                 self.advance(); // Consume 'printventi'
-                self.print_statement()
+                self.print_statement(true)
+            }
+            Some(Token::PrintInline) => {
+                self.advance(); // Consume 'printventi_inline'
+                self.print_statement(false)
+            }
+            Some(Token::Func) => {
+                self.advance(); // Consume 'funcVenti'
+                self.function_definition()
+            }
+            Some(Token::Async) => {
+                self.advance(); // Consume 'async'
+                self.async_function_definition()
+            }
+            Some(Token::Inline) => {
+                self.advance(); // Consume 'inline_venti'
+                self.annotated_function(InlineHint::Inline)
+            }
+            Some(Token::NoInline) => {
+                self.advance(); // Consume 'noinline_venti'
+                self.annotated_function(InlineHint::NoInline)
+            }
+            Some(Token::Spawn) => {
+                let start = self.current_span();
+                self.advance(); // Consume 'spawn_venti'
+                let body = self.block()?;
+                Ok(Statement::Expression(
+                    self.node(ExprKind::Spawn(body), start),
+                ))
+            }
+            Some(Token::Return) => {
+                self.advance(); // Consume 'return_venti'
+                self.return_statement()
+            }
+            Some(Token::Match) => {
+                self.advance(); // Consume 'match_venti'
+                self.match_statement()
+            }
+            Some(Token::For) => {
+                self.advance(); // Consume 'for_venti'
+                self.for_statement()
+            }
+            Some(Token::Import) => {
+                self.advance(); // Consume 'import_venti'
+                self.import_statement()
+            }
+            Some(Token::Extern) => {
+                self.advance(); // Consume 'extern_venti'
+                self.extern_declaration()
+            }
+            Some(Token::Trait) => {
+                self.advance(); // Consume 'trait_venti'
+                self.trait_declaration()
+            }
+            Some(Token::Impl) => {
+                self.advance(); // Consume 'impl_venti'
+                self.impl_block()
+            }
+            Some(Token::Test) => {
+                self.advance(); // Consume 'test_venti'
+                self.test_definition()
+            }
+            Some(Token::Try) => {
+                self.advance(); // Consume 'try_venti'
+                self.try_statement()
+            }
+            Some(Token::Assert) => {
+                self.advance(); // Consume 'assert_venti'
+                let condition = self.expression()?;
+                self.expect(Token::Semicolon, "Expected ';' after assertion.")?;
+                Ok(Statement::Assert(condition))
             }
             Some(Token::Identifier(_)) => self.function_or_variable(),
-            _ => Err(VentiError::SyntaxError(format!(
-                "Unexpected token: {:?}",
-                self.current_token()
-            ))),
+            _ => {
+                let expr = self.expression()?;
+                self.expression_statement(expr)
+            }
         }
     }
 
+    /// Finishes an expression used as a statement, such as `await task;` or `x * 2;`.
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The leftmost operand of the expression, already parsed.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Statement::Expression` or a `VentiError` if invalid.
+    fn expression_statement(&mut self, left: Expr<'a>) -> Result<Statement<'a>, VentiError> {
+        let expr = self.expression_from(left)?;
+        self.expect(Token::Semicolon, "Expected ';' after expression.")?;
+        Ok(Statement::Expression(expr))
+    }
+
     /// Parses a variable declaration statement.
     ///
     /// # Returns
     ///
     /// A `Result` containing a `Statement::VariableDeclaration` or a `VentiError` if the declaration is invalid.
-    fn variable_declaration(&mut self) -> Result<Statement, VentiError> {
+    fn variable_declaration(&mut self) -> Result<Statement<'a>, VentiError> {
         // Match the identifier
         let identifier = if let Some(Token::Identifier(id)) = self.current_token() {
             id.clone()
         } else {
             return Err(VentiError::SyntaxError(
                 "Expected identifier in variable declaration.".to_string(),
+                self.location(),
             ));
         };
 
@@ -105,116 +493,914 @@ impl Parser {
             if let Some(Token::Semicolon) = self.current_token() {
                 self.advance(); // Consume ';'
                                 // Return the variable declaration statement
-                return Ok(Statement::VariableDeclaration { identifier, value });
+                Ok(Statement::VariableDeclaration { identifier, value })
             } else {
-                return Err(VentiError::SyntaxError(
+                Err(VentiError::SyntaxError(
                     "Expected ';' at the end of variable declaration.".to_string(),
-                ));
+                    self.location(),
+                )
+                .with_code(ErrorCode::MissingSemicolon))
             }
         } else {
-            return Err(VentiError::SyntaxError(
+            Err(VentiError::SyntaxError(
                 "Expected '=' in variable declaration.".to_string(),
-            ));
+                self.location(),
+            ))
         }
     }
 
     /// Parses a print statement.
     ///
+    /// # Arguments
+    ///
+    /// * `newline` - Whether the printed value is followed by a newline.
+    ///
     /// # Returns
     ///
     /// A `Result` containing a `Statement::Print` or a `VentiError` if the print statement is invalid.
-    fn print_statement(&mut self) -> Result<Statement, VentiError> {
+    fn print_statement(&mut self, newline: bool) -> Result<Statement<'a>, VentiError> {
         // Parse the expression to be printed
         let value = self.expression()?;
 
         // Consume the semicolon
         if let Some(Token::Semicolon) = self.current_token() {
             self.advance(); // Consume ';'
-            return Ok(Statement::Print(value));
+            Ok(Statement::Print { value, newline })
         } else {
-            return Err(VentiError::SyntaxError(
+            Err(VentiError::SyntaxError(
                 "Expected ';' at the end of print statement.".to_string(),
+                self.location(),
+            )
+            .with_code(ErrorCode::MissingSemicolon))
+        }
+    }
+
+    /// Parses a function definition such as `funcVenti add(a: int, b: int) { ... }`, whose
+    /// parameters may be followed by the type of its result, as in `-> vec`.
+    ///
+    /// `fn_venti` is accepted as an alternative spelling of `funcVenti`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Statement::Function` or a `VentiError` if the definition is invalid.
+    fn function_definition(&mut self) -> Result<Statement<'a>, VentiError> {
+        let identifier = if let Some(Token::Identifier(id)) = self.current_token() {
+            id.clone()
+        } else {
+            return Err(VentiError::SyntaxError(
+                "Expected function name after 'funcVenti'.".to_string(),
+                self.location(),
             ));
+        };
+        self.advance(); // Consume function name
+
+        let params = self.parameters()?;
+        let returns = self.result_type()?;
+        let body = self.block()?;
+        Ok(Statement::Function {
+            identifier,
+            params,
+            returns,
+            body,
+            inline: None,
+        })
+    }
+
+    /// Parses the `-> type` naming the type of a function's result, if there is one.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the type, `None` if there is no `->`, or a `VentiError` if no
+    /// type name follows it.
+    fn result_type(&mut self) -> Result<Option<VarType>, VentiError> {
+        if self.current_token() != Some(&Token::Arrow) {
+            return Ok(None);
+        }
+        self.advance(); // Consume '->'
+        match self.current_token() {
+            Some(Token::Identifier(name)) if name == "vec" => {
+                self.advance(); // Consume 'vec'
+                Ok(Some(VarType::Vec))
+            }
+            _ => self.var_type().map(Some),
         }
     }
 
-    /// Parses an expression, starting with the term.
+    /// Parses the rest of `async fn_venti name(...) { ... }` after the `async`.
     ///
     /// # Returns
     ///
-    /// A `Result` containing an `Expr` or a `VentiError` if the expression is invalid.
-    fn expression(&mut self) -> Result<Expr, VentiError> {
-        self.term()
+    /// A `Result` containing a `Statement::AsyncFunction` or a `VentiError` if the definition is invalid.
+    fn async_function_definition(&mut self) -> Result<Statement<'a>, VentiError> {
+        self.expect(Token::Func, "Expected 'fn_venti' after 'async'.")?;
+        match self.function_definition()? {
+            Statement::Function { returns: Some(_), .. } => Err(VentiError::SyntaxError(
+                "An async function gives a task, so its result cannot be given a type.".to_string(),
+                self.location(),
+            )),
+            Statement::Function {
+                identifier,
+                params,
+                returns: None,
+                body,
+                inline,
+            } => Ok(Statement::AsyncFunction {
+                identifier,
+                params,
+                body,
+                inline,
+            }),
+            _ => unreachable!("function_definition always yields a function"),
+        }
     }
 
-    /// Parses a term, which may include addition and subtraction.
+    /// Parses the function definition following an `inline_venti` or `noinline_venti`
+    /// annotation, which may be async.
+    ///
+    /// # Arguments
+    ///
+    /// * `hint` - The inlining hint given by the annotation.
     ///
     /// # Returns
     ///
-    /// A `Result` containing an `Expr` or a `VentiError` if the term is invalid.
-    fn term(&mut self) -> Result<Expr, VentiError> {
-        let mut left = self.factor()?;
-        while let Some(token) = self.current_token() {
-            match token {
-                Token::Plus => {
-                    self.advance();
-                    let right = self.factor()?;
-                    left = Expr::BinaryOp(Box::new(left), BinOp::Add, Box::new(right));
+    /// A `Result` containing the annotated function or a `VentiError` if no function follows.
+    fn annotated_function(&mut self, hint: InlineHint) -> Result<Statement<'a>, VentiError> {
+        let mut function = match self.current_token() {
+            Some(Token::Func) => {
+                self.advance(); // Consume 'fn_venti'
+                self.function_definition()?
+            }
+            Some(Token::Async) => {
+                self.advance(); // Consume 'async'
+                self.async_function_definition()?
+            }
+            _ => {
+                return Err(VentiError::SyntaxError(
+                    format!(
+                        "Expected a function definition after an inlining annotation. Found: {:?}",
+                        self.current_token()
+                    ),
+                    self.location(),
+                ))
+            }
+        };
+        if let Statement::Function { inline, .. } | Statement::AsyncFunction { inline, .. } =
+            &mut function
+        {
+            *inline = Some(hint);
+        }
+        Ok(function)
+    }
+
+    /// Parses a parenthesized parameter list with optional type annotations.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the parameters or a `VentiError` if the list is malformed.
+    fn parameters(&mut self) -> Result<Vec<Parameter>, VentiError> {
+        self.expect(Token::LParen, "Expected '(' after function name.")?;
+        let mut params = Vec::new();
+        while self.current_token() != Some(&Token::RParen) {
+            params.push(self.parameter()?);
+
+            match self.current_token() {
+                Some(Token::Comma) => self.advance(), // Consume ','
+                Some(Token::RParen) => break,
+                _ => {
+                    return Err(VentiError::SyntaxError(
+                        "Expected ',' or ')' in parameter list.".to_string(),
+                        self.location(),
+                    ))
                 }
-                Token::Minus => {
-                    self.advance();
-                    let right = self.factor()?;
-                    left = Expr::BinaryOp(Box::new(left), BinOp::Subtract, Box::new(right));
+            }
+        }
+        self.advance(); // Consume ')'
+        Ok(params)
+    }
+
+    /// Parses a single parameter: a name with an optional `: type` annotation.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Parameter` or a `VentiError` if it is malformed.
+    fn parameter(&mut self) -> Result<Parameter, VentiError> {
+        let name = if let Some(Token::Identifier(name)) = self.current_token() {
+            name.clone()
+        } else {
+            return Err(VentiError::SyntaxError(
+                "Expected parameter name.".to_string(),
+                self.location(),
+            ));
+        };
+        self.advance(); // Consume parameter name
+
+        let var_type = if let Some(Token::Colon) = self.current_token() {
+            self.advance(); // Consume ':'
+            Some(self.parameter_type()?)
+        } else {
+            None
+        };
+        Ok(Parameter { name, var_type })
+    }
+
+    /// Parses the annotation of a parameter, which is a type name or the name of a trait.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `VarType` or a `VentiError` if no type name is present.
+    fn parameter_type(&mut self) -> Result<VarType, VentiError> {
+        if let Some(Token::Identifier(name)) = self.current_token() {
+            let name = name.clone();
+            self.advance(); // Consume the trait name
+            // `vec` names the built-in growable array rather than a trait
+            if name == "vec" {
+                return Ok(VarType::Vec);
+            }
+            return Ok(VarType::Trait(name));
+        }
+        self.var_type()
+    }
+
+    /// Parses a type name used in annotations.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `VarType` or a `VentiError` if no type name is present.
+    fn var_type(&mut self) -> Result<VarType, VentiError> {
+        let var_type = match self.current_token() {
+            Some(Token::Int) => VarType::Int,
+            Some(Token::Float) => VarType::Float,
+            Some(Token::Bool) => VarType::Bool,
+            Some(Token::StringType) => VarType::String,
+            other => {
+                return Err(VentiError::SyntaxError(
+                    format!("Expected a type name, found {:?}", other),
+                    self.location(),
+                ))
+            }
+        };
+        self.advance(); // Consume the type name
+        Ok(var_type)
+    }
+
+    /// Parses a brace-delimited block of statements.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the statements in the block or a `VentiError` if the block is invalid.
+    fn block(&mut self) -> Result<Vec<Statement<'a>>, VentiError> {
+        self.nested(Self::block_statements)
+    }
+
+    /// Parses a block like `block`, at the nesting depth of the block.
+    fn block_statements(&mut self) -> Result<Vec<Statement<'a>>, VentiError> {
+        self.expect(Token::LBrace, "Expected '{' to start a block.")?;
+        let mut statements = Vec::new();
+        while self.current_token() != Some(&Token::RBrace) {
+            if self.current_token().is_none() {
+                return Err(VentiError::SyntaxError(
+                    "Expected '}' to close the block.".to_string(),
+                    self.location(),
+                )
+                .with_code(ErrorCode::UnclosedBlock));
+            }
+            self.recover_statement(&mut statements, true);
+        }
+        // Comments before the '}' stay inside the block
+        if let Some(span) = self.peek_span() {
+            self.push_comments(&mut statements, Some(span.line));
+        }
+        self.advance(); // Consume '}'
+        Ok(statements)
+    }
+
+    /// Parses a return statement with an optional value.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Statement::Return` or a `VentiError` if the statement is invalid.
+    fn return_statement(&mut self) -> Result<Statement<'a>, VentiError> {
+        let value = if let Some(Token::Semicolon) = self.current_token() {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.expect(Token::Semicolon, "Expected ';' after return statement.")?;
+        Ok(Statement::Return(value))
+    }
+
+    /// Parses an import such as `import_venti "utils.venti";`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Statement::Import` or a `VentiError` if the import is invalid.
+    fn import_statement(&mut self) -> Result<Statement<'a>, VentiError> {
+        let path = match self.current_token() {
+            Some(Token::StringLiteral(path)) => path.clone(),
+            token => {
+                return Err(VentiError::SyntaxError(
+                    format!(
+                        "Expected a file path after import_venti. Found: {:?}",
+                        token
+                    ),
+                    self.location(),
+                ))
+            }
+        };
+        self.advance(); // Consume the path
+        self.expect(Token::Semicolon, "Expected ';' after import.")?;
+        Ok(Statement::Import(path))
+    }
+
+    /// Parses the rest of a C function declaration such as
+    /// `extern_venti fn puts(s: string) -> int;` after the `extern_venti`.
+    ///
+    /// `fn_venti` and `funcVenti` are accepted in place of `fn`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Statement::Extern` or a `VentiError` if the declaration is invalid.
+    fn extern_declaration(&mut self) -> Result<Statement<'a>, VentiError> {
+        self.fn_keyword("Expected 'fn' after 'extern_venti'.")?;
+        let identifier = if let Some(Token::Identifier(id)) = self.current_token() {
+            id.clone()
+        } else {
+            return Err(VentiError::SyntaxError(
+                "Expected function name in extern declaration.".to_string(),
+                self.location(),
+            ));
+        };
+        self.advance(); // Consume function name
+
+        let params = self.parameters()?;
+        if let Some(param) = params.iter().find(|param| param.var_type.is_none()) {
+            return Err(VentiError::SyntaxError(
+                format!(
+                    "Parameter '{}' of extern function '{}' needs a type.",
+                    param.name, identifier
+                ),
+                self.location(),
+            ));
+        }
+        let returns = if let Some(Token::Arrow) = self.current_token() {
+            self.advance(); // Consume '->'
+            Some(self.var_type()?)
+        } else {
+            None
+        };
+        self.expect(Token::Semicolon, "Expected ';' after extern declaration.")?;
+        Ok(Statement::Extern {
+            identifier,
+            params,
+            returns,
+        })
+    }
+
+    /// Consumes the `fn` that starts a declaration without a body, for which `fn_venti`
+    /// and `funcVenti` are accepted too.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The error message to report if no `fn` is present.
+    fn fn_keyword(&mut self, message: &str) -> Result<(), VentiError> {
+        match self.current_token() {
+            Some(Token::Func) => self.advance(), // Consume 'fn_venti'
+            Some(Token::Identifier(keyword)) if keyword == "fn" => self.advance(), // Consume 'fn'
+            other => {
+                return Err(VentiError::SyntaxError(
+                    format!("{} Found: {:?}", message, other),
+                    self.location(),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses the name after `trait_venti` or in `impl_venti Shape for`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the name or a `VentiError` if there is none.
+    fn trait_name(&mut self) -> Result<String, VentiError> {
+        let name = if let Some(Token::Identifier(name)) = self.current_token() {
+            name.clone()
+        } else {
+            return Err(VentiError::SyntaxError(
+                format!("Expected a trait name. Found: {:?}", self.current_token()),
+                self.location(),
+            ));
+        };
+        self.advance(); // Consume the trait name
+        Ok(name)
+    }
+
+    /// Parses the parameters of a trait method, whose first parameter must be a bare
+    /// `self`.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The name of the method, for error messages.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing all the parameters, `self` included, or a `VentiError`.
+    fn method_parameters(&mut self, method: &str) -> Result<Vec<Parameter>, VentiError> {
+        let params = self.parameters()?;
+        match params.first() {
+            Some(param) if param.name == "self" && param.var_type.is_none() => Ok(params),
+            _ => Err(self.missing_self(method)),
+        }
+    }
+
+    /// Parses the rest of a trait such as `trait_venti Shape { fn area(self); }` after
+    /// the `trait_venti`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Statement::Trait` or a `VentiError` if the trait is invalid.
+    fn trait_declaration(&mut self) -> Result<Statement<'a>, VentiError> {
+        let identifier = self.trait_name()?;
+        self.expect(Token::LBrace, "Expected '{' after the trait name.")?;
+        let mut methods = Vec::new();
+        while self.current_token() != Some(&Token::RBrace) {
+            if self.current_token().is_none() {
+                return Err(VentiError::SyntaxError(
+                    "Expected '}' to close the trait.".to_string(),
+                    self.location(),
+                )
+                .with_code(ErrorCode::UnclosedBlock));
+            }
+            self.fn_keyword("Expected 'fn' before a trait method.")?;
+            let name = if let Some(Token::Identifier(name)) = self.current_token() {
+                name.clone()
+            } else {
+                return Err(VentiError::SyntaxError(
+                    "Expected a method name in the trait.".to_string(),
+                    self.location(),
+                ));
+            };
+            self.advance(); // Consume the method name
+            let mut params = self.method_parameters(&name)?;
+            params.remove(0);
+            self.expect(Token::Semicolon, "Expected ';' after a trait method.")?;
+            methods.push(TraitMethod { name, params });
+        }
+        self.advance(); // Consume '}'
+        Ok(Statement::Trait {
+            identifier,
+            methods,
+        })
+    }
+
+    /// Parses the rest of an implementation such as
+    /// `impl_venti Shape for float { fn_venti area(self) { ... } }` after the `impl_venti`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Statement::Impl` or a `VentiError` if it is invalid.
+    fn impl_block(&mut self) -> Result<Statement<'a>, VentiError> {
+        let trait_name = self.trait_name()?;
+        match self.current_token() {
+            Some(Token::Identifier(keyword)) if keyword == "for" => self.advance(), // Consume 'for'
+            other => {
+                return Err(VentiError::SyntaxError(
+                    format!("Expected 'for' after the trait name. Found: {:?}", other),
+                    self.location(),
+                ))
+            }
+        }
+        let target = self.var_type()?;
+        let mut methods = self.block()?;
+        for method in &mut methods {
+            match method {
+                Statement::Function {
+                    identifier, params, ..
+                } => match params.first_mut() {
+                    Some(param) if param.name == "self" && param.var_type.is_none() => {
+                        param.var_type = Some(target.clone())
+                    }
+                    _ => return Err(self.missing_self(identifier)),
+                },
+                Statement::Comment { .. } | Statement::Line(_) => {}
+                _ => {
+                    return Err(VentiError::SyntaxError(
+                        format!(
+                            "An implementation of '{}' may only contain methods.",
+                            trait_name
+                        ),
+                        self.location(),
+                    ))
                 }
-                _ => break,
             }
         }
-        Ok(left)
+        Ok(Statement::Impl {
+            trait_name,
+            target,
+            methods,
+        })
+    }
+
+    /// The error for a method whose first parameter is not a bare `self`.
+    fn missing_self(&mut self, method: &str) -> VentiError {
+        VentiError::SyntaxError(
+            format!(
+                "The first parameter of method '{}' must be an untyped 'self'.",
+                method
+            ),
+            self.location(),
+        )
     }
 
-    /// Parses a factor, which may include multiplication and division.
+    /// Parses a test such as `test_venti "adds" { assert_venti add(1, 2) == 3; }`.
     ///
     /// # Returns
     ///
-    /// A `Result` containing an `Expr` or a `VentiError` if the factor is invalid.
-    fn factor(&mut self) -> Result<Expr, VentiError> {
-        let mut left = self.primary()?;
-        while let Some(token) = self.current_token() {
-            match token {
-                Token::Star => {
-                    self.advance();
-                    let right = self.primary()?;
-                    left = Expr::BinaryOp(Box::new(left), BinOp::Multiply, Box::new(right));
+    /// A `Result` containing a `Statement::Test` or a `VentiError` if the test is invalid.
+    fn test_definition(&mut self) -> Result<Statement<'a>, VentiError> {
+        let name = match self.current_token() {
+            Some(Token::StringLiteral(name)) => name.clone(),
+            token => {
+                return Err(VentiError::SyntaxError(
+                    format!("Expected a test name after test_venti. Found: {:?}", token),
+                    self.location(),
+                ))
+            }
+        };
+        self.advance(); // Consume the name
+        let body = self.block()?;
+        Ok(Statement::Test { name, body })
+    }
+
+    /// Parses a match statement such as `match_venti x { 1 => { ... }, _ => { ... } }`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Statement::Match` or a `VentiError` if the statement is invalid.
+    fn match_statement(&mut self) -> Result<Statement<'a>, VentiError> {
+        let scrutinee = self.expression()?;
+        self.expect(Token::LBrace, "Expected '{' after match scrutinee.")?;
+        let mut arms = Vec::new();
+        while self.current_token() != Some(&Token::RBrace) {
+            let pattern = self.pattern()?;
+            self.expect(Token::FatArrow, "Expected '=>' after match pattern.")?;
+            let body = self.block()?;
+            arms.push(MatchArm { pattern, body });
+
+            // The comma between block-bodied arms is optional
+            if let Some(Token::Comma) = self.current_token() {
+                self.advance(); // Consume ','
+            }
+            if self.current_token().is_none() {
+                return Err(VentiError::SyntaxError(
+                    "Expected '}' to close the match.".to_string(),
+                    self.location(),
+                )
+                .with_code(ErrorCode::UnclosedBlock));
+            }
+        }
+        self.advance(); // Consume '}'
+        Ok(Statement::Match { scrutinee, arms })
+    }
+
+    /// Parses a for loop such as `for_venti i in 0..10 { ... }`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Statement::For` or a `VentiError` if the loop is invalid.
+    fn for_statement(&mut self) -> Result<Statement<'a>, VentiError> {
+        let variable = if let Some(Token::Identifier(id)) = self.current_token() {
+            id.clone()
+        } else {
+            return Err(VentiError::SyntaxError(
+                "Expected loop variable after 'for_venti'.".to_string(),
+                self.location(),
+            ));
+        };
+        self.advance(); // Consume loop variable
+        self.expect(Token::In, "Expected 'in' after loop variable.")?;
+        let iterable = self.expression()?;
+        let body = self.block()?;
+        Ok(Statement::For {
+            variable,
+            iterable,
+            body,
+        })
+    }
+
+    /// Parses a try statement such as `try_venti { ... } catch_venti (e) { ... }`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Statement::Try` or a `VentiError` if the statement is invalid.
+    fn try_statement(&mut self) -> Result<Statement<'a>, VentiError> {
+        let body = self.block()?;
+        self.expect(Token::Catch, "Expected 'catch_venti' after the try block.")?;
+        self.expect(Token::LParen, "Expected '(' after 'catch_venti'.")?;
+        let error = if let Some(Token::Identifier(id)) = self.current_token() {
+            id.clone()
+        } else {
+            return Err(VentiError::SyntaxError(
+                "Expected a name for the error after 'catch_venti ('.".to_string(),
+                self.location(),
+            ));
+        };
+        self.advance(); // Consume the error's name
+        self.expect(Token::RParen, "Expected ')' after the error's name.")?;
+        let handler = self.block()?;
+        Ok(Statement::Try {
+            body,
+            error,
+            handler,
+        })
+    }
+
+    /// Parses a match pattern: an integer, a string, or the wildcard `_`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Pattern` or a `VentiError` if no pattern is present.
+    fn pattern(&mut self) -> Result<Pattern, VentiError> {
+        let pattern = match self.current_token().cloned() {
+            Some(Token::NumberLiteral(n)) => Pattern::Number(n),
+            Some(Token::Minus) => {
+                self.advance(); // Consume '-'
+                if let Some(Token::NumberLiteral(n)) = self.current_token() {
+                    Pattern::Number(-n)
+                } else {
+                    return Err(VentiError::SyntaxError(
+                        "Expected a number after '-' in match pattern.".to_string(),
+                        self.location(),
+                    ));
                 }
-                Token::Slash => {
-                    self.advance();
-                    let right = self.primary()?;
-                    left = Expr::BinaryOp(Box::new(left), BinOp::Divide, Box::new(right));
+            }
+            Some(Token::StringLiteral(s)) => Pattern::String(s),
+            Some(Token::Identifier(id)) if id == "_" => Pattern::Wildcard,
+            other => {
+                return Err(VentiError::SyntaxError(
+                    format!("Expected a match pattern, found {:?}", other),
+                    self.location(),
+                ))
+            }
+        };
+        self.advance(); // Consume the pattern
+        Ok(pattern)
+    }
+
+    /// Parses an expression using precedence climbing.
+    ///
+    /// A range such as `1..10` or `1..=10` binds more loosely than every operator and
+    /// does not chain, so it is handled here rather than in the binding-power table.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `Expr` or a `VentiError` if the expression is invalid.
+    fn expression(&mut self) -> Result<Expr<'a>, VentiError> {
+        self.nested(|parser| {
+            let left = parser.prefix()?;
+            parser.expression_from(left)
+        })
+    }
+
+    /// Parses the rest of an expression whose leftmost operand is already parsed.
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The leftmost operand of the expression.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `Expr` or a `VentiError` if the expression is invalid.
+    fn expression_from(&mut self, left: Expr<'a>) -> Result<Expr<'a>, VentiError> {
+        let start = self.infix(left, 0)?;
+        let inclusive = match self.current_token() {
+            Some(Token::DotDot) => false,
+            Some(Token::DotDotEqual) => true,
+            _ => return Ok(start),
+        };
+        self.advance(); // Consume '..' or '..='
+        let end = self.expression_bp(0)?;
+        let span = start.span.to(end.span);
+        Ok(Expr::new(
+            ExprKind::Range {
+                start: self.alloc(start),
+                end: self.alloc(end),
+                inclusive,
+            },
+            span,
+        ))
+    }
+
+    /// Parses an expression whose infix operators bind at least as tightly as `min_bp`.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_bp` - The minimum left binding power an operator needs to be consumed here.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `Expr` or a `VentiError` if the expression is invalid.
+    fn expression_bp(&mut self, min_bp: u8) -> Result<Expr<'a>, VentiError> {
+        self.nested(|parser| {
+            let left = parser.prefix()?;
+            parser.infix(left, min_bp)
+        })
+    }
+
+    /// Parses the infix operators that follow `left` and bind at least as tightly as
+    /// `min_bp`.
+    ///
+    /// Operator precedence and associativity come entirely from `infix_binding_power`,
+    /// so supporting a new binary operator only requires a new entry in that table. The
+    /// one exception is `as`, whose right side is a type name rather than an expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The operand the operators apply to.
+    /// * `min_bp` - The minimum left binding power an operator needs to be consumed here.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `Expr` or a `VentiError` if the expression is invalid.
+    fn infix(&mut self, mut left: Expr<'a>, min_bp: u8) -> Result<Expr<'a>, VentiError> {
+        loop {
+            if let Some(Token::As) = self.current_token() {
+                if CAST_BINDING_POWER < min_bp {
+                    break;
                 }
-                _ => break,
+                self.advance(); // Consume 'as'
+                let start = left.span;
+                let target = self.var_type()?;
+                left = self.node(
+                    ExprKind::Cast {
+                        value: self.alloc(left),
+                        target,
+                    },
+                    start,
+                );
+                continue;
+            }
+            let Some((op, left_bp, right_bp)) = self.current_token().and_then(infix_binding_power)
+            else {
+                break;
+            };
+            if left_bp < min_bp {
+                break;
             }
+            self.advance(); // Consume the operator
+            let right = self.expression_bp(right_bp)?;
+            let span = left.span.to(right.span);
+            left = Expr::new(
+                ExprKind::BinaryOp(self.alloc(left), op, self.alloc(right)),
+                span,
+            );
         }
         Ok(left)
     }
 
+    /// Parses a prefix (unary) expression, falling back to a primary expression.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `Expr` or a `VentiError` if the expression is invalid.
+    fn prefix(&mut self) -> Result<Expr<'a>, VentiError> {
+        let start = self.current_span();
+        let op = match self.current_token() {
+            Some(Token::Minus) => UnaryOp::Negate,
+            Some(Token::Bang) => UnaryOp::Not,
+            Some(Token::Await) => {
+                self.advance(); // Consume 'await'
+                let operand = self.expression_bp(PREFIX_BINDING_POWER)?;
+                return Ok(self.node(ExprKind::Await(self.alloc(operand)), start));
+            }
+            _ => {
+                let expr = self.primary()?;
+                return self.postfix(expr);
+            }
+        };
+        self.advance(); // Consume the operator
+        let operand = self.expression_bp(PREFIX_BINDING_POWER)?;
+        Ok(self.node(ExprKind::UnaryOp(op, self.alloc(operand)), start))
+    }
+
+    /// Parses any chain of postfix operations following an already-parsed expression,
+    /// such as the calls in `make_adder(1)(2)`, the indexing in `m[1][0]`, or the method
+    /// calls in `name.trim().upper()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - The expression the postfix operations apply to.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `Expr` or a `VentiError` if a postfix operation is invalid.
+    fn postfix(&mut self, mut expr: Expr<'a>) -> Result<Expr<'a>, VentiError> {
+        loop {
+            let start = expr.span;
+            match self.current_token() {
+                Some(Token::LParen) => {
+                    let args = self.arguments()?;
+                    expr = self.node(
+                        ExprKind::Call {
+                            callee: self.alloc(expr),
+                            args,
+                        },
+                        start,
+                    );
+                }
+                Some(Token::LBracket) => {
+                    self.advance(); // Consume '['
+                    let index = self.expression()?;
+                    self.expect(Token::RBracket, "Expected ']' after index.")?;
+                    expr = self.node(
+                        ExprKind::Index {
+                            target: self.alloc(expr),
+                            index: self.alloc(index),
+                        },
+                        start,
+                    );
+                }
+                Some(Token::Dot) => {
+                    self.advance(); // Consume '.'
+                    let name = if let Some(Token::Identifier(name)) = self.current_token() {
+                        name.clone()
+                    } else {
+                        return Err(VentiError::SyntaxError(
+                            "Expected method name after '.'".to_string(),
+                            self.location(),
+                        ));
+                    };
+                    self.advance(); // Consume method name
+                    let args = self.arguments()?;
+                    expr = self.node(
+                        ExprKind::MethodCall {
+                            receiver: self.alloc(expr),
+                            name,
+                            args,
+                        },
+                        start,
+                    );
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    /// Parses a parenthesized, comma-separated argument list.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the argument expressions or a `VentiError` if the list is malformed.
+    fn arguments(&mut self) -> Result<Vec<Expr<'a>>, VentiError> {
+        self.expect(Token::LParen, "Expected '(' to start argument list.")?;
+        let mut args = Vec::new();
+        while self.current_token() != Some(&Token::RParen) {
+            args.push(self.expression()?);
+            match self.current_token() {
+                Some(Token::Comma) => self.advance(), // Consume ','
+                Some(Token::RParen) => break,
+                _ => {
+                    return Err(VentiError::SyntaxError(
+                        "Expected ',' or ')' in argument list".to_string(),
+                        self.location(),
+                    ))
+                }
+            }
+        }
+        self.advance(); // Consume ')'
+        Ok(args)
+    }
+
     /// Parses a primary expression, which can be a number, string, identifier, or parenthesized expression.
     ///
     /// # Returns
     ///
     /// A `Result` containing an `Expr` or a `VentiError` if the primary expression is invalid.
-    fn primary(&mut self) -> Result<Expr, VentiError> {
-        match self.current_token() {
+    fn primary(&mut self) -> Result<Expr<'a>, VentiError> {
+        let start = self.current_span();
+        match self.current_token().cloned() {
             Some(Token::NumberLiteral(n)) => {
                 self.advance(); // Consume the number literal token
-                Ok(Expr::Number(n.clone()))
+                Ok(self.node(ExprKind::Number(n), start))
+            }
+            Some(Token::FloatLiteral(f)) => {
+                self.advance(); // Consume the float literal token
+                Ok(self.node(ExprKind::Float(f), start))
             }
             Some(Token::StringLiteral(s)) => {
                 self.advance(); // Consume the string literal token
-                Ok(Expr::String(s.clone()))
+                Ok(self.node(ExprKind::String(s), start))
+            }
+            Some(Token::BooleanLiteral(b)) => {
+                self.advance(); // Consume 'true' or 'false'
+                Ok(self.node(ExprKind::Boolean(b), start))
             }
             Some(Token::Identifier(id)) => {
                 self.advance(); // Consume the identifier token
-                Ok(Expr::Identifier(id.clone()))
+                Ok(self.node(ExprKind::Identifier(id), start))
+            }
+            Some(Token::Nothing) => {
+                self.advance(); // Consume 'nothing'
+                Ok(self.node(ExprKind::Null, start))
             }
             Some(Token::LParen) => {
                 self.advance(); // consume '('
@@ -223,15 +1409,67 @@ impl Parser {
                     self.advance(); // consume ')'
                     Ok(expr)
                 } else {
-                    Err(VentiError::SyntaxError("Expected ')'".to_string()))
+                    Err(VentiError::SyntaxError(
+                        "Expected ')'".to_string(),
+                        self.location(),
+                    ))
                 }
             }
             Some(Token::LBracket) => self.parse_array(),
-            _ => Err(VentiError::SyntaxError(format!(
-                "Unexpected token: {:?}",
-                self.current_token()
-            ))),
+            Some(Token::LBrace) => self.parse_map(),
+            Some(Token::Spawn) => {
+                self.advance(); // Consume 'spawn_venti'
+                let body = self.block()?;
+                Ok(self.node(ExprKind::Spawn(body), start))
+            }
+            Some(Token::Pipe) | Some(Token::OrOr) => self.lambda(),
+            _ => Err(VentiError::SyntaxError(
+                format!("Unexpected token: {:?}", self.current_token()),
+                self.location(),
+            )),
+        }
+    }
+
+    /// Parses a lambda expression such as `|x, y| x + y`.
+    ///
+    /// A lambda without parameters is written `|| expr`, which the lexer produces as a
+    /// single `||` token.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `Expr::Lambda` or a `VentiError` if the lambda is invalid.
+    fn lambda(&mut self) -> Result<Expr<'a>, VentiError> {
+        let start = self.current_span();
+        let mut params = Vec::new();
+        if let Some(Token::OrOr) = self.current_token() {
+            self.advance(); // Consume '||'
+        } else {
+            self.advance(); // Consume '|'
+            while self.current_token() != Some(&Token::Pipe) {
+                params.push(self.parameter()?);
+
+                match self.current_token() {
+                    Some(Token::Comma) => self.advance(), // Consume ','
+                    Some(Token::Pipe) => break,
+                    _ => {
+                        return Err(VentiError::SyntaxError(
+                            "Expected ',' or '|' in lambda parameters.".to_string(),
+                            self.location(),
+                        ))
+                    }
+                }
+            }
+            self.advance(); // Consume '|'
         }
+
+        let body = self.expression()?;
+        Ok(self.node(
+            ExprKind::Lambda {
+                params,
+                body: self.alloc(body),
+            },
+            start,
+        ))
     }
 
     /// Parses an array literal.
@@ -239,7 +1477,8 @@ impl Parser {
     /// # Returns
     ///
     /// A `Result` containing an `Expr::Array` or a `VentiError` if the array is invalid.
-    fn parse_array(&mut self) -> Result<Expr, VentiError> {
+    fn parse_array(&mut self) -> Result<Expr<'a>, VentiError> {
+        let start = self.current_span();
         self.advance(); // consume '['
         let mut elements = Vec::new();
         while self.current_token() != Some(&Token::RBracket) {
@@ -251,39 +1490,141 @@ impl Parser {
             }
         }
         self.advance(); // consume ']'
-        Ok(Expr::Array(elements))
+        Ok(self.node(ExprKind::Array(elements), start))
+    }
+
+    /// Parses a map literal such as `{ "a": 1, "b": 2 }`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `Expr::Map` or a `VentiError` if the map is invalid.
+    fn parse_map(&mut self) -> Result<Expr<'a>, VentiError> {
+        let start = self.current_span();
+        self.advance(); // Consume '{'
+        let mut entries = Vec::new();
+        while self.current_token() != Some(&Token::RBrace) {
+            let key = self.expression()?;
+            self.expect(Token::Colon, "Expected ':' after map key.")?;
+            let value = self.expression()?;
+            entries.push((key, value));
+
+            match self.current_token() {
+                Some(Token::Comma) => self.advance(), // Consume ','
+                Some(Token::RBrace) => break,
+                _ => {
+                    return Err(VentiError::SyntaxError(
+                        "Expected ',' or '}' in map literal.".to_string(),
+                        self.location(),
+                    ))
+                }
+            }
+        }
+        self.advance(); // Consume '}'
+        Ok(self.node(ExprKind::Map(entries), start))
     }
 
-    /// Parses either a function call or a variable assignment.
+    /// Parses a statement that starts with an identifier: a function call, an assignment,
+    /// or an expression such as `x * 2;`.
     ///
     /// # Returns
     ///
-    /// A `Result` containing either a `Statement::FunctionCall` or `Statement::VariableAssignment`, or a `VentiError` if invalid.
-    fn function_or_variable(&mut self) -> Result<Statement, VentiError> {
+    /// A `Result` containing a `Statement::FunctionCall`, `Statement::VariableAssignment`, `Statement::IndexAssignment` or `Statement::Expression`, or a `VentiError` if invalid.
+    fn function_or_variable(&mut self) -> Result<Statement<'a>, VentiError> {
+        let start = self.current_span();
         let identifier = if let Some(Token::Identifier(id)) = self.current_token() {
             id.clone()
         } else {
-            return Err(VentiError::SyntaxError("Expected identifier".to_string()));
+            return Err(VentiError::SyntaxError(
+                "Expected identifier".to_string(),
+                self.location(),
+            ));
         };
 
         self.advance(); // Consume identifier
+        let variable =
+            |identifier: &str| Expr::new(ExprKind::Identifier(identifier.to_string()), start);
 
         if let Some(Token::LParen) = self.current_token() {
-            self.advance(); // Consume '('
-            let mut args = Vec::new();
-            while self.current_token() != Some(&Token::RParen) {
-                args.push(self.expression()?);
-                if let Some(Token::Comma) = self.current_token() {
-                    self.advance(); // Consume ','
-                } else if let Some(Token::RParen) = self.current_token() {
-                    break;
-                }
+            let args = self.arguments()?;
+            if let Some(Token::Semicolon) = self.current_token() {
+                self.advance(); // Consume ';'
+                return Ok(Statement::FunctionCall { identifier, args });
+            }
+            // A call that starts a longer expression, e.g. `add(1, 2) * 3;`
+            let call = self.node(
+                ExprKind::Call {
+                    callee: self.alloc(variable(&identifier)),
+                    args,
+                },
+                start,
+            );
+            let call = self.postfix(call)?;
+            return self.expression_statement(call);
+        }
+
+        // A method call used as a statement, e.g. `arr.push(4);`
+        if let Some(Token::Dot) = self.current_token() {
+            let expr = self.postfix(variable(&identifier))?;
+            return self.expression_statement(expr);
+        }
+
+        // An increment or decrement, e.g. `count++;`, is sugar for `count = count + 1;`
+        let step = match self.current_token() {
+            Some(Token::PlusPlus) => Some(BinOp::Add),
+            Some(Token::MinusMinus) => Some(BinOp::Subtract),
+            _ => None,
+        };
+        if let Some(op) = step {
+            self.advance(); // Consume '++' or '--'
+            self.expect(
+                Token::Semicolon,
+                "Expected ';' after increment or decrement.",
+            )?;
+            let one = self.node(ExprKind::Number(1), start);
+            let value = self.node(
+                ExprKind::BinaryOp(self.alloc(variable(&identifier)), op, self.alloc(one)),
+                start,
+            );
+            return Ok(Statement::VariableAssignment { identifier, value });
+        }
+
+        // An element assignment, e.g. `scores["alice"] = 10;`
+        if let Some(Token::LBracket) = self.current_token() {
+            let target = self.postfix(variable(&identifier))?;
+            // Without '=' the element is only read, e.g. `scores["alice"] + 1;`
+            if !matches!(self.current_token(), Some(Token::Equals)) {
+                return self.expression_statement(target);
             }
-            self.advance(); // Consume ')'
-            return Ok(Statement::FunctionCall { identifier, args });
+            let ExprKind::Index { target, index } = target.kind else {
+                return Err(VentiError::SyntaxError(
+                    "Expected an indexed target before '='.".to_string(),
+                    self.location(),
+                ));
+            };
+            self.advance(); // Consume '='
+            let value = self.expression()?;
+            self.expect(Token::Semicolon, "Expected ';' after assignment.")?;
+            return Ok(Statement::IndexAssignment {
+                target: target.take(),
+                index: index.take(),
+                value,
+            });
+        }
+
+        // A variable used in an expression statement, e.g. `x;` or `x * 2;`
+        let current = self.current_token();
+        if matches!(
+            current,
+            Some(Token::Semicolon | Token::As | Token::DotDot | Token::DotDotEqual)
+        ) || current.and_then(infix_binding_power).is_some()
+        {
+            return self.expression_statement(variable(&identifier));
         }
 
         // Handle variable assignment if no '(' is found
+        if let Some(Token::Equals) = self.current_token() {
+            self.advance(); // Consume '='
+        }
         let value = self.expression()?;
         if let Some(Token::Semicolon) = self.current_token() {
             self.advance(); // Consume ';'
@@ -292,7 +1633,8 @@ impl Parser {
 
         Err(VentiError::SyntaxError(
             "Expected ';' after variable assignment.".to_string(),
-        ))
+            self.location(),
+        )
+        .with_code(ErrorCode::MissingSemicolon))
     }
 }
-
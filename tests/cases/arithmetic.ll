@@ -0,0 +1,24 @@
+target datalayout = "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+target triple = "x86_64-unknown-linux-gnu"
+
+@venti_argc = weak global i64 0
+@venti_argv = weak global ptr null
+@_V4main1a = global i64 7
+@_V4main1b = global i64 19
+@_V4main1c = global double 9.500000e+00
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+@format.1 = private unnamed_addr constant [4 x i8] c"%f\0A\00", align 1
+@format.2 = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+
+declare i32 @printf(ptr, ...)
+
+define i32 @main(i32 %0, ptr %1) {
+entry:
+  %argc = sext i32 %0 to i64
+  store i64 %argc, ptr @venti_argc, align 4
+  store ptr %1, ptr @venti_argv, align 8
+  %printf_call = call i32 (ptr, ...) @printf(ptr @format, i64 19)
+  %printf_call1 = call i32 (ptr, ...) @printf(ptr @format.1, double 9.500000e+00)
+  %printf_call2 = call i32 (ptr, ...) @printf(ptr @format.2, i64 29)
+  ret i32 0
+}
@@ -0,0 +1,526 @@
+target datalayout = "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+target triple = "x86_64-unknown-linux-gnu"
+
+%venti.map = type { ptr }
+%venti.buffer = type { ptr, i64 }
+%venti.handler = type { ptr, ptr, [64 x i64] }
+
+@venti_argc = weak global i64 0
+@venti_argv = weak global ptr null
+@_V4main7numbers = global [3 x i64] [i64 1, i64 2, i64 3]
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+@format.1 = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+@str = private unnamed_addr constant { i64, [4 x i8] } { i64 -1, [4 x i8] c"ana\00" }, align 8
+@str.2 = private unnamed_addr constant { i64, [3 x i8] } { i64 -1, [3 x i8] c"bo\00" }, align 8
+@_V4main4ages = global %venti.map zeroinitializer
+@str.3 = private unnamed_addr constant { i64, [3 x i8] } { i64 -1, [3 x i8] c"cy\00" }, align 8
+@str.4 = private unnamed_addr constant { i64, [4 x i8] } { i64 -1, [4 x i8] c"ana\00" }, align 8
+@venti_handler_key.5 = weak global i64 0
+@uncaught = private unnamed_addr constant [19 x i8] c"Runtime Error: %s\0A\00", align 1
+@error = private unnamed_addr constant [24 x i8] c"key %s not found in map\00", align 1
+@format.6 = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+@error.7 = private unnamed_addr constant [41 x i8] c"could not allocate a buffer of %ld slots\00", align 1
+@_V4main3buf = global %venti.buffer zeroinitializer
+@error.8 = private unnamed_addr constant [54 x i8] c"index %ld is out of bounds for a buffer of length %ld\00", align 1
+@format.9 = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+
+declare i32 @printf(ptr, ...)
+
+define i32 @main(i32 %0, ptr %1) {
+entry:
+  %temporary = alloca %venti.map, align 8
+  store %venti.map zeroinitializer, ptr %temporary, align 8
+  %argc = sext i32 %0 to i64
+  store i64 %argc, ptr @venti_argc, align 4
+  store ptr %1, ptr @venti_argv, align 8
+  %numbers = load [3 x i64], ptr @_V4main7numbers, align 4
+  %element = extractvalue [3 x i64] %numbers, 2
+  %printf_call = call i32 (ptr, ...) @printf(ptr @format, i64 %element)
+  %printf_call1 = call i32 (ptr, ...) @printf(ptr @format.1, i64 3)
+  %map = call ptr @venti_map_new(i64 8)
+  call void @venti_map_insert(ptr %map, ptr getelementptr inbounds ({ i64, [4 x i8] }, ptr @str, i32 0, i32 1), i64 30)
+  call void @venti_map_insert(ptr %map, ptr getelementptr inbounds ({ i64, [3 x i8] }, ptr @str.2, i32 0, i32 1), i64 25)
+  %map_value = insertvalue %venti.map undef, ptr %map, 0
+  %map_header = extractvalue %venti.map %map_value, 0
+  call void @venti_map_retain(ptr %map_header)
+  %previous = load %venti.map, ptr %temporary, align 8
+  store %venti.map %map_value, ptr %temporary, align 8
+  %map_header2 = extractvalue %venti.map %previous, 0
+  call void @venti_map_release(ptr %map_header2)
+  %map_header3 = extractvalue %venti.map %map_value, 0
+  call void @venti_map_retain(ptr %map_header3)
+  store %venti.map %map_value, ptr @_V4main4ages, align 8
+  %temporary4 = load %venti.map, ptr %temporary, align 8
+  %map_header5 = extractvalue %venti.map %temporary4, 0
+  call void @venti_map_release(ptr %map_header5)
+  store %venti.map zeroinitializer, ptr %temporary, align 8
+  %ages = load %venti.map, ptr @_V4main4ages, align 8
+  %map_header6 = extractvalue %venti.map %ages, 0
+  call void @venti_map_insert(ptr %map_header6, ptr getelementptr inbounds ({ i64, [3 x i8] }, ptr @str.3, i32 0, i32 1), i64 41)
+  %ages7 = load %venti.map, ptr @_V4main4ages, align 8
+  %map_header8 = extractvalue %venti.map %ages7, 0
+  %map_get = call i64 @venti_map_get(ptr %map_header8, ptr getelementptr inbounds ({ i64, [4 x i8] }, ptr @str.4, i32 0, i32 1))
+  %printf_call9 = call i32 (ptr, ...) @printf(ptr @format.6, i64 %map_get)
+  %buffer_data = call ptr @venti_buffer_alloc(i64 4)
+  %buffer_data10 = insertvalue %venti.buffer undef, ptr %buffer_data, 0
+  %buffer = insertvalue %venti.buffer %buffer_data10, i64 4, 1
+  store %venti.buffer %buffer, ptr @_V4main3buf, align 8
+  %buf = load %venti.buffer, ptr @_V4main3buf, align 8
+  %buffer_data11 = extractvalue %venti.buffer %buf, 0
+  %buffer_len = extractvalue %venti.buffer %buf, 1
+  %slot = call ptr @venti_buffer_slot(ptr %buffer_data11, i64 %buffer_len, i64 0)
+  store i64 9, ptr %slot, align 4
+  %buf12 = load %venti.buffer, ptr @_V4main3buf, align 8
+  %buffer_data13 = extractvalue %venti.buffer %buf12, 0
+  %buffer_len14 = extractvalue %venti.buffer %buf12, 1
+  %slot15 = call ptr @venti_buffer_slot(ptr %buffer_data13, i64 %buffer_len14, i64 0)
+  %slot_value = load i64, ptr %slot15, align 4
+  %printf_call16 = call i32 (ptr, ...) @printf(ptr @format.9, i64 %slot_value)
+  %buf17 = load %venti.buffer, ptr @_V4main3buf, align 8
+  %buffer_data18 = extractvalue %venti.buffer %buf17, 0
+  call void @free(ptr %buffer_data18)
+  store %venti.buffer zeroinitializer, ptr @_V4main3buf, align 8
+  ret i32 0
+}
+
+define internal ptr @venti_map_new(i64 %0) {
+entry:
+  %header = call ptr @malloc(i64 ptrtoint (ptr getelementptr ({ i64, i64, ptr, ptr, i64 }, ptr null, i32 1) to i64))
+  %keys = call ptr @calloc(i64 %0, i64 8)
+  %values = call ptr @calloc(i64 %0, i64 8)
+  %field = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %header, i32 0, i32 0
+  store i64 %0, ptr %field, align 4
+  %field1 = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %header, i32 0, i32 1
+  store i64 0, ptr %field1, align 4
+  %field2 = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %header, i32 0, i32 2
+  store ptr %keys, ptr %field2, align 8
+  %field3 = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %header, i32 0, i32 3
+  store ptr %values, ptr %field3, align 8
+  %field4 = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %header, i32 0, i32 4
+  store i64 0, ptr %field4, align 4
+  ret ptr %header
+}
+
+declare ptr @malloc(i64)
+
+declare ptr @calloc(i64, i64)
+
+define internal void @venti_map_insert(ptr %0, ptr %1, i64 %2) {
+entry:
+  %len = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 1
+  %len1 = load i64, ptr %len, align 4
+  %capacity = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 0
+  %capacity2 = load i64, ptr %capacity, align 4
+  %next_len = add i64 %len1, 1
+  %needed = mul i64 %next_len, 2
+  %is_full = icmp ugt i64 %needed, %capacity2
+  br i1 %is_full, label %grow, label %insert
+
+grow:                                             ; preds = %entry
+  call void @venti_map_grow(ptr %0)
+  br label %insert
+
+insert:                                           ; preds = %grow, %entry
+  %slot = call i64 @venti_map_find_slot(ptr %0, ptr %1)
+  %keys = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 2
+  %keys3 = load ptr, ptr %keys, align 8
+  %key_slot = getelementptr inbounds i64, ptr %keys3, i64 %slot
+  %existing = load ptr, ptr %key_slot, align 8
+  %is_new = icmp eq ptr %existing, null
+  br i1 %is_new, label %fresh, label %store
+
+fresh:                                            ; preds = %insert
+  call void @venti_str_retain(ptr %1)
+  store ptr %1, ptr %key_slot, align 8
+  %field = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 1
+  store i64 %next_len, ptr %field, align 4
+  br label %store
+
+store:                                            ; preds = %fresh, %insert
+  %values = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 3
+  %values4 = load ptr, ptr %values, align 8
+  %value_slot = getelementptr inbounds i64, ptr %values4, i64 %slot
+  store i64 %2, ptr %value_slot, align 4
+  ret void
+}
+
+define internal void @venti_map_grow(ptr %0) {
+entry:
+  %old_capacity = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 0
+  %old_capacity1 = load i64, ptr %old_capacity, align 4
+  %old_keys = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 2
+  %old_keys2 = load ptr, ptr %old_keys, align 8
+  %old_values = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 3
+  %old_values3 = load ptr, ptr %old_values, align 8
+  %capacity = mul i64 %old_capacity1, 2
+  %keys = call ptr @calloc(i64 %capacity, i64 8)
+  %values = call ptr @calloc(i64 %capacity, i64 8)
+  %field = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 0
+  store i64 %capacity, ptr %field, align 4
+  %field4 = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 2
+  store ptr %keys, ptr %field4, align 8
+  %field5 = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 3
+  store ptr %values, ptr %field5, align 8
+  br label %loop
+
+loop:                                             ; preds = %next, %entry
+  %index = phi i64 [ 0, %entry ], [ %next6, %next ]
+  %done = icmp uge i64 %index, %old_capacity1
+  br i1 %done, label %exit, label %body
+
+body:                                             ; preds = %loop
+  %old_key_slot = getelementptr inbounds i64, ptr %old_keys2, i64 %index
+  %key = load ptr, ptr %old_key_slot, align 8
+  %is_empty = icmp eq ptr %key, null
+  br i1 %is_empty, label %next, label %move
+
+move:                                             ; preds = %body
+  %slot = call i64 @venti_map_find_slot(ptr %0, ptr %key)
+  %key_slot = getelementptr inbounds i64, ptr %keys, i64 %slot
+  store ptr %key, ptr %key_slot, align 8
+  %old_value_slot = getelementptr inbounds i64, ptr %old_values3, i64 %index
+  %value = load i64, ptr %old_value_slot, align 4
+  %value_slot = getelementptr inbounds i64, ptr %values, i64 %slot
+  store i64 %value, ptr %value_slot, align 4
+  br label %next
+
+next:                                             ; preds = %move, %body
+  %next6 = add i64 %index, 1
+  br label %loop
+
+exit:                                             ; preds = %loop
+  call void @free(ptr %old_keys2)
+  call void @free(ptr %old_values3)
+  ret void
+}
+
+declare void @free(ptr)
+
+define internal i64 @venti_map_find_slot(ptr %0, ptr %1) {
+entry:
+  %capacity = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 0
+  %capacity1 = load i64, ptr %capacity, align 4
+  %keys = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 2
+  %keys2 = load ptr, ptr %keys, align 8
+  %mask = sub i64 %capacity1, 1
+  %hash = call i64 @venti_hash_str(ptr %1)
+  %start = and i64 %hash, %mask
+  br label %probe
+
+probe:                                            ; preds = %advance, %entry
+  %index = phi i64 [ %start, %entry ], [ %wrapped, %advance ]
+  %key_slot = getelementptr inbounds i64, ptr %keys2, i64 %index
+  %slot_key = load ptr, ptr %key_slot, align 8
+  %is_empty = icmp eq ptr %slot_key, null
+  br i1 %is_empty, label %found, label %compare
+
+compare:                                          ; preds = %probe
+  %order = call i32 @strcmp(ptr %slot_key, ptr %1)
+  %is_match = icmp eq i32 %order, 0
+  br i1 %is_match, label %found, label %advance
+
+advance:                                          ; preds = %compare
+  %next = add i64 %index, 1
+  %wrapped = and i64 %next, %mask
+  br label %probe
+
+found:                                            ; preds = %compare, %probe
+  ret i64 %index
+}
+
+define internal i64 @venti_hash_str(ptr %0) {
+entry:
+  br label %loop
+
+loop:                                             ; preds = %body, %entry
+  %hash = phi i64 [ -3750763034362895579, %entry ], [ %next_hash, %body ]
+  %index = phi i64 [ 0, %entry ], [ %next_index, %body ]
+  %byte_ptr = getelementptr inbounds i8, ptr %0, i64 %index
+  %byte = load i8, ptr %byte_ptr, align 1
+  %at_end = icmp eq i8 %byte, 0
+  br i1 %at_end, label %exit, label %body
+
+body:                                             ; preds = %loop
+  %byte64 = zext i8 %byte to i64
+  %mixed = xor i64 %hash, %byte64
+  %next_hash = mul i64 %mixed, 1099511628211
+  %next_index = add i64 %index, 1
+  br label %loop
+
+exit:                                             ; preds = %loop
+  ret i64 %hash
+}
+
+declare i32 @strcmp(ptr, ptr)
+
+define internal void @venti_str_retain(ptr %0) {
+entry:
+  %is_null = icmp eq ptr %0, null
+  br i1 %is_null, label %done, label %present
+
+present:                                          ; preds = %entry
+  %count = getelementptr inbounds i64, ptr %0, i64 -1
+  call void @venti_rc_retain(ptr %count)
+  br label %done
+
+done:                                             ; preds = %present, %entry
+  ret void
+}
+
+define internal void @venti_rc_retain(ptr %0) {
+entry:
+  %current = load i64, ptr %0, align 4
+  %is_counted = icmp sge i64 %current, 0
+  br i1 %is_counted, label %counted, label %done
+
+counted:                                          ; preds = %entry
+  %1 = atomicrmw add ptr %0, i64 1 monotonic, align 8
+  br label %done
+
+done:                                             ; preds = %counted, %entry
+  ret void
+}
+
+define internal void @venti_map_retain(ptr %0) {
+entry:
+  %count = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 4
+  call void @venti_rc_retain(ptr %count)
+  ret void
+}
+
+define internal void @venti_map_release(ptr %0) {
+entry:
+  %is_null = icmp eq ptr %0, null
+  br i1 %is_null, label %done, label %present
+
+present:                                          ; preds = %entry
+  %count = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 4
+  %is_last = call i1 @venti_rc_release(ptr %count)
+  br i1 %is_last, label %last, label %done
+
+last:                                             ; preds = %present
+  %capacity = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 0
+  %capacity1 = load i64, ptr %capacity, align 4
+  %keys = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 2
+  %keys2 = load ptr, ptr %keys, align 8
+  %values = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 3
+  %values3 = load ptr, ptr %values, align 8
+  br label %loop
+
+loop:                                             ; preds = %body, %last
+  %index = phi i64 [ 0, %last ], [ %next_index, %body ]
+  %in_range = icmp ult i64 %index, %capacity1
+  br i1 %in_range, label %body, label %exit
+
+body:                                             ; preds = %loop
+  %key_slot = getelementptr inbounds i64, ptr %keys2, i64 %index
+  %key = load ptr, ptr %key_slot, align 8
+  call void @venti_str_release(ptr %key)
+  %next_index = add i64 %index, 1
+  br label %loop
+
+exit:                                             ; preds = %loop
+  call void @free(ptr %keys2)
+  call void @free(ptr %values3)
+  call void @free(ptr %0)
+  br label %done
+
+done:                                             ; preds = %exit, %present, %entry
+  ret void
+}
+
+define internal i1 @venti_rc_release(ptr %0) {
+entry:
+  %current = load i64, ptr %0, align 4
+  %is_counted = icmp sge i64 %current, 0
+  br i1 %is_counted, label %counted, label %immortal
+
+counted:                                          ; preds = %entry
+  %1 = atomicrmw sub ptr %0, i64 1 acq_rel, align 8
+  %is_last = icmp eq i64 %1, 1
+  ret i1 %is_last
+
+immortal:                                         ; preds = %entry
+  ret i1 false
+}
+
+define internal void @venti_str_release(ptr %0) {
+entry:
+  %is_null = icmp eq ptr %0, null
+  br i1 %is_null, label %done, label %present
+
+present:                                          ; preds = %entry
+  %count = getelementptr inbounds i64, ptr %0, i64 -1
+  %is_last = call i1 @venti_rc_release(ptr %count)
+  br i1 %is_last, label %last, label %done
+
+last:                                             ; preds = %present
+  call void @free(ptr %count)
+  br label %done
+
+done:                                             ; preds = %last, %present, %entry
+  ret void
+}
+
+define internal i64 @venti_map_get(ptr %0, ptr %1) {
+entry:
+  %slot = call i64 @venti_map_find_slot(ptr %0, ptr %1)
+  %keys = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 2
+  %keys1 = load ptr, ptr %keys, align 8
+  %key_slot = getelementptr inbounds i64, ptr %keys1, i64 %slot
+  %existing = load ptr, ptr %key_slot, align 8
+  %is_missing = icmp eq ptr %existing, null
+  br i1 %is_missing, label %missing, label %found
+
+missing:                                          ; preds = %entry
+  %len = call i32 (ptr, i64, ptr, ...) @snprintf(ptr null, i64 0, ptr @error, ptr %1)
+  %len2 = sext i32 %len to i64
+  %size = add i64 %len2, 1
+  %message = call ptr @malloc(i64 %size)
+  %2 = call i32 (ptr, i64, ptr, ...) @snprintf(ptr %message, i64 %size, ptr @error, ptr %1)
+  call void @venti_raise(ptr %message)
+  unreachable
+
+found:                                            ; preds = %entry
+  %values = getelementptr inbounds { i64, i64, ptr, ptr, i64 }, ptr %0, i32 0, i32 3
+  %values3 = load ptr, ptr %values, align 8
+  %value_slot = getelementptr inbounds i64, ptr %values3, i64 %slot
+  %value = load i64, ptr %value_slot, align 4
+  ret i64 %value
+}
+
+declare i32 @snprintf(ptr, i64, ptr, ...)
+
+; Function Attrs: noreturn
+define internal void @venti_raise(ptr %0) #0 {
+entry:
+  %handler = call ptr @venti_handler_get()
+  %is_uncaught = icmp eq ptr %handler, null
+  br i1 %is_uncaught, label %uncaught, label %caught
+
+caught:                                           ; preds = %entry
+  %previous_ptr = getelementptr inbounds %venti.handler, ptr %handler, i32 0, i32 0
+  %previous = load ptr, ptr %previous_ptr, align 8
+  call void @venti_handler_set(ptr %previous)
+  %message_ptr = getelementptr inbounds %venti.handler, ptr %handler, i32 0, i32 1
+  store ptr %0, ptr %message_ptr, align 8
+  %jump_buffer = getelementptr inbounds %venti.handler, ptr %handler, i32 0, i32 2
+  call void @longjmp(ptr %jump_buffer, i32 1)
+  unreachable
+
+uncaught:                                         ; preds = %entry
+  %1 = call i32 (ptr, ...) @printf(ptr @uncaught, ptr %0)
+  call void @exit(i32 1)
+  unreachable
+}
+
+define internal ptr @venti_handler_get() {
+entry:
+  %key = call i64 @venti_handler_key()
+  %handler = call ptr @pthread_getspecific(i64 %key)
+  ret ptr %handler
+}
+
+define internal i64 @venti_handler_key() {
+entry:
+  %key_slot = alloca i64, align 8
+  call void @llvm.x86.sse2.mfence()
+  %0 = load atomic i64, ptr @venti_handler_key.5 seq_cst, align 8
+  %is_created = icmp ne i64 %0, 0
+  br i1 %is_created, label %created, label %create
+
+created:                                          ; preds = %entry
+  %key = sub i64 %0, 1
+  ret i64 %key
+
+create:                                           ; preds = %entry
+  store i64 0, ptr %key_slot, align 4
+  %1 = call i32 @pthread_key_create(ptr %key_slot, ptr null)
+  %new_key = load i64, ptr %key_slot, align 4
+  %stored_key = add i64 %new_key, 1
+  %2 = cmpxchg ptr @venti_handler_key.5, i64 0, i64 %stored_key seq_cst seq_cst, align 8
+  %is_first = extractvalue { i64, i1 } %2, 1
+  br i1 %is_first, label %won, label %lost
+
+won:                                              ; preds = %create
+  ret i64 %new_key
+
+lost:                                             ; preds = %create
+  %3 = call i32 @pthread_key_delete(i64 %new_key)
+  %winner = extractvalue { i64, i1 } %2, 0
+  %winner_key = sub i64 %winner, 1
+  ret i64 %winner_key
+}
+
+declare i32 @pthread_key_create(ptr, ptr)
+
+declare i32 @pthread_key_delete(i64)
+
+declare ptr @pthread_getspecific(i64)
+
+define internal void @venti_handler_set(ptr %0) {
+entry:
+  %key = call i64 @venti_handler_key()
+  %1 = call i32 @pthread_setspecific(i64 %key, ptr %0)
+  ret void
+}
+
+declare i32 @pthread_setspecific(i64, ptr)
+
+; Function Attrs: noreturn
+declare void @longjmp(ptr, i32) #0
+
+declare void @exit(i32)
+
+define internal ptr @venti_buffer_alloc(i64 %0) {
+entry:
+  %is_negative = icmp slt i64 %0, 0
+  br i1 %is_negative, label %failed, label %allocate
+
+allocate:                                         ; preds = %entry
+  %data = call ptr @calloc(i64 %0, i64 8)
+  %is_null = icmp eq ptr %data, null
+  br i1 %is_null, label %check, label %done
+
+check:                                            ; preds = %allocate
+  %is_empty = icmp eq i64 %0, 0
+  br i1 %is_empty, label %done, label %failed
+
+failed:                                           ; preds = %check, %entry
+  %len = call i32 (ptr, i64, ptr, ...) @snprintf(ptr null, i64 0, ptr @error.7, i64 %0)
+  %len1 = sext i32 %len to i64
+  %size = add i64 %len1, 1
+  %message = call ptr @malloc(i64 %size)
+  %1 = call i32 (ptr, i64, ptr, ...) @snprintf(ptr %message, i64 %size, ptr @error.7, i64 %0)
+  call void @venti_raise(ptr %message)
+  unreachable
+
+done:                                             ; preds = %check, %allocate
+  %result = phi ptr [ %data, %allocate ], [ null, %check ]
+  ret ptr %result
+}
+
+define internal ptr @venti_buffer_slot(ptr %0, i64 %1, i64 %2) {
+entry:
+  %is_in_range = icmp ult i64 %2, %1
+  br i1 %is_in_range, label %in_range, label %out_of_range
+
+in_range:                                         ; preds = %entry
+  %slot = getelementptr inbounds i64, ptr %0, i64 %2
+  ret ptr %slot
+
+out_of_range:                                     ; preds = %entry
+  %len = call i32 (ptr, i64, ptr, ...) @snprintf(ptr null, i64 0, ptr @error.8, i64 %2, i64 %1)
+  %len1 = sext i32 %len to i64
+  %size = add i64 %len1, 1
+  %message = call ptr @malloc(i64 %size)
+  %3 = call i32 (ptr, i64, ptr, ...) @snprintf(ptr %message, i64 %size, ptr @error.8, i64 %2, i64 %1)
+  call void @venti_raise(ptr %message)
+  unreachable
+}
+
+; Function Attrs: nounwind
+declare void @llvm.x86.sse2.mfence() #1
+
+attributes #0 = { noreturn }
+attributes #1 = { nounwind }
@@ -0,0 +1,26 @@
+target datalayout = "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+target triple = "x86_64-unknown-linux-gnu"
+
+@venti_argc = weak global i64 0
+@venti_argv = weak global ptr null
+@str = private unnamed_addr constant { i64, [13 x i8] } { i64 -1, [13 x i8] c"hello from C\00" }, align 8
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+
+declare i32 @printf(ptr, ...)
+
+declare i32 @abs(i32)
+
+declare i32 @puts(ptr)
+
+define i32 @main(i32 %0, ptr %1) {
+entry:
+  %argc = sext i32 %0 to i64
+  store i64 %argc, ptr @venti_argc, align 4
+  store ptr %1, ptr @venti_argv, align 8
+  %c_call = call i32 @puts(ptr getelementptr inbounds ({ i64, [13 x i8] }, ptr @str, i32 0, i32 1))
+  %c_result = sext i32 %c_call to i64
+  %c_call1 = call i32 @abs(i32 -5)
+  %c_result2 = sext i32 %c_call1 to i64
+  %printf_call = call i32 (ptr, ...) @printf(ptr @format, i64 %c_result2)
+  ret i32 0
+}
@@ -0,0 +1,41 @@
+target datalayout = "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+target triple = "x86_64-unknown-linux-gnu"
+
+@venti_argc = weak global i64 0
+@venti_argv = weak global ptr null
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+
+declare i32 @printf(ptr, ...)
+
+define i32 @main(i32 %0, ptr %1) {
+entry:
+  %argc = sext i32 %0 to i64
+  store i64 %argc, ptr @venti_argc, align 4
+  store ptr %1, ptr @venti_argv, align 8
+  %call_func = call i64 @_V4main3add2(i64 1, i64 2)
+  %call_func1 = call i64 @_V4main5twice1(i64 %call_func)
+  %printf_call = call i32 (ptr, ...) @printf(ptr @format, i64 %call_func1)
+  ret i32 0
+}
+
+define i64 @_V4main3add2(i64 %a, i64 %b) {
+entry:
+  %b2 = alloca i64, align 8
+  %a1 = alloca i64, align 8
+  store i64 %a, ptr %a1, align 4
+  store i64 %b, ptr %b2, align 4
+  %a3 = load i64, ptr %a1, align 4
+  %b4 = load i64, ptr %b2, align 4
+  %tmpadd = add i64 %a3, %b4
+  ret i64 %tmpadd
+}
+
+define i64 @_V4main5twice1(i64 %x) {
+entry:
+  %x1 = alloca i64, align 8
+  store i64 %x, ptr %x1, align 4
+  %x2 = load i64, ptr %x1, align 4
+  %x3 = load i64, ptr %x1, align 4
+  %call_func = call i64 @_V4main3add2(i64 %x2, i64 %x3)
+  ret i64 %call_func
+}
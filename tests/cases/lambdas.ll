@@ -0,0 +1,48 @@
+target datalayout = "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+target triple = "x86_64-unknown-linux-gnu"
+
+@venti_argc = weak global i64 0
+@venti_argv = weak global ptr null
+@_V4main6double = global { ptr, ptr } { ptr @__venti_lambda_0, ptr null }
+@_V4main6offset = global i64 10
+@_V4main5shift = global { ptr, ptr } { ptr @__venti_lambda_1, ptr null }
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+@format.1 = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+
+declare i32 @printf(ptr, ...)
+
+define i32 @main(i32 %0, ptr %1) {
+entry:
+  %argc = sext i32 %0 to i64
+  store i64 %argc, ptr @venti_argc, align 4
+  store ptr %1, ptr @venti_argv, align 8
+  %double = load { ptr, ptr }, ptr @_V4main6double, align 8
+  %closure_fn = extractvalue { ptr, ptr } %double, 0
+  %closure_env = extractvalue { ptr, ptr } %double, 1
+  %closure_call = call i64 %closure_fn(ptr %closure_env, i64 4)
+  %printf_call = call i32 (ptr, ...) @printf(ptr @format, i64 %closure_call)
+  %shift = load { ptr, ptr }, ptr @_V4main5shift, align 8
+  %closure_fn1 = extractvalue { ptr, ptr } %shift, 0
+  %closure_env2 = extractvalue { ptr, ptr } %shift, 1
+  %closure_call3 = call i64 %closure_fn1(ptr %closure_env2, i64 1)
+  %printf_call4 = call i32 (ptr, ...) @printf(ptr @format.1, i64 %closure_call3)
+  ret i32 0
+}
+
+define internal i64 @__venti_lambda_0(ptr %env, i64 %x) {
+entry:
+  %x1 = alloca i64, align 8
+  store i64 %x, ptr %x1, align 4
+  %x2 = load i64, ptr %x1, align 4
+  %tmpmul = mul i64 %x2, 2
+  ret i64 %tmpmul
+}
+
+define internal i64 @__venti_lambda_1(ptr %env, i64 %x) {
+entry:
+  %x1 = alloca i64, align 8
+  store i64 %x, ptr %x1, align 4
+  %x2 = load i64, ptr %x1, align 4
+  %tmpadd = add i64 %x2, 10
+  ret i64 %tmpadd
+}
@@ -0,0 +1,177 @@
+target datalayout = "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+target triple = "x86_64-unknown-linux-gnu"
+
+%venti.str = type { ptr, i64 }
+
+@venti_argc = weak global i64 0
+@venti_argv = weak global ptr null
+@_V4main5total = global i64 0
+@str = private unnamed_addr constant { i64, [4 x i8] } { i64 -1, [4 x i8] c"ana\00" }, align 8
+@str.1 = private unnamed_addr constant { i64, [3 x i8] } { i64 -1, [3 x i8] c"bo\00" }, align 8
+@str.2 = private unnamed_addr constant { i64, [3 x i8] } { i64 -1, [3 x i8] c"cy\00" }, align 8
+@_V4main5names = global [3 x %venti.str] [%venti.str { ptr getelementptr inbounds ({ i64, [4 x i8] }, ptr @str, i32 0, i32 1), i64 3 }, %venti.str { ptr getelementptr inbounds ({ i64, [3 x i8] }, ptr @str.1, i32 0, i32 1), i64 2 }, %venti.str { ptr getelementptr inbounds ({ i64, [3 x i8] }, ptr @str.2, i32 0, i32 1), i64 2 }]
+@str.3 = private unnamed_addr constant { i64, [8 x i8] } { i64 -1, [8 x i8] c"nothing\00" }, align 8
+@format = private unnamed_addr constant [6 x i8] c"%.*s\0A\00", align 1
+@format.4 = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+
+declare i32 @printf(ptr, ...)
+
+define i32 @main(i32 %0, ptr %1) {
+entry:
+  %name20 = alloca %venti.str, align 8
+  %for_counter17 = alloca i64, align 8
+  %for_array = alloca [3 x %venti.str], align 8
+  %j9 = alloca i64, align 8
+  %for_counter7 = alloca i64, align 8
+  %i1 = alloca i64, align 8
+  %for_counter = alloca i64, align 8
+  %argc = sext i32 %0 to i64
+  store i64 %argc, ptr @venti_argc, align 4
+  store ptr %1, ptr @venti_argv, align 8
+  store i64 0, ptr %for_counter, align 4
+  br label %for_cond
+
+for_cond:                                         ; preds = %for_step, %entry
+  %i = load i64, ptr %for_counter, align 4
+  %in_range = icmp slt i64 %i, 10
+  br i1 %in_range, label %for_body, label %for_end
+
+for_body:                                         ; preds = %for_cond
+  store i64 %i, ptr %i1, align 4
+  %total = load i64, ptr @_V4main5total, align 4
+  %i2 = load i64, ptr %i1, align 4
+  %tmpadd = add i64 %total, %i2
+  store i64 %tmpadd, ptr @_V4main5total, align 4
+  br label %for_step
+
+for_step:                                         ; preds = %for_body
+  %next = add i64 %i, 1
+  store i64 %next, ptr %for_counter, align 4
+  br label %for_cond
+
+for_end:                                          ; preds = %for_cond
+  store i64 1, ptr %for_counter7, align 4
+  br label %for_cond3
+
+for_cond3:                                        ; preds = %for_step5, %for_end
+  %j = load i64, ptr %for_counter7, align 4
+  %in_range8 = icmp slt i64 %j, 4
+  br i1 %in_range8, label %for_body4, label %for_end6
+
+for_body4:                                        ; preds = %for_cond3
+  store i64 %j, ptr %j9, align 4
+  %total10 = load i64, ptr @_V4main5total, align 4
+  %tmpadd11 = add i64 %total10, 1
+  store i64 %tmpadd11, ptr @_V4main5total, align 4
+  br label %for_step5
+
+for_step5:                                        ; preds = %for_body4
+  %next12 = add i64 %j, 1
+  store i64 %next12, ptr %for_counter7, align 4
+  br label %for_cond3
+
+for_end6:                                         ; preds = %for_cond3
+  %names = load [3 x %venti.str], ptr @_V4main5names, align 8
+  store [3 x %venti.str] %names, ptr %for_array, align 8
+  store i64 0, ptr %for_counter17, align 4
+  br label %for_cond13
+
+for_cond13:                                       ; preds = %for_step15, %for_end6
+  %name = load i64, ptr %for_counter17, align 4
+  %in_range18 = icmp slt i64 %name, 3
+  br i1 %in_range18, label %for_body14, label %for_end16
+
+for_body14:                                       ; preds = %for_cond13
+  %element_ptr = getelementptr inbounds %venti.str, ptr %for_array, i64 %name
+  %name19 = load %venti.str, ptr %element_ptr, align 8
+  store %venti.str %name19, ptr %name20, align 8
+  %str_data = extractvalue %venti.str %name19, 0
+  %str_len = extractvalue %venti.str %name19, 1
+  call void @venti_str_retain(ptr %str_data)
+  %name21 = load %venti.str, ptr %name20, align 8
+  %str_data22 = extractvalue %venti.str %name21, 0
+  %str_len23 = extractvalue %venti.str %name21, 1
+  %is_nothing = icmp eq ptr %str_data22, null
+  %print_str = select i1 %is_nothing, ptr getelementptr inbounds ({ i64, [8 x i8] }, ptr @str.3, i32 0, i32 1), ptr %str_data22
+  %print_len = select i1 %is_nothing, i64 7, i64 %str_len23
+  %print_len24 = trunc i64 %print_len to i32
+  %printf_call = call i32 (ptr, ...) @printf(ptr @format, i32 %print_len24, ptr %print_str)
+  %owned = load %venti.str, ptr %name20, align 8
+  %str_data25 = extractvalue %venti.str %owned, 0
+  %str_len26 = extractvalue %venti.str %owned, 1
+  call void @venti_str_release(ptr %str_data25)
+  br label %for_step15
+
+for_step15:                                       ; preds = %for_body14
+  %next27 = add i64 %name, 1
+  store i64 %next27, ptr %for_counter17, align 4
+  br label %for_cond13
+
+for_end16:                                        ; preds = %for_cond13
+  %total28 = load i64, ptr @_V4main5total, align 4
+  %printf_call29 = call i32 (ptr, ...) @printf(ptr @format.4, i64 %total28)
+  ret i32 0
+}
+
+define internal void @venti_str_retain(ptr %0) {
+entry:
+  %is_null = icmp eq ptr %0, null
+  br i1 %is_null, label %done, label %present
+
+present:                                          ; preds = %entry
+  %count = getelementptr inbounds i64, ptr %0, i64 -1
+  call void @venti_rc_retain(ptr %count)
+  br label %done
+
+done:                                             ; preds = %present, %entry
+  ret void
+}
+
+define internal void @venti_rc_retain(ptr %0) {
+entry:
+  %current = load i64, ptr %0, align 4
+  %is_counted = icmp sge i64 %current, 0
+  br i1 %is_counted, label %counted, label %done
+
+counted:                                          ; preds = %entry
+  %1 = atomicrmw add ptr %0, i64 1 monotonic, align 8
+  br label %done
+
+done:                                             ; preds = %counted, %entry
+  ret void
+}
+
+define internal void @venti_str_release(ptr %0) {
+entry:
+  %is_null = icmp eq ptr %0, null
+  br i1 %is_null, label %done, label %present
+
+present:                                          ; preds = %entry
+  %count = getelementptr inbounds i64, ptr %0, i64 -1
+  %is_last = call i1 @venti_rc_release(ptr %count)
+  br i1 %is_last, label %last, label %done
+
+last:                                             ; preds = %present
+  call void @free(ptr %count)
+  br label %done
+
+done:                                             ; preds = %last, %present, %entry
+  ret void
+}
+
+define internal i1 @venti_rc_release(ptr %0) {
+entry:
+  %current = load i64, ptr %0, align 4
+  %is_counted = icmp sge i64 %current, 0
+  br i1 %is_counted, label %counted, label %immortal
+
+counted:                                          ; preds = %entry
+  %1 = atomicrmw sub ptr %0, i64 1 acq_rel, align 8
+  %is_last = icmp eq i64 %1, 1
+  ret i1 %is_last
+
+immortal:                                         ; preds = %entry
+  ret i1 false
+}
+
+declare void @free(ptr)
@@ -0,0 +1,49 @@
+target datalayout = "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+target triple = "x86_64-unknown-linux-gnu"
+
+@venti_argc = weak global i64 0
+@venti_argv = weak global ptr null
+@str = private unnamed_addr constant { i64, [5 x i8] } { i64 -1, [5 x i8] c"zero\00" }, align 8
+@format = private unnamed_addr constant [6 x i8] c"%.*s\0A\00", align 1
+@str.2 = private unnamed_addr constant { i64, [10 x i8] } { i64 -1, [10 x i8] c"minus one\00" }, align 8
+@format.4 = private unnamed_addr constant [6 x i8] c"%.*s\0A\00", align 1
+@str.5 = private unnamed_addr constant { i64, [5 x i8] } { i64 -1, [5 x i8] c"many\00" }, align 8
+@format.7 = private unnamed_addr constant [6 x i8] c"%.*s\0A\00", align 1
+
+declare i32 @printf(ptr, ...)
+
+define i32 @main(i32 %0, ptr %1) {
+entry:
+  %argc = sext i32 %0 to i64
+  store i64 %argc, ptr @venti_argc, align 4
+  store ptr %1, ptr @venti_argv, align 8
+  %call_func = call i64 @_V4main8describe1(i64 0)
+  %call_func1 = call i64 @_V4main8describe1(i64 5)
+  ret i32 0
+}
+
+define i64 @_V4main8describe1(i64 %n) {
+entry:
+  %n1 = alloca i64, align 8
+  store i64 %n, ptr %n1, align 4
+  %n2 = load i64, ptr %n1, align 4
+  switch i64 %n2, label %match_arm4 [
+    i64 0, label %match_arm
+    i64 -1, label %match_arm3
+  ]
+
+match_arm:                                        ; preds = %entry
+  %printf_call = call i32 (ptr, ...) @printf(ptr @format, i32 4, ptr getelementptr inbounds ({ i64, [5 x i8] }, ptr @str, i32 0, i32 1))
+  br label %match_end
+
+match_arm3:                                       ; preds = %entry
+  %printf_call5 = call i32 (ptr, ...) @printf(ptr @format.4, i32 9, ptr getelementptr inbounds ({ i64, [10 x i8] }, ptr @str.2, i32 0, i32 1))
+  br label %match_end
+
+match_arm4:                                       ; preds = %entry
+  %printf_call6 = call i32 (ptr, ...) @printf(ptr @format.7, i32 4, ptr getelementptr inbounds ({ i64, [5 x i8] }, ptr @str.5, i32 0, i32 1))
+  br label %match_end
+
+match_end:                                        ; preds = %match_arm4, %match_arm3, %match_arm
+  ret i64 0
+}
@@ -0,0 +1,50 @@
+target datalayout = "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128"
+target triple = "x86_64-unknown-linux-gnu"
+
+%venti.str = type { ptr, i64 }
+
+@venti_argc = weak global i64 0
+@venti_argv = weak global ptr null
+@str = private unnamed_addr constant { i64, [6 x i8] } { i64 -1, [6 x i8] c"venti\00" }, align 8
+@_V4main4name = global %venti.str { ptr getelementptr inbounds ({ i64, [6 x i8] }, ptr @str, i32 0, i32 1), i64 5 }
+@str.1 = private unnamed_addr constant { i64, [6 x i8] } { i64 -1, [6 x i8] c"venti\00" }, align 8
+@format = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+@str.2 = private unnamed_addr constant { i64, [6 x i8] } { i64 -1, [6 x i8] c"venti\00" }, align 8
+@format.3 = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+@str.4 = private unnamed_addr constant { i64, [5 x i8] } { i64 -1, [5 x i8] c"true\00" }, align 8
+@str.5 = private unnamed_addr constant { i64, [6 x i8] } { i64 -1, [6 x i8] c"false\00" }, align 8
+@str.6 = private unnamed_addr constant { i64, [8 x i8] } { i64 -1, [8 x i8] c"nothing\00" }, align 8
+@format.7 = private unnamed_addr constant [6 x i8] c"%.*s\0A\00", align 1
+@str.8 = private unnamed_addr constant { i64, [11 x i8] } { i64 -1, [11 x i8] c"no newline\00" }, align 8
+@str.9 = private unnamed_addr constant { i64, [8 x i8] } { i64 -1, [8 x i8] c"nothing\00" }, align 8
+@format.10 = private unnamed_addr constant [5 x i8] c"%.*s\00", align 1
+
+declare i32 @printf(ptr, ...)
+
+define i32 @main(i32 %0, ptr %1) {
+entry:
+  %argc = sext i32 %0 to i64
+  store i64 %argc, ptr @venti_argc, align 4
+  store ptr %1, ptr @venti_argv, align 8
+  %printf_call = call i32 (ptr, ...) @printf(ptr @format, i64 5)
+  %byte = call i64 @venti_str_byte_at(ptr getelementptr inbounds ({ i64, [6 x i8] }, ptr @str.2, i32 0, i32 1), i64 5, i64 0)
+  %printf_call1 = call i32 (ptr, ...) @printf(ptr @format.3, i64 %byte)
+  %printf_call2 = call i32 (ptr, ...) @printf(ptr @format.7, i32 4, ptr getelementptr inbounds ({ i64, [5 x i8] }, ptr @str.4, i32 0, i32 1))
+  %printf_call3 = call i32 (ptr, ...) @printf(ptr @format.10, i32 10, ptr getelementptr inbounds ({ i64, [11 x i8] }, ptr @str.8, i32 0, i32 1))
+  ret i32 0
+}
+
+define internal i64 @venti_str_byte_at(ptr %0, i64 %1, i64 %2) {
+entry:
+  %is_in_range = icmp ult i64 %2, %1
+  br i1 %is_in_range, label %in_range, label %out_of_range
+
+in_range:                                         ; preds = %entry
+  %byte_ptr = getelementptr inbounds i8, ptr %0, i64 %2
+  %byte = load i8, ptr %byte_ptr, align 1
+  %byte64 = zext i8 %byte to i64
+  ret i64 %byte64
+
+out_of_range:                                     ; preds = %entry
+  ret i64 -1
+}
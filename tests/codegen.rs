@@ -0,0 +1,166 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use venti::{compile_str, CompileOptions};
+
+/*
+Golden-file tests for code generation. Each `tests/cases/<name>.vt` is compiled as
+`venti build` would compile it without flags, and its LLVM IR is compared with the
+snapshot checked in next to it as `tests/cases/<name>.ll`:
+
+    cargo test --test codegen
+
+A change to codegen that changes the IR of a case fails the test with the first line
+that differs. When the change is intended, the snapshots are written again with
+
+    UPDATE_SNAPSHOTS=1 cargo test --test codegen
+
+and the diff of the `.ll` files is reviewed like any other. A new case is added by
+writing its `.vt` file and running the same command.
+
+Every case is compiled for one target, so the snapshots hold the same data layout and
+calling conventions whatever machine runs the tests. The lines that only name the module
+are left out of the comparison, as are trailing spaces.
+*/
+
+/// The target the cases are compiled for.
+const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+/// Returns the `.vt` files under `tests/cases`, in name order.
+fn cases(directory: &Path) -> Vec<PathBuf> {
+    let mut cases = fs::read_dir(directory)
+        .unwrap_or_else(|e| panic!("Could not read {}: {}", directory.display(), e))
+        .map(|entry| entry.expect("a readable directory entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "vt"))
+        .collect::<Vec<_>>();
+    cases.sort();
+    cases
+}
+
+/// Drops what does not belong in a snapshot from the IR of a module.
+fn normalize(ir: &str) -> String {
+    let mut lines = ir
+        .lines()
+        .filter(|line| !line.starts_with("; ModuleID") && !line.starts_with("source_filename"))
+        .map(str::trim_end)
+        .collect::<Vec<_>>();
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Describes the first line where `actual` differs from `expected`.
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => line += 1,
+            (expected, actual) => {
+                return format!(
+                    "line {}\n  expected: {}\n  actual:   {}",
+                    line,
+                    expected.unwrap_or("<end of snapshot>"),
+                    actual.unwrap_or("<end of output>")
+                )
+            }
+        }
+    }
+}
+
+#[test]
+fn codegen_matches_snapshots() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let update = env::var("UPDATE_SNAPSHOTS").as_deref() == Ok("1");
+    let cases = cases(&directory);
+    assert!(!cases.is_empty(), "No cases in {}", directory.display());
+
+    // Every case is compiled before failing, so one run reports all of them
+    let mut failures = Vec::new();
+    for case in &cases {
+        let source = fs::read_to_string(case)
+            .unwrap_or_else(|e| panic!("Could not read {}: {}", case.display(), e));
+        let options = CompileOptions::new()
+            .with_target(TARGET)
+            .with_import_dir(&directory);
+        let ir = match compile_str(&source, &options) {
+            Ok(module) => normalize(module.ir()),
+            Err(error) => {
+                failures.push(format!("{} did not compile: {}", case.display(), error));
+                continue;
+            }
+        };
+
+        let snapshot = case.with_extension("ll");
+        if update {
+            fs::write(&snapshot, &ir)
+                .unwrap_or_else(|e| panic!("Could not write {}: {}", snapshot.display(), e));
+            continue;
+        }
+        match fs::read_to_string(&snapshot) {
+            Ok(expected) if normalize(&expected) == ir => {}
+            Ok(expected) => failures.push(format!(
+                "{} differs from its snapshot at {}",
+                case.display(),
+                first_difference(&normalize(&expected), &ir)
+            )),
+            Err(_) => failures.push(format!(
+                "{} has no snapshot; run with UPDATE_SNAPSHOTS=1 to write it",
+                case.display()
+            )),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} cases failed:\n\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n\n")
+    );
+}
+
+#[test]
+fn socket_constants_follow_the_target() {
+    // `tcp_listen` passes `SOL_SOCKET` and `SO_REUSEADDR` to `setsockopt`, which macOS
+    // numbers differently from Linux whatever machine compiles the program
+    let source = "venti server = tcp_listen(8080);\n";
+    for (target, constants) in [
+        (TARGET, "i32 1, i32 2"),
+        ("x86_64-apple-darwin", "i32 65535, i32 4"),
+    ] {
+        let options = CompileOptions::new().with_target(target);
+        let module = compile_str(source, &options)
+            .unwrap_or_else(|error| panic!("Could not compile for {}: {}", target, error));
+        assert!(
+            module
+                .ir()
+                .lines()
+                .any(|line| line.contains("@setsockopt(") && line.contains(constants)),
+            "setsockopt is not called with {} for {}",
+            constants,
+            target
+        );
+    }
+}
+
+#[test]
+fn monotonic_clock_follows_the_target() {
+    // `timer` reads `CLOCK_MONOTONIC`, which macOS numbers differently from Linux
+    let source = "venti start = timer();\n";
+    for (target, clock) in [(TARGET, "i32 1,"), ("x86_64-apple-darwin", "i32 6,")] {
+        let options = CompileOptions::new().with_target(target);
+        let module = compile_str(source, &options)
+            .unwrap_or_else(|error| panic!("Could not compile for {}: {}", target, error));
+        assert!(
+            module
+                .ir()
+                .lines()
+                .any(|line| line.contains("@clock_gettime(") && line.contains(clock)),
+            "clock_gettime is not called with {} for {}",
+            clock,
+            target
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use proptest::prelude::*;
+use proptest::strategy::LazyJust;
+use venti::formatter::printer::print_statements;
+use venti::venti_lexer::lexer::Lexer;
+use venti::venti_lexer::span::Span;
+use venti::venti_parser::arena::Arena;
+use venti::venti_parser::ast::{
+    BinOp, Expr, ExprKind, InlineHint, MatchArm, Parameter, Pattern, Statement, TraitMethod,
+    UnaryOp, VarType,
+};
+use venti::venti_parser::parser::Parser;
+
+/*
+Checks that the printer and the parser agree: printing a syntax tree and parsing the text
+gives the same tree back.
+
+    cargo test --test roundtrip
+
+The trees are generated, so they hold operator nestings nobody would write by hand, and a
+printer that drops parentheses the parser needs, or a parser that binds an operator
+differently from `binding_power`, shows up as a tree that comes back changed. proptest
+shrinks a failing tree before reporting it, with the text it was printed as.
+
+Only trees the parser could have produced are generated. Number literals are never
+negative, since `-1` parses as a negation; `async` only appears on function definitions;
+a spawn is only a statement or the value of a declaration; and comments are left out, as
+the parser only keeps them for the formatter. The parsed tree is compared without the
+`Line` markers the parser puts before each statement, and `Expr`'s equality ignores spans.
+
+proptest builds a tree again each time it is asked for one while shrinking, so the
+children of generated expressions, and of the parsed ones compared with them, go in an
+arena that lives as long as the thread running the test.
+*/
+
+/// The names identifiers are drawn from, none of them a keyword.
+const NAMES: [&str; 6] = ["a", "b", "count", "total", "x1", "_tmp"];
+
+const OPERATORS: [BinOp; 18] = [
+    BinOp::Add,
+    BinOp::Subtract,
+    BinOp::Multiply,
+    BinOp::Divide,
+    BinOp::Equal,
+    BinOp::NotEqual,
+    BinOp::Less,
+    BinOp::LessEqual,
+    BinOp::Greater,
+    BinOp::GreaterEqual,
+    BinOp::And,
+    BinOp::Or,
+    BinOp::BitAnd,
+    BinOp::BitOr,
+    BinOp::BitXor,
+    BinOp::ShiftLeft,
+    BinOp::ShiftRight,
+    BinOp::Coalesce,
+];
+
+/// The arena of the thread's trees, which is never freed.
+fn arena() -> &'static Arena<Expr<'static>> {
+    thread_local! {
+        static ARENA: &'static Arena<Expr<'static>> = Box::leak(Box::new(Arena::new()));
+    }
+    ARENA.with(|&arena| arena)
+}
+
+/// Wraps `kind` in an expression with an empty span.
+fn expr(kind: ExprKind<'static>) -> Expr<'static> {
+    Expr::new(kind, Span::default())
+}
+
+/// Moves `expr` into the arena, as the child of another expression.
+fn node(expr: Expr<'static>) -> &'static mut Expr<'static> {
+    arena().alloc(expr)
+}
+
+fn name() -> impl Strategy<Value = String> {
+    prop::sample::select(NAMES.to_vec()).prop_map(String::from)
+}
+
+/// The text of a string literal, which cannot hold a `"`.
+fn text() -> impl Strategy<Value = String> {
+    "[a-z ]{0,8}"
+}
+
+fn var_type() -> impl Strategy<Value = VarType> {
+    prop_oneof![
+        Just(VarType::Int),
+        Just(VarType::Float),
+        Just(VarType::Bool),
+        Just(VarType::String),
+    ]
+}
+
+fn parameters() -> impl Strategy<Value = Vec<Parameter>> {
+    prop::collection::vec(
+        (name(), prop::option::of(var_type()))
+            .prop_map(|(name, var_type)| Parameter { name, var_type }),
+        0..3,
+    )
+}
+
+fn leaf() -> impl Strategy<Value = Expr<'static>> {
+    prop_oneof![
+        (0..=i64::MAX).prop_map(ExprKind::Number),
+        // Quarters print exactly, without an exponent
+        (0..4000u32).prop_map(|quarters| ExprKind::Float(quarters as f64 / 4.0)),
+        text().prop_map(ExprKind::String),
+        any::<bool>().prop_map(ExprKind::Boolean),
+        LazyJust::new(|| ExprKind::Null),
+        name().prop_map(ExprKind::Identifier),
+    ]
+    .prop_map(expr)
+}
+
+fn expression() -> impl Strategy<Value = Expr<'static>> {
+    leaf().prop_recursive(4, 48, 3, |inner| {
+        let list = prop::collection::vec(inner.clone(), 0..3);
+        prop_oneof![
+            (
+                inner.clone(),
+                prop::sample::select(OPERATORS.to_vec()),
+                inner.clone()
+            )
+                .prop_map(|(left, op, right)| ExprKind::BinaryOp(
+                    node(left),
+                    op,
+                    node(right)
+                )),
+            (
+                prop_oneof![Just(UnaryOp::Negate), Just(UnaryOp::Not)],
+                inner.clone()
+            )
+                .prop_map(|(op, operand)| ExprKind::UnaryOp(op, node(operand))),
+            (inner.clone(), list.clone()).prop_map(|(callee, args)| ExprKind::Call {
+                callee: node(callee),
+                args
+            }),
+            (parameters(), inner.clone()).prop_map(|(params, body)| ExprKind::Lambda {
+                params,
+                body: node(body)
+            }),
+            (inner.clone(), name(), list.clone()).prop_map(|(receiver, name, args)| {
+                ExprKind::MethodCall {
+                    receiver: node(receiver),
+                    name,
+                    args,
+                }
+            }),
+            list.prop_map(ExprKind::Array),
+            prop::collection::vec((inner.clone(), inner.clone()), 0..3).prop_map(ExprKind::Map),
+            (inner.clone(), inner.clone(), any::<bool>()).prop_map(|(start, end, inclusive)| {
+                ExprKind::Range {
+                    start: node(start),
+                    end: node(end),
+                    inclusive,
+                }
+            }),
+            (inner.clone(), inner.clone()).prop_map(|(target, index)| ExprKind::Index {
+                target: node(target),
+                index: node(index)
+            }),
+            (inner.clone(), var_type()).prop_map(|(value, target)| ExprKind::Cast {
+                value: node(value),
+                target
+            }),
+            inner.prop_map(|operand| ExprKind::Await(node(operand))),
+        ]
+        .prop_map(expr)
+    })
+}
+
+/// The target of an element assignment, which the parser only reads as a name followed
+/// by indexes.
+fn assignment_target() -> impl Strategy<Value = Expr<'static>> {
+    (name(), prop::collection::vec(expression(), 0..2)).prop_map(|(name, indexes)| {
+        indexes
+            .into_iter()
+            .fold(expr(ExprKind::Identifier(name)), |target, index| {
+                expr(ExprKind::Index {
+                    target: node(target),
+                    index: node(index),
+                })
+            })
+    })
+}
+
+/// Statements without a body.
+fn simple_statement() -> impl Strategy<Value = Statement<'static>> {
+    prop_oneof![
+        (name(), expression())
+            .prop_map(|(identifier, value)| Statement::VariableDeclaration { identifier, value }),
+        (name(), expression())
+            .prop_map(|(identifier, value)| Statement::VariableAssignment { identifier, value }),
+        (assignment_target(), expression(), expression()).prop_map(|(target, index, value)| {
+            Statement::IndexAssignment {
+                target,
+                index,
+                value,
+            }
+        }),
+        (name(), prop::collection::vec(expression(), 0..3))
+            .prop_map(|(identifier, args)| Statement::FunctionCall { identifier, args }),
+        (expression(), any::<bool>())
+            .prop_map(|(value, newline)| Statement::Print { value, newline }),
+        // A statement starting with a name is only an expression when something follows it
+        (name(), name(), prop::collection::vec(expression(), 0..3)).prop_map(
+            |(receiver, name, args)| {
+                Statement::Expression(expr(ExprKind::MethodCall {
+                    receiver: node(expr(ExprKind::Identifier(receiver))),
+                    name,
+                    args,
+                }))
+            }
+        ),
+        expression()
+            .prop_map(|operand| Statement::Expression(expr(ExprKind::Await(node(operand))))),
+        prop::option::of(expression()).prop_map(Statement::Return),
+        expression().prop_map(Statement::Assert),
+        text().prop_map(Statement::Import),
+        (
+            name(),
+            prop::collection::vec(
+                (name(), var_type()).prop_map(|(name, var_type)| Parameter {
+                    name,
+                    var_type: Some(var_type)
+                }),
+                0..3
+            ),
+            prop::option::of(var_type())
+        )
+            .prop_map(|(identifier, params, returns)| Statement::Extern {
+                identifier,
+                params,
+                returns
+            }),
+        (
+            name(),
+            prop::collection::vec(
+                (name(), parameters()).prop_map(|(name, params)| TraitMethod { name, params }),
+                0..3
+            )
+        )
+            .prop_map(|(identifier, methods)| Statement::Trait {
+                identifier,
+                methods
+            }),
+    ]
+}
+
+fn statement() -> impl Strategy<Value = Statement<'static>> {
+    simple_statement().prop_recursive(3, 24, 3, |inner| {
+        let block = prop::collection::vec(inner, 0..3);
+        let inline = prop::option::of(prop_oneof![
+            Just(InlineHint::Inline),
+            Just(InlineHint::NoInline)
+        ]);
+        let pattern = prop_oneof![
+            (-1000..1000i64).prop_map(Pattern::Number),
+            text().prop_map(Pattern::String),
+            LazyJust::new(|| Pattern::Wildcard),
+        ];
+        prop_oneof![
+            (
+                name(),
+                parameters(),
+                prop::option::of(var_type()),
+                block.clone(),
+                inline.clone(),
+                any::<bool>()
+            )
+                .prop_map(|(identifier, params, returns, body, inline, is_async)| {
+                    // Only a function that is not async can have its result type written
+                    if is_async {
+                        Statement::AsyncFunction {
+                            identifier,
+                            params,
+                            body,
+                            inline,
+                        }
+                    } else {
+                        Statement::Function {
+                            identifier,
+                            params,
+                            returns,
+                            body,
+                            inline,
+                        }
+                    }
+                }),
+            (
+                expression(),
+                prop::collection::vec(
+                    (pattern, block.clone()).prop_map(|(pattern, body)| MatchArm { pattern, body }),
+                    0..3
+                )
+            )
+                .prop_map(|(scrutinee, arms)| Statement::Match { scrutinee, arms }),
+            (name(), expression(), block.clone()).prop_map(|(variable, iterable, body)| {
+                Statement::For {
+                    variable,
+                    iterable,
+                    body,
+                }
+            }),
+            (text(), block.clone()).prop_map(|(name, body)| Statement::Test { name, body }),
+            (block.clone(), name(), block.clone()).prop_map(|(body, error, handler)| {
+                Statement::Try {
+                    body,
+                    error,
+                    handler,
+                }
+            }),
+            block
+                .clone()
+                .prop_map(|body| Statement::Expression(expr(ExprKind::Spawn(body)))),
+            (name(), block.clone()).prop_map(|(identifier, body)| {
+                Statement::VariableDeclaration {
+                    identifier,
+                    value: expr(ExprKind::Spawn(body)),
+                }
+            }),
+            (
+                name(),
+                var_type(),
+                prop::collection::vec((name(), parameters(), block, inline), 0..3)
+            )
+                .prop_map(|(trait_name, target, methods)| {
+                    // The parser gives `self` the type the block implements the trait for
+                    let receiver = Parameter {
+                        name: "self".to_string(),
+                        var_type: Some(target.clone()),
+                    };
+                    let methods = methods
+                        .into_iter()
+                        .map(|(identifier, params, body, inline)| Statement::Function {
+                            identifier,
+                            params: std::iter::once(receiver.clone()).chain(params).collect(),
+                            returns: None,
+                            body,
+                            inline,
+                        })
+                        .collect();
+                    Statement::Impl {
+                        trait_name,
+                        target,
+                        methods,
+                    }
+                }),
+        ]
+    })
+}
+
+/// Removes the `Line` markers the parser puts before each statement, in `statements` and
+/// every block in them.
+fn strip_lines(statements: &mut Vec<Statement>) {
+    statements.retain(|statement| !matches!(statement, Statement::Line(_)));
+    for statement in statements {
+        match statement {
+            Statement::Function { body, .. }
+            | Statement::AsyncFunction { body, .. }
+            | Statement::For { body, .. }
+            | Statement::Test { body, .. }
+            | Statement::Impl { methods: body, .. }
+            | Statement::Expression(Expr {
+                kind: ExprKind::Spawn(body),
+                ..
+            })
+            | Statement::VariableDeclaration {
+                value:
+                    Expr {
+                        kind: ExprKind::Spawn(body),
+                        ..
+                    },
+                ..
+            } => strip_lines(body),
+            Statement::Match { arms, .. } => {
+                arms.iter_mut().for_each(|arm| strip_lines(&mut arm.body))
+            }
+            Statement::Try { body, handler, .. } => {
+                strip_lines(body);
+                strip_lines(handler);
+            }
+            _ => {}
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn parsing_printed_tree_gives_it_back(statements in prop::collection::vec(statement(), 0..6)) {
+        let source = print_statements(&statements);
+        let parsed = Parser::new(Lexer::new(&source), arena()).parse();
+        prop_assert!(parsed.is_ok(), "{:?} when parsing:\n{}", parsed, source);
+        let mut parsed = parsed.unwrap();
+        strip_lines(&mut parsed);
+        prop_assert_eq!(parsed, statements, "printed as:\n{}", source);
+    }
+}
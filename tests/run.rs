@@ -0,0 +1,587 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+/*
+End-to-end tests that run programs and check what they do. Each `tests/programs/<name>.vt`
+is run with `venti run` on each backend, through the JIT, through C and with the
+interpreter, and its output and exit code are compared with what comments in the program
+expect:
+
+    # ARGS: 5 done
+    # BACKENDS: llvm interp
+    # EXPECT: 120
+    # EXPECT: done
+    # EXIT: 3
+
+Each `EXPECT` comment is one line the program prints, in order, and the program must
+print nothing else to stdout. `EXIT` is the code it exits with, 0 when there is none, and
+`ARGS` the arguments it is run with, separated by spaces. `BACKENDS` names the backends
+the program runs on, all of them when there is none, for programs using something the
+others do not support.
+A program that is expected not to compile names the diagnostics it gets in `ERROR`
+comments, each some text that stderr must contain, and expects to exit with 1.
+Runtime errors are printed to stdout too, so a program expected to fail expects its
+message as well as its exit code. The interpreter reports them as diagnostics on stderr
+instead, exiting with 5, so there the message is looked for on stderr.
+
+    cargo test --test run
+
+The programs are run without the cache, so the tests neither read nor fill the user's.
+The tests of the cache itself give it a directory of their own. Each run of a program
+starts in an empty directory of its own, so the files a program writes, which it names
+relative to where it runs, cannot collide with another's.
+*/
+
+/// The backends `venti run` can run a program on.
+const BACKENDS: [&str; 3] = ["llvm", "c", "interp"];
+
+/// The code `venti run` exits with when the interpreter reports a runtime error.
+const EXIT_RUNTIME: i32 = 5;
+
+/// The code `venti` exits with when it cannot read or write a file.
+const EXIT_IO: i32 = 4;
+
+/// What a program is run with and on, and is expected to print and exit with.
+struct Expectation {
+    args: Vec<String>,
+    backends: Vec<String>,
+    stdout: String,
+    stderr: Vec<String>,
+    code: i32,
+}
+
+/// Returns the `.vt` files under `tests/programs`, in name order.
+fn programs(directory: &Path) -> Vec<PathBuf> {
+    let mut programs = fs::read_dir(directory)
+        .unwrap_or_else(|e| panic!("Could not read {}: {}", directory.display(), e))
+        .map(|entry| entry.expect("a readable directory entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "vt"))
+        .collect::<Vec<_>>();
+    programs.sort();
+    programs
+}
+
+/// Reads the `ARGS`, `BACKENDS`, `EXPECT`, `ERROR` and `EXIT` comments of a program.
+fn expectation(source: &str) -> Result<Expectation, String> {
+    let mut args = Vec::new();
+    let mut backends = Vec::new();
+    let mut stdout = String::new();
+    let mut stderr = Vec::new();
+    let mut code = 0;
+    for line in source.lines().map(str::trim) {
+        if let Some(expected) = line.strip_prefix("# EXPECT:") {
+            // One space after the colon separates the comment from the line it expects
+            stdout.push_str(expected.strip_prefix(' ').unwrap_or(expected));
+            stdout.push('\n');
+        } else if let Some(expected) = line.strip_prefix("# ERROR:") {
+            stderr.push(expected.trim().to_string());
+        } else if let Some(given) = line.strip_prefix("# ARGS:") {
+            args.extend(given.split_whitespace().map(str::to_string));
+        } else if let Some(given) = line.strip_prefix("# BACKENDS:") {
+            for backend in given.split_whitespace() {
+                if !BACKENDS.contains(&backend) {
+                    return Err(format!("'{}' is not a backend", backend));
+                }
+                backends.push(backend.to_string());
+            }
+        } else if let Some(expected) = line.strip_prefix("# EXIT:") {
+            code = expected
+                .trim()
+                .parse()
+                .map_err(|_| format!("'{}' is not an exit code", expected.trim()))?;
+        }
+    }
+    if backends.is_empty() {
+        backends.extend(BACKENDS.map(str::to_string));
+    }
+    Ok(Expectation {
+        args,
+        backends,
+        stdout,
+        stderr,
+        code,
+    })
+}
+
+/// Runs `program` on `backend` and describes how it did not meet `expected`, if it did
+/// not.
+fn check(program: &Path, backend: &str, expected: &Expectation) -> Option<String> {
+    let name = program.file_stem().unwrap_or_default().to_string_lossy();
+    let directory = env::temp_dir().join(format!(
+        "venti-program-{}-{}-{}",
+        process::id(),
+        name,
+        backend
+    ));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("a temporary directory");
+    let output = Command::new(env!("CARGO_BIN_EXE_venti"))
+        .arg("run")
+        .arg("--no-cache")
+        .arg("--backend")
+        .arg(backend)
+        .arg(program)
+        .arg("--")
+        .args(&expected.args)
+        .current_dir(&directory)
+        .output()
+        .unwrap_or_else(|e| panic!("Could not run venti: {}", e));
+    let _ = fs::remove_dir_all(&directory);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let code = output.status.code();
+    let (expected_stdout, errors, expected_code) = match runtime_error(&expected.stdout) {
+        Some((printed, message)) if backend == "interp" => (printed, vec![message], EXIT_RUNTIME),
+        _ => (
+            expected.stdout.as_str(),
+            expected.stderr.iter().map(String::as_str).collect(),
+            expected.code,
+        ),
+    };
+    let reported = errors.iter().all(|error| stderr.contains(error));
+    if stdout == expected_stdout && reported && code == Some(expected_code) {
+        return None;
+    }
+    Some(format!(
+        "{} on {} exited with {:?}, expected {}\n--- expected stdout\n{}--- actual stdout\n{}--- expected stderr\n{}\n--- stderr\n{}",
+        program.display(),
+        backend,
+        code,
+        expected_code,
+        expected_stdout,
+        stdout,
+        errors.join("\n"),
+        stderr
+    ))
+}
+
+/// Splits the runtime error a compiled program prints last off its expected `stdout`,
+/// giving what it prints before the error and the error's message.
+fn runtime_error(stdout: &str) -> Option<(&str, &str)> {
+    let printed = stdout.strip_suffix('\n')?;
+    let start = printed.rfind('\n').map_or(0, |end| end + 1);
+    let message = printed[start..].strip_prefix("Runtime Error: ")?;
+    Some((&stdout[..start], message))
+}
+
+#[test]
+fn programs_print_what_they_expect() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let programs = programs(&directory);
+    assert!(
+        !programs.is_empty(),
+        "No programs in {}",
+        directory.display()
+    );
+
+    // Every program is run before failing, so one run reports all of them
+    let mut failures = Vec::new();
+    for program in &programs {
+        let source = fs::read_to_string(program)
+            .unwrap_or_else(|e| panic!("Could not read {}: {}", program.display(), e));
+        match expectation(&source) {
+            Ok(expected) => failures.extend(
+                expected
+                    .backends
+                    .iter()
+                    .filter_map(|backend| check(program, backend, &expected)),
+            ),
+            Err(error) => failures.push(format!("{}: {}", program.display(), error)),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} runs of {} programs failed:\n\n{}",
+        failures.len(),
+        programs.len(),
+        failures.join("\n\n")
+    );
+}
+
+/// Runs the program at `path` with the cache kept in `cache`, and returns its exit code and
+/// what it printed.
+fn run_cached(path: &Path, cache: &Path) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_venti"))
+        .arg("run")
+        .arg(path)
+        .env("VENTI_CACHE_DIR", cache)
+        .output()
+        .unwrap_or_else(|e| panic!("Could not run venti: {}", e));
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    )
+}
+
+#[test]
+fn function_cache_follows_result_types() {
+    let directory = env::temp_dir().join(format!("venti-function-cache-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("a temporary directory");
+    let program = directory.join("program.vt");
+    let cache = directory.join("cache");
+
+    // `show` is unchanged between the runs, but is built against the result type of `make`
+    let caller = "fn_venti show() {\n    venti made = make();\n    venti _kept = made;\n    printventi(7);\n}\n\nshow();\n";
+    let makes = [
+        "fn_venti make() -> vec {\n    venti v = vec();\n    v.push(3);\n    return_venti v;\n}\n\n",
+        "fn_venti make() {\n    return_venti 3;\n}\n\n",
+    ];
+    for make in makes {
+        fs::write(&program, format!("{}{}", make, caller)).expect("a writable program");
+        assert_eq!(
+            run_cached(&program, &cache),
+            (Some(0), "7\n".to_string()),
+            "after changing the program to:\n{}{}",
+            make,
+            caller
+        );
+    }
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn function_cache_follows_channel_types() {
+    let directory = env::temp_dir().join(format!("venti-channel-cache-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("a temporary directory");
+    let program = directory.join("program.vt");
+    let cache = directory.join("cache");
+
+    // `take` and the statements before it are unchanged between the runs, but it receives
+    // what `fill` sends
+    let taker = "fn_venti take() {\n    printventi(recv(inbox));\n    return_venti 0;\n}\n\nfill();\ntake();\n";
+    let sends = [("0.5", "0.500000\n"), ("7", "7\n")];
+    for (value, printed) in sends {
+        let filler = format!(
+            "venti inbox = channel();\n\nfn_venti fill() {{\n    send(inbox, {});\n    return_venti 0;\n}}\n\n",
+            value
+        );
+        fs::write(&program, format!("{}{}", filler, taker)).expect("a writable program");
+        assert_eq!(
+            run_cached(&program, &cache),
+            (Some(0), printed.to_string()),
+            "after changing the program to:\n{}{}",
+            filler,
+            taker
+        );
+    }
+    let _ = fs::remove_dir_all(&directory);
+}
+
+/// Runs `venti` with `args` in `directory`, with `input` on stdin, and returns its exit code,
+/// what it printed and what it reported on stderr.
+fn run_venti(directory: &Path, args: &[&str], input: &str) -> (Option<i32>, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_venti"))
+        .args(args)
+        .current_dir(directory)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|e| panic!("Could not run venti: {}", e));
+    child
+        .stdin
+        .take()
+        .expect("a piped stdin")
+        .write_all(input.as_bytes())
+        .expect("venti to read its input");
+    let output = child
+        .wait_with_output()
+        .unwrap_or_else(|e| panic!("Could not run venti: {}", e));
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn tests_carry_on_after_a_runtime_error() {
+    let directory = env::temp_dir().join(format!("venti-test-raise-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("a temporary directory");
+    let program = "venti buf = alloc(2);\n\ntest_venti \"raises\" {\n    buf[5] = 1;\n}\n\ntest_venti \"passes\" {\n    assert_venti buf[0] == 0;\n}\n";
+    fs::write(directory.join("program.vt"), program).expect("a writable program");
+
+    let (code, stdout, _) = run_venti(&directory, &["test", "program.vt"], "");
+    assert_eq!(
+        (code, stdout.as_str()),
+        (
+            Some(1),
+            "test raises ... FAILED (index 5 is out of bounds for a buffer of length 2)\ntest passes ... ok\n\n1 passed, 1 failed\n"
+        )
+    );
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn repl_carries_on_after_a_runtime_error() {
+    let (code, stdout, stderr) = run_venti(
+        &env::temp_dir(),
+        &["repl"],
+        "venti buf = alloc(2);\nbuf[5] = 1;\nprintventi(buf[1]);\n",
+    );
+    assert_eq!(
+        (code, stdout.as_str()),
+        (Some(0), "venti> venti> venti> 0\nventi> \n")
+    );
+    assert!(
+        stderr.contains("index 5 is out of bounds for a buffer of length 2"),
+        "stderr was:\n{}",
+        stderr
+    );
+}
+
+#[test]
+fn type_errors_name_what_is_wrong() {
+    let cases = [
+        (
+            "printventi(missing);",
+            "Type Error[V0042]: Undefined variable 'missing'",
+        ),
+        (
+            "venti n = 1;\nn = \"two\";",
+            "Type Error[V0040]: Cannot assign a value of type string to 'n' of type int",
+        ),
+        (
+            "printventi(\"a\" + 1);",
+            "Type Error[V0040]: Operator '+' cannot be applied to string and int",
+        ),
+        (
+            "printventi(len(5));",
+            "Type Error[V0040]: Argument 1 of 'len' expects an array, map, string, buffer, vector or JSON value but found int",
+        ),
+        (
+            "fn_venti f(a) {\n    return_venti a;\n}\nprintventi(f(1, 2));",
+            "Type Error[V0044]: Function 'f' expects 1 arguments but 2 were given",
+        ),
+    ];
+    for (program, error) in cases {
+        let (code, _, stderr) = run_venti(&env::temp_dir(), &["check", "-e", program], "");
+        assert_eq!(code, Some(1), "checking {:?}", program);
+        assert!(
+            stderr.contains(error),
+            "checking {:?}, stderr was:\n{}",
+            program,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn no_fold_keeps_constant_expressions() {
+    let directory = env::temp_dir().join(format!("venti-test-fold-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("a temporary directory");
+    let program = "printventi(2 + 3 * 4);";
+    let build = |output: &str, flags: &[&str]| {
+        let mut args = vec!["build", "-q", "-e", program];
+        args.extend(["--backend", "c", "--emit", "c", "-o", output]);
+        args.extend(flags);
+        let (code, _, stderr) = run_venti(&directory, &args, "");
+        assert_eq!(code, Some(0), "stderr was:\n{}", stderr);
+        fs::read_to_string(directory.join(output)).expect("the generated C")
+    };
+
+    let folded = build("folded.c", &[]);
+    assert!(
+        folded.contains("(long long)14)"),
+        "folded C was:\n{}",
+        folded
+    );
+    let unfolded = build("unfolded.c", &["--no-fold"]);
+    assert!(
+        unfolded.contains("(uint64_t)3 * (uint64_t)4"),
+        "unfolded C was:\n{}",
+        unfolded
+    );
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn imports_merge_files_and_report_cycles() {
+    let directory = env::temp_dir().join(format!("venti-test-imports-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("a temporary directory");
+    let write = |name: &str, program: &str| {
+        fs::write(directory.join(name), program).expect("a writable program");
+    };
+    write(
+        "utils.vt",
+        "fn_venti twice(n) {\n    return_venti n * 2;\n}\n",
+    );
+    write(
+        "main.vt",
+        "import_venti \"utils.vt\";\n\nfn_venti twice(n) {\n    return_venti n;\n}\n\nprintventi(utils.twice(21) + twice(1));\n",
+    );
+    write("a.vt", "import_venti \"b.vt\";\nprintventi(1);\n");
+    write("b.vt", "import_venti \"a.vt\";\n");
+
+    let (code, stdout, stderr) = run_venti(&directory, &["run", "--no-cache", "main.vt"], "");
+    assert_eq!(
+        (code, stdout.as_str()),
+        (Some(0), "43\n"),
+        "stderr was:\n{}",
+        stderr
+    );
+    let (code, _, stderr) = run_venti(&directory, &["check", "a.vt"], "");
+    assert_eq!(code, Some(1));
+    assert!(
+        stderr.contains("Import cycle: a.vt -> b.vt -> a.vt") && stderr.contains("b.vt:1:1"),
+        "stderr was:\n{}",
+        stderr
+    );
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn new_projects_build_from_their_manifest() {
+    let directory = env::temp_dir().join(format!("venti-test-project-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("a temporary directory");
+    let project = directory.join("greeter");
+
+    let (code, _, stderr) = run_venti(&directory, &["new", "greeter"], "");
+    assert_eq!(code, Some(0), "stderr was:\n{}", stderr);
+    let (code, stdout, _) = run_venti(&project, &["run", "--no-cache"], "");
+    assert_eq!((code, stdout.as_str()), (Some(0), "Hello, Venti!\n"));
+
+    let manifest = "[package]\nname = \"greeter\"\n\n[build]\noutput = \"out\"\nemit = \"bc\"\n";
+    fs::write(project.join("Venti.toml"), manifest).expect("a writable manifest");
+    let (code, _, stderr) = run_venti(&project, &["build", "-q"], "");
+    assert_eq!(code, Some(0), "stderr was:\n{}", stderr);
+    assert!(project.join("out.bc").exists());
+    // A flag overrides what the manifest says
+    let (code, _, stderr) = run_venti(&project, &["build", "-q", "--emit", "llvm-ir"], "");
+    assert_eq!(code, Some(0), "stderr was:\n{}", stderr);
+    assert!(project.join("out.ll").exists());
+
+    let (code, _, stderr) = run_venti(&directory, &["new", "greeter"], "");
+    assert_eq!(code, Some(EXIT_IO));
+    assert!(
+        stderr.contains("greeter already exists"),
+        "stderr was:\n{}",
+        stderr
+    );
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn json_diagnostics_are_one_object_per_line() {
+    let program = "venti a = ;\nventi b = 2 +;";
+    let (code, _, stderr) = run_venti(
+        &env::temp_dir(),
+        &["--error-format", "json", "check", "-e", program],
+        "",
+    );
+    assert_eq!(code, Some(1));
+    assert_eq!(
+        stderr.lines().collect::<Vec<_>>(),
+        [
+            r#"{"severity": "error", "code": "V0001", "kind": "syntax-error", "message": "Unexpected token: Some(Semicolon)", "file": "eval.venti", "span": {"start": 10, "end": 11, "line": 1, "column": 11}, "help": null}"#,
+            r#"{"severity": "error", "code": "V0001", "kind": "syntax-error", "message": "Unexpected token: Some(Semicolon)", "file": "eval.venti", "span": {"start": 25, "end": 26, "line": 2, "column": 14}, "help": null}"#,
+        ]
+    );
+
+    let (code, _, stderr) = run_venti(
+        &env::temp_dir(),
+        &["--error-format", "json", "check", "-e", "venti unused = 1;"],
+        "",
+    );
+    assert_eq!(code, Some(0));
+    assert_eq!(
+        stderr.lines().collect::<Vec<_>>(),
+        [
+            r#"{"severity": "warning", "code": "unused-variable", "kind": "lint", "message": "Variable 'unused' is never used", "file": "eval.venti", "span": {"start": 0, "end": 17, "line": 1, "column": 1}, "help": "remove it, or start its name with `_` if it is meant to be unused"}"#,
+        ]
+    );
+}
+
+#[test]
+fn explain_describes_error_codes() {
+    let (code, stdout, _) = run_venti(&env::temp_dir(), &["explain", "V0042"], "");
+    assert_eq!(code, Some(0));
+    assert!(
+        stdout.starts_with("V0042\n\nA variable is used that has not been declared")
+            && stdout.contains("    venti count = 0;\n"),
+        "stdout was:\n{}",
+        stdout
+    );
+
+    let (code, _, stderr) = run_venti(&env::temp_dir(), &["explain", "V9999"], "");
+    assert_eq!(code, Some(2));
+    assert!(
+        stderr.contains("'V9999' is not a Venti error code"),
+        "stderr was:\n{}",
+        stderr
+    );
+}
+
+#[test]
+fn lints_warn_unless_allowed_or_denied() {
+    let directory = env::temp_dir().join(format!("venti-test-lints-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("a temporary directory");
+    let program = "fn_venti helper() {\n    return_venti 1;\n}\nfn_venti answer() {\n    return_venti 42;\n    printventi(0);\n}\nventi total = answer();\nventi spare = 0;\nfor_venti i in 0..2 {\n    venti total = i;\n    printventi(total);\n}\nprintventi(total);\n";
+    fs::write(directory.join("lints.vt"), program).expect("a writable program");
+    let check = |flags: &[&str]| {
+        let mut args = vec!["check", "lints.vt"];
+        args.extend(flags);
+        let (code, _, stderr) = run_venti(&directory, &args, "");
+        (code, stderr)
+    };
+    let warnings = [
+        "Warning[unused-function]: Function 'helper' is never called",
+        "Warning[unreachable-code]: Unreachable statement after return_venti",
+        "Warning[unused-variable]: Variable 'spare' is never used",
+        "Warning[shadowed-variable]: Variable 'total' shadows the variable declared on line 8",
+    ];
+
+    let (code, stderr) = check(&[]);
+    assert_eq!(code, Some(0));
+    for warning in warnings {
+        assert!(stderr.contains(warning), "stderr was:\n{}", stderr);
+    }
+    assert!(
+        stderr.contains("Found 4 warnings"),
+        "stderr was:\n{}",
+        stderr
+    );
+
+    let all = [
+        "unused-function",
+        "unreachable-code",
+        "unused-variable",
+        "shadowed-variable",
+    ];
+    for (lint, warning) in all.into_iter().zip(warnings) {
+        let (code, stderr) = check(&["--allow", lint]);
+        assert_eq!(code, Some(0));
+        assert!(!stderr.contains(warning), "stderr was:\n{}", stderr);
+
+        let (code, stderr) = check(&["--deny", lint]);
+        assert_eq!(code, Some(1));
+        let error = warning.replace(&format!("Warning[{}]", lint), "Lint Error[V0050]");
+        assert!(
+            stderr.contains(&format!("{} ({} is denied)", error, lint)),
+            "stderr was:\n{}",
+            stderr
+        );
+    }
+
+    // A later flag for the same lint wins
+    let (code, _) = check(&["-D", "unused-variable", "-W", "unused-variable"]);
+    assert_eq!(code, Some(0));
+    let (code, stderr) = check(&["--deny-warnings", "-A", "unused-variable"]);
+    assert_eq!(code, Some(1));
+    assert!(
+        stderr.contains("Found 3 errors") && !stderr.contains("'spare'"),
+        "stderr was:\n{}",
+        stderr
+    );
+    let _ = fs::remove_dir_all(&directory);
+}